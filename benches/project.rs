@@ -0,0 +1,80 @@
+// Compares compiling a many-file project on a thread pool with compiling
+// its files one after another:
+//
+//   cargo bench --bench project --features parallel
+//
+// Each way is run until a second has passed, and the mean time per project
+// is reported, for projects of a few sizes.
+
+use mylang2::{
+    pipeline::{CompileOptions, Lowering},
+    project::{compile_project, compile_project_sequentially},
+};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const SIZES: &[usize] = &[10, 100, 400];
+
+// A file with a few functions that call each other, and a `main` that
+// calls them.
+fn file(i: usize) -> String {
+    let mut source = String::new();
+    for j in 0..8 {
+        source += &format!(
+            "\
+fn f{j}(x: int64, n: int32) -> int64 {{
+    if n == 0 {{ return x * {i} + {j}; }}
+    let y: int64 = x * (n as int64) - {j};
+    return y + f{j}(x + 1, n - 1);
+}}
+"
+        );
+    }
+    source += "fn main() -> int64 {\n    let total: int64 = 0";
+    for j in 0..8 {
+        source += &format!(" + f{j}({i}, {j})");
+    }
+    source + ";\n    return total;\n}\n"
+}
+
+fn measure(mut call: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    let mut count = 0;
+    while start.elapsed() < Duration::from_secs(1) {
+        call();
+        count += 1;
+    }
+    start.elapsed() / count
+}
+
+fn main() {
+    let options = CompileOptions {
+        lowering: Lowering::Bytecode,
+        ..CompileOptions::default()
+    };
+    println!("{:<20} {:>12} {:>12}", "files", "sequential", "parallel");
+    for &size in SIZES {
+        let sources: Vec<(String, String)> = (0..size)
+            .map(|i| (format!("file{i}.my"), file(i)))
+            .collect();
+        let files: Vec<(&str, &str)> = sources
+            .iter()
+            .map(|(name, source)| (name.as_str(), source.as_str()))
+            .collect();
+        let project = compile_project(&files, &options);
+        assert!(!project.has_errors(), "{}", project.render());
+        assert_eq!(
+            project.render(),
+            compile_project_sequentially(&files, &options).render()
+        );
+        let sequential = measure(|| {
+            black_box(compile_project_sequentially(&files, &options));
+        });
+        let parallel = measure(|| {
+            black_box(compile_project(&files, &options));
+        });
+        println!("{:<20} {:>12.2?} {:>12.2?}", size, sequential, parallel);
+    }
+}
@@ -0,0 +1,100 @@
+// Compares the VM's two value representations on arithmetic-heavy programs:
+//
+//   cargo bench --bench vm
+//
+// Each program's `main` is called until a second has passed, and the mean
+// time per call is reported for `Vm::new`, which holds `Value`s, and
+// `Vm::packed`, which holds NaN-boxed `Packed` values.
+
+use mylang2::{bytecode, interpreter::Limits, lexer::Lexer, parser::Parser, vm::Vm};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const PROGRAMS: &[(&str, &str)] = &[
+    (
+        "fib int64",
+        "\
+fn fib(n: int64) -> int64 {
+    if n < 2 { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+fn main() -> int64 { return fib(20); }",
+    ),
+    (
+        "fib int16",
+        "\
+fn fib(n: int16) -> int16 {
+    if n < 2 { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+fn main() -> int16 { return fib(20); }",
+    ),
+    (
+        "polynomial float64",
+        "\
+fn sum(x: float64, n: int32) -> float64 {
+    if n == 0 { return 0; }
+    let y: float64 = x * x * x - 2 * x * x + x / 3 - 1;
+    return y + sum(x + 1, n - 1);
+}
+fn main() -> float64 { return sum(0, 400); }",
+    ),
+    (
+        "collatz int32",
+        "\
+fn steps(n: int32) -> int32 {
+    if n == 1 { return 0; }
+    if n - n / 2 * 2 == 0 { return 1 + steps(n / 2); }
+    return 1 + steps(3 * n + 1);
+}
+fn total(n: int32) -> int32 {
+    if n == 0 { return 0; }
+    return steps(n) + total(n - 1);
+}
+fn main() -> int32 { return total(100); }",
+    ),
+];
+
+fn measure(mut call: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    let mut count = 0;
+    while start.elapsed() < Duration::from_secs(1) {
+        call();
+        count += 1;
+    }
+    start.elapsed() / count
+}
+
+fn main() {
+    println!("{:<20} {:>12} {:>12}", "program", "Value", "Packed");
+    for (name, source) in PROGRAMS {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let module = bytecode::compile(&program.to_hir());
+        // The VM keeps its calls off the native stack, so it can go deeper
+        // than the interpreter's default allows.
+        let limits = Limits {
+            max_depth: 10_000,
+            ..Limits::default()
+        };
+        let mut plain = Vm::new(&module);
+        plain.limit(limits);
+        let mut packed = Vm::packed(&module);
+        packed.limit(limits);
+        assert_eq!(
+            plain.call("main", vec![]),
+            packed.call("main", vec![]),
+            "{}",
+            name
+        );
+        let plain = measure(|| {
+            black_box(plain.call("main", vec![]).unwrap());
+        });
+        let packed = measure(|| {
+            black_box(packed.call("main", vec![]).unwrap());
+        });
+        println!("{:<20} {:>12.2?} {:>12.2?}", name, plain, packed);
+    }
+}
@@ -0,0 +1,35 @@
+// With the `ffi` feature, writes the C header for the `ffi` module to
+// `$OUT_DIR/mylang2.h`. The build never writes to the source tree; a test in
+// the `ffi` module checks that the committed `include/mylang2.h` matches.
+
+fn main() {
+    #[cfg(feature = "ffi")]
+    write_header();
+}
+
+#[cfg(feature = "ffi")]
+fn write_header() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        include_guard: Some("MYLANG2_H".to_string()),
+        cpp_compat: true,
+        header: Some(
+            "// The C API of mylang2. Generated from src/ffi.rs by the build script;\n\
+             // see that file for how to use it."
+                .to_string(),
+        ),
+        autogen_warning: Some(
+            "// Do not edit: build with `--features ffi` and copy `$OUT_DIR/mylang2.h`\n\
+             // here to regenerate."
+                .to_string(),
+        ),
+        ..cbindgen::Config::default()
+    };
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src("src/ffi.rs")
+        .generate()
+        .expect("src/ffi.rs is valid for cbindgen")
+        .write_to_file(std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("mylang2.h"));
+}
@@ -0,0 +1,123 @@
+// The C API of mylang2. Generated from src/ffi.rs by the build script;
+// see that file for how to use it.
+
+#ifndef MYLANG2_H
+#define MYLANG2_H
+
+// Do not edit: build with `--features ffi` and copy `$OUT_DIR/mylang2.h`
+// here to regenerate.
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define MYLANG_OK 0
+
+#define MYLANG_INVALID_ARGUMENT 1
+
+#define MYLANG_COMPILE_ERROR 2
+
+#define MYLANG_RUNTIME_ERROR 3
+
+#define MYLANG_INTERNAL_ERROR 4
+
+typedef struct MylangEngine MylangEngine;
+
+typedef struct MylangProgram MylangProgram;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an engine with the default options, to be freed with
+ * `mylang_engine_free`. Returns null if the engine cannot be made.
+ */
+struct MylangEngine *mylang_engine_new(void);
+
+/**
+ * Frees an engine. Does nothing if `engine` is null.
+ *
+ * # Safety
+ *
+ * `engine` must be null or an engine from `mylang_engine_new` that has not
+ * been freed, and must not be used again.
+ */
+void mylang_engine_free(struct MylangEngine *engine);
+
+/**
+ * Sets the level of a lint, by name, for the sources the engine compiles
+ * from then on: "allow", "warn" or "deny".
+ *
+ * # Safety
+ *
+ * `engine` must be null or a live engine that no other thread is using.
+ * `lint` and `level` must each be null or a null-terminated string.
+ */
+int32_t mylang_engine_set_lint(struct MylangEngine *engine, const char *lint, const char *level);
+
+/**
+ * Compiles `length` bytes of source, named `name` in diagnostics. Returns a
+ * program even if the source has errors, and null only for invalid
+ * arguments or a panic.
+ *
+ * # Safety
+ *
+ * `engine` must be null or a live engine that no other thread is using.
+ * `name` must be null or a null-terminated string. Unless `length` is 0,
+ * `source` must be null or point to `length` readable bytes.
+ */
+struct MylangProgram *mylang_compile(const struct MylangEngine *engine,
+                                     const char *name,
+                                     const uint8_t *source,
+                                     uintptr_t length);
+
+/**
+ * Whether the program has errors, and so cannot be run. Returns `true` if
+ * `program` is null.
+ *
+ * # Safety
+ *
+ * `program` must be null or a live program that no other thread is using.
+ */
+bool mylang_program_has_errors(const struct MylangProgram *program);
+
+/**
+ * The program's diagnostics as a JSON array, or null if `program` is.
+ *
+ * # Safety
+ *
+ * `program` must be null or a live program that no other thread is using.
+ * The string must not be used after the program is run again or freed.
+ */
+const char *mylang_program_diagnostics(const struct MylangProgram *program);
+
+/**
+ * Runs the program's top-level statements and then its `main` function, if
+ * it has one. If `main` returns an integer and `result` is not null, the
+ * integer is stored in `result`. A runtime error is added to the program's
+ * diagnostics.
+ *
+ * # Safety
+ *
+ * `program` must be null or a live program that no other thread is using.
+ * `result` must be null or point to an `int64_t` that can be written.
+ */
+int32_t mylang_program_run(struct MylangProgram *program, int64_t *result);
+
+/**
+ * Frees a program. Does nothing if `program` is null.
+ *
+ * # Safety
+ *
+ * `program` must be null or a program from `mylang_compile` that has not
+ * been freed, and must not be used again.
+ */
+void mylang_program_free(struct MylangProgram *program);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MYLANG2_H */
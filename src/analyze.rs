@@ -0,0 +1,212 @@
+use crate::{
+    ast::Program,
+    diagnostic::{Diagnostic, Severity},
+    hir,
+    lint::LintLevels,
+    pass::{Context, PassManager},
+    resolver::Resolution,
+    source_map::SourceMap,
+    timings::Timings,
+    typecheck::TypeCheck,
+};
+
+// Everything the semantic passes found out about a program.
+#[derive(Debug)]
+pub struct AnalysisResult<'m> {
+    pub source_map: &'m SourceMap,
+    pub resolution: Resolution,
+    pub typecheck: TypeCheck,
+    // The program lowered to HIR, if it has no errors.
+    pub hir: Option<hir::Program>,
+    // The errors and warnings of every pass, in source order.
+    pub diagnostics: Vec<Diagnostic>,
+    // How long each pass took.
+    pub timings: Timings,
+}
+
+impl AnalysisResult<'_> {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    }
+
+    // Renders every diagnostic against the source, separated by blank lines.
+    pub fn render(&self) -> String {
+        self.diagnostics
+            .iter()
+            .map(|d| d.render(self.source_map))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Serializes every diagnostic as a line of JSON; see
+    // `Diagnostic::to_json`.
+    pub fn render_json(&self) -> String {
+        self.diagnostics
+            .iter()
+            .map(|d| d.to_json(self.source_map) + "\n")
+            .collect()
+    }
+}
+
+// Runs the semantic passes over a parsed program: name resolution, constant
+// evaluation and type checking, and the lints they report. The program must
+// have been parsed from the source in `source_map`, which diagnostics are
+// rendered against.
+pub fn check_program<'m>(program: &Program, source_map: &'m SourceMap) -> AnalysisResult<'m> {
+    check_program_with_lints(program, source_map, &LintLevels::default())
+}
+
+// Like `check_program`, with lint levels starting from `levels` instead of
+// the defaults. Lint attributes in the source override them.
+pub fn check_program_with_lints<'m>(
+    program: &Program,
+    source_map: &'m SourceMap,
+    levels: &LintLevels,
+) -> AnalysisResult<'m> {
+    analyze(source_map, Context::new(program, source_map, levels))
+}
+
+// Like `check_program_with_lints`, for a program whose names are resolved
+// already, such as one `loader::Loader::link` linked from modules.
+pub fn check_resolved<'m>(
+    program: &Program,
+    source_map: &'m SourceMap,
+    levels: &LintLevels,
+    resolution: Resolution,
+) -> AnalysisResult<'m> {
+    let mut context = Context::new(program, source_map, levels);
+    context.resolution = Some(resolution);
+    analyze(source_map, context)
+}
+
+fn analyze<'m>(source_map: &'m SourceMap, mut context: Context) -> AnalysisResult<'m> {
+    PassManager::semantic().run(&mut context);
+    AnalysisResult {
+        source_map,
+        resolution: context.resolution.unwrap(),
+        typecheck: context.typecheck.unwrap(),
+        hir: context.hir,
+        diagnostics: context.diagnostics,
+        timings: context.timings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    use crate::lint::{self, Level};
+
+    fn check(source: &str, test: impl FnOnce(&AnalysisResult)) {
+        check_with_lints(source, &LintLevels::default(), test);
+    }
+
+    fn check_with_lints(source: &str, levels: &LintLevels, test: impl FnOnce(&AnalysisResult)) {
+        let map = SourceMap::new("main", source);
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        test(&check_program_with_lints(&program, &map, levels));
+    }
+
+    #[test]
+    fn diagnostics_of_every_pass_are_collected_in_source_order() {
+        let source = "\
+fn main(a: int32) -> int8 {
+    let unused: int8 = a;
+    return b;
+}";
+        check(source, |result| {
+            let codes: Vec<&str> = result.diagnostics.iter().map(|d| d.code).collect();
+            assert_eq!(codes, ["W0200", "E0300", "E0200"]);
+            assert!(result.has_errors());
+            assert_eq!(result.count(Severity::Warning), 1);
+            assert_eq!(result.count(Severity::Error), 2);
+            assert!(result.hir.is_none());
+            assert!(result.render().starts_with(
+                "\
+warning[W0200]: unused variable `unused`
+ --> main:2:9
+"
+            ));
+        });
+    }
+
+    #[test]
+    fn unknown_types_are_reported_once() {
+        let source = "let x: in32 = 1; let y: List<int32> = x; let _z: bool = y;";
+        check(source, |result| {
+            let reported: Vec<(&str, &str)> = result
+                .diagnostics
+                .iter()
+                .map(|d| (d.code, &source[d.span.range()]))
+                .collect();
+            assert_eq!(reported, [("E0202", "in32"), ("E0202", "List")]);
+        });
+    }
+
+    #[test]
+    fn programs_without_errors_are_lowered() {
+        check("fn main() { let _x: int64 = 1; }", |result| {
+            assert!(!result.has_errors());
+            assert_eq!(
+                result.hir.as_ref().unwrap().to_string(),
+                "(fn main () () (block (let _x int64 (int64 1)) (return)))\n"
+            );
+        });
+    }
+
+    #[test]
+    fn lint_levels_decide_how_warnings_are_reported() {
+        let source = "\
+#[deny(unused)]
+fn main() { let a: int64 = 1; let a: int64 = 2; let _b: float16 = 2049; }
+";
+        let mut levels = LintLevels::new();
+        levels.set(&lint::UNUSED, Level::Allow);
+        levels.set(&lint::SAME_SCOPE_SHADOWING, Level::Allow);
+        check_with_lints(source, &levels, |result| {
+            let reported: Vec<(Severity, &str)> = result
+                .diagnostics
+                .iter()
+                .map(|d| (d.severity, d.code))
+                .collect();
+            // The attribute overrides the configuration for `unused`.
+            assert_eq!(
+                reported,
+                [
+                    (Severity::Error, "W0200"),
+                    (Severity::Error, "W0200"),
+                    (Severity::Warning, "W0300"),
+                ]
+            );
+            assert!(result.hir.is_none());
+        });
+    }
+
+    // Tools analyse files in parallel, so what the analyses make can be
+    // sent to and shared between threads.
+    #[test]
+    fn analysis_artifacts_are_send_and_sync() {
+        fn shareable<T: Send + Sync>() {}
+        shareable::<Program<'static>>();
+        shareable::<Resolution>();
+        shareable::<TypeCheck>();
+        shareable::<hir::Program>();
+        shareable::<AnalysisResult>();
+        shareable::<crate::session::Checked>();
+        shareable::<crate::callgraph::CallGraph>();
+        shareable::<crate::cfg::Cfg>();
+        shareable::<crate::ssa::Function>();
+        shareable::<crate::database::Database>();
+        shareable::<crate::source_map::SourceMap>();
+        shareable::<Diagnostic>();
+    }
+}
@@ -1,69 +1,1073 @@
-#[derive(Debug)]
+pub use crate::fold::Folder;
+pub use crate::node::NodeRef;
+#[cfg(feature = "std")]
+pub use crate::printer::print;
+pub use crate::symbol::Symbol;
+pub use crate::visit::{Control, Visitor};
+use alloc::{borrow::Cow, boxed::Box, vec, vec::Vec};
+
+// Identifies an AST node. Ids are assigned in parse order and are unique
+// within a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeId(pub u32);
+
+impl NodeId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+
+    // Returns this id and advances it to the next one.
+    pub fn advance(&mut self) -> NodeId {
+        let id = *self;
+        self.0 += 1;
+        id
+    }
+}
+
+// The byte range of source text a node was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    // Returns the smallest span covering both spans.
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    pub fn range(&self) -> core::ops::Range<usize> {
+        self.start..self.end
+    }
+
+    // Returns true if `other` lies entirely inside this span.
+    pub fn contains(&self, other: Span) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+}
+
+// Implemented by AST nodes that know where in the source they came from.
+pub trait Spanned {
+    fn span(&self) -> Span;
+}
+
+macro_rules! impl_spanned {
+    ($($node:ty),*) => {
+        $(
+            impl Spanned for $node {
+                fn span(&self) -> Span {
+                    self.span
+                }
+            }
+        )*
+    };
+}
+
+impl_spanned!(
+    Parameter<'_>,
+    FunctionDeclaration<'_>,
+    Block<'_>,
+    ReturnStatement<'_>,
+    IntegerLiteral<'_>,
+    Type,
+    ArrayType<'_>,
+    GenericType<'_>,
+    FunctionType<'_>,
+    BinaryExpression<'_>,
+    Identifier,
+    LetStatement<'_>,
+    ConstDeclaration<'_>,
+    IfStatement<'_>,
+    WhileStatement<'_>,
+    CallExpression<'_>,
+    CastExpression<'_>
+);
+
+impl Spanned for Statement<'_> {
+    fn span(&self) -> Span {
+        match self {
+            Statement::Let(s) => s.span,
+            Statement::Const(c) => c.span,
+            Statement::FunctionDeclaration(f) => f.span,
+            // The span of an expression statement does not include its ';'.
+            Statement::Expression(e) => e.span(),
+            Statement::Return(r) => r.span,
+            Statement::If(i) => i.span,
+            Statement::While(w) => w.span,
+            Statement::Block(b) => b.span,
+            Statement::Import(i) => i.span,
+        }
+    }
+}
+
+impl Spanned for TypeExpr<'_> {
+    fn span(&self) -> Span {
+        match self {
+            TypeExpr::Named(t) => t.span,
+            TypeExpr::Array(a) => a.span,
+            TypeExpr::Generic(g) => g.span,
+            // Tuple types do not record their parentheses, so this covers
+            // their elements only.
+            TypeExpr::Tuple(elements) => match (elements.first(), elements.last()) {
+                (Some(first), Some(last)) => first.span().to(last.span()),
+                _ => Span::default(),
+            },
+            TypeExpr::Function(f) => f.span,
+        }
+    }
+}
+
+impl Spanned for Expression<'_> {
+    fn span(&self) -> Span {
+        match self {
+            Expression::IntegerLiteral(i) => i.span,
+            Expression::Identifier(i) => i.span,
+            Expression::BinaryExpression(b) => b.span,
+            Expression::Call(c) => c.span,
+            Expression::Cast(c) => c.span,
+        }
+    }
+}
+
+// A side table associating values with AST nodes, used to record information
+// such as types or resolved symbols without mutating the AST.
+#[derive(Debug, Clone)]
+pub struct NodeMap<T> {
+    entries: Vec<Option<T>>,
+    len: usize,
+}
+
+impl<T> Default for NodeMap<T> {
+    fn default() -> NodeMap<T> {
+        NodeMap::new()
+    }
+}
+
+impl<T> NodeMap<T> {
+    pub fn new() -> NodeMap<T> {
+        NodeMap {
+            entries: vec![],
+            len: 0,
+        }
+    }
+
+    // Sets the value for a node, returning the previous value if any.
+    pub fn insert(&mut self, id: NodeId, value: T) -> Option<T> {
+        if id.index() >= self.entries.len() {
+            self.entries.resize_with(id.index() + 1, || None);
+        }
+        let previous = self.entries[id.index()].replace(value);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.entries.get(id.index()).and_then(Option::as_ref)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.entries.get_mut(id.index()).and_then(Option::as_mut)
+    }
+
+    pub fn remove(&mut self, id: NodeId) -> Option<T> {
+        let previous = self.entries.get_mut(id.index()).and_then(Option::take);
+        if previous.is_some() {
+            self.len -= 1;
+        }
+        previous
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Iterates over the entries in node id order.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &T)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, value)| value.as_ref().map(|v| (NodeId(i as u32), v)))
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Statement<'a> {
     Let(LetStatement<'a>),
+    Const(ConstDeclaration<'a>),
     FunctionDeclaration(FunctionDeclaration<'a>),
     Expression(Expression<'a>),
+    Return(ReturnStatement<'a>),
+    If(IfStatement<'a>),
+    While(WhileStatement<'a>),
+    Block(Block<'a>),
+    Import(ImportStatement),
+}
+
+// `import geometry;`, which makes the top-level functions and constants of
+// the module `geometry` visible in the file. Imports are only allowed at the
+// top level; `loader::Loader` loads the modules they name.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImportStatement {
+    pub id: NodeId,
+    pub span: Span,
+    pub module: Identifier,
+}
+
+// `if condition { ... }`, optionally followed by `else { ... }` or
+// `else if ...`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IfStatement<'a> {
+    pub id: NodeId,
+    pub span: Span,
+    pub condition: Box<Expression<'a>>,
+    pub then_block: Block<'a>,
+    // Either a `Statement::Block` or, for `else if`, a `Statement::If`.
+    pub else_branch: Option<Box<Statement<'a>>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WhileStatement<'a> {
+    pub id: NodeId,
+    pub span: Span,
+    pub condition: Box<Expression<'a>>,
+    pub body: Block<'a>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Parameter<'a> {
-    pub identifier: Identifier<'a>,
-    pub ttype: Type<'a>,
+    pub id: NodeId,
+    pub span: Span,
+    pub identifier: Identifier,
+    pub ttype: TypeExpr<'a>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FunctionDeclaration<'a> {
-    pub identifier: Identifier<'a>,
+    pub id: NodeId,
+    pub span: Span,
+    pub identifier: Identifier,
     pub parameters: Vec<Parameter<'a>>,
-    pub return_type: Type<'a>,
+    pub return_type: TypeExpr<'a>,
+    pub body: Option<Block<'a>>,
+    pub docs: Vec<Cow<'a, str>>,
+    // Whether an `#[inline]` comment comes right before it, asking for its
+    // calls to be inlined.
+    pub inline: bool,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Block<'a> {
+    pub id: NodeId,
+    pub span: Span,
+    pub statements: Vec<Statement<'a>>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReturnStatement<'a> {
+    pub id: NodeId,
+    pub span: Span,
+    pub expression: Option<Box<Expression<'a>>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expression<'a> {
     IntegerLiteral(IntegerLiteral<'a>),
-    Identifier(Identifier<'a>),
+    Identifier(Identifier),
     BinaryExpression(BinaryExpression<'a>),
+    Call(CallExpression<'a>),
+    Cast(CastExpression<'a>),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CallExpression<'a> {
+    pub id: NodeId,
+    pub span: Span,
+    pub callee: Box<Expression<'a>>,
+    pub arguments: Vec<Expression<'a>>,
 }
 
-#[derive(Debug)]
+// An explicit conversion such as `x as int64`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CastExpression<'a> {
+    pub id: NodeId,
+    pub span: Span,
+    pub expression: Box<Expression<'a>>,
+    pub ttype: TypeExpr<'a>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IntegerLiteral<'a> {
-    pub text: &'a str,
+    pub id: NodeId,
+    pub span: Span,
+    pub text: Cow<'a, str>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Type {
+    pub id: NodeId,
+    pub span: Span,
+    pub kind: TypeKind,
+    // The type as written in source, which names a `TypeKind::Named` type.
+    pub name: Symbol,
+}
+
+// The built-in primitive types and user-defined named types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TypeKind {
+    Int1,
+    Int2,
+    Int4,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Float16,
+    BFloat16,
+    Float32,
+    Float64,
+    Bool,
+    String,
+    // The type of functions that return no value, written `()`.
+    Unit,
+    // A type named by something other than a primitive, whose name is on
+    // its `Type`.
+    Named,
+}
+
+impl TypeKind {
+    // The primitive types that are written as a name.
+    pub const PRIMITIVES: [TypeKind; 13] = [
+        TypeKind::Int1,
+        TypeKind::Int2,
+        TypeKind::Int4,
+        TypeKind::Int8,
+        TypeKind::Int16,
+        TypeKind::Int32,
+        TypeKind::Int64,
+        TypeKind::Float16,
+        TypeKind::BFloat16,
+        TypeKind::Float32,
+        TypeKind::Float64,
+        TypeKind::Bool,
+        TypeKind::String,
+    ];
+
+    // Returns the primitive type with the given name, or a named type.
+    pub fn from_name(name: &str) -> TypeKind {
+        match name {
+            "int1" => TypeKind::Int1,
+            "int2" => TypeKind::Int2,
+            "int4" => TypeKind::Int4,
+            "int8" => TypeKind::Int8,
+            "int16" => TypeKind::Int16,
+            "int32" => TypeKind::Int32,
+            "int64" => TypeKind::Int64,
+            "float16" => TypeKind::Float16,
+            "bfloat16" => TypeKind::BFloat16,
+            "float32" => TypeKind::Float32,
+            "float64" => TypeKind::Float64,
+            "bool" => TypeKind::Bool,
+            "string" => TypeKind::String,
+            "()" => TypeKind::Unit,
+            _ => TypeKind::Named,
+        }
+    }
+
+    // Returns the type as written in source, or `{unknown}` for a named type.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TypeKind::Int1 => "int1",
+            TypeKind::Int2 => "int2",
+            TypeKind::Int4 => "int4",
+            TypeKind::Int8 => "int8",
+            TypeKind::Int16 => "int16",
+            TypeKind::Int32 => "int32",
+            TypeKind::Int64 => "int64",
+            TypeKind::Float16 => "float16",
+            TypeKind::BFloat16 => "bfloat16",
+            TypeKind::Float32 => "float32",
+            TypeKind::Float64 => "float64",
+            TypeKind::Bool => "bool",
+            TypeKind::String => "string",
+            TypeKind::Unit => "()",
+            TypeKind::Named => "{unknown}",
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.integer_bits().is_some()
+    }
+
+    // Returns the width in bits of an integer type.
+    pub fn integer_bits(&self) -> Option<u32> {
+        match self {
+            TypeKind::Int1 => Some(1),
+            TypeKind::Int2 => Some(2),
+            TypeKind::Int4 => Some(4),
+            TypeKind::Int8 => Some(8),
+            TypeKind::Int16 => Some(16),
+            TypeKind::Int32 => Some(32),
+            TypeKind::Int64 => Some(64),
+            _ => None,
+        }
+    }
+
+    // Returns the exponent width and the significand precision, in bits, of
+    // a floating-point type.
+    pub fn float_format(&self) -> Option<(u32, u32)> {
+        match self {
+            TypeKind::Float16 => Some((5, 11)),
+            TypeKind::BFloat16 => Some((8, 8)),
+            TypeKind::Float32 => Some((8, 24)),
+            TypeKind::Float64 => Some((11, 53)),
+            _ => None,
+        }
+    }
+
+    // Returns the smallest and largest values of an integer type. Integers
+    // are signed two's complement, except `int1`, which holds 0 or 1.
+    pub fn integer_range(&self) -> Option<(i128, i128)> {
+        match self.integer_bits()? {
+            1 => Some((0, 1)),
+            bits => Some((-(1 << (bits - 1)), (1 << (bits - 1)) - 1)),
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(
+            self,
+            TypeKind::Float16 | TypeKind::BFloat16 | TypeKind::Float32 | TypeKind::Float64
+        )
+    }
 }
 
-#[derive(Debug)]
-pub struct Type<'a> {
-    pub name: &'a str,
+impl core::fmt::Display for TypeKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TypeExpr<'a> {
+    Named(Type),
+    Array(ArrayType<'a>),
+    Generic(GenericType<'a>),
+    Tuple(Vec<TypeExpr<'a>>),
+    Function(FunctionType<'a>),
+}
+
+// A fixed-size array type such as `[int32; 4]`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArrayType<'a> {
+    pub id: NodeId,
+    pub span: Span,
+    pub element: Box<TypeExpr<'a>>,
+    // An integer literal or an expression over constants, such as `N * 2`.
+    pub size: Box<Expression<'a>>,
+}
+
+// A named type applied to type arguments such as `List<int32>`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GenericType<'a> {
+    pub id: NodeId,
+    pub span: Span,
+    pub base: Type,
+    pub arguments: Vec<TypeExpr<'a>>,
+}
+
+// A function type such as `fn(int32) -> int32`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FunctionType<'a> {
+    pub id: NodeId,
+    pub span: Span,
+    pub parameters: Vec<TypeExpr<'a>>,
+    pub return_type: Box<TypeExpr<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOperator {
     Divide,
     Plus,
     Minus,
+    Power,
     Star,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
 }
 
-#[derive(Debug)]
+impl BinaryOperator {
+    // Returns the binding strength of the operator; higher binds tighter.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::Less
+            | BinaryOperator::LessEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterEqual => 1,
+            BinaryOperator::Plus | BinaryOperator::Minus => 2,
+            BinaryOperator::Star | BinaryOperator::Divide => 3,
+            BinaryOperator::Power => 4,
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        self.precedence() == 1
+    }
+
+    // Returns true if `a op b op c` groups as `a op (b op c)`.
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self, BinaryOperator::Power)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BinaryExpression<'a> {
+    pub id: NodeId,
+    pub span: Span,
     pub operator: BinaryOperator,
     pub left: Box<Expression<'a>>,
     pub right: Box<Expression<'a>>,
 }
 
-#[derive(Debug)]
-pub struct Identifier<'a> {
-    pub name: &'a str,
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Identifier {
+    pub id: NodeId,
+    pub span: Span,
+    pub name: Symbol,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LetStatement<'a> {
-    pub identifier: Identifier<'a>,
-    pub ttype: Type<'a>,
+    pub id: NodeId,
+    pub span: Span,
+    pub identifier: Identifier,
+    pub ttype: TypeExpr<'a>,
     pub mutable: bool,
     pub expression: Box<Expression<'a>>,
+    pub docs: Vec<Cow<'a, str>>,
+}
+
+// `const NAME: type = expression;`, whose value is computed at compile time.
+// The expression may refer only to literals and earlier constants.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConstDeclaration<'a> {
+    pub id: NodeId,
+    pub span: Span,
+    pub identifier: Identifier,
+    pub ttype: TypeExpr<'a>,
+    pub expression: Box<Expression<'a>>,
+    pub docs: Vec<Cow<'a, str>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Program<'a> {
     pub statements: Vec<Statement<'a>>,
 }
+
+// Converts borrowed text into owned text.
+fn own(text: Cow<str>) -> Cow<'static, str> {
+    Cow::Owned(text.into_owned())
+}
+
+fn own_docs(docs: Vec<Cow<str>>) -> Vec<Cow<'static, str>> {
+    docs.into_iter().map(own).collect()
+}
+
+fn own_types(types: Vec<TypeExpr>) -> Vec<TypeExpr<'static>> {
+    types.into_iter().map(TypeExpr::into_owned).collect()
+}
+
+// The passes outside the front end, which need `std`.
+#[cfg(feature = "std")]
+impl Program<'_> {
+    // Returns a readable indented tree of the program's nodes.
+    pub fn dump(&self) -> String {
+        crate::dump::dump(self)
+    }
+
+    // Returns the program as compact s-expressions, one statement per line.
+    pub fn to_sexp(&self) -> String {
+        crate::sexp::sexp(self)
+    }
+
+    // Returns node counts and other size measurements of the program.
+    pub fn metrics(&self) -> crate::metrics::Metrics {
+        crate::metrics::metrics(self)
+    }
+
+    // Links identifier uses to their declarations.
+    pub fn resolve(&self) -> crate::resolver::Resolution {
+        crate::resolver::resolve(self)
+    }
+
+    // Resolves names and checks that the program is well typed.
+    pub fn typecheck(&self) -> crate::typecheck::TypeCheck {
+        crate::typecheck::typecheck(self, &self.resolve())
+    }
+
+    // Resolves names and builds the graph of which functions call which.
+    pub fn call_graph(&self) -> crate::callgraph::CallGraph {
+        crate::callgraph::CallGraph::build(self, &self.resolve())
+    }
+
+    // Resolves names, type checks the program and lowers it to HIR.
+    pub fn to_hir(&self) -> crate::hir::Program {
+        let resolution = self.resolve();
+        let check = crate::typecheck::typecheck(self, &resolution);
+        crate::hir::lower(self, &resolution, &check)
+    }
+}
+
+impl Program<'_> {
+    // Returns a copy of the program that does not borrow from the source text,
+    // so it can outlive the source buffer or be sent to another thread.
+    pub fn into_owned(self) -> Program<'static> {
+        Program {
+            statements: self
+                .statements
+                .into_iter()
+                .map(Statement::into_owned)
+                .collect(),
+        }
+    }
+
+    // The program's imports, in source order.
+    pub fn imports(&self) -> impl Iterator<Item = &ImportStatement> {
+        self.statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Import(import) => Some(import),
+                _ => None,
+            })
+    }
+}
+
+impl Statement<'_> {
+    pub fn into_owned(self) -> Statement<'static> {
+        match self {
+            Statement::Let(s) => Statement::Let(s.into_owned()),
+            Statement::Const(c) => Statement::Const(c.into_owned()),
+            Statement::FunctionDeclaration(f) => Statement::FunctionDeclaration(f.into_owned()),
+            Statement::Expression(e) => Statement::Expression(e.into_owned()),
+            Statement::Return(r) => Statement::Return(r.into_owned()),
+            Statement::If(i) => Statement::If(i.into_owned()),
+            Statement::While(w) => Statement::While(WhileStatement {
+                id: w.id,
+                span: w.span,
+                condition: Box::new(w.condition.into_owned()),
+                body: w.body.into_owned(),
+            }),
+            Statement::Block(b) => Statement::Block(b.into_owned()),
+            Statement::Import(i) => Statement::Import(i),
+        }
+    }
+}
+
+impl IfStatement<'_> {
+    pub fn into_owned(self) -> IfStatement<'static> {
+        IfStatement {
+            id: self.id,
+            span: self.span,
+            condition: Box::new(self.condition.into_owned()),
+            then_block: self.then_block.into_owned(),
+            else_branch: self.else_branch.map(|s| Box::new(s.into_owned())),
+        }
+    }
+}
+
+impl ConstDeclaration<'_> {
+    pub fn into_owned(self) -> ConstDeclaration<'static> {
+        ConstDeclaration {
+            id: self.id,
+            span: self.span,
+            identifier: self.identifier,
+            ttype: self.ttype.into_owned(),
+            expression: Box::new(self.expression.into_owned()),
+            docs: own_docs(self.docs),
+        }
+    }
+}
+
+impl LetStatement<'_> {
+    pub fn into_owned(self) -> LetStatement<'static> {
+        LetStatement {
+            id: self.id,
+            span: self.span,
+            identifier: self.identifier,
+            ttype: self.ttype.into_owned(),
+            mutable: self.mutable,
+            expression: Box::new(self.expression.into_owned()),
+            docs: own_docs(self.docs),
+        }
+    }
+}
+
+impl FunctionDeclaration<'_> {
+    // Returns the span from `fn` to the end of the return type, or to the
+    // closing ')' of the parameter list if the return type is implicit.
+    pub fn signature_span(&self) -> Span {
+        Span::new(self.span.start, self.return_type.span().end)
+    }
+
+    pub fn into_owned(self) -> FunctionDeclaration<'static> {
+        FunctionDeclaration {
+            id: self.id,
+            span: self.span,
+            identifier: self.identifier,
+            parameters: self
+                .parameters
+                .into_iter()
+                .map(Parameter::into_owned)
+                .collect(),
+            return_type: self.return_type.into_owned(),
+            body: self.body.map(Block::into_owned),
+            docs: own_docs(self.docs),
+            inline: self.inline,
+        }
+    }
+}
+
+impl Parameter<'_> {
+    pub fn into_owned(self) -> Parameter<'static> {
+        Parameter {
+            id: self.id,
+            span: self.span,
+            identifier: self.identifier,
+            ttype: self.ttype.into_owned(),
+        }
+    }
+}
+
+impl Block<'_> {
+    pub fn into_owned(self) -> Block<'static> {
+        Block {
+            id: self.id,
+            span: self.span,
+            statements: self
+                .statements
+                .into_iter()
+                .map(Statement::into_owned)
+                .collect(),
+        }
+    }
+}
+
+impl ReturnStatement<'_> {
+    pub fn into_owned(self) -> ReturnStatement<'static> {
+        ReturnStatement {
+            id: self.id,
+            span: self.span,
+            expression: self.expression.map(|e| Box::new(e.into_owned())),
+        }
+    }
+}
+
+impl Expression<'_> {
+    pub fn into_owned(self) -> Expression<'static> {
+        match self {
+            Expression::IntegerLiteral(i) => Expression::IntegerLiteral(i.into_owned()),
+            Expression::Identifier(i) => Expression::Identifier(i),
+            Expression::BinaryExpression(b) => Expression::BinaryExpression(BinaryExpression {
+                id: b.id,
+                span: b.span,
+                operator: b.operator,
+                left: Box::new(b.left.into_owned()),
+                right: Box::new(b.right.into_owned()),
+            }),
+            Expression::Call(c) => Expression::Call(CallExpression {
+                id: c.id,
+                span: c.span,
+                callee: Box::new(c.callee.into_owned()),
+                arguments: c
+                    .arguments
+                    .into_iter()
+                    .map(Expression::into_owned)
+                    .collect(),
+            }),
+            Expression::Cast(c) => Expression::Cast(CastExpression {
+                id: c.id,
+                span: c.span,
+                expression: Box::new(c.expression.into_owned()),
+                ttype: c.ttype.into_owned(),
+            }),
+        }
+    }
+}
+
+impl IntegerLiteral<'_> {
+    pub fn into_owned(self) -> IntegerLiteral<'static> {
+        IntegerLiteral {
+            id: self.id,
+            span: self.span,
+            text: own(self.text),
+        }
+    }
+}
+
+impl TypeExpr<'_> {
+    pub fn is_unit(&self) -> bool {
+        matches!(self, TypeExpr::Named(t) if t.kind == TypeKind::Unit)
+    }
+
+    pub fn into_owned(self) -> TypeExpr<'static> {
+        match self {
+            TypeExpr::Named(t) => TypeExpr::Named(t),
+            TypeExpr::Array(a) => TypeExpr::Array(ArrayType {
+                id: a.id,
+                span: a.span,
+                element: Box::new(a.element.into_owned()),
+                size: Box::new(a.size.into_owned()),
+            }),
+            TypeExpr::Generic(g) => TypeExpr::Generic(GenericType {
+                id: g.id,
+                span: g.span,
+                base: g.base,
+                arguments: own_types(g.arguments),
+            }),
+            TypeExpr::Tuple(elements) => TypeExpr::Tuple(own_types(elements)),
+            TypeExpr::Function(f) => TypeExpr::Function(FunctionType {
+                id: f.id,
+                span: f.span,
+                parameters: own_types(f.parameters),
+                return_type: Box::new(f.return_type.into_owned()),
+            }),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    // Parses `source` into an AST that outlives it.
+    fn parse_owned(source: String) -> Program<'static> {
+        let tokens = Lexer::tokenize(&source);
+        Parser::parse_program(&tokens).unwrap().into_owned()
+    }
+
+    #[test]
+    fn node_map_stores_values_by_id() {
+        let mut map = NodeMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert(NodeId(3), "three"), None);
+        assert_eq!(map.insert(NodeId(1), "one"), None);
+        assert_eq!(map.insert(NodeId(3), "drei"), Some("three"));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(NodeId(3)), Some(&"drei"));
+        assert_eq!(map.get(NodeId(2)), None);
+        assert_eq!(map.get(NodeId(100)), None);
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            [(NodeId(1), &"one"), (NodeId(3), &"drei")]
+        );
+        assert_eq!(map.remove(NodeId(1)), Some("one"));
+        assert!(!map.contains(NodeId(1)));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn parsed_nodes_have_unique_ids() {
+        let tokens = Lexer::tokenize("fn f(x: int32) -> int32 { return x * 2; } let y: int32 = 1;");
+        let program = Parser::parse_program(&tokens).unwrap();
+        let Statement::FunctionDeclaration(f) = &program.statements[0] else {
+            panic!("Expected a function");
+        };
+        let Statement::Let(l) = &program.statements[1] else {
+            panic!("Expected a let statement");
+        };
+        let mut ids = vec![
+            f.id,
+            f.identifier.id,
+            f.parameters[0].id,
+            l.id,
+            l.identifier.id,
+        ];
+        ids.push(f.body.as_ref().unwrap().id);
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 6);
+    }
+
+    #[test]
+    fn nodes_know_their_spans() {
+        let source = "fn f(x: List<int32>) -> int32 {\n    return (x + 1) * 2;\n}\nx;";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let text = |node: &dyn Spanned| &source[node.span().range()];
+        let Statement::FunctionDeclaration(f) = &program.statements[0] else {
+            panic!("Expected a function");
+        };
+        assert_eq!(text(&program.statements[0]), &source[..source.len() - 3]);
+        assert_eq!(text(&f.identifier), "f");
+        assert_eq!(text(&f.parameters[0]), "x: List<int32>");
+        let TypeExpr::Generic(generic) = &f.parameters[0].ttype else {
+            panic!("Expected a generic type");
+        };
+        assert_eq!(text(generic), "List<int32>");
+        let body = f.body.as_ref().unwrap();
+        assert_eq!(text(body), "{\n    return (x + 1) * 2;\n}");
+        let Statement::Return(r) = &body.statements[0] else {
+            panic!("Expected a return statement");
+        };
+        assert_eq!(text(r), "return (x + 1) * 2;");
+        assert_eq!(text(r.expression.as_deref().unwrap()), "(x + 1) * 2");
+        assert_eq!(text(&program.statements[1]), "x");
+    }
+
+    #[test]
+    fn type_names_map_to_kinds() {
+        for name in [
+            "int1", "int2", "int4", "int64", "bfloat16", "float32", "bool", "string", "()",
+        ] {
+            let kind = TypeKind::from_name(name);
+            assert_ne!(kind, TypeKind::Named);
+            assert_eq!(kind.name(), name);
+        }
+        assert_eq!(TypeKind::from_name("Point"), TypeKind::Named);
+        assert!(TypeKind::Int8.is_integer() && TypeKind::Float16.is_float());
+        assert_eq!(TypeKind::Int1.integer_range(), Some((0, 1)));
+        assert_eq!(TypeKind::Int4.integer_range(), Some((-8, 7)));
+        assert_eq!(
+            TypeKind::Int64.integer_range(),
+            Some((i64::MIN as i128, i64::MAX as i128))
+        );
+        assert_eq!(TypeKind::Float32.integer_range(), None);
+
+        let tokens = Lexer::tokenize("fn f() { }");
+        let program = Parser::parse_program(&tokens).unwrap();
+        let Statement::FunctionDeclaration(f) = &program.statements[0] else {
+            panic!("Expected a function");
+        };
+        assert!(f.return_type.is_unit());
+    }
+
+    #[test]
+    fn owned_program_outlives_its_source() {
+        let source = String::from("## Doc.\nfn f(x: List<int32>) -> int32 { return x; }");
+        let program = parse_owned(source);
+        let printed = std::thread::spawn(move || print(&program)).join().unwrap();
+        assert_eq!(
+            printed,
+            "## Doc.\nfn f(x: List<int32>) -> int32 {\n    return x;\n}\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn program_serializes_to_json() {
+        let tokens = Lexer::tokenize("## Doc.\nlet x: [int32; 2] = a + 1;");
+        let program = Parser::parse_program(&tokens).unwrap();
+        let json = serde_json::to_value(&program).unwrap();
+        let span = |start: usize, end: usize| serde_json::json!({ "start": start, "end": end });
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "statements": [{
+                    "Let": {
+                        "id": 7,
+                        "span": span(8, 34),
+                        "identifier": { "id": 0, "span": span(12, 13), "name": "x" },
+                        "ttype": {
+                            "Array": {
+                                "id": 3,
+                                "span": span(15, 25),
+                                "element": {
+                                    "Named": {
+                                        "id": 1,
+                                        "span": span(16, 21),
+                                        "kind": "Int32",
+                                        "name": "int32"
+                                    }
+                                },
+                                "size": {
+                                    "IntegerLiteral": { "id": 2, "span": span(23, 24), "text": "2" }
+                                }
+                            }
+                        },
+                        "mutable": false,
+                        "expression": {
+                            "BinaryExpression": {
+                                "id": 6,
+                                "span": span(28, 33),
+                                "operator": "Plus",
+                                "left": {
+                                    "Identifier": { "id": 4, "span": span(28, 29), "name": "a" }
+                                },
+                                "right": {
+                                    "IntegerLiteral": { "id": 5, "span": span(32, 33), "text": "1" }
+                                }
+                            }
+                        },
+                        "docs": ["Doc."]
+                    }
+                }]
+            })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn program_round_trips_through_json() {
+        let source = "let x: (int32, fn() -> ()) = (a + 1) ** 2;\nfn f();\n";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let json = serde_json::to_string(&program).unwrap();
+        let decoded: Program<'static> = serde_json::from_str(&json).unwrap();
+        assert_eq!(print(&decoded), source);
+    }
+}
@@ -0,0 +1,23 @@
+use std::{io, thread};
+
+use mylang2::cli::{self, Console};
+
+fn main() {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    let thread = thread::Builder::new().stack_size(cli::STACK_SIZE);
+    let status = thread
+        .spawn(move || {
+            cli::main(
+                &arguments,
+                &mut Console {
+                    input: &mut io::stdin().lock(),
+                    output: &mut io::stdout(),
+                    errors: &mut io::stderr(),
+                },
+            )
+        })
+        .expect("cannot start the main thread")
+        .join()
+        .unwrap_or(101);
+    std::process::exit(status);
+}
@@ -3,6 +3,7 @@ use std::io::BufRead;
 
 use mylang2::lexer;
 use mylang2::parser::Parser;
+use mylang2::source_map::SourceMap;
 
 fn main() {
     let stdin = io::stdin();
@@ -36,7 +37,10 @@ fn main() {
             }
         }
         Err(error) => {
-            println!("error: {}", error.message);
+            print!(
+                "{}",
+                error.render(&SourceMap::new("<stdin>", source.as_str()))
+            );
         }
     }
 }
@@ -0,0 +1,189 @@
+use crate::{
+    ast::{Span, TypeKind},
+    interpreter::{RuntimeError, RuntimeErrorKind},
+    symbol::{sym, Symbol},
+    typecheck::Ty,
+    value::Value,
+};
+use std::io::{self, BufRead, Write};
+
+// A function every program can call without declaring it. A declaration of
+// the same name shadows it.
+//
+// Builtins can only be called, not used as values. `print` and `println`
+// take one value of any primitive type but unit and write it as `Value`
+// displays it, `println` followed by a newline. `read_line` returns the next
+// line of input without its line ending, or an empty string at the end of
+// the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    Print,
+    Println,
+    ReadLine,
+}
+
+impl Builtin {
+    pub const ALL: [Builtin; 3] = [Builtin::Print, Builtin::Println, Builtin::ReadLine];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Print => "print",
+            Builtin::Println => "println",
+            Builtin::ReadLine => "read_line",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Builtin::ALL
+            .into_iter()
+            .find(|builtin| builtin.name() == name)
+    }
+
+    pub fn symbol(&self) -> Symbol {
+        match self {
+            Builtin::Print => sym::PRINT,
+            Builtin::Println => sym::PRINTLN,
+            Builtin::ReadLine => sym::READ_LINE,
+        }
+    }
+
+    pub fn from_symbol(name: &Symbol) -> Option<Builtin> {
+        Builtin::ALL
+            .into_iter()
+            .find(|builtin| builtin.symbol() == *name)
+    }
+
+    // The number of arguments it takes.
+    pub fn arity(&self) -> usize {
+        match self {
+            Builtin::Print | Builtin::Println => 1,
+            Builtin::ReadLine => 0,
+        }
+    }
+
+    pub fn return_type(&self) -> Ty {
+        match self {
+            Builtin::Print | Builtin::Println => Ty::Primitive(TypeKind::Unit),
+            Builtin::ReadLine => Ty::Primitive(TypeKind::String),
+        }
+    }
+}
+
+// Where the I/O builtins read and write: the process's stdin and stdout by
+// default, or buffers, so that a program's output can be checked.
+pub struct Streams<'a> {
+    pub input: Box<dyn BufRead + 'a>,
+    pub output: Box<dyn Write + 'a>,
+}
+
+impl<'a> Streams<'a> {
+    pub fn new(input: impl BufRead + 'a, output: impl Write + 'a) -> Streams<'a> {
+        Streams {
+            input: Box::new(input),
+            output: Box::new(output),
+        }
+    }
+
+    // Holds the lock on stdin until it is dropped.
+    pub fn standard() -> Streams<'static> {
+        Streams::new(io::stdin().lock(), io::stdout())
+    }
+
+    // Runs a builtin, returning its result or `None` for unit.
+    fn call(&mut self, builtin: Builtin, arguments: &[Value]) -> io::Result<Option<Value>> {
+        match builtin {
+            Builtin::Print => write!(self.output, "{}", arguments[0])?,
+            Builtin::Println => writeln!(self.output, "{}", arguments[0])?,
+            Builtin::ReadLine => {
+                // Show a prompt written with `print` before waiting.
+                self.output.flush()?;
+                let mut line = String::new();
+                self.input.read_line(&mut line)?;
+                let end = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(end);
+                return Ok(Some(Value::String(line.into())));
+            }
+        }
+        Ok(None)
+    }
+}
+
+// Runs a builtin called at `span` with `streams`, or with the standard
+// streams if there are none. Those are only locked during the call, so that
+// programs running on other threads can use them too.
+pub(crate) fn call(
+    streams: &mut Option<Streams>,
+    builtin: Builtin,
+    arguments: &[Value],
+    span: Span,
+) -> Result<Option<Value>, RuntimeError> {
+    let result = match streams {
+        Some(streams) => streams.call(builtin, arguments),
+        None => Streams::standard().call(builtin, arguments),
+    };
+    result.map_err(|error| RuntimeError::new(RuntimeErrorKind::Io(error.kind()), span))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bytecode, interpreter::Interpreter, lexer::Lexer, parser::Parser, vm::Vm};
+
+    #[test]
+    fn output_and_input_can_be_redirected() {
+        let source = "\
+fn main() {
+    print(1);
+    let name: string = read_line();
+    println(name);
+    let x: float32 = 1;
+    println(x / 10);
+    println(2 > 1);
+    println(read_line());
+    println(read_line());
+}";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        assert!(program.typecheck().errors.is_empty());
+        let hir = program.to_hir();
+        let module = bytecode::compile(&hir);
+        let input = "Ada\r\nrest";
+        let expected = "1Ada\n0.1\ntrue\nrest\n\n";
+
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new(&hir);
+        interpreter.redirect(Streams::new(input.as_bytes(), &mut output));
+        assert_eq!(interpreter.call("main", vec![]), Ok(None));
+        drop(interpreter);
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+        let mut output = vec![];
+        let mut vm = Vm::new(&module);
+        vm.redirect(Streams::new(input.as_bytes(), &mut output));
+        assert_eq!(vm.call("main", vec![]), Ok(None));
+        drop(vm);
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn write_errors_stop_the_program() {
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let source = "fn main() { println(1); }";
+        let tokens = Lexer::tokenize(source);
+        let hir = Parser::parse_program(&tokens).unwrap().to_hir();
+        let mut interpreter = Interpreter::new(&hir);
+        interpreter.redirect(Streams::new(io::empty(), Closed));
+        let error = interpreter.call("main", vec![]).unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::Io(io::ErrorKind::BrokenPipe));
+        assert_eq!(&source[error.span.range()], "println(1)");
+        assert_eq!(error.to_string(), "I/O error: broken pipe");
+    }
+}
@@ -0,0 +1,710 @@
+use crate::{
+    ast::{BinaryOperator, NodeId, Span, Symbol, TypeKind},
+    builtin::Builtin,
+    consteval::ConstValue,
+    hir::{self, Binding, Expression, ExpressionKind, Statement},
+    typecheck::Ty,
+    value::Value,
+};
+use std::{collections::HashMap, fmt};
+
+// A bytecode instruction for a stack machine. Operands are indices into the
+// module's constant pool or function table, the running function's local
+// slots or captured values, or its code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // Pushes a constant from the pool.
+    Constant(u32),
+    // Pushes the value of a local slot. Parameters are the first slots.
+    Local(u32),
+    // Pops a value into a local slot.
+    SetLocal(u32),
+    // Pushes the value of a global, one of the `let` bindings at the top
+    // level of the program.
+    Global(u32),
+    SetGlobal(u32),
+    // Pushes one of the values the running function captured from the
+    // functions enclosing it.
+    Capture(u32),
+    // Pushes a function that captures nothing.
+    Function(FunctionId),
+    // Pops the values a nested function captures, in the order of its
+    // `captures`, and pushes the function with them.
+    Closure(FunctionId),
+    // Pushes the running function, for a nested function that calls itself.
+    Callee,
+    // Pop the right operand, then the left, and push the result.
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    // Converts the value on top of the stack as an `as` cast does.
+    Cast(TypeKind),
+    // Pops that many arguments and then the function to call, and pushes
+    // what it returns unless it returns unit.
+    Call(u32),
+    // Pops a builtin's arguments, and pushes what it returns unless it
+    // returns unit.
+    Builtin(Builtin),
+    Pop,
+    // Continues at an index in the running function's code.
+    Jump(u32),
+    // Pops a `bool` and jumps if it is false.
+    JumpIfFalse(u32),
+    // Pops the return value and returns it to the caller.
+    Return,
+    // Returns from a function that returns unit.
+    ReturnUnit,
+}
+
+impl Instruction {
+    fn binary(operator: &BinaryOperator) -> Instruction {
+        match operator {
+            BinaryOperator::Plus => Instruction::Add,
+            BinaryOperator::Minus => Instruction::Subtract,
+            BinaryOperator::Star => Instruction::Multiply,
+            BinaryOperator::Divide => Instruction::Divide,
+            BinaryOperator::Power => Instruction::Power,
+            BinaryOperator::Equal => Instruction::Equal,
+            BinaryOperator::NotEqual => Instruction::NotEqual,
+            BinaryOperator::Less => Instruction::Less,
+            BinaryOperator::LessEqual => Instruction::LessEqual,
+            BinaryOperator::Greater => Instruction::Greater,
+            BinaryOperator::GreaterEqual => Instruction::GreaterEqual,
+        }
+    }
+
+    // Returns the operator of an arithmetic or comparison instruction.
+    pub fn operator(&self) -> Option<BinaryOperator> {
+        Some(match self {
+            Instruction::Add => BinaryOperator::Plus,
+            Instruction::Subtract => BinaryOperator::Minus,
+            Instruction::Multiply => BinaryOperator::Star,
+            Instruction::Divide => BinaryOperator::Divide,
+            Instruction::Power => BinaryOperator::Power,
+            Instruction::Equal => BinaryOperator::Equal,
+            Instruction::NotEqual => BinaryOperator::NotEqual,
+            Instruction::Less => BinaryOperator::Less,
+            Instruction::LessEqual => BinaryOperator::LessEqual,
+            Instruction::Greater => BinaryOperator::Greater,
+            Instruction::GreaterEqual => BinaryOperator::GreaterEqual,
+            _ => return None,
+        })
+    }
+}
+
+// Identifies a function in a module's function table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FunctionId(pub u32);
+
+impl FunctionId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: Symbol,
+    pub arity: u32,
+    // The number of local slots, parameters included.
+    pub locals: u32,
+    // The parameters and `let` bindings held in local slots, with where in
+    // the code each is in scope, in the order they are declared.
+    pub variables: Vec<Variable>,
+    // The bindings of enclosing functions the function uses, in the order
+    // `Capture` numbers them.
+    pub captures: Vec<Binding>,
+    // False for a function declared without a body, whose code is empty.
+    pub defined: bool,
+    pub code: Vec<Instruction>,
+    // The source span of each instruction, for reporting runtime errors.
+    pub spans: Vec<Span>,
+}
+
+// A binding held in a local slot, and the instructions where it is in
+// scope: from the one after the slot is first set to the end of the block
+// declaring the binding. Parameters are in scope throughout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub binding: Binding,
+    pub slot: u32,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Variable {
+    pub fn in_scope(&self, ip: usize) -> bool {
+        (self.start as usize..self.end as usize).contains(&ip)
+    }
+}
+
+// A compiled program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub constants: Vec<Value>,
+    // Every function, the top-level ones first in source order.
+    pub functions: Vec<Function>,
+    // The number of globals.
+    pub globals: u32,
+    // A function without parameters holding the program's top-level
+    // statements, which initializes the globals.
+    pub main: FunctionId,
+}
+
+impl Module {
+    pub fn function(&self, id: FunctionId) -> &Function {
+        &self.functions[id.index()]
+    }
+
+    // Returns the top-level function with a name.
+    pub fn find(&self, name: &str) -> Option<FunctionId> {
+        (0..self.functions.len() as u32)
+            .map(FunctionId)
+            .find(|id| id != &self.main && self.function(*id).name == name)
+    }
+}
+
+// Compiles a lowered program to bytecode.
+//
+// Every `let` binding gets a slot of its own in the function it is declared
+// in, or a global at the top level. A nested function captures the values
+// of the enclosing bindings it uses when its declaration runs; since
+// bindings are never assigned after they are defined, a copy is as good as
+// a reference.
+//
+// The program must be free of errors: it panics on `ExpressionKind::Error`.
+pub fn compile(program: &hir::Program) -> Module {
+    let mut compiler = Compiler {
+        constants: vec![],
+        constant_ids: HashMap::new(),
+        functions: vec![],
+        globals: HashMap::new(),
+        top_level: HashMap::new(),
+        states: vec![],
+    };
+    // Top-level functions can be used before their declaration, so they are
+    // numbered first.
+    for statement in &program.statements {
+        match statement {
+            Statement::Function(function) => {
+                let id = compiler.reserve_function(function);
+                compiler.top_level.insert(function.binding.id, id);
+            }
+            Statement::Let(let_statement) => {
+                let global = compiler.globals.len() as u32;
+                compiler.globals.insert(let_statement.binding.id, global);
+            }
+            _ => {}
+        }
+    }
+    let main = compiler.reserve(Symbol::new("<main>"), 0);
+    compiler.states.push(State::new(None));
+    compiler.statements(&program.statements);
+    compiler.emit(Instruction::ReturnUnit, Span::new(0, 0));
+    compiler.finish(main, true);
+    Module {
+        constants: compiler.constants,
+        functions: compiler.functions,
+        globals: compiler.globals.len() as u32,
+        main,
+    }
+}
+
+struct Compiler {
+    constants: Vec<Value>,
+    // Literals already in the pool, by type and value.
+    constant_ids: HashMap<(TypeKind, i128), u32>,
+    functions: Vec<Function>,
+    globals: HashMap<NodeId, u32>,
+    top_level: HashMap<NodeId, FunctionId>,
+    // The functions being compiled, innermost last.
+    states: Vec<State>,
+}
+
+struct State {
+    // The binding of a nested function, which it refers to itself by.
+    binding: Option<Binding>,
+    locals: HashMap<NodeId, u32>,
+    variables: Vec<Variable>,
+    // The indices in `variables` of those whose scope has not ended yet.
+    open: Vec<usize>,
+    captures: Vec<Binding>,
+    code: Vec<Instruction>,
+    spans: Vec<Span>,
+}
+
+impl State {
+    fn new(binding: Option<Binding>) -> State {
+        State {
+            binding,
+            locals: HashMap::new(),
+            variables: vec![],
+            open: vec![],
+            captures: vec![],
+            code: vec![],
+            spans: vec![],
+        }
+    }
+
+    // Gives a binding a slot, in scope from the next instruction on.
+    fn declare(&mut self, binding: Binding) -> u32 {
+        let slot = self.locals.len() as u32;
+        self.locals.insert(binding.id, slot);
+        let start = self.code.len() as u32;
+        self.open.push(self.variables.len());
+        self.variables.push(Variable {
+            binding,
+            slot,
+            start,
+            end: start,
+        });
+        slot
+    }
+
+    // Ends the scope of the variables declared since `open` had `mark`
+    // entries.
+    fn close(&mut self, mark: usize) {
+        let end = self.code.len() as u32;
+        for i in self.open.drain(mark..) {
+            self.variables[i].end = end;
+        }
+    }
+}
+
+impl Compiler {
+    // Adds a function to the table, to be filled in once it is compiled.
+    fn reserve(&mut self, name: Symbol, arity: u32) -> FunctionId {
+        self.functions.push(Function {
+            name,
+            arity,
+            locals: 0,
+            variables: vec![],
+            captures: vec![],
+            defined: false,
+            code: vec![],
+            spans: vec![],
+        });
+        FunctionId(self.functions.len() as u32 - 1)
+    }
+
+    fn reserve_function(&mut self, function: &hir::Function) -> FunctionId {
+        self.reserve(
+            function.binding.name.clone(),
+            function.parameters.len() as u32,
+        )
+    }
+
+    // Moves the innermost function being compiled into its table entry.
+    fn finish(&mut self, id: FunctionId, defined: bool) {
+        let mut state = self.states.pop().unwrap();
+        state.close(0);
+        let function = &mut self.functions[id.index()];
+        function.locals = state.locals.len() as u32;
+        function.variables = state.variables;
+        function.captures = state.captures;
+        function.defined = defined;
+        function.code = state.code;
+        function.spans = state.spans;
+    }
+
+    fn state(&mut self) -> &mut State {
+        self.states.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        let state = self.state();
+        state.code.push(instruction);
+        state.spans.push(span);
+        state.code.len() - 1
+    }
+
+    // Returns the index the next instruction will have.
+    fn here(&mut self) -> u32 {
+        self.state().code.len() as u32
+    }
+
+    // Points a jump emitted earlier at the next instruction.
+    fn patch(&mut self, jump: usize) {
+        let target = self.here();
+        match &mut self.state().code[jump] {
+            Instruction::Jump(to) | Instruction::JumpIfFalse(to) => *to = target,
+            _ => unreachable!("not a jump"),
+        }
+    }
+
+    fn constant(&mut self, kind: TypeKind, value: ConstValue) -> u32 {
+        let key = match value {
+            ConstValue::Integer(value) => (kind, value),
+            ConstValue::Bool(value) => (kind, value.into()),
+        };
+        if let Some(id) = self.constant_ids.get(&key) {
+            return *id;
+        }
+        let value = Value::from_const(value, kind).expect("literal of the wrong type");
+        self.constants.push(value);
+        let id = self.constants.len() as u32 - 1;
+        self.constant_ids.insert(key, id);
+        id
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    // Compiles the statements of a block, whose bindings go out of scope at
+    // its end.
+    fn block(&mut self, block: &hir::Block) {
+        let mark = self.state().open.len();
+        self.statements(&block.statements);
+        self.state().close(mark);
+    }
+
+    // Pops a value into a new local slot for a binding.
+    fn set_local(&mut self, binding: Binding, span: Span) {
+        let slot = self.state().locals.len() as u32;
+        self.emit(Instruction::SetLocal(slot), span);
+        self.state().declare(binding);
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let(let_statement) => {
+                self.expression(&let_statement.value);
+                match self.globals.get(&let_statement.binding.id) {
+                    Some(global) => {
+                        self.emit(Instruction::SetGlobal(*global), let_statement.span);
+                    }
+                    None => self.set_local(let_statement.binding.clone(), let_statement.span),
+                }
+            }
+            Statement::Function(function) => {
+                if let Some(id) = self.top_level.get(&function.binding.id).copied() {
+                    self.function(id, function, None);
+                } else {
+                    let id = self.reserve_function(function);
+                    self.function(id, function, Some(function.binding.clone()));
+                    // Load the captured values where the declaration is.
+                    for binding in self.functions[id.index()].captures.clone() {
+                        let instruction = self.load(&binding);
+                        self.emit(instruction, function.span);
+                    }
+                    self.emit(Instruction::Closure(id), function.span);
+                    self.set_local(function.binding.clone(), function.span);
+                }
+            }
+            Statement::Expression(expression) => {
+                self.expression(expression);
+                if !is_unit(&expression.ty) {
+                    self.emit(Instruction::Pop, expression.span);
+                }
+            }
+            Statement::Return(return_statement) => match &return_statement.value {
+                Some(value) => {
+                    self.expression(value);
+                    self.emit(Instruction::Return, return_statement.span);
+                }
+                None => {
+                    self.emit(Instruction::ReturnUnit, return_statement.span);
+                }
+            },
+            Statement::If(if_statement) => {
+                self.expression(&if_statement.condition);
+                let span = if_statement.condition.span;
+                let to_else = self.emit(Instruction::JumpIfFalse(0), span);
+                self.block(&if_statement.then_block);
+                match &if_statement.else_block {
+                    Some(else_block) => {
+                        let to_end = self.emit(Instruction::Jump(0), if_statement.span);
+                        self.patch(to_else);
+                        self.block(else_block);
+                        self.patch(to_end);
+                    }
+                    None => self.patch(to_else),
+                }
+            }
+            Statement::While(while_statement) => {
+                let start = self.here();
+                self.expression(&while_statement.condition);
+                let span = while_statement.condition.span;
+                let to_end = self.emit(Instruction::JumpIfFalse(0), span);
+                self.block(&while_statement.body);
+                self.emit(Instruction::Jump(start), while_statement.span);
+                self.patch(to_end);
+            }
+            Statement::Block(block) => self.block(block),
+        }
+    }
+
+    // Compiles a function into its reserved entry. `binding` is the binding
+    // of a nested function.
+    fn function(&mut self, id: FunctionId, function: &hir::Function, binding: Option<Binding>) {
+        let mut state = State::new(binding);
+        for parameter in &function.parameters {
+            state.declare(parameter.binding.clone());
+        }
+        self.states.push(state);
+        if let Some(body) = &function.body {
+            self.statements(&body.statements);
+        }
+        self.finish(id, function.body.is_some());
+    }
+
+    // Returns the instruction that pushes the value of a binding in the
+    // innermost function, capturing it if it belongs to an enclosing one.
+    fn load(&mut self, binding: &Binding) -> Instruction {
+        if let Some(global) = self.globals.get(&binding.id) {
+            return Instruction::Global(*global);
+        }
+        if let Some(id) = self.top_level.get(&binding.id) {
+            return Instruction::Function(*id);
+        }
+        self.load_in(self.states.len() - 1, binding)
+    }
+
+    fn load_in(&mut self, depth: usize, binding: &Binding) -> Instruction {
+        let state = &mut self.states[depth];
+        if let Some(slot) = state.locals.get(&binding.id) {
+            return Instruction::Local(*slot);
+        }
+        if state.binding.as_ref() == Some(binding) {
+            return Instruction::Callee;
+        }
+        if let Some(i) = state.captures.iter().position(|b| b == binding) {
+            return Instruction::Capture(i as u32);
+        }
+        assert!(depth > 0, "`{}` is not in scope", binding.name);
+        state.captures.push(binding.clone());
+        Instruction::Capture(state.captures.len() as u32 - 1)
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        let span = expression.span;
+        match &expression.kind {
+            ExpressionKind::Integer(value) => {
+                let id = self.constant(kind(&expression.ty), ConstValue::Integer(*value));
+                self.emit(Instruction::Constant(id), span);
+            }
+            ExpressionKind::Bool(value) => {
+                let id = self.constant(TypeKind::Bool, ConstValue::Bool(*value));
+                self.emit(Instruction::Constant(id), span);
+            }
+            ExpressionKind::Name(binding) => {
+                let instruction = self.load(binding);
+                self.emit(instruction, span);
+            }
+            ExpressionKind::Binary(operator, left, right) => {
+                self.expression(left);
+                self.expression(right);
+                self.emit(Instruction::binary(operator), span);
+            }
+            ExpressionKind::Call(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                self.emit(Instruction::Call(arguments.len() as u32), span);
+            }
+            ExpressionKind::Builtin(builtin, arguments) => {
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                self.emit(Instruction::Builtin(*builtin), span);
+            }
+            ExpressionKind::Cast(operand) => {
+                self.expression(operand);
+                self.emit(Instruction::Cast(kind(&expression.ty)), span);
+            }
+            ExpressionKind::Error => panic!("cannot compile a program with errors"),
+        }
+    }
+}
+
+// Returns the primitive type of a value. Integer literals whose width nothing
+// fixed are `int32`, as the type checker defaults them.
+fn kind(ty: &Ty) -> TypeKind {
+    match ty {
+        Ty::Primitive(kind) => *kind,
+        Ty::IntegerLiteral => TypeKind::Int32,
+        _ => panic!("`{}` is not a primitive type", ty),
+    }
+}
+
+fn is_unit(ty: &Ty) -> bool {
+    *ty == Ty::Primitive(TypeKind::Unit)
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Constant(id) => write!(f, "constant {}", id),
+            Instruction::Local(slot) => write!(f, "local {}", slot),
+            Instruction::SetLocal(slot) => write!(f, "set-local {}", slot),
+            Instruction::Global(global) => write!(f, "global {}", global),
+            Instruction::SetGlobal(global) => write!(f, "set-global {}", global),
+            Instruction::Capture(i) => write!(f, "capture {}", i),
+            Instruction::Function(id) => write!(f, "function {}", id.0),
+            Instruction::Closure(id) => write!(f, "closure {}", id.0),
+            Instruction::Callee => f.write_str("callee"),
+            Instruction::Cast(kind) => write!(f, "cast {}", kind),
+            Instruction::Call(arguments) => write!(f, "call {}", arguments),
+            Instruction::Builtin(builtin) => write!(f, "builtin {}", builtin.name()),
+            Instruction::Pop => f.write_str("pop"),
+            Instruction::Jump(to) => write!(f, "jump {}", to),
+            Instruction::JumpIfFalse(to) => write!(f, "jump-if-false {}", to),
+            Instruction::Return => f.write_str("return"),
+            Instruction::ReturnUnit => f.write_str("return-unit"),
+            operator => f.write_str(match operator {
+                Instruction::Add => "add",
+                Instruction::Subtract => "subtract",
+                Instruction::Multiply => "multiply",
+                Instruction::Divide => "divide",
+                Instruction::Power => "power",
+                Instruction::Equal => "equal",
+                Instruction::NotEqual => "not-equal",
+                Instruction::Less => "less",
+                Instruction::LessEqual => "less-equal",
+                Instruction::Greater => "greater",
+                _ => "greater-equal",
+            }),
+        }
+    }
+}
+
+// A module is disassembled as its constant pool followed by each function,
+// one instruction per line, numbered by index:
+//
+//   constants:
+//       0: int32 1
+//
+//   fn 0 f (1 parameter, 1 local, 0 captures):
+//       0: local 0
+//       1: constant 0
+//       2: add
+//       3: return
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "constants:")?;
+        for (i, constant) in self.constants.iter().enumerate() {
+            writeln!(f, "    {}: {} {}", i, constant.type_name(), constant)?;
+        }
+        for (i, function) in self.functions.iter().enumerate() {
+            let plural = |n: usize| if n == 1 { "" } else { "s" };
+            writeln!(f)?;
+            write!(
+                f,
+                "fn {} {} ({} parameter{}, {} local{}, {} capture{})",
+                i,
+                function.name,
+                function.arity,
+                plural(function.arity as usize),
+                function.locals,
+                plural(function.locals as usize),
+                function.captures.len(),
+                plural(function.captures.len()),
+            )?;
+            if !function.defined {
+                writeln!(f, " without a body")?;
+                continue;
+            }
+            writeln!(f, ":")?;
+            for (i, instruction) in function.code.iter().enumerate() {
+                writeln!(f, "    {}: {}", i, instruction)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn compile_source(source: &str) -> Module {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        assert!(program.typecheck().errors.is_empty());
+        compile(&program.to_hir())
+    }
+
+    #[test]
+    fn instructions_are_compact() {
+        assert!(std::mem::size_of::<Instruction>() <= 8);
+    }
+
+    #[test]
+    fn functions_compile_to_stack_code() {
+        let module = compile_source(
+            "\
+let base: int64 = 10;
+fn sum(n: int64) -> int64 {
+    let total: int64 = 0;
+    fn add(x: int64) -> int64 { return x + total + base; }
+    if n > 0 { return add(n) + sum(n - 1); }
+    return total;
+}
+sum(3);",
+        );
+        assert_eq!(module.find("sum"), Some(FunctionId(0)));
+        assert_eq!(
+            module.to_string(),
+            "\
+constants:
+    0: int64 10
+    1: int64 0
+    2: int64 1
+    3: int64 3
+
+fn 0 sum (1 parameter, 3 locals, 0 captures):
+    0: constant 1
+    1: set-local 1
+    2: local 1
+    3: closure 2
+    4: set-local 2
+    5: local 0
+    6: constant 1
+    7: greater
+    8: jump-if-false 19
+    9: local 2
+    10: local 0
+    11: call 1
+    12: function 0
+    13: local 0
+    14: constant 2
+    15: subtract
+    16: call 1
+    17: add
+    18: return
+    19: local 1
+    20: return
+
+fn 1 <main> (0 parameters, 0 locals, 0 captures):
+    0: constant 0
+    1: set-global 0
+    2: function 0
+    3: constant 3
+    4: call 1
+    5: pop
+    6: return-unit
+
+fn 2 add (1 parameter, 1 local, 1 capture):
+    0: local 0
+    1: capture 0
+    2: add
+    3: global 0
+    4: add
+    5: return
+"
+        );
+    }
+}
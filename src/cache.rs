@@ -0,0 +1,240 @@
+use crate::{
+    ast::{Program, Span},
+    database::Lexeme,
+    diagnostic::Diagnostic,
+    session::Session,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// A cache on disk of what checking a source found, so that checking a file
+// that has not changed since the last run reads a file instead of lexing,
+// parsing and checking it again.
+//
+// An entry holds the tokens of a source, its syntax tree and the
+// diagnostics checking it reported. It is keyed by a hash of the source, the
+// lint levels it was checked with and the compiler's version, and stored as
+// JSON in a file named after the key. The hash is short, so two checks may
+// share a key: the entry also holds what was hashed, and is only used for a
+// check of the same. Entries are never invalidated: an edited source has
+// another key, and the old entry is left for a `clean` to remove with the
+// rest of the target directory.
+//
+// Whatever goes wrong with the cache, such as an entry that cannot be read
+// or written or that a different version wrote, is treated as a miss.
+
+// The version of the entries' format, part of every key.
+const FORMAT: u32 = 2;
+
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+// What checking a source found.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    // What the key is a hash of: the compiler's version, the lint levels
+    // and the source.
+    pub version: String,
+    pub lints: String,
+    pub source: String,
+    pub tokens: Vec<Lexeme>,
+    // The syntax tree, if the source parses.
+    pub program: Option<Program<'static>>,
+    // The diagnostics, in the order they were reported, at the levels the
+    // lints gave them.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Cache {
+    // A cache with its entries in `dir`, which is created when the first
+    // entry is stored.
+    pub fn new(dir: impl Into<PathBuf>) -> Cache {
+        Cache { dir: dir.into() }
+    }
+
+    // A cache in a directory of its own in a build's target directory.
+    pub fn in_target_dir(target_dir: impl AsRef<Path>) -> Cache {
+        Cache::new(target_dir.as_ref().join("mylang2-cache"))
+    }
+
+    // The key of a session's source, as checked with its lint levels.
+    pub fn key(session: &Session) -> String {
+        let mut hash = Fnv::default();
+        hash.write(&FORMAT.to_le_bytes());
+        hash.write(VERSION.as_bytes());
+        hash.write(lints(session).as_bytes());
+        hash.write(session.source().as_bytes());
+        format!("{:016x}", hash.0)
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension("json")
+    }
+
+    // Loads the entry for a session's source, as checked with its lint
+    // levels. An entry for another source or other levels with the same key
+    // is a miss.
+    pub fn load(&self, session: &Session) -> Option<Entry> {
+        let bytes = fs::read(self.path(&Cache::key(session))).ok()?;
+        let entry: Entry = serde_json::from_slice(&bytes).ok()?;
+        let same = entry.version == VERSION
+            && entry.lints == lints(session)
+            && entry.source == session.source();
+        same.then_some(entry)
+    }
+
+    // Stores an entry, writing it to a temporary file first so that a
+    // concurrent `load` never reads half of it.
+    pub fn store(&self, key: &str, entry: &Entry) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let json = serde_json::to_vec(entry).map_err(io::Error::other)?;
+        let temporary = self.dir.join(format!("{}.{}.tmp", key, std::process::id()));
+        fs::write(&temporary, json)?;
+        fs::rename(&temporary, self.path(key))
+    }
+
+    // Checks a session's source as `Session::parse` and `Session::check`
+    // would, reporting the diagnostics to the session, or restores what an
+    // earlier check of the same source found. Only the tokens, the syntax
+    // tree and the diagnostics are kept: a caller that needs the HIR or the
+    // type tables checks the program again.
+    pub fn check(&self, session: &mut Session) -> Entry {
+        if let Some(entry) = self.load(session) {
+            session.restore(entry.diagnostics.iter().cloned());
+            return entry;
+        }
+        let tokens = session
+            .tokenize()
+            .iter()
+            .map(|token| Lexeme {
+                kind: token.kind(),
+                span: Span::new(token.offset(), token.offset() + token.len()),
+            })
+            .collect();
+        let start = session.diagnostics().len();
+        let program = session.parse();
+        // A program that imports modules is checked linked with them, and is
+        // not stored, as the key does not cover their sources.
+        let imports = program
+            .as_ref()
+            .is_some_and(|p| p.imports().next().is_some());
+        if let Some(program) = program.clone().and_then(|p| session.link(p)) {
+            session.check(&program);
+        }
+        let entry = Entry {
+            version: VERSION.to_string(),
+            lints: lints(session),
+            source: session.source().to_string(),
+            tokens,
+            program,
+            diagnostics: session.diagnostics()[start..].to_vec(),
+        };
+        // A cache that cannot be written only makes the next check slower.
+        if !imports {
+            let _ = self.store(&Cache::key(session), &entry);
+        }
+        entry
+    }
+}
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// The lint levels of a session, in a fixed order.
+fn lints(session: &Session) -> String {
+    let mut lints: Vec<_> = session.options.lints.iter().collect();
+    lints.sort();
+    format!("{:?}", lints)
+}
+
+// The 64-bit FNV-1a hash, which unlike `DefaultHasher` is the same in every
+// build, as keys must be.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lint::{self, Level, LintLevels},
+        pipeline::CompileOptions,
+        sexp::sexp,
+    };
+
+    fn session(source: &str, lints: &LintLevels) -> Session {
+        let options = CompileOptions {
+            lints: lints.clone(),
+            ..CompileOptions::default()
+        };
+        Session::new(source, options)
+    }
+
+    #[test]
+    fn unchanged_sources_are_checked_once() {
+        let dir = std::env::temp_dir().join(format!("mylang2-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = Cache::in_target_dir(&dir);
+        let source = "let unused: int8 = 1;\nprintln(y);\n";
+        let lints = LintLevels::new();
+
+        let mut first = session(source, &lints);
+        let entry = cache.check(&mut first);
+        assert!(cache.load(&first).is_some());
+        let codes: Vec<&str> = entry.diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, ["W0200", "E0200"]);
+        assert_eq!(entry.tokens.len(), first.tokenize().len());
+
+        // A hit restores the same tokens, program and diagnostics.
+        let mut second = session(source, &lints);
+        let hit = cache.check(&mut second);
+        assert_eq!(hit.tokens, entry.tokens);
+        assert_eq!(
+            sexp(hit.program.as_ref().unwrap()),
+            sexp(entry.program.as_ref().unwrap())
+        );
+        assert_eq!(hit.diagnostics, entry.diagnostics);
+        assert_eq!(second.render(), first.render());
+
+        // Other lint levels or another source are another entry.
+        let mut denied = lints.clone();
+        denied.set(&lint::UNUSED, Level::Deny);
+        assert_ne!(Cache::key(&session(source, &denied)), Cache::key(&first));
+        let mut edited = session("fn f( {", &lints);
+        let entry = cache.check(&mut edited);
+        assert!(entry.program.is_none());
+        assert_eq!(entry.diagnostics.len(), 1);
+
+        // A corrupt entry is a miss, and is replaced.
+        let key = Cache::key(&first);
+        fs::write(cache.path(&key), "{").unwrap();
+        assert!(cache.load(&first).is_none());
+        cache.check(&mut session(source, &lints));
+        assert!(cache.load(&first).is_some());
+
+        // So is the entry of another source that has the same key.
+        let other = session("println(1);\n", &lints);
+        let key = Cache::key(&other);
+        fs::copy(cache.path(&Cache::key(&first)), cache.path(&key)).unwrap();
+        assert!(cache.load(&other).is_none());
+        let mut other = session("println(1);\n", &lints);
+        assert_eq!(cache.check(&mut other).diagnostics, []);
+        assert_eq!(cache.load(&other).unwrap().source, "println(1);\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
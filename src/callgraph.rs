@@ -0,0 +1,433 @@
+use crate::{
+    ast::{Expression, Identifier, NodeId, NodeMap, Program, Span, Statement, Symbol},
+    diagnostic::Diagnostic,
+    resolver::{DeclarationKind, Resolution},
+    visit::{self, Control, Visitor},
+};
+use std::fmt::Write;
+
+// A function in the call graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    // The id of the function's declaring identifier, as in `Resolution`.
+    pub id: NodeId,
+    pub name: Symbol,
+    // The span of the declaring identifier.
+    pub span: Span,
+    // Whether the function has a body. Functions without one are defined
+    // outside the program and have no calls of their own.
+    pub defined: bool,
+}
+
+// A reference from the body of one function to another. References that are
+// not calls, such as passing a function as an argument, count too: the
+// function may be called through them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Call {
+    pub caller: NodeId,
+    pub callee: NodeId,
+    // The span of the callee's name at the call site.
+    pub span: Span,
+}
+
+// Which functions refer to which, across the whole program. Nested functions
+// are functions of their own, so a call from a nested function is not a
+// call from the function it is nested in. Calls from outside any function,
+// such as in the initializer of a global, are not part of the graph.
+#[derive(Debug, Clone, Default)]
+pub struct CallGraph {
+    // Every function, in source order.
+    functions: Vec<Function>,
+    // The index of each function in `functions`.
+    indices: NodeMap<usize>,
+    // Every call, in source order.
+    calls: Vec<Call>,
+}
+
+impl CallGraph {
+    pub fn build(program: &Program, resolution: &Resolution) -> CallGraph {
+        let mut builder = Builder {
+            resolution,
+            graph: CallGraph::default(),
+            caller: None,
+        };
+        visit::walk_program(&mut builder, program);
+        builder.graph
+    }
+
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    pub fn function(&self, id: NodeId) -> Option<&Function> {
+        self.indices.get(id).map(|&index| &self.functions[index])
+    }
+
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    // Returns the functions a function refers to, each once, in the order
+    // of their first call.
+    pub fn callees(&self, caller: NodeId) -> Vec<NodeId> {
+        let mut callees = vec![];
+        for call in self.calls.iter().filter(|c| c.caller == caller) {
+            if !callees.contains(&call.callee) {
+                callees.push(call.callee);
+            }
+        }
+        callees
+    }
+
+    // Returns the functions that refer to a function, each once, in the
+    // order of their first call.
+    pub fn callers(&self, callee: NodeId) -> Vec<NodeId> {
+        let mut callers = vec![];
+        for call in self.calls.iter().filter(|c| c.callee == callee) {
+            if !callers.contains(&call.caller) {
+                callers.push(call.caller);
+            }
+        }
+        callers
+    }
+
+    // Returns whether a function can end up calling itself, directly or
+    // through other functions.
+    pub fn is_recursive(&self, id: NodeId) -> bool {
+        self.cycles().iter().any(|cycle| cycle.contains(&id))
+    }
+
+    // Returns the groups of functions that call each other recursively: the
+    // strongly connected components of the graph that contain a call. A
+    // function calling only itself forms a group of one. Groups and the
+    // functions in them are in source order.
+    pub fn cycles(&self) -> Vec<Vec<NodeId>> {
+        let mut tarjan = Tarjan {
+            successors: self
+                .functions
+                .iter()
+                .map(|f| {
+                    self.callees(f.id)
+                        .iter()
+                        .map(|id| *self.indices.get(*id).unwrap())
+                        .collect()
+                })
+                .collect(),
+            next: 0,
+            order: vec![None; self.functions.len()],
+            low: vec![0; self.functions.len()],
+            stack: vec![],
+            on_stack: vec![false; self.functions.len()],
+            components: vec![],
+        };
+        for index in 0..self.functions.len() {
+            if tarjan.order[index].is_none() {
+                tarjan.visit(index);
+            }
+        }
+        let mut cycles: Vec<Vec<usize>> = tarjan
+            .components
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1 || tarjan.successors[component[0]].contains(&component[0])
+            })
+            .collect();
+        for cycle in &mut cycles {
+            cycle.sort_unstable();
+        }
+        cycles.sort_unstable();
+        cycles
+            .into_iter()
+            .map(|cycle| cycle.into_iter().map(|i| self.functions[i].id).collect())
+            .collect()
+    }
+
+    // Reports every recursive function, for programs that must not recurse.
+    // Each group of mutually recursive functions is reported once, at its
+    // first function, with a label at a call that keeps the cycle going from
+    // each function in the group.
+    pub fn check_recursion(&self) -> Vec<Diagnostic> {
+        self.cycles()
+            .iter()
+            .map(|cycle| {
+                let first = self.function(cycle[0]).unwrap();
+                let mut diagnostic = if cycle.len() == 1 {
+                    Diagnostic::error(
+                        "E0500",
+                        format!("function `{}` calls itself", first.name),
+                        first.span,
+                    )
+                } else {
+                    let names: Vec<String> = cycle
+                        .iter()
+                        .map(|id| format!("`{}`", self.function(*id).unwrap().name))
+                        .collect();
+                    let (last, rest) = names.split_last().unwrap();
+                    Diagnostic::error(
+                        "E0501",
+                        format!(
+                            "functions {} and {} are mutually recursive",
+                            rest.join(", "),
+                            last
+                        ),
+                        first.span,
+                    )
+                };
+                for caller in cycle {
+                    let call = self
+                        .calls
+                        .iter()
+                        .find(|c| c.caller == *caller && cycle.contains(&c.callee))
+                        .unwrap();
+                    diagnostic = diagnostic.with_label(
+                        call.span,
+                        format!(
+                            "`{}` calls `{}` here",
+                            self.function(call.caller).unwrap().name,
+                            self.function(call.callee).unwrap().name
+                        ),
+                    );
+                }
+                diagnostic.with_note("recursion is not allowed in this program")
+            })
+            .collect()
+    }
+
+    // Returns the graph in Graphviz's DOT language. Each function is a node
+    // named after its id, since nested functions can share a name, and each
+    // pair of caller and callee is one edge.
+    pub fn to_dot(&self) -> String {
+        let mut output = String::from("digraph calls {\n");
+        for function in &self.functions {
+            let style = if function.defined {
+                ""
+            } else {
+                ", style=dashed"
+            };
+            writeln!(
+                output,
+                "    n{} [label=\"{}\"{}];",
+                function.id.0, function.name, style
+            )
+            .unwrap();
+        }
+        for function in &self.functions {
+            for callee in self.callees(function.id) {
+                writeln!(output, "    n{} -> n{};", function.id.0, callee.0).unwrap();
+            }
+        }
+        output.push_str("}\n");
+        output
+    }
+}
+
+struct Builder<'r> {
+    resolution: &'r Resolution,
+    graph: CallGraph,
+    // The function whose body is being walked.
+    caller: Option<NodeId>,
+}
+
+impl<'ast> Visitor<'ast> for Builder<'_> {
+    fn visit_statement(&mut self, statement: &'ast Statement<'ast>) -> Control {
+        let Statement::FunctionDeclaration(function) = statement else {
+            return Control::Continue;
+        };
+        let id = function.identifier.id;
+        self.graph.indices.insert(id, self.graph.functions.len());
+        self.graph.functions.push(Function {
+            id,
+            name: function.identifier.name.clone(),
+            span: function.identifier.span,
+            defined: function.body.is_some(),
+        });
+        // Walk the body with this function as the caller, then restore the
+        // enclosing one.
+        let outer = self.caller.replace(id);
+        for statement in function.body.iter().flat_map(|b| &b.statements) {
+            visit::walk_statement(self, statement);
+        }
+        self.caller = outer;
+        Control::SkipChildren
+    }
+
+    fn visit_expression(&mut self, expression: &'ast Expression<'ast>) -> Control {
+        if let (Some(caller), Expression::Identifier(identifier)) = (self.caller, expression) {
+            self.reference(caller, identifier);
+        }
+        Control::Continue
+    }
+}
+
+impl Builder<'_> {
+    fn reference(&mut self, caller: NodeId, identifier: &Identifier) {
+        let Some(&callee) = self.resolution.uses.get(identifier.id) else {
+            return;
+        };
+        let is_function = self
+            .resolution
+            .declarations
+            .get(callee)
+            .is_some_and(|d| d.kind == DeclarationKind::Function);
+        if is_function {
+            self.graph.calls.push(Call {
+                caller,
+                callee,
+                span: identifier.span,
+            });
+        }
+    }
+}
+
+// Tarjan's algorithm for strongly connected components, over the indices of
+// the graph's functions.
+struct Tarjan {
+    successors: Vec<Vec<usize>>,
+    // The next visit number to hand out.
+    next: usize,
+    // The order in which each function was first visited.
+    order: Vec<Option<usize>>,
+    // The lowest visit number reachable from each function.
+    low: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    components: Vec<Vec<usize>>,
+}
+
+impl Tarjan {
+    fn visit(&mut self, index: usize) {
+        self.order[index] = Some(self.next);
+        self.low[index] = self.next;
+        self.next += 1;
+        self.stack.push(index);
+        self.on_stack[index] = true;
+        for i in 0..self.successors[index].len() {
+            let successor = self.successors[index][i];
+            match self.order[successor] {
+                None => {
+                    self.visit(successor);
+                    self.low[index] = self.low[index].min(self.low[successor]);
+                }
+                Some(order) if self.on_stack[successor] => {
+                    self.low[index] = self.low[index].min(order);
+                }
+                Some(_) => {}
+            }
+        }
+        if Some(self.low[index]) == self.order[index] {
+            let mut component = vec![];
+            while let Some(member) = self.stack.pop() {
+                self.on_stack[member] = false;
+                component.push(member);
+                if member == index {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn graph(source: &str, test: impl FnOnce(&CallGraph, &dyn Fn(&str) -> NodeId)) {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let graph = program.call_graph();
+        let id = |name: &str| {
+            graph
+                .functions()
+                .iter()
+                .find(|f| f.name == name)
+                .unwrap()
+                .id
+        };
+        test(&graph, &id);
+    }
+
+    #[test]
+    fn calls_are_recorded_per_function() {
+        let source = "\
+fn main() { helper(1); helper(2); apply(helper); fn inner() { main(); } }
+fn helper(x: int32) -> int32 { return external(x); }
+fn apply(f: fn(int32) -> int32) { f(1); }
+fn external(x: int32) -> int32;
+let global: int32 = helper(3);";
+        graph(source, |graph, id| {
+            let names = |ids: Vec<NodeId>| -> Vec<Symbol> {
+                ids.iter()
+                    .map(|id| graph.function(*id).unwrap().name.clone())
+                    .collect()
+            };
+            assert_eq!(names(graph.callees(id("main"))), ["helper", "apply"]);
+            assert_eq!(names(graph.callees(id("inner"))), ["main"]);
+            assert_eq!(names(graph.callers(id("helper"))), ["main"]);
+            assert!(graph.callees(id("apply")).is_empty());
+            assert_eq!(graph.calls().len(), 6);
+            assert_eq!(&source[graph.calls()[3].span.range()], "helper");
+            assert_eq!(
+                graph.to_dot(),
+                "\
+digraph calls {
+    n0 [label=\"main\"];
+    n11 [label=\"inner\"];
+    n19 [label=\"helper\"];
+    n30 [label=\"apply\"];
+    n42 [label=\"external\", style=dashed];
+    n0 -> n19;
+    n0 -> n30;
+    n11 -> n0;
+    n19 -> n42;
+}
+"
+            );
+        });
+    }
+
+    #[test]
+    fn recursion_is_detected() {
+        let source = "\
+fn even(n: int32) -> int32 { return odd(n - 1); }
+fn fact(n: int32) -> int32 { return n * fact(n - 1); }
+fn odd(n: int32) -> int32 { return even(n - 1) + third(n); }
+fn third(n: int32) -> int32 { return even(n); }
+fn leaf() { }
+fn main() { even(1); fact(1); leaf(); }";
+        graph(source, |graph, id| {
+            assert_eq!(
+                graph.cycles(),
+                [vec![id("even"), id("odd"), id("third")], vec![id("fact")]]
+            );
+            assert!(graph.is_recursive(id("third")));
+            assert!(!graph.is_recursive(id("main")));
+            assert!(!graph.is_recursive(id("leaf")));
+
+            let diagnostics = graph.check_recursion();
+            assert_eq!(diagnostics.len(), 2);
+            assert_eq!(diagnostics[0].code, "E0501");
+            assert_eq!(
+                diagnostics[0].message,
+                "functions `even`, `odd` and `third` are mutually recursive"
+            );
+            let labels: Vec<&str> = diagnostics[0]
+                .labels
+                .iter()
+                .map(|l| l.message.as_str())
+                .collect();
+            assert_eq!(
+                labels,
+                [
+                    "`even` calls `odd` here",
+                    "`odd` calls `even` here",
+                    "`third` calls `even` here"
+                ]
+            );
+            assert_eq!(diagnostics[1].code, "E0500");
+            assert_eq!(diagnostics[1].message, "function `fact` calls itself");
+            assert_eq!(&source[diagnostics[1].span.range()], "fact");
+        });
+    }
+}
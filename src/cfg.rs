@@ -0,0 +1,341 @@
+use crate::hir::{self, Binding, Expression, Let, Return, Statement};
+use std::fmt;
+
+// Identifies a basic block in a function's control flow graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockId(pub u32);
+
+impl BlockId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+// A statement that does not transfer control.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Let(Let),
+    Expression(Expression),
+}
+
+// How control leaves a basic block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    Goto(BlockId),
+    // Continues at `then_block` if the condition is true and at
+    // `else_block` otherwise.
+    Branch {
+        condition: Expression,
+        then_block: BlockId,
+        else_block: BlockId,
+    },
+    Return(Return),
+    // The end of a block that control never reaches, such as the code after
+    // an `if` whose branches both return.
+    Unreachable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+// The control flow graph of a function's body: straight-line basic blocks
+// linked by the jumps between them. Functions nested in the body have graphs
+// of their own and are not part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+    pub function: Binding,
+    // Indexed by `BlockId`. Control enters at the first block.
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl Cfg {
+    pub const ENTRY: BlockId = BlockId(0);
+
+    // Builds the graph of a function, or returns `None` if it has no body.
+    pub fn build(function: &hir::Function) -> Option<Cfg> {
+        let body = function.body.as_ref()?;
+        let mut builder = Builder {
+            blocks: vec![],
+            current: BlockId(0),
+        };
+        builder.current = builder.new_block();
+        builder.statements(&body.statements);
+        Some(Cfg {
+            function: function.binding.clone(),
+            blocks: builder
+                .blocks
+                .into_iter()
+                .map(|(instructions, terminator)| BasicBlock {
+                    instructions,
+                    terminator: terminator.unwrap_or(Terminator::Unreachable),
+                })
+                .collect(),
+        })
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id.index()]
+    }
+
+    pub fn block_ids(&self) -> impl Iterator<Item = BlockId> {
+        (0..self.blocks.len() as u32).map(BlockId)
+    }
+
+    pub fn successors(&self, id: BlockId) -> Vec<BlockId> {
+        match &self.block(id).terminator {
+            Terminator::Goto(target) => vec![*target],
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::Unreachable => vec![],
+        }
+    }
+
+    // Returns the predecessors of every block, indexed by `BlockId`.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        for id in self.block_ids() {
+            for successor in self.successors(id) {
+                predecessors[successor.index()].push(id);
+            }
+        }
+        predecessors
+    }
+}
+
+// Builds the graph of every function with a body in a program, including
+// nested functions, in source order.
+pub fn build_all(program: &hir::Program) -> Vec<Cfg> {
+    functions(program)
+        .into_iter()
+        .filter_map(Cfg::build)
+        .collect()
+}
+
+// Returns every function in a program, including nested functions, in
+// source order.
+pub(crate) fn functions(program: &hir::Program) -> Vec<&hir::Function> {
+    let mut functions = vec![];
+    collect(&program.statements, &mut functions);
+    functions
+}
+
+fn collect<'p>(statements: &'p [Statement], functions: &mut Vec<&'p hir::Function>) {
+    for statement in statements {
+        match statement {
+            Statement::Function(function) => {
+                functions.push(function);
+                if let Some(body) = &function.body {
+                    collect(&body.statements, functions);
+                }
+            }
+            Statement::If(if_statement) => {
+                collect(&if_statement.then_block.statements, functions);
+                if let Some(else_block) = &if_statement.else_block {
+                    collect(&else_block.statements, functions);
+                }
+            }
+            Statement::While(while_statement) => {
+                collect(&while_statement.body.statements, functions)
+            }
+            Statement::Block(block) => collect(&block.statements, functions),
+            Statement::Let(_) | Statement::Expression(_) | Statement::Return(_) => {}
+        }
+    }
+}
+
+struct Builder {
+    // The instructions of each block, and its terminator once it has one.
+    blocks: Vec<(Vec<Instruction>, Option<Terminator>)>,
+    // The block statements are being added to.
+    current: BlockId,
+}
+
+impl Builder {
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push((vec![], None));
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    // Ends the current block and continues in `next`.
+    fn terminate(&mut self, terminator: Terminator, next: BlockId) {
+        self.blocks[self.current.index()].1 = Some(terminator);
+        self.current = next;
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let(let_statement) => self.blocks[self.current.index()]
+                .0
+                .push(Instruction::Let(let_statement.clone())),
+            Statement::Expression(expression) => self.blocks[self.current.index()]
+                .0
+                .push(Instruction::Expression(expression.clone())),
+            Statement::Function(_) => {}
+            Statement::Return(return_statement) => {
+                // Anything after a return is unreachable; it goes in a block
+                // of its own that nothing jumps to.
+                let next = self.new_block();
+                self.terminate(Terminator::Return(return_statement.clone()), next);
+            }
+            Statement::If(if_statement) => {
+                let then_block = self.new_block();
+                let join = self.new_block();
+                let else_block = match if_statement.else_block {
+                    Some(_) => self.new_block(),
+                    None => join,
+                };
+                let branch = Terminator::Branch {
+                    condition: if_statement.condition.clone(),
+                    then_block,
+                    else_block,
+                };
+                self.terminate(branch, then_block);
+                self.statements(&if_statement.then_block.statements);
+                self.terminate(Terminator::Goto(join), else_block);
+                if let Some(block) = &if_statement.else_block {
+                    self.statements(&block.statements);
+                    self.terminate(Terminator::Goto(join), join);
+                }
+            }
+            Statement::While(while_statement) => {
+                let header = self.new_block();
+                let body = self.new_block();
+                let exit = self.new_block();
+                self.terminate(Terminator::Goto(header), header);
+                let branch = Terminator::Branch {
+                    condition: while_statement.condition.clone(),
+                    then_block: body,
+                    else_block: exit,
+                };
+                self.terminate(branch, body);
+                self.statements(&while_statement.body.statements);
+                self.terminate(Terminator::Goto(header), exit);
+            }
+            Statement::Block(block) => self.statements(&block.statements),
+        }
+    }
+}
+
+// A graph is displayed one block per paragraph, with instructions and
+// terminators in the HIR's s-expression syntax:
+//
+//   bb0:
+//       (let x int64 (int64 1))
+//       branch (< x:int64 (int64 2)) bb1 bb2
+impl fmt::Display for Cfg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, block) in self.blocks.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "{}:", BlockId(i as u32))?;
+            for instruction in &block.instructions {
+                match instruction {
+                    Instruction::Let(let_statement) => {
+                        writeln!(f, "    {}", Statement::Let(let_statement.clone()))?
+                    }
+                    Instruction::Expression(expression) => writeln!(f, "    {}", expression)?,
+                }
+            }
+            match &block.terminator {
+                Terminator::Goto(target) => writeln!(f, "    goto {}", target)?,
+                Terminator::Branch {
+                    condition,
+                    then_block,
+                    else_block,
+                } => writeln!(f, "    branch {} {} {}", condition, then_block, else_block)?,
+                Terminator::Return(Return { value: None, .. }) => writeln!(f, "    return")?,
+                Terminator::Return(Return {
+                    value: Some(value), ..
+                }) => writeln!(f, "    return {}", value)?,
+                Terminator::Unreachable => writeln!(f, "    unreachable")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn graphs(source: &str) -> Vec<Cfg> {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        build_all(&program.to_hir())
+    }
+
+    #[test]
+    fn control_flow_is_split_into_blocks() {
+        let source = "\
+fn f(a: int64) -> int64 {
+    let b: int64 = a + 1;
+    fn g() { }
+    while a < b {
+        if b == 2 { return 1; } else { g(); }
+    }
+    return b;
+}";
+        let graphs = graphs(source);
+        assert_eq!(graphs.len(), 2);
+        assert_eq!(graphs[1].function.name, "g");
+        let graph = &graphs[0];
+        assert_eq!(
+            graph.to_string(),
+            "\
+bb0:
+    (let b int64 (+ a:int64 (int64 1)))
+    goto bb1
+
+bb1:
+    branch (< a:int64 b:int64) bb2 bb3
+
+bb2:
+    branch (== b:int64 (int64 2)) bb4 bb6
+
+bb3:
+    return b:int64
+
+bb4:
+    return (int64 1)
+
+bb5:
+    goto bb1
+
+bb6:
+    (call g:fn() -> ())
+    goto bb5
+
+bb7:
+    goto bb5
+
+bb8:
+    unreachable
+"
+        );
+        assert_eq!(
+            graph.predecessors()[1],
+            [BlockId(0), BlockId(5)],
+            "the loop header is entered from before the loop and from its end"
+        );
+    }
+}
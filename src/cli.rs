@@ -0,0 +1,1116 @@
+use crate::{
+    ast::{Program, Spanned},
+    builtin::Streams,
+    cache::Cache,
+    codegen::{
+        llvm, object,
+        target::{Architecture, Target},
+        wasm, x86_64,
+    },
+    dap, dump,
+    format::{self, BraceStyle, FormatOptions},
+    grammar, highlight, hir,
+    interpreter::{Interpreter, Limits},
+    pipeline::CompileOptions,
+    query::{self, Query},
+    sarif,
+    session::Session,
+    source_map::SourceMap,
+    symbol::sym,
+    value::Value,
+};
+use std::{
+    fmt, fs,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+// The `mylang` command line, which runs the library's stages over a source
+// file:
+//
+//   mylang check <file>      reports the program's errors and warnings
+//   mylang run <file>        runs it with the interpreter
+//   mylang build <file>      compiles it to an executable or other output
+//   mylang fmt <file>        prints it formatted, with its comments
+//   mylang dump-ast <file>   prints its syntax tree
+//   mylang highlight <file>  prints it colored for a terminal or as HTML
+//   mylang query <q> <file>  prints the nodes a path query selects
+//   mylang dap               serves the Debug Adapter Protocol
+//   mylang grammar           prints the language's grammar
+//   mylang --help            prints how to use it
+//   mylang --version         prints its version
+//
+// A file named `-` is read from standard input. `run` exits with what the
+// program's `main` function returns, if it returns an integer, as a native
+// executable would.
+
+pub const USAGE: &str = "\
+usage: mylang <command> [options] <file>
+
+commands:
+  check <file>      report errors and warnings
+      --json            as lines of JSON on standard output
+      --sarif           as a SARIF log on standard output
+      --cache <dir>     keep what checking found in <dir>, and reuse it
+                        while the file is unchanged
+  run <file>        run the program with the interpreter
+  build <file>      compile the program
+      -o <path>         write to <path>, or `-` for standard output
+      --emit <kind>     exe (the default), obj, asm, llvm-ir or wasm
+      --target <triple> compile for another machine than this one
+      -g                include debug info in an exe, obj or asm
+  fmt <file>        print the program formatted
+      --write           replace the file instead
+      --indent <width>  indent by <width> spaces, 4 by default
+      --hard-tabs       indent with tabs
+      --brace-style <style>
+                        same-line (the default) or next-line
+  dump-ast <file>   print the program's syntax tree
+  highlight <file>  print the program colored for a terminal
+      --html            mark it up as HTML instead
+  query <query> <file>
+                    print where the nodes a path query such as
+                    `fn[name=main]//let[mutable]` selects are
+  dap               debug programs from an editor, speaking the Debug
+                    Adapter Protocol on standard input and output
+  grammar           print the grammar the parser accepts, as EBNF
+      --svg <rule>      draw a rule's railroad diagram as SVG instead
+
+options:
+  --timings         after compiling, report how long each phase took on
+                    standard error
+  -h, --help        print this help
+  --version         print mylang's version
+
+A <file> of `-` is read from standard input.
+";
+
+// What the command line asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Check {
+        file: String,
+        report: Report,
+        // The directory of the cache to check through, if any.
+        cache: Option<String>,
+    },
+    Run(String),
+    Build(Build),
+    Fmt {
+        file: String,
+        write: bool,
+        options: FormatOptions,
+    },
+    DumpAst(String),
+    Highlight {
+        file: String,
+        html: bool,
+    },
+    Query {
+        query: String,
+        file: String,
+    },
+    Dap,
+    // Prints the grammar, or the railroad diagram of one of its rules.
+    Grammar {
+        svg: Option<String>,
+    },
+    // Prints the usage, given `-h` or `--help` with any command or none.
+    Help,
+    // Prints mylang's version.
+    Version,
+}
+
+// How `check` reports diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Report {
+    // Rendered with their source, on standard error.
+    Human,
+    // As lines of JSON, on standard output.
+    Json,
+    // As a SARIF log, on standard output.
+    Sarif,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Build {
+    pub file: String,
+    pub output: Option<String>,
+    pub emit: Emit,
+    pub target: Option<String>,
+    pub debug_info: bool,
+}
+
+// What `build` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Executable,
+    Object,
+    Assembly,
+    LlvmIr,
+    Wasm,
+}
+
+impl Emit {
+    const ALL: [Emit; 5] = [
+        Emit::Executable,
+        Emit::Object,
+        Emit::Assembly,
+        Emit::LlvmIr,
+        Emit::Wasm,
+    ];
+
+    fn from_name(name: &str) -> Option<Emit> {
+        Emit::ALL.into_iter().find(|emit| emit.name() == name)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Emit::Executable => "exe",
+            Emit::Object => "obj",
+            Emit::Assembly => "asm",
+            Emit::LlvmIr => "llvm-ir",
+            Emit::Wasm => "wasm",
+        }
+    }
+
+    // The extension of the file written when no output is given.
+    fn extension(&self) -> &'static str {
+        match self {
+            Emit::Executable => "",
+            Emit::Object => "o",
+            Emit::Assembly => "s",
+            Emit::LlvmIr => "ll",
+            Emit::Wasm => "wasm",
+        }
+    }
+}
+
+impl fmt::Display for Emit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// A command line that does not say what to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Command {
+    // Parses the arguments after the program's name.
+    pub fn parse(arguments: &[String]) -> Result<Command, UsageError> {
+        let usage = |message: String| Err(UsageError(message));
+        if arguments
+            .iter()
+            .any(|argument| argument == "-h" || argument == "--help")
+        {
+            return Ok(Command::Help);
+        }
+        if arguments.iter().any(|argument| argument == "--version") {
+            return Ok(Command::Version);
+        }
+        let Some((command, arguments)) = arguments.split_first() else {
+            return usage("no command given".to_string());
+        };
+        let mut file = None;
+        let mut output = None;
+        let mut emit = None;
+        let mut target = None;
+        let mut debug_info = false;
+        let mut write = false;
+        let mut html = false;
+        let mut report = Report::Human;
+        let mut cache = None;
+        let mut svg = None;
+        let mut query = None;
+        let mut options = FormatOptions::default();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            let mut value = |option: &str| match arguments.next() {
+                Some(value) => Ok(value.clone()),
+                None => Err(UsageError(format!("`{}` needs a value", option))),
+            };
+            match argument.as_str() {
+                "-o" if command == "build" => output = Some(value("-o")?),
+                "--emit" if command == "build" => {
+                    let name = value("--emit")?;
+                    let Some(kind) = Emit::from_name(&name) else {
+                        return usage(format!("cannot emit `{}`", name));
+                    };
+                    emit = Some(kind);
+                }
+                "--target" if command == "build" => target = Some(value("--target")?),
+                "-g" if command == "build" => debug_info = true,
+                "--write" if command == "fmt" => write = true,
+                "--indent" if command == "fmt" => {
+                    let width = value("--indent")?;
+                    let Ok(width) = width.parse() else {
+                        return usage(format!("cannot indent by `{}`", width));
+                    };
+                    options.indent_width = width;
+                }
+                "--hard-tabs" if command == "fmt" => options.hard_tabs = true,
+                "--brace-style" if command == "fmt" => {
+                    options.brace_style = match value("--brace-style")?.as_str() {
+                        "same-line" => BraceStyle::SameLine,
+                        "next-line" => BraceStyle::NextLine,
+                        style => return usage(format!("unknown brace style `{}`", style)),
+                    };
+                }
+                "--html" if command == "highlight" => html = true,
+                "--json" | "--sarif" if command == "check" => {
+                    if report != Report::Human {
+                        return usage("`--json` and `--sarif` cannot be combined".to_string());
+                    }
+                    report = match argument.as_str() {
+                        "--json" => Report::Json,
+                        _ => Report::Sarif,
+                    };
+                }
+                "--cache" if command == "check" => cache = Some(value("--cache")?),
+                "--svg" if command == "grammar" => svg = Some(value("--svg")?),
+                "-" => file = Some(argument.clone()),
+                option if option.starts_with('-') => {
+                    return usage(format!("unknown option `{}` for `{}`", option, command))
+                }
+                _ if command == "query" && query.is_none() => query = Some(argument.clone()),
+                _ if file.is_some() => return usage("more than one file given".to_string()),
+                _ => file = Some(argument.clone()),
+            }
+        }
+        if command == "dap" {
+            return match file {
+                Some(_) => usage("`dap` takes no file".to_string()),
+                None => Ok(Command::Dap),
+            };
+        }
+        if command == "grammar" {
+            return match file {
+                Some(_) => usage("`grammar` takes no file".to_string()),
+                None => Ok(Command::Grammar { svg }),
+            };
+        }
+        if command == "query" && query.is_none() {
+            return usage("`query` needs a query".to_string());
+        }
+        let Some(file) = file else {
+            return usage(format!("`{}` needs a file", command));
+        };
+        Ok(match command.as_str() {
+            "check" => Command::Check {
+                file,
+                report,
+                cache,
+            },
+            "run" => Command::Run(file),
+            "build" => Command::Build(Build {
+                file,
+                output,
+                emit: emit.unwrap_or(Emit::Executable),
+                target,
+                debug_info,
+            }),
+            "fmt" if write && file == "-" => {
+                return usage("`--write` needs a file, not standard input".to_string())
+            }
+            "fmt" => Command::Fmt {
+                file,
+                write,
+                options,
+            },
+            "dump-ast" => Command::DumpAst(file),
+            "highlight" => Command::Highlight { file, html },
+            "query" => Command::Query {
+                query: query.unwrap(),
+                file,
+            },
+            _ => return usage(format!("unknown command `{}`", command)),
+        })
+    }
+}
+
+// Where a command reads and writes: standard input and output, and where
+// diagnostics go.
+pub struct Console<'a> {
+    pub input: &'a mut dyn BufRead,
+    pub output: &'a mut dyn Write,
+    pub errors: &'a mut dyn Write,
+}
+
+// The stack a command line needs. The interpreter recurses on the native
+// stack, so `run` needs far more than a thread's default to let programs
+// recurse deeply; the virtual memory is reserved, not used, until needed.
+pub const STACK_SIZE: usize = 512 << 20;
+
+// The number of calls `run` lets be in progress at once, which fits in
+// `STACK_SIZE` with room to spare even in a debug build.
+const MAX_DEPTH: usize = 10_000;
+
+// Runs a command line, returning the process's exit status: 0 on success,
+// 1 if the program has errors or fails, and 2 if the command line is wrong.
+// `run` exits with what `main` returns instead. It must be called on a
+// thread with a stack of `STACK_SIZE` bytes.
+pub fn main(arguments: &[String], console: &mut Console) -> i32 {
+    let timings = arguments.iter().any(|argument| argument == "--timings");
+    let arguments: Vec<String> = arguments
+        .iter()
+        .filter(|argument| *argument != "--timings")
+        .cloned()
+        .collect();
+    let command = match Command::parse(&arguments) {
+        Ok(command) => command,
+        Err(error) => {
+            let _ = write!(console.errors, "error: {}\n\n{}", error, USAGE);
+            return 2;
+        }
+    };
+    command.run(timings, console).unwrap_or(1)
+}
+
+// A command that failed, having said why.
+struct Failed;
+
+impl Command {
+    fn run(&self, timings: bool, console: &mut Console) -> Result<i32, Failed> {
+        let file = match self {
+            Command::Check { file, .. } | Command::Run(file) | Command::DumpAst(file) => file,
+            Command::Build(build) => &build.file,
+            Command::Fmt { file, .. }
+            | Command::Highlight { file, .. }
+            | Command::Query { file, .. } => file,
+            Command::Dap => {
+                return dap::serve(console.input, console.output)
+                    .map(|()| 0)
+                    .map_err(|error| fail(console, error));
+            }
+            Command::Help => return output(console, USAGE.as_bytes()).map(|()| 0),
+            Command::Version => {
+                let version = format!("mylang {}\n", env!("CARGO_PKG_VERSION"));
+                return output(console, version.as_bytes()).map(|()| 0);
+            }
+            Command::Grammar { svg: None } => {
+                return output(console, grammar::to_ebnf().as_bytes()).map(|()| 0);
+            }
+            Command::Grammar { svg: Some(name) } => {
+                let Some(rule) = grammar::find(name) else {
+                    return Err(fail(console, format!("no rule is named `{}`", name)));
+                };
+                return output(console, grammar::to_svg(rule).as_bytes()).map(|()| 0);
+            }
+        };
+        let options = CompileOptions {
+            name: name(file).to_string(),
+            timings,
+            ..CompileOptions::default()
+        };
+        // A file that `fmt --write` rewrites is read rather than mapped, as
+        // its mapping would change under it.
+        let map = match (file.as_str(), self) {
+            ("-", _) | (_, Command::Fmt { write: true, .. }) => None,
+            _ => map(file),
+        };
+        let mut session = match map {
+            Some(map) => Session::with_source_map(map, options),
+            None => Session::new(read(file, console)?, options),
+        };
+        let status = self.run_in(file, &mut session, console);
+        if timings {
+            let _ = write!(console.errors, "{}", session.timings.render());
+        }
+        status
+    }
+
+    // Runs a command that reads a file, given a session for it.
+    fn run_in(
+        &self,
+        file: &str,
+        session: &mut Session,
+        console: &mut Console,
+    ) -> Result<i32, Failed> {
+        match self {
+            Command::Check { report, cache, .. } => {
+                match cache {
+                    Some(dir) => {
+                        Cache::new(dir).check(session);
+                    }
+                    None => {
+                        if let Some(program) = session.parse().and_then(|p| session.link(p)) {
+                            session.check(&program);
+                        }
+                    }
+                }
+                report_check(session, *report, console)?;
+            }
+            Command::Run(_) => {
+                let program = analyze(session, console)?;
+                return run(&program, &session.source_map, console);
+            }
+            Command::Build(build) => {
+                let program = analyze(session, console)?;
+                let map = &session.source_map;
+                session
+                    .timings
+                    .time("codegen", || build.run(&program, map, console))?;
+            }
+            Command::Fmt { write, options, .. } => {
+                let formatted = match format::format_source_with(session.source(), options) {
+                    Ok(formatted) => formatted,
+                    Err(error) => {
+                        let _ = write!(console.errors, "{}", error.render(&session.source_map));
+                        return Err(Failed);
+                    }
+                };
+                if *write {
+                    fs::write(file, formatted).map_err(|error| {
+                        fail(console, format!("cannot write `{}`: {}", file, error))
+                    })?;
+                } else {
+                    output(console, formatted.as_bytes())?;
+                }
+            }
+            Command::DumpAst(_) => {
+                let program = parse(session, console)?;
+                output(console, dump::dump(&program).as_bytes())?;
+            }
+            Command::Highlight { html: false, .. } => {
+                output(console, highlight::to_ansi(session.source()).as_bytes())?;
+            }
+            Command::Highlight { html: true, .. } => {
+                output(console, highlight::to_html(session.source()).as_bytes())?;
+            }
+            Command::Query { query, .. } => {
+                let query = Query::parse(query)
+                    .map_err(|error| fail(console, format!("invalid query: {}", error)))?;
+                let program = parse(session, console)?;
+                let map = &session.source_map;
+                let mut found = String::new();
+                for node in query.find(&program) {
+                    let span = node.span();
+                    let (line, column) = map.location(span.start);
+                    let text = map.source()[span.range()].lines().next().unwrap_or("");
+                    found += &format!(
+                        "{}:{}:{}: {}: {}\n",
+                        session.options.name,
+                        line,
+                        column,
+                        query::kind(node),
+                        text
+                    );
+                }
+                output(console, found.as_bytes())?;
+            }
+            // Served above, without a file.
+            Command::Dap | Command::Grammar { .. } | Command::Help | Command::Version => {
+                unreachable!()
+            }
+        }
+        Ok(0)
+    }
+}
+
+// Reports an error that is not about the program's source.
+fn fail(console: &mut Console, message: impl fmt::Display) -> Failed {
+    let _ = writeln!(console.errors, "error: {}", message);
+    Failed
+}
+
+fn output(console: &mut Console, bytes: &[u8]) -> Result<(), Failed> {
+    console
+        .output
+        .write_all(bytes)
+        .map_err(|error| fail(console, format!("cannot write output: {}", error)))
+}
+
+// The name diagnostics give a file.
+fn name(file: &str) -> &str {
+    match file {
+        "-" => "<stdin>",
+        file => file,
+    }
+}
+
+fn read(file: &str, console: &mut Console) -> Result<String, Failed> {
+    let result = match file {
+        "-" => {
+            let mut source = String::new();
+            console.input.read_to_string(&mut source).map(|_| source)
+        }
+        file => fs::read_to_string(file),
+    };
+    result.map_err(|error| fail(console, format!("cannot read `{}`: {}", name(file), error)))
+}
+
+// Maps a regular file into memory. A pipe, a device or a file that cannot be
+// mapped is left to be read instead.
+fn map(file: &str) -> Option<SourceMap> {
+    if !fs::metadata(file).is_ok_and(|metadata| metadata.is_file()) {
+        return None;
+    }
+    // SAFETY: Like any compiler, mylang takes it that the files it compiles
+    // are not rewritten while it compiles them.
+    unsafe { SourceMap::map(name(file), file) }.ok()
+}
+
+// Parses the session's source, or reports why it does not parse.
+fn parse(session: &mut Session, console: &mut Console) -> Result<Program<'static>, Failed> {
+    session.parse().ok_or_else(|| {
+        let _ = write!(console.errors, "{}", session.render());
+        Failed
+    })
+}
+
+// Parses the session's source and links it with the modules it imports, or
+// reports why it cannot.
+fn link(session: &mut Session, console: &mut Console) -> Result<Program<'static>, Failed> {
+    let program = parse(session, console)?;
+    session.link(program).ok_or_else(|| {
+        let _ = write!(console.errors, "{}", session.render());
+        Failed
+    })
+}
+
+// Parses, links and checks the session's source, reporting its diagnostics,
+// and returns it lowered to HIR if it has no errors.
+fn analyze(session: &mut Session, console: &mut Console) -> Result<hir::Program, Failed> {
+    let program = link(session, console)?;
+    let checked = session.check(&program);
+    if !session.diagnostics().is_empty() {
+        let _ = write!(console.errors, "{}", session.render());
+    }
+    match checked.hir {
+        Some(hir) if !session.has_errors() => Ok(hir),
+        _ => Err(Failed),
+    }
+}
+
+// Reports the diagnostics of a checked session as `check` was asked to,
+// failing if it has errors.
+fn report_check(session: &Session, report: Report, console: &mut Console) -> Result<(), Failed> {
+    let (map, diagnostics) = (&session.source_map, session.diagnostics());
+    match report {
+        Report::Human => {
+            let _ = write!(console.errors, "{}", session.render());
+        }
+        Report::Json => {
+            let text: String = diagnostics.iter().map(|d| d.to_json(map) + "\n").collect();
+            output(console, text.as_bytes())?;
+        }
+        Report::Sarif => output(
+            console,
+            (sarif::to_sarif(&[(map, diagnostics)]) + "\n").as_bytes(),
+        )?,
+    }
+    match session.has_errors() {
+        true => Err(Failed),
+        false => Ok(()),
+    }
+}
+
+// Runs a program's top-level statements and then its `main` function, if
+// it has one, returning the exit status.
+fn run(program: &hir::Program, map: &SourceMap, console: &mut Console) -> Result<i32, Failed> {
+    let has_main = program.statements.iter().any(|statement| {
+        matches!(statement, hir::Statement::Function(function) if function.binding.name == sym::MAIN)
+    });
+    let result = {
+        let mut interpreter = Interpreter::new(program);
+        interpreter.limit(Limits {
+            max_depth: MAX_DEPTH,
+            ..Limits::default()
+        });
+        interpreter.redirect(Streams::new(&mut *console.input, &mut *console.output));
+        interpreter.run().and_then(|()| match has_main {
+            true => interpreter.call("main", vec![]),
+            false => Ok(None),
+        })
+    };
+    match result {
+        Ok(Some(Value::Integer(_, status))) => Ok(status as i32),
+        Ok(_) => Ok(0),
+        Err(error) => {
+            let _ = write!(console.errors, "{}", error.to_diagnostic(map).render(map));
+            Err(Failed)
+        }
+    }
+}
+
+impl Build {
+    fn run(
+        &self,
+        program: &hir::Program,
+        map: &SourceMap,
+        console: &mut Console,
+    ) -> Result<(), Failed> {
+        let target = match (&self.target, self.emit) {
+            (Some(triple), _) => {
+                Target::from_triple(triple).map_err(|error| fail(console, error))?
+            }
+            (None, Emit::Wasm) => Target::wasm32(),
+            (None, _) => Target::host().ok_or_else(|| {
+                fail(
+                    console,
+                    "cannot compile for this machine; give a `--target`",
+                )
+            })?,
+        };
+        let architecture = match self.emit {
+            Emit::LlvmIr => target.architecture,
+            Emit::Wasm => Architecture::Wasm32,
+            _ => Architecture::X86_64,
+        };
+        if target.architecture != architecture {
+            return Err(fail(
+                console,
+                format!("cannot emit {} for `{}`", self.emit, target),
+            ));
+        }
+        if self.debug_info && matches!(self.emit, Emit::LlvmIr | Emit::Wasm) {
+            return Err(fail(
+                console,
+                "debug info is only written for exe, obj and asm",
+            ));
+        }
+        let debug = self.debug_info.then_some(map);
+        let compiled = match self.emit {
+            Emit::Executable | Emit::Object => match debug {
+                Some(map) => object::compile_with_debug_info(program, &target, map),
+                None => object::compile(program, &target),
+            },
+            Emit::Assembly => match debug {
+                Some(map) => x86_64::compile_with_debug_info(program, &target, map),
+                None => x86_64::compile(program, &target),
+            }
+            .map(String::into_bytes),
+            Emit::LlvmIr => {
+                llvm::compile(program, &target).map(|module| module.to_string().into_bytes())
+            }
+            Emit::Wasm => wasm::compile(program, &target),
+        };
+        let bytes = compiled.map_err(|error| {
+            let _ = write!(console.errors, "{}", error.to_diagnostic().render(map));
+            Failed
+        })?;
+
+        let output = match &self.output {
+            Some(output) => PathBuf::from(output),
+            None => {
+                let stem = match self.file.as_str() {
+                    "-" => Path::new("out"),
+                    file => Path::new(file),
+                };
+                stem.with_extension(self.emit.extension())
+            }
+        };
+        if self.emit != Emit::Executable {
+            if output == Path::new("-") {
+                return self::output(console, &bytes);
+            }
+            return fs::write(&output, bytes).map_err(|error| {
+                fail(
+                    console,
+                    format!("cannot write `{}`: {}", output.display(), error),
+                )
+            });
+        }
+        if output == Path::new("-") {
+            return Err(fail(
+                console,
+                "an executable cannot be written to standard output",
+            ));
+        }
+        // The object is linked from beside the executable, so that a failed
+        // link leaves it to look at.
+        let object = output.with_extension("o");
+        fs::write(&object, bytes).map_err(|error| {
+            fail(
+                console,
+                format!("cannot write `{}`: {}", object.display(), error),
+            )
+        })?;
+        let linked = object::link(&[&object], &output);
+        let _ = fs::remove_file(&object);
+        linked.map_err(|error| fail(console, error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn arguments(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    // Runs a command line with `stdin` as its input, returning its exit
+    // status, output and errors.
+    // Runs a command line on a thread with the stack the binary gives it.
+    fn mylang(line: &str, stdin: &str) -> (i32, String, String) {
+        std::thread::scope(|scope| {
+            let run = || {
+                let (mut input, mut output, mut errors) = (stdin.as_bytes(), vec![], vec![]);
+                let mut console = Console {
+                    input: &mut input,
+                    output: &mut output,
+                    errors: &mut errors,
+                };
+                let status = main(&arguments(line), &mut console);
+                let text = |bytes| String::from_utf8(bytes).unwrap();
+                (status, text(output), text(errors))
+            };
+            let thread = std::thread::Builder::new().stack_size(STACK_SIZE);
+            thread.spawn_scoped(scope, run).unwrap().join().unwrap()
+        })
+    }
+
+    // A file of its own for a test, which tests running in parallel do not
+    // share.
+    fn temporary(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mylang-cli-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn command_lines_are_parsed() {
+        assert_eq!(
+            Command::parse(&arguments("build -g --emit asm x.my2 -o -")),
+            Ok(Command::Build(Build {
+                file: "x.my2".to_string(),
+                output: Some("-".to_string()),
+                emit: Emit::Assembly,
+                target: None,
+                debug_info: true,
+            }))
+        );
+        assert_eq!(
+            Command::parse(&arguments(
+                "fmt --write --indent 2 --brace-style next-line x.my2"
+            )),
+            Ok(Command::Fmt {
+                file: "x.my2".to_string(),
+                write: true,
+                options: FormatOptions {
+                    indent_width: 2,
+                    hard_tabs: false,
+                    brace_style: BraceStyle::NextLine,
+                    max_blank_lines: 1,
+                },
+            })
+        );
+        assert_eq!(
+            Command::parse(&arguments("dump-ast -")),
+            Ok(Command::DumpAst("-".to_string()))
+        );
+        assert_eq!(Command::parse(&arguments("dap")), Ok(Command::Dap));
+        assert_eq!(
+            Command::parse(&arguments("grammar --svg type")),
+            Ok(Command::Grammar {
+                svg: Some("type".to_string())
+            })
+        );
+        for (line, message) in [
+            ("", "no command given"),
+            ("check", "`check` needs a file"),
+            ("check a b", "more than one file given"),
+            ("run -g x", "unknown option `-g` for `run`"),
+            ("build --emit", "`--emit` needs a value"),
+            ("build --emit ir x", "cannot emit `ir`"),
+            (
+                "fmt --write -",
+                "`--write` needs a file, not standard input",
+            ),
+            ("fmt --indent two x", "cannot indent by `two`"),
+            ("fmt --brace-style k&r x", "unknown brace style `k&r`"),
+            ("compile x", "unknown command `compile`"),
+            ("dap x.my2", "`dap` takes no file"),
+            ("grammar x.my2", "`grammar` takes no file"),
+            ("query", "`query` needs a query"),
+            ("query //fn", "`query` needs a file"),
+        ] {
+            let error = UsageError(message.to_string());
+            assert_eq!(Command::parse(&arguments(line)), Err(error), "{}", line);
+        }
+        let (status, _, errors) = mylang("", "");
+        assert_eq!(status, 2);
+        assert!(errors.starts_with("error: no command given\n\nusage:"));
+
+        assert_eq!(mylang("grammar", ""), (0, grammar::to_ebnf(), "".into()));
+        let (status, _, errors) = mylang("grammar --svg statements", "");
+        assert_eq!(status, 1);
+        assert_eq!(errors, "error: no rule is named `statements`\n");
+
+        for line in ["--help", "-h", "run --help", "check x.my2 -h"] {
+            assert_eq!(mylang(line, ""), (0, USAGE.into(), "".into()), "{}", line);
+        }
+        let version = format!("mylang {}\n", env!("CARGO_PKG_VERSION"));
+        assert_eq!(mylang("--version", ""), (0, version, "".into()));
+    }
+
+    #[test]
+    fn check_reports_diagnostics() {
+        assert_eq!(
+            mylang("check -", "let _x: int32 = 1;"),
+            (0, "".into(), "".into())
+        );
+        // Warnings alone do not fail.
+        let (status, _, errors) = mylang("check -", "let x: int32 = 1;");
+        assert_eq!(status, 0);
+        assert!(errors.starts_with("warning[W0200]"), "{}", errors);
+        let (status, output, errors) = mylang("check -", "let _x: int32 = y;");
+        assert_eq!((status, output.as_str()), (1, ""));
+        assert!(errors.starts_with("error[E0200]"), "{}", errors);
+        assert!(errors.contains("--> <stdin>:1:17"), "{}", errors);
+        let (status, _, errors) = mylang("check -", "let x: int32 = ;");
+        assert_eq!(status, 1);
+        assert!(errors.starts_with("error[E01"), "{}", errors);
+        let (status, _, errors) = mylang("check /nonexistent/x.my2", "");
+        assert_eq!(status, 1);
+        assert!(errors.starts_with("error: cannot read `/nonexistent/x.my2`"));
+
+        // A second check through the cache reads what the first stored.
+        let cache = temporary("cache");
+        let line = format!("check --cache {} -", cache.display());
+        let first = mylang(&line, "let x: int32 = y;");
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 1);
+        assert_eq!(mylang(&line, "let x: int32 = y;"), first);
+        assert_eq!(first.0, 1);
+        assert!(first.2.starts_with("warning[W0200]"), "{}", first.2);
+        fs::remove_dir_all(&cache).unwrap();
+
+        let (status, _, errors) = mylang("check --timings -", "let _x: int32 = 1;");
+        assert_eq!(status, 0);
+        let phases: Vec<&str> = errors
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(phases[..4], ["phase", "lex", "parse", "resolve"]);
+        assert_eq!(phases.last(), Some(&"total"));
+        assert!(errors.contains(" tokens\n"), "{}", errors);
+    }
+
+    // A pipe cannot be mapped, so it is read.
+    #[cfg(unix)]
+    #[test]
+    fn check_reads_pipes() {
+        let path = temporary("pipe.my2");
+        let made = std::process::Command::new("mkfifo").arg(&path).status();
+        assert!(made.unwrap().success());
+        let writer = {
+            let path = path.clone();
+            std::thread::spawn(move || fs::write(path, "let _x: int32 = 1;\n").unwrap())
+        };
+        let line = format!("check {}", path.display());
+        assert_eq!(mylang(&line, ""), (0, "".into(), "".into()));
+        writer.join().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn check_writes_json_lines_or_sarif_on_request() {
+        assert_eq!(
+            Command::parse(&arguments("check --json x.my2")),
+            Ok(Command::Check {
+                file: "x.my2".to_string(),
+                report: Report::Json,
+                cache: None,
+            })
+        );
+        let (status, output, errors) = mylang("check --json -", "let x: int32 = y;");
+        assert_eq!((status, errors.as_str()), (1, ""));
+        let codes: Vec<String> = output
+            .lines()
+            .map(|line| {
+                let value: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert_eq!(value["file"], "<stdin>");
+                value["code"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(codes, ["W0200", "E0200"]);
+        let (status, output, _) = mylang("check --json -", "let x: int32 = ;");
+        assert_eq!(status, 1);
+        assert!(output.starts_with("{\"code\":\"E01"), "{}", output);
+        assert_eq!(
+            mylang("check --json -", "let _x: int32 = 1;"),
+            (0, "".into(), "".into())
+        );
+
+        let (status, output, _) = mylang("check --sarif -", "let x: int32 = 1;");
+        assert_eq!(status, 0);
+        let log: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(log["runs"][0]["results"][0]["ruleId"], "W0200");
+        let error = UsageError("`--json` and `--sarif` cannot be combined".to_string());
+        assert_eq!(
+            Command::parse(&arguments("check --json --sarif x")),
+            Err(error)
+        );
+    }
+
+    #[test]
+    fn run_interprets_programs() {
+        let source = "\
+fn main() -> int32 {
+    println(read_line());
+    return 3;
+}";
+        let path = temporary("run.my2");
+        fs::write(&path, source).unwrap();
+        let line = format!("run {}", path.display());
+        assert_eq!(mylang(&line, "hello\n"), (3, "hello\n".into(), "".into()));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(mylang("run -", "println(2);"), (0, "2\n".into(), "".into()));
+        let (status, _, errors) = mylang("run -", "let zero: int32 = 0;\nprintln(1 / zero);");
+        assert_eq!(status, 1);
+        assert!(
+            errors.starts_with("error[E0800]: division by zero\n --> <stdin>:2:9"),
+            "{}",
+            errors
+        );
+
+        let deep = "\
+fn depth(n: int32) -> int32 {
+    if n == 0 { return 0; }
+    return depth(n - 1) + 1;
+}
+println(depth(5000));";
+        assert_eq!(mylang("run -", deep), (0, "5000\n".into(), "".into()));
+        let (status, _, errors) = mylang("run -", &deep.replace("5000", "20000"));
+        assert_eq!(status, 1);
+        assert!(errors.starts_with("error[E0805]"), "{}", errors);
+    }
+
+    #[test]
+    fn check_and_run_load_imported_modules() {
+        let directory = temporary("modules");
+        fs::create_dir_all(&directory).unwrap();
+        let main = directory.join("main.my");
+        fs::write(
+            &main,
+            "import geometry;\nfn main() -> int32 { return area(2, 3); }\n",
+        )
+        .unwrap();
+        let geometry = directory.join("geometry.my");
+        fs::write(
+            &geometry,
+            "fn area(w: int32, h: int32) -> int32 { return w * h; }\n",
+        )
+        .unwrap();
+        let check = format!("check {}", main.display());
+        assert_eq!(mylang(&check, ""), (0, "".into(), "".into()));
+        let run = format!("run {}", main.display());
+        assert_eq!(mylang(&run, ""), (6, "".into(), "".into()));
+
+        // Diagnostics in a module point into its file.
+        fs::write(
+            &geometry,
+            "fn area(w: int32, h: int32) -> int32 { let n: int32 = 1; return w * h; }\n",
+        )
+        .unwrap();
+        let (status, _, errors) = mylang(&check, "");
+        assert_eq!(status, 0);
+        let location = format!(" --> {}:1:44", geometry.display());
+        assert!(
+            errors.starts_with("warning[W0200]: unused variable `n`\n"),
+            "{}",
+            errors
+        );
+        assert!(errors.contains(&location), "{}", errors);
+        fs::remove_file(&geometry).unwrap();
+        let (status, _, errors) = mylang(&run, "");
+        assert_eq!(status, 1);
+        assert!(errors.starts_with("error[E1100]: cannot find module `geometry`"));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn fmt_dump_ast_highlight_and_query_print_the_program() {
+        let source = "# One.\nfn  f ( ) -> int32 { return 1 ; }";
+        let formatted = "# One.\nfn f() -> int32 {\n    return 1;\n}\n".to_string();
+        assert_eq!(mylang("fmt -", source), (0, formatted.clone(), "".into()));
+        let tabbed = formatted.replace("    ", "\t");
+        assert_eq!(mylang("fmt --hard-tabs -", source), (0, tabbed, "".into()));
+        let path = temporary("fmt.my2");
+        fs::write(&path, source).unwrap();
+        assert_eq!(mylang(&format!("fmt --write {}", path.display()), "").0, 0);
+        assert_eq!(fs::read_to_string(&path).unwrap(), formatted);
+        fs::remove_file(&path).unwrap();
+
+        let program = Parser::parse_program(&Lexer::tokenize(source)).unwrap();
+        let tree = dump::dump(&program);
+        assert_eq!(mylang("dump-ast -", source), (0, tree, "".into()));
+
+        let colored = highlight::to_ansi(source);
+        assert_eq!(mylang("highlight -", source), (0, colored, "".into()));
+        let html = highlight::to_html(source);
+        assert_eq!(mylang("highlight --html -", source), (0, html, "".into()));
+
+        assert_eq!(
+            mylang("query fn[name=f]//integer -", source),
+            (0, "<stdin>:2:29: integer: 1\n".into(), "".into())
+        );
+        let (status, _, errors) = mylang("query fn[ -", source);
+        assert_eq!(status, 1);
+        assert_eq!(
+            errors,
+            "error: invalid query: expected an attribute at offset 3\n"
+        );
+    }
+
+    #[test]
+    fn build_writes_every_kind_of_output() {
+        let source = "fn main() -> int64 { return 42; }";
+        let (status, output, errors) = mylang("build --emit asm -o - -", source);
+        assert_eq!(status, 0, "{}", errors);
+        assert!(output.contains("main:\n"), "{}", output);
+        let (status, output, _) = mylang("build --emit llvm-ir -o - -", source);
+        assert_eq!(status, 0);
+        assert!(output.contains("define i64 @main()"), "{}", output);
+        let (status, _, errors) =
+            mylang("build --emit wasm --target x86_64-pc-linux-gnu -", source);
+        assert_eq!(
+            (status, errors.as_str()),
+            (1, "error: cannot emit wasm for `x86_64-pc-linux-gnu`\n")
+        );
+        let (status, _, errors) = mylang("build --emit asm -o - -", "fn _f(x: float32) { }");
+        assert_eq!(status, 1);
+        assert!(
+            errors.starts_with("error[E0900]: cannot compile floats"),
+            "{}",
+            errors
+        );
+        let (status, _, errors) = mylang("build --target riscv64 -", source);
+        assert_eq!(
+            (status, errors.as_str()),
+            (1, "error: unknown target `riscv64`\n")
+        );
+
+        let path = temporary("wasm");
+        let (status, _, _) = mylang(
+            &format!("build --emit wasm -o {} -", path.display()),
+            source,
+        );
+        assert_eq!(status, 0);
+        assert!(fs::read(&path).unwrap().starts_with(b"\0asm"));
+        fs::remove_file(&path).unwrap();
+
+        // Linking needs a C compiler, and running the executable needs an
+        // x86-64 Linux machine.
+        if !cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            return;
+        }
+        let path = temporary("exe");
+        let (status, _, errors) = mylang(&format!("build -g -o {} -", path.display()), source);
+        if errors.contains("cannot find the linker") {
+            return;
+        }
+        assert_eq!(status, 0, "{}", errors);
+        let exit = std::process::Command::new(&path).status().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(exit.code(), Some(42));
+    }
+}
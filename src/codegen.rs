@@ -0,0 +1,50 @@
+use crate::{ast::Span, diagnostic::Diagnostic};
+use std::fmt;
+
+// Backends that compile a lowered program for something other than the
+// interpreter and the VM to run.
+#[cfg(test)]
+mod filecheck;
+#[cfg(feature = "llvm")]
+pub mod llvm;
+#[cfg(feature = "object")]
+pub mod object;
+pub mod target;
+pub mod wasm;
+pub mod x86_64;
+
+// Something in a program a backend cannot compile, such as `strings`, and
+// where it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodegenError {
+    pub unsupported: &'static str,
+    pub span: Span,
+}
+
+impl CodegenError {
+    pub(crate) fn new(unsupported: &'static str, span: Span) -> CodegenError {
+        CodegenError { unsupported, span }
+    }
+
+    // Reports the error at the code the backend cannot compile.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error("E0900", self.to_string(), self.span)
+    }
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot compile {}", self.unsupported)
+    }
+}
+
+// Parses, checks and lowers a program for a backend's tests. The program
+// must be free of errors.
+#[cfg(test)]
+fn lower(source: &str) -> crate::hir::Program {
+    let tokens = crate::lexer::Lexer::tokenize(source);
+    let program = crate::parser::Parser::parse_program(&tokens).unwrap();
+    let errors = program.typecheck().errors;
+    assert!(errors.is_empty(), "{}: {:?}", source, errors);
+    program.to_hir()
+}
@@ -0,0 +1,330 @@
+use regex::Regex;
+use std::fmt;
+
+// Golden tests for the backends, in the style of LLVM's FileCheck.
+//
+// Each snippet in `tests/codegen` is a program whose comments say what a
+// backend's output for it must contain. A directive names the output it
+// checks by a prefix, `X86` for the x86-64 assembly, `LLVM` for LLVM IR,
+// `SSA` for the SSA form and `OPT` for the SSA form after the standard
+// pipeline:
+//
+//   # X86: f:
+//   # X86-NEXT: pushq %rbp
+//   # X86-NOT: call
+//
+// A plain directive matches the first line after the previous match that
+// contains its pattern. A `-NEXT` one must match the line right after the
+// previous match, and a `-NOT` one must match no line between the matches
+// around it. Runs of spaces and tabs count as one space, and `{{...}}` in
+// a pattern is a regular expression. A snippet is only compiled by the
+// backends it has directives for.
+
+// A directive, and the line of the snippet it is on.
+#[derive(Debug)]
+struct Check {
+    kind: Kind,
+    line: usize,
+    text: String,
+    pattern: Regex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Match,
+    Next,
+    Not,
+}
+
+// Why a snippet failed: what went wrong at a line of the snippet, and the
+// line of the output it went wrong at, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CheckError {
+    line: usize,
+    message: String,
+    output_line: Option<usize>,
+}
+
+impl CheckError {
+    fn new(line: usize, message: impl Into<String>, output_line: Option<usize>) -> CheckError {
+        CheckError {
+            line,
+            message: message.into(),
+            output_line,
+        }
+    }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+// Returns the directives with `prefix` in a snippet, in order.
+fn checks(source: &str, prefix: &str) -> Result<Vec<Check>, CheckError> {
+    let mut checks: Vec<Check> = vec![];
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let Some(comment) = line.trim_start().strip_prefix('#') else {
+            continue;
+        };
+        let Some((name, text)) = comment.split_once(':') else {
+            continue;
+        };
+        let kind = match name.trim().strip_prefix(prefix) {
+            Some("") => Kind::Match,
+            Some("-NEXT") => Kind::Next,
+            Some("-NOT") => Kind::Not,
+            _ => continue,
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(CheckError::new(line_number, "empty pattern", None));
+        }
+        if kind == Kind::Next && checks.last().is_none_or(|check| check.kind == Kind::Not) {
+            let message = format!("`{}-NEXT` must follow a match", prefix);
+            return Err(CheckError::new(line_number, message, None));
+        }
+        let pattern =
+            pattern(text).map_err(|message| CheckError::new(line_number, message, None))?;
+        checks.push(Check {
+            kind,
+            line: line_number,
+            text: text.to_string(),
+            pattern,
+        });
+    }
+    Ok(checks)
+}
+
+// Compiles a pattern, whose text outside `{{...}}` is matched literally.
+fn pattern(text: &str) -> Result<Regex, String> {
+    let mut regex = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        regex += &literal(&rest[..start]);
+        let Some(end) = rest[start..].find("}}") else {
+            return Err("`{{` without `}}`".to_string());
+        };
+        regex += &format!("(?:{})", &rest[start + 2..start + end]);
+        rest = &rest[start + end + 2..];
+    }
+    regex += &literal(rest);
+    Regex::new(&regex).map_err(|error| error.to_string())
+}
+
+fn literal(text: &str) -> String {
+    regex::escape(&collapse(text))
+}
+
+// Collapses runs of spaces and tabs into one space.
+fn collapse(text: &str) -> String {
+    let mut collapsed = String::new();
+    for c in text.chars() {
+        match c {
+            ' ' | '\t' if collapsed.ends_with(' ') => {}
+            ' ' | '\t' => collapsed.push(' '),
+            c => collapsed.push(c),
+        }
+    }
+    collapsed
+}
+
+// Checks an output against directives.
+fn check(checks: &[Check], output: &str) -> Result<(), CheckError> {
+    let lines: Vec<String> = output
+        .lines()
+        .map(|line| collapse(line).trim().to_string())
+        .collect();
+    // The line after the previous match.
+    let mut position = 0;
+    // The `-NOT` directives since the previous match.
+    let mut nots: Vec<&Check> = vec![];
+    let unmatched = |nots: &mut Vec<&Check>, range: std::ops::Range<usize>| {
+        for not in nots.drain(..) {
+            if let Some(index) = range.clone().find(|&i| not.pattern.is_match(&lines[i])) {
+                let message = format!("`{}` was not expected", not.text);
+                return Err(CheckError::new(not.line, message, Some(index + 1)));
+            }
+        }
+        Ok(())
+    };
+    for check in checks {
+        let found = match check.kind {
+            Kind::Not => {
+                nots.push(check);
+                continue;
+            }
+            Kind::Match => (position..lines.len()).find(|&i| check.pattern.is_match(&lines[i])),
+            Kind::Next => {
+                Some(position).filter(|&i| i < lines.len() && check.pattern.is_match(&lines[i]))
+            }
+        };
+        let Some(index) = found else {
+            let message = match check.kind {
+                Kind::Next => format!("expected `{}` on the next line", check.text),
+                _ => format!("expected `{}`", check.text),
+            };
+            return Err(CheckError::new(check.line, message, Some(position + 1)));
+        };
+        unmatched(&mut nots, position..index)?;
+        position = index + 1;
+    }
+    unmatched(&mut nots, position..lines.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codegen::{target::Target, x86_64},
+        hir,
+        lexer::Lexer,
+        opt::Pipeline,
+        parser::Parser,
+        ssa,
+    };
+    use std::{fs, path::Path};
+
+    // Compiles a program to the text a prefix's directives check.
+    type Backend = fn(&hir::Program) -> Result<String, String>;
+
+    // The outputs that directives can check, by prefix. Directives for a
+    // backend that is not built are not checked.
+    const BACKENDS: &[(&str, Backend)] = &[
+        ("X86", |program| {
+            x86_64::compile(program, &Target::x86_64_linux()).map_err(|error| error.to_string())
+        }),
+        #[cfg(feature = "llvm")]
+        ("LLVM", |program| {
+            crate::codegen::llvm::compile(program, &Target::x86_64_linux())
+                .map(|module| module.to_string())
+                .map_err(|error| error.to_string())
+        }),
+        ("SSA", |program| Ok(functions(ssa::build_all(program)))),
+        ("OPT", |program| {
+            let mut functions = ssa::build_all(program);
+            Pipeline::standard().run_all(&mut functions);
+            Ok(self::functions(functions))
+        }),
+    ];
+
+    fn functions(functions: Vec<ssa::Function>) -> String {
+        let functions: Vec<String> = functions.iter().map(|f| f.to_string()).collect();
+        functions.join("\n")
+    }
+
+    // Describes a failure with the output around where it happened, with
+    // that line marked.
+    fn report(name: &str, prefix: &str, error: &CheckError, output: &str) -> String {
+        let mut report = format!("{}: {}: {}\n", name, prefix, error);
+        let Some(marked) = error.output_line else {
+            return report;
+        };
+        report += &format!("{} output:\n", prefix);
+        let lines: Vec<&str> = output.lines().collect();
+        let first = marked.saturating_sub(6);
+        for (index, line) in lines.iter().enumerate().skip(first).take(12) {
+            let marker = if index + 1 == marked { ">" } else { " " };
+            report += &format!("{} {:4} | {}\n", marker, index + 1, line);
+        }
+        if marked > lines.len() {
+            report += ">      | <end of output>\n";
+        }
+        report
+    }
+
+    // Checks a snippet with every backend it has directives for, returning
+    // a report of each failure.
+    fn run(name: &str, source: &str) -> Vec<String> {
+        let mut failures = vec![];
+        let tokens = Lexer::tokenize(source);
+        let program = match Parser::parse_program(&tokens) {
+            Ok(program) => program,
+            Err(errors) => return vec![format!("{}: {:?}", name, errors)],
+        };
+        let errors = program.typecheck().errors;
+        if !errors.is_empty() {
+            return vec![format!("{}: {:?}", name, errors)];
+        }
+        let program = program.to_hir();
+        for &(prefix, compile) in BACKENDS {
+            let checks = match checks(source, prefix) {
+                Ok(checks) if checks.is_empty() => continue,
+                Ok(checks) => checks,
+                Err(error) => {
+                    failures.push(format!("{}: {}: {}", name, prefix, error));
+                    continue;
+                }
+            };
+            let output = match compile(&program) {
+                Ok(output) => output,
+                Err(error) => {
+                    failures.push(format!("{}: {}: {}", name, prefix, error));
+                    continue;
+                }
+            };
+            if let Err(error) = check(&checks, &output) {
+                failures.push(report(name, prefix, &error, &output));
+            }
+        }
+        failures
+    }
+
+    #[test]
+    fn snippets_match_their_directives() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/codegen");
+        let mut paths: Vec<_> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "my2"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "no snippets in {}", directory.display());
+        let mut failures = vec![];
+        for path in &paths {
+            let name = path.file_name().unwrap().to_string_lossy();
+            failures.extend(run(&name, &fs::read_to_string(path).unwrap()));
+        }
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn directives_match_lines_in_order() {
+        let output = "f:\n\tpushq\t%rbp\n\tmovq\t%rsp, %rbp\n\tcall\tg\n\tret\n";
+        let run = |source: &str| check(&checks(source, "X86").unwrap(), output);
+        assert_eq!(run("# X86: f:\n# X86-NEXT: pushq %rbp\n# X86: ret"), Ok(()));
+        assert_eq!(run("# X86: movq {{%r[a-z]+}}, %rbp"), Ok(()));
+        assert_eq!(run("# LLVM: define\n# X86: call g"), Ok(()));
+        assert_eq!(
+            run("# X86: ret\n# X86: f:"),
+            Err(CheckError::new(2, "expected `f:`", Some(6)))
+        );
+        assert_eq!(
+            run("# X86: f:\n# X86-NEXT: movq"),
+            Err(CheckError::new(
+                2,
+                "expected `movq` on the next line",
+                Some(2)
+            ))
+        );
+        assert_eq!(
+            run("# X86: pushq\n# X86-NOT: call\n# X86: ret"),
+            Err(CheckError::new(2, "`call` was not expected", Some(4)))
+        );
+        assert_eq!(run("# X86: call\n# X86-NOT: call"), Ok(()));
+    }
+
+    #[test]
+    fn malformed_directives_are_errors() {
+        for (source, line, message) in [
+            ("# X86-NEXT: ret", 1, "`X86-NEXT` must follow a match"),
+            ("# X86: f\n# X86:", 2, "empty pattern"),
+            ("# X86: {{[a-z]", 1, "`{{` without `}}`"),
+        ] {
+            let error = checks(source, "X86").unwrap_err();
+            assert_eq!((error.line, error.message.as_str()), (line, message));
+        }
+    }
+}
@@ -0,0 +1,1114 @@
+use super::{target::Target, CodegenError};
+use crate::{
+    ast::{BinaryOperator, NodeId, Span, Symbol, TypeKind},
+    hir::{self, Expression, ExpressionKind, Statement},
+    typecheck::Ty,
+    value::Value,
+};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+// Compiles a lowered program to LLVM IR, in LLVM's textual form, which the
+// LLVM tools turn into an object file or run in their JIT.
+//
+// Numbers, bools, top-level functions and control flow are supported.
+// Strings, builtins, nested functions and functions used as values are not,
+// and neither is `bfloat16`, which not every LLVM target can pass to a
+// function.
+//
+// Top-level functions keep their names and use the C calling convention, so
+// an object file can be linked with C: `fn add(a: int64, b: int64) -> int64`
+// is `int64_t add(int64_t, int64_t)`, and one declared without a body is an
+// external function. Top-level `let` bindings are globals, set by a
+// constructor that runs the program's top-level statements before `main`.
+//
+// Arithmetic means what it does in the interpreter. Integers wrap around and
+// division truncates; `int1` is unsigned and the other integer types are
+// signed. Float arithmetic is done in `double` and rounded to the type. A
+// division by zero or a negative exponent calls `llvm.trap`.
+//
+// The module is for `target`, which LLVM can compile for whatever machine
+// the program is run on. The program must be free of errors: it panics on
+// `ExpressionKind::Error`.
+pub fn compile(program: &hir::Program, target: &Target) -> Result<Module, CodegenError> {
+    let mut compiler = Compiler::default();
+    // Top-level functions can be used before their declaration, so their
+    // signatures are known first.
+    for statement in &program.statements {
+        match statement {
+            Statement::Function(function) => {
+                let signature = compiler.signature(function)?;
+                compiler.functions.insert(function.binding.id, signature);
+            }
+            Statement::Let(let_statement) => {
+                let kind = compiler.kind(&let_statement.ty, let_statement.span)?;
+                let name = compiler.unique(&let_statement.binding.name);
+                compiler
+                    .globals
+                    .insert(let_statement.binding.id, (name, kind));
+            }
+            _ => {}
+        }
+    }
+
+    let mut text = String::from("source_filename = \"mylang\"\n");
+    let triple = format!("target triple = \"{}\"\n", target.triple);
+    text += &triple;
+    let mut globals = false;
+    for statement in &program.statements {
+        if let Statement::Let(let_statement) = statement {
+            let (name, kind) = &compiler.globals[&let_statement.binding.id];
+            let zero = if kind.is_float() { "0.0" } else { "0" };
+            text += &format!(
+                "@{} = internal global {} {}\n",
+                name,
+                llvm_type(*kind),
+                zero
+            );
+            globals = true;
+        }
+    }
+    if globals {
+        text += "\n";
+    }
+
+    let mut functions = vec![];
+    for statement in &program.statements {
+        if let Statement::Function(function) = statement {
+            text += &compiler.function(function)?;
+            text += "\n";
+            if function.body.is_some() {
+                functions.push(compiler.functions[&function.binding.id].clone());
+            }
+        }
+    }
+
+    if program
+        .statements
+        .iter()
+        .any(|statement| !matches!(statement, Statement::Function(_)))
+    {
+        text += &compiler.initializer(&program.statements)?;
+        text += "\n";
+        text += "@llvm.global_ctors = appending global [1 x { i32, void ()*, i8* }] \
+[{ i32, void ()*, i8* } { i32 65535, void ()* @mylang.init, i8* null }]\n\n";
+    }
+    if compiler.power {
+        text += POWER;
+        text += "\n";
+    }
+    for declaration in &compiler.declarations {
+        text += declaration;
+        text += "\n";
+    }
+    Ok(Module {
+        text,
+        triple,
+        functions,
+    })
+}
+
+// A compiled program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    text: String,
+    // The line of `text` naming the target.
+    triple: String,
+    // The top-level functions with a body, which `run` can call.
+    functions: Vec<Signature>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Signature {
+    name: Symbol,
+    // The name of the LLVM function.
+    symbol: String,
+    parameters: Vec<TypeKind>,
+    return_type: TypeKind,
+}
+
+// Why running one of the LLVM tools failed.
+#[derive(Debug)]
+pub enum ToolError {
+    // The tool could not be started, or writing to it or reading from it
+    // failed.
+    Io(io::Error),
+    // The tool exited unsuccessfully, with what it wrote to stderr. For
+    // `Module::run`, the program may have trapped.
+    Failed(String),
+    // `Module::run` was given a function the module does not define with
+    // parameters of the arguments' types.
+    NoSuchFunction(String),
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolError::Io(error) => write!(f, "cannot run LLVM: {}", error),
+            ToolError::Failed(stderr) => write!(f, "LLVM failed: {}", stderr.trim_end()),
+            ToolError::NoSuchFunction(name) => {
+                write!(f, "no function `{}` takes these arguments", name)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for ToolError {
+    fn from(error: io::Error) -> ToolError {
+        ToolError::Io(error)
+    }
+}
+
+impl Module {
+    // Writes an object file for the module's target, with `llc`.
+    pub fn emit_object(&self, path: &Path) -> Result<(), ToolError> {
+        let mut llc = Command::new("llc");
+        llc.args(["-O2", "-filetype=obj", "-o"]).arg(path).arg("-");
+        tool(&mut llc, &self.text).map(|_| ())
+    }
+
+    // Calls a top-level function in LLVM's JIT, with `lli`, returning its
+    // result or `None` for a function that returns unit. The program's
+    // top-level statements run first.
+    pub fn run(&self, name: &str, arguments: Vec<Value>) -> Result<Option<Value>, ToolError> {
+        let signature = self
+            .functions
+            .iter()
+            .find(|signature| {
+                signature.name == name
+                    && signature.parameters.len() == arguments.len()
+                    && (signature.parameters.iter())
+                        .zip(&arguments)
+                        .all(|(kind, argument)| argument.kind() == Some(*kind))
+            })
+            .ok_or_else(|| ToolError::NoSuchFunction(name.to_string()))?;
+
+        // An entry point that calls the function and prints the bits of its
+        // result.
+        let arguments: Vec<String> = arguments
+            .iter()
+            .map(|argument| {
+                let kind = argument.kind().unwrap();
+                let value = match argument {
+                    Value::Integer(_, value) => value.to_string(),
+                    Value::Float(_, value) => float(*value),
+                    Value::Bool(value) => value.to_string(),
+                    _ => unreachable!("parameters have supported types"),
+                };
+                format!("{} {}", llvm_type(kind), value)
+            })
+            .collect();
+        let kind = signature.return_type;
+        let call = format!(
+            "call {} @{}({})",
+            llvm_type(kind),
+            signature.symbol,
+            arguments.join(", ")
+        );
+        // The JIT compiles for the machine it runs on, which may call
+        // different runtime functions than the module's target would.
+        let mut text = self.text.replacen(&self.triple, "", 1);
+        text += "@mylang.format = private constant [6 x i8] c\"%lld\\0A\\00\"\n\
+declare i32 @printf(i8*, ...)\n\
+define i32 @mylang.run() {\n";
+        if kind == TypeKind::Unit {
+            text += &format!("  {}\n", call);
+        } else {
+            text += &format!("  %result = {}\n", call);
+            let bits = match kind {
+                TypeKind::Int1 | TypeKind::Bool => "zext i1 %result to i64".to_string(),
+                TypeKind::Int64 => "add i64 %result, 0".to_string(),
+                TypeKind::Float64 => "bitcast double %result to i64".to_string(),
+                kind if kind.is_float() => {
+                    text += &format!("  %wide = fpext {} %result to double\n", llvm_type(kind));
+                    "bitcast double %wide to i64".to_string()
+                }
+                kind => format!("sext {} %result to i64", llvm_type(kind)),
+            };
+            text += &format!(
+                "  %bits = {}\n  \
+%format = getelementptr [6 x i8], [6 x i8]* @mylang.format, i64 0, i64 0\n  \
+call i32 (i8*, ...) @printf(i8* %format, i64 %bits)\n",
+                bits
+            );
+        }
+        text += "  ret i32 0\n}\n";
+
+        let mut lli = Command::new("lli");
+        lli.args(["--entry-function=mylang.run", "-"]);
+        let output = tool(&mut lli, &text)?;
+        if kind == TypeKind::Unit {
+            return Ok(None);
+        }
+        let bits: i64 = output
+            .trim()
+            .parse()
+            .map_err(|_| ToolError::Failed(format!("unexpected output {:?}", output)))?;
+        Ok(Some(match kind {
+            TypeKind::Bool => Value::Bool(bits != 0),
+            kind if kind.is_float() => Value::float(kind, f64::from_bits(bits as u64)),
+            kind => Value::integer(kind, bits.into()),
+        }))
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+// Runs a tool with `input` on its stdin, returning what it wrote to stdout.
+fn tool(command: &mut Command, input: &str) -> Result<String, ToolError> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // A tool that rejects its input may exit before reading all of it; its
+    // stderr says why.
+    match child.stdin.take().unwrap().write_all(input.as_bytes()) {
+        Err(error) if error.kind() != io::ErrorKind::BrokenPipe => return Err(error.into()),
+        _ => {}
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(ToolError::Failed(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Computes `base` to the power of `exponent`, wrapping around at 64 bits,
+// which leaves the low bits narrower types keep unchanged.
+const POWER: &str = "\
+define internal i64 @mylang.power(i64 %base, i64 %exponent) {
+entry:
+  %negative = icmp slt i64 %exponent, 0
+  br i1 %negative, label %trap, label %loop
+loop:
+  %result = phi i64 [ 1, %entry ], [ %next, %step ]
+  %factor = phi i64 [ %base, %entry ], [ %square, %step ]
+  %rest = phi i64 [ %exponent, %entry ], [ %shifted, %step ]
+  %done = icmp eq i64 %rest, 0
+  br i1 %done, label %exit, label %step
+step:
+  %low = and i64 %rest, 1
+  %odd = icmp ne i64 %low, 0
+  %product = mul i64 %result, %factor
+  %next = select i1 %odd, i64 %product, i64 %result
+  %square = mul i64 %factor, %factor
+  %shifted = lshr i64 %rest, 1
+  br label %loop
+exit:
+  ret i64 %result
+trap:
+  call void @llvm.trap()
+  unreachable
+}
+";
+
+// Returns the LLVM type of a supported type.
+fn llvm_type(kind: TypeKind) -> &'static str {
+    match kind {
+        TypeKind::Int1 | TypeKind::Bool => "i1",
+        TypeKind::Int2 => "i2",
+        TypeKind::Int4 => "i4",
+        TypeKind::Int8 => "i8",
+        TypeKind::Int16 => "i16",
+        TypeKind::Int32 => "i32",
+        TypeKind::Int64 => "i64",
+        TypeKind::Float16 => "half",
+        TypeKind::Float32 => "float",
+        TypeKind::Float64 => "double",
+        TypeKind::Unit => "void",
+        _ => unreachable!("unsupported types are rejected"),
+    }
+}
+
+// Returns the width of a number or bool.
+fn bits(kind: TypeKind) -> u32 {
+    match kind {
+        TypeKind::Bool => 1,
+        TypeKind::Float16 => 16,
+        TypeKind::Float32 => 32,
+        TypeKind::Float64 => 64,
+        kind => kind.integer_bits().expect("a number or bool"),
+    }
+}
+
+// Whether an integer type is signed: `int1` and `bool` are not.
+fn signed(kind: TypeKind) -> bool {
+    !matches!(kind, TypeKind::Int1 | TypeKind::Bool)
+}
+
+// A float constant, as the bits of a `double`, which LLVM accepts for every
+// float type the value is exact in.
+fn float(value: f64) -> String {
+    format!("0x{:016X}", value.to_bits())
+}
+
+#[derive(Default)]
+struct Compiler {
+    // The LLVM names of globals and functions already taken.
+    names: HashSet<String>,
+    functions: HashMap<NodeId, Signature>,
+    globals: HashMap<NodeId, (String, TypeKind)>,
+    // Declarations of the intrinsics used.
+    declarations: BTreeSet<String>,
+    // Whether `mylang.power` is used.
+    power: bool,
+    body: Body,
+}
+
+// The function being compiled.
+#[derive(Default)]
+struct Body {
+    code: String,
+    // The names of its parameters.
+    names: HashSet<String>,
+    // The value of each parameter and `let` binding.
+    locals: HashMap<NodeId, String>,
+    temporaries: u32,
+    labels: u32,
+    // Whether the current basic block has ended, so what follows cannot be
+    // reached.
+    terminated: bool,
+    // Whether anything jumps to the block that traps.
+    traps: bool,
+}
+
+impl Compiler {
+    // Returns a name for a global or function that no other has.
+    fn unique(&mut self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 0;
+        while self.names.contains(&unique) {
+            n += 1;
+            unique = format!("{}.{}", name, n);
+        }
+        self.names.insert(unique.clone());
+        unique
+    }
+
+    // Returns the type of a value the backend can compile.
+    fn kind(&self, ty: &Ty, span: Span) -> Result<TypeKind, CodegenError> {
+        match ty {
+            Ty::Primitive(TypeKind::String) => Err(CodegenError::new("strings", span)),
+            Ty::Primitive(TypeKind::BFloat16) => Err(CodegenError::new("`bfloat16` values", span)),
+            Ty::Primitive(kind) => Ok(*kind),
+            Ty::Function(..) => Err(CodegenError::new("function values", span)),
+            Ty::Error => panic!("cannot compile a program with errors"),
+            _ => unreachable!("values have primitive or function types"),
+        }
+    }
+
+    fn signature(&mut self, function: &hir::Function) -> Result<Signature, CodegenError> {
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|parameter| self.kind(&parameter.ty, function.span))
+            .collect::<Result<_, _>>()?;
+        Ok(Signature {
+            name: function.binding.name.clone(),
+            symbol: self.unique(&function.binding.name),
+            parameters,
+            return_type: self.kind(&function.return_type, function.span)?,
+        })
+    }
+
+    fn function(&mut self, function: &hir::Function) -> Result<String, CodegenError> {
+        let signature = self.functions[&function.binding.id].clone();
+        let return_type = llvm_type(signature.return_type);
+        let Some(block) = &function.body else {
+            let parameters: Vec<&str> =
+                signature.parameters.iter().map(|k| llvm_type(*k)).collect();
+            return Ok(format!(
+                "declare {} @{}({})\n",
+                return_type,
+                signature.symbol,
+                parameters.join(", ")
+            ));
+        };
+        self.body = Body::default();
+        let mut parameters = vec![];
+        for (parameter, kind) in function.parameters.iter().zip(&signature.parameters) {
+            let mut name = parameter.binding.name.to_string();
+            let mut n = 0;
+            while self.body.names.contains(&name) {
+                n += 1;
+                name = format!("{}.{}", parameter.binding.name, n);
+            }
+            self.body.names.insert(name.clone());
+            self.body
+                .locals
+                .insert(parameter.binding.id, format!("%{}", name));
+            parameters.push(format!("{} %{}", llvm_type(*kind), name));
+        }
+        self.block(block)?;
+        Ok(self.finish(format!(
+            "define {} @{}({})",
+            return_type,
+            signature.symbol,
+            parameters.join(", ")
+        )))
+    }
+
+    // Compiles the top-level statements into the constructor that sets the
+    // globals.
+    fn initializer(&mut self, statements: &[Statement]) -> Result<String, CodegenError> {
+        self.body = Body::default();
+        for statement in statements {
+            match statement {
+                Statement::Function(_) => {}
+                Statement::Let(let_statement) => {
+                    let value = self.operand(&let_statement.value)?;
+                    let (name, kind) = &self.globals[&let_statement.binding.id];
+                    let store = format!("store {0} {1}, {0}* @{2}", llvm_type(*kind), value, name);
+                    self.instruction(store);
+                }
+                statement => self.statement(statement)?,
+            }
+        }
+        if !self.body.terminated {
+            self.terminate("ret void".to_string());
+        }
+        Ok(self.finish("define internal void @mylang.init()".to_string()))
+    }
+
+    // Returns the function being compiled, given its header.
+    fn finish(&mut self, header: String) -> String {
+        if !self.body.terminated {
+            // Type checking makes sure a function that returns a value
+            // cannot reach its end.
+            self.terminate("unreachable".to_string());
+        }
+        let mut text = format!("{} {{\nentry:\n{}", header, self.body.code);
+        if self.body.traps {
+            text += "trap:\n  call void @llvm.trap()\n  unreachable\n";
+            self.declarations
+                .insert("declare void @llvm.trap()".to_string());
+        }
+        text += "}\n";
+        text
+    }
+
+    fn instruction(&mut self, instruction: String) {
+        self.body.code += "  ";
+        self.body.code += &instruction;
+        self.body.code += "\n";
+    }
+
+    // Adds an instruction that produces a value, returning the value.
+    fn value(&mut self, instruction: String) -> String {
+        self.body.temporaries += 1;
+        let temporary = format!("%t.{}", self.body.temporaries);
+        self.instruction(format!("{} = {}", temporary, instruction));
+        temporary
+    }
+
+    // Ends the current basic block.
+    fn terminate(&mut self, instruction: String) {
+        self.instruction(instruction);
+        self.body.terminated = true;
+    }
+
+    // Starts a basic block.
+    fn start(&mut self, label: &str) {
+        self.body.code += label;
+        self.body.code += ":\n";
+        self.body.terminated = false;
+    }
+
+    // Returns a number for the labels of a statement's blocks.
+    fn labels(&mut self) -> u32 {
+        self.body.labels += 1;
+        self.body.labels
+    }
+
+    fn block(&mut self, block: &hir::Block) -> Result<(), CodegenError> {
+        for statement in &block.statements {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), CodegenError> {
+        if self.body.terminated {
+            // Nothing after a `return` can run.
+            return Ok(());
+        }
+        match statement {
+            Statement::Let(let_statement) => {
+                self.kind(&let_statement.ty, let_statement.span)?;
+                // Bindings are never assigned, so a binding is just its
+                // value.
+                if let Some(value) = self.expression(&let_statement.value)? {
+                    self.body.locals.insert(let_statement.binding.id, value);
+                }
+            }
+            Statement::Function(function) => {
+                return Err(CodegenError::new("nested functions", function.span));
+            }
+            Statement::Expression(expression) => {
+                self.expression(expression)?;
+            }
+            Statement::Return(return_statement) => {
+                let value = match &return_statement.value {
+                    Some(value) => {
+                        let kind = self.kind(&value.ty, value.span)?;
+                        self.expression(value)?
+                            .map(|value| format!("{} {}", llvm_type(kind), value))
+                    }
+                    None => None,
+                };
+                let value = value.unwrap_or_else(|| "void".to_string());
+                self.terminate(format!("ret {}", value));
+            }
+            Statement::If(if_statement) => {
+                let condition = self.operand(&if_statement.condition)?;
+                let n = self.labels();
+                let (then, otherwise, end) = (
+                    format!("then.{}", n),
+                    format!("else.{}", n),
+                    format!("end.{}", n),
+                );
+                let target = match if_statement.else_block {
+                    Some(_) => &otherwise,
+                    None => &end,
+                };
+                self.terminate(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    condition, then, target
+                ));
+                self.start(&then);
+                self.block(&if_statement.then_block)?;
+                let mut reaches_end = if_statement.else_block.is_none();
+                if !self.body.terminated {
+                    self.terminate(format!("br label %{}", end));
+                    reaches_end = true;
+                }
+                if let Some(else_block) = &if_statement.else_block {
+                    self.start(&otherwise);
+                    self.block(else_block)?;
+                    if !self.body.terminated {
+                        self.terminate(format!("br label %{}", end));
+                        reaches_end = true;
+                    }
+                }
+                if reaches_end {
+                    self.start(&end);
+                }
+            }
+            Statement::While(while_statement) => {
+                let n = self.labels();
+                let (head, body, end) = (
+                    format!("while.{}", n),
+                    format!("body.{}", n),
+                    format!("end.{}", n),
+                );
+                self.terminate(format!("br label %{}", head));
+                self.start(&head);
+                let condition = self.operand(&while_statement.condition)?;
+                self.terminate(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    condition, body, end
+                ));
+                self.start(&body);
+                self.block(&while_statement.body)?;
+                if !self.body.terminated {
+                    self.terminate(format!("br label %{}", head));
+                }
+                self.start(&end);
+            }
+            Statement::Block(block) => self.block(block)?,
+        }
+        Ok(())
+    }
+
+    // Compiles an expression that has a value.
+    fn operand(&mut self, expression: &Expression) -> Result<String, CodegenError> {
+        Ok(self.expression(expression)?.expect("operands are not unit"))
+    }
+
+    // Compiles an expression, returning its value or `None` for unit.
+    fn expression(&mut self, expression: &Expression) -> Result<Option<String>, CodegenError> {
+        let span = expression.span;
+        let value = match &expression.kind {
+            ExpressionKind::Integer(value) => match self.kind(&expression.ty, span)? {
+                kind if kind.is_float() => float(*value as f64),
+                kind => match Value::integer(kind, *value) {
+                    Value::Integer(_, value) => value.to_string(),
+                    _ => unreachable!(),
+                },
+            },
+            ExpressionKind::Bool(value) => value.to_string(),
+            ExpressionKind::Name(binding) => {
+                if let Some(value) = self.body.locals.get(&binding.id) {
+                    value.clone()
+                } else if let Some((name, kind)) = self.globals.get(&binding.id) {
+                    let load = format!("load {0}, {0}* @{1}", llvm_type(*kind), name);
+                    self.value(load)
+                } else if self.functions.contains_key(&binding.id) {
+                    return Err(CodegenError::new("function values", span));
+                } else {
+                    return Err(CodegenError::new("nested functions", span));
+                }
+            }
+            ExpressionKind::Binary(operator, left, right) => self.binary(operator, left, right)?,
+            ExpressionKind::Call(callee, arguments) => {
+                let signature = match &callee.kind {
+                    ExpressionKind::Name(binding) => self.functions.get(&binding.id).cloned(),
+                    _ => None,
+                };
+                let Some(signature) = signature else {
+                    return Err(CodegenError::new("calls of function values", span));
+                };
+                let mut values = vec![];
+                for (argument, kind) in arguments.iter().zip(&signature.parameters) {
+                    let value = self.operand(argument)?;
+                    values.push(format!("{} {}", llvm_type(*kind), value));
+                }
+                let call = format!(
+                    "call {} @{}({})",
+                    llvm_type(signature.return_type),
+                    signature.symbol,
+                    values.join(", ")
+                );
+                if signature.return_type == TypeKind::Unit {
+                    self.instruction(call);
+                    return Ok(None);
+                }
+                self.value(call)
+            }
+            ExpressionKind::Builtin(..) => return Err(CodegenError::new("builtins", span)),
+            ExpressionKind::Cast(operand) => {
+                let to = self.kind(&expression.ty, span)?;
+                self.cast(operand, to)?
+            }
+            ExpressionKind::Error => panic!("cannot compile a program with errors"),
+        };
+        Ok(Some(value))
+    }
+
+    fn binary(
+        &mut self,
+        operator: &BinaryOperator,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<String, CodegenError> {
+        let kind = self.kind(&left.ty, left.span)?;
+        let ty = llvm_type(kind);
+        let left = self.operand(left)?;
+        let right = self.operand(right)?;
+        if operator.is_comparison() {
+            let (instruction, condition) = if kind.is_float() {
+                let condition = match operator {
+                    BinaryOperator::Equal => "oeq",
+                    BinaryOperator::NotEqual => "une",
+                    BinaryOperator::Less => "olt",
+                    BinaryOperator::LessEqual => "ole",
+                    BinaryOperator::Greater => "ogt",
+                    _ => "oge",
+                };
+                ("fcmp", condition)
+            } else {
+                let condition = match (operator, signed(kind)) {
+                    (BinaryOperator::Equal, _) => "eq",
+                    (BinaryOperator::NotEqual, _) => "ne",
+                    (BinaryOperator::Less, true) => "slt",
+                    (BinaryOperator::Less, false) => "ult",
+                    (BinaryOperator::LessEqual, true) => "sle",
+                    (BinaryOperator::LessEqual, false) => "ule",
+                    (BinaryOperator::Greater, true) => "sgt",
+                    (BinaryOperator::Greater, false) => "ugt",
+                    (_, true) => "sge",
+                    (_, false) => "uge",
+                };
+                ("icmp", condition)
+            };
+            return Ok(self.value(format!(
+                "{} {} {} {}, {}",
+                instruction, condition, ty, left, right
+            )));
+        }
+
+        if kind.is_float() {
+            let (left, right) = if kind == TypeKind::Float64 {
+                (left, right)
+            } else {
+                (
+                    self.value(format!("fpext {} {} to double", ty, left)),
+                    self.value(format!("fpext {} {} to double", ty, right)),
+                )
+            };
+            let result = match operator {
+                BinaryOperator::Power => {
+                    self.declarations
+                        .insert("declare double @llvm.pow.f64(double, double)".to_string());
+                    self.value(format!(
+                        "call double @llvm.pow.f64(double {}, double {})",
+                        left, right
+                    ))
+                }
+                operator => {
+                    let instruction = match operator {
+                        BinaryOperator::Plus => "fadd",
+                        BinaryOperator::Minus => "fsub",
+                        BinaryOperator::Star => "fmul",
+                        _ => "fdiv",
+                    };
+                    self.value(format!("{} double {}, {}", instruction, left, right))
+                }
+            };
+            if kind == TypeKind::Float64 {
+                return Ok(result);
+            }
+            return Ok(self.value(format!("fptrunc double {} to {}", result, ty)));
+        }
+
+        Ok(match operator {
+            BinaryOperator::Plus => self.value(format!("add {} {}, {}", ty, left, right)),
+            BinaryOperator::Minus => self.value(format!("sub {} {}, {}", ty, left, right)),
+            BinaryOperator::Star => self.value(format!("mul {} {}, {}", ty, left, right)),
+            BinaryOperator::Divide => {
+                let zero = self.value(format!("icmp eq {} {}, 0", ty, right));
+                let divide = format!("divide.{}", self.labels());
+                self.body.traps = true;
+                self.terminate(format!("br i1 {}, label %trap, label %{}", zero, divide));
+                self.start(&divide);
+                if !signed(kind) {
+                    return Ok(self.value(format!("udiv {} {}, {}", ty, left, right)));
+                }
+                // Dividing the most negative value by -1 overflows, which
+                // LLVM leaves undefined; it wraps around to itself.
+                let minus_one = self.value(format!("icmp eq {} {}, -1", ty, right));
+                let divisor = self.value(format!(
+                    "select i1 {}, {} 1, {} {}",
+                    minus_one, ty, ty, right
+                ));
+                let quotient = self.value(format!("sdiv {} {}, {}", ty, left, divisor));
+                let negated = self.value(format!("sub {} 0, {}", ty, left));
+                self.value(format!(
+                    "select i1 {}, {} {}, {} {}",
+                    minus_one, ty, negated, ty, quotient
+                ))
+            }
+            _ => {
+                self.power = true;
+                self.declarations
+                    .insert("declare void @llvm.trap()".to_string());
+                let (base, exponent) = if kind == TypeKind::Int64 {
+                    (left, right)
+                } else {
+                    let extend = if signed(kind) { "sext" } else { "zext" };
+                    (
+                        self.value(format!("{} {} {} to i64", extend, ty, left)),
+                        self.value(format!("{} {} {} to i64", extend, ty, right)),
+                    )
+                };
+                let power = self.value(format!(
+                    "call i64 @mylang.power(i64 {}, i64 {})",
+                    base, exponent
+                ));
+                if kind == TypeKind::Int64 {
+                    power
+                } else {
+                    self.value(format!("trunc i64 {} to {}", power, ty))
+                }
+            }
+        })
+    }
+
+    // Converts a value as an `as` cast does.
+    fn cast(&mut self, operand: &Expression, to: TypeKind) -> Result<String, CodegenError> {
+        let from = self.kind(&operand.ty, operand.span)?;
+        let value = self.operand(operand)?;
+        let (from_type, to_type) = (llvm_type(from), llvm_type(to));
+        Ok(
+            if bits(from) == bits(to) && from.is_float() == to.is_float() {
+                // `bool` and `int1` are both `i1`.
+                value
+            } else if from.is_float() && to.is_float() {
+                let instruction = if bits(from) < bits(to) {
+                    "fpext"
+                } else {
+                    "fptrunc"
+                };
+                self.value(format!(
+                    "{} {} {} to {}",
+                    instruction, from_type, value, to_type
+                ))
+            } else if to.is_float() {
+                // Integers convert to `double` first, as in the interpreter, so
+                // a narrower float rounds the same way.
+                let instruction = if signed(from) { "sitofp" } else { "uitofp" };
+                let wide = self.value(format!("{} {} {} to double", instruction, from_type, value));
+                if to == TypeKind::Float64 {
+                    wide
+                } else {
+                    self.value(format!("fptrunc double {} to {}", wide, to_type))
+                }
+            } else if from.is_float() {
+                let wide = if from == TypeKind::Float64 {
+                    value
+                } else {
+                    self.value(format!("fpext {} {} to double", from_type, value))
+                };
+                // These clamp to the integer's range and turn NaN into zero.
+                let intrinsic = format!(
+                    "llvm.fpto{}i.sat.{}.f64",
+                    if signed(to) { "s" } else { "u" },
+                    to_type
+                );
+                self.declarations
+                    .insert(format!("declare {} @{}(double)", to_type, intrinsic));
+                self.value(format!("call {} @{}(double {})", to_type, intrinsic, wide))
+            } else if bits(from) < bits(to) {
+                let instruction = if signed(from) { "sext" } else { "zext" };
+                self.value(format!(
+                    "{} {} {} to {}",
+                    instruction, from_type, value, to_type
+                ))
+            } else {
+                self.value(format!("trunc {} {} to {}", from_type, value, to_type))
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codegen::lower, interpreter::Interpreter};
+
+    // The machine running the tests, which `lli` and `llc` compile for.
+    fn host() -> Target {
+        Target::host().unwrap_or_else(Target::x86_64_linux)
+    }
+
+    #[test]
+    fn functions_compile_to_llvm_ir() {
+        let module = compile(
+            &lower(
+                "\
+let base: int64 = 10;
+fn f(n: int32, x: float32) -> int64 {
+    if n > 0 { return n / 2 + base; }
+    let y: float32 = x * 3;
+    return y as int64;
+}",
+            ),
+            &Target::x86_64_linux(),
+        )
+        .unwrap();
+        assert_eq!(
+            module.to_string(),
+            "\
+source_filename = \"mylang\"
+target triple = \"x86_64-unknown-linux-gnu\"
+@base = internal global i64 0
+
+define i64 @f(i32 %n, float %x) {
+entry:
+  %t.1 = icmp sgt i32 %n, 0
+  br i1 %t.1, label %then.1, label %end.1
+then.1:
+  %t.2 = icmp eq i32 2, 0
+  br i1 %t.2, label %trap, label %divide.2
+divide.2:
+  %t.3 = icmp eq i32 2, -1
+  %t.4 = select i1 %t.3, i32 1, i32 2
+  %t.5 = sdiv i32 %n, %t.4
+  %t.6 = sub i32 0, %n
+  %t.7 = select i1 %t.3, i32 %t.6, i32 %t.5
+  %t.8 = sext i32 %t.7 to i64
+  %t.9 = load i64, i64* @base
+  %t.10 = add i64 %t.8, %t.9
+  ret i64 %t.10
+end.1:
+  %t.11 = fpext float %x to double
+  %t.12 = fpext float 0x4008000000000000 to double
+  %t.13 = fmul double %t.11, %t.12
+  %t.14 = fptrunc double %t.13 to float
+  %t.15 = fpext float %t.14 to double
+  %t.16 = call i64 @llvm.fptosi.sat.i64.f64(double %t.15)
+  ret i64 %t.16
+trap:
+  call void @llvm.trap()
+  unreachable
+}
+
+define internal void @mylang.init() {
+entry:
+  store i64 10, i64* @base
+  ret void
+}
+
+@llvm.global_ctors = appending global [1 x { i32, void ()*, i8* }] \
+[{ i32, void ()*, i8* } { i32 65535, void ()* @mylang.init, i8* null }]
+
+declare i64 @llvm.fptosi.sat.i64.f64(double)
+declare void @llvm.trap()
+"
+        );
+    }
+
+    #[test]
+    fn unsupported_features_are_reported() {
+        for (source, unsupported, at) in [
+            (
+                "fn f() -> string { return read_line(); }",
+                "strings",
+                "fn f",
+            ),
+            ("fn f(x: int64) { println(x); }", "builtins", "println(x)"),
+            (
+                "fn f() { fn g() { } g(); }",
+                "nested functions",
+                "fn g() { }",
+            ),
+            (
+                "fn f(g: fn() -> int64) -> int64 { return g(); }",
+                "function values",
+                "fn f",
+            ),
+            ("fn f(x: bfloat16) { }", "`bfloat16` values", "fn f"),
+        ] {
+            let error = compile(&lower(source), &host()).unwrap_err();
+            assert_eq!(error.unsupported, unsupported, "{}", source);
+            assert!(source[error.span.range()].starts_with(at), "{}", source);
+        }
+    }
+
+    // Skips a test when the LLVM tools are not installed.
+    fn installed(result: &Result<Option<Value>, ToolError>) -> bool {
+        !matches!(result, Err(ToolError::Io(error)) if error.kind() == io::ErrorKind::NotFound)
+    }
+
+    #[test]
+    fn compiled_programs_agree_with_the_interpreter() {
+        let corpus = [
+            "fn main() -> int64 { return 1 + 2 * 3 ** 2 - 4 / 3; }",
+            "\
+fn fib(n: int32) -> int32 {
+    if n < 2 { return n; } else { return fib(n - 1) + fib(n - 2); }
+}
+fn main() -> int32 { return fib(15); }",
+            "\
+fn main() -> int8 {
+    let a: int8 = 120;
+    let b: int8 = a + 10;
+    let c: int16 = 1000;
+    return b * 3 + c as int8;
+}",
+            "\
+fn main() -> int4 {
+    let x: int4 = 7;
+    let y: int2 = 1;
+    let z: int1 = 1;
+    return x + (y + 1) as int4 + (z + z) as int4 + (z > 0) as int4;
+}",
+            "\
+fn main() -> float16 {
+    let h: float16 = 2049;
+    let x: float32 = 1;
+    return h / 3 + (x / 10) as float16;
+}",
+            "\
+fn main() -> float64 {
+    let x: float32 = 1;
+    let tenth: float32 = x / 10;
+    return (tenth + tenth + tenth) as float64 ** 2;
+}",
+            "\
+fn main() -> bool {
+    let x: float64 = 0;
+    let nan: float64 = x / x;
+    return nan != nan;
+}",
+            "\
+fn main() -> int16 {
+    let x: float64 = 1;
+    let big: float64 = x * 100000;
+    let zero: float64 = 0;
+    return big as int16 + (zero / zero) as int16 + (x - 3) as int2;
+}",
+            "\
+let base: int64 = 1000;
+fn add(x: int64) -> int64 { return x + base; }
+fn main() -> int64 { return add(add(1)); }",
+            "\
+fn main() -> int64 {
+    let n: int64 = 3;
+    while n > 0 {
+        if n == 3 { return 30; }
+    }
+    return 0;
+}",
+            "\
+fn side(x: int64) { }
+fn main() -> int64 {
+    let big: int64 = 0 - 9223372036854775807 - 1;
+    side(big);
+    let minus: int64 = 0 - 1;
+    let seven: int64 = 7;
+    { let inner: int64 = big / minus; return inner + seven ** 30; }
+}",
+            "fn main() { }",
+        ];
+        for source in corpus {
+            let hir = lower(source);
+            let mut interpreter = Interpreter::new(&hir);
+            interpreter.run().unwrap();
+            let expected = interpreter.call("main", vec![]).unwrap();
+            let result = compile(&hir, &host()).unwrap().run("main", vec![]);
+            if !installed(&result) {
+                return;
+            }
+            assert_eq!(result.unwrap(), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn runtime_errors_trap() {
+        let module = compile(
+            &lower("fn divide(x: int64, y: int64) -> int64 { return x / y; }"),
+            &host(),
+        )
+        .unwrap();
+        let int64 = |value| Value::integer(TypeKind::Int64, value);
+        let result = module.run("divide", vec![int64(7), int64(2)]);
+        if !installed(&result) {
+            return;
+        }
+        assert_eq!(result.unwrap(), Some(int64(3)));
+        let result = module.run("divide", vec![int64(7), int64(0)]);
+        assert!(matches!(result, Err(ToolError::Failed(_))), "{:?}", result);
+        let result = module.run("divide", vec![int64(7)]);
+        assert!(matches!(result, Err(ToolError::NoSuchFunction(_))));
+    }
+
+    #[test]
+    fn object_files_can_be_written() {
+        let module = compile(
+            &lower("fn add(a: int64, b: int64) -> int64 { return a + b; }"),
+            &host(),
+        )
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("mylang-{}.o", std::process::id()));
+        match module.emit_object(&path) {
+            Err(ToolError::Io(error)) if error.kind() == io::ErrorKind::NotFound => return,
+            result => result.unwrap(),
+        }
+        let object = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!object.is_empty());
+    }
+}
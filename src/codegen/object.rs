@@ -0,0 +1,860 @@
+use super::{target::Target, x86_64, CodegenError};
+use crate::{hir, source_map::SourceMap};
+use object::{
+    elf,
+    write::{Object, Relocation, SectionId, Symbol, SymbolId, SymbolSection},
+    Architecture, BinaryFormat, Endianness, RelocationFlags, SectionFlags, SectionKind,
+    SymbolFlags, SymbolKind, SymbolScope,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    path::Path,
+    process::Command,
+};
+
+mod dwarf;
+
+// Compiles a lowered program to an ELF relocatable object for an x86-64
+// target, which the system linker can link into an executable or with C
+// code.
+//
+// The program is compiled by the `x86_64` backend, and the assembly it
+// prints is assembled here, so what that backend supports is what can be
+// compiled. Only the instructions and directives it prints are understood.
+pub fn compile(program: &hir::Program, target: &Target) -> Result<Vec<u8>, CodegenError> {
+    Ok(assemble(&x86_64::compile(program, target)?))
+}
+
+// Compiles a program like `compile`, with DWARF line tables mapping its code
+// back to the lines and columns of `source`, for debuggers.
+pub fn compile_with_debug_info(
+    program: &hir::Program,
+    target: &Target,
+    source: &SourceMap,
+) -> Result<Vec<u8>, CodegenError> {
+    let assembly = x86_64::compile_with_debug_info(program, target, source)?;
+    Ok(assemble(&assembly))
+}
+
+// Links objects into an executable with the C compiler, `$CC` or else `cc`,
+// which knows where the C runtime and library are. A program with a `main`
+// function becomes an executable that runs it.
+pub fn link(objects: &[&Path], output: &Path) -> Result<(), LinkError> {
+    let linker = std::env::var_os("CC").unwrap_or_else(|| "cc".into());
+    let linked = Command::new(&linker)
+        .arg("-o")
+        .arg(output)
+        .args(objects)
+        .output()
+        .map_err(|error| match error.kind() {
+            io::ErrorKind::NotFound => LinkError::NoLinker(linker.to_string_lossy().into()),
+            _ => LinkError::Io(error),
+        })?;
+    if !linked.status.success() {
+        let message = String::from_utf8_lossy(&linked.stderr).trim().to_string();
+        return Err(LinkError::Failed(message));
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum LinkError {
+    // The linker to run was not found.
+    NoLinker(String),
+    Io(io::Error),
+    // The linker ran and failed, with what it printed.
+    Failed(String),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::NoLinker(linker) => write!(f, "cannot find the linker `{}`", linker),
+            LinkError::Io(error) => write!(f, "cannot run the linker: {}", error),
+            LinkError::Failed(message) => write!(f, "linking failed:\n{}", message),
+        }
+    }
+}
+
+// The sections the assembly puts things in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Section {
+    Text,
+    InitArray,
+    Bss,
+    // `.note.GNU-stack`, which is empty and always written.
+    Note,
+}
+
+// An operand of an instruction.
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Immediate(i64),
+    // A register, by its number in encodings.
+    Register(u8),
+    // An offset from the frame pointer, like `-8(%rbp)`.
+    Frame(i32),
+    // The address of a symbol relative to the instruction pointer, like
+    // `x(%rip)`.
+    Relative(String),
+    // A label or symbol, the target of a jump or call.
+    Symbol(String),
+    // A register holding the target of a call, like `*%r11`.
+    Indirect(u8),
+}
+
+// Condition codes, which are added to the opcodes of conditional
+// instructions.
+fn condition(name: &str) -> Option<u8> {
+    Some(match name {
+        "b" => 0x2,
+        "ae" => 0x3,
+        "e" => 0x4,
+        "ne" => 0x5,
+        "be" => 0x6,
+        "a" => 0x7,
+        "s" => 0x8,
+        "ns" => 0x9,
+        "l" => 0xc,
+        "ge" => 0xd,
+        "le" => 0xe,
+        "g" => 0xf,
+        _ => return None,
+    })
+}
+
+// Returns the number of a register, in any of its widths.
+fn register(name: &str) -> Option<u8> {
+    const REGISTERS: [[&str; 4]; 8] = [
+        ["rax", "eax", "ax", "al"],
+        ["rcx", "ecx", "cx", "cl"],
+        ["rdx", "edx", "dx", "dl"],
+        ["rbx", "ebx", "bx", "bl"],
+        ["rsp", "esp", "sp", ""],
+        ["rbp", "ebp", "bp", ""],
+        ["rsi", "esi", "si", ""],
+        ["rdi", "edi", "di", ""],
+    ];
+    if let Some(number) = REGISTERS.iter().position(|names| names.contains(&name)) {
+        return Some(number as u8);
+    }
+    let number: u8 = name.strip_prefix('r')?.parse().ok()?;
+    (8..16).contains(&number).then_some(number)
+}
+
+fn operand(text: &str) -> Operand {
+    let malformed = || -> ! { panic!("malformed operand `{}`", text) };
+    if let Some(value) = text.strip_prefix('$') {
+        return Operand::Immediate(value.parse().unwrap_or_else(|_| malformed()));
+    }
+    if let Some(name) = text.strip_prefix("*%") {
+        return Operand::Indirect(register(name).unwrap_or_else(|| malformed()));
+    }
+    if let Some(name) = text.strip_prefix('%') {
+        return Operand::Register(register(name).unwrap_or_else(|| malformed()));
+    }
+    if let Some(symbol) = text.strip_suffix("(%rip)") {
+        return Operand::Relative(symbol.to_string());
+    }
+    if let Some(offset) = text.strip_suffix("(%rbp)") {
+        return Operand::Frame(offset.parse().unwrap_or_else(|_| malformed()));
+    }
+    Operand::Symbol(text.to_string())
+}
+
+// Assembles what the `x86_64` backend prints into an object.
+fn assemble(assembly: &str) -> Vec<u8> {
+    let mut assembler = Assembler::new();
+    for line in assembly.lines() {
+        assembler.line(line.trim());
+    }
+    assembler.finish()
+}
+
+struct Assembler {
+    section: Section,
+    // The contents of each section but `.bss`.
+    data: HashMap<Section, Vec<u8>>,
+    // The size of `.bss`.
+    bss: u64,
+    // The section and offset of each label.
+    labels: HashMap<String, (Section, u64)>,
+    // The symbols declared with `.globl`.
+    globals: HashSet<String>,
+    // The offset of each jump's 32-bit displacement in `.text`, and the
+    // label it jumps to.
+    jumps: Vec<(u64, String)>,
+    // The relocations to make: the section and offset of each place, the
+    // symbol, the addend and the ELF relocation type.
+    relocations: Vec<(Section, u64, String, i64, u32)>,
+    // The source file named by `.file`, if any.
+    file: Option<String>,
+    // Where the code comes from, as `.loc` directives say.
+    rows: Vec<dwarf::Row>,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            section: Section::Text,
+            data: HashMap::new(),
+            bss: 0,
+            labels: HashMap::new(),
+            globals: HashSet::new(),
+            jumps: vec![],
+            relocations: vec![],
+            file: None,
+            rows: vec![],
+        }
+    }
+
+    // Assembles a line: a label, a directive or an instruction.
+    fn line(&mut self, line: &str) {
+        if line.is_empty() {
+            return;
+        }
+        if let Some(label) = line.strip_suffix(':') {
+            let place = (self.section, self.offset());
+            self.labels.insert(label.to_string(), place);
+            return;
+        }
+        let (mnemonic, operands) = line.split_once('\t').unwrap_or((line, ""));
+        if mnemonic.starts_with('.') {
+            self.directive(mnemonic, operands);
+        } else {
+            let operands: Vec<Operand> = match operands {
+                "" => vec![],
+                operands => operands.split(", ").map(operand).collect(),
+            };
+            self.instruction(mnemonic, &operands);
+        }
+    }
+
+    fn offset(&self) -> u64 {
+        match self.section {
+            Section::Bss => self.bss,
+            section => self.data.get(&section).map_or(0, |data| data.len() as u64),
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data.entry(self.section).or_default().extend(bytes);
+    }
+
+    fn relocate(&mut self, symbol: &str, addend: i64, r_type: u32) {
+        let offset = self.offset();
+        let symbol = symbol.to_string();
+        self.relocations
+            .push((self.section, offset, symbol, addend, r_type));
+    }
+
+    fn directive(&mut self, directive: &str, operands: &str) {
+        match directive {
+            ".text" => self.section = Section::Text,
+            ".bss" => self.section = Section::Bss,
+            ".section" => {
+                let name = operands.split(',').next().unwrap_or_default();
+                self.section = match name {
+                    ".init_array" => Section::InitArray,
+                    ".note.GNU-stack" => Section::Note,
+                    name => panic!("unexpected section `{}`", name),
+                };
+            }
+            ".globl" => {
+                self.globals.insert(operands.to_string());
+            }
+            ".align" => {
+                let alignment: u64 = operands.parse().expect("an alignment");
+                let padding = self.offset().next_multiple_of(alignment) - self.offset();
+                self.zero(padding);
+            }
+            ".zero" => self.zero(operands.parse().expect("a size")),
+            // Only the first file is used.
+            ".file" => {
+                let (_, name) = operands.split_once(' ').expect("a file number and name");
+                let name = name.trim_matches('"').replace("\\\"", "\"");
+                self.file = Some(name.replace("\\\\", "\\"));
+            }
+            ".loc" => {
+                let numbers: Vec<u64> = operands
+                    .split(' ')
+                    .map(|number| number.parse().expect("a number"))
+                    .collect();
+                let [_, line, column] = numbers[..] else {
+                    panic!("malformed location `{}`", operands);
+                };
+                let offset = self.offset();
+                // Code comes from the last place said before it.
+                if self.rows.last().is_some_and(|row| row.offset == offset) {
+                    self.rows.pop();
+                }
+                self.rows.push(dwarf::Row {
+                    offset,
+                    line,
+                    column,
+                });
+            }
+            ".quad" => {
+                self.relocate(operands, 0, elf::R_X86_64_64);
+                self.bytes(&[0; 8]);
+            }
+            directive => panic!("unexpected directive `{}`", directive),
+        }
+    }
+
+    fn zero(&mut self, size: u64) {
+        match self.section {
+            Section::Bss => self.bss += size,
+            _ => self.bytes(&vec![0; size as usize]),
+        }
+    }
+
+    // Encodes an instruction whose operand `rm` is in the ModRM byte, with
+    // `reg` being the other operand's register or an extension of the
+    // opcode. `wide` instructions work on 64 bits.
+    fn modrm(&mut self, wide: bool, opcode: &[u8], reg: u8, rm: &Operand) {
+        let base = match rm {
+            Operand::Register(number) | Operand::Indirect(number) => *number,
+            _ => 0,
+        };
+        let rex = 0x40 | (wide as u8) << 3 | (reg >> 3) << 2 | base >> 3;
+        if rex != 0x40 {
+            self.bytes(&[rex]);
+        }
+        self.bytes(opcode);
+        let reg = (reg & 7) << 3;
+        match rm {
+            Operand::Register(number) | Operand::Indirect(number) => {
+                self.bytes(&[0xc0 | reg | number & 7])
+            }
+            // `%rbp` as a base always has a displacement.
+            Operand::Frame(offset) => match i8::try_from(*offset) {
+                Ok(offset) => self.bytes(&[0x45 | reg, offset as u8]),
+                Err(_) => {
+                    self.bytes(&[0x85 | reg]);
+                    self.bytes(&offset.to_le_bytes());
+                }
+            },
+            Operand::Relative(symbol) => {
+                self.bytes(&[0x05 | reg]);
+                // The displacement is from the end of the instruction,
+                // which is where it ends.
+                self.relocate(symbol, -4, elf::R_X86_64_PC32);
+                self.bytes(&[0; 4]);
+            }
+            operand => panic!("unexpected operand {:?}", operand),
+        }
+    }
+
+    // Encodes a jump or call to a 32-bit displacement.
+    fn jump(&mut self, opcode: &[u8], target: &Operand) {
+        self.bytes(opcode);
+        let Operand::Symbol(target) = target else {
+            panic!("unexpected jump target {:?}", target);
+        };
+        if target.starts_with(".L") {
+            self.jumps.push((self.offset(), target.clone()));
+        } else {
+            self.relocate(target, -4, elf::R_X86_64_PLT32);
+        }
+        self.bytes(&[0; 4]);
+    }
+
+    fn instruction(&mut self, mnemonic: &str, operands: &[Operand]) {
+        use Operand::*;
+        // The opcode extensions of the arithmetic instructions with an
+        // immediate operand, and their opcodes with a register operand.
+        let arithmetic = |mnemonic| match mnemonic {
+            "addq" => Some((0, 0x01)),
+            "andq" => Some((4, 0x21)),
+            "subq" => Some((5, 0x29)),
+            "cmpq" => Some((7, 0x39)),
+            _ => None,
+        };
+        match (mnemonic, operands) {
+            ("movq", [Immediate(value), rm]) => {
+                self.modrm(true, &[0xc7], 0, rm);
+                self.bytes(&(*value as i32).to_le_bytes());
+            }
+            ("movq", [Register(source), rm]) => self.modrm(true, &[0x89], *source, rm),
+            ("movq", [rm, Register(destination)]) => self.modrm(true, &[0x8b], *destination, rm),
+            ("movabsq", [Immediate(value), Register(destination)]) => {
+                self.bytes(&[0x48 | destination >> 3, 0xb8 + (destination & 7)]);
+                self.bytes(&value.to_le_bytes());
+            }
+            ("leaq", [rm, Register(destination)]) => self.modrm(true, &[0x8d], *destination, rm),
+            (mnemonic, [Immediate(value), rm]) if arithmetic(mnemonic).is_some() => {
+                let (extension, _) = arithmetic(mnemonic).unwrap();
+                match i8::try_from(*value) {
+                    Ok(value) => {
+                        self.modrm(true, &[0x83], extension, rm);
+                        self.bytes(&[value as u8]);
+                    }
+                    Err(_) => {
+                        self.modrm(true, &[0x81], extension, rm);
+                        self.bytes(&(*value as i32).to_le_bytes());
+                    }
+                }
+            }
+            (mnemonic, [Register(source), rm]) if arithmetic(mnemonic).is_some() => {
+                let (_, opcode) = arithmetic(mnemonic).unwrap();
+                self.modrm(true, &[opcode], *source, rm);
+            }
+            ("testq", [Immediate(value), rm]) => {
+                self.modrm(true, &[0xf7], 0, rm);
+                self.bytes(&(*value as i32).to_le_bytes());
+            }
+            ("testq", [Register(source), rm]) => self.modrm(true, &[0x85], *source, rm),
+            ("xorl", [Register(source), rm]) => self.modrm(false, &[0x31], *source, rm),
+            ("imulq", [rm, Register(destination)]) => {
+                self.modrm(true, &[0x0f, 0xaf], *destination, rm)
+            }
+            ("negq", [rm]) => self.modrm(true, &[0xf7], 3, rm),
+            ("divq", [rm]) => self.modrm(true, &[0xf7], 6, rm),
+            ("idivq", [rm]) => self.modrm(true, &[0xf7], 7, rm),
+            ("shlq", [Immediate(count), rm]) => {
+                self.modrm(true, &[0xc1], 4, rm);
+                self.bytes(&[*count as u8]);
+            }
+            ("sarq", [Immediate(count), rm]) => {
+                self.modrm(true, &[0xc1], 7, rm);
+                self.bytes(&[*count as u8]);
+            }
+            ("shrq", [rm]) => self.modrm(true, &[0xd1], 5, rm),
+            ("movsbq", [rm, Register(destination)]) => {
+                self.modrm(true, &[0x0f, 0xbe], *destination, rm)
+            }
+            ("movswq", [rm, Register(destination)]) => {
+                self.modrm(true, &[0x0f, 0xbf], *destination, rm)
+            }
+            ("movslq", [rm, Register(destination)]) => self.modrm(true, &[0x63], *destination, rm),
+            ("movzbq", [rm, Register(destination)]) => {
+                self.modrm(true, &[0x0f, 0xb6], *destination, rm)
+            }
+            ("pushq", [Register(number)]) => self.short(0x50, *number),
+            ("popq", [Register(number)]) => self.short(0x58, *number),
+            ("call", [target @ Symbol(_)]) => self.jump(&[0xe8], target),
+            ("call", [rm @ Indirect(_)]) => self.modrm(false, &[0xff], 2, rm),
+            ("jmp", [target]) => self.jump(&[0xe9], target),
+            ("cqto", []) => self.bytes(&[0x48, 0x99]),
+            ("leave", []) => self.bytes(&[0xc9]),
+            ("ret", []) => self.bytes(&[0xc3]),
+            ("ud2", []) => self.bytes(&[0x0f, 0x0b]),
+            (mnemonic, [target]) if mnemonic.starts_with('j') => {
+                let code = condition(&mnemonic[1..]).expect("a condition");
+                self.jump(&[0x0f, 0x80 + code], target);
+            }
+            (mnemonic, [rm]) if mnemonic.starts_with("set") => {
+                let code = condition(&mnemonic[3..]).expect("a condition");
+                self.modrm(false, &[0x0f, 0x90 + code], 0, rm);
+            }
+            _ => panic!("unexpected instruction `{}` {:?}", mnemonic, operands),
+        }
+    }
+
+    // Encodes an instruction with its register in the opcode.
+    fn short(&mut self, opcode: u8, register: u8) {
+        if register >= 8 {
+            self.bytes(&[0x41]);
+        }
+        self.bytes(&[opcode + (register & 7)]);
+    }
+
+    // Returns the code, with the jumps to labels resolved.
+    fn text(&mut self) -> Vec<u8> {
+        let mut text = self.data.remove(&Section::Text).unwrap_or_default();
+        for (offset, label) in &self.jumps {
+            let (_, target) = self.labels[label];
+            let displacement = target as i64 - (*offset as i64 + 4);
+            let offset = *offset as usize;
+            text[offset..offset + 4].copy_from_slice(&(displacement as i32).to_le_bytes());
+        }
+        text
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let text = self.text();
+
+        let mut object = Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        let mut sections: HashMap<Section, SectionId> = HashMap::new();
+        let id = object.add_section(vec![], b".text".to_vec(), SectionKind::Text);
+        object.append_section_data(id, &text, 16);
+        sections.insert(Section::Text, id);
+        if let Some(data) = self.data.get(&Section::InitArray) {
+            let id = object.add_section(
+                vec![],
+                b".init_array".to_vec(),
+                SectionKind::Elf(elf::SHT_INIT_ARRAY),
+            );
+            object.section_mut(id).flags = SectionFlags::Elf {
+                sh_flags: u64::from(elf::SHF_ALLOC | elf::SHF_WRITE),
+            };
+            object.append_section_data(id, data, 8);
+            sections.insert(Section::InitArray, id);
+        }
+        if self.bss > 0 {
+            let id = object.add_section(vec![], b".bss".to_vec(), SectionKind::UninitializedData);
+            object.append_section_bss(id, self.bss, 8);
+            sections.insert(Section::Bss, id);
+        }
+        // Without it, linkers make the stack executable.
+        object.add_section(vec![], b".note.GNU-stack".to_vec(), SectionKind::Other);
+        if let Some(file) = &self.file {
+            let size = text.len() as u64;
+            dwarf::write(
+                &mut object,
+                sections[&Section::Text],
+                size,
+                file,
+                &self.rows,
+            );
+        }
+
+        let mut symbols: HashMap<&str, SymbolId> = HashMap::new();
+        let mut labels: Vec<(&String, &(Section, u64))> = self
+            .labels
+            .iter()
+            .filter(|(label, _)| !label.starts_with(".L"))
+            .collect();
+        // Symbols are written in the order they are defined.
+        labels.sort_by_key(|(_, (section, offset))| (*section != Section::Text, *offset));
+        for (label, (section, offset)) in labels {
+            let kind = match section {
+                Section::Text => SymbolKind::Text,
+                _ => SymbolKind::Data,
+            };
+            let scope = match self.globals.contains(label) {
+                true => SymbolScope::Dynamic,
+                false => SymbolScope::Compilation,
+            };
+            let id = object.add_symbol(Symbol {
+                name: label.as_bytes().to_vec(),
+                value: *offset,
+                size: 0,
+                kind,
+                scope,
+                weak: false,
+                section: SymbolSection::Section(sections[section]),
+                flags: SymbolFlags::None,
+            });
+            symbols.insert(label, id);
+        }
+        for (section, offset, symbol, addend, r_type) in &self.relocations {
+            let id = *symbols.entry(symbol).or_insert_with(|| {
+                // Functions declared without a body are defined elsewhere.
+                object.add_symbol(Symbol {
+                    name: symbol.as_bytes().to_vec(),
+                    value: 0,
+                    size: 0,
+                    kind: SymbolKind::Unknown,
+                    scope: SymbolScope::Dynamic,
+                    weak: false,
+                    section: SymbolSection::Undefined,
+                    flags: SymbolFlags::None,
+                })
+            });
+            let relocation = Relocation {
+                offset: *offset,
+                symbol: id,
+                addend: *addend,
+                flags: RelocationFlags::Elf { r_type: *r_type },
+            };
+            object
+                .add_relocation(sections[section], relocation)
+                .expect("a valid relocation");
+        }
+        object.write().expect("a valid object")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codegen::lower, interpreter::Interpreter, value::Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Assembles lines of code, returning it as hexadecimal bytes.
+    fn encode(lines: &[&str]) -> String {
+        let mut assembler = Assembler::new();
+        for line in lines {
+            assembler.line(line);
+        }
+        let bytes: Vec<String> = assembler
+            .text()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        bytes.join(" ")
+    }
+
+    #[test]
+    fn instructions_are_encoded_like_the_gnu_assembler() {
+        for (line, bytes) in [
+            ("movq\t$100, %rax", "48 c7 c0 64 00 00 00"),
+            ("movq\t$-1, %rax", "48 c7 c0 ff ff ff ff"),
+            ("movq\t%rax, %rcx", "48 89 c1"),
+            ("movq\t%rdi, %rax", "48 89 f8"),
+            ("movq\t%r12, %rax", "4c 89 e0"),
+            ("movq\t%rax, -8(%rbp)", "48 89 45 f8"),
+            ("movq\t-16(%rbp), %r12", "4c 8b 65 f0"),
+            ("movq\t%r15, -1024(%rbp)", "4c 89 bd 00 fc ff ff"),
+            ("movq\t%rax, g(%rip)", "48 89 05 00 00 00 00"),
+            ("movq\tg(%rip), %rax", "48 8b 05 00 00 00 00"),
+            (
+                "movabsq\t$9223372036854775807, %rax",
+                "48 b8 ff ff ff ff ff ff ff 7f",
+            ),
+            ("leaq\tf(%rip), %rax", "48 8d 05 00 00 00 00"),
+            ("addq\t%rcx, %rax", "48 01 c8"),
+            ("subq\t%rcx, %rax", "48 29 c8"),
+            ("cmpq\t%rcx, %rax", "48 39 c8"),
+            ("andq\t$1, %rax", "48 83 e0 01"),
+            ("subq\t$8, %rsp", "48 83 ec 08"),
+            ("subq\t$4096, %rsp", "48 81 ec 00 10 00 00"),
+            ("cmpq\t$-1, %rcx", "48 83 f9 ff"),
+            ("testq\t%rax, %rax", "48 85 c0"),
+            ("testq\t$1, %rcx", "48 f7 c1 01 00 00 00"),
+            ("xorl\t%edx, %edx", "31 d2"),
+            ("imulq\t%rdx, %rdx", "48 0f af d2"),
+            ("negq\t%rax", "48 f7 d8"),
+            ("divq\t%rcx", "48 f7 f1"),
+            ("idivq\t%rcx", "48 f7 f9"),
+            ("shlq\t$62, %rax", "48 c1 e0 3e"),
+            ("sarq\t$60, %rax", "48 c1 f8 3c"),
+            ("shrq\t%rcx", "48 d1 e9"),
+            ("movsbq\t%al, %rax", "48 0f be c0"),
+            ("movswq\t%ax, %rax", "48 0f bf c0"),
+            ("movslq\t%eax, %rax", "48 63 c0"),
+            ("movzbq\t%al, %rax", "48 0f b6 c0"),
+            ("setl\t%al", "0f 9c c0"),
+            ("setae\t%al", "0f 93 c0"),
+            ("pushq\t%rbp", "55"),
+            ("popq\t%r9", "41 59"),
+            ("call\t*%r11", "41 ff d3"),
+            ("cqto", "48 99"),
+            ("leave", "c9"),
+            ("ret", "c3"),
+            ("ud2", "0f 0b"),
+        ] {
+            assert_eq!(encode(&[line]), bytes, "{}", line);
+        }
+    }
+
+    #[test]
+    fn jumps_are_resolved() {
+        assert_eq!(
+            encode(&["jmp\t.L2", "ud2", "je\t.L2", ".L2:", "jne\t.L2"]),
+            "e9 08 00 00 00 0f 0b 0f 84 00 00 00 00 0f 85 fa ff ff ff"
+        );
+    }
+
+    // Writes a file of its own for a test, since tests run in parallel.
+    fn temporary(extension: &str) -> std::path::PathBuf {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        std::env::temp_dir()
+            .join(format!(
+                "mylang-object-{}-{}",
+                std::process::id(),
+                FILES.fetch_add(1, Ordering::Relaxed)
+            ))
+            .with_extension(extension)
+    }
+
+    // Links an object with `files` into an executable and runs it, returning
+    // its exit status and output, or `None` when there is no linker or this
+    // is not an x86-64 Linux machine, which could run the ELF executable.
+    fn run(object: &[u8], files: &[&Path]) -> Option<(i32, String)> {
+        if !cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            return None;
+        }
+        let (path, executable) = (temporary("o"), temporary(""));
+        std::fs::write(&path, object).unwrap();
+        let mut objects = vec![path.as_path()];
+        objects.extend(files);
+        let linked = link(&objects, &executable);
+        std::fs::remove_file(&path).unwrap();
+        match linked {
+            Err(LinkError::NoLinker(_)) => return None,
+            linked => linked.unwrap(),
+        }
+        let output = Command::new(&executable).output().unwrap();
+        std::fs::remove_file(&executable).unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Some((output.status.code().unwrap_or(-1), stdout))
+    }
+
+    #[test]
+    fn programs_with_main_link_into_executables() {
+        let object = compile(
+            &lower(
+                "\
+let base: int32 = 40;
+fn add(x: int32) -> int32 { return x + base; }
+fn apply(f: fn(int32) -> int32, x: int32) -> int32 { return f(x); }
+fn main() -> int32 { return apply(add, 2); }",
+            ),
+            &Target::x86_64_linux(),
+        )
+        .unwrap();
+        let Some(result) = run(&object, &[]) else {
+            return;
+        };
+        assert_eq!(result, (42, String::new()));
+        let object = compile(
+            &lower("fn f() -> int32 { return 0; }"),
+            &Target::x86_64_linux(),
+        )
+        .unwrap();
+        let path = temporary("o");
+        std::fs::write(&path, object).unwrap();
+        let error = link(&[&path], &temporary("")).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(error, LinkError::Failed(_)), "{}", error);
+    }
+
+    #[test]
+    fn line_tables_say_where_code_comes_from() {
+        let source = "\
+fn add(a: int32, b: int32) -> int32 {
+    let c: int32 = a + b;
+    return c;
+}
+fn main() -> int32 {
+    let x: int32 = add(1, 2);
+    if x > 2 {
+        return x;
+    }
+    return 0;
+}
+";
+        let map = SourceMap::new("main.my", source);
+        let object =
+            compile_with_debug_info(&lower(source), &Target::x86_64_linux(), &map).unwrap();
+        let path = temporary("o");
+        std::fs::write(&path, &object).unwrap();
+        let dump = |option| {
+            Command::new("llvm-dwarfdump")
+                .arg(option)
+                .arg(&path)
+                .output()
+        };
+        let (verified, lines) = (dump("--verify"), dump("--debug-line"));
+        std::fs::remove_file(&path).unwrap();
+        if let (Ok(verified), Ok(lines)) = (verified, lines) {
+            assert!(verified.status.success());
+            // The rows of the table, after its header, are the address,
+            // line and column.
+            let lines = String::from_utf8(lines.stdout).unwrap();
+            let rows: Vec<(u64, u64)> = lines
+                .lines()
+                .skip_while(|line| !line.starts_with("----"))
+                .skip(1)
+                .take_while(|row| !row.is_empty())
+                .map(|row| {
+                    let fields: Vec<&str> = row.split_whitespace().collect();
+                    (fields[1].parse().unwrap(), fields[2].parse().unwrap())
+                })
+                .collect();
+            assert_eq!(
+                rows,
+                [
+                    (1, 1),
+                    (2, 5),
+                    (3, 5),
+                    (5, 1),
+                    (6, 5),
+                    (7, 5),
+                    (8, 9),
+                    (10, 5),
+                    (10, 5)
+                ]
+            );
+        }
+        if let Some(result) = run(&object, &[]) {
+            assert_eq!(result, (3, String::new()));
+        }
+    }
+
+    #[test]
+    fn compiled_objects_agree_with_the_interpreter() {
+        const DRIVER: &str = "\
+#include <stdio.h>
+long long entry(void);
+long long external(long long x) { return x * 2; }
+int main(void) { printf(\"%lld\\n\", entry()); return 0; }
+";
+        let driver = temporary("c");
+        std::fs::write(&driver, DRIVER).unwrap();
+        for source in [
+            "\
+fn fib(n: int32) -> int32 {
+    if n < 2 { return n; } else { return fib(n - 1) + fib(n - 2); }
+}
+fn entry() -> int32 { return fib(20); }",
+            "\
+fn entry() -> int8 {
+    let a: int8 = 120;
+    let b: int8 = a + 10;
+    let c: int16 = 1000;
+    let d: int8 = 0 - 127 - 1;
+    let minus: int8 = 0 - 1;
+    return b * 3 + c as int8 + d / minus + minus ** 3;
+}",
+            "\
+fn entry() -> int4 {
+    let x: int4 = 7;
+    let y: int2 = 1;
+    let z: int1 = 1;
+    return x + (y + 1) as int4 + (z + z) as int4 + (z > 0) as int4;
+}",
+            "\
+let big: int64 = 9223372036854775807;
+let small: int64 = big / 1000000000000;
+fn entry() -> int64 {
+    let n: int64 = 10;
+    while n > 0 {
+        if big / small > 1000 { return big - small * 3 + (small <= big) as int64; }
+    }
+    return 0;
+}",
+            "\
+fn square(x: int64) -> int64 { let y: int64 = x * x; return y; }
+fn entry() -> int64 {
+    let a: int64 = square(1);
+    let b: int64 = square(2);
+    let c: int64 = square(3);
+    let d: int64 = square(4);
+    let e: int64 = square(5);
+    let f: int64 = square(6);
+    let g: int64 = square(7);
+    return a + b * 10 + c * 100 + d * 1000 + e * 10000 + f * 100000 + g;
+}",
+        ] {
+            let hir = lower(source);
+            let mut interpreter = Interpreter::new(&hir);
+            interpreter.run().unwrap();
+            let expected = match interpreter.call("entry", vec![]).unwrap() {
+                Some(Value::Integer(_, value)) => value,
+                value => panic!("unexpected result {:?}", value),
+            };
+            let Some(result) = run(&compile(&hir, &Target::x86_64_linux()).unwrap(), &[&driver])
+            else {
+                break;
+            };
+            assert_eq!(result, (0, expected.to_string()), "{}", source);
+        }
+
+        // Functions declared without a body are linked from elsewhere.
+        let source = "\
+fn external(x: int64) -> int64;
+fn entry() -> int64 { return external(21); }";
+        if let Some(result) = run(
+            &compile(&lower(source), &Target::x86_64_linux()).unwrap(),
+            &[&driver],
+        ) {
+            assert_eq!(result, (0, "42".to_string()));
+        }
+        std::fs::remove_file(&driver).unwrap();
+    }
+}
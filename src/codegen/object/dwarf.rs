@@ -0,0 +1,203 @@
+use object::{
+    elf,
+    write::{Object, Relocation, SectionId},
+    RelocationFlags, SectionKind,
+};
+
+// DWARF 4 debug info for an object: a line table saying which line and
+// column of the source each piece of code comes from, and the compilation
+// unit that debuggers look it up from.
+
+// Where the code starting at an offset in `.text` comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Row {
+    pub offset: u64,
+    pub line: u64,
+    pub column: u64,
+}
+
+// Tags, attributes and forms.
+const DW_TAG_COMPILE_UNIT: u8 = 0x11;
+const DW_AT_NAME: u8 = 0x03;
+const DW_AT_STMT_LIST: u8 = 0x10;
+const DW_AT_LOW_PC: u8 = 0x11;
+const DW_AT_HIGH_PC: u8 = 0x12;
+const DW_AT_LANGUAGE: u8 = 0x13;
+const DW_AT_COMP_DIR: u8 = 0x1b;
+const DW_AT_PRODUCER: u8 = 0x25;
+const DW_FORM_ADDR: u8 = 0x01;
+const DW_FORM_DATA2: u8 = 0x05;
+const DW_FORM_DATA8: u8 = 0x07;
+const DW_FORM_STRING: u8 = 0x08;
+const DW_FORM_SEC_OFFSET: u8 = 0x17;
+// mylang has no language code of its own, so it uses the first one left for
+// languages that do not.
+const DW_LANG_LO_USER: u16 = 0x8000;
+
+// Line number opcodes.
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNS_SET_COLUMN: u8 = 0x05;
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+// The number of operands of each standard opcode, from `DW_LNS_copy`.
+const STANDARD_OPCODE_LENGTHS: [u8; 12] = [0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+// Adds `.debug_abbrev`, `.debug_info` and `.debug_line` sections describing
+// `rows` of the `size` bytes of code in `text`, which come from `file`.
+pub(super) fn write(object: &mut Object, text: SectionId, size: u64, file: &str, rows: &[Row]) {
+    let text = object.section_symbol(text);
+    let add = |object: &mut Object, name: &str| {
+        let id = object.add_section(vec![], name.as_bytes().to_vec(), SectionKind::Debug);
+        (id, object.section_symbol(id))
+    };
+    let (abbrev, abbrev_symbol) = add(object, ".debug_abbrev");
+    let (info, _) = add(object, ".debug_info");
+    let (line, line_symbol) = add(object, ".debug_line");
+    let relocate = |object: &mut Object, section, offset, symbol, r_type| {
+        let relocation = Relocation {
+            offset,
+            symbol,
+            addend: 0,
+            flags: RelocationFlags::Elf { r_type },
+        };
+        object
+            .add_relocation(section, relocation)
+            .expect("a valid relocation");
+    };
+
+    let mut abbreviations = vec![1, DW_TAG_COMPILE_UNIT, 0];
+    for (attribute, form) in [
+        (DW_AT_PRODUCER, DW_FORM_STRING),
+        (DW_AT_LANGUAGE, DW_FORM_DATA2),
+        (DW_AT_NAME, DW_FORM_STRING),
+        (DW_AT_COMP_DIR, DW_FORM_STRING),
+        (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET),
+        (DW_AT_LOW_PC, DW_FORM_ADDR),
+        (DW_AT_HIGH_PC, DW_FORM_DATA8),
+    ] {
+        abbreviations.extend([attribute, form]);
+    }
+    abbreviations.extend([0, 0, 0]);
+    object.append_section_data(abbrev, &abbreviations, 1);
+
+    // The unit's length, which does not count itself, is filled in last.
+    let mut unit = vec![0; 4];
+    unit.extend(4u16.to_le_bytes());
+    let abbrev_offset = unit.len() as u64;
+    unit.extend(0u32.to_le_bytes());
+    // Addresses are 8 bytes.
+    unit.push(8);
+    // The unit's only entry, as the first abbreviation describes it.
+    unit.push(1);
+    string(&mut unit, concat!("mylang2 ", env!("CARGO_PKG_VERSION")));
+    unit.extend(DW_LANG_LO_USER.to_le_bytes());
+    string(&mut unit, file);
+    let directory = std::env::current_dir().unwrap_or_default();
+    string(&mut unit, &directory.to_string_lossy());
+    let line_offset = unit.len() as u64;
+    unit.extend(0u32.to_le_bytes());
+    let low_pc = unit.len() as u64;
+    unit.extend(0u64.to_le_bytes());
+    unit.extend(size.to_le_bytes());
+    let length = unit.len() as u32 - 4;
+    unit[..4].copy_from_slice(&length.to_le_bytes());
+    object.append_section_data(info, &unit, 1);
+    relocate(object, info, abbrev_offset, abbrev_symbol, elf::R_X86_64_32);
+    relocate(object, info, line_offset, line_symbol, elf::R_X86_64_32);
+    relocate(object, info, low_pc, text, elf::R_X86_64_64);
+
+    let (table, address) = line_table(size, file, rows);
+    object.append_section_data(line, &table, 1);
+    relocate(object, line, address, text, elf::R_X86_64_64);
+}
+
+// Returns a line table for `rows`, and the offset in it of the address
+// where the code starts, which is left to a relocation.
+fn line_table(size: u64, file: &str, rows: &[Row]) -> (Vec<u8>, u64) {
+    let mut header = vec![
+        // The minimum instruction length and the maximum number of
+        // operations per instruction.
+        1,
+        1,
+        // Rows are statements by default.
+        1,
+        // The line base and range of special opcodes, which are not used.
+        -5i8 as u8,
+        14,
+        STANDARD_OPCODE_LENGTHS.len() as u8 + 1,
+    ];
+    header.extend(STANDARD_OPCODE_LENGTHS);
+    // No include directories, and one file in the compilation directory.
+    header.push(0);
+    string(&mut header, file);
+    header.extend([0, 0, 0, 0]);
+
+    let mut program = vec![0, 9, DW_LNE_SET_ADDRESS];
+    let address = program.len();
+    program.extend(0u64.to_le_bytes());
+    let (mut offset, mut line, mut column) = (0, 1, 0);
+    for row in rows {
+        if row.offset != offset {
+            program.push(DW_LNS_ADVANCE_PC);
+            unsigned(&mut program, row.offset - offset);
+        }
+        if row.line != line {
+            program.push(DW_LNS_ADVANCE_LINE);
+            signed(&mut program, row.line as i64 - line as i64);
+        }
+        if row.column != column {
+            program.push(DW_LNS_SET_COLUMN);
+            unsigned(&mut program, row.column);
+        }
+        program.push(DW_LNS_COPY);
+        (offset, line, column) = (row.offset, row.line, row.column);
+    }
+    if size != offset {
+        program.push(DW_LNS_ADVANCE_PC);
+        unsigned(&mut program, size - offset);
+    }
+    program.extend([0, 1, DW_LNE_END_SEQUENCE]);
+
+    let mut table = vec![0; 4];
+    table.extend(4u16.to_le_bytes());
+    table.extend((header.len() as u32).to_le_bytes());
+    table.extend(header);
+    let address = (table.len() + address) as u64;
+    table.extend(program);
+    let length = table.len() as u32 - 4;
+    table[..4].copy_from_slice(&length.to_le_bytes());
+    (table, address)
+}
+
+fn string(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend(string.as_bytes());
+    bytes.push(0);
+}
+
+// Appends an unsigned LEB128 number.
+fn unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+// Appends a signed LEB128 number.
+fn signed(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
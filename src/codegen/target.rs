@@ -0,0 +1,115 @@
+use std::fmt;
+
+// A machine that backends compile for, which need not be the one the
+// compiler runs on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    // The target triple, as LLVM and the C compiler name it, like
+    // `x86_64-unknown-linux-gnu`.
+    pub triple: String,
+    pub architecture: Architecture,
+    // The width of addresses, including those of functions, in bits.
+    pub pointer_width: u32,
+    pub endianness: Endianness,
+    // The width in bits of the integer type literals default to where
+    // nothing else fixes it, which is that of C's `int`.
+    pub default_int_width: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Architecture {
+    X86_64,
+    Wasm32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Target {
+    pub fn x86_64_linux() -> Target {
+        Target::new("x86_64-unknown-linux-gnu", Architecture::X86_64)
+    }
+
+    pub fn wasm32() -> Target {
+        Target::new("wasm32-unknown-unknown", Architecture::Wasm32)
+    }
+
+    // The machine the compiler runs on, if backends can compile for it.
+    pub fn host() -> Option<Target> {
+        match std::env::consts::ARCH {
+            "x86_64" if cfg!(target_os = "linux") => Some(Target::x86_64_linux()),
+            "x86_64" => {
+                let triple = format!("x86_64-unknown-{}", std::env::consts::OS);
+                Some(Target::new(&triple, Architecture::X86_64))
+            }
+            _ => None,
+        }
+    }
+
+    // Returns the target a triple names, such as `wasm32-unknown-unknown`.
+    // Only the architecture is checked: the rest is kept for the tools that
+    // read it.
+    pub fn from_triple(triple: &str) -> Result<Target, UnknownTarget> {
+        let architecture = match triple.split('-').next() {
+            Some("x86_64") => Architecture::X86_64,
+            Some("wasm32") => Architecture::Wasm32,
+            _ => return Err(UnknownTarget(triple.to_string())),
+        };
+        Ok(Target::new(triple, architecture))
+    }
+
+    fn new(triple: &str, architecture: Architecture) -> Target {
+        let pointer_width = match architecture {
+            Architecture::X86_64 => 64,
+            Architecture::Wasm32 => 32,
+        };
+        Target {
+            triple: triple.to_string(),
+            architecture,
+            pointer_width,
+            endianness: Endianness::Little,
+            default_int_width: 32,
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.triple)
+    }
+}
+
+// A triple naming a machine backends cannot compile for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownTarget(pub String);
+
+impl fmt::Display for UnknownTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown target `{}`", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_are_named_by_triples() {
+        let target = Target::from_triple("x86_64-pc-linux-gnu").unwrap();
+        assert_eq!(target.architecture, Architecture::X86_64);
+        assert_eq!(target.pointer_width, 64);
+        assert_eq!(target.to_string(), "x86_64-pc-linux-gnu");
+        assert_eq!(
+            Target::from_triple("wasm32-unknown-unknown"),
+            Ok(Target::wasm32())
+        );
+        assert_eq!(Target::wasm32().pointer_width, 32);
+        assert_eq!(
+            Target::from_triple("riscv64gc-unknown-linux-gnu"),
+            Err(UnknownTarget("riscv64gc-unknown-linux-gnu".to_string()))
+        );
+    }
+}
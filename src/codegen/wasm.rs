@@ -0,0 +1,1093 @@
+use super::{
+    target::{Architecture, Target},
+    CodegenError,
+};
+use crate::{
+    ast::{BinaryOperator, NodeId, Span, TypeKind},
+    hir::{self, Expression, ExpressionKind, Statement},
+    typecheck::Ty,
+    value::Value,
+};
+use std::collections::{HashMap, HashSet};
+
+// Compiles a lowered program to a WebAssembly module, in the binary format.
+//
+// Every integer type narrower than `int64` is an `i32` holding its value:
+// sign-extended, or 0 or 1 for `int1` and `bool`. `int64` is an `i64`,
+// `float64` an `f64`, and the other float types are `f32`s. Arithmetic means
+// what it does in the interpreter: integers wrap around at their width, and
+// `float16` and `bfloat16` arithmetic is done in `f64` and rounded to the
+// type. Division by zero and negative exponents trap.
+//
+// Top-level functions are exported by name, and ones declared without a
+// body are imported from the `env` module. Top-level `let` bindings are
+// globals, set by a start function that runs the program's top-level
+// statements when the module is instantiated.
+//
+// Strings, builtins, nested functions, functions used as values and float
+// powers are not supported. The program must be free of errors: it panics
+// on `ExpressionKind::Error`.
+pub fn compile(program: &hir::Program, target: &Target) -> Result<Vec<u8>, CodegenError> {
+    if target.architecture != Architecture::Wasm32 {
+        let unsupported = "targets other than wasm32";
+        return Err(CodegenError::new(unsupported, Span::default()));
+    }
+    let mut compiler = Compiler::default();
+    // Imported functions are numbered before the module's own.
+    let top_level: Vec<&hir::Function> = program
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Function(function) => Some(function),
+            _ => None,
+        })
+        .collect();
+    let (imports, defined): (Vec<&hir::Function>, Vec<&hir::Function>) = top_level
+        .into_iter()
+        .partition(|function| function.body.is_none());
+    for function in imports.iter().chain(&defined) {
+        let signature = compiler.signature(function)?;
+        compiler.type_index(wasm_type(&signature));
+        let index = compiler.functions.len() as u32;
+        compiler
+            .functions
+            .insert(function.binding.id, (index, signature));
+    }
+    for statement in &program.statements {
+        if let Statement::Let(let_statement) = statement {
+            let kind = compiler.kind(&let_statement.ty, let_statement.span)?;
+            let index = compiler.globals.len() as u32;
+            compiler
+                .globals
+                .insert(let_statement.binding.id, (index, kind));
+        }
+    }
+    let initializer = program
+        .statements
+        .iter()
+        .any(|statement| !matches!(statement, Statement::Function(_)));
+    compiler.helpers_start = compiler.functions.len() as u32 + initializer as u32;
+
+    let mut bodies = vec![];
+    for function in &defined {
+        let (_, signature) = compiler.functions[&function.binding.id].clone();
+        let type_index = compiler.type_index(wasm_type(&signature));
+        bodies.push((type_index, compiler.function(function, &signature)?));
+    }
+    if initializer {
+        let type_index = compiler.type_index(wasm_type(&(vec![], TypeKind::Unit)));
+        bodies.push((type_index, compiler.initializer(&program.statements)?));
+    }
+    // Helpers are added as they are first used, which may be by another
+    // helper.
+    let mut compiled = 0;
+    while compiled < compiler.helpers.len() {
+        let helper = compiler.helpers[compiled];
+        let type_index = compiler.type_index(helper.signature());
+        bodies.push((type_index, helper.body()));
+        compiled += 1;
+    }
+
+    let mut module = b"\0asm".to_vec();
+    module.extend(1u32.to_le_bytes());
+
+    let mut types = vec![];
+    vector(
+        &mut types,
+        &compiler.types,
+        |bytes, (parameters, results)| {
+            bytes.push(0x60);
+            vector(bytes, parameters, |bytes, ty| bytes.push(*ty));
+            vector(bytes, results, |bytes, ty| bytes.push(*ty));
+        },
+    );
+    section(&mut module, 1, types);
+
+    if !imports.is_empty() {
+        let mut section_bytes = vec![];
+        vector(&mut section_bytes, &imports, |bytes, function| {
+            let (_, signature) = &compiler.functions[&function.binding.id];
+            name(bytes, "env");
+            name(bytes, &function.binding.name);
+            bytes.push(0x00);
+            let ty = wasm_type(signature);
+            let type_index = compiler.types.iter().position(|t| *t == ty).unwrap();
+            unsigned(bytes, type_index as u32);
+        });
+        section(&mut module, 2, section_bytes);
+    }
+
+    let mut functions = vec![];
+    vector(&mut functions, &bodies, |bytes, (type_index, _)| {
+        unsigned(bytes, *type_index)
+    });
+    section(&mut module, 3, functions);
+
+    if !compiler.globals.is_empty() {
+        let mut globals: Vec<&(u32, TypeKind)> = compiler.globals.values().collect();
+        globals.sort_by_key(|(index, _)| *index);
+        let mut section_bytes = vec![];
+        vector(&mut section_bytes, &globals, |bytes, (_, kind)| {
+            let ty = value_type(*kind);
+            bytes.extend([ty, 0x01]);
+            match ty {
+                I32 => bytes.extend([0x41, 0]),
+                I64 => bytes.extend([0x42, 0]),
+                F32 => {
+                    bytes.push(0x43);
+                    bytes.extend(0f32.to_le_bytes());
+                }
+                _ => {
+                    bytes.push(0x44);
+                    bytes.extend(0f64.to_le_bytes());
+                }
+            }
+            bytes.push(END);
+        });
+        section(&mut module, 6, section_bytes);
+    }
+
+    let mut exported = HashSet::new();
+    let exports: Vec<&hir::Function> = imports
+        .iter()
+        .chain(&defined)
+        .copied()
+        .filter(|function| exported.insert(function.binding.name.clone()))
+        .collect();
+    let mut section_bytes = vec![];
+    vector(&mut section_bytes, &exports, |bytes, function| {
+        name(bytes, &function.binding.name);
+        bytes.push(0x00);
+        unsigned(bytes, compiler.functions[&function.binding.id].0);
+    });
+    section(&mut module, 7, section_bytes);
+
+    if initializer {
+        let mut start = vec![];
+        unsigned(&mut start, compiler.helpers_start - 1);
+        section(&mut module, 8, start);
+    }
+
+    let mut code = vec![];
+    vector(&mut code, &bodies, |bytes, (_, body)| {
+        unsigned(bytes, body.len() as u32);
+        bytes.extend(body);
+    });
+    section(&mut module, 10, code);
+    Ok(module)
+}
+
+// Value types.
+const I32: u8 = 0x7f;
+const I64: u8 = 0x7e;
+const F32: u8 = 0x7d;
+const F64: u8 = 0x7c;
+
+// The block type of a block without a value.
+const EMPTY: u8 = 0x40;
+
+// Opcodes.
+const UNREACHABLE: u8 = 0x00;
+const BLOCK: u8 = 0x02;
+const LOOP: u8 = 0x03;
+const IF: u8 = 0x04;
+const ELSE: u8 = 0x05;
+const END: u8 = 0x0b;
+const BR: u8 = 0x0c;
+const BR_IF: u8 = 0x0d;
+const RETURN: u8 = 0x0f;
+const CALL: u8 = 0x10;
+const DROP: u8 = 0x1a;
+const SELECT: u8 = 0x1b;
+const LOCAL_GET: u8 = 0x20;
+const LOCAL_SET: u8 = 0x21;
+const GLOBAL_GET: u8 = 0x23;
+const GLOBAL_SET: u8 = 0x24;
+const I32_CONST: u8 = 0x41;
+const I64_CONST: u8 = 0x42;
+const F32_CONST: u8 = 0x43;
+const F64_CONST: u8 = 0x44;
+const I32_EQZ: u8 = 0x45;
+const I32_EQ: u8 = 0x46;
+const I32_OR: u8 = 0x72;
+const I32_ADD: u8 = 0x6a;
+const I32_SUB: u8 = 0x6b;
+const I32_MUL: u8 = 0x6c;
+const I32_DIV_S: u8 = 0x6d;
+const I32_DIV_U: u8 = 0x6e;
+const I32_AND: u8 = 0x71;
+const I32_SHL: u8 = 0x74;
+const I32_SHR_S: u8 = 0x75;
+const I32_GT_S: u8 = 0x4a;
+const I32_WRAP_I64: u8 = 0xa7;
+const I64_EQ: u8 = 0x51;
+const I64_LT_S: u8 = 0x53;
+const I64_EQZ: u8 = 0x50;
+const I64_ADD: u8 = 0x7c;
+const I64_SUB: u8 = 0x7d;
+const I64_MUL: u8 = 0x7e;
+const I64_DIV_S: u8 = 0x7f;
+const I64_AND: u8 = 0x83;
+const I64_SHL: u8 = 0x86;
+const I64_SHR_U: u8 = 0x88;
+const I64_EXTEND_I32_S: u8 = 0xac;
+const I64_EXTEND_I32_U: u8 = 0xad;
+const F64_NE: u8 = 0x62;
+const F64_EQ: u8 = 0x61;
+const F64_GT: u8 = 0x64;
+const F64_ABS: u8 = 0x99;
+const F64_NEAREST: u8 = 0x9e;
+const F64_SUB: u8 = 0xa1;
+const F64_MUL: u8 = 0xa2;
+const F64_DIV: u8 = 0xa3;
+const F64_MIN: u8 = 0xa4;
+const F64_MAX: u8 = 0xa5;
+const F64_COPYSIGN: u8 = 0xa6;
+const F32_DEMOTE_F64: u8 = 0xb6;
+const F64_CONVERT_I32_S: u8 = 0xb7;
+const F64_CONVERT_I64_S: u8 = 0xb9;
+const F64_PROMOTE_F32: u8 = 0xbb;
+const I64_REINTERPRET_F64: u8 = 0xbd;
+const F64_REINTERPRET_I64: u8 = 0xbf;
+// The prefix of the saturating float-to-integer conversions.
+const SATURATING: u8 = 0xfc;
+const I32_TRUNC_SAT_F64_S: u8 = 2;
+const I64_TRUNC_SAT_F64_S: u8 = 6;
+
+// Returns the value type a supported type is held in.
+fn value_type(kind: TypeKind) -> u8 {
+    match kind {
+        TypeKind::Int64 => I64,
+        TypeKind::Float64 => F64,
+        kind if kind.is_float() => F32,
+        _ => I32,
+    }
+}
+
+// Appends an unsigned LEB128 number.
+fn unsigned(bytes: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+// Appends a signed LEB128 number.
+fn signed(bytes: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn name(bytes: &mut Vec<u8>, name: &str) {
+    unsigned(bytes, name.len() as u32);
+    bytes.extend(name.as_bytes());
+}
+
+fn vector<T>(bytes: &mut Vec<u8>, items: &[T], mut item: impl FnMut(&mut Vec<u8>, &T)) {
+    unsigned(bytes, items.len() as u32);
+    for i in items {
+        item(bytes, i);
+    }
+}
+
+fn section(module: &mut Vec<u8>, id: u8, contents: Vec<u8>) {
+    module.push(id);
+    unsigned(module, contents.len() as u32);
+    module.extend(contents);
+}
+
+// The parameter types and return type of a function.
+type Signature = (Vec<TypeKind>, TypeKind);
+
+// Functions the compiled code calls that are not part of the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Helper {
+    // Computes an `i64` to the power of an `i64`, wrapping around, and traps
+    // on a negative exponent.
+    Power,
+    // Rounds an `f64` to a narrower float format, given its smallest
+    // exponent, its number of significand bits after the point and its
+    // largest finite value, as `value::round_float` does.
+    Round,
+}
+
+impl Helper {
+    fn signature(&self) -> (Vec<u8>, Vec<u8>) {
+        match self {
+            Helper::Power => (vec![I64, I64], vec![I64]),
+            Helper::Round => (vec![F64, I32, I32, F64], vec![F64]),
+        }
+    }
+
+    fn body(&self) -> Vec<u8> {
+        let mut body = vec![];
+        match self {
+            Helper::Power => {
+                // Parameters: 0 base, 1 exponent. Local: 2 result.
+                body.extend([1, 1, I64]);
+                body.extend([
+                    LOCAL_GET,
+                    1,
+                    I64_CONST,
+                    0,
+                    I64_LT_S,
+                    IF,
+                    EMPTY,
+                    UNREACHABLE,
+                    END,
+                ]);
+                body.extend([I64_CONST, 1, LOCAL_SET, 2]);
+                body.extend([BLOCK, EMPTY, LOOP, EMPTY]);
+                body.extend([LOCAL_GET, 1, I64_EQZ, BR_IF, 1]);
+                // result = exponent & 1 ? result * base : result
+                body.extend([LOCAL_GET, 2, LOCAL_GET, 0, I64_MUL, LOCAL_GET, 2]);
+                body.extend([LOCAL_GET, 1, I64_CONST, 1, I64_AND, I32_WRAP_I64, SELECT]);
+                body.extend([LOCAL_SET, 2]);
+                body.extend([LOCAL_GET, 0, LOCAL_GET, 0, I64_MUL, LOCAL_SET, 0]);
+                body.extend([LOCAL_GET, 1, I64_CONST, 1, I64_SHR_U, LOCAL_SET, 1]);
+                body.extend([BR, 0, END, END, LOCAL_GET, 2]);
+            }
+            Helper::Round => {
+                // Parameters: 0 value, 1 smallest exponent, 2 bits after the
+                // point, 3 largest value. Locals: 4 quantum, 5 rounded,
+                // 6 exponent.
+                body.extend([2, 2, F64, 1, I32]);
+                // Zero, infinities and NaN are left as they are.
+                body.extend([LOCAL_GET, 0, F64_CONST]);
+                body.extend(0f64.to_le_bytes());
+                body.extend([F64_EQ, LOCAL_GET, 0, LOCAL_GET, 0, F64_SUB, F64_CONST]);
+                body.extend(0f64.to_le_bytes());
+                body.extend([F64_NE, I32_OR, IF, EMPTY, LOCAL_GET, 0, RETURN, END]);
+                // The value's exponent, at least the smallest, less the
+                // number of bits after the point, is the place value of the
+                // last significand bit: 2 to that is built from its bits.
+                body.extend([LOCAL_GET, 0, I64_REINTERPRET_F64, I64_CONST, 52, I64_SHR_U]);
+                body.extend([I32_WRAP_I64, I32_CONST]);
+                signed(&mut body, 0x7ff);
+                body.extend([I32_AND, I32_CONST]);
+                signed(&mut body, 1023);
+                body.extend([I32_SUB, LOCAL_SET, 6]);
+                body.extend([
+                    LOCAL_GET, 6, LOCAL_GET, 1, LOCAL_GET, 6, LOCAL_GET, 1, I32_GT_S,
+                ]);
+                body.extend([SELECT, LOCAL_GET, 2, I32_SUB, I32_CONST]);
+                signed(&mut body, 1023);
+                body.extend([I32_ADD, I64_EXTEND_I32_U, I64_CONST, 52, I64_SHL]);
+                body.extend([F64_REINTERPRET_I64, LOCAL_SET, 4]);
+                body.extend([LOCAL_GET, 0, LOCAL_GET, 4, F64_DIV, F64_NEAREST]);
+                body.extend([LOCAL_GET, 4, F64_MUL, LOCAL_SET, 5]);
+                body.extend([LOCAL_GET, 5, F64_ABS, LOCAL_GET, 3, F64_GT]);
+                body.extend([IF, F64, F64_CONST]);
+                body.extend(f64::INFINITY.to_le_bytes());
+                body.extend([LOCAL_GET, 0, F64_COPYSIGN, ELSE, LOCAL_GET, 5, END]);
+            }
+        }
+        body.push(END);
+        body
+    }
+}
+
+#[derive(Default)]
+struct Compiler {
+    // The module's function types, as their parameter and result types.
+    types: Vec<(Vec<u8>, Vec<u8>)>,
+    // The top-level functions, with their indices.
+    functions: HashMap<NodeId, (u32, Signature)>,
+    globals: HashMap<NodeId, (u32, TypeKind)>,
+    // The helpers used, numbered from `helpers_start` in this order.
+    helpers: Vec<Helper>,
+    helpers_start: u32,
+    body: Body,
+}
+
+// The function being compiled.
+#[derive(Default)]
+struct Body {
+    code: Vec<u8>,
+    parameters: u32,
+    // The types of the locals that are not parameters.
+    locals: Vec<u8>,
+    // The local holding each parameter and `let` binding.
+    bindings: HashMap<NodeId, u32>,
+}
+
+impl Compiler {
+    // Returns the type of a value the backend can compile.
+    fn kind(&self, ty: &Ty, span: Span) -> Result<TypeKind, CodegenError> {
+        match ty {
+            Ty::Primitive(TypeKind::String) => Err(CodegenError::new("strings", span)),
+            Ty::Primitive(kind) => Ok(*kind),
+            Ty::Function(..) => Err(CodegenError::new("function values", span)),
+            Ty::Error => panic!("cannot compile a program with errors"),
+            _ => unreachable!("values have primitive or function types"),
+        }
+    }
+
+    fn signature(&self, function: &hir::Function) -> Result<Signature, CodegenError> {
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|parameter| self.kind(&parameter.ty, function.span))
+            .collect::<Result<_, _>>()?;
+        Ok((parameters, self.kind(&function.return_type, function.span)?))
+    }
+
+    // Returns the index of a function type, adding it if it is new.
+    fn type_index(&mut self, ty: (Vec<u8>, Vec<u8>)) -> u32 {
+        match self.types.iter().position(|t| *t == ty) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    // Returns the index of a helper, adding it if it is new.
+    fn helper(&mut self, helper: Helper) -> u32 {
+        let position = match self.helpers.iter().position(|h| *h == helper) {
+            Some(position) => position,
+            None => {
+                self.helpers.push(helper);
+                self.helpers.len() - 1
+            }
+        };
+        self.helpers_start + position as u32
+    }
+
+    fn function(
+        &mut self,
+        function: &hir::Function,
+        signature: &Signature,
+    ) -> Result<Vec<u8>, CodegenError> {
+        self.body = Body::default();
+        for parameter in &function.parameters {
+            self.body
+                .bindings
+                .insert(parameter.binding.id, self.body.parameters);
+            self.body.parameters += 1;
+        }
+        self.statements(&function.body.as_ref().unwrap().statements)?;
+        if signature.1 != TypeKind::Unit {
+            // Type checking makes sure the end cannot be reached.
+            self.body.code.push(UNREACHABLE);
+        }
+        Ok(self.finish())
+    }
+
+    // Compiles the top-level statements into the start function, which sets
+    // the globals.
+    fn initializer(&mut self, statements: &[Statement]) -> Result<Vec<u8>, CodegenError> {
+        self.body = Body::default();
+        for statement in statements {
+            match statement {
+                Statement::Function(_) => {}
+                Statement::Let(let_statement) => {
+                    self.expression(&let_statement.value)?;
+                    let (index, _) = self.globals[&let_statement.binding.id];
+                    self.body.code.push(GLOBAL_SET);
+                    unsigned(&mut self.body.code, index);
+                }
+                statement => self.statement(statement)?,
+            }
+        }
+        Ok(self.finish())
+    }
+
+    // Returns the function being compiled, with its locals declared.
+    fn finish(&mut self) -> Vec<u8> {
+        let body = std::mem::take(&mut self.body);
+        let mut bytes = vec![];
+        unsigned(&mut bytes, body.locals.len() as u32);
+        for ty in &body.locals {
+            bytes.extend([1, *ty]);
+        }
+        bytes.extend(body.code);
+        bytes.push(END);
+        bytes
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.body.code.extend(bytes);
+    }
+
+    // Adds a local of a value type, returning its index.
+    fn local(&mut self, ty: u8) -> u32 {
+        self.body.locals.push(ty);
+        self.body.parameters + self.body.locals.len() as u32 - 1
+    }
+
+    fn local_instruction(&mut self, opcode: u8, index: u32) {
+        self.body.code.push(opcode);
+        unsigned(&mut self.body.code, index);
+    }
+
+    fn call(&mut self, index: u32) {
+        self.body.code.push(CALL);
+        unsigned(&mut self.body.code, index);
+    }
+
+    fn i32_const(&mut self, value: i32) {
+        self.body.code.push(I32_CONST);
+        signed(&mut self.body.code, value.into());
+    }
+
+    fn f64_const(&mut self, value: f64) {
+        self.body.code.push(F64_CONST);
+        self.body.code.extend(value.to_le_bytes());
+    }
+
+    fn statements(&mut self, statements: &[Statement]) -> Result<(), CodegenError> {
+        for statement in statements {
+            self.statement(statement)?;
+            if matches!(statement, Statement::Return(_)) {
+                // Nothing after a `return` can run.
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), CodegenError> {
+        match statement {
+            Statement::Let(let_statement) => {
+                let kind = self.kind(&let_statement.ty, let_statement.span)?;
+                self.expression(&let_statement.value)?;
+                if kind != TypeKind::Unit {
+                    let local = self.local(value_type(kind));
+                    self.body.bindings.insert(let_statement.binding.id, local);
+                    self.local_instruction(LOCAL_SET, local);
+                }
+            }
+            Statement::Function(function) => {
+                return Err(CodegenError::new("nested functions", function.span));
+            }
+            Statement::Expression(expression) => {
+                let kind = self.kind(&expression.ty, expression.span)?;
+                self.expression(expression)?;
+                if kind != TypeKind::Unit {
+                    self.emit(&[DROP]);
+                }
+            }
+            Statement::Return(return_statement) => {
+                if let Some(value) = &return_statement.value {
+                    self.expression(value)?;
+                }
+                self.emit(&[RETURN]);
+            }
+            Statement::If(if_statement) => {
+                self.expression(&if_statement.condition)?;
+                self.emit(&[IF, EMPTY]);
+                self.statements(&if_statement.then_block.statements)?;
+                if let Some(else_block) = &if_statement.else_block {
+                    self.emit(&[ELSE]);
+                    self.statements(&else_block.statements)?;
+                }
+                self.emit(&[END]);
+            }
+            Statement::While(while_statement) => {
+                self.emit(&[BLOCK, EMPTY, LOOP, EMPTY]);
+                self.expression(&while_statement.condition)?;
+                self.emit(&[I32_EQZ, BR_IF, 1]);
+                self.statements(&while_statement.body.statements)?;
+                self.emit(&[BR, 0, END, END]);
+            }
+            Statement::Block(block) => self.statements(&block.statements)?,
+        }
+        Ok(())
+    }
+
+    // Compiles an expression, leaving its value on the stack unless it is
+    // unit.
+    fn expression(&mut self, expression: &Expression) -> Result<(), CodegenError> {
+        let span = expression.span;
+        match &expression.kind {
+            ExpressionKind::Integer(value) => match self.kind(&expression.ty, span)? {
+                TypeKind::Float64 => self.f64_const(*value as f64),
+                kind if kind.is_float() => {
+                    self.body.code.push(F32_CONST);
+                    self.body.code.extend((*value as f32).to_le_bytes());
+                }
+                kind => {
+                    let Value::Integer(_, value) = Value::integer(kind, *value) else {
+                        unreachable!()
+                    };
+                    let opcode = match kind {
+                        TypeKind::Int64 => I64_CONST,
+                        _ => I32_CONST,
+                    };
+                    self.body.code.push(opcode);
+                    signed(&mut self.body.code, value);
+                }
+            },
+            ExpressionKind::Bool(value) => self.i32_const(*value as i32),
+            ExpressionKind::Name(binding) => {
+                if let Some(local) = self.body.bindings.get(&binding.id) {
+                    self.local_instruction(LOCAL_GET, *local);
+                } else if let Some((global, _)) = self.globals.get(&binding.id) {
+                    self.local_instruction(GLOBAL_GET, *global);
+                } else if self.functions.contains_key(&binding.id) {
+                    return Err(CodegenError::new("function values", span));
+                } else {
+                    return Err(CodegenError::new("nested functions", span));
+                }
+            }
+            ExpressionKind::Binary(operator, left, right) => {
+                self.binary(operator, left, right, span)?
+            }
+            ExpressionKind::Call(callee, arguments) => {
+                let function = match &callee.kind {
+                    ExpressionKind::Name(binding) => self.functions.get(&binding.id),
+                    _ => None,
+                };
+                let Some((index, _)) = function else {
+                    return Err(CodegenError::new("calls of function values", span));
+                };
+                let index = *index;
+                for argument in arguments {
+                    self.expression(argument)?;
+                }
+                self.call(index);
+            }
+            ExpressionKind::Builtin(..) => return Err(CodegenError::new("builtins", span)),
+            ExpressionKind::Cast(operand) => {
+                let from = self.kind(&operand.ty, operand.span)?;
+                let to = self.kind(&expression.ty, span)?;
+                self.expression(operand)?;
+                self.cast(from, to);
+            }
+            ExpressionKind::Error => panic!("cannot compile a program with errors"),
+        }
+        Ok(())
+    }
+
+    fn binary(
+        &mut self,
+        operator: &BinaryOperator,
+        left: &Expression,
+        right: &Expression,
+        span: Span,
+    ) -> Result<(), CodegenError> {
+        let kind = self.kind(&left.ty, left.span)?;
+        let ty = value_type(kind);
+        // `float16` and `bfloat16` arithmetic is done in `f64`.
+        let widen =
+            matches!(kind, TypeKind::Float16 | TypeKind::BFloat16) && !operator.is_comparison();
+        self.expression(left)?;
+        if widen {
+            self.emit(&[F64_PROMOTE_F32]);
+        }
+        self.expression(right)?;
+        if widen {
+            self.emit(&[F64_PROMOTE_F32]);
+        }
+        if operator.is_comparison() {
+            // Opcodes for i32, i64, f32 and f64.
+            let opcodes = match operator {
+                BinaryOperator::Equal => [0x46, 0x51, 0x5b, 0x61],
+                BinaryOperator::NotEqual => [0x47, 0x52, 0x5c, 0x62],
+                BinaryOperator::Less => [0x48, 0x53, 0x5d, 0x63],
+                BinaryOperator::Greater => [0x4a, 0x55, 0x5e, 0x64],
+                BinaryOperator::LessEqual => [0x4c, 0x57, 0x5f, 0x65],
+                _ => [0x4e, 0x59, 0x60, 0x66],
+            };
+            let opcode = match ty {
+                I32 => opcodes[0],
+                I64 => opcodes[1],
+                F32 => opcodes[2],
+                _ => opcodes[3],
+            };
+            self.emit(&[opcode]);
+            return Ok(());
+        }
+
+        if kind.is_float() {
+            // Opcodes for f32 and f64.
+            let opcodes = match operator {
+                BinaryOperator::Plus => [0x92, 0xa0],
+                BinaryOperator::Minus => [0x93, 0xa1],
+                BinaryOperator::Star => [0x94, 0xa2],
+                BinaryOperator::Divide => [0x95, 0xa3],
+                _ => return Err(CodegenError::new("float powers", span)),
+            };
+            if widen {
+                self.emit(&[opcodes[1]]);
+                self.round(kind);
+                self.emit(&[F32_DEMOTE_F64]);
+            } else {
+                // Rounding an `f32` result computed in `f64` gives the
+                // result computed in `f32`.
+                self.emit(&[if ty == F32 { opcodes[0] } else { opcodes[1] }]);
+            }
+            return Ok(());
+        }
+
+        let wide = kind == TypeKind::Int64;
+        match operator {
+            BinaryOperator::Plus => self.emit(&[if wide { I64_ADD } else { I32_ADD }]),
+            BinaryOperator::Minus => self.emit(&[if wide { I64_SUB } else { I32_SUB }]),
+            BinaryOperator::Star => self.emit(&[if wide { I64_MUL } else { I32_MUL }]),
+            BinaryOperator::Divide => {
+                if !signed_kind(kind) {
+                    self.emit(&[I32_DIV_U]);
+                } else if matches!(kind, TypeKind::Int32 | TypeKind::Int64) {
+                    // Dividing the most negative value by -1 traps; it wraps
+                    // around to itself. Division by zero traps as it should.
+                    let divisor = self.local(ty);
+                    let dividend = self.local(ty);
+                    self.local_instruction(LOCAL_SET, divisor);
+                    self.local_instruction(LOCAL_SET, dividend);
+                    self.local_instruction(LOCAL_GET, divisor);
+                    if wide {
+                        self.emit(&[I64_CONST, 0x7f, I64_EQ, IF, ty, I64_CONST, 0]);
+                    } else {
+                        self.emit(&[I32_CONST, 0x7f, I32_EQ, IF, ty, I32_CONST, 0]);
+                    }
+                    self.local_instruction(LOCAL_GET, dividend);
+                    self.emit(&[if wide { I64_SUB } else { I32_SUB }, ELSE]);
+                    self.local_instruction(LOCAL_GET, dividend);
+                    self.local_instruction(LOCAL_GET, divisor);
+                    self.emit(&[if wide { I64_DIV_S } else { I32_DIV_S }, END]);
+                } else {
+                    self.emit(&[I32_DIV_S]);
+                }
+            }
+            _ => {
+                if wide {
+                    let power = self.helper(Helper::Power);
+                    self.call(power);
+                } else {
+                    let exponent = self.local(I32);
+                    self.local_instruction(LOCAL_SET, exponent);
+                    self.emit(&[I64_EXTEND_I32_S]);
+                    self.local_instruction(LOCAL_GET, exponent);
+                    self.emit(&[I64_EXTEND_I32_S]);
+                    let power = self.helper(Helper::Power);
+                    self.call(power);
+                    self.emit(&[I32_WRAP_I64]);
+                }
+            }
+        }
+        self.wrap(kind);
+        Ok(())
+    }
+
+    // Reduces an `i32` to the range of a narrower integer type.
+    fn wrap(&mut self, kind: TypeKind) {
+        match kind.integer_bits() {
+            Some(1) => {
+                self.i32_const(1);
+                self.emit(&[I32_AND]);
+            }
+            Some(bits) if bits < 32 => {
+                let shift = 32 - bits as i32;
+                self.i32_const(shift);
+                self.emit(&[I32_SHL]);
+                self.i32_const(shift);
+                self.emit(&[I32_SHR_S]);
+            }
+            _ => {}
+        }
+    }
+
+    // Rounds the `f64` on the stack to a narrower float type.
+    fn round(&mut self, kind: TypeKind) {
+        let (exponent, precision) = kind.float_format().unwrap();
+        let max_exponent = (1 << (exponent - 1)) - 1;
+        let largest = (2.0 - 2f64.powi(1 - precision as i32)) * 2f64.powi(max_exponent);
+        self.i32_const(1 - max_exponent);
+        self.i32_const(precision as i32 - 1);
+        self.f64_const(largest);
+        let round = self.helper(Helper::Round);
+        self.call(round);
+    }
+
+    // Converts the value on the stack as an `as` cast does.
+    fn cast(&mut self, from: TypeKind, to: TypeKind) {
+        if from == to {
+            return;
+        }
+        if from.is_float() || to.is_float() {
+            // Convert through `f64`, as the interpreter does.
+            match value_type(from) {
+                F32 => self.emit(&[F64_PROMOTE_F32]),
+                F64 => {}
+                I64 => self.emit(&[F64_CONVERT_I64_S]),
+                _ if from.is_float() => unreachable!(),
+                _ => self.emit(&[F64_CONVERT_I32_S]),
+            }
+            match to {
+                TypeKind::Float64 => {}
+                TypeKind::Float32 => self.emit(&[F32_DEMOTE_F64]),
+                TypeKind::Float16 | TypeKind::BFloat16 => {
+                    self.round(to);
+                    self.emit(&[F32_DEMOTE_F64]);
+                }
+                TypeKind::Int64 => self.emit(&[SATURATING, I64_TRUNC_SAT_F64_S]),
+                _ => {
+                    // Clamp to the range first; NaN stays NaN and becomes
+                    // zero.
+                    let (min, max) = to.integer_range().unwrap();
+                    self.f64_const(min as f64);
+                    self.emit(&[F64_MAX]);
+                    self.f64_const(max as f64);
+                    self.emit(&[F64_MIN, SATURATING, I32_TRUNC_SAT_F64_S]);
+                }
+            }
+            return;
+        }
+        match (from, to) {
+            (TypeKind::Int64, _) => {
+                self.emit(&[I32_WRAP_I64]);
+                self.wrap(to);
+            }
+            // Narrower values are already sign-extended, and `int1` and
+            // `bool` are 0 or 1.
+            (_, TypeKind::Int64) if signed_kind(from) => self.emit(&[I64_EXTEND_I32_S]),
+            (_, TypeKind::Int64) => self.emit(&[I64_EXTEND_I32_U]),
+            _ => {
+                if to.integer_bits().unwrap() < from.integer_bits().unwrap_or(1) {
+                    self.wrap(to);
+                }
+            }
+        }
+    }
+}
+
+fn signed_kind(kind: TypeKind) -> bool {
+    !matches!(kind, TypeKind::Int1 | TypeKind::Bool)
+}
+
+// Returns the parameter and result value types of a function.
+fn wasm_type((parameters, return_type): &Signature) -> (Vec<u8>, Vec<u8>) {
+    let parameters = parameters.iter().map(|kind| value_type(*kind)).collect();
+    let results = match *return_type {
+        TypeKind::Unit => vec![],
+        kind => vec![value_type(kind)],
+    };
+    (parameters, results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codegen::lower, interpreter::Interpreter};
+    use std::{
+        io,
+        process::Command,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    // Validates a module with node, instantiates it and calls an export,
+    // returning what it printed or the error. Returns `None` when node is
+    // not installed.
+    fn run(module: &[u8], function: &str) -> Option<Result<String, String>> {
+        const SCRIPT: &str = "
+const [path, name] = process.argv.slice(1);
+const bytes = require('fs').readFileSync(path);
+if (!WebAssembly.validate(bytes)) throw new Error('invalid module');
+WebAssembly.instantiate(bytes, { env: {} }).then(
+    ({ instance }) => console.log(String(instance.exports[name]())),
+    error => { console.error(String(error)); process.exit(1); });
+";
+        // Tests run in parallel, so each module gets a file of its own.
+        static MODULES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "mylang-{}-{}.wasm",
+            std::process::id(),
+            MODULES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, module).unwrap();
+        let output = Command::new("node")
+            .args(["-e", SCRIPT])
+            .arg(&path)
+            .arg(function)
+            .output();
+        std::fs::remove_file(&path).unwrap();
+        let output = match output {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return None,
+            output => output.unwrap(),
+        };
+        let text = |bytes: Vec<u8>| String::from_utf8(bytes).unwrap().trim().to_string();
+        Some(match output.status.success() {
+            true => Ok(text(output.stdout)),
+            false => Err(text(output.stderr)),
+        })
+    }
+
+    #[test]
+    fn modules_are_encoded_in_the_binary_format() {
+        let module = compile(
+            &lower("fn main() -> int32 { return 1; }"),
+            &Target::wasm32(),
+        )
+        .unwrap();
+        assert_eq!(
+            module,
+            [
+                0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+                0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // type: () -> i32
+                0x03, 0x02, 0x01, 0x00, // function 0 has type 0
+                0x07, 0x08, 0x01, 0x04, b'm', b'a', b'i', b'n', 0x00, 0x00, // export
+                0x0a, 0x08, 0x01, 0x06, 0x00, 0x41, 0x01, 0x0f, 0x00, 0x0b, // code
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_features_are_reported() {
+        for (source, unsupported, at) in [
+            (
+                "fn f() -> string { return read_line(); }",
+                "strings",
+                "fn f",
+            ),
+            ("fn f(x: int64) { println(x); }", "builtins", "println(x)"),
+            (
+                "fn f() { fn g() { } g(); }",
+                "nested functions",
+                "fn g() { }",
+            ),
+            (
+                "fn f(x: float32) -> float32 { return x ** x; }",
+                "float powers",
+                "x ** x",
+            ),
+        ] {
+            let error = compile(&lower(source), &Target::wasm32()).unwrap_err();
+            assert_eq!(error.unsupported, unsupported, "{}", source);
+            assert!(source[error.span.range()].starts_with(at), "{}", source);
+        }
+        let error = compile(&lower("fn f() { }"), &Target::x86_64_linux()).unwrap_err();
+        assert_eq!(error.unsupported, "targets other than wasm32");
+    }
+
+    #[test]
+    fn compiled_programs_agree_with_the_interpreter() {
+        let corpus = [
+            "fn main() -> int64 { return 1 + 2 * 3 ** 2 - 4 / 3; }",
+            "\
+fn fib(n: int32) -> int32 {
+    if n < 2 { return n; } else { return fib(n - 1) + fib(n - 2); }
+}
+fn main() -> int32 { return fib(15); }",
+            "\
+fn main() -> int8 {
+    let a: int8 = 120;
+    let b: int8 = a + 10;
+    let c: int16 = 1000;
+    let d: int8 = 0 - 127 - 1;
+    let minus: int8 = 0 - 1;
+    return b * 3 + c as int8 + d / minus + minus ** 3;
+}",
+            "\
+fn main() -> int4 {
+    let x: int4 = 7;
+    let y: int2 = 1;
+    let z: int1 = 1;
+    return x + (y + 1) as int4 + (z + z) as int4 + (z > 0) as int4;
+}",
+            "\
+fn main() -> float32 {
+    let h: float16 = 2049;
+    let b: bfloat16 = 257;
+    let x: float32 = 1;
+    return h / 3 + b * 3 + (x / 10) as float16 + (x / 3) as bfloat16;
+}",
+            "\
+fn main() -> float64 {
+    let x: float32 = 1;
+    let tenth: float32 = x / 10;
+    let big: float64 = 65520;
+    return (tenth + tenth + tenth) as float64 + big as float16 as float64;
+}",
+            "\
+fn main() -> bool {
+    let x: float64 = 0;
+    let nan: float64 = x / x;
+    return nan != nan;
+}",
+            "\
+fn main() -> int16 {
+    let x: float64 = 1;
+    let big: float64 = x * 100000;
+    let zero: float64 = 0;
+    let half: float16 = 1;
+    return big as int16 + (zero / zero) as int16 + (x - 3) as int2 + (half / 3) as int16;
+}",
+            "\
+let base: int64 = 1000;
+fn add(x: int64) -> int64 { return x + base; }
+fn main() -> int64 { return add(add(1)); }",
+            "\
+fn main() -> int64 {
+    let n: int64 = 3;
+    while n > 0 {
+        if n == 3 { return 30; }
+    }
+    return 0;
+}",
+            "\
+fn side(x: int64) { }
+fn main() -> int64 {
+    let big: int64 = 0 - 9223372036854775807 - 1;
+    side(big);
+    let minus: int64 = 0 - 1;
+    let seven: int64 = 7;
+    { let inner: int64 = big / minus; return inner + seven ** 30 + big as int32 as int64; }
+}",
+            "fn main() { }",
+        ];
+        for source in corpus {
+            let hir = lower(source);
+            let mut interpreter = Interpreter::new(&hir);
+            interpreter.run().unwrap();
+            let expected = interpreter.call("main", vec![]).unwrap();
+            let Some(output) = run(&compile(&hir, &Target::wasm32()).unwrap(), "main") else {
+                return;
+            };
+            let output = output.unwrap();
+            let result = match &expected {
+                None => (output == "undefined").then_some(None),
+                Some(Value::Integer(kind, _)) => {
+                    Some(Some(Value::integer(*kind, output.parse().unwrap())))
+                }
+                Some(Value::Float(kind, _)) => {
+                    Some(Some(Value::float(*kind, output.parse().unwrap())))
+                }
+                Some(Value::Bool(_)) => Some(Some(Value::Bool(output == "1"))),
+                Some(_) => None,
+            };
+            assert_eq!(result, Some(expected), "{}", source);
+        }
+    }
+
+    #[test]
+    fn runtime_errors_trap() {
+        for source in [
+            "fn main() -> int32 { let zero: int32 = 0; return 1 / zero; }",
+            "fn main() -> int8 { let minus: int8 = 0 - 1; return 2 ** minus; }",
+            "let zero: int64 = 0; let x: int64 = 1 / zero; fn main() { }",
+        ] {
+            let Some(result) = run(&compile(&lower(source), &Target::wasm32()).unwrap(), "main")
+            else {
+                return;
+            };
+            let error = result.unwrap_err();
+            assert!(error.contains("RuntimeError"), "{}: {}", source, error);
+        }
+    }
+}
@@ -0,0 +1,911 @@
+use super::{
+    target::{Architecture, Target},
+    CodegenError,
+};
+use crate::{
+    ast::{BinaryOperator, NodeId, Span, TypeKind},
+    hir::{self, Expression, ExpressionKind, Statement},
+    source_map::SourceMap,
+    typecheck::Ty,
+    value::Value,
+};
+use std::collections::{HashMap, HashSet};
+
+mod regalloc;
+
+// Compiles a lowered program to x86-64 assembly for the System V ABI, in the
+// AT&T syntax of the GNU assembler, like `cc -S` prints. It is meant for
+// reading more than for speed: every expression is computed into `%rax`
+// and operands wait on the stack. Bindings are kept in the callee-saved
+// registers, as `regalloc` assigns them, and those that do not fit have a
+// slot in their function's frame.
+//
+// Integers, bools, functions and control flow are supported. Values are
+// kept sign-extended to 64 bits, or 0 or 1 for `int1` and `bool`, and
+// arithmetic wraps around at the width of the type. A division by zero or a
+// negative exponent executes `ud2`. Floats, strings, builtins, nested
+// functions and calls with more than six arguments are not supported.
+//
+// Top-level functions are global symbols, callable from C, and ones
+// declared without a body are external. Top-level `let` bindings are
+// globals, set by a function in `.init_array` that runs the program's
+// top-level statements before `main`.
+//
+// The target must be an x86-64 one. The program must be free of errors: it
+// panics on `ExpressionKind::Error`.
+pub fn compile(program: &hir::Program, target: &Target) -> Result<String, CodegenError> {
+    check_target(target)?;
+    Compiler::default().program(program)
+}
+
+// Compiles a program like `compile`, with `.loc` directives telling the
+// assembler which line and column of `source` each statement's code comes
+// from, which it turns into DWARF line tables for debuggers.
+pub fn compile_with_debug_info(
+    program: &hir::Program,
+    target: &Target,
+    source: &SourceMap,
+) -> Result<String, CodegenError> {
+    check_target(target)?;
+    let compiler = Compiler {
+        source: Some(source),
+        ..Compiler::default()
+    };
+    compiler.program(program)
+}
+
+fn check_target(target: &Target) -> Result<(), CodegenError> {
+    match target.architecture {
+        Architecture::X86_64 => Ok(()),
+        _ => Err(CodegenError::new(
+            "targets other than x86-64",
+            Span::default(),
+        )),
+    }
+}
+
+// The registers that pass the first six arguments.
+const ARGUMENTS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+// The registers bindings are allocated to. Calls keep them, so bindings
+// survive calls, but a function must restore those it uses before it
+// returns.
+const REGISTERS: [&str; 5] = ["%rbx", "%r12", "%r13", "%r14", "%r15"];
+
+#[derive(Default)]
+struct Compiler<'s> {
+    // The source to say the code comes from, if debug info is wanted.
+    source: Option<&'s SourceMap>,
+    // The symbols of globals and functions already taken.
+    names: HashSet<String>,
+    functions: HashMap<NodeId, String>,
+    globals: HashMap<NodeId, String>,
+    // The number of labels used so far, which are numbered across the
+    // whole file.
+    labels: u32,
+    body: Body,
+}
+
+// The function being compiled.
+#[derive(Default)]
+struct Body {
+    code: String,
+    // The register of each parameter and `let` binding that has one.
+    registers: HashMap<NodeId, &'static str>,
+    // The registers the function uses and must restore, which are saved in
+    // the first slots of its frame.
+    saved: Vec<&'static str>,
+    // The frame offset of each spilled binding's slot.
+    slots: HashMap<NodeId, i32>,
+    // The number of values on the stack below the frame's slots, which
+    // decides whether a call needs padding to align the stack.
+    pushed: u32,
+}
+
+impl Compiler<'_> {
+    fn program(mut self, program: &hir::Program) -> Result<String, CodegenError> {
+        // Top-level functions can be used before their declaration, so their
+        // names are known first.
+        for statement in &program.statements {
+            match statement {
+                Statement::Function(function) => {
+                    for parameter in &function.parameters {
+                        self.kind(&parameter.ty, function.span)?;
+                    }
+                    self.kind(&function.return_type, function.span)?;
+                    if function.parameters.len() > ARGUMENTS.len() {
+                        let unsupported = "functions with more than six parameters";
+                        return Err(CodegenError::new(unsupported, function.span));
+                    }
+                    let symbol = self.unique(&function.binding.name);
+                    self.functions.insert(function.binding.id, symbol);
+                }
+                Statement::Let(let_statement) => {
+                    self.kind(&let_statement.ty, let_statement.span)?;
+                    let symbol = self.unique(&let_statement.binding.name);
+                    self.globals.insert(let_statement.binding.id, symbol);
+                }
+                _ => {}
+            }
+        }
+
+        let mut text = String::from("\t.text\n");
+        if let Some(source) = self.source {
+            let name = source.name().replace('\\', "\\\\").replace('"', "\\\"");
+            text += &format!("\t.file\t1 \"{}\"\n", name);
+        }
+        for statement in &program.statements {
+            if let Statement::Function(function) = statement {
+                if function.body.is_some() {
+                    text += &self.function(function)?;
+                }
+            }
+        }
+        if program
+            .statements
+            .iter()
+            .any(|statement| !matches!(statement, Statement::Function(_)))
+        {
+            text += &self.initializer(&program.statements)?;
+            text += "\n\t.section\t.init_array,\"aw\"\n\t.align\t8\n\t.quad\tmylang.init\n";
+        }
+        let mut globals = false;
+        for statement in &program.statements {
+            if let Statement::Let(let_statement) = statement {
+                if !globals {
+                    text += "\n\t.bss\n\t.align\t8\n";
+                    globals = true;
+                }
+                text += &format!("{}:\n\t.zero\t8\n", self.globals[&let_statement.binding.id]);
+            }
+        }
+        text += "\n\t.section\t.note.GNU-stack,\"\",@progbits\n";
+        Ok(text)
+    }
+
+    // Returns a symbol for a global or function that no other has.
+    fn unique(&mut self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 0;
+        while self.names.contains(&unique) {
+            n += 1;
+            unique = format!("{}.{}", name, n);
+        }
+        self.names.insert(unique.clone());
+        unique
+    }
+
+    // Returns the type of a value the backend can compile, or unit for a
+    // function.
+    fn kind(&self, ty: &Ty, span: Span) -> Result<TypeKind, CodegenError> {
+        match ty {
+            Ty::Primitive(TypeKind::String) => Err(CodegenError::new("strings", span)),
+            Ty::Primitive(kind) if kind.is_float() => Err(CodegenError::new("floats", span)),
+            Ty::Primitive(kind) => Ok(*kind),
+            // Functions are their addresses.
+            Ty::Function(parameters, return_type) => {
+                for parameter in parameters {
+                    self.kind(parameter, span)?;
+                }
+                self.kind(return_type, span)?;
+                Ok(TypeKind::Unit)
+            }
+            Ty::Error => panic!("cannot compile a program with errors"),
+            _ => unreachable!("values have primitive or function types"),
+        }
+    }
+
+    // Returns a directive telling the assembler that the code that follows
+    // comes from `span`, if debug info is wanted.
+    fn location(&self, span: Span) -> String {
+        match self.source {
+            Some(source) => {
+                let (line, column) = source.location(span.start);
+                format!("\t.loc\t1 {} {}\n", line, column)
+            }
+            None => String::new(),
+        }
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    fn emit(&mut self, instruction: &str) {
+        self.body.code += "\t";
+        self.body.code += instruction;
+        self.body.code += "\n";
+    }
+
+    fn place(&mut self, label: &str) {
+        self.body.code += label;
+        self.body.code += ":\n";
+    }
+
+    fn push(&mut self) {
+        self.emit("pushq\t%rax");
+        self.body.pushed += 1;
+    }
+
+    fn pop(&mut self, register: &str) {
+        self.emit(&format!("popq\t{}", register));
+        self.body.pushed -= 1;
+    }
+
+    // Gives a binding a slot in the frame, returning its offset.
+    fn slot(&mut self, id: NodeId) -> i32 {
+        let used = self.body.saved.len() + self.body.slots.len();
+        let offset = -8 * (used as i32 + 1);
+        self.body.slots.insert(id, offset);
+        offset
+    }
+
+    // Starts compiling a function whose bindings live as `intervals` says.
+    fn start(&mut self, intervals: regalloc::Intervals) {
+        let registers = intervals.allocate(&REGISTERS);
+        let saved = REGISTERS
+            .into_iter()
+            .filter(|register| registers.values().any(|used| used == register))
+            .collect();
+        self.body = Body {
+            registers,
+            saved,
+            ..Body::default()
+        };
+    }
+
+    // Keeps `%rax` as the value of a binding, in its register or else in a
+    // new slot.
+    fn define(&mut self, id: NodeId) {
+        let destination = match self.body.registers.get(&id) {
+            Some(register) => register.to_string(),
+            None => format!("{}(%rbp)", self.slot(id)),
+        };
+        self.emit(&format!("movq\t%rax, {}", destination));
+    }
+
+    fn function(&mut self, function: &hir::Function) -> Result<String, CodegenError> {
+        let statements = &function.body.as_ref().unwrap().statements;
+        let mut intervals = regalloc::Intervals::default();
+        for parameter in &function.parameters {
+            intervals.define(parameter.binding.id);
+        }
+        intervals.statements(statements);
+        self.start(intervals);
+        for (parameter, register) in function.parameters.iter().zip(ARGUMENTS) {
+            self.emit(&format!("movq\t{}, %rax", register));
+            // The ABI leaves the upper bits of narrower arguments undefined.
+            if let Ty::Primitive(kind) = parameter.ty {
+                self.wrap(kind);
+            }
+            self.define(parameter.binding.id);
+        }
+        self.statements(statements)?;
+        if !matches!(statements.last(), Some(Statement::Return(_))) {
+            // Type checking makes sure a function that returns a value
+            // cannot reach its end.
+            self.emit("ud2");
+        }
+        let symbol = &self.functions[&function.binding.id];
+        let header = format!("\n\t.globl\t{0}\n{0}:\n", symbol) + &self.location(function.span);
+        Ok(self.finish(&header))
+    }
+
+    // Compiles the top-level statements into the function that sets the
+    // globals.
+    fn initializer(&mut self, statements: &[Statement]) -> Result<String, CodegenError> {
+        let mut intervals = regalloc::Intervals::default();
+        for statement in statements {
+            match statement {
+                Statement::Function(_) => {}
+                // Top-level bindings are globals.
+                Statement::Let(let_statement) => intervals.expression(&let_statement.value),
+                statement => intervals.statement(statement),
+            }
+        }
+        self.start(intervals);
+        for statement in statements {
+            match statement {
+                Statement::Function(_) => {}
+                Statement::Let(let_statement) => {
+                    self.body.code += &self.location(let_statement.span);
+                    self.expression(&let_statement.value)?;
+                    let symbol = &self.globals[&let_statement.binding.id];
+                    let store = format!("movq\t%rax, {}(%rip)", symbol);
+                    self.emit(&store);
+                }
+                statement => self.statement(statement)?,
+            }
+        }
+        self.epilogue();
+        Ok(self.finish("\nmylang.init:\n"))
+    }
+
+    // Restores the saved registers and returns.
+    fn epilogue(&mut self) {
+        for (i, register) in self.body.saved.clone().into_iter().enumerate() {
+            self.emit(&format!(
+                "movq\t{}(%rbp), {}",
+                -8 * (i as i32 + 1),
+                register
+            ));
+        }
+        self.emit("leave");
+        self.emit("ret");
+    }
+
+    // Returns the function being compiled, with its prologue.
+    fn finish(&mut self, header: &str) -> String {
+        let body = std::mem::take(&mut self.body);
+        let mut text = header.to_string();
+        text += "\tpushq\t%rbp\n\tmovq\t%rsp, %rbp\n";
+        // The frame keeps the stack 16-byte aligned.
+        let frame = ((body.saved.len() + body.slots.len()) * 8).next_multiple_of(16);
+        if frame > 0 {
+            text += &format!("\tsubq\t${}, %rsp\n", frame);
+        }
+        for (i, register) in body.saved.iter().enumerate() {
+            text += &format!("\tmovq\t{}, {}(%rbp)\n", register, -8 * (i as i32 + 1));
+        }
+        text += &body.code;
+        text
+    }
+
+    fn statements(&mut self, statements: &[Statement]) -> Result<(), CodegenError> {
+        for statement in statements {
+            self.statement(statement)?;
+            if matches!(statement, Statement::Return(_)) {
+                // Nothing after a `return` can run.
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), CodegenError> {
+        let span = match statement {
+            Statement::Let(let_statement) => Some(let_statement.span),
+            Statement::Expression(expression) => Some(expression.span),
+            Statement::Return(return_statement) => Some(return_statement.span),
+            Statement::If(if_statement) => Some(if_statement.span),
+            Statement::While(while_statement) => Some(while_statement.span),
+            // Their statements say where they come from.
+            Statement::Function(_) | Statement::Block(_) => None,
+        };
+        if let Some(span) = span {
+            self.body.code += &self.location(span);
+        }
+        match statement {
+            Statement::Let(let_statement) => {
+                self.kind(&let_statement.ty, let_statement.span)?;
+                self.expression(&let_statement.value)?;
+                self.define(let_statement.binding.id);
+            }
+            Statement::Function(function) => {
+                return Err(CodegenError::new("nested functions", function.span));
+            }
+            Statement::Expression(expression) => self.expression(expression)?,
+            Statement::Return(return_statement) => {
+                if let Some(value) = &return_statement.value {
+                    self.expression(value)?;
+                }
+                self.epilogue();
+            }
+            Statement::If(if_statement) => {
+                let otherwise = self.label();
+                self.expression(&if_statement.condition)?;
+                self.emit("testq\t%rax, %rax");
+                self.emit(&format!("je\t{}", otherwise));
+                self.statements(&if_statement.then_block.statements)?;
+                match &if_statement.else_block {
+                    Some(else_block) => {
+                        let end = self.label();
+                        self.emit(&format!("jmp\t{}", end));
+                        self.place(&otherwise);
+                        self.statements(&else_block.statements)?;
+                        self.place(&end);
+                    }
+                    None => self.place(&otherwise),
+                }
+            }
+            Statement::While(while_statement) => {
+                let (head, end) = (self.label(), self.label());
+                self.place(&head);
+                self.expression(&while_statement.condition)?;
+                self.emit("testq\t%rax, %rax");
+                self.emit(&format!("je\t{}", end));
+                self.statements(&while_statement.body.statements)?;
+                self.emit(&format!("jmp\t{}", head));
+                self.place(&end);
+            }
+            Statement::Block(block) => self.statements(&block.statements)?,
+        }
+        Ok(())
+    }
+
+    // Compiles an expression, leaving its value in `%rax`.
+    fn expression(&mut self, expression: &Expression) -> Result<(), CodegenError> {
+        let span = expression.span;
+        match &expression.kind {
+            ExpressionKind::Integer(value) => {
+                let kind = self.kind(&expression.ty, span)?;
+                let Value::Integer(_, value) = Value::integer(kind, *value) else {
+                    unreachable!()
+                };
+                if i32::try_from(value).is_ok() {
+                    self.emit(&format!("movq\t${}, %rax", value));
+                } else {
+                    self.emit(&format!("movabsq\t${}, %rax", value));
+                }
+            }
+            ExpressionKind::Bool(value) => self.emit(&format!("movq\t${}, %rax", *value as u8)),
+            ExpressionKind::Name(binding) => {
+                let load = if let Some(register) = self.body.registers.get(&binding.id) {
+                    format!("movq\t{}, %rax", register)
+                } else if let Some(offset) = self.body.slots.get(&binding.id) {
+                    format!("movq\t{}(%rbp), %rax", offset)
+                } else if let Some(symbol) = self.globals.get(&binding.id) {
+                    format!("movq\t{}(%rip), %rax", symbol)
+                } else if let Some(symbol) = self.functions.get(&binding.id) {
+                    format!("leaq\t{}(%rip), %rax", symbol)
+                } else {
+                    return Err(CodegenError::new("nested functions", span));
+                };
+                self.emit(&load);
+            }
+            ExpressionKind::Binary(operator, left, right) => {
+                let kind = self.kind(&left.ty, left.span)?;
+                self.expression(left)?;
+                self.push();
+                self.expression(right)?;
+                self.emit("movq\t%rax, %rcx");
+                self.pop("%rax");
+                self.binary(operator, kind);
+            }
+            ExpressionKind::Call(callee, arguments) => self.call(callee, arguments, span)?,
+            ExpressionKind::Builtin(..) => return Err(CodegenError::new("builtins", span)),
+            ExpressionKind::Cast(operand) => {
+                let from = self.kind(&operand.ty, operand.span)?;
+                let to = self.kind(&expression.ty, span)?;
+                self.expression(operand)?;
+                // Narrower values are already sign-extended, and `int1` and
+                // `bool` are 0 or 1.
+                let from_bits = from.integer_bits().unwrap_or(1);
+                if to.integer_bits().is_some_and(|bits| bits < from_bits) {
+                    self.wrap(to);
+                }
+            }
+            ExpressionKind::Error => panic!("cannot compile a program with errors"),
+        }
+        Ok(())
+    }
+
+    // Applies an operator to `%rax` and `%rcx`, leaving the result in
+    // `%rax`.
+    fn binary(&mut self, operator: &BinaryOperator, kind: TypeKind) {
+        let signed = !matches!(kind, TypeKind::Int1 | TypeKind::Bool);
+        if operator.is_comparison() {
+            let condition = match (operator, signed) {
+                (BinaryOperator::Equal, _) => "e",
+                (BinaryOperator::NotEqual, _) => "ne",
+                (BinaryOperator::Less, true) => "l",
+                (BinaryOperator::Less, false) => "b",
+                (BinaryOperator::LessEqual, true) => "le",
+                (BinaryOperator::LessEqual, false) => "be",
+                (BinaryOperator::Greater, true) => "g",
+                (BinaryOperator::Greater, false) => "a",
+                (_, true) => "ge",
+                (_, false) => "ae",
+            };
+            self.emit("cmpq\t%rcx, %rax");
+            self.emit(&format!("set{}\t%al", condition));
+            self.emit("movzbq\t%al, %rax");
+            return;
+        }
+        match operator {
+            BinaryOperator::Plus => self.emit("addq\t%rcx, %rax"),
+            BinaryOperator::Minus => self.emit("subq\t%rcx, %rax"),
+            BinaryOperator::Star => self.emit("imulq\t%rcx, %rax"),
+            BinaryOperator::Divide => {
+                let nonzero = self.label();
+                self.emit("testq\t%rcx, %rcx");
+                self.emit(&format!("jne\t{}", nonzero));
+                self.emit("ud2");
+                self.place(&nonzero);
+                if signed {
+                    // `idivq` faults on the most negative value divided by
+                    // -1, which wraps around to itself.
+                    let (divide, done) = (self.label(), self.label());
+                    self.emit("cmpq\t$-1, %rcx");
+                    self.emit(&format!("jne\t{}", divide));
+                    self.emit("negq\t%rax");
+                    self.emit(&format!("jmp\t{}", done));
+                    self.place(&divide);
+                    self.emit("cqto");
+                    self.emit("idivq\t%rcx");
+                    self.place(&done);
+                } else {
+                    self.emit("xorl\t%edx, %edx");
+                    self.emit("divq\t%rcx");
+                }
+            }
+            _ => {
+                // Square and multiply, keeping the low bits.
+                let (positive, head, even, done) =
+                    (self.label(), self.label(), self.label(), self.label());
+                self.emit("testq\t%rcx, %rcx");
+                self.emit(&format!("jns\t{}", positive));
+                self.emit("ud2");
+                self.place(&positive);
+                self.emit("movq\t%rax, %rdx");
+                self.emit("movq\t$1, %rax");
+                self.place(&head);
+                self.emit("testq\t%rcx, %rcx");
+                self.emit(&format!("je\t{}", done));
+                self.emit("testq\t$1, %rcx");
+                self.emit(&format!("je\t{}", even));
+                self.emit("imulq\t%rdx, %rax");
+                self.place(&even);
+                self.emit("imulq\t%rdx, %rdx");
+                self.emit("shrq\t%rcx");
+                self.emit(&format!("jmp\t{}", head));
+                self.place(&done);
+            }
+        }
+        self.wrap(kind);
+    }
+
+    // Reduces `%rax` to the range of an integer type.
+    fn wrap(&mut self, kind: TypeKind) {
+        match kind {
+            TypeKind::Int1 => self.emit("andq\t$1, %rax"),
+            TypeKind::Int2 => {
+                self.emit("shlq\t$62, %rax");
+                self.emit("sarq\t$62, %rax");
+            }
+            TypeKind::Int4 => {
+                self.emit("shlq\t$60, %rax");
+                self.emit("sarq\t$60, %rax");
+            }
+            TypeKind::Int8 => self.emit("movsbq\t%al, %rax"),
+            TypeKind::Int16 => self.emit("movswq\t%ax, %rax"),
+            TypeKind::Int32 => self.emit("movslq\t%eax, %rax"),
+            _ => {}
+        }
+    }
+
+    fn call(
+        &mut self,
+        callee: &Expression,
+        arguments: &[Expression],
+        span: Span,
+    ) -> Result<(), CodegenError> {
+        if arguments.len() > ARGUMENTS.len() {
+            let unsupported = "calls with more than six arguments";
+            return Err(CodegenError::new(unsupported, span));
+        }
+        // A top-level function is called by name, anything else through its
+        // address.
+        let direct = match &callee.kind {
+            ExpressionKind::Name(binding) => self.functions.get(&binding.id).cloned(),
+            _ => None,
+        };
+        if direct.is_none() {
+            self.expression(callee)?;
+            self.push();
+        }
+        for argument in arguments {
+            self.expression(argument)?;
+            self.push();
+        }
+        for register in ARGUMENTS[..arguments.len()].iter().rev() {
+            self.pop(register);
+        }
+        if direct.is_none() {
+            self.pop("%r11");
+        }
+        // The stack must be 16-byte aligned at a call.
+        let padded = self.body.pushed % 2 == 1;
+        if padded {
+            self.emit("subq\t$8, %rsp");
+        }
+        match direct {
+            Some(symbol) => self.emit(&format!("call\t{}", symbol)),
+            None => self.emit("call\t*%r11"),
+        }
+        if padded {
+            self.emit("addq\t$8, %rsp");
+        }
+        // The ABI leaves the upper bits of narrower results undefined.
+        if let Ty::Function(_, return_type) = &callee.ty {
+            if let Ty::Primitive(kind) = **return_type {
+                self.wrap(kind);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codegen::lower, interpreter::Interpreter};
+    use std::{
+        io,
+        process::Command,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    #[test]
+    fn functions_compile_to_assembly() {
+        let assembly = compile(
+            &lower(
+                "\
+fn add(a: int8, b: int8) -> int8 { return a + b; }
+fn main() -> int32 { return add(100, 28) as int32; }",
+            ),
+            &Target::x86_64_linux(),
+        )
+        .unwrap();
+        assert_eq!(
+            assembly,
+            "\
+\t.text
+
+\t.globl\tadd
+add:
+\tpushq\t%rbp
+\tmovq\t%rsp, %rbp
+\tsubq\t$16, %rsp
+\tmovq\t%rbx, -8(%rbp)
+\tmovq\t%r12, -16(%rbp)
+\tmovq\t%rdi, %rax
+\tmovsbq\t%al, %rax
+\tmovq\t%rax, %rbx
+\tmovq\t%rsi, %rax
+\tmovsbq\t%al, %rax
+\tmovq\t%rax, %r12
+\tmovq\t%rbx, %rax
+\tpushq\t%rax
+\tmovq\t%r12, %rax
+\tmovq\t%rax, %rcx
+\tpopq\t%rax
+\taddq\t%rcx, %rax
+\tmovsbq\t%al, %rax
+\tmovq\t-8(%rbp), %rbx
+\tmovq\t-16(%rbp), %r12
+\tleave
+\tret
+
+\t.globl\tmain
+main:
+\tpushq\t%rbp
+\tmovq\t%rsp, %rbp
+\tmovq\t$100, %rax
+\tpushq\t%rax
+\tmovq\t$28, %rax
+\tpushq\t%rax
+\tpopq\t%rsi
+\tpopq\t%rdi
+\tcall\tadd
+\tmovsbq\t%al, %rax
+\tleave
+\tret
+
+\t.section\t.note.GNU-stack,\"\",@progbits
+"
+        );
+    }
+
+    #[test]
+    fn debug_info_locates_statements() {
+        let source = "\
+fn entry() -> int64 {
+    let y: int64 = 2;
+    if y > 1 {
+        return y;
+    }
+    return 0;
+}";
+        let map = SourceMap::new("dir/\"f\".my", source);
+        let assembly =
+            compile_with_debug_info(&lower(source), &Target::x86_64_linux(), &map).unwrap();
+        let directives: Vec<&str> = assembly
+            .lines()
+            .filter(|line| line.starts_with("\t.file") || line.starts_with("\t.loc"))
+            .collect();
+        assert_eq!(
+            directives,
+            [
+                "\t.file\t1 \"dir/\\\"f\\\".my\"",
+                "\t.loc\t1 1 1",
+                "\t.loc\t1 2 5",
+                "\t.loc\t1 3 5",
+                "\t.loc\t1 4 9",
+                "\t.loc\t1 6 5",
+            ]
+        );
+        // The assembler understands them.
+        if let Some(output) = run(&assembly) {
+            assert_eq!(output, Ok("2".to_string()));
+        }
+    }
+
+    #[test]
+    fn unsupported_features_are_reported() {
+        for (source, unsupported, at) in [
+            ("fn f(x: float32) { }", "floats", "fn f"),
+            (
+                "fn f() -> string { return read_line(); }",
+                "strings",
+                "fn f",
+            ),
+            ("fn f(x: int64) { println(x); }", "builtins", "println(x)"),
+            (
+                "fn f() { fn g() { } g(); }",
+                "nested functions",
+                "fn g() { }",
+            ),
+        ] {
+            let error = compile(&lower(source), &Target::x86_64_linux()).unwrap_err();
+            assert_eq!(error.unsupported, unsupported, "{}", source);
+            assert!(source[error.span.range()].starts_with(at), "{}", source);
+        }
+        let error = compile(&lower("fn f() { }"), &Target::wasm32()).unwrap_err();
+        assert_eq!(error.unsupported, "targets other than x86-64");
+    }
+
+    // Assembles a program with a C driver that prints what its `entry`
+    // function returns, and runs it. Returns `None` when there is no C
+    // compiler, or when this is not an x86-64 Linux machine, whose C
+    // compiler could assemble and link the SysV ELF code.
+    fn run(assembly: &str) -> Option<Result<String, String>> {
+        if !cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+            return None;
+        }
+        const DRIVER: &str = "\
+#include <stdio.h>
+long long entry(void);
+int main(void) { printf(\"%lld\\n\", entry()); return 0; }
+";
+        // Tests run in parallel, so each program gets files of its own.
+        static PROGRAMS: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "mylang-{}-{}",
+            std::process::id(),
+            PROGRAMS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let (source, driver) = (path.with_extension("s"), path.with_extension("c"));
+        std::fs::write(&source, assembly).unwrap();
+        std::fs::write(&driver, DRIVER).unwrap();
+        let built = Command::new("cc")
+            .arg("-o")
+            .arg(&path)
+            .arg(&source)
+            .arg(&driver)
+            .output();
+        std::fs::remove_file(&source).unwrap();
+        std::fs::remove_file(&driver).unwrap();
+        let built = match built {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return None,
+            built => built.unwrap(),
+        };
+        let text = |bytes: Vec<u8>| String::from_utf8_lossy(&bytes).trim().to_string();
+        if !built.status.success() {
+            return Some(Err(text(built.stderr)));
+        }
+        let output = Command::new(&path).output().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        Some(match output.status.success() {
+            true => Ok(text(output.stdout)),
+            false => Err(format!("{}", output.status)),
+        })
+    }
+
+    #[test]
+    fn compiled_programs_agree_with_the_interpreter() {
+        let corpus = [
+            "fn entry() -> int64 { return 1 + 2 * 3 ** 2 - 4 / 3; }",
+            "\
+fn fib(n: int32) -> int32 {
+    if n < 2 { return n; } else { return fib(n - 1) + fib(n - 2); }
+}
+fn entry() -> int32 { return fib(15); }",
+            "\
+fn entry() -> int8 {
+    let a: int8 = 120;
+    let b: int8 = a + 10;
+    let c: int16 = 1000;
+    let d: int8 = 0 - 127 - 1;
+    let minus: int8 = 0 - 1;
+    return b * 3 + c as int8 + d / minus + minus ** 3;
+}",
+            "\
+fn entry() -> int4 {
+    let x: int4 = 7;
+    let y: int2 = 1;
+    let z: int1 = 1;
+    return x + (y + 1) as int4 + (z + z) as int4 + (z > 0) as int4;
+}",
+            "\
+let base: int64 = 1000;
+fn add(x: int64) -> int64 { return x + base; }
+fn twice(f: fn(int64) -> int64, x: int64) -> int64 { return f(f(x)); }
+fn entry() -> int64 { return twice(add, 1) + add(2); }",
+            "\
+fn sum(a: int16, b: int16, c: int16, d: int16, e: int16, f: int16) -> int16 {
+    return a * 1000 + b * 100 + c * 10 + d - e - f;
+}
+fn entry() -> int16 { return 1 + sum(30, 2, 3, 4, 5, 6); }",
+            "\
+fn entry() -> int64 {
+    let n: int64 = 3;
+    while n > 0 {
+        if n == 3 { return 30; }
+    }
+    return 0;
+}",
+            "\
+fn side(x: int64) { }
+fn entry() -> int64 {
+    let big: int64 = 0 - 9223372036854775807 - 1;
+    side(big);
+    let minus: int64 = 0 - 1;
+    let seven: int64 = 7;
+    { let inner: int64 = big / minus; return inner + seven ** 30 + big as int32 as int64; }
+}",
+            "fn entry() -> bool { let x: int1 = 1; return x > 0 == (2 < 1); }",
+            // More bindings live at once than there are registers, across
+            // calls that use the registers themselves.
+            "\
+fn square(x: int64) -> int64 { let y: int64 = x * x; return y; }
+fn entry() -> int64 {
+    let a: int64 = square(1);
+    let b: int64 = square(2);
+    let c: int64 = square(3);
+    let d: int64 = square(4);
+    let e: int64 = square(5);
+    let f: int64 = square(6);
+    let g: int64 = square(7);
+    let n: int64 = 3;
+    while n > 0 {
+        let h: int64 = square(a + b);
+        return a + b * 10 + c * 100 + d * 1000 + e * 10000 + f * 100000 + g + h;
+    }
+    return 0;
+}",
+            "\
+let scale: int64 = 3;
+fn entry() -> int64 { return scale * 2; }",
+        ];
+        for source in corpus {
+            let hir = lower(source);
+            let mut interpreter = Interpreter::new(&hir);
+            interpreter.run().unwrap();
+            let expected = match interpreter.call("entry", vec![]).unwrap() {
+                Some(Value::Integer(_, value)) => value,
+                Some(Value::Bool(value)) => value.into(),
+                value => panic!("unexpected result {:?}", value),
+            };
+            let Some(output) = run(&compile(&hir, &Target::x86_64_linux()).unwrap()) else {
+                return;
+            };
+            assert_eq!(output, Ok(expected.to_string()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn runtime_errors_trap() {
+        for source in [
+            "fn entry() -> int32 { let zero: int32 = 0; return 1 / zero; }",
+            "fn entry() -> int8 { let minus: int8 = 0 - 1; return 2 ** minus; }",
+        ] {
+            let Some(result) = run(&compile(&lower(source), &Target::x86_64_linux()).unwrap())
+            else {
+                return;
+            };
+            assert!(result.is_err(), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{
+    ast::NodeId,
+    hir::{Expression, ExpressionKind, Statement},
+};
+use std::collections::HashMap;
+
+// Linear-scan register allocation for the bindings of a function, as
+// described by Poletto and Sarkar.
+//
+// The code of a function is numbered in the order the backend compiles it,
+// and each binding lives from where it is defined to where it is last used.
+// A binding used in a loop it is defined outside of lives until the end of
+// the loop, since the next iteration uses it again. Going through the
+// bindings in the order they are defined, each gets a register that no
+// binding living at the same time has. When there are none left, the
+// binding living the longest gives up its register and is spilled.
+#[derive(Default)]
+pub(super) struct Intervals {
+    // The next position in the code.
+    position: u32,
+    // The interval of each binding, in the order they are defined.
+    intervals: Vec<Interval>,
+    // The index of each binding's interval.
+    bindings: HashMap<NodeId, usize>,
+    // The first and last positions of each loop.
+    loops: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Interval {
+    binding: NodeId,
+    start: u32,
+    end: u32,
+}
+
+impl Intervals {
+    fn next(&mut self) -> u32 {
+        self.position += 1;
+        self.position
+    }
+
+    // Defines a binding at the current position.
+    pub(super) fn define(&mut self, binding: NodeId) {
+        let position = self.next();
+        self.bindings.insert(binding, self.intervals.len());
+        self.intervals.push(Interval {
+            binding,
+            start: position,
+            end: position,
+        });
+    }
+
+    // Mirrors `Compiler::statements`, which skips what follows a `return`.
+    pub(super) fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+            if matches!(statement, Statement::Return(_)) {
+                break;
+            }
+        }
+    }
+
+    pub(super) fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let(let_statement) => {
+                self.expression(&let_statement.value);
+                self.define(let_statement.binding.id);
+            }
+            // Nested functions are not compiled.
+            Statement::Function(_) => {}
+            Statement::Expression(expression) => self.expression(expression),
+            Statement::Return(return_statement) => {
+                if let Some(value) = &return_statement.value {
+                    self.expression(value);
+                }
+            }
+            Statement::If(if_statement) => {
+                self.expression(&if_statement.condition);
+                self.statements(&if_statement.then_block.statements);
+                if let Some(else_block) = &if_statement.else_block {
+                    self.statements(&else_block.statements);
+                }
+            }
+            Statement::While(while_statement) => {
+                let start = self.next();
+                self.expression(&while_statement.condition);
+                self.statements(&while_statement.body.statements);
+                let end = self.next();
+                self.loops.push((start, end));
+            }
+            Statement::Block(block) => self.statements(&block.statements),
+        }
+    }
+
+    pub(super) fn expression(&mut self, expression: &Expression) {
+        match &expression.kind {
+            ExpressionKind::Integer(_) | ExpressionKind::Bool(_) | ExpressionKind::Error => {}
+            ExpressionKind::Name(binding) => {
+                let position = self.next();
+                // Globals and functions are not allocated.
+                if let Some(&index) = self.bindings.get(&binding.id) {
+                    self.intervals[index].end = position;
+                }
+            }
+            ExpressionKind::Binary(_, left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExpressionKind::Call(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            ExpressionKind::Builtin(_, arguments) => {
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            ExpressionKind::Cast(operand) => self.expression(operand),
+        }
+    }
+
+    // Assigns the bindings to `registers`, returning the register of each
+    // binding that got one. The others are spilled.
+    pub(super) fn allocate(mut self, registers: &[&'static str]) -> HashMap<NodeId, &'static str> {
+        // Loops are pushed when they end, so inner loops come before the
+        // loops around them, which then extend what the inner ones did.
+        for &(start, end) in &self.loops {
+            for interval in &mut self.intervals {
+                if interval.start < start && interval.end > start && interval.end < end {
+                    interval.end = end;
+                }
+            }
+        }
+
+        let mut free: Vec<&'static str> = registers.iter().rev().copied().collect();
+        let mut allocated = HashMap::new();
+        // The intervals that have registers, by increasing end.
+        let mut active: Vec<Interval> = vec![];
+        for interval in self.intervals {
+            active.retain(|other| {
+                let expired = other.end < interval.start;
+                if expired {
+                    free.push(allocated[&other.binding]);
+                }
+                !expired
+            });
+            let register = match free.pop() {
+                Some(register) => register,
+                None => match active.last() {
+                    Some(&longest) if longest.end > interval.end => {
+                        active.pop();
+                        allocated.remove(&longest.binding).unwrap()
+                    }
+                    _ => continue,
+                },
+            };
+            allocated.insert(interval.binding, register);
+            let position = active.partition_point(|other| other.end <= interval.end);
+            active.insert(position, interval);
+        }
+        allocated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    // Allocates the parameters and bindings of a program's only function,
+    // returning the register of each, by name, or `None` if it is spilled.
+    fn allocate(source: &str, registers: &[&'static str]) -> Vec<(String, Option<&'static str>)> {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        assert!(program.typecheck().errors.is_empty(), "{}", source);
+        let program = program.to_hir();
+        let Statement::Function(function) = &program.statements[0] else {
+            panic!("expected a function");
+        };
+        let mut intervals = Intervals::default();
+        let mut names = vec![];
+        for parameter in &function.parameters {
+            intervals.define(parameter.binding.id);
+            names.push(parameter.binding.clone());
+        }
+        let statements = &function.body.as_ref().unwrap().statements;
+        intervals.statements(statements);
+        let mut lets = vec![];
+        collect_lets(statements, &mut lets);
+        names.extend(lets);
+        let allocated = intervals.allocate(registers);
+        names
+            .iter()
+            .map(|binding| {
+                let register = allocated.get(&binding.id).copied();
+                (binding.name.to_string(), register)
+            })
+            .collect()
+    }
+
+    fn collect_lets(statements: &[Statement], lets: &mut Vec<crate::hir::Binding>) {
+        for statement in statements {
+            match statement {
+                Statement::Let(let_statement) => lets.push(let_statement.binding.clone()),
+                Statement::If(if_statement) => {
+                    collect_lets(&if_statement.then_block.statements, lets);
+                    if let Some(else_block) = &if_statement.else_block {
+                        collect_lets(&else_block.statements, lets);
+                    }
+                }
+                Statement::While(while_statement) => {
+                    collect_lets(&while_statement.body.statements, lets)
+                }
+                Statement::Block(block) => collect_lets(&block.statements, lets),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn registers_are_reused_after_last_uses() {
+        let source = "\
+fn f(a: int64) -> int64 {
+    let b: int64 = a + 1;
+    let c: int64 = b * 2;
+    let d: int64 = c - b;
+    return d;
+}";
+        assert_eq!(
+            allocate(source, &["r1", "r2"]),
+            [
+                ("a".to_string(), Some("r1")),
+                ("b".to_string(), Some("r1")),
+                ("c".to_string(), Some("r2")),
+                ("d".to_string(), Some("r1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_longest_living_binding_is_spilled() {
+        let source = "\
+fn f(a: int64, b: int64) -> int64 {
+    let c: int64 = b + 1;
+    return c + b + a;
+}";
+        assert_eq!(
+            allocate(source, &["r1", "r2"]),
+            [
+                ("a".to_string(), None),
+                ("b".to_string(), Some("r2")),
+                ("c".to_string(), Some("r1")),
+            ]
+        );
+        let source = "\
+fn f(a: int64, b: int64) -> int64 {
+    let c: int64 = b + 1;
+    return a + b + c;
+}";
+        assert_eq!(
+            allocate(source, &["r1", "r2"]),
+            [
+                ("a".to_string(), Some("r1")),
+                ("b".to_string(), Some("r2")),
+                ("c".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn bindings_used_in_loops_live_until_their_end() {
+        let source = "\
+fn f(a: int64, n: int64) -> int64 {
+    while n > 0 {
+        let b: int64 = a + 1;
+        while b > a {
+            let c: int64 = b + 1;
+        }
+        let d: int64 = 2;
+    }
+    return 0;
+}";
+        // `a` is used again in the next iteration, so `d` cannot have its
+        // register, but `b` is defined anew each time.
+        assert_eq!(
+            allocate(source, &["r1", "r2", "r3", "r4"]),
+            [
+                ("a".to_string(), Some("r1")),
+                ("n".to_string(), Some("r2")),
+                ("b".to_string(), Some("r3")),
+                ("c".to_string(), Some("r4")),
+                ("d".to_string(), Some("r3")),
+            ]
+        );
+    }
+}
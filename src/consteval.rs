@@ -0,0 +1,400 @@
+use crate::{
+    ast::{
+        BinaryOperator, Block, Expression, NodeMap, Program, Span, Spanned, Statement, Symbol,
+        TypeExpr, TypeKind,
+    },
+    diagnostic::Diagnostic,
+    resolver::{DeclarationKind, Resolution},
+    value::wrap,
+};
+use std::fmt;
+
+// A value computed at compile time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConstValue {
+    Integer(i128),
+    Bool(bool),
+}
+
+impl fmt::Display for ConstValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstValue::Integer(value) => write!(f, "{}", value),
+            ConstValue::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConstErrorKind {
+    // A name that refers to something other than a constant.
+    NotConstant(Symbol),
+    // An expression, such as a call, that cannot be evaluated at compile time.
+    NotEvaluable,
+    DivisionByZero,
+    NegativeExponent,
+    // A result outside the range of the type it is computed in.
+    Overflow(TypeKind),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConstError {
+    pub kind: ConstErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for ConstErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstErrorKind::NotConstant(name) => write!(f, "`{}` is not a constant", name),
+            ConstErrorKind::NotEvaluable => {
+                f.write_str("expression cannot be evaluated at compile time")
+            }
+            ConstErrorKind::DivisionByZero => f.write_str("division by zero"),
+            ConstErrorKind::NegativeExponent => f.write_str("negative exponent"),
+            ConstErrorKind::Overflow(kind) => write!(f, "arithmetic overflow in `{}`", kind),
+        }
+    }
+}
+
+impl ConstErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ConstErrorKind::NotConstant(_) => "E0400",
+            ConstErrorKind::NotEvaluable => "E0401",
+            ConstErrorKind::DivisionByZero => "E0402",
+            ConstErrorKind::NegativeExponent => "E0403",
+            ConstErrorKind::Overflow(_) => "E0404",
+        }
+    }
+}
+
+impl ConstError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(self.kind.code(), self.to_string(), self.span)
+    }
+}
+
+impl fmt::Display for ConstError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+// The values of a program's constants.
+#[derive(Debug, Default)]
+pub struct Consts {
+    // The value of every constant that could be evaluated, keyed by the id of
+    // its declaring identifier.
+    pub values: NodeMap<ConstValue>,
+    pub errors: Vec<ConstError>,
+}
+
+// Evaluates every `const` declaration in a program, in source order.
+//
+// Each initializer is computed in its declared type: every intermediate
+// result must fit in that type, so `const N: int8 = 100 + 100;` overflows
+// even if the final value is later cast to something wider. Mistakes the type
+// checker reports, such as adding a `bool`, make a constant's value unknown
+// without an error of their own.
+pub fn evaluate_consts(program: &Program, resolution: &Resolution) -> Consts {
+    let mut consts = Consts::default();
+    consts.statements(&program.statements, resolution);
+    consts
+}
+
+impl Consts {
+    fn statements(&mut self, statements: &[Statement], resolution: &Resolution) {
+        for statement in statements {
+            self.statement(statement, resolution);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement, resolution: &Resolution) {
+        match statement {
+            Statement::Const(constant) => {
+                let kind = match &constant.ttype {
+                    TypeExpr::Named(named) => named.kind,
+                    _ => TypeKind::Unit,
+                };
+                match self.evaluate(&constant.expression, kind, resolution) {
+                    Ok(value) => {
+                        self.values.insert(constant.identifier.id, value);
+                    }
+                    Err(Some(error)) => self.errors.push(error),
+                    Err(None) => {}
+                }
+            }
+            Statement::FunctionDeclaration(function) => {
+                if let Some(body) = &function.body {
+                    self.block(body, resolution);
+                }
+            }
+            Statement::If(if_statement) => {
+                self.block(&if_statement.then_block, resolution);
+                if let Some(else_branch) = &if_statement.else_branch {
+                    self.statement(else_branch, resolution);
+                }
+            }
+            Statement::While(while_statement) => self.block(&while_statement.body, resolution),
+            Statement::Block(block) => self.block(block, resolution),
+            Statement::Let(_)
+            | Statement::Expression(_)
+            | Statement::Return(_)
+            | Statement::Import(_) => {}
+        }
+    }
+
+    fn block(&mut self, block: &Block, resolution: &Resolution) {
+        self.statements(&block.statements, resolution);
+    }
+
+    // Evaluates an expression over literals and constants, checking every
+    // intermediate result against the range of `kind`.
+    //
+    // Returns `Err(None)` when the value is unknown because of an error that
+    // is reported elsewhere: a constant that could not be evaluated, a name
+    // that did not resolve, or a value of the wrong type.
+    pub fn evaluate(
+        &self,
+        expression: &Expression,
+        kind: TypeKind,
+        resolution: &Resolution,
+    ) -> Result<ConstValue, Option<ConstError>> {
+        let overflow = || {
+            Some(ConstError {
+                kind: ConstErrorKind::Overflow(kind),
+                span: expression.span(),
+            })
+        };
+        let value = match expression {
+            Expression::IntegerLiteral(literal) => {
+                ConstValue::Integer(literal.text.parse().map_err(|_| overflow())?)
+            }
+            Expression::Identifier(identifier) => {
+                let declaration = resolution.uses.get(identifier.id).ok_or(None)?;
+                match resolution.declarations.get(*declaration).map(|d| d.kind) {
+                    Some(DeclarationKind::Constant) => {
+                        *self.values.get(*declaration).ok_or(None)?
+                    }
+                    _ => {
+                        return Err(Some(ConstError {
+                            kind: ConstErrorKind::NotConstant(identifier.name.clone()),
+                            span: identifier.span,
+                        }))
+                    }
+                }
+            }
+            Expression::BinaryExpression(binary) if binary.operator.is_comparison() => {
+                // The operands' type is not known here, so they are computed
+                // without a range.
+                let left = self.evaluate(&binary.left, TypeKind::Unit, resolution)?;
+                let right = self.evaluate(&binary.right, TypeKind::Unit, resolution)?;
+                ConstValue::Bool(compare(&binary.operator, left, right).ok_or(None)?)
+            }
+            Expression::BinaryExpression(binary) => {
+                let left = integer(self.evaluate(&binary.left, kind, resolution)?)?;
+                let right = integer(self.evaluate(&binary.right, kind, resolution)?)?;
+                let result = arithmetic(&binary.operator, left, right).map_err(|kind| {
+                    Some(ConstError {
+                        kind,
+                        span: binary.span,
+                    })
+                })?;
+                ConstValue::Integer(result.ok_or_else(overflow)?)
+            }
+            Expression::Cast(cast) => {
+                let value = self.evaluate(&cast.expression, TypeKind::Unit, resolution)?;
+                let TypeExpr::Named(target) = &cast.ttype else {
+                    return Err(None);
+                };
+                convert(value, target.kind).ok_or(None)?
+            }
+            Expression::Call(call) => {
+                return Err(Some(ConstError {
+                    kind: ConstErrorKind::NotEvaluable,
+                    span: call.span,
+                }))
+            }
+        };
+        match (value, kind.integer_range()) {
+            (ConstValue::Integer(value), Some((min, max))) if value < min || value > max => {
+                Err(overflow())
+            }
+            (ConstValue::Integer(_), None) if kind == TypeKind::Bool => Err(None),
+            (ConstValue::Bool(_), Some(_)) => Err(None),
+            _ => Ok(value),
+        }
+    }
+}
+
+// Applies an arithmetic operator to two integers. Returns `Ok(None)` if the
+// result does not fit in an `i128`; the caller checks it against the range
+// of its type.
+pub(crate) fn arithmetic(
+    operator: &BinaryOperator,
+    left: i128,
+    right: i128,
+) -> Result<Option<i128>, ConstErrorKind> {
+    Ok(match operator {
+        BinaryOperator::Plus => left.checked_add(right),
+        BinaryOperator::Minus => left.checked_sub(right),
+        BinaryOperator::Star => left.checked_mul(right),
+        BinaryOperator::Divide if right == 0 => return Err(ConstErrorKind::DivisionByZero),
+        BinaryOperator::Divide => left.checked_div(right),
+        BinaryOperator::Power if right < 0 => return Err(ConstErrorKind::NegativeExponent),
+        BinaryOperator::Power => u32::try_from(right)
+            .ok()
+            .and_then(|right| left.checked_pow(right)),
+        _ => unreachable!("comparisons are not arithmetic"),
+    })
+}
+
+fn integer(value: ConstValue) -> Result<i128, Option<ConstError>> {
+    match value {
+        ConstValue::Integer(value) => Ok(value),
+        ConstValue::Bool(_) => Err(None),
+    }
+}
+
+pub(crate) fn compare(
+    operator: &BinaryOperator,
+    left: ConstValue,
+    right: ConstValue,
+) -> Option<bool> {
+    let ordering = match (left, right) {
+        (ConstValue::Integer(left), ConstValue::Integer(right)) => left.cmp(&right),
+        (ConstValue::Bool(left), ConstValue::Bool(right)) => left.cmp(&right),
+        _ => return None,
+    };
+    Some(match operator {
+        BinaryOperator::Equal => ordering.is_eq(),
+        BinaryOperator::NotEqual => ordering.is_ne(),
+        BinaryOperator::Less => ordering.is_lt(),
+        BinaryOperator::LessEqual => ordering.is_le(),
+        BinaryOperator::Greater => ordering.is_gt(),
+        BinaryOperator::GreaterEqual => ordering.is_ge(),
+        _ => return None,
+    })
+}
+
+// Converts a value as an `as` cast does. A cast to an integer type keeps the
+// low bits of the value, two's complement.
+pub(crate) fn convert(value: ConstValue, kind: TypeKind) -> Option<ConstValue> {
+    let value = match value {
+        ConstValue::Integer(value) => value,
+        ConstValue::Bool(value) => value as i128,
+    };
+    if kind.is_float() {
+        return Some(ConstValue::Integer(value));
+    }
+    wrap(value, kind).map(ConstValue::Integer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    // Returns each constant's name and value, or each error's message and the
+    // source text it points at if there are errors.
+    type Pairs = Vec<(String, String)>;
+
+    fn evaluate(source: &str) -> Result<Pairs, Pairs> {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let resolution = program.resolve();
+        let consts = evaluate_consts(&program, &resolution);
+        if !consts.errors.is_empty() {
+            return Err(consts
+                .errors
+                .iter()
+                .map(|e| (e.to_string(), source[e.span.range()].to_string()))
+                .collect());
+        }
+        Ok(consts
+            .values
+            .iter()
+            .map(|(id, value)| {
+                let name = &resolution.declarations.get(id).unwrap().name;
+                (name.to_string(), value.to_string())
+            })
+            .collect())
+    }
+
+    fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn constants_are_computed_from_earlier_constants() {
+        let source = "\
+const A: int32 = 6;
+const B: int32 = A * 7 - 2 ** 3 / 3;
+const C: bool = B >= 40;
+const D: int8 = 300 as int8;
+fn f() { const E: int64 = B + 1; }";
+        assert_eq!(
+            evaluate(source).unwrap(),
+            pairs(&[
+                ("A", "6"),
+                ("B", "40"),
+                ("C", "true"),
+                ("D", "44"),
+                ("E", "41")
+            ])
+        );
+    }
+
+    #[test]
+    fn evaluation_failures_are_errors() {
+        let source = "\
+const A: int8 = 100 + 100;
+const B: int32 = 1 / (2 - 2);
+const C: int32 = A + 1;
+let x: int32 = 1;
+const D: int32 = x;
+const E: int32 = f();
+const F: int32 = 2 ** (1 - 2);
+const G: int64 = 99999999999999999999999999999999999999999;
+fn f() -> int32 { return 1; }";
+        assert_eq!(
+            evaluate(source).unwrap_err(),
+            pairs(&[
+                ("arithmetic overflow in `int8`", "100 + 100"),
+                ("division by zero", "1 / (2 - 2)"),
+                ("`x` is not a constant", "x"),
+                ("expression cannot be evaluated at compile time", "f()"),
+                ("negative exponent", "2 ** (1 - 2)"),
+                (
+                    "arithmetic overflow in `int64`",
+                    "99999999999999999999999999999999999999999"
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn casts_wrap_to_the_target_width() {
+        assert_eq!(
+            convert(ConstValue::Integer(255), TypeKind::Int8),
+            Some(ConstValue::Integer(-1))
+        );
+        assert_eq!(
+            convert(ConstValue::Integer(-3), TypeKind::Int4),
+            Some(ConstValue::Integer(-3))
+        );
+        assert_eq!(
+            convert(ConstValue::Integer(3), TypeKind::Int1),
+            Some(ConstValue::Integer(1))
+        );
+        assert_eq!(
+            convert(ConstValue::Bool(true), TypeKind::Int16),
+            Some(ConstValue::Integer(1))
+        );
+        assert_eq!(convert(ConstValue::Integer(1), TypeKind::Bool), None);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::{
+    ast::{BinaryOperator, NodeMap},
+    cfg::{BlockId, Cfg, Instruction, Terminator},
+    consteval::{arithmetic, compare, convert, ConstValue},
+    diagnostic::{Diagnostic, DiagnosticSink},
+    hir::{Expression, ExpressionKind},
+    typecheck::Ty,
+};
+use std::collections::VecDeque;
+
+// What is known at compile time about a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    // The value is the same every time it is computed.
+    Constant(ConstValue),
+    // The value depends on something only known when the program runs.
+    Varying,
+}
+
+// Sparse conditional constant propagation over a function's control flow
+// graph: finds the bindings whose value is always the same and the blocks
+// that can run at all, given that a branch on a constant condition only ever
+// takes one way.
+//
+// The analysis is sparse: it tracks one value per binding rather than the
+// value of every binding at every point. That is exact here because each
+// binding is defined once and its definition is reached before any of its
+// uses, so a binding's value is final as soon as its definition has been
+// seen, and each block needs to be visited only once.
+//
+// Only integer and `bool` values are propagated. Parameters, call results,
+// bindings from enclosing functions and floating point values vary, as does
+// arithmetic that overflows its type or divides by zero, which is left to
+// fail when the program runs.
+#[derive(Debug, Clone)]
+pub struct Constants {
+    // The value of every binding defined in an executable block.
+    values: NodeMap<Value>,
+    // Indexed by `BlockId`.
+    executable: Vec<bool>,
+}
+
+impl Constants {
+    pub fn analyze(cfg: &Cfg) -> Constants {
+        let mut constants = Constants {
+            values: NodeMap::new(),
+            executable: vec![false; cfg.blocks.len()],
+        };
+        constants.executable[Cfg::ENTRY.index()] = true;
+        let mut worklist = VecDeque::from([Cfg::ENTRY]);
+        while let Some(id) = worklist.pop_front() {
+            let block = cfg.block(id);
+            for instruction in &block.instructions {
+                if let Instruction::Let(let_statement) = instruction {
+                    let value = constants.evaluate(&let_statement.value);
+                    constants.values.insert(let_statement.binding.id, value);
+                }
+            }
+            let successors = match &block.terminator {
+                Terminator::Branch {
+                    condition,
+                    then_block,
+                    else_block,
+                } => match constants.evaluate(condition) {
+                    Value::Constant(ConstValue::Bool(true)) => vec![*then_block],
+                    Value::Constant(ConstValue::Bool(false)) => vec![*else_block],
+                    _ => vec![*then_block, *else_block],
+                },
+                _ => cfg.successors(id),
+            };
+            for successor in successors {
+                if !constants.executable[successor.index()] {
+                    constants.executable[successor.index()] = true;
+                    worklist.push_back(successor);
+                }
+            }
+        }
+        constants
+    }
+
+    // Returns whether control can reach a block.
+    pub fn is_executable(&self, id: BlockId) -> bool {
+        self.executable[id.index()]
+    }
+
+    // Returns the value of an expression in an executable block, from the
+    // values of the bindings it uses.
+    pub fn evaluate(&self, expression: &Expression) -> Value {
+        let Ty::Primitive(kind) = expression.ty else {
+            return Value::Varying;
+        };
+        if kind.is_float() {
+            return Value::Varying;
+        }
+        match &expression.kind {
+            ExpressionKind::Integer(value) => Value::Constant(ConstValue::Integer(*value)),
+            ExpressionKind::Bool(value) => Value::Constant(ConstValue::Bool(*value)),
+            ExpressionKind::Name(binding) => self
+                .values
+                .get(binding.id)
+                .copied()
+                .unwrap_or(Value::Varying),
+            ExpressionKind::Binary(operator, left, right) => {
+                let (Value::Constant(left), Value::Constant(right)) =
+                    (self.evaluate(left), self.evaluate(right))
+                else {
+                    return Value::Varying;
+                };
+                let value = if operator.is_comparison() {
+                    compare(operator, left, right).map(ConstValue::Bool)
+                } else if let (ConstValue::Integer(left), ConstValue::Integer(right)) =
+                    (left, right)
+                {
+                    let range = kind.integer_range();
+                    match (arithmetic(operator, left, right), range) {
+                        (Ok(Some(value)), Some((min, max))) if (min..=max).contains(&value) => {
+                            Some(ConstValue::Integer(value))
+                        }
+                        _ => None,
+                    }
+                } else {
+                    None
+                };
+                value.map_or(Value::Varying, Value::Constant)
+            }
+            ExpressionKind::Cast(operand) => match self.evaluate(operand) {
+                Value::Constant(value) => {
+                    convert(value, kind).map_or(Value::Varying, Value::Constant)
+                }
+                Value::Varying => Value::Varying,
+            },
+            ExpressionKind::Call(..) | ExpressionKind::Builtin(..) | ExpressionKind::Error => {
+                Value::Varying
+            }
+        }
+    }
+
+    // Reports the integer divisions in executable blocks whose divisor is
+    // always zero.
+    pub fn check(&self, cfg: &Cfg, sink: &mut dyn DiagnosticSink) {
+        for id in cfg.block_ids().filter(|id| self.is_executable(*id)) {
+            for expression in roots(cfg, id) {
+                self.check_divisions(expression, sink);
+            }
+        }
+    }
+
+    fn check_divisions(&self, expression: &Expression, sink: &mut dyn DiagnosticSink) {
+        match &expression.kind {
+            ExpressionKind::Binary(operator, left, right) => {
+                let is_integer = matches!(expression.ty, Ty::Primitive(kind) if kind.is_integer());
+                if *operator == BinaryOperator::Divide
+                    && is_integer
+                    && self.evaluate(right) == Value::Constant(ConstValue::Integer(0))
+                {
+                    sink.emit(
+                        Diagnostic::warning(
+                            "W0700",
+                            "this division always divides by zero",
+                            expression.span,
+                        )
+                        .with_label(right.span, "this is always zero"),
+                    );
+                }
+                self.check_divisions(left, sink);
+                self.check_divisions(right, sink);
+            }
+            ExpressionKind::Call(callee, arguments) => {
+                self.check_divisions(callee, sink);
+                for argument in arguments {
+                    self.check_divisions(argument, sink);
+                }
+            }
+            ExpressionKind::Builtin(_, arguments) => {
+                for argument in arguments {
+                    self.check_divisions(argument, sink);
+                }
+            }
+            ExpressionKind::Cast(operand) => self.check_divisions(operand, sink),
+            _ => {}
+        }
+    }
+
+    // Rewrites a graph with what the analysis found: in executable blocks,
+    // expressions with a constant value become literals and branches on a
+    // constant condition become jumps. Blocks that cannot run are left as
+    // they are. The graph must be the one that was analyzed.
+    pub fn fold(&self, cfg: &mut Cfg) {
+        for (i, block) in cfg.blocks.iter_mut().enumerate() {
+            if !self.executable[i] {
+                continue;
+            }
+            for instruction in &mut block.instructions {
+                match instruction {
+                    Instruction::Let(let_statement) => {
+                        self.fold_expression(&mut let_statement.value)
+                    }
+                    Instruction::Expression(expression) => self.fold_expression(expression),
+                }
+            }
+            match &mut block.terminator {
+                Terminator::Branch {
+                    condition,
+                    then_block,
+                    else_block,
+                } => match self.evaluate(condition) {
+                    Value::Constant(ConstValue::Bool(true)) => {
+                        block.terminator = Terminator::Goto(*then_block)
+                    }
+                    Value::Constant(ConstValue::Bool(false)) => {
+                        block.terminator = Terminator::Goto(*else_block)
+                    }
+                    _ => self.fold_expression(condition),
+                },
+                Terminator::Return(return_statement) => {
+                    if let Some(value) = &mut return_statement.value {
+                        self.fold_expression(value);
+                    }
+                }
+                Terminator::Goto(_) | Terminator::Unreachable => {}
+            }
+        }
+    }
+
+    fn fold_expression(&self, expression: &mut Expression) {
+        if let Value::Constant(value) = self.evaluate(expression) {
+            expression.kind = match value {
+                ConstValue::Integer(value) => ExpressionKind::Integer(value),
+                ConstValue::Bool(value) => ExpressionKind::Bool(value),
+            };
+            return;
+        }
+        match &mut expression.kind {
+            ExpressionKind::Binary(_, left, right) => {
+                self.fold_expression(left);
+                self.fold_expression(right);
+            }
+            ExpressionKind::Call(callee, arguments) => {
+                self.fold_expression(callee);
+                for argument in arguments {
+                    self.fold_expression(argument);
+                }
+            }
+            ExpressionKind::Builtin(_, arguments) => {
+                for argument in arguments {
+                    self.fold_expression(argument);
+                }
+            }
+            ExpressionKind::Cast(operand) => self.fold_expression(operand),
+            _ => {}
+        }
+    }
+}
+
+// Returns the expressions a block evaluates, outermost only.
+fn roots(cfg: &Cfg, id: BlockId) -> Vec<&Expression> {
+    let block = cfg.block(id);
+    let mut roots: Vec<&Expression> = block
+        .instructions
+        .iter()
+        .map(|instruction| match instruction {
+            Instruction::Let(let_statement) => &let_statement.value,
+            Instruction::Expression(expression) => expression,
+        })
+        .collect();
+    match &block.terminator {
+        Terminator::Branch { condition, .. } => roots.push(condition),
+        Terminator::Return(return_statement) => roots.extend(&return_statement.value),
+        Terminator::Goto(_) | Terminator::Unreachable => {}
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cfg, lexer::Lexer, parser::Parser};
+
+    #[test]
+    fn constants_flow_through_copies_and_arithmetic() {
+        let source = "\
+fn f(a: int32) -> int32 {
+    let zero: int32 = 2 - 2;
+    let copy: int32 = zero;
+    if copy > 0 {
+        return a / copy;
+    }
+    let n: int32 = a / (copy * 5);
+    let small: int8 = (copy + 300) as int8;
+    return n + copy + small;
+}";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        assert!(program.typecheck().errors.is_empty());
+        let mut graph = cfg::build_all(&program.to_hir()).remove(0);
+        let constants = Constants::analyze(&graph);
+        let executable: Vec<bool> = graph
+            .block_ids()
+            .map(|id| constants.is_executable(id))
+            .collect();
+        assert_eq!(executable, [true, false, true, false, false]);
+
+        // Only the division that can run is reported.
+        let mut diagnostics = vec![];
+        constants.check(&graph, &mut diagnostics);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "W0700");
+        assert_eq!(&source[diagnostics[0].span.range()], "a / (copy * 5)");
+        assert_eq!(&source[diagnostics[0].labels[0].span.range()], "copy * 5");
+
+        constants.fold(&mut graph);
+        assert_eq!(
+            graph.to_string(),
+            "\
+bb0:
+    (let zero int32 (int32 0))
+    (let copy int32 (int32 0))
+    goto bb2
+
+bb1:
+    return (/ a:int32 copy:int32)
+
+bb2:
+    (let n int32 (/ a:int32 (int32 0)))
+    (let small int8 (int8 44))
+    return (+ (+ n:int32 (int32 0)) (int32 44))
+
+bb3:
+    goto bb2
+
+bb4:
+    unreachable
+"
+        );
+    }
+}
@@ -0,0 +1,771 @@
+use crate::{
+    analyze,
+    ast::{Span, Spanned, Statement},
+    builtin::Streams,
+    hir::{self, Binding},
+    interpreter::{Interpreter, Observer, RuntimeErrorKind, Step},
+    lexer::Lexer,
+    parser::Parser,
+    source_map::SourceMap,
+    symbol::sym,
+    value::Value,
+    visit::{self, Control, Visitor},
+};
+use serde_json::{json, Value as Json};
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    fs,
+    io::{self, BufRead, LineWriter, Write},
+    ops::ControlFlow,
+    rc::Rc,
+};
+
+// A server for the Debug Adapter Protocol, through which editors debug
+// programs run by the interpreter.
+//
+// A session launches one file, given as the `program` argument of the
+// `launch` request, and runs it once the client has sent its breakpoints
+// and `configurationDone`: its top-level code, then its `main` function if
+// it has one. The program's output is sent to the client as `output`
+// events, and it reads no input.
+//
+// The server pauses the program from the interpreter's observer, before a
+// statement runs: at a breakpoint, after a step, or at the first statement
+// if `stopOnEntry` is set. While paused it answers requests for the stack
+// frames and the variables in each, which are the locals the interpreter
+// reports: parameters and `let` bindings in scope. Stepping over a statement
+// pauses at the next one in the same call or a caller, stepping in at the
+// very next one, and stepping out at the next one in a caller. There is one
+// thread, with id 1.
+//
+// The server reads requests only while the program is paused. A program
+// the client disconnects from or terminates there is stopped by the
+// observer: after a disconnect nothing more is sent, and after a terminate
+// the `terminated` event is.
+//
+// A breakpoint is set on a line, and moves to the first line at or after it
+// where a statement starts. It stops the program at the first statement
+// that starts on its line each time the line is reached.
+
+// Serves one debugging session over `input` and `output`, returning when
+// the client disconnects or closes `input`.
+pub fn serve(input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+    let connection = Rc::new(RefCell::new(Connection::new(input, output)));
+    let mut launched = None;
+    let mut configured = false;
+    loop {
+        let Some(request) = connection.borrow_mut().read()? else {
+            return Ok(());
+        };
+        match command(&request) {
+            "launch" => {
+                let mut connection = connection.borrow_mut();
+                match Program::load(&request["arguments"]) {
+                    Ok(program) => {
+                        connection.statement_lines = program.statement_lines.clone();
+                        connection.respond(&request, json!({}))?;
+                        connection.event("initialized", json!({}))?;
+                        launched = Some(program);
+                    }
+                    Err(message) => connection.fail(&request, &message)?,
+                }
+            }
+            "configurationDone" => {
+                configured = true;
+                connection.borrow_mut().respond(&request, json!({}))?;
+            }
+            _ => connection.borrow_mut().handle(&request)?,
+        }
+        if connection.borrow().disconnected {
+            return Ok(());
+        }
+        if configured {
+            if let Some(program) = launched.take() {
+                program.run(&connection)?;
+            }
+        }
+    }
+}
+
+fn command(message: &Json) -> &str {
+    message["command"].as_str().unwrap_or("")
+}
+
+// The client, and what the session knows regardless of whether the program
+// is running.
+struct Connection<'io> {
+    input: &'io mut dyn BufRead,
+    output: &'io mut dyn Write,
+    // The sequence number of the next message sent.
+    seq: u64,
+    // Whether the client counts lines and columns from 1 rather than 0.
+    lines_start_at_1: bool,
+    columns_start_at_1: bool,
+    // The lines, counted from 1, that breakpoints are set on, and those that
+    // a statement starts on, which are where they can be set.
+    breakpoints: BTreeSet<usize>,
+    statement_lines: BTreeSet<usize>,
+    disconnected: bool,
+}
+
+impl<'io> Connection<'io> {
+    fn new(input: &'io mut dyn BufRead, output: &'io mut dyn Write) -> Connection<'io> {
+        Connection {
+            input,
+            output,
+            seq: 1,
+            lines_start_at_1: true,
+            columns_start_at_1: true,
+            breakpoints: BTreeSet::new(),
+            statement_lines: BTreeSet::new(),
+            disconnected: false,
+        }
+    }
+
+    // Reads the next message, or returns `None` at the end of the input.
+    fn read(&mut self) -> io::Result<Option<Json>> {
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim_end();
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            } else if line.is_empty() && length.is_some() {
+                break;
+            }
+        }
+        let mut body = vec![0; length.unwrap()];
+        self.input.read_exact(&mut body)?;
+        Ok(Some(serde_json::from_slice(&body)?))
+    }
+
+    fn send(&mut self, mut message: Json) -> io::Result<()> {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": true,
+            "command": command(request),
+            "body": body,
+        }))
+    }
+
+    fn fail(&mut self, request: &Json, message: &str) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": false,
+            "command": command(request),
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    // Answers the requests that do not depend on whether the program is
+    // paused.
+    fn handle(&mut self, request: &Json) -> io::Result<()> {
+        let arguments = &request["arguments"];
+        match command(request) {
+            "initialize" => {
+                self.lines_start_at_1 = arguments["linesStartAt1"].as_bool().unwrap_or(true);
+                self.columns_start_at_1 = arguments["columnsStartAt1"].as_bool().unwrap_or(true);
+                self.respond(
+                    request,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsTerminateRequest": true,
+                    }),
+                )
+            }
+            "setBreakpoints" => {
+                let lines = arguments["breakpoints"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|breakpoint| breakpoint["line"].as_u64());
+                self.breakpoints.clear();
+                let mut breakpoints = vec![];
+                for line in lines {
+                    let line = line as usize + usize::from(!self.lines_start_at_1);
+                    let breakpoint = match self.statement_lines.range(line..).next() {
+                        Some(&line) => {
+                            self.breakpoints.insert(line);
+                            json!({ "verified": true, "line": self.line(line) })
+                        }
+                        None => json!({
+                            "verified": false,
+                            "message": "no statement starts on or after this line",
+                        }),
+                    };
+                    breakpoints.push(breakpoint);
+                }
+                self.respond(request, json!({ "breakpoints": breakpoints }))
+            }
+            "threads" => self.respond(request, json!({ "threads": [{ "id": 1, "name": "main" }] })),
+            "disconnect" => {
+                self.disconnected = true;
+                self.respond(request, json!({}))
+            }
+            "stackTrace" | "scopes" | "variables" | "continue" | "next" | "stepIn" | "stepOut"
+            | "terminate" => self.fail(request, "the program is not paused"),
+            command => self.fail(request, &format!("unsupported request `{}`", command)),
+        }
+    }
+
+    // Converts a line counted from 1 to the client's numbering.
+    fn line(&self, line: usize) -> usize {
+        line - usize::from(!self.lines_start_at_1)
+    }
+
+    fn column(&self, column: usize) -> usize {
+        column - usize::from(!self.columns_start_at_1)
+    }
+}
+
+// A program that has been launched, checked and lowered, ready to run.
+struct Program {
+    path: String,
+    map: SourceMap,
+    hir: hir::Program,
+    stop_on_entry: bool,
+    statement_lines: BTreeSet<usize>,
+}
+
+impl Program {
+    // Loads the program named by the arguments of a `launch` request, or
+    // returns why it cannot be run.
+    fn load(arguments: &Json) -> Result<Program, String> {
+        let Some(path) = arguments["program"].as_str() else {
+            return Err("`launch` needs a `program` to debug".to_string());
+        };
+        let source = fs::read_to_string(path)
+            .map_err(|error| format!("cannot read `{}`: {}", path, error))?;
+        let map = SourceMap::new(path, source);
+        let tokens = Lexer::tokenize(map.source());
+        let program = Parser::parse_program(&tokens).map_err(|error| error.render(&map))?;
+        let mut result = analyze::check_program(&program, &map);
+        let hir = match result.hir.take() {
+            Some(hir) if !result.has_errors() => hir,
+            _ => return Err(result.render()),
+        };
+        let mut lines = StatementLines {
+            map: &map,
+            lines: BTreeSet::new(),
+        };
+        visit::walk_program(&mut lines, &program);
+        let statement_lines = lines.lines;
+        Ok(Program {
+            path: path.to_string(),
+            hir,
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+            statement_lines,
+            map,
+        })
+    }
+
+    // Runs the program under the debugger, then reports how it exited.
+    fn run(&self, connection: &Rc<RefCell<Connection>>) -> io::Result<()> {
+        let has_main = self.hir.statements.iter().any(|statement| {
+            matches!(statement, hir::Statement::Function(function) if function.binding.name == sym::MAIN)
+        });
+        let result = {
+            let mut interpreter = Interpreter::new(&self.hir);
+            let output = LineWriter::new(Output(connection.clone()));
+            interpreter.redirect(Streams::new(io::empty(), output));
+            interpreter.observe(Debugger {
+                connection: connection.clone(),
+                program: self,
+                mode: match self.stop_on_entry {
+                    true => Mode::Entry,
+                    false => Mode::Continue,
+                },
+                frames: vec![],
+                previous: None,
+                terminated: false,
+            });
+            interpreter.run().and_then(|()| match has_main {
+                true => interpreter.call("main", vec![]),
+                false => Ok(None),
+            })
+        };
+        let mut connection = connection.borrow_mut();
+        if connection.disconnected {
+            return Ok(());
+        }
+        let status = match result {
+            Ok(Some(Value::Integer(_, status))) => status as i32,
+            Ok(_) => 0,
+            Err(error) if error.kind == RuntimeErrorKind::Stopped => {
+                return connection.event("terminated", json!({}));
+            }
+            Err(error) => {
+                let message = error.to_diagnostic(&self.map).render(&self.map);
+                connection.event("output", json!({ "category": "stderr", "output": message }))?;
+                1
+            }
+        };
+        connection.event("exited", json!({ "exitCode": status }))?;
+        connection.event("terminated", json!({}))
+    }
+}
+
+// Collects the lines statements start on.
+struct StatementLines<'m> {
+    map: &'m SourceMap,
+    lines: BTreeSet<usize>,
+}
+
+impl<'ast> Visitor<'ast> for StatementLines<'_> {
+    fn visit_statement(&mut self, statement: &'ast Statement<'ast>) -> Control {
+        self.lines
+            .insert(self.map.location(statement.span().start).0);
+        Control::Continue
+    }
+}
+
+// Sends what the program writes to the client, as `output` events, until
+// the client disconnects.
+struct Output<'io>(Rc<RefCell<Connection<'io>>>);
+
+impl Write for Output<'_> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let mut connection = self.0.borrow_mut();
+        if !connection.disconnected {
+            let output = String::from_utf8_lossy(bytes);
+            connection.event("output", json!({ "category": "stdout", "output": output }))?;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// When the program pauses next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    // At the first statement.
+    Entry,
+    // Only at breakpoints.
+    Continue,
+    // At the next statement.
+    StepIn,
+    // At the next statement in a call at most this deep.
+    Next(usize),
+    // At the next statement in a call less deep than this.
+    StepOut(usize),
+}
+
+// A call in progress, as of the last statement the program paused at or
+// passed in it.
+#[derive(Debug, Clone)]
+struct Frame {
+    name: String,
+    span: Span,
+    locals: Vec<(Binding, Value)>,
+}
+
+// The observer that pauses the program and answers the client while it is
+// paused.
+struct Debugger<'p, 'io> {
+    connection: Rc<RefCell<Connection<'io>>>,
+    program: &'p Program,
+    mode: Mode,
+    // The calls in progress, outermost first.
+    frames: Vec<Frame>,
+    // The line, call depth and start of the previous statement.
+    previous: Option<(usize, usize, usize)>,
+    terminated: bool,
+}
+
+impl Observer for Debugger<'_, '_> {
+    fn step(&mut self, step: &Step) -> ControlFlow<()> {
+        if self.stopped() {
+            return ControlFlow::Break(());
+        }
+        let frame = Frame {
+            name: step
+                .function
+                .clone()
+                .map_or("<top level>".to_string(), |name| name.to_string()),
+            span: step.span,
+            locals: step.locals.to_vec(),
+        };
+        self.frames.truncate(step.depth);
+        while self.frames.len() < step.depth {
+            self.frames.push(frame.clone());
+        }
+        self.frames[step.depth - 1] = frame;
+
+        let line = self.program.map.location(step.span.start).0;
+        let reason = match self.mode {
+            Mode::Entry => Some("entry"),
+            Mode::StepIn => Some("step"),
+            Mode::Next(depth) if step.depth <= depth => Some("step"),
+            Mode::StepOut(depth) if step.depth < depth => Some("step"),
+            _ => None,
+        };
+        // A statement after another on the same line in the same call is
+        // not where the line's breakpoint stops.
+        let same_line = self.previous.is_some_and(|(previous, depth, start)| {
+            previous == line && depth == step.depth && start < step.span.start
+        });
+        let breakpoint = self.connection.borrow().breakpoints.contains(&line) && !same_line;
+        self.previous = Some((line, step.depth, step.span.start));
+        if let Some(reason) = reason.or(breakpoint.then_some("breakpoint")) {
+            if self.pause(reason, step.depth).is_err() {
+                self.connection.borrow_mut().disconnected = true;
+            }
+        }
+        match self.stopped() {
+            true => ControlFlow::Break(()),
+            false => ControlFlow::Continue(()),
+        }
+    }
+}
+
+impl Debugger<'_, '_> {
+    // Whether the client has disconnected or asked for the program to be
+    // terminated.
+    fn stopped(&self) -> bool {
+        self.terminated || self.connection.borrow().disconnected
+    }
+
+    // Tells the client the program has paused, then answers its requests
+    // until it resumes the program or disconnects.
+    fn pause(&mut self, reason: &str, depth: usize) -> io::Result<()> {
+        let shared = self.connection.clone();
+        let mut connection = shared.borrow_mut();
+        connection.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": 1, "allThreadsStopped": true }),
+        )?;
+        loop {
+            let Some(request) = connection.read()? else {
+                connection.disconnected = true;
+                return Ok(());
+            };
+            let arguments = &request["arguments"];
+            let mode = match command(&request) {
+                "continue" => Mode::Continue,
+                "next" => Mode::Next(depth),
+                "stepIn" => Mode::StepIn,
+                "stepOut" => Mode::StepOut(depth),
+                "terminate" => {
+                    self.terminated = true;
+                    return connection.respond(&request, json!({}));
+                }
+                "stackTrace" => {
+                    let frames: Vec<Json> = (0..self.frames.len())
+                        .rev()
+                        .map(|index| self.frame(&connection, index))
+                        .collect();
+                    let total = frames.len();
+                    connection.respond(
+                        &request,
+                        json!({ "stackFrames": frames, "totalFrames": total }),
+                    )?;
+                    continue;
+                }
+                "scopes" => {
+                    let reference = arguments["frameId"].as_u64().unwrap_or(0);
+                    connection.respond(
+                        &request,
+                        json!({ "scopes": [{
+                            "name": "Locals",
+                            "presentationHint": "locals",
+                            "variablesReference": reference,
+                            "expensive": false,
+                        }] }),
+                    )?;
+                    continue;
+                }
+                "variables" => {
+                    let reference = arguments["variablesReference"].as_u64().unwrap_or(0);
+                    let variables = match self.frames.get((reference as usize).wrapping_sub(1)) {
+                        Some(frame) => frame.locals.iter().map(variable).collect(),
+                        None => vec![],
+                    };
+                    connection.respond(&request, json!({ "variables": variables }))?;
+                    continue;
+                }
+                _ => {
+                    connection.handle(&request)?;
+                    if connection.disconnected {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+            self.mode = mode;
+            return connection.respond(&request, json!({ "allThreadsContinued": true }));
+        }
+    }
+
+    // Describes the frame of the call at a depth, counted from 0, whose id
+    // is one more than its depth.
+    fn frame(&self, connection: &Connection, index: usize) -> Json {
+        let frame = &self.frames[index];
+        let (line, column) = self.program.map.location(frame.span.start);
+        json!({
+            "id": index + 1,
+            "name": frame.name,
+            "line": connection.line(line),
+            "column": connection.column(column),
+            "source": { "name": self.program.path, "path": self.program.path },
+        })
+    }
+}
+
+fn variable((binding, value): &(Binding, Value)) -> Json {
+    let text = match value {
+        Value::String(text) => format!("{:?}", text),
+        value => value.to_string(),
+    };
+    json!({
+        "name": binding.name.to_string(),
+        "value": text,
+        "type": value.type_name(),
+        "variablesReference": 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+fn square(x: int64) -> int64 {
+    let y: int64 = x * x;
+    return y;
+}
+
+fn main() -> int64 {
+    let a: int64 = 3;
+    let b: int64 = square(a);
+    println(b);
+    return b;
+}
+";
+
+    // Serves a session that sends `requests`, each a command and its
+    // arguments, and returns a line describing each message sent back.
+    fn session(requests: &[(&str, Json)]) -> Vec<String> {
+        let mut input = vec![];
+        for (seq, (command, arguments)) in requests.iter().enumerate() {
+            let request = json!({
+                "seq": seq + 1,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            })
+            .to_string();
+            write!(
+                input,
+                "Content-Length: {}\r\n\r\n{}",
+                request.len(),
+                request
+            )
+            .unwrap();
+        }
+        let mut output = vec![];
+        serve(&mut input.as_slice(), &mut output).unwrap();
+
+        let (mut output, mut sink) = (output.as_slice(), io::sink());
+        let mut connection = Connection::new(&mut output, &mut sink);
+        let mut messages = vec![];
+        while let Some(message) = connection.read().unwrap() {
+            messages.push(describe(&message));
+        }
+        messages
+    }
+
+    fn describe(message: &Json) -> String {
+        let body = &message["body"];
+        if message["type"] == "event" {
+            let detail = match message["event"].as_str().unwrap() {
+                "stopped" => body["reason"].to_string(),
+                "output" => body["output"].to_string(),
+                "exited" => body["exitCode"].to_string(),
+                _ => String::new(),
+            };
+            return format!("event {} {}", message["event"].as_str().unwrap(), detail);
+        }
+        if message["success"] == false {
+            return format!("{} failed: {}", command(message), message["message"]);
+        }
+        let items = |key: &str, f: &dyn Fn(&Json) -> String| -> String {
+            let items: Vec<String> = body[key].as_array().unwrap().iter().map(f).collect();
+            items.join(" ")
+        };
+        let detail = match command(message) {
+            "stackTrace" => items("stackFrames", &|frame| {
+                format!("{}:{}", frame["name"].as_str().unwrap(), frame["line"])
+            }),
+            "variables" => items("variables", &|variable| {
+                format!(
+                    "{}={}",
+                    variable["name"].as_str().unwrap(),
+                    variable["value"].as_str().unwrap()
+                )
+            }),
+            "setBreakpoints" => items("breakpoints", &|breakpoint| match breakpoint["line"] {
+                Json::Null => "unverified".to_string(),
+                ref line => line.to_string(),
+            }),
+            _ => String::new(),
+        };
+        format!("{} {}", command(message), detail)
+            .trim_end()
+            .to_string()
+    }
+
+    fn program(name: &str, source: &str) -> String {
+        let path = std::env::temp_dir().join(format!("mylang-dap-{}-{}", std::process::id(), name));
+        fs::write(&path, source).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn breakpoints_and_steps_pause_the_program() {
+        let path = program("steps.my2", SOURCE);
+        let messages = session(&[
+            ("initialize", json!({ "adapterID": "mylang" })),
+            ("launch", json!({ "program": path })),
+            (
+                "setBreakpoints",
+                json!({ "breakpoints": [{ "line": 8 }, { "line": 40 }] }),
+            ),
+            ("configurationDone", json!({})),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("variables", json!({ "variablesReference": 1 })),
+            ("stepIn", json!({ "threadId": 1 })),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("next", json!({ "threadId": 1 })),
+            ("scopes", json!({ "frameId": 2 })),
+            ("variables", json!({ "variablesReference": 2 })),
+            ("stepOut", json!({ "threadId": 1 })),
+            ("threads", json!({})),
+            ("variables", json!({ "variablesReference": 1 })),
+            ("continue", json!({ "threadId": 1 })),
+            ("disconnect", json!({})),
+        ]);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            messages,
+            [
+                "initialize",
+                "launch",
+                "event initialized ",
+                "setBreakpoints 8 unverified",
+                "configurationDone",
+                "event stopped \"breakpoint\"",
+                "stackTrace main:8",
+                "variables a=3",
+                "stepIn",
+                "event stopped \"step\"",
+                "stackTrace square:2 main:8",
+                "next",
+                "event stopped \"step\"",
+                "scopes",
+                "variables x=3 y=9",
+                "stepOut",
+                "event stopped \"step\"",
+                "threads",
+                "variables a=3 b=9",
+                "continue",
+                "event output \"9\\n\"",
+                "event exited 9",
+                "event terminated ",
+                "disconnect",
+            ]
+        );
+    }
+
+    #[test]
+    fn endless_programs_stop_when_the_client_leaves() {
+        let path = program("endless.my2", "while 1 > 0 {\n    0;\n}\n");
+        let paused = [
+            ("launch", json!({ "program": path })),
+            ("setBreakpoints", json!({ "breakpoints": [{ "line": 2 }] })),
+            ("configurationDone", json!({})),
+        ];
+        let messages = session(&[&paused[..], &[("terminate", json!({}))]].concat());
+        assert_eq!(
+            messages[4..],
+            [
+                "event stopped \"breakpoint\"",
+                "terminate",
+                "event terminated "
+            ]
+        );
+        let messages = session(&[&paused[..], &[("disconnect", json!({}))]].concat());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            messages[4..],
+            ["event stopped \"breakpoint\"", "disconnect"]
+        );
+    }
+
+    #[test]
+    fn sessions_start_and_end_cleanly() {
+        let path = program("entry.my2", SOURCE);
+        let messages = session(&[
+            ("initialize", json!({ "linesStartAt1": false })),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("launch", json!({ "program": path, "stopOnEntry": true })),
+            // Line 3 counted from 0 is the blank line before `fn main`.
+            ("setBreakpoints", json!({ "breakpoints": [{ "line": 4 }] })),
+            ("configurationDone", json!({})),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("evaluate", json!({ "expression": "a" })),
+            ("disconnect", json!({})),
+        ]);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            messages,
+            [
+                "initialize",
+                "stackTrace failed: \"the program is not paused\"",
+                "launch",
+                "event initialized ",
+                "setBreakpoints 5",
+                "configurationDone",
+                "event stopped \"entry\"",
+                "stackTrace <top level>:0",
+                "evaluate failed: \"unsupported request `evaluate`\"",
+                "disconnect",
+            ]
+        );
+
+        let path = program("broken.my2", "let _x: int64 = y;");
+        let messages = session(&[("launch", json!({ "program": path }))]);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(
+            messages[0].starts_with("launch failed: \"error[E0200]"),
+            "{}",
+            messages[0]
+        );
+    }
+}
@@ -0,0 +1,571 @@
+use crate::{
+    ast::{Program, Span, Spanned, Statement},
+    lexer::Lexer,
+    parser::{Parser, ParserError},
+    resolver::{resolve, Resolution},
+    symbol::{Interner, Symbol},
+    token::{Kind, Token},
+    typecheck::{typecheck, TypeError},
+};
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+// A database of memoized queries over source files, so that editors and
+// watch modes recompute only what an edit invalidates.
+//
+// The source of each file is an input, set with `set_source`. Every other
+// query is a function of the inputs and of other queries: `tokens`, `ast`,
+// `resolved`, `typechecked` for one unit of a file and `type_errors` for a
+// whole file. Each query remembers its value, the queries it read and the
+// revision it was last verified in. Setting a source starts a new revision;
+// asking for a query then checks the queries it read, recomputing them first
+// if they are out of date, and recomputes it only if one of them changed.
+// When a recomputed value equals the old one, it counts as unchanged, so the
+// queries that read it are not recomputed either.
+//
+// Type checking is split into units: the code at the top level of a file,
+// and each of its functions. A unit is checked on its own, as an item made
+// of the unit's text and the declarations it can see: the signatures of the
+// top-level functions, and the top-level bindings and constants. An item
+// does not depend on where the unit is in the file, so an edit elsewhere
+// that leaves the declarations alone, or that only moves the unit, does not
+// check it again.
+//
+// Unlike `incremental::ParsedDocument`, which reuses the statements around a
+// known edit, the database only compares sources and values, so it works
+// with whatever changed between two revisions.
+//
+// Values are shared as `Arc`s, so the values a query returns, and the
+// database itself, can be sent to other threads.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Revision(u64);
+
+// A token of a file, without its text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lexeme {
+    pub kind: Kind,
+    pub span: Span,
+}
+
+// A part of a file that is type checked on its own.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Unit {
+    // The statements outside of functions.
+    TopLevel,
+    // The top-level functions with a name, of which there is one unless the
+    // name is declared twice.
+    Function(Symbol),
+}
+
+// The source a unit is checked in. `targets` are the ranges of `text` that
+// belong to the unit, in the order of its statements in the file; the rest
+// declares what it can see.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Item {
+    pub text: String,
+    pub targets: Vec<Range<usize>>,
+}
+
+// A query and its arguments, as `take_recomputed` reports them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Query {
+    Source(Arc<str>),
+    Tokens(Arc<str>),
+    Ast(Arc<str>),
+    Resolved(Arc<str>),
+    Item(Arc<str>, Unit),
+    Typechecked(Arc<str>, Unit),
+    TypeErrors(Arc<str>),
+}
+
+type Ast = Arc<Result<Program<'static>, ParserError>>;
+
+#[derive(Debug, Clone)]
+enum Value {
+    Tokens(Arc<[Lexeme]>),
+    Ast(Ast),
+    Resolved(Arc<Resolution>),
+    Item(Arc<Item>),
+    Errors(Arc<Vec<TypeError>>),
+}
+
+impl Value {
+    // Whether a recomputed value is the same as the old one. Syntax trees
+    // and resolutions are not compared: they change with almost any edit,
+    // and the items built from them are compared instead.
+    fn same(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Tokens(a), Value::Tokens(b)) => a == b,
+            (Value::Item(a), Value::Item(b)) => a == b,
+            (Value::Errors(a), Value::Errors(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Input {
+    text: Arc<str>,
+    changed_at: Revision,
+}
+
+#[derive(Debug)]
+struct Memo {
+    value: Value,
+    // The revision the value last changed in, and the last one it was
+    // known to be up to date in.
+    changed_at: Revision,
+    verified_at: Revision,
+    // The queries read to compute the value, in the order they were read.
+    dependencies: Vec<Query>,
+}
+
+#[derive(Debug, Default)]
+pub struct Database {
+    revision: Revision,
+    files: HashMap<Arc<str>, Input>,
+    memos: HashMap<Query, Memo>,
+    // The queries read so far by each query being computed, innermost last.
+    active: Vec<Vec<Query>>,
+    recomputed: Vec<Query>,
+    // Interns the names of every file's syntax tree. Names that no memo
+    // holds any more are forgotten when a source changes.
+    interner: Interner,
+}
+
+impl Database {
+    pub fn new() -> Database {
+        Database::default()
+    }
+
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    // Sets the source of a file, starting a new revision unless it is the
+    // same as before.
+    pub fn set_source(&mut self, file: &str, text: &str) {
+        if self
+            .files
+            .get(file)
+            .is_some_and(|input| &*input.text == text)
+        {
+            return;
+        }
+        self.revision = Revision(self.revision.0 + 1);
+        self.interner.forget_unused();
+        let input = Input {
+            text: text.into(),
+            changed_at: self.revision,
+        };
+        self.files.insert(file.into(), input);
+    }
+
+    // Returns the source of a file. A file whose source was never set is
+    // empty.
+    pub fn source(&mut self, file: &str) -> Arc<str> {
+        let file: Arc<str> = file.into();
+        let text = match self.files.get(&file) {
+            Some(input) => input.text.clone(),
+            None => "".into(),
+        };
+        self.read(Query::Source(file));
+        text
+    }
+
+    pub fn tokens(&mut self, file: &str) -> Arc<[Lexeme]> {
+        match self.fetch(Query::Tokens(file.into())) {
+            Value::Tokens(tokens) => tokens,
+            value => unreachable!("tokens query returned {value:?}"),
+        }
+    }
+
+    // Returns the syntax tree of a file, or why it does not parse.
+    pub fn ast(&mut self, file: &str) -> Ast {
+        match self.fetch(Query::Ast(file.into())) {
+            Value::Ast(ast) => ast,
+            value => unreachable!("ast query returned {value:?}"),
+        }
+    }
+
+    // Returns the name resolution of a file. A file that does not parse has
+    // nothing resolved.
+    pub fn resolved(&mut self, file: &str) -> Arc<Resolution> {
+        match self.fetch(Query::Resolved(file.into())) {
+            Value::Resolved(resolution) => resolution,
+            value => unreachable!("resolved query returned {value:?}"),
+        }
+    }
+
+    // Returns the item a unit of a file is checked in. It is empty if the
+    // file does not parse.
+    pub fn item(&mut self, file: &str, unit: &Unit) -> Arc<Item> {
+        match self.fetch(Query::Item(file.into(), unit.clone())) {
+            Value::Item(item) => item,
+            value => unreachable!("item query returned {value:?}"),
+        }
+    }
+
+    // Returns the type errors of a unit of a file, with spans in its item.
+    pub fn typechecked(&mut self, file: &str, unit: &Unit) -> Arc<Vec<TypeError>> {
+        match self.fetch(Query::Typechecked(file.into(), unit.clone())) {
+            Value::Errors(errors) => errors,
+            value => unreachable!("typechecked query returned {value:?}"),
+        }
+    }
+
+    // Returns the type errors of a whole file in source order, as `typecheck`
+    // finds them. A file that does not parse has none.
+    pub fn type_errors(&mut self, file: &str) -> Arc<Vec<TypeError>> {
+        match self.fetch(Query::TypeErrors(file.into())) {
+            Value::Errors(errors) => errors,
+            value => unreachable!("type errors query returned {value:?}"),
+        }
+    }
+
+    // Returns the queries computed since the last call, in the order they
+    // finished. Queries whose memoized value was reused are not included.
+    pub fn take_recomputed(&mut self) -> Vec<Query> {
+        std::mem::take(&mut self.recomputed)
+    }
+
+    // Records that the query being computed, if any, read another.
+    fn read(&mut self, query: Query) {
+        if let Some(dependencies) = self.active.last_mut() {
+            dependencies.push(query);
+        }
+    }
+
+    fn fetch(&mut self, query: Query) -> Value {
+        self.refresh(&query);
+        let value = self.memos[&query].value.clone();
+        self.read(query);
+        value
+    }
+
+    // Brings a query up to date with the current revision, and returns the
+    // revision its value last changed in.
+    fn refresh(&mut self, query: &Query) -> Revision {
+        if let Query::Source(file) = query {
+            return self
+                .files
+                .get(file)
+                .map_or(Revision(0), |input| input.changed_at);
+        }
+        if let Some(memo) = self.memos.get(query) {
+            if memo.verified_at == self.revision {
+                return memo.changed_at;
+            }
+            let verified_at = memo.verified_at;
+            let dependencies = memo.dependencies.clone();
+            // A dependency that changed may make the query read different
+            // ones, so those after it are not brought up to date.
+            if dependencies
+                .iter()
+                .all(|dependency| self.refresh(dependency) <= verified_at)
+            {
+                let memo = self.memos.get_mut(query).unwrap();
+                memo.verified_at = self.revision;
+                return memo.changed_at;
+            }
+        }
+        self.compute(query)
+    }
+
+    fn compute(&mut self, query: &Query) -> Revision {
+        self.active.push(vec![]);
+        let value = match query {
+            Query::Source(_) => unreachable!("sources are inputs"),
+            Query::Tokens(file) => Value::Tokens(self.compute_tokens(file)),
+            Query::Ast(file) => Value::Ast(self.compute_ast(file)),
+            Query::Resolved(file) => Value::Resolved(self.compute_resolved(file)),
+            Query::Item(file, unit) => Value::Item(self.compute_item(file, unit)),
+            Query::Typechecked(file, unit) => Value::Errors(self.compute_typechecked(file, unit)),
+            Query::TypeErrors(file) => Value::Errors(self.compute_type_errors(file)),
+        };
+        let dependencies = self.active.pop().unwrap();
+        let changed_at = match self.memos.get(query) {
+            Some(old) if old.value.same(&value) => old.changed_at,
+            _ => self.revision,
+        };
+        let memo = Memo {
+            value,
+            changed_at,
+            verified_at: self.revision,
+            dependencies,
+        };
+        self.memos.insert(query.clone(), memo);
+        self.recomputed.push(query.clone());
+        changed_at
+    }
+
+    fn compute_tokens(&mut self, file: &str) -> Arc<[Lexeme]> {
+        let source = self.source(file);
+        Lexer::tokenize(&source)
+            .iter()
+            .map(|token| Lexeme {
+                kind: token.kind(),
+                span: Span::new(token.offset(), token.offset() + token.len()),
+            })
+            .collect()
+    }
+
+    fn compute_ast(&mut self, file: &str) -> Ast {
+        let source = self.source(file);
+        let tokens: Vec<Token> = self
+            .tokens(file)
+            .iter()
+            .map(|lexeme| {
+                let span = lexeme.span;
+                Token::new(source.as_bytes(), span.start, span.len(), lexeme.kind)
+            })
+            .collect();
+        let parsed = Parser::parse_stream_in(tokens.as_slice(), &mut self.interner);
+        Arc::new(parsed.map(Program::into_owned))
+    }
+
+    fn compute_resolved(&mut self, file: &str) -> Arc<Resolution> {
+        match &*self.ast(file) {
+            Ok(program) => Arc::new(resolve(program)),
+            Err(_) => Arc::default(),
+        }
+    }
+
+    fn compute_item(&mut self, file: &str, unit: &Unit) -> Arc<Item> {
+        let source = self.source(file);
+        match &*self.ast(file) {
+            Ok(program) => Arc::new(build_item(&source, program, unit)),
+            Err(_) => Arc::default(),
+        }
+    }
+
+    fn compute_typechecked(&mut self, file: &str, unit: &Unit) -> Arc<Vec<TypeError>> {
+        let item = self.item(file, unit);
+        let tokens = Lexer::tokenize(&item.text);
+        // Items are made of whole statements of a file that parsed.
+        let Ok(program) = Parser::parse_stream_in(tokens.as_slice(), &mut self.interner) else {
+            return Arc::default();
+        };
+        let check = typecheck(&program, &resolve(&program));
+        let errors = check
+            .errors
+            .into_iter()
+            .filter(|error| {
+                item.targets
+                    .iter()
+                    .any(|target| target.contains(&error.span.start))
+            })
+            .collect();
+        Arc::new(errors)
+    }
+
+    fn compute_type_errors(&mut self, file: &str) -> Arc<Vec<TypeError>> {
+        let ast = self.ast(file);
+        let Ok(program) = &*ast else {
+            return Arc::default();
+        };
+        let mut errors = vec![];
+        for unit in units(program) {
+            let item = self.item(file, &unit);
+            let statements = unit_statements(program, &unit);
+            for error in self.typechecked(file, &unit).iter() {
+                // Moves the error from the item to the statement it is in.
+                let (target, statement) = item
+                    .targets
+                    .iter()
+                    .zip(&statements)
+                    .find(|(target, _)| target.contains(&error.span.start))
+                    .unwrap();
+                let start = statement.span().start + error.span.start - target.start;
+                errors.push(TypeError {
+                    kind: error.kind.clone(),
+                    span: Span::new(start, start + error.span.len()),
+                });
+            }
+        }
+        errors.sort_by_key(|error| error.span.start);
+        Arc::new(errors)
+    }
+}
+
+// The units of a program: its top level, then its functions in the order
+// they are first declared in.
+fn units(program: &Program) -> Vec<Unit> {
+    let mut units = vec![Unit::TopLevel];
+    for statement in &program.statements {
+        if let Statement::FunctionDeclaration(function) = statement {
+            let unit = Unit::Function(function.identifier.name.clone());
+            if !units.contains(&unit) {
+                units.push(unit);
+            }
+        }
+    }
+    units
+}
+
+fn belongs_to(statement: &Statement, unit: &Unit) -> bool {
+    match (statement, unit) {
+        (Statement::FunctionDeclaration(function), Unit::Function(name)) => {
+            function.identifier.name == *name
+        }
+        (Statement::FunctionDeclaration(_), Unit::TopLevel) => false,
+        (_, unit) => *unit == Unit::TopLevel,
+    }
+}
+
+fn unit_statements<'p>(program: &'p Program<'p>, unit: &Unit) -> Vec<&'p Statement<'p>> {
+    program
+        .statements
+        .iter()
+        .filter(|statement| belongs_to(statement, unit))
+        .collect()
+}
+
+// Builds the item of a unit from the file it is in: the unit's statements
+// and the declarations around them, in the order of the file, each on a
+// line of its own. Functions are declared by their signatures alone.
+fn build_item(source: &str, program: &Program, unit: &Unit) -> Item {
+    let mut item = Item::default();
+    for statement in &program.statements {
+        let span = statement.span();
+        let text = if belongs_to(statement, unit) {
+            let start = item.text.len();
+            item.targets.push(start..start + span.len());
+            &source[span.range()]
+        } else {
+            match statement {
+                Statement::FunctionDeclaration(function) => match &function.body {
+                    Some(body) => {
+                        item.text += source[span.start..body.span.start].trim_end();
+                        ";"
+                    }
+                    None => &source[span.range()],
+                },
+                Statement::Let(_) | Statement::Const(_) => &source[span.range()],
+                _ => continue,
+            }
+        };
+        item.text += text;
+        // Expression statements end before their semicolon.
+        if !text.ends_with([';', '}']) {
+            item.text.push(';');
+        }
+        item.text.push('\n');
+    }
+    item
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+const N: int32 = 4;
+fn f(x: int32) -> int8 {
+    let y: int8 = x;
+    return y;
+}
+let a: bool = f(N);
+fn g(n: int64) -> int64 {
+    return n * 2;
+}
+println(g(N > 1));
+";
+
+    fn full_check(source: &str) -> Vec<TypeError> {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        typecheck(&program, &resolve(&program)).errors
+    }
+
+    fn checked_units(recomputed: Vec<Query>) -> Vec<Unit> {
+        recomputed
+            .into_iter()
+            .filter_map(|query| match query {
+                Query::Typechecked(_, unit) => Some(unit),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn units_find_the_errors_of_a_full_check() {
+        let mut database = Database::new();
+        database.set_source("main", SOURCE);
+        let errors = database.type_errors("main");
+        assert_eq!(errors.len(), 3);
+        assert_eq!(*errors, full_check(SOURCE));
+        let item = database.item("main", &Unit::Function(Symbol::new("g")));
+        assert_eq!(
+            item.text,
+            "\
+const N: int32 = 4;
+fn f(x: int32) -> int8;
+let a: bool = f(N);
+fn g(n: int64) -> int64 {
+    return n * 2;
+}
+"
+        );
+        assert_eq!(item.targets.len(), 1);
+        assert!(item.text[item.targets[0].clone()].starts_with("fn g(n: int64)"));
+        assert_eq!(database.resolved("main").errors, []);
+    }
+
+    #[test]
+    fn edits_recompute_only_the_units_they_change() {
+        let mut database = Database::new();
+        database.set_source("main", SOURCE);
+        database.type_errors("main");
+        let f = Unit::Function(Symbol::new("f"));
+        let g = Unit::Function(Symbol::new("g"));
+        assert_eq!(
+            checked_units(database.take_recomputed()),
+            [Unit::TopLevel, f.clone(), g.clone()]
+        );
+
+        // Changing the body of `g` checks `g` alone.
+        let source = SOURCE.replace("n * 2", "n * 3");
+        database.set_source("main", &source);
+        assert_eq!(*database.type_errors("main"), full_check(&source));
+        assert_eq!(
+            checked_units(database.take_recomputed()),
+            [Unit::Function(Symbol::new("g"))]
+        );
+
+        // Moving every unit checks none of them, but moves their errors.
+        let source = format!("\n\n{source}");
+        database.set_source("main", &source);
+        assert_eq!(*database.type_errors("main"), full_check(&source));
+        assert_eq!(checked_units(database.take_recomputed()), []);
+
+        // Changing a signature checks the units that can see it.
+        let source = source.replace("-> int8", "-> bool");
+        database.set_source("main", &source);
+        assert_eq!(*database.type_errors("main"), full_check(&source));
+        assert_eq!(
+            checked_units(database.take_recomputed()),
+            [Unit::TopLevel, f, g]
+        );
+    }
+
+    #[test]
+    fn unchanged_queries_are_not_recomputed() {
+        let mut database = Database::new();
+        database.set_source("main", SOURCE);
+        database.type_errors("main");
+        database.take_recomputed();
+        let revision = database.revision();
+        database.set_source("main", SOURCE);
+        assert_eq!(database.revision(), revision);
+        database.type_errors("main");
+        assert_eq!(database.take_recomputed(), []);
+
+        database.set_source("main", "fn f( {");
+        assert!(database.ast("main").is_err());
+        assert_eq!(*database.type_errors("main"), []);
+        assert_eq!(database.tokens("other").len(), 1);
+        assert!(database.source("other").is_empty());
+    }
+}
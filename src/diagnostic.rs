@@ -0,0 +1,451 @@
+use crate::{ast::Span, fold::Shift, source_map::SourceMap};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+// Diagnostics are the errors and warnings reported by every stage of the
+// compiler. Each has a code that identifies what kind of problem it is, so
+// that tools and tests can match on it without parsing the message:
+//
+// - E00xx: lexing
+// - E01xx: parsing
+// - E02xx and W02xx: name resolution
+// - E03xx and W03xx: type checking
+// - E04xx: constant evaluation
+// - E05xx: call graph checks
+// - W06xx: lint attributes
+// - W07xx: data-flow analysis
+// - E08xx: running programs
+// - E09xx: compiling programs with a backend
+// - W10xx: lint rules
+// - E11xx: loading modules
+//
+// Warnings that belong to a lint can be allowed or turned into errors; see
+// `lint::LintLevels`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+// A secondary location that helps explain a diagnostic, such as the opening
+// delimiter of an unclosed block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+// A change that would fix the problem: replacing the text at `span` with
+// `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Suggestion {
+    pub message: String,
+    pub span: Span,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    // Where the problem is.
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+// Codes are few, so each distinct code deserialized is leaked once to live
+// as long as the ones in the compiler's source.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Diagnostic {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Diagnostic, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            severity: Severity,
+            code: String,
+            message: String,
+            span: Span,
+            labels: Vec<Label>,
+            notes: Vec<String>,
+            suggestions: Vec<Suggestion>,
+        }
+        let fields = Fields::deserialize(deserializer)?;
+        Ok(Diagnostic {
+            severity: fields.severity,
+            code: static_code(&fields.code),
+            message: fields.message,
+            span: fields.span,
+            labels: fields.labels,
+            notes: fields.notes,
+            suggestions: fields.suggestions,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+fn static_code(code: &str) -> &'static str {
+    use std::{collections::HashSet, sync::Mutex};
+    static CODES: Mutex<Option<HashSet<&'static str>>> = Mutex::new(None);
+    let mut codes = CODES.lock().unwrap();
+    let codes = codes.get_or_insert_with(HashSet::new);
+    match codes.get(code) {
+        Some(code) => code,
+        None => {
+            let code: &'static str = Box::leak(code.into());
+            codes.insert(code);
+            code
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn new(
+        severity: Severity,
+        code: &'static str,
+        message: impl Into<String>,
+        span: Span,
+    ) -> Diagnostic {
+        Diagnostic {
+            severity,
+            code,
+            message: message.into(),
+            span,
+            labels: vec![],
+            notes: vec![],
+            suggestions: vec![],
+        }
+    }
+
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic::new(Severity::Error, code, message, span)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, code, message, span)
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_suggestion(
+        mut self,
+        message: impl Into<String>,
+        span: Span,
+        replacement: impl Into<String>,
+    ) -> Diagnostic {
+        self.suggestions.push(Suggestion {
+            message: message.into(),
+            span,
+            replacement: replacement.into(),
+        });
+        self
+    }
+
+    // Moves every span of the diagnostic by `delta` bytes, as `fold::Shift`
+    // moves those of nodes.
+    pub fn shifted(self, delta: isize) -> Diagnostic {
+        let shift = Shift { delta };
+        Diagnostic {
+            span: shift.span(self.span),
+            labels: self
+                .labels
+                .into_iter()
+                .map(|label| Label {
+                    span: shift.span(label.span),
+                    ..label
+                })
+                .collect(),
+            suggestions: self
+                .suggestions
+                .into_iter()
+                .map(|suggestion| Suggestion {
+                    span: shift.span(suggestion.span),
+                    ..suggestion
+                })
+                .collect(),
+            ..self
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // Renders the diagnostic with the source lines it points at:
+    //
+    //   error[E0300]: mismatched types: expected `int8`, found `int32`
+    //    --> main:2:15
+    //     |
+    //   2 | let b: int8 = a;
+    //     |               ^
+    //     = help: convert it explicitly: `a as int8`
+    //
+    // The primary span is underlined with `^` and labels with `-`. A span
+    // that covers several lines is underlined to the end of its first line.
+    pub fn render(&self, map: &SourceMap) -> String {
+        let (line, column) = map.location(self.span.start);
+        let mut marks = vec![(self.span, '^', "")];
+        marks.extend(
+            self.labels
+                .iter()
+                .map(|label| (label.span, '-', label.message.as_str())),
+        );
+        marks.sort_by_key(|(span, _, _)| span.start);
+        let last_line = marks
+            .iter()
+            .map(|(span, _, _)| map.location(span.start).0)
+            .max()
+            .unwrap_or(line);
+        let gutter = " ".repeat(last_line.to_string().len());
+
+        let mut output = format!("{}\n", self);
+        output.push_str(&format!(
+            "{}--> {}:{}:{}\n",
+            gutter,
+            map.name_at(self.span.start),
+            line,
+            column
+        ));
+        output.push_str(&format!("{} |\n", gutter));
+        let mut previous_line = None;
+        for (span, mark, message) in marks {
+            let (line, column) = map.location(span.start);
+            let text = map.line_at(span.start);
+            if previous_line != Some(line) {
+                output.push_str(&format!(
+                    "{:>width$} | {}\n",
+                    line,
+                    text,
+                    width = gutter.len()
+                ));
+                previous_line = Some(line);
+            }
+            let width = span.len().min(text.len() + 1 - column).max(1);
+            let underline = mark.to_string().repeat(width);
+            let underline = format!("{}{} {}", " ".repeat(column - 1), underline, message);
+            output.push_str(&format!("{} | {}\n", gutter, underline.trim_end()));
+        }
+        for note in &self.notes {
+            output.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+        for suggestion in &self.suggestions {
+            output.push_str(&format!(
+                "{} = help: {}: `{}`\n",
+                gutter, suggestion.message, suggestion.replacement
+            ));
+        }
+        output
+    }
+
+    // Serializes the diagnostic as one line of JSON, for tools to read:
+    //
+    //   {"code":"E0200","severity":"error","message":"...","file":"main",
+    //    "range":{...},"labels":[...],"notes":[...],"suggestions":[...]}
+    //
+    // Each range is an object with the `start` and `end` of a span, as byte
+    // offsets, and its start and end as 1-based `line`s and `column`s counted
+    // in bytes, as in rendered diagnostics. Labels have a `message` and a
+    // `range`, and suggestions a `message`, a `range` and a `replacement`.
+    pub fn to_json(&self, map: &SourceMap) -> String {
+        let range = |span: Span| {
+            let (line, column) = map.location(span.start);
+            let (end_line, end_column) = map.location(span.end);
+            format!(
+                concat!(
+                    r#"{{"start":{},"end":{},"line":{},"column":{},"#,
+                    r#""end_line":{},"end_column":{}}}"#
+                ),
+                span.start, span.end, line, column, end_line, end_column
+            )
+        };
+        let labels: Vec<String> = self
+            .labels
+            .iter()
+            .map(|label| {
+                format!(
+                    r#"{{"message":{},"range":{}}}"#,
+                    json_string(&label.message),
+                    range(label.span)
+                )
+            })
+            .collect();
+        let notes: Vec<String> = self.notes.iter().map(|note| json_string(note)).collect();
+        let suggestions: Vec<String> = self
+            .suggestions
+            .iter()
+            .map(|suggestion| {
+                format!(
+                    r#"{{"message":{},"range":{},"replacement":{}}}"#,
+                    json_string(&suggestion.message),
+                    range(suggestion.span),
+                    json_string(&suggestion.replacement)
+                )
+            })
+            .collect();
+        format!(
+            concat!(
+                r#"{{"code":{},"severity":{},"message":{},"file":{},"range":{},"#,
+                r#""labels":[{}],"notes":[{}],"suggestions":[{}]}}"#
+            ),
+            json_string(self.code),
+            json_string(&self.severity.to_string()),
+            json_string(&self.message),
+            json_string(map.name_at(self.span.start)),
+            range(self.span),
+            labels.join(","),
+            notes.join(","),
+            suggestions.join(",")
+        )
+    }
+}
+
+// Quotes text as a JSON string.
+pub(crate) fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// `error[E0200]: use of undeclared variable `x``
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+// Receives diagnostics as the compiler stages report them.
+pub trait DiagnosticSink {
+    fn emit(&mut self, diagnostic: Diagnostic);
+}
+
+impl DiagnosticSink for Vec<Diagnostic> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        self.push(diagnostic);
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_render_with_their_source() {
+        let map = SourceMap::new("main", "fn f() {\n    let b: int8 = a;\n");
+        let diagnostic = Diagnostic::error("E0300", "mismatched types", Span::new(27, 28))
+            .with_label(Span::new(20, 24), "expected because of this")
+            .with_label(Span::new(7, 8), "in this block")
+            .with_note("`int32` does not fit in `int8`")
+            .with_suggestion("convert it explicitly", Span::new(27, 28), "a as int8");
+        assert_eq!(diagnostic.to_string(), "error[E0300]: mismatched types");
+        assert_eq!(
+            diagnostic.render(&map),
+            "\
+error[E0300]: mismatched types
+ --> main:2:19
+  |
+1 | fn f() {
+  |        - in this block
+2 |     let b: int8 = a;
+  |            ---- expected because of this
+  |                   ^
+  = note: `int32` does not fit in `int8`
+  = help: convert it explicitly: `a as int8`
+"
+        );
+    }
+
+    #[test]
+    fn spans_are_underlined_to_the_end_of_their_first_line() {
+        let map = SourceMap::new("main", "fn f() {\n}");
+        let diagnostic = Diagnostic::warning("W0200", "unused function `f`", Span::new(0, 10));
+        assert_eq!(
+            diagnostic.render(&map),
+            "\
+warning[W0200]: unused function `f`
+ --> main:1:1
+  |
+1 | fn f() {
+  | ^^^^^^^^
+"
+        );
+        let end = Diagnostic::error("E0100", "unexpected end of file", Span::new(10, 10));
+        assert!(end.render(&map).ends_with("2 | }\n  |  ^\n"));
+    }
+
+    #[test]
+    fn diagnostics_serialize_to_json_lines() {
+        let map = SourceMap::new("dir/\"main\"", "let x: int8 = 1;\nlet y: int8 = x;\n");
+        let diagnostic = Diagnostic::warning("W0200", "unused variable `y`", Span::new(21, 22))
+            .with_label(Span::new(4, 5), "first\tlabel")
+            .with_note("a note")
+            .with_suggestion("prefix it", Span::new(21, 22), "_y");
+        let json = diagnostic.to_json(&map);
+        assert!(!json.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let range = |start: usize, end: usize, line: usize, column: usize| {
+            serde_json::json!({
+                "start": start, "end": end, "line": line, "column": column,
+                "end_line": line, "end_column": column + end - start,
+            })
+        };
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "code": "W0200",
+                "severity": "warning",
+                "message": "unused variable `y`",
+                "file": "dir/\"main\"",
+                "range": range(21, 22, 2, 5),
+                "labels": [{ "message": "first\tlabel", "range": range(4, 5, 1, 5) }],
+                "notes": ["a note"],
+                "suggestions": [
+                    { "message": "prefix it", "range": range(21, 22, 2, 5), "replacement": "_y" }
+                ],
+            })
+        );
+        assert_eq!(json_string("\u{1}\\"), "\"\\u0001\\\\\"");
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{
+    ast::{Block, Expression, Program, Statement, TypeExpr},
+    printer::operator_text,
+};
+use std::borrow::Cow;
+
+const INDENT: &str = "  ";
+
+// Returns a readable indented tree of the program's nodes, one node per line,
+// for debugging the parser.
+pub fn dump(program: &Program) -> String {
+    let mut dumper = Dumper {
+        output: String::new(),
+        depth: 0,
+    };
+    for statement in &program.statements {
+        dumper.statement(statement);
+    }
+    dumper.output
+}
+
+struct Dumper {
+    output: String,
+    depth: usize,
+}
+
+impl Dumper {
+    // Writes a node line at the current depth.
+    fn node(&mut self, name: &str, text: Option<&str>) {
+        for _ in 0..self.depth {
+            self.output.push_str(INDENT);
+        }
+        self.output.push_str(name);
+        if let Some(text) = text {
+            self.output.push_str(&format!(" {:?}", text));
+        }
+        self.output.push('\n');
+    }
+
+    // Writes a node line and then its children one level deeper.
+    fn nested(&mut self, name: &str, text: Option<&str>, children: impl FnOnce(&mut Dumper)) {
+        self.node(name, text);
+        self.depth += 1;
+        children(self);
+        self.depth -= 1;
+    }
+
+    fn docs(&mut self, docs: &[Cow<str>]) {
+        for doc in docs {
+            self.node("Doc", Some(doc));
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let(let_statement) => {
+                let name = if let_statement.mutable {
+                    "LetStatement mut"
+                } else {
+                    "LetStatement"
+                };
+                self.nested(name, None, |d| {
+                    d.docs(&let_statement.docs);
+                    d.node("Identifier", Some(&let_statement.identifier.name));
+                    d.ttype(&let_statement.ttype);
+                    d.expression(&let_statement.expression);
+                });
+            }
+            Statement::Const(constant) => {
+                self.nested("ConstDeclaration", None, |d| {
+                    d.docs(&constant.docs);
+                    d.node("Identifier", Some(&constant.identifier.name));
+                    d.ttype(&constant.ttype);
+                    d.expression(&constant.expression);
+                });
+            }
+            Statement::FunctionDeclaration(function) => {
+                self.nested("FunctionDeclaration", None, |d| {
+                    d.docs(&function.docs);
+                    if function.inline {
+                        d.node("Inline", None);
+                    }
+                    d.node("Identifier", Some(&function.identifier.name));
+                    for parameter in &function.parameters {
+                        d.nested("Parameter", None, |d| {
+                            d.node("Identifier", Some(&parameter.identifier.name));
+                            d.ttype(&parameter.ttype);
+                        });
+                    }
+                    d.ttype(&function.return_type);
+                    if let Some(body) = &function.body {
+                        d.block(body);
+                    }
+                });
+            }
+            Statement::Expression(expression) => {
+                self.nested("ExpressionStatement", None, |d| d.expression(expression));
+            }
+            Statement::Return(return_statement) => {
+                self.nested("ReturnStatement", None, |d| {
+                    if let Some(expression) = &return_statement.expression {
+                        d.expression(expression);
+                    }
+                });
+            }
+            Statement::If(if_statement) => {
+                self.nested("IfStatement", None, |d| {
+                    d.expression(&if_statement.condition);
+                    d.block(&if_statement.then_block);
+                    if let Some(else_branch) = &if_statement.else_branch {
+                        d.statement(else_branch);
+                    }
+                });
+            }
+            Statement::While(while_statement) => {
+                self.nested("WhileStatement", None, |d| {
+                    d.expression(&while_statement.condition);
+                    d.block(&while_statement.body);
+                });
+            }
+            Statement::Block(block) => self.block(block),
+            Statement::Import(import) => {
+                self.nested("ImportStatement", None, |d| {
+                    d.node("Identifier", Some(&import.module.name));
+                });
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.nested("Block", None, |d| {
+            for statement in &block.statements {
+                d.statement(statement);
+            }
+        });
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::IntegerLiteral(literal) => self.node("IntegerLiteral", Some(&literal.text)),
+            Expression::Identifier(identifier) => self.node("Identifier", Some(&identifier.name)),
+            Expression::BinaryExpression(binary) => {
+                self.nested(
+                    "BinaryExpression",
+                    Some(operator_text(&binary.operator)),
+                    |d| {
+                        d.expression(&binary.left);
+                        d.expression(&binary.right);
+                    },
+                );
+            }
+            Expression::Call(call) => {
+                self.nested("CallExpression", None, |d| {
+                    d.expression(&call.callee);
+                    for argument in &call.arguments {
+                        d.expression(argument);
+                    }
+                });
+            }
+            Expression::Cast(cast) => {
+                self.nested("CastExpression", None, |d| {
+                    d.expression(&cast.expression);
+                    d.ttype(&cast.ttype);
+                });
+            }
+        }
+    }
+
+    fn ttype(&mut self, ttype: &TypeExpr) {
+        match ttype {
+            TypeExpr::Named(named) => self.node("Type", Some(&named.name)),
+            TypeExpr::Array(array) => self.nested("ArrayType", None, |d| {
+                d.ttype(&array.element);
+                d.expression(&array.size);
+            }),
+            TypeExpr::Generic(generic) => {
+                self.nested("GenericType", Some(&generic.base.name), |d| {
+                    for argument in &generic.arguments {
+                        d.ttype(argument);
+                    }
+                });
+            }
+            TypeExpr::Tuple(elements) => self.nested("TupleType", None, |d| {
+                for element in elements {
+                    d.ttype(element);
+                }
+            }),
+            TypeExpr::Function(function) => self.nested("FunctionType", None, |d| {
+                for parameter in &function.parameters {
+                    d.ttype(parameter);
+                }
+                d.ttype(&function.return_type);
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn check_dump(input: &str, expected: &str) {
+        let tokens = Lexer::tokenize(input);
+        let program = Parser::parse_program(&tokens).unwrap();
+        assert_eq!(dump(&program), expected);
+    }
+
+    #[test]
+    fn let_statement() {
+        check_dump(
+            "let x: int32 = 5;",
+            "LetStatement\n  Identifier \"x\"\n  Type \"int32\"\n  IntegerLiteral \"5\"\n",
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        check_dump(
+            "if f(x) >= 1 { } else { while y { } }",
+            concat!(
+                "IfStatement\n",
+                "  BinaryExpression \">=\"\n",
+                "    CallExpression\n",
+                "      Identifier \"f\"\n",
+                "      Identifier \"x\"\n",
+                "    IntegerLiteral \"1\"\n",
+                "  Block\n",
+                "  Block\n",
+                "    WhileStatement\n",
+                "      Identifier \"y\"\n",
+                "      Block\n",
+            ),
+        );
+    }
+
+    #[test]
+    fn nested_nodes() {
+        check_dump(
+            "## Adds.\nfn f(a: [int32; 2], b: Map<string, (int32,)>) { return a + b * 2; }",
+            concat!(
+                "FunctionDeclaration\n",
+                "  Doc \"Adds.\"\n",
+                "  Identifier \"f\"\n",
+                "  Parameter\n",
+                "    Identifier \"a\"\n",
+                "    ArrayType\n",
+                "      Type \"int32\"\n",
+                "      IntegerLiteral \"2\"\n",
+                "  Parameter\n",
+                "    Identifier \"b\"\n",
+                "    GenericType \"Map\"\n",
+                "      Type \"string\"\n",
+                "      TupleType\n",
+                "        Type \"int32\"\n",
+                "  Type \"()\"\n",
+                "  Block\n",
+                "    ReturnStatement\n",
+                "      BinaryExpression \"+\"\n",
+                "        Identifier \"a\"\n",
+                "        BinaryExpression \"*\"\n",
+                "          Identifier \"b\"\n",
+                "          IntegerLiteral \"2\"\n",
+            ),
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use crate::{
+    diagnostic::Diagnostic,
+    hir,
+    interpreter::Interpreter,
+    lint::{self, Level},
+    pipeline::{compile, CompileOptions},
+    source_map::SourceMap,
+    symbol::sym,
+    value::Value,
+};
+use std::{
+    ffi::{c_char, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice, str,
+};
+
+// A C API for embedding the language in C and C++ programs, with the header
+// `include/mylang2.h`. With the `ffi` feature, the build script generates
+// the header from this module into `OUT_DIR`, and a test checks that the
+// committed one matches it. A static library to link them with is built by
+//
+//   cargo rustc --lib --release --features ffi --crate-type staticlib
+//
+// An engine holds the options sources are compiled with. Compiling a source
+// gives a program, which holds its diagnostics and, if it has no errors,
+// can be run. Each object is freed with its `_free` function, which accepts
+// null; freeing an engine does not free the programs it compiled.
+//
+// Strings passed in are UTF-8 and null-terminated, except sources, which
+// are given with their length. Functions given a null pointer or text that
+// is not UTF-8 where they need neither return `MYLANG_INVALID_ARGUMENT` or
+// null. Diagnostics are read as a JSON array of the objects
+// `Diagnostic::to_json` writes; the string belongs to the program, and is
+// valid until the program is run again or freed.
+//
+// A program runs on the calling thread, writing to the process's standard
+// output and reading its standard input. Neither engines nor programs may
+// be used from two threads at once.
+//
+// A panic in the compiler or interpreter, which is a bug in them, does not
+// unwind into the host: the function returns `MYLANG_INTERNAL_ERROR`, or
+// null or `true` if it returns neither a status nor an integer. An engine
+// or program that a function panicked with may only be freed.
+
+pub const MYLANG_OK: i32 = 0;
+pub const MYLANG_INVALID_ARGUMENT: i32 = 1;
+// The program has errors, and cannot be run.
+pub const MYLANG_COMPILE_ERROR: i32 = 2;
+// Running the program failed; its diagnostics say why.
+pub const MYLANG_RUNTIME_ERROR: i32 = 3;
+// The compiler or interpreter panicked.
+pub const MYLANG_INTERNAL_ERROR: i32 = 4;
+
+pub struct MylangEngine {
+    options: CompileOptions,
+}
+
+pub struct MylangProgram {
+    source_map: SourceMap,
+    diagnostics: Vec<Diagnostic>,
+    // The program lowered to HIR, if it has no errors.
+    hir: Option<hir::Program>,
+    json: CString,
+}
+
+impl MylangProgram {
+    fn new(source_map: SourceMap, diagnostics: Vec<Diagnostic>, hir: Option<hir::Program>) -> Self {
+        let mut program = MylangProgram {
+            source_map,
+            diagnostics,
+            hir,
+            json: CString::default(),
+        };
+        program.update_json();
+        program
+    }
+
+    fn update_json(&mut self) {
+        let diagnostics: Vec<String> = self
+            .diagnostics
+            .iter()
+            .map(|d| d.to_json(&self.source_map))
+            .collect();
+        // JSON escapes control characters, so it has no null bytes.
+        self.json = CString::new(format!("[{}]", diagnostics.join(","))).unwrap();
+    }
+}
+
+// Runs the body of an API function, returning `failed` if it panics rather
+// than unwinding into C.
+fn guard<T>(failed: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(failed)
+}
+
+// The text of a null-terminated UTF-8 string, or `None` if it is null or not
+// UTF-8. `string` must be null or a null-terminated string that lives for
+// `'a`.
+unsafe fn text<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(string) }.to_str().ok()
+}
+
+/// Creates an engine with the default options, to be freed with
+/// `mylang_engine_free`. Returns null if the engine cannot be made.
+#[no_mangle]
+pub extern "C" fn mylang_engine_new() -> *mut MylangEngine {
+    guard(ptr::null_mut(), || {
+        Box::into_raw(Box::new(MylangEngine {
+            options: CompileOptions::default(),
+        }))
+    })
+}
+
+/// Frees an engine. Does nothing if `engine` is null.
+///
+/// # Safety
+///
+/// `engine` must be null or an engine from `mylang_engine_new` that has not
+/// been freed, and must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn mylang_engine_free(engine: *mut MylangEngine) {
+    guard((), || {
+        if !engine.is_null() {
+            drop(unsafe { Box::from_raw(engine) });
+        }
+    })
+}
+
+/// Sets the level of a lint, by name, for the sources the engine compiles
+/// from then on: "allow", "warn" or "deny".
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine that no other thread is using.
+/// `lint` and `level` must each be null or a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mylang_engine_set_lint(
+    engine: *mut MylangEngine,
+    lint: *const c_char,
+    level: *const c_char,
+) -> i32 {
+    guard(MYLANG_INTERNAL_ERROR, || {
+        let Some(engine) = (unsafe { engine.as_mut() }) else {
+            return MYLANG_INVALID_ARGUMENT;
+        };
+        let lint = unsafe { text(lint) }.and_then(lint::find);
+        let level = unsafe { text(level) }.and_then(Level::from_name);
+        let (Some(lint), Some(level)) = (lint, level) else {
+            return MYLANG_INVALID_ARGUMENT;
+        };
+        engine.options.lints.set(lint, level);
+        MYLANG_OK
+    })
+}
+
+/// Compiles `length` bytes of source, named `name` in diagnostics. Returns a
+/// program even if the source has errors, and null only for invalid
+/// arguments or a panic.
+///
+/// # Safety
+///
+/// `engine` must be null or a live engine that no other thread is using.
+/// `name` must be null or a null-terminated string. Unless `length` is 0,
+/// `source` must be null or point to `length` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mylang_compile(
+    engine: *const MylangEngine,
+    name: *const c_char,
+    source: *const u8,
+    length: usize,
+) -> *mut MylangProgram {
+    guard(ptr::null_mut(), || {
+        let (Some(engine), Some(name)) = (unsafe { engine.as_ref() }, unsafe { text(name) }) else {
+            return ptr::null_mut();
+        };
+        let source = match (source.is_null(), length) {
+            (_, 0) => "",
+            (true, _) => return ptr::null_mut(),
+            (false, _) => match str::from_utf8(unsafe { slice::from_raw_parts(source, length) }) {
+                Ok(source) => source,
+                Err(_) => return ptr::null_mut(),
+            },
+        };
+        let options = CompileOptions {
+            name: name.to_string(),
+            ..engine.options.clone()
+        };
+        let program = match compile(source, options) {
+            Ok(compiled) => {
+                MylangProgram::new(compiled.source_map, compiled.warnings, compiled.hir)
+            }
+            Err(diagnostics) => {
+                MylangProgram::new(diagnostics.source_map, diagnostics.diagnostics, None)
+            }
+        };
+        Box::into_raw(Box::new(program))
+    })
+}
+
+/// Whether the program has errors, and so cannot be run. Returns `true` if
+/// `program` is null.
+///
+/// # Safety
+///
+/// `program` must be null or a live program that no other thread is using.
+#[no_mangle]
+pub unsafe extern "C" fn mylang_program_has_errors(program: *const MylangProgram) -> bool {
+    guard(true, || {
+        unsafe { program.as_ref() }.is_none_or(|program| program.hir.is_none())
+    })
+}
+
+/// The program's diagnostics as a JSON array, or null if `program` is.
+///
+/// # Safety
+///
+/// `program` must be null or a live program that no other thread is using.
+/// The string must not be used after the program is run again or freed.
+#[no_mangle]
+pub unsafe extern "C" fn mylang_program_diagnostics(
+    program: *const MylangProgram,
+) -> *const c_char {
+    guard(ptr::null(), || match unsafe { program.as_ref() } {
+        Some(program) => program.json.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Runs the program's top-level statements and then its `main` function, if
+/// it has one. If `main` returns an integer and `result` is not null, the
+/// integer is stored in `result`. A runtime error is added to the program's
+/// diagnostics.
+///
+/// # Safety
+///
+/// `program` must be null or a live program that no other thread is using.
+/// `result` must be null or point to an `int64_t` that can be written.
+#[no_mangle]
+pub unsafe extern "C" fn mylang_program_run(program: *mut MylangProgram, result: *mut i64) -> i32 {
+    guard(MYLANG_INTERNAL_ERROR, || {
+        let Some(program) = (unsafe { program.as_mut() }) else {
+            return MYLANG_INVALID_ARGUMENT;
+        };
+        let Some(hir) = &program.hir else {
+            return MYLANG_COMPILE_ERROR;
+        };
+        let has_main = hir.statements.iter().any(|statement| {
+            matches!(statement, hir::Statement::Function(function) if function.binding.name == sym::MAIN)
+        });
+        let returned = {
+            let mut interpreter = Interpreter::new(hir);
+            interpreter.run().and_then(|()| match has_main {
+                true => interpreter.call("main", vec![]),
+                false => Ok(None),
+            })
+        };
+        match returned {
+            Ok(value) => {
+                if let (Some(Value::Integer(_, value)), false) = (value, result.is_null()) {
+                    unsafe { *result = value };
+                }
+                MYLANG_OK
+            }
+            Err(error) => {
+                let diagnostic = error.to_diagnostic(&program.source_map);
+                program.diagnostics.push(diagnostic);
+                program.update_json();
+                MYLANG_RUNTIME_ERROR
+            }
+        }
+    })
+}
+
+/// Frees a program. Does nothing if `program` is null.
+///
+/// # Safety
+///
+/// `program` must be null or a program from `mylang_compile` that has not
+/// been freed, and must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn mylang_program_free(program: *mut MylangProgram) {
+    guard((), || {
+        if !program.is_null() {
+            drop(unsafe { Box::from_raw(program) });
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn compile(engine: *const MylangEngine, source: &str) -> *mut MylangProgram {
+        mylang_compile(engine, c"main.my".as_ptr(), source.as_ptr(), source.len())
+    }
+
+    unsafe fn diagnostics(program: *const MylangProgram) -> serde_json::Value {
+        let json = CStr::from_ptr(mylang_program_diagnostics(program));
+        serde_json::from_str(json.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn programs_compile_and_run_through_the_c_api() {
+        unsafe {
+            let engine = mylang_engine_new();
+            let source = "fn main() -> int32 { let unused: int32 = 2; return 6 * 7; }";
+            let program = compile(engine, source);
+            assert!(!mylang_program_has_errors(program));
+            assert_eq!(diagnostics(program)[0]["code"], "W0200");
+            let mut result = 0;
+            assert_eq!(mylang_program_run(program, &mut result), MYLANG_OK);
+            assert_eq!(result, 42);
+            mylang_program_free(program);
+
+            assert_eq!(
+                mylang_engine_set_lint(engine, c"unused".as_ptr(), c"deny".as_ptr()),
+                MYLANG_OK
+            );
+            let program = compile(engine, source);
+            assert!(mylang_program_has_errors(program));
+            assert_eq!(diagnostics(program)[0]["severity"], "error");
+            assert_eq!(
+                mylang_program_run(program, ptr::null_mut()),
+                MYLANG_COMPILE_ERROR
+            );
+            mylang_program_free(program);
+
+            let program = compile(engine, "fn main() -> int32 { return 1 / (1 - 1); }");
+            let mut result = 7;
+            assert_eq!(
+                mylang_program_run(program, &mut result),
+                MYLANG_RUNTIME_ERROR
+            );
+            assert_eq!(result, 7);
+            assert_eq!(diagnostics(program)[0]["file"], "main.my");
+            mylang_program_free(program);
+
+            let invalid = [0xff];
+            assert!(mylang_compile(engine, c"x".as_ptr(), invalid.as_ptr(), 1).is_null());
+            assert_eq!(
+                mylang_engine_set_lint(engine, c"unknown".as_ptr(), c"deny".as_ptr()),
+                MYLANG_INVALID_ARGUMENT
+            );
+            mylang_engine_free(engine);
+            mylang_program_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn panics_become_internal_errors() {
+        assert_eq!(
+            guard(MYLANG_INTERNAL_ERROR, || panic!("a bug")),
+            MYLANG_INTERNAL_ERROR
+        );
+        assert_eq!(guard(MYLANG_INTERNAL_ERROR, || MYLANG_OK), MYLANG_OK);
+    }
+
+    // The committed header is what the build script generates from this
+    // module, so that embedders can use it without building with `ffi`.
+    #[test]
+    fn the_header_is_up_to_date() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/mylang2.h"));
+        assert!(
+            include_str!("../include/mylang2.h") == generated,
+            "include/mylang2.h is out of date: copy {}/mylang2.h over it",
+            env!("OUT_DIR")
+        );
+    }
+}
@@ -0,0 +1,568 @@
+use crate::ast::{
+    ArrayType, BinaryExpression, Block, CallExpression, CastExpression, ConstDeclaration,
+    Expression, FunctionDeclaration, FunctionType, GenericType, Identifier, IfStatement,
+    ImportStatement, IntegerLiteral, LetStatement, Parameter, Program, ReturnStatement, Span,
+    Statement, Type, TypeExpr, WhileStatement,
+};
+use alloc::{boxed::Box, vec::Vec};
+
+// Rebuilds an AST by taking each node by value and returning its replacement.
+//
+// Every method defaults to the matching `walk_*` function, which folds the
+// node's children and reassembles the node unchanged. Override the methods for
+// the nodes a pass rewrites and call the `walk_*` function from the override
+// to keep folding the children. Methods for nodes that appear in more than one
+// syntactic category return the category, so a pass can replace, for example,
+// a binary expression with an identifier.
+pub trait Folder<'a> {
+    fn fold_program(&mut self, program: Program<'a>) -> Program<'a> {
+        walk_program(self, program)
+    }
+
+    fn fold_statement(&mut self, statement: Statement<'a>) -> Statement<'a> {
+        walk_statement(self, statement)
+    }
+
+    fn fold_let_statement(&mut self, let_statement: LetStatement<'a>) -> Statement<'a> {
+        Statement::Let(walk_let_statement(self, let_statement))
+    }
+
+    fn fold_const_declaration(&mut self, constant: ConstDeclaration<'a>) -> Statement<'a> {
+        Statement::Const(walk_const_declaration(self, constant))
+    }
+
+    fn fold_function_declaration(&mut self, function: FunctionDeclaration<'a>) -> Statement<'a> {
+        Statement::FunctionDeclaration(walk_function_declaration(self, function))
+    }
+
+    fn fold_parameter(&mut self, parameter: Parameter<'a>) -> Parameter<'a> {
+        walk_parameter(self, parameter)
+    }
+
+    fn fold_block(&mut self, block: Block<'a>) -> Block<'a> {
+        walk_block(self, block)
+    }
+
+    fn fold_return_statement(&mut self, return_statement: ReturnStatement<'a>) -> Statement<'a> {
+        Statement::Return(walk_return_statement(self, return_statement))
+    }
+
+    fn fold_import_statement(&mut self, import: ImportStatement) -> Statement<'a> {
+        Statement::Import(walk_import_statement(self, import))
+    }
+
+    fn fold_if_statement(&mut self, if_statement: IfStatement<'a>) -> Statement<'a> {
+        Statement::If(walk_if_statement(self, if_statement))
+    }
+
+    fn fold_while_statement(&mut self, while_statement: WhileStatement<'a>) -> Statement<'a> {
+        Statement::While(walk_while_statement(self, while_statement))
+    }
+
+    fn fold_expression(&mut self, expression: Expression<'a>) -> Expression<'a> {
+        walk_expression(self, expression)
+    }
+
+    fn fold_binary_expression(&mut self, binary: BinaryExpression<'a>) -> Expression<'a> {
+        Expression::BinaryExpression(walk_binary_expression(self, binary))
+    }
+
+    fn fold_call_expression(&mut self, call: CallExpression<'a>) -> Expression<'a> {
+        Expression::Call(walk_call_expression(self, call))
+    }
+
+    fn fold_cast_expression(&mut self, cast: CastExpression<'a>) -> Expression<'a> {
+        Expression::Cast(walk_cast_expression(self, cast))
+    }
+
+    fn fold_integer_literal(&mut self, literal: IntegerLiteral<'a>) -> IntegerLiteral<'a> {
+        literal
+    }
+
+    // Called for every identifier, both where a name is declared and where it
+    // is used.
+    fn fold_identifier(&mut self, identifier: Identifier) -> Identifier {
+        identifier
+    }
+
+    fn fold_type(&mut self, ttype: TypeExpr<'a>) -> TypeExpr<'a> {
+        walk_type(self, ttype)
+    }
+
+    fn fold_named_type(&mut self, named: Type) -> Type {
+        named
+    }
+}
+
+pub fn walk_program<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    program: Program<'a>,
+) -> Program<'a> {
+    Program {
+        statements: program
+            .statements
+            .into_iter()
+            .map(|s| folder.fold_statement(s))
+            .collect(),
+    }
+}
+
+pub fn walk_statement<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    statement: Statement<'a>,
+) -> Statement<'a> {
+    match statement {
+        Statement::Let(let_statement) => folder.fold_let_statement(let_statement),
+        Statement::Const(constant) => folder.fold_const_declaration(constant),
+        Statement::FunctionDeclaration(function) => folder.fold_function_declaration(function),
+        Statement::Expression(expression) => {
+            Statement::Expression(folder.fold_expression(expression))
+        }
+        Statement::Return(return_statement) => folder.fold_return_statement(return_statement),
+        Statement::If(if_statement) => folder.fold_if_statement(if_statement),
+        Statement::While(while_statement) => folder.fold_while_statement(while_statement),
+        Statement::Block(block) => Statement::Block(folder.fold_block(block)),
+        Statement::Import(import) => folder.fold_import_statement(import),
+    }
+}
+
+pub fn walk_import_statement<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    import: ImportStatement,
+) -> ImportStatement {
+    ImportStatement {
+        module: folder.fold_identifier(import.module),
+        ..import
+    }
+}
+
+pub fn walk_const_declaration<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    constant: ConstDeclaration<'a>,
+) -> ConstDeclaration<'a> {
+    ConstDeclaration {
+        identifier: folder.fold_identifier(constant.identifier),
+        ttype: folder.fold_type(constant.ttype),
+        expression: Box::new(folder.fold_expression(*constant.expression)),
+        ..constant
+    }
+}
+
+pub fn walk_let_statement<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    let_statement: LetStatement<'a>,
+) -> LetStatement<'a> {
+    LetStatement {
+        identifier: folder.fold_identifier(let_statement.identifier),
+        ttype: folder.fold_type(let_statement.ttype),
+        expression: Box::new(folder.fold_expression(*let_statement.expression)),
+        ..let_statement
+    }
+}
+
+pub fn walk_function_declaration<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    function: FunctionDeclaration<'a>,
+) -> FunctionDeclaration<'a> {
+    FunctionDeclaration {
+        identifier: folder.fold_identifier(function.identifier),
+        parameters: function
+            .parameters
+            .into_iter()
+            .map(|p| folder.fold_parameter(p))
+            .collect(),
+        return_type: folder.fold_type(function.return_type),
+        body: function.body.map(|b| folder.fold_block(b)),
+        ..function
+    }
+}
+
+pub fn walk_parameter<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    parameter: Parameter<'a>,
+) -> Parameter<'a> {
+    Parameter {
+        identifier: folder.fold_identifier(parameter.identifier),
+        ttype: folder.fold_type(parameter.ttype),
+        ..parameter
+    }
+}
+
+pub fn walk_block<'a, F: Folder<'a> + ?Sized>(folder: &mut F, block: Block<'a>) -> Block<'a> {
+    Block {
+        statements: block
+            .statements
+            .into_iter()
+            .map(|s| folder.fold_statement(s))
+            .collect(),
+        ..block
+    }
+}
+
+pub fn walk_return_statement<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    return_statement: ReturnStatement<'a>,
+) -> ReturnStatement<'a> {
+    ReturnStatement {
+        expression: return_statement
+            .expression
+            .map(|e| Box::new(folder.fold_expression(*e))),
+        ..return_statement
+    }
+}
+
+pub fn walk_if_statement<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    if_statement: IfStatement<'a>,
+) -> IfStatement<'a> {
+    IfStatement {
+        condition: Box::new(folder.fold_expression(*if_statement.condition)),
+        then_block: folder.fold_block(if_statement.then_block),
+        else_branch: if_statement
+            .else_branch
+            .map(|s| Box::new(folder.fold_statement(*s))),
+        ..if_statement
+    }
+}
+
+pub fn walk_while_statement<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    while_statement: WhileStatement<'a>,
+) -> WhileStatement<'a> {
+    WhileStatement {
+        condition: Box::new(folder.fold_expression(*while_statement.condition)),
+        body: folder.fold_block(while_statement.body),
+        ..while_statement
+    }
+}
+
+pub fn walk_expression<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    expression: Expression<'a>,
+) -> Expression<'a> {
+    match expression {
+        Expression::IntegerLiteral(literal) => {
+            Expression::IntegerLiteral(folder.fold_integer_literal(literal))
+        }
+        Expression::Identifier(identifier) => {
+            Expression::Identifier(folder.fold_identifier(identifier))
+        }
+        Expression::BinaryExpression(binary) => folder.fold_binary_expression(binary),
+        Expression::Call(call) => folder.fold_call_expression(call),
+        Expression::Cast(cast) => folder.fold_cast_expression(cast),
+    }
+}
+
+pub fn walk_binary_expression<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    binary: BinaryExpression<'a>,
+) -> BinaryExpression<'a> {
+    BinaryExpression {
+        left: Box::new(folder.fold_expression(*binary.left)),
+        right: Box::new(folder.fold_expression(*binary.right)),
+        ..binary
+    }
+}
+
+pub fn walk_call_expression<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    call: CallExpression<'a>,
+) -> CallExpression<'a> {
+    CallExpression {
+        callee: Box::new(folder.fold_expression(*call.callee)),
+        arguments: call
+            .arguments
+            .into_iter()
+            .map(|e| folder.fold_expression(e))
+            .collect(),
+        ..call
+    }
+}
+
+pub fn walk_cast_expression<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    cast: CastExpression<'a>,
+) -> CastExpression<'a> {
+    CastExpression {
+        expression: Box::new(folder.fold_expression(*cast.expression)),
+        ttype: folder.fold_type(cast.ttype),
+        ..cast
+    }
+}
+
+pub fn walk_type<'a, F: Folder<'a> + ?Sized>(folder: &mut F, ttype: TypeExpr<'a>) -> TypeExpr<'a> {
+    match ttype {
+        TypeExpr::Named(named) => TypeExpr::Named(folder.fold_named_type(named)),
+        TypeExpr::Array(array) => TypeExpr::Array(ArrayType {
+            element: Box::new(folder.fold_type(*array.element)),
+            size: Box::new(folder.fold_expression(*array.size)),
+            ..array
+        }),
+        TypeExpr::Generic(generic) => TypeExpr::Generic(GenericType {
+            base: folder.fold_named_type(generic.base),
+            arguments: walk_types(folder, generic.arguments),
+            ..generic
+        }),
+        TypeExpr::Tuple(elements) => TypeExpr::Tuple(walk_types(folder, elements)),
+        TypeExpr::Function(function) => TypeExpr::Function(FunctionType {
+            parameters: walk_types(folder, function.parameters),
+            return_type: Box::new(folder.fold_type(*function.return_type)),
+            ..function
+        }),
+    }
+}
+
+fn walk_types<'a, F: Folder<'a> + ?Sized>(
+    folder: &mut F,
+    types: Vec<TypeExpr<'a>>,
+) -> Vec<TypeExpr<'a>> {
+    types.into_iter().map(|t| folder.fold_type(t)).collect()
+}
+
+// Moves an offset by `delta` bytes.
+pub fn shift(offset: usize, delta: isize) -> usize {
+    (offset as isize + delta) as usize
+}
+
+// Moves the spans of a node and all of its children by `delta` bytes, such as
+// a statement reused after an edit before it changed the length of the source,
+// or a module's statements placed after other modules' in a linked program.
+// Children are reached through the walk, so a new kind of node is shifted once
+// the folder walks it.
+pub struct Shift {
+    pub delta: isize,
+}
+
+impl Shift {
+    pub fn span(&self, span: Span) -> Span {
+        Span::new(shift(span.start, self.delta), shift(span.end, self.delta))
+    }
+}
+
+impl<'a> Folder<'a> for Shift {
+    fn fold_let_statement(&mut self, let_statement: LetStatement<'a>) -> Statement<'a> {
+        let span = self.span(let_statement.span);
+        Statement::Let(LetStatement {
+            span,
+            ..walk_let_statement(self, let_statement)
+        })
+    }
+
+    fn fold_const_declaration(&mut self, constant: ConstDeclaration<'a>) -> Statement<'a> {
+        let span = self.span(constant.span);
+        Statement::Const(ConstDeclaration {
+            span,
+            ..walk_const_declaration(self, constant)
+        })
+    }
+
+    fn fold_function_declaration(&mut self, function: FunctionDeclaration<'a>) -> Statement<'a> {
+        let span = self.span(function.span);
+        Statement::FunctionDeclaration(FunctionDeclaration {
+            span,
+            ..walk_function_declaration(self, function)
+        })
+    }
+
+    fn fold_parameter(&mut self, parameter: Parameter<'a>) -> Parameter<'a> {
+        let span = self.span(parameter.span);
+        Parameter {
+            span,
+            ..walk_parameter(self, parameter)
+        }
+    }
+
+    fn fold_block(&mut self, block: Block<'a>) -> Block<'a> {
+        let span = self.span(block.span);
+        Block {
+            span,
+            ..walk_block(self, block)
+        }
+    }
+
+    fn fold_import_statement(&mut self, import: ImportStatement) -> Statement<'a> {
+        let span = self.span(import.span);
+        Statement::Import(ImportStatement {
+            span,
+            ..walk_import_statement(self, import)
+        })
+    }
+
+    fn fold_return_statement(&mut self, return_statement: ReturnStatement<'a>) -> Statement<'a> {
+        let span = self.span(return_statement.span);
+        Statement::Return(ReturnStatement {
+            span,
+            ..walk_return_statement(self, return_statement)
+        })
+    }
+
+    fn fold_if_statement(&mut self, if_statement: IfStatement<'a>) -> Statement<'a> {
+        let span = self.span(if_statement.span);
+        Statement::If(IfStatement {
+            span,
+            ..walk_if_statement(self, if_statement)
+        })
+    }
+
+    fn fold_while_statement(&mut self, while_statement: WhileStatement<'a>) -> Statement<'a> {
+        let span = self.span(while_statement.span);
+        Statement::While(WhileStatement {
+            span,
+            ..walk_while_statement(self, while_statement)
+        })
+    }
+
+    fn fold_binary_expression(&mut self, binary: BinaryExpression<'a>) -> Expression<'a> {
+        let span = self.span(binary.span);
+        Expression::BinaryExpression(BinaryExpression {
+            span,
+            ..walk_binary_expression(self, binary)
+        })
+    }
+
+    fn fold_call_expression(&mut self, call: CallExpression<'a>) -> Expression<'a> {
+        let span = self.span(call.span);
+        Expression::Call(CallExpression {
+            span,
+            ..walk_call_expression(self, call)
+        })
+    }
+
+    fn fold_cast_expression(&mut self, cast: CastExpression<'a>) -> Expression<'a> {
+        let span = self.span(cast.span);
+        Expression::Cast(CastExpression {
+            span,
+            ..walk_cast_expression(self, cast)
+        })
+    }
+
+    fn fold_integer_literal(&mut self, literal: IntegerLiteral<'a>) -> IntegerLiteral<'a> {
+        IntegerLiteral {
+            span: self.span(literal.span),
+            ..literal
+        }
+    }
+
+    fn fold_identifier(&mut self, identifier: Identifier) -> Identifier {
+        Identifier {
+            span: self.span(identifier.span),
+            ..identifier
+        }
+    }
+
+    fn fold_type(&mut self, ttype: TypeExpr<'a>) -> TypeExpr<'a> {
+        match walk_type(self, ttype) {
+            TypeExpr::Array(array) => TypeExpr::Array(ArrayType {
+                span: self.span(array.span),
+                ..array
+            }),
+            TypeExpr::Generic(generic) => TypeExpr::Generic(GenericType {
+                span: self.span(generic.span),
+                ..generic
+            }),
+            TypeExpr::Function(function) => TypeExpr::Function(FunctionType {
+                span: self.span(function.span),
+                ..function
+            }),
+            ttype @ (TypeExpr::Named(_) | TypeExpr::Tuple(_)) => ttype,
+        }
+    }
+
+    fn fold_named_type(&mut self, named: Type) -> Type {
+        Type {
+            span: self.span(named.span),
+            ..named
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::{print, BinaryOperator, TypeKind},
+        lexer::Lexer,
+        parser::Parser,
+    };
+
+    // Folds the program parsed from `tokens` and prints the result.
+    fn fold<'a>(folder: &mut impl Folder<'a>, tokens: &'a [crate::token::Token<'a>]) -> String {
+        let program = Parser::parse_program(tokens).unwrap();
+        print(&folder.fold_program(program))
+    }
+
+    struct Rename;
+
+    impl<'a> Folder<'a> for Rename {
+        fn fold_identifier(&mut self, identifier: Identifier) -> Identifier {
+            if identifier.name == "x" {
+                Identifier {
+                    name: "renamed".into(),
+                    ..identifier
+                }
+            } else {
+                identifier
+            }
+        }
+
+        fn fold_named_type(&mut self, named: Type) -> Type {
+            match named.kind {
+                TypeKind::Int32 => Type {
+                    kind: TypeKind::Int64,
+                    name: "int64".into(),
+                    ..named
+                },
+                TypeKind::Named => Type {
+                    name: named.name.to_uppercase().as_str().into(),
+                    ..named
+                },
+                _ => named,
+            }
+        }
+    }
+
+    #[test]
+    fn renames_every_occurrence() {
+        let tokens =
+            Lexer::tokenize("fn f(x: int32) -> List<int32> { let y: int32 = x + 1; return x; }");
+        assert_eq!(
+            fold(&mut Rename, &tokens),
+            "fn f(renamed: int64) -> LIST<int64> {\n    let y: int64 = renamed + 1;\n    return renamed;\n}\n"
+        );
+    }
+
+    // Rewrites `e ** 2` into `e * e`.
+    struct ExpandSquares;
+
+    impl<'a> Folder<'a> for ExpandSquares {
+        fn fold_binary_expression(&mut self, binary: BinaryExpression<'a>) -> Expression<'a> {
+            let binary = walk_binary_expression(self, binary);
+            match (&binary.operator, binary.right.as_ref()) {
+                (BinaryOperator::Power, Expression::IntegerLiteral(literal))
+                    if literal.text == "2" =>
+                {
+                    Expression::BinaryExpression(BinaryExpression {
+                        operator: BinaryOperator::Star,
+                        right: binary.left.clone(),
+                        ..binary
+                    })
+                }
+                _ => Expression::BinaryExpression(binary),
+            }
+        }
+    }
+
+    #[test]
+    fn rewrites_nested_expressions_bottom_up() {
+        let tokens = Lexer::tokenize("let y: int32 = (a + b ** 2) ** 2 ** 1;");
+        assert_eq!(
+            fold(&mut ExpandSquares, &tokens),
+            "let y: int32 = (a + b * b) ** 2 ** 1;\n"
+        );
+        let tokens = Lexer::tokenize("(a + b ** 2) ** 2;");
+        assert_eq!(
+            fold(&mut ExpandSquares, &tokens),
+            "(a + b * b) * (a + b * b);\n"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{
+    ast::Span,
+    lexer::Lexer,
+    source_map::SourceMap,
+    token::{Kind, Token},
+};
+
+// The parts of a file an editor can fold away, as with the Language Server
+// Protocol's folding ranges:
+//
+//   - blocks, including function bodies, from their opening brace up to the
+//     line before their closing one, so that the brace stays visible,
+//   - runs of comments, or of doc comments, on consecutive lines, and
+//   - the imports at the start of a file.
+//
+// Ranges are found from the tokens alone, so a file that is being edited
+// and does not parse still folds. Only ranges that cover more than one line
+// are folded.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FoldingKind {
+    Block,
+    Comment,
+    Imports,
+}
+
+impl FoldingKind {
+    // The LSP's `FoldingRangeKind`, which blocks do not have.
+    pub fn lsp(&self) -> Option<&'static str> {
+        match self {
+            FoldingKind::Block => None,
+            FoldingKind::Comment => Some("comment"),
+            FoldingKind::Imports => Some("imports"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoldingRange {
+    // The first and last lines folded, 0-based as in the LSP.
+    pub start_line: usize,
+    pub end_line: usize,
+    // The text the range was found from: a block's braces, the comments or
+    // the imports.
+    pub span: Span,
+    pub kind: FoldingKind,
+}
+
+// Returns the folding ranges of a file, ordered by where they start.
+pub fn folding_ranges(source: &str) -> Vec<FoldingRange> {
+    let map = SourceMap::new("", source);
+    let tokens = Lexer::tokenize(source);
+    let mut ranges = vec![];
+    imports(&tokens, &map, &mut ranges);
+    comments(&tokens, &map, &mut ranges);
+    blocks(&tokens, &map, &mut ranges);
+    ranges.sort_by_key(|range| (range.span.start, range.span.end));
+    ranges
+}
+
+fn span(token: &Token) -> Span {
+    Span::new(token.offset(), token.offset() + token.len())
+}
+
+// Adds a range if it covers more than one line. `trailing` lines at its end
+// are left unfolded.
+fn push(
+    ranges: &mut Vec<FoldingRange>,
+    map: &SourceMap,
+    span: Span,
+    kind: FoldingKind,
+    trailing: usize,
+) {
+    let start_line = map.location(span.start).0 - 1;
+    let end_line = map.location(span.end).0 - 1 - trailing;
+    if end_line > start_line {
+        ranges.push(FoldingRange {
+            start_line,
+            end_line,
+            span,
+            kind,
+        });
+    }
+}
+
+// The imports at the start of a file: `import`, a name and a semicolon, with
+// only whitespace and comments between them.
+fn imports(tokens: &[Token], map: &SourceMap, ranges: &mut Vec<FoldingRange>) {
+    let significant: Vec<&Token> = tokens
+        .iter()
+        .filter(|token| {
+            !matches!(
+                token.kind(),
+                Kind::Whitespace | Kind::Comment | Kind::DocComment
+            )
+        })
+        .collect();
+    let mut group: Option<Span> = None;
+    for import in significant.chunks_exact(3) {
+        let [keyword, name, semicolon] = import else {
+            unreachable!()
+        };
+        if keyword.kind() != Kind::Import
+            || name.kind() != Kind::Identifier
+            || semicolon.kind() != Kind::Semicolon
+        {
+            break;
+        }
+        let start = group.map_or(keyword.offset(), |group| group.start);
+        group = Some(Span::new(start, span(semicolon).end));
+    }
+    if let Some(group) = group {
+        push(ranges, map, group, FoldingKind::Imports, 0);
+    }
+}
+
+// Runs of comments of the same kind, separated by single line breaks.
+fn comments(tokens: &[Token], map: &SourceMap, ranges: &mut Vec<FoldingRange>) {
+    let mut run: Option<(Kind, Span)> = None;
+    for token in tokens {
+        // The line breaks between the run and the token. Comment tokens
+        // leave out the line break that ends them, so they are counted in
+        // the source rather than in the whitespace tokens.
+        let breaks = |span: Span| map.source()[span.end..token.offset()].matches('\n').count();
+        match (token.kind(), run) {
+            (Kind::Comment | Kind::DocComment, Some((kind, span)))
+                if token.kind() == kind && breaks(span) <= 1 =>
+            {
+                run = Some((kind, Span::new(span.start, self::span(token).end)));
+            }
+            (Kind::Comment | Kind::DocComment, _) => {
+                if let Some((_, span)) = run {
+                    push(ranges, map, span, FoldingKind::Comment, 0);
+                }
+                run = Some((token.kind(), span(token)));
+            }
+            (Kind::Whitespace, _) => {}
+            (_, Some((_, span))) => {
+                push(ranges, map, span, FoldingKind::Comment, 0);
+                run = None;
+            }
+            (_, None) => {}
+        }
+    }
+}
+
+// Blocks, matched by their braces. An unclosed brace folds nothing.
+fn blocks(tokens: &[Token], map: &SourceMap, ranges: &mut Vec<FoldingRange>) {
+    let mut open = vec![];
+    for token in tokens {
+        match token.kind() {
+            Kind::LeftBrace => open.push(token.offset()),
+            Kind::RightBrace => {
+                if let Some(start) = open.pop() {
+                    let span = Span::new(start, span(token).end);
+                    push(ranges, map, span, FoldingKind::Block, 1);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folds(source: &str) -> Vec<(usize, usize, FoldingKind)> {
+        folding_ranges(source)
+            .iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect()
+    }
+
+    #[test]
+    fn blocks_comments_and_imports_fold() {
+        let source = "\
+import geometry;
+# The shapes.
+import shapes;
+
+## Squares.
+## Twice.
+# Not a doc comment.
+fn square(x: int32) -> int32 {
+    if x < 0 {
+        return 0;
+    }
+    # One.
+
+    # Two.
+    return x * x;
+}
+fn one() -> int32 { return 1; }
+";
+        assert_eq!(
+            folds(source),
+            [
+                (0, 2, FoldingKind::Imports),
+                (4, 5, FoldingKind::Comment),
+                (7, 14, FoldingKind::Block),
+                (8, 9, FoldingKind::Block),
+            ]
+        );
+        let ranges = folding_ranges(source);
+        assert_eq!(&source[ranges[1].span.range()], "## Squares.\n## Twice.");
+        assert_eq!(FoldingKind::Imports.lsp(), Some("imports"));
+
+        // Unfinished code still folds what it can.
+        assert_eq!(
+            folds("fn f() {\n    {\n        x\n    }\n"),
+            [(1, 2, FoldingKind::Block)]
+        );
+    }
+}
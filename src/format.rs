@@ -0,0 +1,182 @@
+use crate::{
+    lexer::Lexer,
+    parser::{Parser, ParserError},
+    printer::{Printer, Source},
+    token::Kind,
+};
+
+// Formats mylang source the way the printer writes programs: one statement
+// per line, single spaces around operators and after commas and colons,
+// and blocks indented by a level with braces on the line of their
+// statement. Unlike the printer, it keeps every comment, where it was
+// relative to the statements around it, and blank lines between
+// statements, up to `max_blank_lines` in a row.
+//
+// Formatting is idempotent: formatted source formats to itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    // The number of spaces in a level of indentation.
+    pub indent_width: usize,
+    // Indents with a tab per level instead of spaces.
+    pub hard_tabs: bool,
+    pub brace_style: BraceStyle,
+    pub max_blank_lines: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions {
+            indent_width: 4,
+            hard_tabs: false,
+            brace_style: BraceStyle::SameLine,
+            max_blank_lines: 1,
+        }
+    }
+}
+
+// Where the opening brace of a function's, `if`'s or `while`'s block goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BraceStyle {
+    // `fn f() {`, and `} else {`.
+    SameLine,
+    // On a line of its own, as is `else`.
+    NextLine,
+}
+
+// Formats source with the default options, or returns why it does not
+// parse.
+pub fn format_source(source: &str) -> Result<String, ParserError> {
+    format_source_with(source, &FormatOptions::default())
+}
+
+pub fn format_source_with(source: &str, options: &FormatOptions) -> Result<String, ParserError> {
+    let tokens = Lexer::tokenize(source);
+    let program = Parser::parse_program(&tokens)?;
+    let comments: Vec<(usize, &str)> = tokens
+        .iter()
+        .filter(|token| matches!(token.kind(), Kind::Comment | Kind::DocComment))
+        .map(|token| (token.offset(), token.text()))
+        .collect();
+    Ok(Printer::new(options, Some(Source::new(source, &comments))).program(&program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_format(input: &str, expected: &str) {
+        let formatted = format_source(input).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(
+            format_source(&formatted).unwrap(),
+            expected,
+            "not idempotent"
+        );
+    }
+
+    #[test]
+    fn spacing_and_indentation_are_normalized() {
+        check_format(
+            "fn  f(a:int32,b :int32)->int32{if a>b{return a;}else{ return b*(a+1) ;}}",
+            "\
+fn f(a: int32, b: int32) -> int32 {
+    if a > b {
+        return a;
+    } else {
+        return b * (a + 1);
+    }
+}
+",
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_kept() {
+        check_format(
+            "\
+#[allow(unused)]
+# The answer.
+
+
+## Doc for x.
+let x: int32 = 42;   # trailing
+fn f() { # opening
+  # inside
+
+  let y:int32=1;
+
+
+  println(y);
+  # closing
+}
+#[inline]
+fn g() -> int32 { return 1; }
+# at the end
+",
+            "\
+#[allow(unused)]
+# The answer.
+
+## Doc for x.
+let x: int32 = 42; # trailing
+fn f() { # opening
+    # inside
+
+    let y: int32 = 1;
+
+    println(y);
+    # closing
+}
+#[inline]
+fn g() -> int32 {
+    return 1;
+}
+# at the end
+",
+        );
+    }
+
+    #[test]
+    fn options_change_indentation_and_braces() {
+        let options = FormatOptions {
+            indent_width: 2,
+            brace_style: BraceStyle::NextLine,
+            max_blank_lines: 0,
+            ..FormatOptions::default()
+        };
+        let source = "fn f(a: int32) {\n\n    while a > 0 { if a > 1 { g(); } else { h(); } }\n}";
+        assert_eq!(
+            format_source_with(source, &options).unwrap(),
+            "\
+fn f(a: int32)
+{
+  while a > 0
+  {
+    if a > 1
+    {
+      g();
+    }
+    else
+    {
+      h();
+    }
+  }
+}
+"
+        );
+        let options = FormatOptions {
+            hard_tabs: true,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_source_with("fn f() { { g(); } }", &options).unwrap(),
+            "fn f() {\n\t{\n\t\tg();\n\t}\n}\n"
+        );
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let error = format_source("let x: int32 = ;").unwrap_err();
+        assert!(error.code.starts_with("E01"), "{}", error);
+    }
+}
@@ -0,0 +1,549 @@
+use crate::{parser::GRAMMAR, token::Kind};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+
+// The grammar the parser accepts, kept as data next to it in
+// `parser::GRAMMAR`, and ways to print it: as EBNF, in the W3C notation
+// that railroad diagram tools read,
+//
+//   let_statement ::= 'let' 'mut'? IDENTIFIER ':' type '=' expression ';'
+//
+// and as railroad diagrams drawn in SVG. Token kinds with fixed text are
+// written quoted; the others, such as identifiers, in capitals.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Production {
+    Terminal(Kind),
+    // Another rule, by name.
+    Ref(&'static str),
+    Sequence(&'static [Production]),
+    Choice(&'static [Production]),
+    Optional(&'static Production),
+    // Zero or more times.
+    Repeat(&'static Production),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub name: &'static str,
+    pub production: Production,
+}
+
+impl Rule {
+    pub const fn new(name: &'static str, production: Production) -> Rule {
+        Rule { name, production }
+    }
+}
+
+// The rules, starting with `program`.
+pub fn rules() -> &'static [Rule] {
+    GRAMMAR
+}
+
+pub fn find(name: &str) -> Option<&'static Rule> {
+    GRAMMAR.iter().find(|rule| rule.name == name)
+}
+
+// How a terminal is written: its text, quoted, or the name of its kind.
+fn terminal(kind: Kind) -> String {
+    let text = kind.to_string();
+    match text.strip_prefix('\'') {
+        Some(quoted) => format!("'{}", quoted),
+        None => text.to_uppercase().replace(' ', "_"),
+    }
+}
+
+// Writes a production, parenthesized if it binds more loosely than
+// `context`: choices bind loosest, then sequences, then the rest.
+fn write_production(out: &mut String, production: &Production, context: u8) {
+    let precedence = match production {
+        Production::Choice(_) => 0,
+        Production::Sequence(_) => 1,
+        _ => 2,
+    };
+    if precedence < context {
+        out.push('(');
+    }
+    match production {
+        Production::Terminal(kind) => out.push_str(&terminal(*kind)),
+        Production::Ref(name) => out.push_str(name),
+        Production::Sequence(items) => {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                write_production(out, item, 1);
+            }
+        }
+        Production::Choice(alternatives) => {
+            for (i, alternative) in alternatives.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(" | ");
+                }
+                write_production(out, alternative, 1);
+            }
+        }
+        Production::Optional(item) => {
+            write_production(out, item, 2);
+            out.push('?');
+        }
+        Production::Repeat(item) => {
+            write_production(out, item, 2);
+            out.push('*');
+        }
+    }
+    if precedence < context {
+        out.push(')');
+    }
+}
+
+// What the rules leave out: where whitespace and comments may appear, and
+// the comment that marks a function for inlining.
+const LEXICAL_NOTE: &str = "\
+(* Whitespace may appear between any two tokens. Comments may appear only
+   between statements, and before the '}' of a block or the end of the file. *)
+(* A function is marked for inlining by a comment that is just '#[inline]',
+   right before its declaration. There is no other attribute syntax, such as
+   '@inline'. *)
+";
+
+// The grammar as EBNF, a rule a line, with the alternatives of a rule that
+// is a choice on lines of their own, after a note on what they leave out.
+pub fn to_ebnf() -> String {
+    let mut ebnf = String::from(LEXICAL_NOTE);
+    for rule in GRAMMAR {
+        let _ = write!(ebnf, "{} ::= ", rule.name);
+        match &rule.production {
+            Production::Choice(alternatives) => {
+                let indent = " ".repeat(rule.name.len() + 3);
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        let _ = write!(ebnf, "\n{}| ", indent);
+                    }
+                    write_production(&mut ebnf, alternative, 1);
+                }
+            }
+            production => write_production(&mut ebnf, production, 0),
+        }
+        ebnf.push('\n');
+    }
+    ebnf
+}
+
+// The railroad diagram of a rule, as an SVG document. Terminals are drawn
+// in rounded boxes and references to other rules in square ones.
+pub fn to_svg(rule: &Rule) -> String {
+    let layout = Layout::of(&rule.production);
+    let width = layout.width + 2 * MARGIN;
+    let height = layout.up + layout.down + 2 * MARGIN;
+    let y = MARGIN + layout.up;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         viewBox=\"0 0 {} {}\" font-family=\"monospace\" font-size=\"12\">\n\
+         <title>{}</title>\n\
+         <g fill=\"none\" stroke=\"black\">\n",
+        width, height, width, height, rule.name
+    );
+    let _ = writeln!(svg, "<circle cx=\"{}\" cy=\"{}\" r=\"4\"/>", MARGIN / 2, y);
+    line(&mut svg, (MARGIN / 2 + 4, y), (MARGIN, y));
+    draw(&mut svg, &rule.production, MARGIN, y);
+    line(
+        &mut svg,
+        (MARGIN + layout.width, y),
+        (width - MARGIN / 2 - 4, y),
+    );
+    let _ = writeln!(
+        svg,
+        "<circle cx=\"{}\" cy=\"{}\" r=\"4\"/>",
+        width - MARGIN / 2,
+        y
+    );
+    svg + "</g>\n</svg>\n"
+}
+
+// The space around a diagram, the gap between the items of a sequence and
+// the space a choice or loop takes on each side of its items.
+const MARGIN: u32 = 20;
+const GAP: u32 = 10;
+const SIDE: u32 = 20;
+// Half the height of a box, and the width of a character in it.
+const HALF: u32 = 11;
+const CHARACTER: u32 = 7;
+
+// The size of a production's diagram: its width, and how far it reaches
+// above and below the line through it.
+#[derive(Clone, Copy)]
+struct Layout {
+    width: u32,
+    up: u32,
+    down: u32,
+}
+
+impl Layout {
+    fn of(production: &Production) -> Layout {
+        match production {
+            Production::Terminal(_) | Production::Ref(_) => Layout {
+                width: label(production).chars().count() as u32 * CHARACTER + 2 * GAP,
+                up: HALF,
+                down: HALF,
+            },
+            Production::Sequence(items) => {
+                let layouts: Vec<Layout> = items.iter().map(Layout::of).collect();
+                Layout {
+                    width: layouts.iter().map(|l| l.width).sum::<u32>()
+                        + GAP * (layouts.len() as u32).saturating_sub(1),
+                    up: layouts.iter().map(|l| l.up).max().unwrap_or(0),
+                    down: layouts.iter().map(|l| l.down).max().unwrap_or(0),
+                }
+            }
+            Production::Choice(alternatives) => {
+                let layouts: Vec<Layout> = alternatives.iter().map(Layout::of).collect();
+                Layout {
+                    width: layouts.iter().map(|l| l.width).max().unwrap_or(0) + 2 * SIDE,
+                    up: layouts[0].up,
+                    down: layouts[0].down
+                        + layouts[1..]
+                            .iter()
+                            .map(|l| GAP + l.up + l.down)
+                            .sum::<u32>(),
+                }
+            }
+            // Drawn as a choice between skipping the item and taking it.
+            Production::Optional(item) => {
+                let item = Layout::of(item);
+                Layout {
+                    width: item.width + 2 * SIDE,
+                    up: HALF,
+                    down: GAP + item.up + item.down,
+                }
+            }
+            // Drawn as an optional loop through the item.
+            Production::Repeat(item) => {
+                let item = Layout::of(item);
+                Layout {
+                    width: item.width + 4 * SIDE,
+                    up: HALF,
+                    down: GAP + item.up + item.down + GAP,
+                }
+            }
+        }
+    }
+}
+
+fn label(production: &Production) -> String {
+    match production {
+        Production::Terminal(kind) => terminal(*kind),
+        Production::Ref(name) => name.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn line(svg: &mut String, from: (u32, u32), to: (u32, u32)) {
+    if from != to {
+        let _ = writeln!(
+            svg,
+            "<path d=\"M{} {}L{} {}\"/>",
+            from.0, from.1, to.0, to.1
+        );
+    }
+}
+
+// Draws a path through the points, in order.
+fn path(svg: &mut String, points: &[(u32, u32)]) {
+    let mut d = String::new();
+    for (i, (x, y)) in points.iter().enumerate() {
+        let _ = write!(d, "{}{} {}", if i == 0 { 'M' } else { 'L' }, x, y);
+    }
+    let _ = writeln!(svg, "<path d=\"{}\"/>", d);
+}
+
+// Draws a production with its line entering at `(x, y)`.
+fn draw(svg: &mut String, production: &Production, x: u32, y: u32) {
+    let layout = Layout::of(production);
+    match production {
+        Production::Terminal(_) | Production::Ref(_) => {
+            let rounded = if let Production::Terminal(_) = production {
+                HALF
+            } else {
+                0
+            };
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\"/>\n\
+                 <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" fill=\"black\" \
+                 stroke=\"none\">{}</text>",
+                x,
+                y - HALF,
+                layout.width,
+                2 * HALF,
+                rounded,
+                x + layout.width / 2,
+                y + 4,
+                escape(&label(production))
+            );
+        }
+        Production::Sequence(items) => {
+            let mut x = x;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    line(svg, (x, y), (x + GAP, y));
+                    x += GAP;
+                }
+                draw(svg, item, x, y);
+                x += Layout::of(item).width;
+            }
+        }
+        Production::Choice(alternatives) => {
+            let end = x + layout.width;
+            let mut branch = y;
+            for (i, alternative) in alternatives.iter().enumerate() {
+                let item = Layout::of(alternative);
+                if i > 0 {
+                    branch += GAP + item.up;
+                    path(svg, &[(x, y), (x + SIDE / 2, y), (x + SIDE / 2, branch)]);
+                    path(
+                        svg,
+                        &[(end - SIDE / 2, branch), (end - SIDE / 2, y), (end, y)],
+                    );
+                }
+                line(svg, (x + SIDE / 2, branch), (x + SIDE, branch));
+                draw(svg, alternative, x + SIDE, branch);
+                line(
+                    svg,
+                    (x + SIDE + item.width, branch),
+                    (end - SIDE / 2, branch),
+                );
+                if i == 0 {
+                    line(svg, (x, y), (x + SIDE / 2, y));
+                    line(svg, (end - SIDE / 2, y), (end, y));
+                }
+                branch += item.down;
+            }
+        }
+        Production::Optional(item) => {
+            let end = x + layout.width;
+            let inner = Layout::of(item);
+            let branch = y + GAP + inner.up;
+            line(svg, (x, y), (end, y));
+            path(
+                svg,
+                &[
+                    (x + SIDE / 2, y),
+                    (x + SIDE / 2, branch),
+                    (x + SIDE, branch),
+                ],
+            );
+            draw(svg, item, x + SIDE, branch);
+            path(
+                svg,
+                &[
+                    (x + SIDE + inner.width, branch),
+                    (end - SIDE / 2, branch),
+                    (end - SIDE / 2, y),
+                ],
+            );
+        }
+        Production::Repeat(item) => {
+            let end = x + layout.width;
+            let inner = Layout::of(item);
+            let branch = y + GAP + inner.up;
+            let back = branch + inner.down + GAP;
+            line(svg, (x, y), (end, y));
+            path(
+                svg,
+                &[
+                    (x + SIDE / 2, y),
+                    (x + SIDE / 2, branch),
+                    (x + 2 * SIDE, branch),
+                ],
+            );
+            draw(svg, item, x + 2 * SIDE, branch);
+            path(
+                svg,
+                &[
+                    (x + 2 * SIDE + inner.width, branch),
+                    (end - SIDE / 2, branch),
+                    (end - SIDE / 2, y),
+                ],
+            );
+            // The way back to the start of the item, to take it again.
+            path(
+                svg,
+                &[
+                    (end - SIDE, branch),
+                    (end - SIDE, back),
+                    (x + SIDE, back),
+                    (x + SIDE, branch),
+                ],
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{
+        lexer::Lexer,
+        parser::{Parser, EXPRESSION_STARTS, STATEMENT_STARTS, TYPE_STARTS},
+    };
+
+    // The token kinds that can start a production, and whether it can be
+    // empty.
+    fn first(production: &Production, kinds: &mut Vec<Kind>) -> bool {
+        match production {
+            Production::Terminal(kind) => {
+                if !kinds.contains(kind) {
+                    kinds.push(*kind);
+                }
+                false
+            }
+            Production::Ref(name) => first(&find(name).unwrap().production, kinds),
+            Production::Sequence(items) => items.iter().all(|item| first(item, kinds)),
+            Production::Choice(alternatives) => {
+                let mut empty = false;
+                for alternative in *alternatives {
+                    empty |= first(alternative, kinds);
+                }
+                empty
+            }
+            Production::Optional(item) | Production::Repeat(item) => {
+                first(item, kinds);
+                true
+            }
+        }
+    }
+
+    fn starts(name: &str) -> Vec<Kind> {
+        let mut kinds = vec![];
+        first(&find(name).unwrap().production, &mut kinds);
+        kinds
+    }
+
+    fn same(mut a: Vec<Kind>, b: &[Kind]) -> bool {
+        a.sort_by_key(|kind| kind.to_string());
+        let mut b = b.to_vec();
+        b.sort_by_key(|kind| kind.to_string());
+        a == b
+    }
+
+    #[test]
+    fn the_grammar_is_exported_as_ebnf_and_svg() {
+        let ebnf = to_ebnf();
+        assert!(ebnf.starts_with(LEXICAL_NOTE));
+        assert!(ebnf[LEXICAL_NOTE.len()..].starts_with(concat!(
+            "program ::= (import_statement | statement)*\n",
+            "import_statement ::= 'import' IDENTIFIER ';'\n",
+            "statement ::= let_statement\n",
+        )));
+        assert!(ebnf
+            .contains("\nlet_statement ::= 'let' 'mut'? IDENTIFIER ':' type '=' expression ';'\n"));
+        assert!(ebnf.contains(
+            "\nprimary ::= INTEGER_LITERAL\n          | (IDENTIFIER | '(' expression ')') call*\n"
+        ));
+        assert_eq!(
+            ebnf.lines().filter(|line| line.contains("::=")).count(),
+            rules().len()
+        );
+
+        let svg = to_svg(find("type").unwrap());
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+        assert!(svg.contains(">'&lt;'</text>") && svg.contains(">type_list</text>"));
+        assert!(find("unknown").is_none());
+    }
+
+    #[test]
+    fn the_grammar_matches_the_parser() {
+        // Every rule refers only to rules that exist.
+        for rule in rules() {
+            let mut kinds = vec![];
+            first(&rule.production, &mut kinds);
+        }
+        assert!(same(starts("statement"), &STATEMENT_STARTS));
+        assert!(same(starts("expression"), &EXPRESSION_STARTS));
+        assert!(same(starts("type"), &TYPE_STARTS));
+    }
+
+    // Derives a random sentence from a production, taking the first
+    // alternative and no optional items once `depth` rules deep.
+    fn derive(production: &Production, depth: u32, seed: &mut u64, tokens: &mut Vec<Kind>) {
+        let mut random = |n: usize| {
+            *seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (*seed >> 33) as usize % n
+        };
+        let deep = depth > 6;
+        match production {
+            Production::Terminal(kind) => tokens.push(*kind),
+            Production::Ref(name) => {
+                derive(&find(name).unwrap().production, depth + 1, seed, tokens)
+            }
+            Production::Sequence(items) => {
+                for item in *items {
+                    derive(item, depth, seed, tokens);
+                }
+            }
+            Production::Choice(alternatives) => {
+                let alternative = if deep { 0 } else { random(alternatives.len()) };
+                derive(&alternatives[alternative], depth, seed, tokens);
+            }
+            Production::Optional(item) => {
+                if !deep && random(2) == 0 {
+                    derive(item, depth, seed, tokens);
+                }
+            }
+            Production::Repeat(item) => {
+                for _ in 0..if deep { 0 } else { random(3) } {
+                    derive(item, depth, seed, tokens);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sentences_of_the_grammar_parse() {
+        let mut seed = 1;
+        let mut parsed = 0;
+        for _ in 0..300 {
+            let mut kinds = vec![];
+            derive(
+                &find("program").unwrap().production,
+                0,
+                &mut seed,
+                &mut kinds,
+            );
+            // A comparison after a cast to a named type is read as the
+            // type's arguments.
+            let ambiguous = kinds
+                .windows(3)
+                .any(|w| w == [Kind::As, Kind::Identifier, Kind::LessThan]);
+            let source: Vec<String> = kinds
+                .iter()
+                .map(|kind| match kind {
+                    Kind::Identifier => "x".to_string(),
+                    Kind::IntegerLiteral => "1".to_string(),
+                    kind => kind.to_string().trim_matches('\'').to_string(),
+                })
+                .collect();
+            let source = source.join(" ");
+            let tokens = Lexer::tokenize(&source);
+            let result = Parser::parse_program(&tokens);
+            if !ambiguous {
+                assert!(result.is_ok(), "{}", source);
+                parsed += 1;
+            }
+        }
+        assert!(parsed > 250, "{}", parsed);
+    }
+}
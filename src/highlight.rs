@@ -0,0 +1,244 @@
+use crate::{
+    ast::TypeKind,
+    lexer::Lexer,
+    token::{Kind, Token},
+};
+
+// Renders source with its tokens colored by kind, as ANSI escape sequences
+// for terminals or as HTML spans for web pages. Highlighting only lexes the
+// source, so it works on any text, including programs that do not parse,
+// and colors an identifier as a type only when it names a primitive type.
+
+// How a token is colored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Style {
+    Keyword,
+    Type,
+    Number,
+    String,
+    Comment,
+    DocComment,
+    Operator,
+    // Text the lexer does not accept, which runs to the end of the source.
+    Error,
+}
+
+impl Style {
+    pub const ALL: [Style; 8] = [
+        Style::Keyword,
+        Style::Type,
+        Style::Number,
+        Style::String,
+        Style::Comment,
+        Style::DocComment,
+        Style::Operator,
+        Style::Error,
+    ];
+
+    // The style of a token, or `None` for identifiers, punctuation and
+    // whitespace, which are left uncolored.
+    pub fn of(token: &Token) -> Option<Style> {
+        let style = match token.kind() {
+            Kind::As
+            | Kind::Const
+            | Kind::Else
+            | Kind::Fn
+            | Kind::If
+            | Kind::Import
+            | Kind::Let
+            | Kind::Mut
+            | Kind::Return
+            | Kind::While => Style::Keyword,
+            Kind::Identifier => match TypeKind::from_name(token.text()) {
+                TypeKind::Named => return None,
+                _ => Style::Type,
+            },
+            Kind::IntegerLiteral | Kind::DecimalLiteral => Style::Number,
+            Kind::String => Style::String,
+            Kind::Comment => Style::Comment,
+            Kind::DocComment => Style::DocComment,
+            Kind::Arrow
+            | Kind::Divide
+            | Kind::EqualEqual
+            | Kind::EqualSign
+            | Kind::GreaterEqual
+            | Kind::GreaterThan
+            | Kind::LessEqual
+            | Kind::LessThan
+            | Kind::Minus
+            | Kind::NotEqual
+            | Kind::Plus
+            | Kind::Star
+            | Kind::StarStar => Style::Operator,
+            Kind::Unknown => Style::Error,
+            Kind::Colon
+            | Kind::Comma
+            | Kind::EndOfFile
+            | Kind::LeftBrace
+            | Kind::LeftParenthesis
+            | Kind::LeftSquareBracket
+            | Kind::RightBrace
+            | Kind::RightParenthesis
+            | Kind::RightSquareBracket
+            | Kind::Semicolon
+            | Kind::Whitespace => return None,
+        };
+        Some(style)
+    }
+
+    // The name of the style, which is also the class of its HTML spans.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Style::Keyword => "keyword",
+            Style::Type => "type",
+            Style::Number => "number",
+            Style::String => "string",
+            Style::Comment => "comment",
+            Style::DocComment => "doc-comment",
+            Style::Operator => "operator",
+            Style::Error => "error",
+        }
+    }
+
+    // The SGR parameters the style is written with in a terminal.
+    pub fn ansi(&self) -> &'static str {
+        match self {
+            Style::Keyword => "1;35",
+            Style::Type => "36",
+            Style::Number => "33",
+            Style::String => "32",
+            Style::Comment => "90",
+            Style::DocComment => "3;32",
+            Style::Operator => "39",
+            Style::Error => "1;31",
+        }
+    }
+}
+
+// Colors source for a terminal. Each colored token is followed by a reset,
+// so the output can be printed as is or cut at any line.
+pub fn to_ansi(source: &str) -> String {
+    let mut output = String::with_capacity(source.len() * 2);
+    for (text, style) in pieces(source) {
+        match style {
+            // Colors are set per line, since terminals and pagers such as
+            // `less -R` forget them at line breaks.
+            Some(style) => {
+                for (index, line) in text.split('\n').enumerate() {
+                    if index > 0 {
+                        output.push('\n');
+                    }
+                    if !line.is_empty() {
+                        output += &format!("\x1b[{}m{}\x1b[0m", style.ansi(), line);
+                    }
+                }
+            }
+            None => output += text,
+        }
+    }
+    output
+}
+
+// Marks up source as HTML: each colored token is a `span` whose class is
+// its style's name, prefixed with `my-`, and the text is escaped. The
+// result belongs in a `pre` element, and the page's style sheet decides
+// the colors.
+pub fn to_html(source: &str) -> String {
+    let mut output = String::with_capacity(source.len() * 2);
+    for (text, style) in pieces(source) {
+        match style {
+            Some(style) => {
+                output += &format!(
+                    "<span class=\"my-{}\">{}</span>",
+                    style.name(),
+                    escape(text)
+                )
+            }
+            None => output += &escape(text),
+        }
+    }
+    output
+}
+
+// Splits source into runs of text and their style. Every byte of the source
+// is in exactly one run.
+fn pieces(source: &str) -> Vec<(&str, Option<Style>)> {
+    let mut pieces = vec![];
+    let mut position = 0;
+    for token in Lexer::tokenize(source) {
+        if token.kind() == Kind::EndOfFile || token.offset() < position {
+            continue;
+        }
+        if token.offset() > position {
+            pieces.push((&source[position..token.offset()], None));
+        }
+        let end = token.offset() + token.text().len();
+        pieces.push((&source[token.offset()..end], Style::of(&token)));
+        position = end;
+    }
+    if position < source.len() {
+        pieces.push((&source[position..], None));
+    }
+    pieces
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_wrapped_in_html_spans() {
+        assert_eq!(
+            to_html("let x: int32 = a < 1; # <b>\n"),
+            "<span class=\"my-keyword\">let</span> x: <span class=\"my-type\">int32</span> \
+             <span class=\"my-operator\">=</span> a <span class=\"my-operator\">&lt;</span> \
+             <span class=\"my-number\">1</span>; <span class=\"my-comment\"># &lt;b&gt;</span>\n"
+        );
+        let source = "fn f() { return $; }";
+        assert!(to_html(source).ends_with("<span class=\"my-error\">$; }</span>"));
+    }
+
+    #[test]
+    fn ansi_colors_are_reset_after_each_token() {
+        assert_eq!(
+            to_ansi("fn f() -> bool {}\n"),
+            "\x1b[1;35mfn\x1b[0m f() \x1b[39m->\x1b[0m \x1b[36mbool\x1b[0m {}\n"
+        );
+    }
+
+    #[test]
+    fn highlighting_keeps_the_source_text() {
+        let source =
+            "## Doc.\nconst N: int64 = 10 ** 2; # note\nfn g(x: Point) {\n\tprintln(\"hi\");\n}";
+        let strip = |text: String| {
+            let mut plain = String::new();
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find('\x1b') {
+                plain += &rest[..start];
+                rest = &rest[start + rest[start..].find('m').unwrap() + 1..];
+            }
+            plain + rest
+        };
+        assert_eq!(strip(to_ansi(source)), source);
+        let styles: Vec<Option<Style>> = pieces(source).iter().map(|(_, style)| *style).collect();
+        for style in Style::ALL {
+            if style != Style::Error {
+                assert!(styles.contains(&Some(style)), "no {} token", style.name());
+            }
+        }
+    }
+}
@@ -0,0 +1,592 @@
+use crate::{
+    ast::{self, BinaryOperator, NodeId, Span, Spanned, Symbol},
+    builtin::Builtin,
+    consteval::ConstValue,
+    printer::operator_text,
+    resolver::{DeclarationKind, Resolution},
+    typecheck::{always_returns, promote, round_to_float, Ty, TypeCheck},
+};
+use std::fmt;
+
+// The high-level intermediate representation: a program after name
+// resolution and type checking, in the form the interpreter and code
+// generators consume.
+//
+// Unlike the AST it records what the program means rather than how it was
+// written:
+//
+// - Every expression carries its type, and integer literals carry their
+//   value in the type the context gave them, rounded if that is a float.
+// - Every name is linked to the declaration it refers to.
+// - Uses of constants are replaced by their values, and `const` declarations
+//   are dropped.
+// - Implicit widening and promotion are explicit `Cast`s, so both operands of
+//   a binary operator have the same type and every value has the type its use
+//   expects.
+// - `else if` is an `if` nested in an `else` block.
+// - A function returning unit whose end is reachable ends in a `return`.
+//
+// Parts of a program that failed to resolve or type check lower to
+// `ExpressionKind::Error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    Let(Let),
+    Function(Function),
+    Expression(Expression),
+    Return(Return),
+    If(If),
+    While(While),
+    Block(Block),
+}
+
+// A declared name, identified by the id of its declaring identifier in the
+// AST. Every use of the name refers to the same binding.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub id: NodeId,
+    pub name: Symbol,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Let {
+    pub binding: Binding,
+    pub mutable: bool,
+    pub ty: Ty,
+    pub value: Expression,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub binding: Binding,
+    pub parameters: Vec<Parameter>,
+    pub return_type: Ty,
+    // `None` for a function declared without a body.
+    pub body: Option<Block>,
+    // Whether it was marked `#[inline]`.
+    pub inline: bool,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub binding: Binding,
+    pub ty: Ty,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Return {
+    pub value: Option<Expression>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct If {
+    pub condition: Expression,
+    pub then_block: Block,
+    pub else_block: Option<Block>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct While {
+    pub condition: Expression,
+    pub body: Block,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub statements: Vec<Statement>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub ty: Ty,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpressionKind {
+    Integer(i128),
+    Bool(bool),
+    // A use of a variable, parameter or function.
+    Name(Binding),
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+    Call(Box<Expression>, Vec<Expression>),
+    Builtin(Builtin, Vec<Expression>),
+    // A conversion to the expression's type, either written with `as` or
+    // inserted where a value is widened implicitly.
+    Cast(Box<Expression>),
+    // Something that failed to resolve or type check.
+    Error,
+}
+
+impl Expression {
+    fn new(kind: ExpressionKind, ty: Ty, span: Span) -> Expression {
+        Expression { kind, ty, span }
+    }
+
+    // Returns the expression converted to `ty`, wrapping it in a cast if it
+    // has a different numeric type.
+    fn coerce(self, ty: &Ty) -> Expression {
+        match (&self.ty, ty) {
+            (Ty::Primitive(from), Ty::Primitive(to)) if from != to => {
+                let span = self.span;
+                Expression::new(ExpressionKind::Cast(Box::new(self)), ty.clone(), span)
+            }
+            _ => self,
+        }
+    }
+}
+
+impl Spanned for Statement {
+    fn span(&self) -> Span {
+        match self {
+            Statement::Let(let_statement) => let_statement.span,
+            Statement::Function(function) => function.span,
+            Statement::Expression(expression) => expression.span,
+            Statement::Return(return_statement) => return_statement.span,
+            Statement::If(if_statement) => if_statement.span,
+            Statement::While(while_statement) => while_statement.span,
+            Statement::Block(block) => block.span,
+        }
+    }
+}
+
+// Lowers a resolved and type checked program to HIR.
+pub fn lower(program: &ast::Program, resolution: &Resolution, check: &TypeCheck) -> Program {
+    let mut lowerer = Lowerer {
+        resolution,
+        check,
+        return_types: vec![],
+    };
+    Program {
+        statements: lowerer.statements(&program.statements),
+    }
+}
+
+struct Lowerer<'r> {
+    resolution: &'r Resolution,
+    check: &'r TypeCheck,
+    // The return types of the functions being lowered, innermost last.
+    return_types: Vec<Ty>,
+}
+
+impl Lowerer<'_> {
+    fn binding(&self, identifier: &ast::Identifier) -> Binding {
+        Binding {
+            id: identifier.id,
+            name: identifier.name.clone(),
+        }
+    }
+
+    fn declared_type(&self, identifier: &ast::Identifier) -> Ty {
+        self.check
+            .declarations
+            .get(identifier.id)
+            .cloned()
+            .unwrap_or(Ty::Error)
+    }
+
+    fn statements(&mut self, statements: &[ast::Statement]) -> Vec<Statement> {
+        statements
+            .iter()
+            .filter_map(|statement| self.statement(statement))
+            .collect()
+    }
+
+    fn statement(&mut self, statement: &ast::Statement) -> Option<Statement> {
+        Some(match statement {
+            ast::Statement::Let(let_statement) => {
+                let ty = self.declared_type(&let_statement.identifier);
+                Statement::Let(Let {
+                    binding: self.binding(&let_statement.identifier),
+                    mutable: let_statement.mutable,
+                    value: self.expression(&let_statement.expression).coerce(&ty),
+                    ty,
+                    span: let_statement.span,
+                })
+            }
+            ast::Statement::Const(_) | ast::Statement::Import(_) => return None,
+            ast::Statement::FunctionDeclaration(function) => {
+                Statement::Function(self.function(function))
+            }
+            ast::Statement::Expression(expression) => {
+                Statement::Expression(self.expression(expression))
+            }
+            ast::Statement::Return(return_statement) => {
+                let return_type = self.return_types.last().cloned();
+                let value = return_statement.expression.as_ref().map(|expression| {
+                    let value = self.expression(expression);
+                    match &return_type {
+                        Some(ty) => value.coerce(ty),
+                        None => value,
+                    }
+                });
+                Statement::Return(Return {
+                    value,
+                    span: return_statement.span,
+                })
+            }
+            ast::Statement::If(if_statement) => Statement::If(self.if_statement(if_statement)),
+            ast::Statement::While(while_statement) => Statement::While(While {
+                condition: self.expression(&while_statement.condition),
+                body: self.block(&while_statement.body),
+                span: while_statement.span,
+            }),
+            ast::Statement::Block(block) => Statement::Block(self.block(block)),
+        })
+    }
+
+    fn function(&mut self, function: &ast::FunctionDeclaration) -> Function {
+        let return_type = match self.declared_type(&function.identifier) {
+            Ty::Function(_, return_type) => *return_type,
+            _ => Ty::Error,
+        };
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|parameter| Parameter {
+                binding: self.binding(&parameter.identifier),
+                ty: self.declared_type(&parameter.identifier),
+            })
+            .collect();
+        self.return_types.push(return_type.clone());
+        let body = function.body.as_ref().map(|body| {
+            let mut block = self.block(body);
+            if return_type == Ty::Primitive(ast::TypeKind::Unit)
+                && !always_returns(&body.statements)
+            {
+                // The implicit return points at the closing brace.
+                block.statements.push(Statement::Return(Return {
+                    value: None,
+                    span: Span::new(body.span.end - 1, body.span.end),
+                }));
+            }
+            block
+        });
+        self.return_types.pop();
+        Function {
+            binding: self.binding(&function.identifier),
+            parameters,
+            return_type,
+            body,
+            inline: function.inline,
+            span: function.span,
+        }
+    }
+
+    fn if_statement(&mut self, if_statement: &ast::IfStatement) -> If {
+        let else_block = if_statement
+            .else_branch
+            .as_deref()
+            .map(|else_branch| match else_branch {
+                ast::Statement::Block(block) => self.block(block),
+                other => Block {
+                    statements: self.statement(other).into_iter().collect(),
+                    span: other.span(),
+                },
+            });
+        If {
+            condition: self.expression(&if_statement.condition),
+            then_block: self.block(&if_statement.then_block),
+            else_block,
+            span: if_statement.span,
+        }
+    }
+
+    fn block(&mut self, block: &ast::Block) -> Block {
+        Block {
+            statements: self.statements(&block.statements),
+            span: block.span,
+        }
+    }
+
+    fn expression(&mut self, expression: &ast::Expression) -> Expression {
+        let ty = self.check.type_of(expression).cloned().unwrap_or(Ty::Error);
+        let span = expression.span();
+        let kind = match expression {
+            ast::Expression::IntegerLiteral(literal) => {
+                let value = literal.text.parse().ok();
+                // A literal given a float type holds the value it rounds to.
+                let value = match &ty {
+                    Ty::Primitive(kind) if kind.is_float() => {
+                        value.and_then(|value| round_to_float(value, *kind))
+                    }
+                    _ => value,
+                };
+                value.map_or(ExpressionKind::Error, ExpressionKind::Integer)
+            }
+            ast::Expression::Identifier(identifier) => self.identifier(identifier),
+            ast::Expression::BinaryExpression(binary) => {
+                let mut left = self.expression(&binary.left);
+                let mut right = self.expression(&binary.right);
+                if let (Ty::Primitive(l), Ty::Primitive(r)) = (&left.ty, &right.ty) {
+                    if let Some(operand) = promote(*l, *r).map(Ty::Primitive) {
+                        left = left.coerce(&operand);
+                        right = right.coerce(&operand);
+                    }
+                }
+                ExpressionKind::Binary(binary.operator.clone(), Box::new(left), Box::new(right))
+            }
+            ast::Expression::Call(call) if self.builtin(&call.callee).is_some() => {
+                let builtin = self.builtin(&call.callee).unwrap();
+                let arguments = call
+                    .arguments
+                    .iter()
+                    .map(|argument| self.expression(argument))
+                    .collect();
+                ExpressionKind::Builtin(builtin, arguments)
+            }
+            ast::Expression::Call(call) => {
+                let callee = self.expression(&call.callee);
+                let parameters = match &callee.ty {
+                    Ty::Function(parameters, _) => parameters.clone(),
+                    _ => vec![],
+                };
+                let arguments = call
+                    .arguments
+                    .iter()
+                    .enumerate()
+                    .map(|(i, argument)| {
+                        let argument = self.expression(argument);
+                        match parameters.get(i) {
+                            Some(ty) => argument.coerce(ty),
+                            None => argument,
+                        }
+                    })
+                    .collect();
+                ExpressionKind::Call(Box::new(callee), arguments)
+            }
+            ast::Expression::Cast(cast) => {
+                ExpressionKind::Cast(Box::new(self.expression(&cast.expression)))
+            }
+        };
+        if ty == Ty::Error {
+            return Expression::new(ExpressionKind::Error, ty, span);
+        }
+        Expression::new(kind, ty, span)
+    }
+
+    // Returns the builtin a callee names, if it does.
+    fn builtin(&self, callee: &ast::Expression) -> Option<Builtin> {
+        match callee {
+            ast::Expression::Identifier(identifier) => {
+                self.resolution.builtins.get(identifier.id).copied()
+            }
+            _ => None,
+        }
+    }
+
+    fn identifier(&self, identifier: &ast::Identifier) -> ExpressionKind {
+        let Some(declaration) = self.resolution.uses.get(identifier.id) else {
+            return ExpressionKind::Error;
+        };
+        let kind = self
+            .resolution
+            .declarations
+            .get(*declaration)
+            .map(|d| d.kind);
+        if kind == Some(DeclarationKind::Constant) {
+            return match self.check.consts.values.get(*declaration) {
+                Some(ConstValue::Integer(value)) => ExpressionKind::Integer(*value),
+                Some(ConstValue::Bool(value)) => ExpressionKind::Bool(*value),
+                None => ExpressionKind::Error,
+            };
+        }
+        ExpressionKind::Name(Binding {
+            id: *declaration,
+            name: identifier.name.clone(),
+        })
+    }
+}
+
+// HIR is displayed as s-expressions in the style of `ast::Program::to_sexp`,
+// one top-level statement per line, with every value annotated with its
+// type: `(let x int64 (as int64 (+ a:int8 (int8 1))))`.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for statement in &self.statements {
+            writeln!(f, "{}", statement)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Let(let_statement) => {
+                f.write_str("(let ")?;
+                if let_statement.mutable {
+                    f.write_str("mut ")?;
+                }
+                write!(
+                    f,
+                    "{} {} {})",
+                    let_statement.binding.name, let_statement.ty, let_statement.value
+                )
+            }
+            Statement::Function(function) => {
+                write!(f, "(fn {} (", function.binding.name)?;
+                for (i, parameter) in function.parameters.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" ")?;
+                    }
+                    write!(f, "({} {})", parameter.binding.name, parameter.ty)?;
+                }
+                write!(f, ") {}", function.return_type)?;
+                if let Some(body) = &function.body {
+                    write!(f, " {}", body)?;
+                }
+                f.write_str(")")
+            }
+            Statement::Expression(expression) => write!(f, "(expr {})", expression),
+            Statement::Return(Return { value: None, .. }) => f.write_str("(return)"),
+            Statement::Return(Return {
+                value: Some(value), ..
+            }) => write!(f, "(return {})", value),
+            Statement::If(if_statement) => {
+                write!(
+                    f,
+                    "(if {} {}",
+                    if_statement.condition, if_statement.then_block
+                )?;
+                if let Some(else_block) = &if_statement.else_block {
+                    write!(f, " {}", else_block)?;
+                }
+                f.write_str(")")
+            }
+            Statement::While(while_statement) => write!(
+                f,
+                "(while {} {})",
+                while_statement.condition, while_statement.body
+            ),
+            Statement::Block(block) => block.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(block")?;
+        for statement in &self.statements {
+            write!(f, " {}", statement)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ExpressionKind::Integer(value) => write!(f, "({} {})", self.ty, value),
+            ExpressionKind::Bool(value) => write!(f, "{}", value),
+            ExpressionKind::Name(binding) => write!(f, "{}:{}", binding.name, self.ty),
+            ExpressionKind::Binary(operator, left, right) => {
+                write!(f, "({} {} {})", operator_text(operator), left, right)
+            }
+            ExpressionKind::Call(callee, arguments) => {
+                write!(f, "(call {}", callee)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                f.write_str(")")
+            }
+            ExpressionKind::Builtin(builtin, arguments) => {
+                write!(f, "({}", builtin.name())?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                f.write_str(")")
+            }
+            ExpressionKind::Cast(expression) => write!(f, "(as {} {})", self.ty, expression),
+            ExpressionKind::Error => f.write_str("{error}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn lower(source: &str) -> String {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        program.to_hir().to_string()
+    }
+
+    #[test]
+    fn expressions_are_typed_and_widened_explicitly() {
+        let source = "\
+const N: int16 = 2 * 3;
+fn f(a: int8, b: int32) -> int64 {
+    let c: int32 = a + N;
+    return f(1, a) * b;
+}";
+        assert_eq!(
+            lower(source),
+            "(fn f ((a int8) (b int32)) int64 (block \
+(let c int32 (as int32 (+ (as int16 a:int8) (int16 6)))) \
+(return (* (call f:fn(int8, int32) -> int64 (int8 1) (as int32 a:int8)) (as int64 b:int32)))))\n"
+        );
+    }
+
+    #[test]
+    fn control_flow_is_desugared() {
+        let source = "\
+fn g(x: bool) {
+    if x { g(x); } else if x { } else { return; }
+    while 1 < 2 { }
+}";
+        assert_eq!(
+            lower(source),
+            "(fn g ((x bool)) () (block \
+(if x:bool (block (expr (call g:fn(bool) -> () x:bool))) (block (if x:bool (block) (block (return))))) \
+(while (< (int32 1) (int32 2)) (block)) \
+(return)))\n"
+        );
+    }
+
+    #[test]
+    fn half_precision_operands_are_promoted() {
+        let source = "fn f(x: float16, y: bfloat16) -> float32 { return x + y * 2049; }";
+        assert_eq!(
+            lower(source),
+            "(fn f ((x float16) (y bfloat16)) float32 (block \
+(return (+ (as float32 x:float16) (as float32 (* y:bfloat16 (bfloat16 2048)))))))\n"
+        );
+    }
+
+    #[test]
+    fn names_are_linked_to_their_declarations() {
+        let source = "let x: int32 = 1; { let x: int32 = x; x; }";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let hir = program.to_hir();
+        let Statement::Let(outer) = &hir.statements[0] else {
+            panic!("Expected a let");
+        };
+        let Statement::Block(block) = &hir.statements[1] else {
+            panic!("Expected a block");
+        };
+        let [Statement::Let(inner), Statement::Expression(use_)] = &block.statements[..] else {
+            panic!("Expected a let and an expression");
+        };
+        assert_eq!(
+            inner.value.kind,
+            ExpressionKind::Name(outer.binding.clone())
+        );
+        assert_eq!(use_.kind, ExpressionKind::Name(inner.binding.clone()));
+        assert_ne!(outer.binding, inner.binding);
+    }
+}
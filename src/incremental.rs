@@ -0,0 +1,365 @@
+use crate::{
+    ast::{NodeId, Program, Statement},
+    fold::{shift, Folder, Shift},
+    lexer::Lexer,
+    parser::{Parser, ParserError},
+    symbol::Interner,
+    token::{Kind, Token},
+};
+use std::ops::Range;
+
+// A replacement of a byte range of source text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, replacement: &str) -> TextEdit {
+        TextEdit {
+            range,
+            replacement: replacement.to_string(),
+        }
+    }
+
+    // Returns a copy of `source` with the edit applied.
+    pub fn apply(&self, source: &str) -> String {
+        let mut text = String::with_capacity(source.len() + self.replacement.len());
+        text.push_str(&source[..self.range.start]);
+        text.push_str(&self.replacement);
+        text.push_str(&source[self.range.end..]);
+        text
+    }
+
+    // Returns the change in source length caused by the edit.
+    fn delta(&self) -> isize {
+        self.replacement.len() as isize - self.range.len() as isize
+    }
+}
+
+// A parsed source file that can be cheaply updated after an edit.
+//
+// Each top-level statement is stored with the byte range it was parsed from so
+// that a later edit only needs to re-lex and re-parse the statements it
+// touches.
+#[derive(Debug)]
+pub struct ParsedDocument<'a> {
+    tokens: Vec<Token<'a>>,
+    statements: Vec<(Statement<'a>, Range<usize>)>,
+    reused: usize,
+    // The id to assign to the next parsed AST node. Reparsed statements get
+    // fresh ids so that reused statements keep theirs.
+    next_id: NodeId,
+}
+
+impl<'a> ParsedDocument<'a> {
+    // Lexes and parses a complete source file.
+    pub fn parse(source: &'a str) -> Result<ParsedDocument<'a>, ParserError> {
+        let tokens = Lexer::tokenize(source);
+        let mut next_id = NodeId(0);
+        let mut interner = Interner::new();
+        let statements = Parser::parse_statements(tokens.as_slice(), &mut next_id, &mut interner)?;
+        Ok(ParsedDocument {
+            tokens,
+            statements,
+            reused: 0,
+            next_id,
+        })
+    }
+
+    pub fn tokens(&self) -> &[Token<'a>] {
+        &self.tokens
+    }
+
+    // Returns the number of statements carried over unchanged from the
+    // document this one was reparsed from.
+    pub fn reused_statements(&self) -> usize {
+        self.reused
+    }
+
+    pub fn program(&self) -> Program<'a> {
+        Program {
+            statements: self.statements.iter().map(|(s, _)| s.clone()).collect(),
+        }
+    }
+
+    pub fn into_program(self) -> Program<'a> {
+        Program {
+            statements: self.statements.into_iter().map(|(s, _)| s).collect(),
+        }
+    }
+
+    // Updates the document after `edit` has been applied to its source.
+    //
+    // `source` must be the result of applying `edit` to the source this
+    // document was parsed from. Statements that lie entirely before or after
+    // the edited range are reused; only the text between them is re-lexed and
+    // re-parsed. Falls back to a full parse if the edit changes how the text
+    // around the edited region is tokenized, or if the region does not parse
+    // on its own, so the result is always the same as a full parse's.
+    pub fn reparse<'b>(
+        &self,
+        source: &'b str,
+        edit: &TextEdit,
+    ) -> Result<ParsedDocument<'b>, ParserError>
+    where
+        'a: 'b,
+    {
+        let delta = edit.delta();
+        let prefix = self
+            .statements
+            .iter()
+            .take_while(|(_, range)| range.end < edit.range.start)
+            .count();
+        let suffix = self.statements[prefix..]
+            .iter()
+            .position(|(_, range)| range.start > edit.range.end)
+            .map_or(self.statements.len(), |i| prefix + i);
+
+        let region_start = match prefix {
+            0 => 0,
+            n => self.statements[n - 1].1.end,
+        };
+        let region_end = match self.statements.get(suffix) {
+            Some((_, range)) => shift(range.start, delta),
+            None => source.len(),
+        };
+
+        let region_tokens = Lexer::tokenize_range(source, region_start..region_end);
+        let crosses_boundary = region_tokens
+            .iter()
+            .rev()
+            .nth(1)
+            .is_some_and(|t| t.offset() + t.len() > region_end);
+        if crosses_boundary {
+            return ParsedDocument::parse(source);
+        }
+        // A region that does not parse on its own may still parse as part of
+        // the whole source, such as a statement whose end was deleted and
+        // which now runs on into the statements after it, so the whole
+        // source is parsed to find out. Names in the region are interned
+        // apart from the reused statements', which symbols compare equal to
+        // by their text.
+        let mut next_id = self.next_id;
+        let mut interner = Interner::new();
+        let Ok(region_statements) =
+            Parser::parse_statements(region_tokens.as_slice(), &mut next_id, &mut interner)
+        else {
+            return ParsedDocument::parse(source);
+        };
+
+        let source_bytes = source.as_bytes();
+        let mut tokens: Vec<Token<'b>> = self
+            .tokens
+            .iter()
+            .take_while(|t| t.kind() != Kind::EndOfFile && t.offset() < region_start)
+            .map(|t| Token::new(source_bytes, t.offset(), t.len(), t.kind()))
+            .collect();
+        tokens.extend(
+            region_tokens
+                .iter()
+                .filter(|t| t.kind() != Kind::EndOfFile)
+                .map(|t| Token::new(source_bytes, t.offset(), t.len(), t.kind())),
+        );
+        if suffix < self.statements.len() {
+            let old_region_end = self.statements[suffix].1.start;
+            tokens.extend(
+                self.tokens
+                    .iter()
+                    .filter(|t| t.kind() != Kind::EndOfFile && t.offset() >= old_region_end)
+                    .map(|t| Token::new(source_bytes, shift(t.offset(), delta), t.len(), t.kind())),
+            );
+            let end_of_file = self.tokens.last().unwrap();
+            tokens.push(Token::end_of_file(shift(end_of_file.offset(), delta)));
+        } else {
+            tokens.push(Token::end_of_file(region_tokens.last().unwrap().offset()));
+        }
+
+        let statements = self.statements[..prefix]
+            .iter()
+            .cloned()
+            .chain(region_statements)
+            .chain(self.statements[suffix..].iter().map(|(s, r)| {
+                let statement = Shift { delta }.fold_statement(s.clone());
+                (statement, shift(r.start, delta)..shift(r.end, delta))
+            }))
+            .collect();
+
+        Ok(ParsedDocument {
+            tokens,
+            statements,
+            reused: prefix + self.statements.len() - suffix,
+            next_id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{print, Span, Spanned};
+    use proptest::{prelude::*, sample::select};
+
+    // Returns the spans of a program's top-level statements.
+    fn statement_spans(program: &Program) -> Vec<Span> {
+        program.statements.iter().map(Spanned::span).collect()
+    }
+
+    // Reparses `source` after `edit` and checks the result against a full parse.
+    fn check_reparse(source: &str, edit: TextEdit, expected_reused: usize) {
+        let document = ParsedDocument::parse(source).unwrap();
+        let new_source = edit.apply(source);
+        let updated = document.reparse(&new_source, &edit).unwrap();
+        let expected = ParsedDocument::parse(&new_source).unwrap();
+
+        assert_eq!(print(&updated.program()), print(&expected.program()));
+        assert_eq!(
+            statement_spans(&updated.program()),
+            statement_spans(&expected.program())
+        );
+        assert_eq!(
+            format!("{:?}", updated.tokens()),
+            format!("{:?}", expected.tokens())
+        );
+        assert_eq!(updated.reused_statements(), expected_reused);
+    }
+
+    #[test]
+    fn edit_inside_a_statement_reuses_the_others() {
+        let source = "let x: int32 = 5;\nlet y: int32 = 7;\nlet z: int32 = 9;\n";
+        check_reparse(source, TextEdit::new(33..34, "42"), 2);
+    }
+
+    #[test]
+    fn inserting_a_statement_reuses_its_neighbours() {
+        let source = "let x: int32 = 5;\n\nlet z: int32 = 9;\n";
+        check_reparse(source, TextEdit::new(18..18, "fn f() -> int32;"), 2);
+    }
+
+    #[test]
+    fn deleting_a_statement() {
+        let source = "let x: int32 = 5;\nx + y;\nlet z: int32 = 9;\n";
+        check_reparse(source, TextEdit::new(18..24, ""), 2);
+    }
+
+    #[test]
+    fn edit_at_the_end_of_the_file() {
+        let source = "let x: int32 = 5;\nlet y: int32 = 7;";
+        check_reparse(source, TextEdit::new(35..35, " let z: int32 = 9;"), 1);
+    }
+
+    #[test]
+    fn editing_a_doc_comment_reparses_its_statement() {
+        let source = "## Old.\nfn f() -> int32;\nlet x: int32 = 1;\n";
+        check_reparse(source, TextEdit::new(3..6, "New"), 1);
+    }
+
+    #[test]
+    fn reused_statements_keep_their_node_ids() {
+        let source = "let x: int32 = 5;\nlet y: int32 = 7;\n";
+        let document = ParsedDocument::parse(source).unwrap();
+        let edit = TextEdit::new(33..34, "8");
+        let new_source = edit.apply(source);
+        let updated = document.reparse(&new_source, &edit).unwrap();
+        let ids = |program: &Program| -> Vec<NodeId> {
+            program
+                .statements
+                .iter()
+                .map(|statement| match statement {
+                    Statement::Let(let_statement) => let_statement.id,
+                    _ => panic!("Expected a let statement"),
+                })
+                .collect()
+        };
+        let (before, after) = (ids(&document.program()), ids(&updated.program()));
+        assert_eq!(before[0], after[0]);
+        assert!(after[1] > before[1]);
+    }
+
+    #[test]
+    fn commenting_out_a_statement() {
+        let source = "x + y;\nfn f() -> int32;\n";
+        check_reparse(source, TextEdit::new(0..0, "# "), 1);
+    }
+
+    #[test]
+    fn edit_that_changes_following_tokens_falls_back_to_a_full_parse() {
+        let source = "x + y;\nfn f() -> int32;\n";
+        let edit = TextEdit::new(4..4, "\"");
+        let document = ParsedDocument::parse(source).unwrap();
+        let new_source = edit.apply(source);
+        let expected = ParsedDocument::parse(&new_source).unwrap_err();
+        let error = document.reparse(&new_source, &edit).unwrap_err();
+        assert_eq!(error.message, expected.message);
+    }
+
+    #[test]
+    fn reparse_reports_errors_in_the_edited_region() {
+        let source = "let x: int32 = 5;\nlet y: int32 = 7;\n";
+        let edit = TextEdit::new(16..17, "");
+        let document = ParsedDocument::parse(source).unwrap();
+        let new_source = edit.apply(source);
+        assert!(document.reparse(&new_source, &edit).is_err());
+    }
+
+    #[test]
+    fn statement_that_runs_into_the_next_after_an_edit() {
+        let source = "let x: int32 = 5;\nlet y: int32 = 7;\nlet z: int32 = 9;\nx + y;";
+        check_reparse(source, TextEdit::new(51..53, ""), 0);
+    }
+
+    // Sources that parse, which random edits are made to.
+    const SOURCES: &[&str] = &[
+        "let x: int32 = 5;\nlet y: int32 = 7;\nlet z: int32 = 9;\nx + y;",
+        "## Docs.\nfn f(a: int8, b: [int32; 2]) -> bool { return a < b; }\nf(1, 2);\n",
+        "if a { b(); } else if c { } else { d; }\nwhile x < y { x; }\n# Done.\n",
+        "const N: int64 = 4;\nlet h: fn((), List<bool>) -> int1 = k;\n{ let t: (int8,) = u; }\n",
+    ];
+
+    // Text that edits insert, some of which changes how the text around it
+    // is lexed or parsed.
+    const REPLACEMENTS: &[&str] = &[
+        "", ";", "9", "x", " ", "\n", "let", "{", "}", "(", ")", "\"", "#", "else { }", "= 1;",
+        "fn g();", "return;", "as int8",
+    ];
+
+    proptest! {
+        #[test]
+        fn reparse_agrees_with_a_full_parse(
+            source in select(SOURCES),
+            start in 0..80usize,
+            length in 0..8usize,
+            replacement in select(REPLACEMENTS),
+        ) {
+            let start = start.min(source.len());
+            let end = (start + length).min(source.len());
+            let edit = TextEdit::new(start..end, replacement);
+            let document = ParsedDocument::parse(source).unwrap();
+            let new_source = edit.apply(source);
+            match (document.reparse(&new_source, &edit), ParsedDocument::parse(&new_source)) {
+                (Ok(updated), Ok(expected)) => {
+                    prop_assert_eq!(print(&updated.program()), print(&expected.program()));
+                    prop_assert_eq!(
+                        statement_spans(&updated.program()),
+                        statement_spans(&expected.program())
+                    );
+                    prop_assert_eq!(
+                        format!("{:?}", updated.tokens()),
+                        format!("{:?}", expected.tokens())
+                    );
+                }
+                (Err(error), Err(expected)) => {
+                    prop_assert_eq!(error.message, expected.message);
+                    prop_assert_eq!(error.span, expected.span);
+                }
+                (updated, expected) => prop_assert!(
+                    false,
+                    "reparse gave {:?}, a full parse {:?}",
+                    updated.map(|d| print(&d.program())),
+                    expected.map(|d| print(&d.program()))
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,833 @@
+use crate::{
+    ast::{NodeId, Span, Spanned, Symbol, TypeKind},
+    builtin::{self, Streams},
+    consteval::ConstValue,
+    diagnostic::Diagnostic,
+    hir::{self, Binding, Expression, ExpressionKind, Statement},
+    snapshot::{self, SnapshotError},
+    source_map::SourceMap,
+    typecheck::Ty,
+    value::{ArithmeticError, Closure, Value},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    ops::ControlFlow,
+    rc::Rc,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    Arithmetic(ArithmeticError),
+    // A call to a function declared without a body.
+    NoBody(Symbol),
+    // A call, from outside the program, to a top-level function that does
+    // not exist.
+    UnknownFunction(String),
+    ResourceLimit(Resource),
+    // A builtin failed to read or write.
+    Io(io::ErrorKind),
+    // The observer stopped the program, as a debugger does when its client
+    // disconnects.
+    Stopped,
+}
+
+// A resource whose use by a running program is limited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Fuel,
+    CallDepth,
+    Allocation,
+}
+
+impl Resource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resource::Fuel => "fuel",
+            Resource::CallDepth => "call depth",
+            Resource::Allocation => "allocation",
+        }
+    }
+}
+
+// How much a program may use of each resource, for running code that is not
+// trusted. A program that exceeds a limit stops with a
+// `RuntimeErrorKind::ResourceLimit` error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    // The number of steps the program may take: statements and loop
+    // iterations in the interpreter, instructions in the VM.
+    pub fuel: Option<u64>,
+    // The number of calls that may be in progress at once, counting the
+    // top-level code as one. The interpreter recurses on the native stack,
+    // so it always needs a limit.
+    pub max_depth: usize,
+    // The number of bytes the program may allocate over its whole run, for
+    // function values and what they capture. Memory freed as values are
+    // dropped still counts, so this is not a limit on the heap's live size:
+    // a loop that makes function values uses it up however few it keeps.
+    pub max_allocated: Option<usize>,
+}
+
+impl Default for Limits {
+    // No limits but a call depth the interpreter can reach on a thread
+    // with a 2 MiB stack, the default for threads other than the main one.
+    fn default() -> Limits {
+        Limits {
+            fuel: None,
+            max_depth: 128,
+            max_allocated: None,
+        }
+    }
+}
+
+// Counts what a running program uses against its limits.
+#[derive(Debug, Clone)]
+pub(crate) struct Meter {
+    pub(crate) limits: Limits,
+    fuel: u64,
+    allocated: usize,
+}
+
+impl Meter {
+    pub(crate) fn new(limits: Limits) -> Meter {
+        Meter {
+            limits,
+            fuel: 0,
+            allocated: 0,
+        }
+    }
+
+    // Takes a step at `span`.
+    pub(crate) fn step(&mut self, span: Span) -> Result<(), RuntimeError> {
+        self.fuel += 1;
+        match self.limits.fuel {
+            Some(fuel) if self.fuel > fuel => Err(exceeded(Resource::Fuel, span)),
+            _ => Ok(()),
+        }
+    }
+
+    // Checks a call at `span` that would make `depth` calls in progress.
+    pub(crate) fn call(&self, depth: usize, span: Span) -> Result<(), RuntimeError> {
+        if depth > self.limits.max_depth {
+            return Err(exceeded(Resource::CallDepth, span));
+        }
+        Ok(())
+    }
+
+    // Allocates a function value at `span`.
+    pub(crate) fn allocate(&mut self, closure: &Closure, span: Span) -> Result<(), RuntimeError> {
+        self.allocated += closure.size();
+        match self.limits.max_allocated {
+            Some(max) if self.allocated > max => Err(exceeded(Resource::Allocation, span)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn exceeded(resource: Resource, span: Span) -> RuntimeError {
+    RuntimeError::new(RuntimeErrorKind::ResourceLimit(resource), span)
+}
+
+impl RuntimeErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeErrorKind::Arithmetic(ArithmeticError::DivisionByZero) => "E0800",
+            RuntimeErrorKind::Arithmetic(ArithmeticError::NegativeExponent) => "E0801",
+            RuntimeErrorKind::Arithmetic(ArithmeticError::Operands(..)) => "E0802",
+            RuntimeErrorKind::NoBody(_) => "E0803",
+            RuntimeErrorKind::UnknownFunction(_) => "E0804",
+            RuntimeErrorKind::ResourceLimit(_) => "E0805",
+            RuntimeErrorKind::Io(_) => "E0806",
+            RuntimeErrorKind::Stopped => "E0807",
+        }
+    }
+}
+
+// An error that stops a running program, at the expression that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Span,
+    // The calls in progress when it happened, innermost first. The top-level
+    // code is not a call, so an error there has none.
+    pub backtrace: Vec<Call>,
+}
+
+// A call in progress: the function running and where it was called, or
+// `None` if it was called from outside the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    pub function: Symbol,
+    pub span: Option<Span>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, span: Span) -> RuntimeError {
+        RuntimeError {
+            kind,
+            span,
+            backtrace: vec![],
+        }
+    }
+
+    // Reports the error with a note for each call in the backtrace:
+    //
+    //   error[E0800]: division by zero
+    //    --> main:2:34
+    //     |
+    //   2 | fn f(n: int64) -> int64 { return 1 / n; }
+    //     |                                  ^^^^^
+    //     = note: in `f`, called at main:3:29
+    //     = note: in `main`
+    //
+    // Runs of calls from the same place, as in a recursion, share a note.
+    pub fn to_diagnostic(&self, map: &SourceMap) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(self.kind.code(), self.to_string(), self.span);
+        let mut calls = self.backtrace.iter().peekable();
+        while let Some(call) = calls.next() {
+            let mut count = 1;
+            while calls.next_if_eq(&call).is_some() {
+                count += 1;
+            }
+            let mut note = format!("in `{}`", call.function);
+            if let Some(span) = call.span {
+                let (line, column) = map.location(span.start);
+                note.push_str(&format!(
+                    ", called at {}:{}:{}",
+                    map.name_at(span.start),
+                    line,
+                    column
+                ));
+            }
+            if count > 1 {
+                note.push_str(&format!(" ({} times)", count));
+            }
+            diagnostic = diagnostic.with_note(note);
+        }
+        diagnostic
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            RuntimeErrorKind::Arithmetic(error) => error.fmt(f),
+            RuntimeErrorKind::NoBody(name) => write!(f, "function `{}` has no body", name),
+            RuntimeErrorKind::UnknownFunction(name) => write!(f, "no function named `{}`", name),
+            RuntimeErrorKind::ResourceLimit(resource) => {
+                write!(f, "resource limit exceeded: {}", resource.name())
+            }
+            RuntimeErrorKind::Io(kind) => write!(f, "I/O error: {}", kind),
+            RuntimeErrorKind::Stopped => f.write_str("the program was stopped"),
+        }
+    }
+}
+
+// What an observer sees before each step of a running program.
+pub struct Step<'a> {
+    // The statement about to run, or for the VM the instruction.
+    pub span: Span,
+    // The number of calls in progress, counting the top-level code as one.
+    pub depth: usize,
+    // The name of the running function, or `None` for the top-level code.
+    pub function: Option<Symbol>,
+    // The running function's parameters and the locals in scope, in the
+    // order they were declared. Globals and captured bindings are not
+    // included.
+    pub locals: &'a [(Binding, Value)],
+}
+
+// Watches a program run, for a debugger, an execution tracer or coverage
+// measurement. The interpreter calls it before each statement it executes,
+// and the VM before each instruction. If it breaks, the step does not run
+// and the program stops with a `RuntimeErrorKind::Stopped` error.
+pub trait Observer {
+    fn step(&mut self, step: &Step) -> ControlFlow<()>;
+}
+
+impl<F: FnMut(&Step) -> ControlFlow<()>> Observer for F {
+    fn step(&mut self, step: &Step) -> ControlFlow<()> {
+        self(step)
+    }
+}
+
+// How control leaves a statement.
+enum Flow {
+    Next,
+    Return(Option<Value>),
+}
+
+// A tree-walking interpreter for lowered programs.
+//
+// `run` executes the top-level statements, which define the globals, and
+// `call` then calls a top-level function. A nested function captures every
+// binding visible where it is declared.
+//
+// The program must be free of errors: it panics on `ExpressionKind::Error`.
+pub struct Interpreter<'h> {
+    program: &'h hir::Program,
+    // Every function in the program, indexed by `Closure::index`.
+    functions: Vec<&'h hir::Function>,
+    indices: HashMap<NodeId, u32>,
+    // The type of every binding, for checking the values a snapshot
+    // restores.
+    types: HashMap<NodeId, Ty>,
+    top_level: HashSet<NodeId>,
+    // The `let` bindings at the top level, and the values of those defined.
+    global_ids: HashSet<NodeId>,
+    globals: HashMap<NodeId, Value>,
+    depth: usize,
+    meter: Meter,
+    observer: Option<Box<dyn Observer + 'h>>,
+    // Where the I/O builtins read and write, if not stdin and stdout.
+    streams: Option<Streams<'h>>,
+}
+
+// A call in progress.
+struct Frame {
+    // The function running, or `None` for the top-level code.
+    closure: Option<Rc<Closure>>,
+    // Its parameters and the locals in scope, in the order they were
+    // declared.
+    locals: Vec<(Binding, Value)>,
+}
+
+impl<'h> Interpreter<'h> {
+    pub fn new(program: &'h hir::Program) -> Interpreter<'h> {
+        let mut interpreter = Interpreter {
+            program,
+            functions: vec![],
+            indices: HashMap::new(),
+            types: HashMap::new(),
+            top_level: HashSet::new(),
+            global_ids: HashSet::new(),
+            globals: HashMap::new(),
+            depth: 0,
+            meter: Meter::new(Limits::default()),
+            observer: None,
+            streams: None,
+        };
+        interpreter.collect(&program.statements);
+        for statement in &program.statements {
+            match statement {
+                Statement::Function(function) => {
+                    interpreter.top_level.insert(function.binding.id);
+                }
+                Statement::Let(let_statement) => {
+                    interpreter.global_ids.insert(let_statement.binding.id);
+                }
+                _ => {}
+            }
+        }
+        interpreter
+    }
+
+    // Limits what the program may use from now on.
+    pub fn limit(&mut self, limits: Limits) {
+        self.meter = Meter::new(limits);
+    }
+
+    // Sets the observer called before each statement.
+    pub fn observe(&mut self, observer: impl Observer + 'h) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    // Sets where the I/O builtins read and write.
+    pub fn redirect(&mut self, streams: Streams<'h>) {
+        self.streams = Some(streams);
+    }
+
+    fn collect(&mut self, statements: &'h [Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Function(function) => {
+                    let index = self.functions.len() as u32;
+                    self.functions.push(function);
+                    self.indices.insert(function.binding.id, index);
+                    self.types
+                        .insert(function.binding.id, function_type(function));
+                    for parameter in &function.parameters {
+                        self.types
+                            .insert(parameter.binding.id, parameter.ty.clone());
+                    }
+                    if let Some(body) = &function.body {
+                        self.collect(&body.statements);
+                    }
+                }
+                Statement::If(if_statement) => {
+                    self.collect(&if_statement.then_block.statements);
+                    if let Some(else_block) = &if_statement.else_block {
+                        self.collect(&else_block.statements);
+                    }
+                }
+                Statement::While(while_statement) => self.collect(&while_statement.body.statements),
+                Statement::Block(block) => self.collect(&block.statements),
+                Statement::Let(let_statement) => {
+                    let id = let_statement.binding.id;
+                    self.types.insert(id, let_statement.ty.clone());
+                }
+                Statement::Expression(_) | Statement::Return(_) => {}
+            }
+        }
+    }
+
+    // Executes the program's top-level statements.
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        let program = self.program;
+        let mut frame = Frame {
+            closure: None,
+            locals: vec![],
+        };
+        self.depth += 1;
+        let result = self.statements(&program.statements, &mut frame);
+        self.depth -= 1;
+        result.map(|_| ())
+    }
+
+    // Calls a top-level function by name, returning its result or `None`
+    // for a function that returns unit. Globals it uses must have been
+    // defined by `run`.
+    pub fn call(
+        &mut self,
+        name: &str,
+        arguments: Vec<Value>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let symbol = Symbol::new(name);
+        let function = self
+            .program
+            .statements
+            .iter()
+            .find_map(|statement| match statement {
+                Statement::Function(function) if function.binding.name == symbol => Some(function),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                RuntimeError::new(
+                    RuntimeErrorKind::UnknownFunction(name.to_string()),
+                    Span::new(0, 0),
+                )
+            })?;
+        let closure = self.closure(function.binding.clone(), vec![], function.span)?;
+        self.apply(&closure, arguments, function.span)
+    }
+
+    // Returns the globals defined so far, as bytes that `restore` accepts.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let globals: Vec<_> = self
+            .global_bindings()
+            .filter_map(|binding| Some((binding.name.clone(), self.globals.get(&binding.id)?)))
+            .collect();
+        snapshot::encode(&globals)
+    }
+
+    // Defines the globals of a snapshot taken by an interpreter of the same
+    // program, replacing any already defined. A global is matched by its
+    // name and, among those of the same name, its position, and its value
+    // must have the global's type. Nothing changes if the snapshot cannot be
+    // restored.
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), SnapshotError> {
+        let function = |name: Symbol, index: u32| {
+            self.functions
+                .get(index as usize)
+                .is_some_and(|function| function.binding.name == name)
+        };
+        let mut bindings: Vec<_> = self.global_bindings().collect();
+        let mut globals = vec![];
+        for (name, value) in snapshot::decode(bytes, &function)? {
+            let position = bindings
+                .iter()
+                .position(|binding| binding.name == name)
+                .ok_or(SnapshotError::UnknownGlobal(name.clone()))?;
+            let binding = bindings.remove(position);
+            if !self.has_type(&value, &self.types[&binding.id]) {
+                return Err(SnapshotError::TypeMismatch(name));
+            }
+            globals.push((binding.id, value));
+        }
+        self.globals.extend(globals);
+        Ok(())
+    }
+
+    // Whether a restored value has type `ty`, as do the values a function
+    // captures.
+    fn has_type(&self, value: &Value, ty: &Ty) -> bool {
+        match (value, ty) {
+            (Value::Function(closure), _) => {
+                // The snapshot's function indices have been checked.
+                let function = self.functions[closure.index as usize];
+                function_type(function) == *ty
+                    && closure.captures.iter().all(|(id, value)| {
+                        self.types
+                            .get(id)
+                            .is_some_and(|ty| self.has_type(value, ty))
+                    })
+            }
+            (value, Ty::Primitive(kind)) => value.kind() == Some(*kind),
+            _ => false,
+        }
+    }
+
+    // The bindings of the top-level `let` statements, in order.
+    fn global_bindings(&self) -> impl Iterator<Item = &'h Binding> + 'h {
+        self.program
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Let(let_statement) => Some(&let_statement.binding),
+                _ => None,
+            })
+    }
+
+    // Creates a function value at `span`.
+    fn closure(
+        &mut self,
+        binding: Binding,
+        captures: Vec<(NodeId, Value)>,
+        span: Span,
+    ) -> Result<Rc<Closure>, RuntimeError> {
+        let closure = Closure {
+            name: binding.name,
+            index: self.indices[&binding.id],
+            captures,
+        };
+        self.meter.allocate(&closure, span)?;
+        Ok(Rc::new(closure))
+    }
+
+    // Calls a function value. `span` is the call, for errors.
+    fn apply(
+        &mut self,
+        closure: &Rc<Closure>,
+        arguments: Vec<Value>,
+        span: Span,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let function = self.functions[closure.index as usize];
+        let Some(body) = &function.body else {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::NoBody(function.binding.name.clone()),
+                span,
+            ));
+        };
+        self.meter.call(self.depth + 1, span)?;
+        let mut frame = Frame {
+            closure: Some(closure.clone()),
+            locals: function
+                .parameters
+                .iter()
+                .map(|parameter| parameter.binding.clone())
+                .zip(arguments)
+                .collect(),
+        };
+        // Only calls from the program itself happen with a call in progress.
+        let call = Call {
+            function: function.binding.name.clone(),
+            span: (self.depth > 0).then_some(span),
+        };
+        self.depth += 1;
+        let flow = self.statements(&body.statements, &mut frame);
+        self.depth -= 1;
+        let flow = flow.map_err(|mut error| {
+            error.backtrace.push(call);
+            error
+        });
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            Flow::Next => Ok(None),
+        }
+    }
+
+    // Returns the value of a binding used at `span` where `frame` is
+    // running.
+    fn lookup(
+        &mut self,
+        binding: &Binding,
+        frame: &Frame,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        if let Some((_, value)) = frame.locals.iter().rev().find(|(b, _)| b.id == binding.id) {
+            return Ok(value.clone());
+        }
+        if let Some(closure) = &frame.closure {
+            if self.functions[closure.index as usize].binding.id == binding.id {
+                return Ok(Value::Function(closure.clone()));
+            }
+            if let Some((_, value)) = closure.captures.iter().find(|(id, _)| *id == binding.id) {
+                return Ok(value.clone());
+            }
+        }
+        match self.globals.get(&binding.id) {
+            Some(value) => Ok(value.clone()),
+            None => Ok(Value::Function(self.closure(
+                binding.clone(),
+                vec![],
+                span,
+            )?)),
+        }
+    }
+
+    // Runs the statements of a block, whose bindings go out of scope at its
+    // end.
+    fn block(&mut self, block: &hir::Block, frame: &mut Frame) -> Result<Flow, RuntimeError> {
+        let mark = frame.locals.len();
+        let flow = self.statements(&block.statements, frame);
+        frame.locals.truncate(mark);
+        flow
+    }
+
+    fn statements(
+        &mut self,
+        statements: &[Statement],
+        frame: &mut Frame,
+    ) -> Result<Flow, RuntimeError> {
+        for statement in statements {
+            if let Some(observer) = &mut self.observer {
+                let step = Step {
+                    span: statement.span(),
+                    depth: self.depth,
+                    function: frame.closure.as_ref().map(|closure| closure.name.clone()),
+                    locals: &frame.locals,
+                };
+                if observer.step(&step).is_break() {
+                    return Err(RuntimeError::new(RuntimeErrorKind::Stopped, step.span));
+                }
+            }
+            self.meter.step(statement.span())?;
+            if let Flow::Return(value) = self.statement(statement, frame)? {
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(
+        &mut self,
+        statement: &Statement,
+        frame: &mut Frame,
+    ) -> Result<Flow, RuntimeError> {
+        match statement {
+            Statement::Let(let_statement) => {
+                let value = self.value(&let_statement.value, frame)?;
+                if self.global_ids.contains(&let_statement.binding.id) {
+                    self.globals.insert(let_statement.binding.id, value);
+                } else {
+                    frame.locals.push((let_statement.binding.clone(), value));
+                }
+            }
+            Statement::Function(function) => {
+                if !self.top_level.contains(&function.binding.id) {
+                    // A nested function captures everything the running
+                    // function can see except globals.
+                    let mut captures: Vec<(NodeId, Value)> = frame
+                        .locals
+                        .iter()
+                        .map(|(binding, value)| (binding.id, value.clone()))
+                        .collect();
+                    if let Some(closure) = &frame.closure {
+                        captures.extend(closure.captures.iter().cloned());
+                        let id = self.functions[closure.index as usize].binding.id;
+                        captures.push((id, Value::Function(closure.clone())));
+                    }
+                    let closure =
+                        self.closure(function.binding.clone(), captures, function.span)?;
+                    frame
+                        .locals
+                        .push((function.binding.clone(), Value::Function(closure)));
+                }
+            }
+            Statement::Expression(expression) => {
+                self.expression(expression, frame)?;
+            }
+            Statement::Return(return_statement) => {
+                let value = match &return_statement.value {
+                    Some(value) => Some(self.value(value, frame)?),
+                    None => None,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Statement::If(if_statement) => {
+                if self.condition(&if_statement.condition, frame)? {
+                    return self.block(&if_statement.then_block, frame);
+                } else if let Some(else_block) = &if_statement.else_block {
+                    return self.block(else_block, frame);
+                }
+            }
+            Statement::While(while_statement) => {
+                while self.condition(&while_statement.condition, frame)? {
+                    self.meter.step(while_statement.condition.span)?;
+                    let flow = self.block(&while_statement.body, frame)?;
+                    if let Flow::Return(_) = flow {
+                        return Ok(flow);
+                    }
+                }
+            }
+            Statement::Block(block) => return self.block(block, frame),
+        }
+        Ok(Flow::Next)
+    }
+
+    fn condition(
+        &mut self,
+        expression: &Expression,
+        frame: &mut Frame,
+    ) -> Result<bool, RuntimeError> {
+        match self.value(expression, frame)? {
+            Value::Bool(value) => Ok(value),
+            other => panic!("condition of type `{}`", other.type_name()),
+        }
+    }
+
+    // Evaluates an expression that is not a call to a function returning
+    // unit.
+    fn value(&mut self, expression: &Expression, frame: &mut Frame) -> Result<Value, RuntimeError> {
+        Ok(self
+            .expression(expression, frame)?
+            .expect("an expression of type `()` used as a value"))
+    }
+
+    // Evaluates an expression, returning `None` for a call to a function
+    // that returns unit.
+    fn expression(
+        &mut self,
+        expression: &Expression,
+        frame: &mut Frame,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let value = match &expression.kind {
+            ExpressionKind::Integer(value) => {
+                let kind = match expression.ty {
+                    Ty::Primitive(kind) => kind,
+                    _ => TypeKind::Int32,
+                };
+                Value::from_const(ConstValue::Integer(*value), kind)
+                    .expect("literal of the wrong type")
+            }
+            ExpressionKind::Bool(value) => Value::Bool(*value),
+            ExpressionKind::Name(binding) => self.lookup(binding, frame, expression.span)?,
+            ExpressionKind::Binary(operator, left, right) => {
+                let left = self.value(left, frame)?;
+                let right = self.value(right, frame)?;
+                left.binary(operator, &right).map_err(|error| {
+                    RuntimeError::new(RuntimeErrorKind::Arithmetic(error), expression.span)
+                })?
+            }
+            ExpressionKind::Call(callee, arguments) => {
+                let Value::Function(closure) = self.value(callee, frame)? else {
+                    panic!("call of a value that is not a function");
+                };
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.value(argument, frame))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return self.apply(&closure, arguments, expression.span);
+            }
+            ExpressionKind::Builtin(builtin, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.value(argument, frame))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return builtin::call(&mut self.streams, *builtin, &arguments, expression.span);
+            }
+            ExpressionKind::Cast(operand) => {
+                let Ty::Primitive(kind) = expression.ty else {
+                    panic!("cast to `{}`", expression.ty);
+                };
+                self.value(operand, frame)?
+                    .cast(kind)
+                    .expect("the type checker allows only valid casts")
+            }
+            ExpressionKind::Error => panic!("cannot run a program with errors"),
+        };
+        Ok(Some(value))
+    }
+}
+
+// The type of a function's value.
+fn function_type(function: &hir::Function) -> Ty {
+    let parameters = function.parameters.iter().map(|p| p.ty.clone()).collect();
+    Ty::Function(parameters, Box::new(function.return_type.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    #[test]
+    fn programs_run_to_a_result() {
+        let source = "\
+let scale: int8 = 100;
+fn apply(f: fn(int8) -> int8, x: int8) -> int8 { return f(x); }
+fn main() -> int8 {
+    let offset: int8 = 2;
+    fn step(x: int8) -> int8 { return x * scale + offset; }
+    return apply(step, 3);
+}
+fn broken() -> int32 { let zero: int32 = 0; return 1 / zero; }";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        assert!(program.typecheck().errors.is_empty());
+        let hir = program.to_hir();
+        let mut interpreter = Interpreter::new(&hir);
+        interpreter.run().unwrap();
+        // 3 * 100 wraps around to 44 in an `int8`.
+        assert_eq!(
+            interpreter.call("main", vec![]),
+            Ok(Some(Value::integer(TypeKind::Int8, 46)))
+        );
+        let error = interpreter.call("broken", vec![]).unwrap_err();
+        assert_eq!(error.to_string(), "division by zero");
+        assert_eq!(&source[error.span.range()], "1 / zero");
+    }
+
+    #[test]
+    fn observers_see_each_statement_before_it_runs() {
+        let source = "\
+fn f(n: int64) -> int64 {
+    let a: int64 = n + 1;
+    if a > 10 {
+        let b: int64 = a * 2;
+        return b;
+    }
+    return a;
+}
+f(1);";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let hir = program.to_hir();
+        let mut steps = vec![];
+        let mut interpreter = Interpreter::new(&hir);
+        interpreter.observe(|step: &Step| {
+            let locals: Vec<String> = step
+                .locals
+                .iter()
+                .map(|(binding, value)| format!("{}={}", binding.name, value))
+                .collect();
+            let statement = source[step.span.range()].lines().next().unwrap();
+            let function = step
+                .function
+                .as_ref()
+                .map_or("-".to_string(), |f| f.to_string());
+            steps.push(format!(
+                "{} {} {} [{}]",
+                step.depth,
+                function,
+                statement,
+                locals.join(" ")
+            ));
+            ControlFlow::Continue(())
+        });
+        interpreter.run().unwrap();
+        drop(interpreter);
+        // The `if` body never runs, so coverage would leave it out.
+        assert_eq!(
+            steps,
+            [
+                "1 - fn f(n: int64) -> int64 { []",
+                "1 - f(1) []",
+                "2 f let a: int64 = n + 1; [n=1]",
+                "2 f if a > 10 { [n=1 a=2]",
+                "2 f return a; [n=1 a=2]",
+            ]
+        );
+    }
+}
@@ -1,6 +1,10 @@
+use crate::ast::Span;
+use crate::diagnostic::{Diagnostic, DiagnosticSink};
 use crate::token::Kind;
-use crate::token::Token;
-use std::str;
+use crate::token::{Token, TokenStream};
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::str;
 
 pub struct Lexer<'a> {
     input: &'a [u8],
@@ -9,17 +13,30 @@ pub struct Lexer<'a> {
     byte: u8,
 }
 impl<'a> Lexer<'a> {
-    fn new(input: &'a str) -> Lexer {
+    // Creates a lexer that reads the tokens of the input as the parser, or
+    // any other reader of a `TokenStream`, asks for them, without holding
+    // more than one at a time.
+    pub fn new(input: &'a str) -> Lexer<'a> {
+        Lexer::new_at(input, 0)
+    }
+
+    // Creates a lexer that starts reading at the given byte offset.
+    fn new_at(input: &'a str, start: usize) -> Lexer<'a> {
         let mut lexer = Lexer {
             input: input.as_bytes(),
-            position: 0,
-            read_position: 0,
+            position: start,
+            read_position: start,
             byte: 0,
         };
         lexer.step();
         lexer
     }
 
+    // Returns the byte at an offset, if it is in the input.
+    fn byte_at(&self, offset: usize) -> Option<u8> {
+        self.input.get(offset).copied()
+    }
+
     // Returns the current character.
     fn char(&self) -> char {
         char::from(self.byte)
@@ -27,12 +44,12 @@ impl<'a> Lexer<'a> {
 
     // Advances the lexer.
     fn step(&mut self) {
-        self.byte = match self.input.get(self.read_position) {
+        self.byte = match self.byte_at(self.read_position) {
             None => 0,
             Some(b) => {
                 self.position = self.read_position;
                 self.read_position += 1;
-                *b
+                b
             }
         }
     }
@@ -41,17 +58,14 @@ impl<'a> Lexer<'a> {
     fn reset(&mut self, position: usize) {
         self.position = position;
         self.read_position = position + 1;
-        self.byte = match self.input.get(self.position) {
-            None => 0,
-            Some(b) => *b,
-        }
+        self.byte = self.byte_at(self.position).unwrap_or(0);
     }
 
     // Returns the next character without advancing the lexer.
     fn peek_char(&self) -> char {
-        match self.input.get(self.read_position) {
+        match self.byte_at(self.read_position) {
             None => '\0',
-            Some(c) => char::from(*c),
+            Some(c) => char::from(c),
         }
     }
 
@@ -86,7 +100,7 @@ impl<'a> Lexer<'a> {
 
     // Attempts to read an identifier token, potentially advancing the lexer.
     fn maybe_read_identifier(&mut self) -> Option<Token<'a>> {
-        if !self.char().is_ascii_alphabetic() {
+        if !self.char().is_ascii_alphabetic() && self.char() != '_' {
             return None;
         }
 
@@ -124,24 +138,38 @@ impl<'a> Lexer<'a> {
 
     // Attempts to read a symbol token, potentially advancing the lexer.
     fn maybe_read_symbol(&mut self) -> Option<Token<'a>> {
-        return if self.char() == '=' {
-            Some(self.char_token(Kind::EqualSign))
+        if self.char() == '=' {
+            Some(self.maybe_read_equals(Kind::EqualSign, Kind::EqualEqual))
+        } else if self.char() == '!' && self.peek_char() == '=' {
+            let start = self.position;
+            self.step();
+            Some(self.text_token(start, Kind::NotEqual))
         } else if self.char() == ':' {
             Some(self.char_token(Kind::Colon))
         } else if self.char() == '+' {
             Some(self.char_token(Kind::Plus))
         } else if self.char() == '-' {
-            return if self.peek_char() == '>' {
+            if self.peek_char() == '>' {
                 let start = self.position;
                 self.step();
                 Some(self.text_token(start, Kind::Arrow))
             } else {
                 Some(self.char_token(Kind::Minus))
-            };
+            }
         } else if self.char() == '/' {
             Some(self.char_token(Kind::Divide))
+        } else if self.char() == '<' {
+            Some(self.maybe_read_equals(Kind::LessThan, Kind::LessEqual))
+        } else if self.char() == '>' {
+            Some(self.maybe_read_equals(Kind::GreaterThan, Kind::GreaterEqual))
         } else if self.char() == '*' {
-            Some(self.char_token(Kind::Star))
+            if self.peek_char() == '*' {
+                let start = self.position;
+                self.step();
+                Some(self.text_token(start, Kind::StarStar))
+            } else {
+                Some(self.char_token(Kind::Star))
+            }
         } else if self.char() == '(' {
             Some(self.char_token(Kind::LeftParenthesis))
         } else if self.char() == ')' {
@@ -160,7 +188,19 @@ impl<'a> Lexer<'a> {
             Some(self.char_token(Kind::Comma))
         } else {
             None
-        };
+        }
+    }
+
+    // Reads the current character as a `single` token, or together with a
+    // following '=' as a `with_equals` token.
+    fn maybe_read_equals(&mut self, single: Kind, with_equals: Kind) -> Token<'a> {
+        if self.peek_char() == '=' {
+            let start = self.position;
+            self.step();
+            self.text_token(start, with_equals)
+        } else {
+            self.char_token(single)
+        }
     }
 
     // Attempts to read an integer token, potentially advancing the lexer.
@@ -172,7 +212,7 @@ impl<'a> Lexer<'a> {
         while self.peek_char().is_ascii_digit() {
             self.step();
         }
-        return Some(self.text_token(start, Kind::IntegerLiteral));
+        Some(self.text_token(start, Kind::IntegerLiteral))
     }
 
     // Attempts to read a string token, potentially advancing the lexer.
@@ -196,10 +236,16 @@ impl<'a> Lexer<'a> {
         Some(token)
     }
 
+    // Attempts to read a comment or doc comment token, potentially advancing the
+    // lexer. Doc comments start with `##`.
     fn maybe_read_comment(&mut self) -> Option<Token<'a>> {
         if self.char() != '#' {
             return None;
         }
+        let kind = match self.peek_char() {
+            '#' => Kind::DocComment,
+            _ => Kind::Comment,
+        };
         let start = self.position;
         while self.peek_char() != '\n' {
             self.step();
@@ -209,7 +255,7 @@ impl<'a> Lexer<'a> {
                 return None;
             }
         }
-        let token = Some(self.text_token(start, Kind::Comment));
+        let token = Some(self.text_token(start, kind));
         self.step(); // consume the newline.
         token
     }
@@ -219,37 +265,30 @@ impl<'a> Lexer<'a> {
         if self.char() == '\0' {
             Token::end_of_file(self.position)
         } else if let Some(t) = self.maybe_read_whitespace() {
-            return t;
+            t
         } else if let Some(t) = self.maybe_read_comment() {
-            return t;
+            t
         } else if let Some(t) = self.maybe_read_symbol() {
-            return t;
+            t
         } else if let Some(t) = self.maybe_read_keyword() {
-            return t;
+            t
         } else if let Some(t) = self.maybe_read_string() {
-            return t;
+            t
         } else if let Some(t) = self.maybe_read_integer() {
-            return t;
+            t
         } else if let Some(t) = self.maybe_read_identifier() {
-            return t;
+            t
         } else {
             let start = self.position;
             while self.char() != '\0' {
                 self.step();
             }
-            return self.text_token(start, Kind::Unknown);
+            self.text_token(start, Kind::Unknown)
         }
     }
 
-    // Reads the next token and advances the lexer.
-    fn next_token(&mut self) -> Token<'a> {
-        let token = self.read_token();
-        self.step();
-        token
-    }
-
     // Converts a string into a vector of tokens.
-    pub fn tokenize(input_text: &str) -> Vec<Token> {
+    pub fn tokenize(input_text: &str) -> Vec<Token<'_>> {
         let mut lexer = Lexer::new(input_text);
         let mut tokens = Vec::<Token>::new();
         let mut t = lexer.next_token();
@@ -260,7 +299,52 @@ impl<'a> Lexer<'a> {
         tokens.push(t);
         tokens
     }
+
+    // Converts a byte range of a string into a vector of tokens.
+    //
+    // Lexing starts at `range.start` and stops at the first token that starts
+    // at or after `range.end`, which is replaced by an end-of-file token. Token
+    // offsets are relative to the whole input. The last token before the
+    // end-of-file token may extend past `range.end`.
+    pub fn tokenize_range(input_text: &str, range: Range<usize>) -> Vec<Token<'_>> {
+        let mut lexer = Lexer::new_at(input_text, range.start);
+        let mut tokens = Vec::<Token>::new();
+        let mut t = lexer.next_token();
+        while t.kind() != Kind::EndOfFile {
+            if t.offset() >= range.end {
+                t = Token::end_of_file(range.end);
+                break;
+            }
+            tokens.push(t);
+            t = lexer.next_token();
+        }
+        tokens.push(t);
+        tokens
+    }
+}
+impl<'a> TokenStream<'a> for Lexer<'a> {
+    // Reads the next token and advances the lexer.
+    fn next_token(&mut self) -> Token<'a> {
+        let token = self.read_token();
+        self.step();
+        token
+    }
 }
+
+// Reports every unknown token. The lexer gives up at the first character it
+// cannot read, so an unknown token covers the rest of the input.
+pub fn report_unknown_tokens(tokens: &[Token], sink: &mut dyn DiagnosticSink) {
+    for token in tokens.iter().filter(|t| t.kind() == Kind::Unknown) {
+        let span = Span::new(token.offset(), token.offset() + token.len());
+        let diagnostic = Diagnostic::error("E0001", "unrecognized input", span);
+        sink.emit(if token.text().starts_with('"') {
+            diagnostic.with_note("the string literal is missing its closing `\"`")
+        } else {
+            diagnostic
+        });
+    }
+}
+
 // Returns the 1-based line number of the given Token.
 pub fn get_line(token: &Token) -> usize {
     assert!(token.kind() != Kind::EndOfFile);
@@ -286,7 +370,7 @@ pub fn get_column(token: &Token) -> usize {
     column
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -443,6 +527,18 @@ mod tests {
         ],
     }
 
+    lexer_test_case! {
+        power,
+        "4 ** 2 * 3",
+        &[
+            ("4", Kind::IntegerLiteral),
+            ("**", Kind::StarStar),
+            ("2", Kind::IntegerLiteral),
+            ("*", Kind::Star),
+            ("3", Kind::IntegerLiteral),
+        ],
+    }
+
     lexer_test_case! {
         braces_brackets_and_parens,
         "()[]{}",
@@ -456,6 +552,70 @@ mod tests {
         ],
     }
 
+    lexer_test_case! {
+        angle_brackets,
+        "List<int32>",
+        &[
+            ("List", Kind::Identifier),
+            ("<", Kind::LessThan),
+            ("int32", Kind::Identifier),
+            (">", Kind::GreaterThan),
+        ],
+    }
+
+    lexer_test_case! {
+        comparisons,
+        "a == b != c <= d >= e < f > g = h",
+        &[
+            ("a", Kind::Identifier),
+            ("==", Kind::EqualEqual),
+            ("b", Kind::Identifier),
+            ("!=", Kind::NotEqual),
+            ("c", Kind::Identifier),
+            ("<=", Kind::LessEqual),
+            ("d", Kind::Identifier),
+            (">=", Kind::GreaterEqual),
+            ("e", Kind::Identifier),
+            ("<", Kind::LessThan),
+            ("f", Kind::Identifier),
+            (">", Kind::GreaterThan),
+            ("g", Kind::Identifier),
+            ("=", Kind::EqualSign),
+            ("h", Kind::Identifier),
+        ],
+    }
+
+    lexer_test_case! {
+        control_flow_keywords,
+        "if else while",
+        &[
+            ("if", Kind::If),
+            ("else", Kind::Else),
+            ("while", Kind::While),
+        ],
+    }
+
+    lexer_test_case! {
+        leading_underscores,
+        "_unused _ a_b",
+        &[
+            ("_unused", Kind::Identifier),
+            ("_", Kind::Identifier),
+            ("a_b", Kind::Identifier),
+        ],
+    }
+
+    lexer_test_case! {
+        as_keyword,
+        "x as int8 asx",
+        &[
+            ("x", Kind::Identifier),
+            ("as", Kind::As),
+            ("int8", Kind::Identifier),
+            ("asx", Kind::Identifier),
+        ],
+    }
+
     lexer_test_case! {
         fn_keyword_arrow_and_return,
         "fn sq(x: int32) -> int32 {
@@ -521,6 +681,16 @@ mod tests {
         ],
     }
 
+    lexer_test_case! {
+        doc_comment,
+        "## Adds two numbers.\nfn add",
+        &[
+            ("## Adds two numbers.", Kind::DocComment),
+            ("fn", Kind::Fn),
+            ("add", Kind::Identifier),
+        ],
+    }
+
     #[test]
     fn test_row_and_column() {
         let input_source = "\
@@ -579,4 +749,38 @@ let z = x + y
             assert_eq!(get_column(&tokens[i]), expected_token.3);
         }
     }
+
+    #[test]
+    fn unknown_tokens_are_reported() {
+        let mut diagnostics = vec![];
+        report_unknown_tokens(&Lexer::tokenize("let x = \"oops;"), &mut diagnostics);
+        report_unknown_tokens(&Lexer::tokenize("x + y;"), &mut diagnostics);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error[E0001]: unrecognized input"
+        );
+        assert_eq!(diagnostics[0].span, Span::new(8, 14));
+        assert_eq!(
+            diagnostics[0].notes,
+            ["the string literal is missing its closing `\"`"]
+        );
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn any_text_lexes_without_panicking(source in "\\PC{0,64}") {
+            let mut lexer = Lexer::new(&source);
+            let mut end = 0;
+            loop {
+                let token = lexer.next_token();
+                if token.kind() == Kind::EndOfFile {
+                    break;
+                }
+                let _ = (token.text(), get_line(&token), get_column(&token));
+                end = token.offset() + token.len();
+            }
+            proptest::prop_assert!(end <= source.len());
+        }
+    }
 }
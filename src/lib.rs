@@ -1,5 +1,118 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod analyze;
 pub mod ast;
+#[cfg(feature = "std")]
+pub mod builtin;
+#[cfg(feature = "std")]
+pub mod bytecode;
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod callgraph;
+#[cfg(feature = "std")]
+pub mod cfg;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "std")]
+pub mod codegen;
+#[cfg(feature = "std")]
+pub mod consteval;
+#[cfg(feature = "std")]
+pub mod constprop;
+#[cfg(feature = "dap")]
+pub mod dap;
+#[cfg(feature = "std")]
+pub mod database;
+pub mod diagnostic;
+#[cfg(feature = "std")]
+pub mod dump;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fold;
+#[cfg(feature = "std")]
+pub mod folding;
+#[cfg(feature = "std")]
+pub mod format;
+#[cfg(feature = "arbitrary")]
+pub mod generate;
+pub mod grammar;
+#[cfg(feature = "std")]
+pub mod highlight;
+#[cfg(feature = "std")]
+pub mod hir;
+#[cfg(feature = "std")]
+pub mod incremental;
+#[cfg(feature = "std")]
+pub mod interpreter;
 pub mod lexer;
+#[cfg(feature = "std")]
+pub mod lint;
+#[cfg(feature = "std")]
+pub mod loader;
+#[cfg(feature = "std")]
 pub mod matcher;
+#[cfg(feature = "std")]
+pub mod metrics;
+pub mod node;
+#[cfg(feature = "std")]
+pub mod opt;
+#[cfg(feature = "std")]
+pub mod outline;
+#[cfg(feature = "std")]
+pub mod packed;
 pub mod parser;
+#[cfg(feature = "std")]
+pub mod pass;
+#[cfg(feature = "std")]
+pub mod pattern;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+pub mod printer;
+#[cfg(feature = "std")]
+pub mod project;
+#[cfg(feature = "std")]
+pub mod query;
+#[cfg(feature = "std")]
+pub mod references;
+#[cfg(feature = "std")]
+pub mod resolver;
+#[cfg(feature = "std")]
+pub mod rewrite;
+#[cfg(feature = "std")]
+pub mod roundtrip;
+#[cfg(feature = "std")]
+pub mod sarif;
+#[cfg(feature = "std")]
+pub mod semantic_tokens;
+#[cfg(feature = "std")]
+pub mod session;
+#[cfg(feature = "std")]
+pub mod sexp;
+#[cfg(feature = "std")]
+pub mod snapshot;
+pub mod source_map;
+#[cfg(feature = "std")]
+pub mod ssa;
+#[cfg(feature = "std")]
+pub mod suggest;
+pub mod symbol;
+#[cfg(feature = "std")]
+pub mod timings;
 pub mod token;
+#[cfg(feature = "std")]
+pub mod typecheck;
+#[cfg(feature = "std")]
+pub mod value;
+pub mod visit;
+#[cfg(feature = "std")]
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "std")]
+pub use pipeline::{compile, CompileOptions, CompiledProgram, Diagnostics};
@@ -0,0 +1,329 @@
+use crate::{
+    ast::Span,
+    diagnostic::{Diagnostic, DiagnosticSink, Severity},
+    suggest,
+    token::{Kind, Token},
+};
+use std::{collections::HashMap, fmt};
+
+pub mod rules;
+
+// How a lint's diagnostics are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    // Not reported at all.
+    Allow,
+    // Reported as a warning.
+    Warn,
+    // Reported as an error.
+    Deny,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Allow => "allow",
+            Level::Warn => "warn",
+            Level::Deny => "deny",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// A kind of warning that can be allowed, kept as a warning or turned into an
+// error. Each lint owns the diagnostic code its warnings are reported with.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Lint {
+    pub name: &'static str,
+    pub code: &'static str,
+    pub default: Level,
+    pub description: &'static str,
+}
+
+pub static UNUSED: Lint = Lint {
+    name: "unused",
+    code: "W0200",
+    default: Level::Warn,
+    description: "`let` bindings, constants and functions that are never used",
+};
+
+pub static SAME_SCOPE_SHADOWING: Lint = Lint {
+    name: "same_scope_shadowing",
+    code: "W0201",
+    default: Level::Warn,
+    description: "`let` bindings that shadow another in the same scope",
+};
+
+pub static INEXACT_FLOAT_LITERAL: Lint = Lint {
+    name: "inexact_float_literal",
+    code: "W0300",
+    default: Level::Warn,
+    description: "literals that a floating point type cannot represent exactly",
+};
+
+pub static DIVISION_BY_ZERO: Lint = Lint {
+    name: "division_by_zero",
+    code: "W0700",
+    default: Level::Warn,
+    description: "integer divisions whose divisor is always zero",
+};
+
+// The lints from here on are reported by the rules in `rules`, with codes in
+// the W10xx range.
+
+pub static NAMING_CONVENTION: Lint = Lint {
+    name: "naming_convention",
+    code: "W1000",
+    default: Level::Warn,
+    description: "functions, variables and parameters not in snake_case, and constants not in SCREAMING_SNAKE_CASE",
+};
+
+pub static MAGIC_NUMBER: Lint = Lint {
+    name: "magic_number",
+    code: "W1001",
+    default: Level::Allow,
+    description: "integer literals other than 0 and 1 outside constant declarations",
+};
+
+pub static LONG_FUNCTION: Lint = Lint {
+    name: "long_function",
+    code: "W1002",
+    default: Level::Allow,
+    description: "functions whose body is longer than 50 lines",
+};
+
+pub static CONSTANT_CONDITION: Lint = Lint {
+    name: "constant_condition",
+    code: "W1003",
+    default: Level::Warn,
+    description: "`if` conditions that are always true or false, and `while` conditions that are always false",
+};
+
+// Every lint, in the order of their codes.
+pub static LINTS: [&Lint; 8] = [
+    &UNUSED,
+    &SAME_SCOPE_SHADOWING,
+    &INEXACT_FLOAT_LITERAL,
+    &DIVISION_BY_ZERO,
+    &NAMING_CONVENTION,
+    &MAGIC_NUMBER,
+    &LONG_FUNCTION,
+    &CONSTANT_CONDITION,
+];
+
+pub fn find(name: &str) -> Option<&'static Lint> {
+    LINTS.iter().copied().find(|lint| lint.name == name)
+}
+
+// Returns the lint whose warnings have a diagnostic code.
+pub fn for_code(code: &str) -> Option<&'static Lint> {
+    LINTS.iter().copied().find(|lint| lint.code == code)
+}
+
+// The level of each lint, where it differs from the lint's default.
+//
+// Levels are set directly with `set`, or by lint attributes in the source:
+// comments of the form `#[allow(unused)]`, `#[warn(...)]` or `#[deny(...)]`,
+// each naming one or more lints separated by commas. Attributes apply to the
+// whole file, and a later one for the same lint overrides an earlier one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintLevels {
+    levels: HashMap<&'static str, Level>,
+}
+
+impl LintLevels {
+    pub fn new() -> LintLevels {
+        LintLevels::default()
+    }
+
+    pub fn set(&mut self, lint: &'static Lint, level: Level) {
+        self.levels.insert(lint.name, level);
+    }
+
+    pub fn level(&self, lint: &Lint) -> Level {
+        self.levels.get(lint.name).copied().unwrap_or(lint.default)
+    }
+
+    // The levels that were set, by lint name, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, Level)> + '_ {
+        self.levels.iter().map(|(name, level)| (*name, *level))
+    }
+
+    // Sets levels from the lint attributes among `tokens`, reporting
+    // attributes that are malformed or name a lint that does not exist.
+    pub fn apply_attributes(&mut self, tokens: &[Token], sink: &mut dyn DiagnosticSink) {
+        for token in tokens.iter().filter(|t| t.kind() == Kind::Comment) {
+            if token.text().starts_with("#[") {
+                self.apply_attribute(token.text(), token.offset(), sink);
+            }
+        }
+    }
+
+    // Applies one attribute, whose text starts at byte offset `offset`.
+    fn apply_attribute(&mut self, text: &str, offset: usize, sink: &mut dyn DiagnosticSink) {
+        let span = Span::new(offset, offset + text.len());
+        let parsed = text
+            .trim_end()
+            .strip_prefix("#[")
+            .and_then(|rest| rest.strip_suffix(")]"))
+            .and_then(|rest| rest.split_once('('))
+            .and_then(|(level, names)| Some((Level::from_name(level.trim())?, names)));
+        let Some((level, names)) = parsed else {
+            sink.emit(
+                Diagnostic::warning("W0601", "malformed lint attribute", span)
+                    .with_note("expected `#[allow(lint)]`, `#[warn(lint)]` or `#[deny(lint)]`"),
+            );
+            return;
+        };
+        // The offset of the list of names within the comment.
+        let mut start = offset + text.find('(').unwrap() + 1;
+        for name in names.split(',') {
+            let trimmed = name.trim();
+            let name_start = start + name.find(trimmed).unwrap_or(0);
+            let name_span = Span::new(name_start, name_start + trimmed.len());
+            start += name.len() + 1;
+            match find(trimmed) {
+                Some(lint) => self.set(lint, level),
+                None => {
+                    let mut warning = Diagnostic::warning(
+                        "W0600",
+                        format!("unknown lint `{}`", trimmed),
+                        name_span,
+                    );
+                    let names = LINTS.iter().map(|lint| lint.name);
+                    if let Some(similar) = suggest::closest(trimmed, names) {
+                        warning = warning.with_suggestion(
+                            "a lint with a similar name exists",
+                            name_span,
+                            similar,
+                        );
+                    }
+                    sink.emit(warning);
+                }
+            }
+        }
+    }
+
+    // Applies the level of the diagnostic's lint to it: returns `None` for an
+    // allowed lint and turns a denied one into an error. Errors and warnings
+    // that do not belong to a lint are returned unchanged.
+    pub fn apply(&self, mut diagnostic: Diagnostic) -> Option<Diagnostic> {
+        if diagnostic.severity != Severity::Warning {
+            return Some(diagnostic);
+        }
+        let Some(lint) = for_code(diagnostic.code) else {
+            return Some(diagnostic);
+        };
+        match self.level(lint) {
+            Level::Allow => None,
+            Level::Warn => Some(diagnostic),
+            Level::Deny => {
+                diagnostic.severity = Severity::Error;
+                diagnostic
+                    .notes
+                    .push(format!("the `{}` lint is set to deny", lint.name));
+                Some(diagnostic)
+            }
+        }
+    }
+}
+
+// A sink that applies lint levels to diagnostics before passing them on.
+pub struct LintSink<'l, 's> {
+    levels: &'l LintLevels,
+    sink: &'s mut dyn DiagnosticSink,
+}
+
+impl<'l, 's> LintSink<'l, 's> {
+    pub fn new(levels: &'l LintLevels, sink: &'s mut dyn DiagnosticSink) -> LintSink<'l, 's> {
+        LintSink { levels, sink }
+    }
+}
+
+impl DiagnosticSink for LintSink<'_, '_> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        if let Some(diagnostic) = self.levels.apply(diagnostic) {
+            self.sink.emit(diagnostic);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn levels_are_applied_to_lint_diagnostics() {
+        let mut levels = LintLevels::new();
+        levels.set(&UNUSED, Level::Deny);
+        levels.set(&INEXACT_FLOAT_LITERAL, Level::Allow);
+        assert_eq!(levels.level(&UNUSED), Level::Deny);
+        assert_eq!(levels.level(&SAME_SCOPE_SHADOWING), Level::Warn);
+
+        let span = Span::new(0, 1);
+        let mut diagnostics = vec![];
+        let mut sink = LintSink::new(&levels, &mut diagnostics);
+        sink.emit(Diagnostic::warning("W0200", "unused variable `a`", span));
+        sink.emit(Diagnostic::warning("W0201", "`a` shadows a binding", span));
+        sink.emit(Diagnostic::warning("W0300", "inexact literal", span));
+        sink.emit(Diagnostic::error("E0200", "undeclared variable", span));
+        let reported: Vec<(Severity, &str)> =
+            diagnostics.iter().map(|d| (d.severity, d.code)).collect();
+        assert_eq!(
+            reported,
+            [
+                (Severity::Error, "W0200"),
+                (Severity::Warning, "W0201"),
+                (Severity::Error, "E0200"),
+            ]
+        );
+        assert_eq!(diagnostics[0].notes, ["the `unused` lint is set to deny"]);
+    }
+
+    #[test]
+    fn attributes_in_comments_set_levels() {
+        let source = "\
+#[deny(unused, same_scope_shadowing)]
+#[allow( inexact_float_literal )]
+#[allow(unused, unusd)]
+#[forbid(unused)]
+# an ordinary comment
+let x: int32 = 1;
+";
+        let tokens = Lexer::tokenize(source);
+        let mut levels = LintLevels::new();
+        let mut diagnostics = vec![];
+        levels.apply_attributes(&tokens, &mut diagnostics);
+        assert_eq!(levels.level(&UNUSED), Level::Allow);
+        assert_eq!(levels.level(&SAME_SCOPE_SHADOWING), Level::Deny);
+        assert_eq!(levels.level(&INEXACT_FLOAT_LITERAL), Level::Allow);
+
+        let reported: Vec<(&str, &str, &str)> = diagnostics
+            .iter()
+            .map(|d| (d.code, d.message.as_str(), &source[d.span.range()]))
+            .collect();
+        assert_eq!(
+            reported,
+            [
+                ("W0600", "unknown lint `unusd`", "unusd"),
+                ("W0601", "malformed lint attribute", "#[forbid(unused)]"),
+            ]
+        );
+        assert_eq!(diagnostics[0].suggestions[0].replacement, "unused");
+    }
+}
@@ -0,0 +1,413 @@
+use super::{Lint, CONSTANT_CONDITION, LONG_FUNCTION, MAGIC_NUMBER, NAMING_CONVENTION};
+use crate::{
+    ast::{Expression, Identifier, Spanned, Statement, TypeExpr, TypeKind},
+    consteval::ConstValue,
+    diagnostic::{Diagnostic, DiagnosticSink},
+    node::NodeRef,
+    pass::Context,
+    query::query,
+    visit::{walk_program, Control, Visitor},
+};
+
+// A check that reports its lint's warnings for a program. Rules read the
+// AST and whatever analyses earlier passes stored in the context, and emit
+// warnings with their lint's code; `LintPass` applies the lint levels.
+pub struct Rule {
+    pub lint: &'static Lint,
+    pub check: fn(&Context, &mut dyn DiagnosticSink),
+}
+
+// Every rule, in the order of their lints' codes.
+pub static RULES: [Rule; 4] = [
+    Rule {
+        lint: &NAMING_CONVENTION,
+        check: naming_convention,
+    },
+    Rule {
+        lint: &MAGIC_NUMBER,
+        check: magic_number,
+    },
+    Rule {
+        lint: &LONG_FUNCTION,
+        check: long_function,
+    },
+    Rule {
+        lint: &CONSTANT_CONDITION,
+        check: constant_condition,
+    },
+];
+
+// Returns the rule that reports a lint's warnings, if it has one. Lints such
+// as `unused` are reported by the analyses that find them instead.
+pub fn for_lint(lint: &Lint) -> Option<&'static Rule> {
+    RULES.iter().find(|rule| rule.lint == lint)
+}
+
+// The number of lines between a function's braces above which it is
+// reported by `long_function`.
+pub const MAX_FUNCTION_LINES: usize = 50;
+
+// Functions, `let` bindings and parameters are named in snake_case and
+// constants in SCREAMING_SNAKE_CASE. Leading underscores, which mark names
+// that are meant to go unused, are allowed.
+fn naming_convention(context: &Context, sink: &mut dyn DiagnosticSink) {
+    struct Names<'s> {
+        sink: &'s mut dyn DiagnosticSink,
+    }
+
+    impl Names<'_> {
+        fn check(&mut self, identifier: &Identifier, what: &str, upper: bool) {
+            let name = identifier.name.as_str();
+            let expected = snake_case(name, upper);
+            if name == expected || expected.trim_start_matches('_').is_empty() {
+                return;
+            }
+            let case = if upper {
+                "SCREAMING_SNAKE_CASE"
+            } else {
+                "snake_case"
+            };
+            self.sink.emit(
+                Diagnostic::warning(
+                    NAMING_CONVENTION.code,
+                    format!("{} `{}` should have a {} name", what, name, case),
+                    identifier.span,
+                )
+                .with_suggestion("rename it", identifier.span, expected),
+            );
+        }
+    }
+
+    impl<'ast> Visitor<'ast> for Names<'_> {
+        fn visit_statement(&mut self, statement: &'ast Statement<'ast>) -> Control {
+            match statement {
+                Statement::FunctionDeclaration(function) => {
+                    self.check(&function.identifier, "function", false);
+                    for parameter in &function.parameters {
+                        self.check(&parameter.identifier, "parameter", false);
+                    }
+                }
+                Statement::Let(let_statement) => {
+                    self.check(&let_statement.identifier, "variable", false)
+                }
+                Statement::Const(constant) => self.check(&constant.identifier, "constant", true),
+                _ => {}
+            }
+            Control::Continue
+        }
+    }
+
+    walk_program(&mut Names { sink }, context.program);
+}
+
+// Converts a name to snake_case, or to SCREAMING_SNAKE_CASE if `upper`,
+// splitting words where a lowercase letter or digit is followed by an
+// uppercase one.
+fn snake_case(name: &str, upper: bool) -> String {
+    let mut converted = String::new();
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c.is_ascii_uppercase()
+            && previous.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit())
+        {
+            converted.push('_');
+        }
+        if upper {
+            converted.push(c.to_ascii_uppercase());
+        } else {
+            converted.push(c.to_ascii_lowercase());
+        }
+        previous = Some(c);
+    }
+    converted
+}
+
+// Integer literals other than 0 and 1 in code, which say less than a named
+// constant would. Constant initializers and array sizes are where such
+// numbers belong, so literals there are not reported.
+fn magic_number(context: &Context, sink: &mut dyn DiagnosticSink) {
+    struct Literals<'s> {
+        sink: &'s mut dyn DiagnosticSink,
+    }
+
+    impl<'ast> Visitor<'ast> for Literals<'_> {
+        fn visit_statement(&mut self, statement: &'ast Statement<'ast>) -> Control {
+            match statement {
+                Statement::Const(_) => Control::SkipChildren,
+                _ => Control::Continue,
+            }
+        }
+
+        fn visit_expression(&mut self, expression: &'ast Expression<'ast>) -> Control {
+            if let Expression::IntegerLiteral(literal) = expression {
+                if literal.text != "0" && literal.text != "1" {
+                    self.sink.emit(
+                        Diagnostic::warning(
+                            MAGIC_NUMBER.code,
+                            format!("magic number `{}`", literal.text),
+                            literal.span,
+                        )
+                        .with_note("give it a name with a `const` declaration"),
+                    );
+                }
+            }
+            Control::Continue
+        }
+
+        fn visit_type(&mut self, _ttype: &'ast TypeExpr<'ast>) -> Control {
+            Control::SkipChildren
+        }
+    }
+
+    walk_program(&mut Literals { sink }, context.program);
+}
+
+// Functions with more than `MAX_FUNCTION_LINES` lines between their braces.
+fn long_function(context: &Context, sink: &mut dyn DiagnosticSink) {
+    for node in query(context.program, "//fn[body]").expect("the query parses") {
+        let NodeRef::Statement(Statement::FunctionDeclaration(function)) = node else {
+            continue;
+        };
+        let Some(body) = &function.body else {
+            continue;
+        };
+        let (first, _) = context.source_map.location(body.span.start);
+        let (last, _) = context.source_map.location(body.span.end - 1);
+        let lines = (last - first).saturating_sub(1);
+        if lines > MAX_FUNCTION_LINES {
+            sink.emit(
+                Diagnostic::warning(
+                    LONG_FUNCTION.code,
+                    format!(
+                        "function `{}` is {} lines long",
+                        function.identifier.name, lines
+                    ),
+                    function.identifier.span,
+                )
+                .with_note(format!(
+                    "functions longer than {} lines are hard to follow; consider splitting it",
+                    MAX_FUNCTION_LINES
+                )),
+            );
+        }
+    }
+}
+
+// `if` conditions that are always true or always false, and `while`
+// conditions that are always false. A `while` whose condition is always true
+// is how a loop that only ends by returning is written, so it is not
+// reported. Conditions are evaluated over literals and constants; those the
+// constant evaluator cannot compute are not reported.
+fn constant_condition(context: &Context, sink: &mut dyn DiagnosticSink) {
+    let (Some(resolution), Some(typecheck)) = (&context.resolution, &context.typecheck) else {
+        return;
+    };
+    let evaluate = |condition: &Expression| match typecheck.consts.evaluate(
+        condition,
+        TypeKind::Bool,
+        resolution,
+    ) {
+        Ok(ConstValue::Bool(value)) => Some(value),
+        _ => None,
+    };
+
+    struct Conditions<'e, 's> {
+        evaluate: &'e dyn Fn(&Expression) -> Option<bool>,
+        sink: &'s mut dyn DiagnosticSink,
+    }
+
+    impl<'ast> Visitor<'ast> for Conditions<'_, '_> {
+        fn visit_statement(&mut self, statement: &'ast Statement<'ast>) -> Control {
+            let (condition, value, note) = match statement {
+                Statement::If(if_statement) => {
+                    let Some(value) = (self.evaluate)(&if_statement.condition) else {
+                        return Control::Continue;
+                    };
+                    let note = match (value, &if_statement.else_branch) {
+                        (true, Some(_)) => "the `else` branch never runs",
+                        (true, None) => "the block always runs",
+                        (false, _) => "the block never runs",
+                    };
+                    (&if_statement.condition, value, note)
+                }
+                Statement::While(while_statement) => {
+                    match (self.evaluate)(&while_statement.condition) {
+                        Some(false) => (&while_statement.condition, false, "the loop never runs"),
+                        _ => return Control::Continue,
+                    }
+                }
+                _ => return Control::Continue,
+            };
+            self.sink.emit(
+                Diagnostic::warning(
+                    CONSTANT_CONDITION.code,
+                    format!("this condition is always {}", value),
+                    condition.span(),
+                )
+                .with_note(note),
+            );
+            Control::Continue
+        }
+    }
+
+    walk_program(
+        &mut Conditions {
+            evaluate: &evaluate,
+            sink,
+        },
+        context.program,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lexer::Lexer,
+        lint::{Level, LintLevels},
+        parser::Parser,
+        pass::PassManager,
+        source_map::SourceMap,
+    };
+
+    // Runs the semantic passes, returning the code, message and text of each
+    // lint rule's diagnostic.
+    fn lint(source: &str, levels: &LintLevels) -> Vec<(&'static str, String, String)> {
+        let map = SourceMap::new("main", source);
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let mut context = Context::new(&program, &map, levels);
+        PassManager::semantic().run(&mut context);
+        context
+            .diagnostics
+            .iter()
+            .filter(|d| d.code.starts_with("W10"))
+            .map(|d| (d.code, d.message.clone(), map.text(d.span).to_string()))
+            .collect()
+    }
+
+    fn warn(lint: &'static Lint) -> LintLevels {
+        let mut levels = LintLevels::new();
+        levels.set(lint, Level::Warn);
+        levels
+    }
+
+    #[test]
+    fn every_rule_has_a_lint_in_the_registry() {
+        for rule in &RULES {
+            assert_eq!(crate::lint::find(rule.lint.name), Some(rule.lint));
+            assert!(rule.lint.code.starts_with("W10"));
+        }
+        assert!(for_lint(&CONSTANT_CONDITION).is_some());
+        assert!(for_lint(&crate::lint::UNUSED).is_none());
+    }
+
+    #[test]
+    fn names_follow_the_conventions() {
+        let source = "\
+const maxSize: int32 = 8;
+const MIN_SIZE: int32 = 1;
+fn printAll(itemCount: int32, _unused: int32) {
+    let lastItem: int32 = itemCount + maxSize + MIN_SIZE;
+    let _: int32 = lastItem;
+}
+fn main() { printAll(1, 2); }
+";
+        let reported = lint(source, &LintLevels::new());
+        let messages: Vec<&str> = reported.iter().map(|(_, m, _)| m.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "constant `maxSize` should have a SCREAMING_SNAKE_CASE name",
+                "function `printAll` should have a snake_case name",
+                "parameter `itemCount` should have a snake_case name",
+                "variable `lastItem` should have a snake_case name",
+            ]
+        );
+        assert_eq!(snake_case("maxSize", true), "MAX_SIZE");
+        assert_eq!(snake_case("printAll", false), "print_all");
+        assert_eq!(snake_case("_itemCount2X", false), "_item_count2_x");
+    }
+
+    #[test]
+    fn magic_numbers_are_reported_when_warned_about() {
+        let source = "\
+const LIMIT: int32 = 100;
+fn f(a: int32) -> int32 {
+    if a > 42 {
+        return a * 2 + LIMIT;
+    }
+    return a + 1 - 0;
+}
+";
+        assert!(lint(source, &LintLevels::new()).is_empty());
+        let reported: Vec<String> = lint(source, &warn(&MAGIC_NUMBER))
+            .into_iter()
+            .map(|(code, _, text)| format!("{} {}", code, text))
+            .collect();
+        assert_eq!(reported, ["W1001 42", "W1001 2"]);
+    }
+
+    #[test]
+    fn long_functions_are_reported_when_warned_about() {
+        let body = |lines: usize| "    println(1);\n".repeat(lines);
+        let source = format!(
+            "fn short() {{\n{}}}\nfn long() {{\n{}}}\n",
+            body(MAX_FUNCTION_LINES),
+            body(MAX_FUNCTION_LINES + 1)
+        );
+        assert!(lint(&source, &LintLevels::new()).is_empty());
+        assert_eq!(
+            lint(&source, &warn(&LONG_FUNCTION)),
+            [(
+                "W1002",
+                "function `long` is 51 lines long".to_string(),
+                "long".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn constant_conditions_are_reported() {
+        let source = "\
+const N: int32 = 3;
+fn f(a: int32) {
+    if N > 2 {
+        f(a);
+    } else {
+        f(a);
+    }
+    if a > N {
+        f(a);
+    }
+    while N < 0 {
+        f(a);
+    }
+    while 1 > 0 {
+        return;
+    }
+}
+";
+        let reported: Vec<(String, String)> = lint(source, &LintLevels::new())
+            .into_iter()
+            .map(|(_, message, text)| (message, text))
+            .collect();
+        assert_eq!(
+            reported,
+            [
+                (
+                    "this condition is always true".to_string(),
+                    "N > 2".to_string()
+                ),
+                (
+                    "this condition is always false".to_string(),
+                    "N < 0".to_string()
+                ),
+            ]
+        );
+
+        let mut levels = LintLevels::new();
+        levels.set(&CONSTANT_CONDITION, Level::Allow);
+        assert!(lint(source, &levels).is_empty());
+    }
+}
@@ -0,0 +1,666 @@
+use crate::{
+    ast::{Identifier, NodeId, Program, Span, Statement, Symbol},
+    diagnostic::Diagnostic,
+    fold::{Folder, Shift},
+    lexer::Lexer,
+    parser::Parser,
+    resolver::{resolve_with_imports, Declaration, Resolution, Scope, ScopeId},
+    source_map::SourceMap,
+    symbol::Interner,
+    visit::{self, Control, Visitor},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+// Loads a program made of modules. A module is a file, and its top-level
+// statements may import others by name:
+//
+//     import geometry;
+//     import shapes;
+//
+// `import geometry;` loads `geometry.my` from the first directory of the
+// search path that has it. Every module is loaded, parsed and resolved once,
+// however many modules import it, and its imports are loaded before it.
+// A module that imports itself, directly or through others, is an error.
+//
+// A module sees the top-level functions and constants of the modules it
+// imports, but not what those import in turn. Its own declarations shadow
+// imported ones, and using a name imported from two modules is an error.
+// Node ids
+// are unique across the modules, so a use in one module refers to its
+// declaration in another by id, and `Loader::declaration` finds it.
+
+// The extension of module files.
+pub const EXTENSION: &str = "my";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModuleId(pub u32);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub name: Symbol,
+    // The span of the module name in the importing file.
+    pub span: Span,
+    // The imported module, or `None` if it could not be loaded or would
+    // close a cycle.
+    pub module: Option<ModuleId>,
+}
+
+#[derive(Debug)]
+pub struct Module {
+    pub name: Symbol,
+    pub path: PathBuf,
+    pub source_map: SourceMap,
+    pub imports: Vec<Import>,
+    // The module's statements, or `None` if it does not parse.
+    pub program: Option<Program<'static>>,
+    // The module's resolution. Its warnings leave out the exports, which
+    // other modules may use.
+    pub resolution: Resolution,
+    // The ids of the module's nodes.
+    pub node_ids: Range<u32>,
+    // The parse error, errors in the imports or the resolution's errors and
+    // warnings, against `source_map`.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+// A module linked with the modules it imports, directly or through others,
+// into one program, so that it can be checked and run whole.
+#[derive(Debug)]
+pub struct Linked {
+    // The statements of every module, each module's after those of the
+    // modules it imports. Imports are kept, and are no-ops from here on.
+    pub program: Program<'static>,
+    // The modules' files, joined in the same order, which every span in the
+    // program, the resolution and the diagnostics is against.
+    pub source_map: SourceMap,
+    // The modules' resolutions merged: a use in one module refers to its
+    // declaration in another by id, as in the modules.
+    pub resolution: Resolution,
+    // The diagnostics of every module.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Linked {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+}
+
+impl Module {
+    // The declarations other modules can import: the top-level functions
+    // and constants, by the ids of their declaring identifiers.
+    pub fn exports(&self) -> Vec<(NodeId, &Declaration)> {
+        let Some(program) = &self.program else {
+            return vec![];
+        };
+        exported(program)
+            .filter_map(|identifier| {
+                let declaration = self.resolution.declarations.get(identifier.id)?;
+                Some((identifier.id, declaration))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct Loader {
+    search_path: Vec<PathBuf>,
+    modules: Vec<Module>,
+    by_path: HashMap<PathBuf, ModuleId>,
+    // The modules being loaded, each imported by the one before it.
+    loading: Vec<ModuleId>,
+    next_id: NodeId,
+    // Interns the names of every module, so that a name imported from one
+    // module is the same symbol in the module that uses it.
+    interner: Interner,
+}
+
+impl Loader {
+    pub fn new(search_path: Vec<PathBuf>) -> Loader {
+        Loader {
+            search_path,
+            modules: vec![],
+            by_path: HashMap::new(),
+            loading: vec![],
+            next_id: NodeId(0),
+            interner: Interner::new(),
+        }
+    }
+
+    // Loads a file and every module it imports, returning its module. Only
+    // a file that cannot be read is an error; problems in the modules are
+    // in their diagnostics.
+    pub fn load_file(&mut self, path: &Path) -> io::Result<ModuleId> {
+        let path = canonical(path);
+        if let Some(id) = self.by_path.get(&path) {
+            return Ok(*id);
+        }
+        let source = fs::read_to_string(&path)?;
+        Ok(self.load_source(&path, source))
+    }
+
+    // Loads a module from its source, and every module it imports, and
+    // returns it. The module is named after the stem of its path.
+    pub fn load_source(&mut self, path: &Path, source: String) -> ModuleId {
+        let id = ModuleId(self.modules.len() as u32);
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        self.modules.push(Module {
+            name: self.interner.intern(&name),
+            path: path.to_path_buf(),
+            source_map: SourceMap::new(path.display().to_string(), source.as_str()),
+            imports: vec![],
+            program: None,
+            resolution: Resolution::default(),
+            node_ids: 0..0,
+            diagnostics: vec![],
+        });
+        self.by_path.insert(canonical(path), id);
+        self.loading.push(id);
+        self.load(id, source);
+        self.loading.pop();
+        id
+    }
+
+    pub fn module(&self, id: ModuleId) -> &Module {
+        &self.modules[id.0 as usize]
+    }
+
+    // The loaded modules, in the order they were first imported.
+    pub fn modules(&self) -> &[Module] {
+        &self.modules
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.modules
+            .iter()
+            .any(|module| module.diagnostics.iter().any(Diagnostic::is_error))
+    }
+
+    // Finds the module that declares a name by the id of its declaring
+    // identifier, and the declaration.
+    pub fn declaration(&self, id: NodeId) -> Option<(ModuleId, &Declaration)> {
+        let index = self
+            .modules
+            .iter()
+            .position(|module| module.node_ids.contains(&id.0))?;
+        let declaration = self.modules[index].resolution.declarations.get(id)?;
+        Some((ModuleId(index as u32), declaration))
+    }
+
+    // Links a module with the modules it imports. The modules that do not
+    // parse have no statements, so check the diagnostics before the program.
+    pub fn link(&self, root: ModuleId) -> Linked {
+        let mut order = vec![];
+        self.order(root, &mut HashSet::new(), &mut order);
+        let source_map = SourceMap::join(order.iter().map(|id| &self.module(*id).source_map));
+        let mut linked = Linked {
+            program: Program { statements: vec![] },
+            source_map,
+            resolution: Resolution::default(),
+            diagnostics: vec![],
+        };
+        for (index, id) in order.into_iter().enumerate() {
+            let module = self.module(id);
+            let mut shift = Shift {
+                delta: linked.source_map.file_start(index) as isize,
+            };
+            if let Some(program) = &module.program {
+                linked.program.statements.extend(
+                    program
+                        .statements
+                        .iter()
+                        .map(|statement| shift.fold_statement(statement.clone())),
+                );
+            }
+            merge(&mut linked.resolution, &module.resolution, &shift);
+            linked.diagnostics.extend(
+                module
+                    .diagnostics
+                    .iter()
+                    .map(|diagnostic| diagnostic.clone().shifted(shift.delta)),
+            );
+        }
+        linked
+    }
+
+    // Appends to `order` the modules a module imports, each after those it
+    // imports in turn, and then the module, skipping the modules in `seen`.
+    fn order(&self, id: ModuleId, seen: &mut HashSet<ModuleId>, order: &mut Vec<ModuleId>) {
+        if !seen.insert(id) {
+            return;
+        }
+        for import in &self.module(id).imports {
+            if let Some(module) = import.module {
+                self.order(module, seen, order);
+            }
+        }
+        order.push(id);
+    }
+
+    // Renders the diagnostics of every module, separated by blank lines.
+    pub fn render(&self) -> String {
+        self.modules
+            .iter()
+            .flat_map(|module| {
+                module
+                    .diagnostics
+                    .iter()
+                    .map(|d| d.render(&module.source_map))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn load(&mut self, id: ModuleId, source: String) {
+        let start = self.next_id;
+        let parsed =
+            Parser::parse_statements(Lexer::new(&source), &mut self.next_id, &mut self.interner);
+        self.modules[id.0 as usize].node_ids = start.0..self.next_id.0;
+
+        let mut diagnostics = vec![];
+        let mut imported = vec![];
+        let mut program = None;
+        let mut resolution = Resolution::default();
+        match parsed {
+            Ok(statements) => {
+                let parsed = Program {
+                    statements: statements.into_iter().map(|(s, _)| s).collect(),
+                }
+                .into_owned();
+                for import in parsed.imports() {
+                    let (name, span) = (import.module.name.clone(), import.module.span);
+                    let module = self.import(name.clone(), span, &mut diagnostics);
+                    imported.push(Import { name, span, module });
+                }
+                let (names, ambiguous) = self.imported_names(&imported);
+                resolution = resolve_with_imports(&parsed, &names);
+                diagnostics.extend(resolution.errors.iter().cloned());
+                let mut uses = AmbiguousUses {
+                    resolution: &resolution,
+                    ambiguous: &ambiguous,
+                    diagnostics: &mut diagnostics,
+                };
+                visit::walk_program(&mut uses, &parsed);
+                // Other modules may use the exports.
+                let exports: Vec<Span> = exported(&parsed)
+                    .map(|identifier| identifier.span)
+                    .collect();
+                resolution
+                    .warnings
+                    .retain(|w| !(w.code == "W0200" && exports.contains(&w.span)));
+                diagnostics.extend(resolution.warnings.iter().cloned());
+                program = Some(parsed);
+            }
+            Err(error) => diagnostics.push(*error),
+        }
+
+        let module = &mut self.modules[id.0 as usize];
+        module.imports = imported;
+        module.program = program;
+        module.resolution = resolution;
+        module.diagnostics = diagnostics;
+    }
+
+    // Loads the module an import names, unless it is loaded already.
+    fn import(
+        &mut self,
+        name: Symbol,
+        span: Span,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<ModuleId> {
+        let file = format!("{name}.{EXTENSION}");
+        let Some(path) = self
+            .search_path
+            .iter()
+            .map(|directory| directory.join(&file))
+            .find(|path| path.is_file())
+        else {
+            let searched: Vec<String> = self
+                .search_path
+                .iter()
+                .map(|directory| directory.display().to_string())
+                .collect();
+            diagnostics.push(
+                Diagnostic::error("E1100", format!("cannot find module `{name}`"), span)
+                    .with_note(format!("searched for `{file}` in: {}", searched.join(", "))),
+            );
+            return None;
+        };
+        if let Some(id) = self.by_path.get(&canonical(&path)) {
+            if let Some(position) = self.loading.iter().position(|loading| loading == id) {
+                let mut cycle: Vec<String> = self.loading[position..]
+                    .iter()
+                    .map(|id| format!("`{}`", self.module(*id).name))
+                    .collect();
+                cycle.push(format!("`{name}`"));
+                diagnostics.push(
+                    Diagnostic::error("E1102", format!("module `{name}` imports itself"), span)
+                        .with_note(format!("the cycle is {}", cycle.join(" -> "))),
+                );
+                return None;
+            }
+            return Some(*id);
+        }
+        match fs::read_to_string(&path) {
+            Ok(source) => Some(self.load_source(&path, source)),
+            Err(error) => {
+                diagnostics.push(Diagnostic::error(
+                    "E1101",
+                    format!(
+                        "cannot read module `{name}` from `{}`: {error}",
+                        path.display()
+                    ),
+                    span,
+                ));
+                None
+            }
+        }
+    }
+
+    // Collects the names a module imports. A name two of its imports export
+    // resolves to the first, and is returned with the modules it comes from
+    // by the id of that declaration, to be reported where it is used.
+    fn imported_names(&self, imports: &[Import]) -> (Vec<(NodeId, Declaration)>, Ambiguous) {
+        let mut names: Vec<(NodeId, Declaration)> = vec![];
+        let mut sources: HashMap<Symbol, (Symbol, NodeId)> = HashMap::new();
+        let mut ambiguous = HashMap::new();
+        for import in imports {
+            let Some(module) = import.module else {
+                continue;
+            };
+            for (id, declaration) in self.module(module).exports() {
+                match sources.get(&declaration.name) {
+                    Some((first, first_id)) if *first != import.name => {
+                        ambiguous
+                            .entry(*first_id)
+                            .or_insert_with(|| (first.clone(), import.name.clone()));
+                    }
+                    Some(_) => {}
+                    None => {
+                        let source = (import.name.clone(), id);
+                        sources.insert(declaration.name.clone(), source);
+                        names.push((id, declaration.clone()));
+                    }
+                }
+            }
+        }
+        (names, ambiguous)
+    }
+}
+
+// The two modules each name imported from both comes from, by the id of the
+// declaration the name resolves to.
+type Ambiguous = HashMap<NodeId, (Symbol, Symbol)>;
+
+// Reports the uses of names imported from two modules.
+struct AmbiguousUses<'a> {
+    resolution: &'a Resolution,
+    ambiguous: &'a Ambiguous,
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+impl<'ast> Visitor<'ast> for AmbiguousUses<'_> {
+    fn visit_identifier(&mut self, identifier: &'ast Identifier) -> Control {
+        let declaration = self.resolution.uses.get(identifier.id);
+        if let Some((first, second)) = declaration.and_then(|id| self.ambiguous.get(id)) {
+            self.diagnostics.push(Diagnostic::error(
+                "E1103",
+                format!(
+                    "`{}` is imported from both `{first}` and `{second}`",
+                    identifier.name
+                ),
+                identifier.span,
+            ));
+        }
+        Control::Continue
+    }
+}
+
+// The declaring identifiers of a program's exports.
+fn exported<'p>(program: &'p Program) -> impl Iterator<Item = &'p Identifier> {
+    program
+        .statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::FunctionDeclaration(function) => Some(&function.identifier),
+            Statement::Const(constant) => Some(&constant.identifier),
+            _ => None,
+        })
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+// Adds a module's resolution to those of the modules linked before it, with
+// its spans moved to where the module is in the linked source. Ids are unique
+// across modules, so the declarations, uses and builtins keep theirs; the
+// module's copies of the declarations it imports are left out, as the
+// modules that declare them come first. Its scopes follow those already
+// there.
+fn merge(linked: &mut Resolution, module: &Resolution, shift: &Shift) {
+    let first_scope = linked.scopes.len() as u32;
+    let scope = |id: ScopeId| ScopeId(first_scope + id.0);
+    for (id, declaration) in module.declarations.iter() {
+        if !linked.declarations.contains(id) {
+            let declaration = Declaration {
+                span: shift.span(declaration.span),
+                ..declaration.clone()
+            };
+            linked.declarations.insert(id, declaration);
+            if let Some(declared_in) = module.declaration_scopes.get(id) {
+                linked.declaration_scopes.insert(id, scope(*declared_in));
+            }
+        }
+    }
+    for (id, declaration) in module.uses.iter() {
+        linked.uses.insert(id, *declaration);
+    }
+    for (id, builtin) in module.builtins.iter() {
+        linked.builtins.insert(id, *builtin);
+    }
+    linked
+        .scopes
+        .extend(module.scopes.iter().map(|module_scope| Scope {
+            parent: module_scope.parent.map(scope),
+            ..module_scope.clone()
+        }));
+    let shifted = |diagnostic: &Diagnostic| diagnostic.clone().shifted(shift.delta);
+    linked.errors.extend(module.errors.iter().map(shifted));
+    linked.warnings.extend(module.warnings.iter().map(shifted));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Spanned;
+
+    // Writes modules to a new directory and returns it.
+    fn directory(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("mylang-loader-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        for (file, source) in files {
+            fs::write(directory.join(file), source).unwrap();
+        }
+        directory
+    }
+
+    fn codes(loader: &Loader) -> Vec<(String, &str)> {
+        loader
+            .modules()
+            .iter()
+            .flat_map(|module| {
+                module
+                    .diagnostics
+                    .iter()
+                    .map(|d| (module.name.to_string(), d.code))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn imported_names_resolve_to_their_modules() {
+        let directory = directory(
+            "resolve",
+            &[
+                (
+                    "main.my",
+                    "# Shapes.\nimport geometry;\nimport shapes;\n\
+                     fn main() { println(area(SIDE)); println(square()); }\n",
+                ),
+                (
+                    "geometry.my",
+                    "import units;\nconst SIDE: int64 = 3;\n\
+                     fn area(side: int64) -> int64 { return side * side * SCALE; }\n",
+                ),
+                (
+                    "shapes.my",
+                    "import geometry;\nfn square() -> int64 { return area(2); }\n",
+                ),
+                ("units.my", "const SCALE: int64 = 1;\n"),
+            ],
+        );
+        let mut loader = Loader::new(vec![directory.clone()]);
+        let main = loader.load_file(&directory.join("main.my")).unwrap();
+        assert_eq!(codes(&loader), []);
+        // `geometry` is loaded once, though two modules import it.
+        let names: Vec<&str> = loader.modules().iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["main", "geometry", "units", "shapes"]);
+
+        let module = loader.module(main);
+        let imports: Vec<(&str, Option<ModuleId>)> = module
+            .imports
+            .iter()
+            .map(|import| (import.name.as_str(), import.module))
+            .collect();
+        assert_eq!(
+            imports,
+            [
+                ("geometry", Some(ModuleId(1))),
+                ("shapes", Some(ModuleId(3)))
+            ]
+        );
+        assert_eq!(module.source_map.text(module.imports[1].span), "shapes");
+        let (uses, declared): (Vec<&str>, Vec<&str>) = module
+            .resolution
+            .uses
+            .iter()
+            .filter_map(|(_, declaration)| {
+                let (id, declaration) = loader.declaration(*declaration)?;
+                Some((declaration.name.as_str(), loader.module(id).name.as_str()))
+            })
+            .unzip();
+        assert_eq!(uses, ["area", "SIDE", "square"]);
+        assert_eq!(declared, ["geometry", "geometry", "shapes"]);
+    }
+
+    #[test]
+    fn linked_programs_check_whole() {
+        let directory = directory(
+            "link",
+            &[
+                (
+                    "main.my",
+                    "import geometry;\nfn main() -> int32 { return area(2); }\n",
+                ),
+                (
+                    "geometry.my",
+                    "fn area(side: int32) -> int32 { let n: int8 = 300; return side * side; }",
+                ),
+            ],
+        );
+        let mut loader = Loader::new(vec![directory.clone()]);
+        let main = loader.load_file(&directory.join("main.my")).unwrap();
+        let linked = loader.link(main);
+        assert!(!linked.has_errors());
+        let map = &linked.source_map;
+        let names: Vec<&str> = linked
+            .program
+            .statements
+            .iter()
+            .map(|statement| map.name_at(statement.span().start))
+            .collect();
+        let geometry = directory.join("geometry.my").display().to_string();
+        let main_name = directory.join("main.my").display().to_string();
+        assert_eq!(names, [&geometry, &main_name, &main_name]);
+        let Statement::FunctionDeclaration(area) = &linked.program.statements[0] else {
+            panic!("expected `area`");
+        };
+        assert_eq!(map.text(area.identifier.span), "area");
+
+        let warning = &linked.diagnostics[0];
+        assert_eq!((warning.code, map.text(warning.span)), ("W0200", "n"));
+        let result = crate::analyze::check_resolved(
+            &linked.program,
+            map,
+            &Default::default(),
+            linked.resolution,
+        );
+        let codes: Vec<&str> = result.diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, ["W0200", "E0308"]);
+        assert!(result.render().contains(&format!(" --> {geometry}:1:47")));
+    }
+
+    #[test]
+    fn missing_modules_cycles_and_conflicts_are_reported() {
+        let directory = directory(
+            "errors",
+            &[
+                (
+                    "a.my",
+                    "import b;\nimport missing;\nfn f() -> int64 { return g(); }\n",
+                ),
+                ("b.my", "import c;\nfn g() -> int64 { return 1; }\n"),
+                ("c.my", "import a;\nimport b;\n"),
+                (
+                    "d.my",
+                    "import b;\nimport e;\nfn main() { println(g()); }\n",
+                ),
+                ("e.my", "fn g() -> int64 { return 2; }\n"),
+                ("f.my", "import;\nfn main() {}\n"),
+                (
+                    "h.my",
+                    "import b;\nimport e;\nfn main() { fn g() {} g(); }\n",
+                ),
+            ],
+        );
+        let mut loader = Loader::new(vec![directory.clone()]);
+        loader.load_file(&directory.join("a.my")).unwrap();
+        assert_eq!(
+            codes(&loader),
+            [
+                ("a".to_string(), "E1100"),
+                ("c".to_string(), "E1102"),
+                ("c".to_string(), "E1102"),
+            ]
+        );
+        let rendered = loader.render();
+        assert!(rendered.contains("the cycle is `a` -> `b` -> `c` -> `a`"));
+        assert!(rendered.contains("the cycle is `b` -> `c` -> `b`"));
+
+        loader.load_file(&directory.join("d.my")).unwrap();
+        assert_eq!(codes(&loader)[3], ("d".to_string(), "E1103"));
+        let d = loader.modules().iter().find(|m| m.name == "d").unwrap();
+        assert_eq!(d.source_map.text(d.diagnostics[0].span), "g");
+        assert_eq!(
+            d.diagnostics[0].message,
+            "`g` is imported from both `b` and `e`"
+        );
+        // Names imported from two modules are only errors where they are used.
+        loader.load_file(&directory.join("h.my")).unwrap();
+        assert_eq!(codes(&loader).len(), 4);
+        loader.load_file(&directory.join("f.my")).unwrap();
+        let f = loader.modules().last().unwrap();
+        assert_eq!(f.diagnostics[0].code, "E0100");
+        assert!(f.program.is_none());
+        assert!(loader.load_file(&directory.join("none.my")).is_err());
+        assert!(loader.has_errors());
+    }
+}
@@ -1,39 +1,398 @@
 #![macro_use]
 
-use crate::ast::{BinaryOperator, Expression, Parameter, Statement, Type};
+// Structural matchers over the AST.
+//
+// A matcher checks whether a node has a given shape, e.g. "a `let` of type
+// int32 whose value is a call". Matchers can be built three ways: with the
+// `match_*!` macros, with the builder functions below that the macros expand
+// to, or from source text with `parse_pattern`. `explain` reports why a node
+// does not match, and the `query` module runs matchers over whole programs.
+use crate::{
+    ast::{
+        BinaryOperator, Block, Expression, Parameter, Program, Span, Spanned, Statement, Symbol,
+        TypeExpr, TypeKind,
+    },
+    printer::{operator_text, print_expression, print_type},
+    visit::{self, Control, Visitor},
+};
+use regex::Regex;
+use std::{
+    fmt,
+    ops::{Bound, RangeBounds},
+};
+
+pub use crate::pattern::{parse_pattern, Pattern};
+
+// Why a node does or does not match a matcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatchReport {
+    Match,
+    // The first field that differs, as a path from the matched node such as
+    // `expression.left`, with what the matcher expected and what it found.
+    Mismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl MatchReport {
+    pub fn mismatch(expected: impl Into<String>, actual: impl Into<String>) -> MatchReport {
+        MatchReport::Mismatch {
+            path: String::new(),
+            expected: expected.into(),
+            actual: actual.into(),
+        }
+    }
+
+    // Reports a match if `matched`, and a mismatch otherwise.
+    pub fn check(
+        matched: bool,
+        expected: impl FnOnce() -> String,
+        actual: impl FnOnce() -> String,
+    ) -> MatchReport {
+        if matched {
+            MatchReport::Match
+        } else {
+            MatchReport::mismatch(expected(), actual())
+        }
+    }
+
+    pub fn is_match(&self) -> bool {
+        matches!(self, MatchReport::Match)
+    }
+
+    // Prefixes the path of a mismatch with the field it was found in.
+    pub fn at(self, field: &str) -> MatchReport {
+        match self {
+            MatchReport::Match => MatchReport::Match,
+            MatchReport::Mismatch {
+                path,
+                expected,
+                actual,
+            } => MatchReport::Mismatch {
+                path: if path.is_empty() {
+                    field.to_string()
+                } else if path.starts_with('[') {
+                    format!("{}{}", field, path)
+                } else {
+                    format!("{}.{}", field, path)
+                },
+                expected,
+                actual,
+            },
+        }
+    }
+
+    // Returns this report if it is a mismatch, and the next one otherwise.
+    pub fn and_then(self, next: impl FnOnce() -> MatchReport) -> MatchReport {
+        match self {
+            MatchReport::Match => next(),
+            mismatch => mismatch,
+        }
+    }
+}
+
+impl fmt::Display for MatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchReport::Match => f.write_str("matched"),
+            MatchReport::Mismatch {
+                path,
+                expected,
+                actual,
+            } => {
+                if !path.is_empty() {
+                    write!(f, "at {}: ", path)?;
+                }
+                write!(f, "expected {}, got {}", expected, actual)
+            }
+        }
+    }
+}
 
 pub trait ExpressionMatcher {
     fn matches(&self, expression: &Expression) -> bool;
+
+    // Explains why the expression does not match. Matchers with fields
+    // override this to point at the first differing field.
+    fn explain(&self, expression: &Expression) -> MatchReport {
+        MatchReport::check(
+            self.matches(expression),
+            || "a matching expression".to_string(),
+            || describe_expression(expression),
+        )
+    }
 }
 
 pub trait StatementMatcher {
     fn matches(&self, statement: &Statement) -> bool;
+
+    // The number of statements this matcher stands for in a statement list,
+    // as a minimum and an optional maximum, or `None` for exactly one. See
+    // `RepeatedMatcher`.
+    fn repetitions(&self) -> Option<(usize, Option<usize>)> {
+        None
+    }
+
+    fn explain(&self, statement: &Statement) -> MatchReport {
+        MatchReport::check(
+            self.matches(statement),
+            || "a matching statement".to_string(),
+            || describe_statement(statement).to_string(),
+        )
+    }
 }
 
 pub trait ParameterMatcher {
     fn matches(&self, parameter: &Parameter) -> bool;
+
+    fn explain(&self, parameter: &Parameter) -> MatchReport {
+        MatchReport::check(
+            self.matches(parameter),
+            || "a matching parameter".to_string(),
+            || format!("`{}`", parameter.identifier.name),
+        )
+    }
 }
 
 pub trait TypeMatcher {
-    fn matches(&self, ttype: &Type) -> bool;
+    fn matches(&self, ttype: &TypeExpr) -> bool;
+
+    fn explain(&self, ttype: &TypeExpr) -> MatchReport {
+        MatchReport::check(
+            self.matches(ttype),
+            || "a matching type".to_string(),
+            || describe_type(ttype),
+        )
+    }
+}
+
+fn describe_expression(expression: &Expression) -> String {
+    format!("`{}`", print_expression(expression))
+}
+
+fn describe_type(ttype: &TypeExpr) -> String {
+    format!("type `{}`", print_type(ttype))
+}
+
+fn describe_statement(statement: &Statement) -> &'static str {
+    match statement {
+        Statement::Let(_) => "let statement",
+        Statement::Const(_) => "const declaration",
+        Statement::FunctionDeclaration(_) => "function declaration",
+        Statement::Expression(_) => "expression statement",
+        Statement::Return(_) => "return statement",
+        Statement::If(_) => "if statement",
+        Statement::While(_) => "while statement",
+        Statement::Block(_) => "block",
+        Statement::Import(_) => "import statement",
+    }
+}
+
+// Explains a list of nodes matched one to one by a list of matchers.
+fn explain_all<M: ?Sized, T>(
+    matchers: &[Box<M>],
+    nodes: &[T],
+    explain: impl Fn(&M, &T) -> MatchReport,
+) -> MatchReport {
+    if matchers.len() != nodes.len() {
+        return MatchReport::mismatch(matchers.len().to_string(), nodes.len().to_string())
+            .at("len");
+    }
+    matchers
+        .iter()
+        .zip(nodes)
+        .enumerate()
+        .map(|(i, (m, n))| explain(m, n).at(&format!("[{}]", i)))
+        .find(|report| !report.is_match())
+        .unwrap_or(MatchReport::Match)
 }
 
 pub struct NamedTypeMatcher {
-    name: String,
+    kind: TypeKind,
 }
 
 impl NamedTypeMatcher {
     pub fn new(name: String) -> Box<NamedTypeMatcher> {
-        Box::new(NamedTypeMatcher { name })
+        Box::new(NamedTypeMatcher {
+            kind: TypeKind::from_name(&name),
+        })
     }
 }
 
 impl TypeMatcher for NamedTypeMatcher {
-    fn matches(&self, ttype: &Type) -> bool {
-        ttype.name == self.name
+    fn matches(&self, ttype: &TypeExpr) -> bool {
+        matches!(ttype, TypeExpr::Named(t) if t.kind == self.kind)
+    }
+
+    fn explain(&self, ttype: &TypeExpr) -> MatchReport {
+        MatchReport::check(
+            self.matches(ttype),
+            || format!("type `{}`", self.kind),
+            || describe_type(ttype),
+        )
+    }
+}
+
+pub struct UnitTypeMatcher {}
+
+impl UnitTypeMatcher {
+    pub fn new() -> Box<UnitTypeMatcher> {
+        Box::new(UnitTypeMatcher {})
+    }
+}
+
+impl TypeMatcher for UnitTypeMatcher {
+    fn matches(&self, ttype: &TypeExpr) -> bool {
+        ttype.is_unit()
+    }
+}
+
+pub struct ArrayTypeMatcher {
+    element: Box<dyn TypeMatcher>,
+    size: String,
+}
+
+impl ArrayTypeMatcher {
+    pub fn new(element: Box<dyn TypeMatcher>, size: String) -> Box<ArrayTypeMatcher> {
+        Box::new(ArrayTypeMatcher { element, size })
+    }
+}
+
+impl TypeMatcher for ArrayTypeMatcher {
+    fn matches(&self, ttype: &TypeExpr) -> bool {
+        matches!(ttype, TypeExpr::Array(array) if {
+            print_expression(&array.size) == self.size && self.element.matches(&array.element)
+        })
+    }
+
+    fn explain(&self, ttype: &TypeExpr) -> MatchReport {
+        let TypeExpr::Array(array) = ttype else {
+            return MatchReport::mismatch("an array type", describe_type(ttype));
+        };
+        self.element
+            .explain(&array.element)
+            .at("element")
+            .and_then(|| {
+                MatchReport::check(
+                    print_expression(&array.size) == self.size,
+                    || format!("size {}", self.size),
+                    || print_expression(&array.size),
+                )
+                .at("size")
+            })
+    }
+}
+
+pub struct GenericTypeMatcher {
+    kind: TypeKind,
+    arguments: Vec<Box<dyn TypeMatcher>>,
+}
+
+impl GenericTypeMatcher {
+    pub fn new(name: String, arguments: Vec<Box<dyn TypeMatcher>>) -> Box<GenericTypeMatcher> {
+        Box::new(GenericTypeMatcher {
+            kind: TypeKind::from_name(&name),
+            arguments,
+        })
     }
 }
 
+impl TypeMatcher for GenericTypeMatcher {
+    fn matches(&self, ttype: &TypeExpr) -> bool {
+        matches!(ttype, TypeExpr::Generic(generic) if {
+            generic.base.kind == self.kind
+                && all_match(&self.arguments, &generic.arguments)
+        })
+    }
+
+    fn explain(&self, ttype: &TypeExpr) -> MatchReport {
+        let TypeExpr::Generic(generic) = ttype else {
+            return MatchReport::mismatch(
+                format!("generic type `{}`", self.kind),
+                describe_type(ttype),
+            );
+        };
+        MatchReport::check(
+            generic.base.kind == self.kind,
+            || format!("`{}`", self.kind),
+            || format!("`{}`", generic.base.kind),
+        )
+        .at("base")
+        .and_then(|| {
+            explain_all(&self.arguments, &generic.arguments, |m, t| m.explain(t)).at("arguments")
+        })
+    }
+}
+
+pub struct TupleTypeMatcher {
+    elements: Vec<Box<dyn TypeMatcher>>,
+}
+
+impl TupleTypeMatcher {
+    pub fn new(elements: Vec<Box<dyn TypeMatcher>>) -> Box<TupleTypeMatcher> {
+        Box::new(TupleTypeMatcher { elements })
+    }
+}
+
+impl TypeMatcher for TupleTypeMatcher {
+    fn matches(&self, ttype: &TypeExpr) -> bool {
+        matches!(ttype, TypeExpr::Tuple(elements) if all_match(&self.elements, elements))
+    }
+
+    fn explain(&self, ttype: &TypeExpr) -> MatchReport {
+        let TypeExpr::Tuple(elements) = ttype else {
+            return MatchReport::mismatch("a tuple type", describe_type(ttype));
+        };
+        explain_all(&self.elements, elements, |m, t| m.explain(t)).at("elements")
+    }
+}
+
+pub struct FunctionTypeMatcher {
+    parameters: Vec<Box<dyn TypeMatcher>>,
+    return_type: Box<dyn TypeMatcher>,
+}
+
+impl FunctionTypeMatcher {
+    pub fn new(
+        parameters: Vec<Box<dyn TypeMatcher>>,
+        return_type: Box<dyn TypeMatcher>,
+    ) -> Box<FunctionTypeMatcher> {
+        Box::new(FunctionTypeMatcher {
+            parameters,
+            return_type,
+        })
+    }
+}
+
+impl TypeMatcher for FunctionTypeMatcher {
+    fn matches(&self, ttype: &TypeExpr) -> bool {
+        matches!(ttype, TypeExpr::Function(function) if {
+            self.return_type.matches(&function.return_type)
+                && all_match(&self.parameters, &function.parameters)
+        })
+    }
+
+    fn explain(&self, ttype: &TypeExpr) -> MatchReport {
+        let TypeExpr::Function(function) = ttype else {
+            return MatchReport::mismatch("a function type", describe_type(ttype));
+        };
+        explain_all(&self.parameters, &function.parameters, |m, t| m.explain(t))
+            .at("parameters")
+            .and_then(|| {
+                self.return_type
+                    .explain(&function.return_type)
+                    .at("return_type")
+            })
+    }
+}
+
+// Returns true if each type is matched by the corresponding matcher.
+fn all_match(matchers: &[Box<dyn TypeMatcher>], types: &[TypeExpr]) -> bool {
+    matchers.len() == types.len() && matchers.iter().zip(types).all(|(m, t)| m.matches(t))
+}
+
 pub struct AnyMatcher {
     _private: (),
 }
@@ -45,7 +404,7 @@ impl AnyMatcher {
 }
 
 impl TypeMatcher for AnyMatcher {
-    fn matches(&self, _ttype: &Type) -> bool {
+    fn matches(&self, _ttype: &TypeExpr) -> bool {
         true
     }
 }
@@ -68,14 +427,145 @@ impl ParameterMatcher for AnyMatcher {
     }
 }
 
+// Matches when both matchers match. Longer conjunctions are built by nesting,
+// see `all_of!`.
+pub struct AllOfMatcher<L: ?Sized, R: ?Sized> {
+    left: Box<L>,
+    right: Box<R>,
+}
+
+impl<L: ?Sized, R: ?Sized> AllOfMatcher<L, R> {
+    pub fn new(left: Box<L>, right: Box<R>) -> Box<AllOfMatcher<L, R>> {
+        Box::new(AllOfMatcher { left, right })
+    }
+}
+
+// Matches when either matcher matches.
+pub struct AnyOfMatcher<L: ?Sized, R: ?Sized> {
+    left: Box<L>,
+    right: Box<R>,
+}
+
+impl<L: ?Sized, R: ?Sized> AnyOfMatcher<L, R> {
+    pub fn new(left: Box<L>, right: Box<R>) -> Box<AnyOfMatcher<L, R>> {
+        Box::new(AnyOfMatcher { left, right })
+    }
+}
+
+// Matches when the inner matcher does not.
+pub struct NotMatcher<M: ?Sized> {
+    inner: Box<M>,
+}
+
+impl<M: ?Sized> NotMatcher<M> {
+    pub fn new(inner: Box<M>) -> Box<NotMatcher<M>> {
+        Box::new(NotMatcher { inner })
+    }
+}
+
+// Implements a matcher trait for the combinators whenever their operands
+// implement it.
+macro_rules! impl_combinators {
+    ($($matcher:ident($node:ty)),*) => {
+        $(
+            impl<L: $matcher + ?Sized, R: $matcher + ?Sized> $matcher for AllOfMatcher<L, R> {
+                fn matches(&self, node: &$node) -> bool {
+                    self.left.matches(node) && self.right.matches(node)
+                }
+
+                fn explain(&self, node: &$node) -> MatchReport {
+                    self.left.explain(node).and_then(|| self.right.explain(node))
+                }
+            }
+
+            impl<L: $matcher + ?Sized, R: $matcher + ?Sized> $matcher for AnyOfMatcher<L, R> {
+                fn matches(&self, node: &$node) -> bool {
+                    self.left.matches(node) || self.right.matches(node)
+                }
+            }
+
+            impl<M: $matcher + ?Sized> $matcher for NotMatcher<M> {
+                fn matches(&self, node: &$node) -> bool {
+                    !self.inner.matches(node)
+                }
+            }
+        )*
+    };
+}
+
+impl_combinators!(
+    ExpressionMatcher(Expression),
+    StatementMatcher(Statement),
+    ParameterMatcher(Parameter),
+    TypeMatcher(TypeExpr)
+);
+
+// Matches nodes that lie entirely inside one of a set of source spans and
+// are matched by the inner matcher. Line ranges and containing functions are
+// turned into spans when the matcher is built, see `within_lines` and
+// `within_function`.
+pub struct PositionMatcher<M: ?Sized> {
+    spans: Vec<Span>,
+    inner: Box<M>,
+}
+
+impl<M: ?Sized> PositionMatcher<M> {
+    pub fn new(spans: Vec<Span>, inner: Box<M>) -> Box<PositionMatcher<M>> {
+        Box::new(PositionMatcher { spans, inner })
+    }
+
+    fn explain_position(&self, span: Span) -> MatchReport {
+        MatchReport::check(
+            self.spans.iter().any(|s| s.contains(span)),
+            || {
+                let spans: Vec<String> = self
+                    .spans
+                    .iter()
+                    .map(|s| format!("{:?}", s.range()))
+                    .collect();
+                format!("a node within {}", spans.join(" or "))
+            },
+            || format!("a node at {:?}", span.range()),
+        )
+        .at("span")
+    }
+}
+
+macro_rules! impl_position_matcher {
+    ($($matcher:ident($node:ty)),*) => {
+        $(
+            impl<M: $matcher + ?Sized> $matcher for PositionMatcher<M> {
+                fn matches(&self, node: &$node) -> bool {
+                    let span = node.span();
+                    self.spans.iter().any(|s| s.contains(span)) && self.inner.matches(node)
+                }
+
+                fn explain(&self, node: &$node) -> MatchReport {
+                    self.explain_position(node.span())
+                        .and_then(|| self.inner.explain(node))
+                }
+            }
+        )*
+    };
+}
+
+impl_position_matcher!(
+    ExpressionMatcher(Expression),
+    StatementMatcher(Statement),
+    ParameterMatcher(Parameter)
+);
+
 pub struct NamedParameterMatcher {
-    identifier: String,
+    identifier: Symbol,
     ttype: Box<dyn TypeMatcher>,
 }
 
 impl NamedParameterMatcher {
     pub fn new(identifier: String, ttype: Box<dyn TypeMatcher>) -> Box<NamedParameterMatcher> {
-        Box::new(NamedParameterMatcher { identifier, ttype })
+        Box::new(NamedParameterMatcher {
+            identifier: Symbol::new(&identifier),
+            ttype,
+        })
     }
 }
 
@@ -83,10 +573,24 @@ impl ParameterMatcher for NamedParameterMatcher {
     fn matches(&self, parameter: &Parameter) -> bool {
         self.identifier == parameter.identifier.name && self.ttype.matches(&parameter.ttype)
     }
+
+    fn explain(&self, parameter: &Parameter) -> MatchReport {
+        explain_name(&self.identifier, &parameter.identifier.name)
+            .at("identifier")
+            .and_then(|| self.ttype.explain(&parameter.ttype).at("ttype"))
+    }
+}
+
+fn explain_name(expected: &Symbol, actual: &Symbol) -> MatchReport {
+    MatchReport::check(
+        expected == actual,
+        || format!("`{}`", expected),
+        || format!("`{}`", actual),
+    )
 }
 
 pub struct LetStatementMatcher {
-    identifier: String,
+    identifier: Symbol,
     ttype: Box<dyn TypeMatcher>,
     mutable: bool,
     expression: Box<dyn ExpressionMatcher>,
@@ -100,7 +604,7 @@ impl LetStatementMatcher {
         expression: Box<dyn ExpressionMatcher>,
     ) -> Box<LetStatementMatcher> {
         Box::new(LetStatementMatcher {
-            identifier,
+            identifier: Symbol::new(&identifier),
             ttype,
             mutable,
             expression,
@@ -117,10 +621,31 @@ impl StatementMatcher for LetStatementMatcher {
                 && self.expression.matches(&let_statement.expression)
         })
     }
+
+    fn explain(&self, statement: &Statement) -> MatchReport {
+        let Statement::Let(let_statement) = statement else {
+            return MatchReport::mismatch("let statement", describe_statement(statement));
+        };
+        MatchReport::check(
+            let_statement.mutable == self.mutable,
+            || format!("mutable = {}", self.mutable),
+            || format!("mutable = {}", let_statement.mutable),
+        )
+        .at("mutable")
+        .and_then(|| {
+            explain_name(&self.identifier, &let_statement.identifier.name).at("identifier")
+        })
+        .and_then(|| self.ttype.explain(&let_statement.ttype).at("ttype"))
+        .and_then(|| {
+            self.expression
+                .explain(&let_statement.expression)
+                .at("expression")
+        })
+    }
 }
 
 pub struct FunctionDeclarationMatcher {
-    identifier: String,
+    identifier: Symbol,
     parameters: Vec<Box<dyn ParameterMatcher>>,
     return_type: Box<dyn TypeMatcher>,
 }
@@ -132,7 +657,7 @@ impl FunctionDeclarationMatcher {
         return_type: Box<dyn TypeMatcher>,
     ) -> Box<FunctionDeclarationMatcher> {
         Box::new(FunctionDeclarationMatcher {
-            identifier,
+            identifier: Symbol::new(&identifier),
             parameters,
             return_type,
         })
@@ -148,200 +673,1264 @@ impl StatementMatcher for FunctionDeclarationMatcher {
                 && self.parameters.iter().zip(function_declaration.parameters.iter()).all(|(m, p)| m.matches(p))
         })
     }
+
+    fn explain(&self, statement: &Statement) -> MatchReport {
+        let Statement::FunctionDeclaration(function) = statement else {
+            return MatchReport::mismatch("function declaration", describe_statement(statement));
+        };
+        explain_name(&self.identifier, &function.identifier.name)
+            .at("identifier")
+            .and_then(|| {
+                explain_all(&self.parameters, &function.parameters, |m, p| m.explain(p))
+                    .at("parameters")
+            })
+            .and_then(|| {
+                self.return_type
+                    .explain(&function.return_type)
+                    .at("return_type")
+            })
+    }
 }
 
-pub struct IdentifierMatcher {
-    identifier: String,
+pub struct ExpressionStatementMatcher {
+    expression: Box<dyn ExpressionMatcher>,
 }
 
-impl IdentifierMatcher {
-    pub fn new(identifier: String) -> Box<IdentifierMatcher> {
-        Box::new(IdentifierMatcher { identifier })
+impl ExpressionStatementMatcher {
+    pub fn new(expression: Box<dyn ExpressionMatcher>) -> Box<ExpressionStatementMatcher> {
+        Box::new(ExpressionStatementMatcher { expression })
     }
 }
 
-impl ExpressionMatcher for IdentifierMatcher {
-    fn matches(&self, expression: &Expression) -> bool {
-        matches!(expression, Expression::Identifier(i) if i.name == self.identifier)
+impl StatementMatcher for ExpressionStatementMatcher {
+    fn matches(&self, statement: &Statement) -> bool {
+        matches!(statement, Statement::Expression(e) if self.expression.matches(e))
+    }
+
+    fn explain(&self, statement: &Statement) -> MatchReport {
+        match statement {
+            Statement::Expression(e) => self.expression.explain(e).at("expression"),
+            _ => MatchReport::mismatch("expression statement", describe_statement(statement)),
+        }
     }
 }
 
-pub struct AnyIdentifierMatcher {
-    _private: (),
+pub struct ReturnStatementMatcher {
+    // `None` matches a bare `return;`.
+    expression: Option<Box<dyn ExpressionMatcher>>,
 }
 
-impl AnyIdentifierMatcher {
-    pub fn new() -> Box<AnyIdentifierMatcher> {
-        Box::new(AnyIdentifierMatcher { _private: () })
+impl ReturnStatementMatcher {
+    pub fn new(expression: Option<Box<dyn ExpressionMatcher>>) -> Box<ReturnStatementMatcher> {
+        Box::new(ReturnStatementMatcher { expression })
     }
 }
 
-impl ExpressionMatcher for AnyIdentifierMatcher {
-    fn matches(&self, expression: &Expression) -> bool {
-        matches!(expression, Expression::Identifier(_))
+impl StatementMatcher for ReturnStatementMatcher {
+    fn matches(&self, statement: &Statement) -> bool {
+        matches!(statement, Statement::Return(return_statement) if {
+            match (&self.expression, &return_statement.expression) {
+                (Some(m), Some(e)) => m.matches(e),
+                (None, None) => true,
+                _ => false,
+            }
+        })
+    }
+
+    fn explain(&self, statement: &Statement) -> MatchReport {
+        let Statement::Return(return_statement) = statement else {
+            return MatchReport::mismatch("return statement", describe_statement(statement));
+        };
+        match (&self.expression, &return_statement.expression) {
+            (Some(m), Some(e)) => m.explain(e),
+            (None, None) => MatchReport::Match,
+            (Some(_), None) => MatchReport::mismatch("a value", "none"),
+            (None, Some(e)) => MatchReport::mismatch("no value", describe_expression(e)),
+        }
+        .at("expression")
     }
 }
 
-pub struct IntegerLiteralMatcher {
-    identifier: String,
+pub struct BlockMatcher {
+    statements: Vec<Box<dyn StatementMatcher>>,
 }
 
-impl IntegerLiteralMatcher {
-    pub fn new(identifier: String) -> Box<IntegerLiteralMatcher> {
-        Box::new(IntegerLiteralMatcher { identifier })
+impl BlockMatcher {
+    pub fn new(statements: Vec<Box<dyn StatementMatcher>>) -> Box<BlockMatcher> {
+        Box::new(BlockMatcher { statements })
     }
 }
 
-impl ExpressionMatcher for IntegerLiteralMatcher {
-    fn matches(&self, expression: &Expression) -> bool {
-        matches!(expression, Expression::IntegerLiteral(i) if i.text == self.identifier)
+impl StatementMatcher for BlockMatcher {
+    fn matches(&self, statement: &Statement) -> bool {
+        matches!(statement, Statement::Block(block) if {
+            all_statements_match(&self.statements, &block.statements)
+        })
+    }
+
+    fn explain(&self, statement: &Statement) -> MatchReport {
+        match statement {
+            Statement::Block(block) => explain_statements(&self.statements, &block.statements),
+            _ => MatchReport::mismatch("block", describe_statement(statement)),
+        }
     }
 }
 
-pub struct AnyIntegerLiteralMatcher {
-    _private: (),
+pub struct IfStatementMatcher {
+    condition: Box<dyn ExpressionMatcher>,
+    then_statements: Vec<Box<dyn StatementMatcher>>,
+    // `None` matches an `if` without an `else`.
+    else_branch: Option<Box<dyn StatementMatcher>>,
 }
 
-impl AnyIntegerLiteralMatcher {
-    pub fn new() -> Box<AnyIntegerLiteralMatcher> {
-        Box::new(AnyIntegerLiteralMatcher { _private: () })
+impl IfStatementMatcher {
+    pub fn new(
+        condition: Box<dyn ExpressionMatcher>,
+        then_statements: Vec<Box<dyn StatementMatcher>>,
+        else_branch: Option<Box<dyn StatementMatcher>>,
+    ) -> Box<IfStatementMatcher> {
+        Box::new(IfStatementMatcher {
+            condition,
+            then_statements,
+            else_branch,
+        })
     }
 }
 
-impl ExpressionMatcher for AnyIntegerLiteralMatcher {
-    fn matches(&self, expression: &Expression) -> bool {
-        matches!(expression, Expression::IntegerLiteral(_))
+impl StatementMatcher for IfStatementMatcher {
+    fn matches(&self, statement: &Statement) -> bool {
+        matches!(statement, Statement::If(if_statement) if {
+            self.condition.matches(&if_statement.condition)
+                && all_statements_match(&self.then_statements, &if_statement.then_block.statements)
+                && match (&self.else_branch, &if_statement.else_branch) {
+                    (Some(m), Some(s)) => m.matches(s),
+                    (None, None) => true,
+                    _ => false,
+                }
+        })
+    }
+
+    fn explain(&self, statement: &Statement) -> MatchReport {
+        let Statement::If(if_statement) = statement else {
+            return MatchReport::mismatch("if statement", describe_statement(statement));
+        };
+        self.condition
+            .explain(&if_statement.condition)
+            .at("condition")
+            .and_then(|| {
+                explain_statements(&self.then_statements, &if_statement.then_block.statements)
+                    .at("then_block")
+            })
+            .and_then(|| {
+                match (&self.else_branch, &if_statement.else_branch) {
+                    (Some(m), Some(s)) => m.explain(s),
+                    (None, None) => MatchReport::Match,
+                    (Some(_), None) => MatchReport::mismatch("an else branch", "none"),
+                    (None, Some(s)) => {
+                        MatchReport::mismatch("no else branch", describe_statement(s))
+                    }
+                }
+                .at("else_branch")
+            })
     }
 }
 
-pub struct BinaryExpressionMatcher {
-    left: Box<dyn ExpressionMatcher>,
-    right: Box<dyn ExpressionMatcher>,
-    operator: BinaryOperator,
+pub struct WhileStatementMatcher {
+    condition: Box<dyn ExpressionMatcher>,
+    body: Vec<Box<dyn StatementMatcher>>,
 }
 
-impl BinaryExpressionMatcher {
+impl WhileStatementMatcher {
     pub fn new(
-        left: Box<dyn ExpressionMatcher>,
-        operator: BinaryOperator,
-        right: Box<dyn ExpressionMatcher>,
-    ) -> Box<BinaryExpressionMatcher> {
-        Box::new(BinaryExpressionMatcher {
-            left,
-            operator,
-            right,
-        })
+        condition: Box<dyn ExpressionMatcher>,
+        body: Vec<Box<dyn StatementMatcher>>,
+    ) -> Box<WhileStatementMatcher> {
+        Box::new(WhileStatementMatcher { condition, body })
     }
 }
 
-impl ExpressionMatcher for BinaryExpressionMatcher {
-    fn matches(&self, expression: &Expression) -> bool {
-        matches!(expression, Expression::BinaryExpression(binary_exp) if {
-            binary_exp.operator == self.operator
-                && self.left.matches(&binary_exp.left)
-                && self.right.matches(&binary_exp.right)
+impl StatementMatcher for WhileStatementMatcher {
+    fn matches(&self, statement: &Statement) -> bool {
+        matches!(statement, Statement::While(while_statement) if {
+            self.condition.matches(&while_statement.condition)
+                && all_statements_match(&self.body, &while_statement.body.statements)
         })
     }
-}
 
-pub struct AnyBinaryExpressionMatcher {}
-
-impl AnyBinaryExpressionMatcher {
-    pub fn new() -> Box<AnyBinaryExpressionMatcher> {
-        Box::new(AnyBinaryExpressionMatcher {})
+    fn explain(&self, statement: &Statement) -> MatchReport {
+        let Statement::While(while_statement) = statement else {
+            return MatchReport::mismatch("while statement", describe_statement(statement));
+        };
+        self.condition
+            .explain(&while_statement.condition)
+            .at("condition")
+            .and_then(|| {
+                explain_statements(&self.body, &while_statement.body.statements).at("body")
+            })
     }
 }
 
-impl ExpressionMatcher for AnyBinaryExpressionMatcher {
-    fn matches(&self, expression: &Expression) -> bool {
-        matches!(expression, Expression::BinaryExpression(_))
+// Explains a block's statements matched in sequence by a list of matchers.
+fn explain_statements(
+    matchers: &[Box<dyn StatementMatcher>],
+    statements: &[Statement],
+) -> MatchReport {
+    if matchers.iter().any(|m| m.repetitions().is_some()) {
+        // With repetitions there is no single statement to blame.
+        return MatchReport::check(
+            all_statements_match(matchers, statements),
+            || "statements matching a repeated sequence".to_string(),
+            || format!("{} statements that do not", statements.len()),
+        )
+        .at("statements");
     }
+    explain_all(matchers, statements, |m, s| m.explain(s)).at("statements")
 }
 
-#[macro_export]
-macro_rules! match_integer_literal {
-    ($integer_literal:literal) => {
-        IntegerLiteralMatcher::new($integer_literal.to_string())
-    };
-    () => {
-        AnyIntegerLiteralMatcher::new()
+// Returns true if the statements can be split into consecutive runs, one per
+// matcher, where each run is made of statements the matcher accepts and is as
+// long as its repetitions allow. Matchers without repetitions take exactly
+// one statement.
+fn all_statements_match(matchers: &[Box<dyn StatementMatcher>], statements: &[Statement]) -> bool {
+    let Some((first, rest)) = matchers.split_first() else {
+        return statements.is_empty();
     };
+    let (min, max) = first.repetitions().unwrap_or((1, Some(1)));
+    let max = max.unwrap_or(usize::MAX);
+    let run = statements
+        .iter()
+        .take(max)
+        .take_while(|s| first.matches(s))
+        .count();
+    // Try the longest run first, then shorter ones.
+    (min..=run)
+        .rev()
+        .any(|n| all_statements_match(rest, &statements[n..]))
 }
 
-#[macro_export]
-macro_rules! match_identifier {
-    ($identifier:literal) => {
-        IdentifierMatcher::new($identifier.to_string())
-    };
-    () => {
-        AnyIdentifierMatcher::new()
-    };
+// Matches a whole program: one statement matcher per top-level statement, in
+// order.
+pub struct ProgramMatcher {
+    statements: Vec<Box<dyn StatementMatcher>>,
 }
 
-#[macro_export]
-macro_rules! match_binary_expression {
-    ($left:expr, $operator:expr, $right:expr) => {
-        BinaryExpressionMatcher::new($left, $operator, $right)
-    };
-    () => {
-        AnyBinaryExpressionMatcher::new()
-    };
-}
+impl ProgramMatcher {
+    pub fn new(statements: Vec<Box<dyn StatementMatcher>>) -> Box<ProgramMatcher> {
+        Box::new(ProgramMatcher { statements })
+    }
 
-#[macro_export]
-macro_rules! match_any_expression {
-    () => {
-        AnyMatcher::new()
-    };
-}
+    pub fn matches(&self, program: &Program) -> bool {
+        all_statements_match(&self.statements, &program.statements)
+    }
 
-#[macro_export]
-macro_rules! match_any_type {
-    () => {
-        AnyTypeMatcher::new()
+    // Returns the index of the first statement that does not match, or the
+    // shorter length if the program and the matcher disagree on the number of
+    // statements. With repeated matchers, statements and matchers are paired
+    // up one to one, so the index is only a hint.
+    pub fn mismatch(&self, program: &Program) -> Option<usize> {
+        if self.matches(program) {
+            return None;
+        }
+        let statements = &program.statements;
+        match self
+            .statements
+            .iter()
+            .zip(statements)
+            .position(|(m, s)| !m.matches(s))
+        {
+            Some(index) => Some(index),
+            None => Some(self.statements.len().min(statements.len())),
+        }
+    }
+
+    pub fn explain(&self, program: &Program) -> MatchReport {
+        explain_statements(&self.statements, &program.statements)
+    }
+
+    // Panics with an explanation of the first mismatch if the program does
+    // not match.
+    pub fn assert_matches(&self, program: &Program) {
+        let report = self.explain(program);
+        if !report.is_match() {
+            panic!("Program did not match: {}", report);
+        }
+    }
+}
+
+// How a list of statement matchers is applied to a block.
+pub enum BodyMatcher {
+    // Matches when the block's statements match the matchers one to one.
+    Exact(Vec<Box<dyn StatementMatcher>>),
+    // Matches when each matcher matches some statement in the block, at any
+    // nesting depth and in any order. A repeated matcher must match as many
+    // statements as its repetitions allow.
+    Contains(Vec<Box<dyn StatementMatcher>>),
+}
+
+impl BodyMatcher {
+    pub fn matches(&self, block: &Block) -> bool {
+        match self {
+            BodyMatcher::Exact(matchers) => all_statements_match(matchers, &block.statements),
+            BodyMatcher::Contains(matchers) => matchers.iter().all(|m| {
+                let (min, max) = m.repetitions().unwrap_or((1, None));
+                // Counting past the maximum is enough to reject the block.
+                let limit = max.map_or(min, |max| max + 1);
+                let count = count_statements(m.as_ref(), &block.statements, limit);
+                count >= min && max.is_none_or(|max| count <= max)
+            }),
+        }
+    }
+
+    pub fn explain(&self, block: &Block) -> MatchReport {
+        match self {
+            BodyMatcher::Exact(matchers) => explain_statements(matchers, &block.statements),
+            BodyMatcher::Contains(_) => MatchReport::check(
+                self.matches(block),
+                || "a body containing every statement matched".to_string(),
+                || "a body that does not".to_string(),
+            ),
+        }
+    }
+}
+
+pub struct FunctionWithBodyMatcher {
+    identifier: Symbol,
+    body: BodyMatcher,
+}
+
+impl FunctionWithBodyMatcher {
+    pub fn new(identifier: String, body: BodyMatcher) -> Box<FunctionWithBodyMatcher> {
+        Box::new(FunctionWithBodyMatcher {
+            identifier: Symbol::new(&identifier),
+            body,
+        })
+    }
+}
+
+impl StatementMatcher for FunctionWithBodyMatcher {
+    fn matches(&self, statement: &Statement) -> bool {
+        matches!(statement, Statement::FunctionDeclaration(function) if {
+            function.identifier.name == self.identifier
+                && function.body.as_ref().is_some_and(|body| self.body.matches(body))
+        })
+    }
+
+    fn explain(&self, statement: &Statement) -> MatchReport {
+        let Statement::FunctionDeclaration(function) = statement else {
+            return MatchReport::mismatch("function declaration", describe_statement(statement));
+        };
+        explain_name(&self.identifier, &function.identifier.name)
+            .at("identifier")
+            .and_then(|| match &function.body {
+                Some(body) => self.body.explain(body).at("body"),
+                None => MatchReport::mismatch("a body", "none").at("body"),
+            })
+    }
+}
+
+// Matches a statement if it, or any statement nested inside it, is matched by
+// the inner matcher.
+pub struct ContainsMatcher {
+    inner: Box<dyn StatementMatcher>,
+}
+
+impl ContainsMatcher {
+    pub fn new(inner: Box<dyn StatementMatcher>) -> Box<ContainsMatcher> {
+        Box::new(ContainsMatcher { inner })
+    }
+}
+
+impl StatementMatcher for ContainsMatcher {
+    fn matches(&self, statement: &Statement) -> bool {
+        contains_statement(self.inner.as_ref(), statement)
+    }
+}
+
+// Returns true if the statement or any statement nested inside it matches.
+fn contains_statement(matcher: &dyn StatementMatcher, statement: &Statement) -> bool {
+    count_statements(matcher, std::slice::from_ref(statement), 1) == 1
+}
+
+// Counts the statements, and the statements nested inside them, that match,
+// stopping once the count reaches `limit`.
+fn count_statements(
+    matcher: &dyn StatementMatcher,
+    statements: &[Statement],
+    limit: usize,
+) -> usize {
+    struct Count<'m> {
+        matcher: &'m dyn StatementMatcher,
+        count: usize,
+        limit: usize,
+    }
+
+    impl<'ast> Visitor<'ast> for Count<'_> {
+        fn visit_statement(&mut self, statement: &'ast Statement<'ast>) -> Control {
+            if self.matcher.matches(statement) {
+                self.count += 1;
+            }
+            if self.count >= self.limit {
+                Control::Stop
+            } else {
+                Control::Continue
+            }
+        }
+    }
+
+    let mut count = Count {
+        matcher,
+        count: 0,
+        limit,
+    };
+    for statement in statements {
+        if visit::walk_statement(&mut count, statement) == Control::Stop {
+            break;
+        }
+    }
+    count.count
+}
+
+// Stands for a run of statements in a statement list, each matched by the
+// inner matcher, with a length between `min` and `max`. On its own it matches
+// a single statement like the inner matcher.
+pub struct RepeatedMatcher {
+    inner: Box<dyn StatementMatcher>,
+    min: usize,
+    max: Option<usize>,
+}
+
+impl RepeatedMatcher {
+    pub fn new(
+        inner: Box<dyn StatementMatcher>,
+        min: usize,
+        max: Option<usize>,
+    ) -> Box<RepeatedMatcher> {
+        Box::new(RepeatedMatcher { inner, min, max })
+    }
+}
+
+impl StatementMatcher for RepeatedMatcher {
+    fn matches(&self, statement: &Statement) -> bool {
+        self.inner.matches(statement)
+    }
+
+    fn explain(&self, statement: &Statement) -> MatchReport {
+        self.inner.explain(statement)
+    }
+
+    fn repetitions(&self) -> Option<(usize, Option<usize>)> {
+        Some((self.min, self.max))
+    }
+}
+
+pub struct IdentifierMatcher {
+    identifier: Symbol,
+}
+
+impl IdentifierMatcher {
+    pub fn new(identifier: String) -> Box<IdentifierMatcher> {
+        Box::new(IdentifierMatcher {
+            identifier: Symbol::new(&identifier),
+        })
+    }
+}
+
+impl ExpressionMatcher for IdentifierMatcher {
+    fn matches(&self, expression: &Expression) -> bool {
+        matches!(expression, Expression::Identifier(i) if i.name == self.identifier)
+    }
+
+    fn explain(&self, expression: &Expression) -> MatchReport {
+        MatchReport::check(
+            self.matches(expression),
+            || format!("`{}`", self.identifier),
+            || describe_expression(expression),
+        )
+    }
+}
+
+pub struct AnyIdentifierMatcher {
+    _private: (),
+}
+
+impl AnyIdentifierMatcher {
+    pub fn new() -> Box<AnyIdentifierMatcher> {
+        Box::new(AnyIdentifierMatcher { _private: () })
+    }
+}
+
+impl ExpressionMatcher for AnyIdentifierMatcher {
+    fn matches(&self, expression: &Expression) -> bool {
+        matches!(expression, Expression::Identifier(_))
+    }
+}
+
+// Matches identifiers whose whole name matches a regular expression.
+pub struct IdentifierRegexMatcher {
+    regex: Regex,
+}
+
+impl IdentifierRegexMatcher {
+    // Matches identifiers whose name matches `regex` from start to end.
+    pub fn new(regex: &Regex) -> Box<IdentifierRegexMatcher> {
+        let anchored = Regex::new(&format!("^(?:{})$", regex.as_str())).unwrap();
+        Box::new(IdentifierRegexMatcher { regex: anchored })
+    }
+}
+
+impl ExpressionMatcher for IdentifierRegexMatcher {
+    fn matches(&self, expression: &Expression) -> bool {
+        matches!(expression, Expression::Identifier(i) if self.regex.is_match(&i.name))
+    }
+
+    fn explain(&self, expression: &Expression) -> MatchReport {
+        MatchReport::check(
+            self.matches(expression),
+            || format!("identifier matching /{}/", self.regex.as_str()),
+            || describe_expression(expression),
+        )
+    }
+}
+
+pub struct IntegerLiteralMatcher {
+    identifier: String,
+}
+
+impl IntegerLiteralMatcher {
+    pub fn new(identifier: String) -> Box<IntegerLiteralMatcher> {
+        Box::new(IntegerLiteralMatcher { identifier })
+    }
+}
+
+impl ExpressionMatcher for IntegerLiteralMatcher {
+    fn matches(&self, expression: &Expression) -> bool {
+        matches!(expression, Expression::IntegerLiteral(i) if i.text == self.identifier)
+    }
+
+    fn explain(&self, expression: &Expression) -> MatchReport {
+        MatchReport::check(
+            self.matches(expression),
+            || format!("`{}`", self.identifier),
+            || describe_expression(expression),
+        )
+    }
+}
+
+pub struct AnyIntegerLiteralMatcher {
+    _private: (),
+}
+
+impl AnyIntegerLiteralMatcher {
+    pub fn new() -> Box<AnyIntegerLiteralMatcher> {
+        Box::new(AnyIntegerLiteralMatcher { _private: () })
+    }
+}
+
+impl ExpressionMatcher for AnyIntegerLiteralMatcher {
+    fn matches(&self, expression: &Expression) -> bool {
+        matches!(expression, Expression::IntegerLiteral(_))
+    }
+}
+
+// Matches integer literals whose value lies in a range.
+pub struct IntegerRangeMatcher {
+    start: Bound<i128>,
+    end: Bound<i128>,
+}
+
+impl IntegerRangeMatcher {
+    pub fn new(range: impl RangeBounds<i128>) -> Box<IntegerRangeMatcher> {
+        Box::new(IntegerRangeMatcher {
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+        })
+    }
+}
+
+impl ExpressionMatcher for IntegerRangeMatcher {
+    fn matches(&self, expression: &Expression) -> bool {
+        matches!(expression, Expression::IntegerLiteral(literal) if {
+            literal
+                .text
+                .parse::<i128>()
+                .is_ok_and(|value| (self.start, self.end).contains(&value))
+        })
+    }
+
+    fn explain(&self, expression: &Expression) -> MatchReport {
+        MatchReport::check(
+            self.matches(expression),
+            || {
+                let start = match self.start {
+                    Bound::Included(start) | Bound::Excluded(start) => start.to_string(),
+                    Bound::Unbounded => String::new(),
+                };
+                let end = match self.end {
+                    Bound::Included(end) => format!("={}", end),
+                    Bound::Excluded(end) => end.to_string(),
+                    Bound::Unbounded => String::new(),
+                };
+                format!("integer literal in {}..{}", start, end)
+            },
+            || describe_expression(expression),
+        )
+    }
+}
+
+pub struct BinaryExpressionMatcher {
+    left: Box<dyn ExpressionMatcher>,
+    right: Box<dyn ExpressionMatcher>,
+    operator: BinaryOperator,
+}
+
+impl BinaryExpressionMatcher {
+    pub fn new(
+        left: Box<dyn ExpressionMatcher>,
+        operator: BinaryOperator,
+        right: Box<dyn ExpressionMatcher>,
+    ) -> Box<BinaryExpressionMatcher> {
+        Box::new(BinaryExpressionMatcher {
+            left,
+            operator,
+            right,
+        })
+    }
+}
+
+impl ExpressionMatcher for BinaryExpressionMatcher {
+    fn matches(&self, expression: &Expression) -> bool {
+        matches!(expression, Expression::BinaryExpression(binary_exp) if {
+            binary_exp.operator == self.operator
+                && self.left.matches(&binary_exp.left)
+                && self.right.matches(&binary_exp.right)
+        })
+    }
+
+    fn explain(&self, expression: &Expression) -> MatchReport {
+        let Expression::BinaryExpression(binary) = expression else {
+            return MatchReport::mismatch("a binary expression", describe_expression(expression));
+        };
+        MatchReport::check(
+            binary.operator == self.operator,
+            || format!("'{}'", operator_text(&self.operator)),
+            || format!("'{}'", operator_text(&binary.operator)),
+        )
+        .at("operator")
+        .and_then(|| self.left.explain(&binary.left).at("left"))
+        .and_then(|| self.right.explain(&binary.right).at("right"))
+    }
+}
+
+pub struct CallExpressionMatcher {
+    callee: Box<dyn ExpressionMatcher>,
+    arguments: Vec<Box<dyn ExpressionMatcher>>,
+}
+
+impl CallExpressionMatcher {
+    pub fn new(
+        callee: Box<dyn ExpressionMatcher>,
+        arguments: Vec<Box<dyn ExpressionMatcher>>,
+    ) -> Box<CallExpressionMatcher> {
+        Box::new(CallExpressionMatcher { callee, arguments })
+    }
+}
+
+impl ExpressionMatcher for CallExpressionMatcher {
+    fn matches(&self, expression: &Expression) -> bool {
+        matches!(expression, Expression::Call(call) if {
+            self.callee.matches(&call.callee)
+                && call.arguments.len() == self.arguments.len()
+                && self.arguments.iter().zip(&call.arguments).all(|(m, a)| m.matches(a))
+        })
+    }
+
+    fn explain(&self, expression: &Expression) -> MatchReport {
+        let Expression::Call(call) = expression else {
+            return MatchReport::mismatch("a call", describe_expression(expression));
+        };
+        self.callee.explain(&call.callee).at("callee").and_then(|| {
+            explain_all(&self.arguments, &call.arguments, |m, a| m.explain(a)).at("arguments")
+        })
+    }
+}
+
+pub struct AnyCallExpressionMatcher {}
+
+impl AnyCallExpressionMatcher {
+    pub fn new() -> Box<AnyCallExpressionMatcher> {
+        Box::new(AnyCallExpressionMatcher {})
+    }
+}
+
+impl ExpressionMatcher for AnyCallExpressionMatcher {
+    fn matches(&self, expression: &Expression) -> bool {
+        matches!(expression, Expression::Call(_))
+    }
+}
+
+pub struct CastExpressionMatcher {
+    expression: Box<dyn ExpressionMatcher>,
+    ttype: Box<dyn TypeMatcher>,
+}
+
+impl CastExpressionMatcher {
+    pub fn new(
+        expression: Box<dyn ExpressionMatcher>,
+        ttype: Box<dyn TypeMatcher>,
+    ) -> Box<CastExpressionMatcher> {
+        Box::new(CastExpressionMatcher { expression, ttype })
+    }
+}
+
+impl ExpressionMatcher for CastExpressionMatcher {
+    fn matches(&self, expression: &Expression) -> bool {
+        matches!(expression, Expression::Cast(cast) if {
+            self.expression.matches(&cast.expression) && self.ttype.matches(&cast.ttype)
+        })
+    }
+
+    fn explain(&self, expression: &Expression) -> MatchReport {
+        let Expression::Cast(cast) = expression else {
+            return MatchReport::mismatch("a cast", describe_expression(expression));
+        };
+        self.expression
+            .explain(&cast.expression)
+            .at("expression")
+            .and_then(|| self.ttype.explain(&cast.ttype).at("type"))
+    }
+}
+
+pub struct AnyBinaryExpressionMatcher {}
+
+impl AnyBinaryExpressionMatcher {
+    pub fn new() -> Box<AnyBinaryExpressionMatcher> {
+        Box::new(AnyBinaryExpressionMatcher {})
+    }
+}
+
+impl ExpressionMatcher for AnyBinaryExpressionMatcher {
+    fn matches(&self, expression: &Expression) -> bool {
+        matches!(expression, Expression::BinaryExpression(_))
+    }
+}
+
+// Builder functions for matchers, one per `match_*!` macro. The macros
+// expand to these, so code that assembles matchers at runtime (from a lint
+// configuration, say) gets exactly the same behaviour.
+
+pub fn any() -> Box<AnyMatcher> {
+    AnyMatcher::new()
+}
+
+pub fn identifier(name: &str) -> Box<IdentifierMatcher> {
+    IdentifierMatcher::new(name.to_string())
+}
+
+pub fn any_identifier() -> Box<AnyIdentifierMatcher> {
+    AnyIdentifierMatcher::new()
+}
+
+// Returns an error if `pattern` is not a valid regular expression.
+pub fn identifier_regex(pattern: &str) -> Result<Box<IdentifierRegexMatcher>, regex::Error> {
+    Ok(IdentifierRegexMatcher::new(&Regex::new(pattern)?))
+}
+
+pub fn integer_in(range: impl RangeBounds<i128>) -> Box<IntegerRangeMatcher> {
+    IntegerRangeMatcher::new(range)
+}
+
+pub fn integer_literal(text: &str) -> Box<IntegerLiteralMatcher> {
+    IntegerLiteralMatcher::new(text.to_string())
+}
+
+pub fn any_integer_literal() -> Box<AnyIntegerLiteralMatcher> {
+    AnyIntegerLiteralMatcher::new()
+}
+
+pub fn binary_expression(
+    left: Box<dyn ExpressionMatcher>,
+    operator: BinaryOperator,
+    right: Box<dyn ExpressionMatcher>,
+) -> Box<BinaryExpressionMatcher> {
+    BinaryExpressionMatcher::new(left, operator, right)
+}
+
+pub fn any_binary_expression() -> Box<AnyBinaryExpressionMatcher> {
+    AnyBinaryExpressionMatcher::new()
+}
+
+pub fn call_expression(
+    callee: Box<dyn ExpressionMatcher>,
+    arguments: Vec<Box<dyn ExpressionMatcher>>,
+) -> Box<CallExpressionMatcher> {
+    CallExpressionMatcher::new(callee, arguments)
+}
+
+pub fn any_call_expression() -> Box<AnyCallExpressionMatcher> {
+    AnyCallExpressionMatcher::new()
+}
+
+pub fn cast_expression(
+    expression: Box<dyn ExpressionMatcher>,
+    ttype: Box<dyn TypeMatcher>,
+) -> Box<CastExpressionMatcher> {
+    CastExpressionMatcher::new(expression, ttype)
+}
+
+pub fn named_type(name: &str) -> Box<NamedTypeMatcher> {
+    NamedTypeMatcher::new(name.to_string())
+}
+
+pub fn unit_type() -> Box<UnitTypeMatcher> {
+    UnitTypeMatcher::new()
+}
+
+pub fn array_type(element: Box<dyn TypeMatcher>, size: &str) -> Box<ArrayTypeMatcher> {
+    ArrayTypeMatcher::new(element, size.to_string())
+}
+
+pub fn generic_type(name: &str, arguments: Vec<Box<dyn TypeMatcher>>) -> Box<GenericTypeMatcher> {
+    GenericTypeMatcher::new(name.to_string(), arguments)
+}
+
+pub fn tuple_type(elements: Vec<Box<dyn TypeMatcher>>) -> Box<TupleTypeMatcher> {
+    TupleTypeMatcher::new(elements)
+}
+
+pub fn function_type(
+    parameters: Vec<Box<dyn TypeMatcher>>,
+    return_type: Box<dyn TypeMatcher>,
+) -> Box<FunctionTypeMatcher> {
+    FunctionTypeMatcher::new(parameters, return_type)
+}
+
+pub fn parameter(name: &str, ttype: Box<dyn TypeMatcher>) -> Box<NamedParameterMatcher> {
+    NamedParameterMatcher::new(name.to_string(), ttype)
+}
+
+pub fn let_statement(
+    name: &str,
+    ttype: Box<dyn TypeMatcher>,
+    expression: Box<dyn ExpressionMatcher>,
+) -> Box<LetStatementMatcher> {
+    LetStatementMatcher::new(name.to_string(), ttype, false, expression)
+}
+
+pub fn mutable_let_statement(
+    name: &str,
+    ttype: Box<dyn TypeMatcher>,
+    expression: Box<dyn ExpressionMatcher>,
+) -> Box<LetStatementMatcher> {
+    LetStatementMatcher::new(name.to_string(), ttype, true, expression)
+}
+
+pub fn function_declaration(
+    name: &str,
+    parameters: Vec<Box<dyn ParameterMatcher>>,
+    return_type: Box<dyn TypeMatcher>,
+) -> Box<FunctionDeclarationMatcher> {
+    FunctionDeclarationMatcher::new(name.to_string(), parameters, return_type)
+}
+
+pub fn function_with_body(name: &str, body: BodyMatcher) -> Box<FunctionWithBodyMatcher> {
+    FunctionWithBodyMatcher::new(name.to_string(), body)
+}
+
+pub fn expression_statement(
+    expression: Box<dyn ExpressionMatcher>,
+) -> Box<ExpressionStatementMatcher> {
+    ExpressionStatementMatcher::new(expression)
+}
+
+pub fn return_statement(
+    expression: Option<Box<dyn ExpressionMatcher>>,
+) -> Box<ReturnStatementMatcher> {
+    ReturnStatementMatcher::new(expression)
+}
+
+pub fn block(statements: Vec<Box<dyn StatementMatcher>>) -> Box<BlockMatcher> {
+    BlockMatcher::new(statements)
+}
+
+pub fn if_statement(
+    condition: Box<dyn ExpressionMatcher>,
+    then_statements: Vec<Box<dyn StatementMatcher>>,
+    else_branch: Option<Box<dyn StatementMatcher>>,
+) -> Box<IfStatementMatcher> {
+    IfStatementMatcher::new(condition, then_statements, else_branch)
+}
+
+pub fn while_statement(
+    condition: Box<dyn ExpressionMatcher>,
+    body: Vec<Box<dyn StatementMatcher>>,
+) -> Box<WhileStatementMatcher> {
+    WhileStatementMatcher::new(condition, body)
+}
+
+pub fn contains(statement: Box<dyn StatementMatcher>) -> Box<ContainsMatcher> {
+    ContainsMatcher::new(statement)
+}
+
+pub fn repeated(
+    inner: Box<dyn StatementMatcher>,
+    min: usize,
+    max: Option<usize>,
+) -> Box<RepeatedMatcher> {
+    RepeatedMatcher::new(inner, min, max)
+}
+
+pub fn program(statements: Vec<Box<dyn StatementMatcher>>) -> Box<ProgramMatcher> {
+    ProgramMatcher::new(statements)
+}
+
+pub fn within_span<M: ?Sized>(span: Span, inner: Box<M>) -> Box<PositionMatcher<M>> {
+    PositionMatcher::new(vec![span], inner)
+}
+
+// Matches nodes on the given 1-based lines of `source`, the text the program
+// was parsed from.
+pub fn within_lines<M: ?Sized>(
+    source: &str,
+    lines: impl RangeBounds<usize>,
+    inner: Box<M>,
+) -> Box<PositionMatcher<M>> {
+    // The byte offset at which each line starts; line 1 is at index 0.
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_start = |line: usize| match line.checked_sub(1) {
+        Some(index) => line_starts.get(index).copied().unwrap_or(source.len()),
+        None => 0,
+    };
+    let start = match lines.start_bound() {
+        Bound::Included(line) => line_start(*line),
+        Bound::Excluded(line) => line_start(line + 1),
+        Bound::Unbounded => 0,
+    };
+    let end = match lines.end_bound() {
+        Bound::Included(line) => line_start(line + 1),
+        Bound::Excluded(line) => line_start(*line),
+        Bound::Unbounded => source.len(),
+    };
+    within_span(Span::new(start, end.max(start)), inner)
+}
+
+// Matches nodes inside any function named `name`, including the function
+// declarations themselves.
+pub fn within_function<M: ?Sized>(
+    program: &Program,
+    name: &str,
+    inner: Box<M>,
+) -> Box<PositionMatcher<M>> {
+    let functions = function_with_body(name, BodyMatcher::Contains(vec![]));
+    let spans = crate::query::find_statements(program, functions.as_ref())
+        .into_iter()
+        .map(Spanned::span)
+        .collect();
+    PositionMatcher::new(spans, inner)
+}
+
+pub fn all_of<L: ?Sized, R: ?Sized>(left: Box<L>, right: Box<R>) -> Box<AllOfMatcher<L, R>> {
+    AllOfMatcher::new(left, right)
+}
+
+pub fn any_of<L: ?Sized, R: ?Sized>(left: Box<L>, right: Box<R>) -> Box<AnyOfMatcher<L, R>> {
+    AnyOfMatcher::new(left, right)
+}
+
+pub fn not<M: ?Sized>(inner: Box<M>) -> Box<NotMatcher<M>> {
+    NotMatcher::new(inner)
+}
+
+#[macro_export]
+macro_rules! match_integer_literal {
+    ($integer_literal:literal) => {
+        $crate::matcher::integer_literal(&$integer_literal.to_string())
+    };
+    () => {
+        $crate::matcher::any_integer_literal()
+    };
+}
+
+#[macro_export]
+macro_rules! match_identifier {
+    ($identifier:literal) => {
+        $crate::matcher::identifier($identifier)
+    };
+    () => {
+        $crate::matcher::any_identifier()
+    };
+}
+
+#[macro_export]
+macro_rules! match_identifier_regex {
+    ($pattern:literal) => {
+        $crate::matcher::identifier_regex($pattern).expect("invalid identifier regex")
+    };
+}
+
+#[macro_export]
+macro_rules! match_integer_in {
+    ($range:expr) => {
+        $crate::matcher::integer_in($range)
+    };
+}
+
+#[macro_export]
+macro_rules! match_binary_expression {
+    ($left:expr, $operator:expr, $right:expr) => {
+        $crate::matcher::binary_expression($left, $operator, $right)
+    };
+    () => {
+        $crate::matcher::any_binary_expression()
+    };
+}
+
+#[macro_export]
+macro_rules! match_any_expression {
+    () => {
+        $crate::matcher::any()
+    };
+}
+
+#[macro_export]
+macro_rules! match_any_type {
+    () => {
+        $crate::matcher::any()
     };
 }
 
 #[macro_export]
 macro_rules! match_let_statement {
     ($identifier:literal, $ttype:expr, $expression:expr) => {
-        LetStatementMatcher::new($identifier.to_string(), $ttype, false, $expression)
+        $crate::matcher::let_statement($identifier, $ttype, $expression)
     };
 }
 
 #[macro_export]
 macro_rules! match_mutable_let_statement {
     ($identifier:literal, $ttype:expr, $expression:expr) => {
-        LetStatementMatcher::new($identifier.to_string(), $ttype, true, $expression)
+        $crate::matcher::mutable_let_statement($identifier, $ttype, $expression)
     };
 }
 
 #[macro_export]
 macro_rules! match_function_declaration {
     ($identifier:literal, $params:expr, $ttype:expr) => {
-        FunctionDeclarationMatcher::new($identifier.to_string(), $params, $ttype)
+        $crate::matcher::function_declaration($identifier, $params, $ttype)
     };
     ($identifier:literal, $ttype:expr) => {
-        FunctionDeclarationMatcher::new($identifier.to_string(), vec![], $ttype)
+        $crate::matcher::function_declaration($identifier, vec![], $ttype)
     };
 }
 
 #[macro_export]
 macro_rules! match_type {
     ($name:literal) => {
-        NamedTypeMatcher::new($name.to_string())
+        $crate::matcher::named_type($name)
     };
     () => {
-        AnyMatcher::new()
+        $crate::matcher::any()
+    };
+}
+
+#[macro_export]
+macro_rules! match_unit_type {
+    () => {
+        $crate::matcher::unit_type()
+    };
+}
+
+#[macro_export]
+macro_rules! match_array_type {
+    ($element:expr, $size:literal) => {
+        $crate::matcher::array_type($element, &$size.to_string())
+    };
+}
+
+#[macro_export]
+macro_rules! match_generic_type {
+    ($name:literal, $arguments:expr) => {
+        $crate::matcher::generic_type($name, $arguments)
+    };
+}
+
+#[macro_export]
+macro_rules! match_tuple_type {
+    ($elements:expr) => {
+        $crate::matcher::tuple_type($elements)
+    };
+}
+
+#[macro_export]
+macro_rules! match_function_type {
+    ($parameters:expr, $return_type:expr) => {
+        $crate::matcher::function_type($parameters, $return_type)
     };
 }
 
 #[macro_export]
 macro_rules! match_parameter {
     ($identifier:literal, $ttype:literal) => {
-        NamedParameterMatcher::new($identifier.to_string(), match_type!($ttype))
+        $crate::matcher::parameter($identifier, $crate::match_type!($ttype))
+    };
+    () => {
+        $crate::matcher::any()
+    };
+}
+
+#[macro_export]
+macro_rules! match_call_expression {
+    ($callee:expr, $arguments:expr) => {
+        $crate::matcher::call_expression($callee, $arguments)
+    };
+    () => {
+        $crate::matcher::any_call_expression()
+    };
+}
+
+#[macro_export]
+macro_rules! match_cast_expression {
+    ($expression:expr, $ttype:expr) => {
+        $crate::matcher::cast_expression($expression, $ttype)
+    };
+}
+
+#[macro_export]
+macro_rules! match_expression_statement {
+    ($expression:expr) => {
+        $crate::matcher::expression_statement($expression)
+    };
+}
+
+#[macro_export]
+macro_rules! match_return_statement {
+    ($expression:expr) => {
+        $crate::matcher::return_statement(Some($expression))
     };
     () => {
-        AnyMatcher::new()
+        $crate::matcher::return_statement(None)
+    };
+}
+
+#[macro_export]
+macro_rules! match_block {
+    ($($statement:expr),* $(,)?) => {
+        $crate::matcher::block(vec![
+            $($statement as Box<dyn $crate::matcher::StatementMatcher>),*
+        ])
+    };
+}
+
+#[macro_export]
+macro_rules! match_if_statement {
+    ($condition:expr, $then:expr, $else:expr) => {
+        $crate::matcher::if_statement($condition, $then, Some($else))
+    };
+    ($condition:expr, $then:expr) => {
+        $crate::matcher::if_statement($condition, $then, None)
+    };
+}
+
+#[macro_export]
+macro_rules! match_while_statement {
+    ($condition:expr, $body:expr) => {
+        $crate::matcher::while_statement($condition, $body)
+    };
+}
+
+#[macro_export]
+macro_rules! match_program {
+    ($($statement:expr),* $(,)?) => {
+        $crate::matcher::program(vec![
+            $($statement as Box<dyn $crate::matcher::StatementMatcher>),*
+        ])
+    };
+}
+
+#[macro_export]
+macro_rules! match_within_lines {
+    ($source:expr, $lines:expr, $matcher:expr) => {
+        $crate::matcher::within_lines($source, $lines, $matcher)
+    };
+}
+
+#[macro_export]
+macro_rules! match_within_function {
+    ($program:expr, $name:literal, $matcher:expr) => {
+        $crate::matcher::within_function($program, $name, $matcher)
+    };
+}
+
+#[macro_export]
+macro_rules! match_repeated {
+    ($statement:expr, at_least = $min:expr, at_most = $max:expr) => {
+        $crate::matcher::repeated($statement, $min, Some($max))
+    };
+    ($statement:expr, at_least = $min:expr) => {
+        $crate::matcher::repeated($statement, $min, None)
+    };
+    ($statement:expr, at_most = $max:expr) => {
+        $crate::matcher::repeated($statement, 0, Some($max))
+    };
+    ($statement:expr, exactly = $count:expr) => {
+        $crate::matcher::repeated($statement, $count, Some($count))
+    };
+    ($statement:expr) => {
+        $crate::matcher::repeated($statement, 0, None)
+    };
+}
+
+#[macro_export]
+macro_rules! all_of {
+    ($matcher:expr $(,)?) => {
+        $matcher
+    };
+    ($first:expr, $($rest:expr),+ $(,)?) => {
+        $crate::matcher::all_of($first, $crate::all_of!($($rest),+))
+    };
+}
+
+#[macro_export]
+macro_rules! any_of {
+    ($matcher:expr $(,)?) => {
+        $matcher
+    };
+    ($first:expr, $($rest:expr),+ $(,)?) => {
+        $crate::matcher::any_of($first, $crate::any_of!($($rest),+))
+    };
+}
+
+#[macro_export]
+macro_rules! not {
+    ($matcher:expr) => {
+        $crate::matcher::not($matcher)
+    };
+}
+
+#[macro_export]
+macro_rules! match_function_with_body {
+    ($identifier:literal, [$($statement:expr),* $(,)?]) => {
+        $crate::matcher::function_with_body(
+            $identifier,
+            $crate::matcher::BodyMatcher::Exact(vec![
+                $($statement as Box<dyn $crate::matcher::StatementMatcher>),*
+            ]),
+        )
+    };
+    ($identifier:literal, contains [$($statement:expr),* $(,)?]) => {
+        $crate::matcher::function_with_body(
+            $identifier,
+            $crate::matcher::BodyMatcher::Contains(vec![
+                $($statement as Box<dyn $crate::matcher::StatementMatcher>),*
+            ]),
+        )
+    };
+}
+
+#[macro_export]
+macro_rules! match_contains {
+    ($statement:expr) => {
+        $crate::matcher::contains($statement)
     };
 }
@@ -0,0 +1,193 @@
+use crate::ast::{Block, Expression, Program, Statement, TypeExpr};
+use std::collections::BTreeMap;
+
+// Size and shape measurements of a program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metrics {
+    // The number of nodes of each kind, keyed by node type name such as
+    // "LetStatement" or "Identifier".
+    pub node_counts: BTreeMap<&'static str, usize>,
+    // The number of statements at any nesting level.
+    pub statements: usize,
+    pub top_level_statements: usize,
+    // The depth of the deepest expression tree; a lone literal has depth 1.
+    pub max_expression_depth: usize,
+}
+
+impl Metrics {
+    // Returns the number of nodes of the named kind.
+    pub fn count(&self, kind: &str) -> usize {
+        self.node_counts.get(kind).copied().unwrap_or(0)
+    }
+
+    pub fn total_nodes(&self) -> usize {
+        self.node_counts.values().sum()
+    }
+
+    fn add(&mut self, kind: &'static str) {
+        *self.node_counts.entry(kind).or_insert(0) += 1;
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        self.statements += 1;
+        match statement {
+            Statement::Let(let_statement) => {
+                self.add("LetStatement");
+                self.add("Identifier");
+                self.ttype(&let_statement.ttype);
+                self.expression_tree(&let_statement.expression);
+            }
+            Statement::Const(constant) => {
+                self.add("ConstDeclaration");
+                self.add("Identifier");
+                self.ttype(&constant.ttype);
+                self.expression_tree(&constant.expression);
+            }
+            Statement::FunctionDeclaration(function) => {
+                self.add("FunctionDeclaration");
+                self.add("Identifier");
+                for parameter in &function.parameters {
+                    self.add("Parameter");
+                    self.add("Identifier");
+                    self.ttype(&parameter.ttype);
+                }
+                self.ttype(&function.return_type);
+                if let Some(body) = &function.body {
+                    self.block(body);
+                }
+            }
+            Statement::Expression(expression) => {
+                self.add("ExpressionStatement");
+                self.expression_tree(expression);
+            }
+            Statement::Return(return_statement) => {
+                self.add("ReturnStatement");
+                if let Some(expression) = &return_statement.expression {
+                    self.expression_tree(expression);
+                }
+            }
+            Statement::If(if_statement) => {
+                self.add("IfStatement");
+                self.expression_tree(&if_statement.condition);
+                self.block(&if_statement.then_block);
+                if let Some(else_branch) = &if_statement.else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::While(while_statement) => {
+                self.add("WhileStatement");
+                self.expression_tree(&while_statement.condition);
+                self.block(&while_statement.body);
+            }
+            Statement::Block(block) => self.block(block),
+            Statement::Import(_) => {
+                self.add("ImportStatement");
+                self.add("Identifier");
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.add("Block");
+        for statement in &block.statements {
+            self.statement(statement);
+        }
+    }
+
+    fn expression_tree(&mut self, expression: &Expression) {
+        let depth = self.expression(expression);
+        self.max_expression_depth = self.max_expression_depth.max(depth);
+    }
+
+    // Counts the nodes of an expression and returns its depth.
+    fn expression(&mut self, expression: &Expression) -> usize {
+        match expression {
+            Expression::IntegerLiteral(_) => {
+                self.add("IntegerLiteral");
+                1
+            }
+            Expression::Identifier(_) => {
+                self.add("Identifier");
+                1
+            }
+            Expression::BinaryExpression(binary) => {
+                self.add("BinaryExpression");
+                let left = self.expression(&binary.left);
+                let right = self.expression(&binary.right);
+                1 + left.max(right)
+            }
+            Expression::Call(call) => {
+                self.add("CallExpression");
+                let callee = self.expression(&call.callee);
+                let arguments = call.arguments.iter().map(|e| self.expression(e));
+                1 + arguments.fold(callee, usize::max)
+            }
+            Expression::Cast(cast) => {
+                self.add("CastExpression");
+                self.ttype(&cast.ttype);
+                1 + self.expression(&cast.expression)
+            }
+        }
+    }
+
+    fn ttype(&mut self, ttype: &TypeExpr) {
+        match ttype {
+            TypeExpr::Named(_) => self.add("Type"),
+            TypeExpr::Array(array) => {
+                self.add("ArrayType");
+                self.ttype(&array.element);
+                self.expression_tree(&array.size);
+            }
+            TypeExpr::Generic(generic) => {
+                self.add("GenericType");
+                self.add("Type");
+                generic.arguments.iter().for_each(|t| self.ttype(t));
+            }
+            TypeExpr::Tuple(elements) => {
+                self.add("TupleType");
+                elements.iter().for_each(|t| self.ttype(t));
+            }
+            TypeExpr::Function(function) => {
+                self.add("FunctionType");
+                function.parameters.iter().for_each(|t| self.ttype(t));
+                self.ttype(&function.return_type);
+            }
+        }
+    }
+}
+
+// Measures a program.
+pub fn metrics(program: &Program) -> Metrics {
+    let mut metrics = Metrics {
+        top_level_statements: program.statements.len(),
+        ..Metrics::default()
+    };
+    for statement in &program.statements {
+        metrics.statement(statement);
+    }
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lexer::Lexer, parser::Parser};
+
+    #[test]
+    fn counts_nodes_and_depth() {
+        let tokens = Lexer::tokenize(
+            "fn f(a: int32, b: List<int32>) -> int32 { let c: int32 = a * (b + 1); return c; }\nf;",
+        );
+        let metrics = Parser::parse_program(&tokens).unwrap().metrics();
+        assert_eq!(metrics.top_level_statements, 2);
+        assert_eq!(metrics.statements, 4);
+        assert_eq!(metrics.max_expression_depth, 3);
+        assert_eq!(metrics.count("FunctionDeclaration"), 1);
+        assert_eq!(metrics.count("Parameter"), 2);
+        assert_eq!(metrics.count("Identifier"), 8);
+        assert_eq!(metrics.count("BinaryExpression"), 2);
+        assert_eq!(metrics.count("Type"), 5);
+        assert_eq!(metrics.count("GenericType"), 1);
+        assert_eq!(metrics.count("ArrayType"), 0);
+        assert_eq!(metrics.total_nodes(), 24);
+    }
+}
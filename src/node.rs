@@ -0,0 +1,195 @@
+use crate::ast::{
+    Block, Expression, Identifier, Parameter, Program, Span, Spanned, Statement, TypeExpr,
+};
+use alloc::vec;
+
+// A reference to any AST node, so that generic tooling can walk the tree
+// without matching on every node type.
+#[derive(Debug, Clone, Copy)]
+pub enum NodeRef<'a> {
+    Program(&'a Program<'a>),
+    Statement(&'a Statement<'a>),
+    Parameter(&'a Parameter<'a>),
+    Block(&'a Block<'a>),
+    Expression(&'a Expression<'a>),
+    Identifier(&'a Identifier),
+    Type(&'a TypeExpr<'a>),
+}
+
+impl<'a> NodeRef<'a> {
+    // Returns the node's direct children in source order. Identifiers used as
+    // expressions are children of their expression node.
+    pub fn children(&self) -> impl Iterator<Item = NodeRef<'a>> {
+        let mut children = vec![];
+        match *self {
+            NodeRef::Program(program) => {
+                children.extend(program.statements.iter().map(NodeRef::Statement));
+            }
+            NodeRef::Statement(statement) => match statement {
+                Statement::Let(let_statement) => {
+                    children.push(NodeRef::Identifier(&let_statement.identifier));
+                    children.push(NodeRef::Type(&let_statement.ttype));
+                    children.push(NodeRef::Expression(&let_statement.expression));
+                }
+                Statement::Const(constant) => {
+                    children.push(NodeRef::Identifier(&constant.identifier));
+                    children.push(NodeRef::Type(&constant.ttype));
+                    children.push(NodeRef::Expression(&constant.expression));
+                }
+                Statement::FunctionDeclaration(function) => {
+                    children.push(NodeRef::Identifier(&function.identifier));
+                    children.extend(function.parameters.iter().map(NodeRef::Parameter));
+                    children.push(NodeRef::Type(&function.return_type));
+                    children.extend(function.body.iter().map(NodeRef::Block));
+                }
+                Statement::Expression(expression) => {
+                    children.push(NodeRef::Expression(expression));
+                }
+                Statement::Return(return_statement) => {
+                    children.extend(
+                        return_statement
+                            .expression
+                            .iter()
+                            .map(|e| NodeRef::Expression(e)),
+                    );
+                }
+                Statement::If(if_statement) => {
+                    children.push(NodeRef::Expression(&if_statement.condition));
+                    children.push(NodeRef::Block(&if_statement.then_block));
+                    children.extend(
+                        if_statement
+                            .else_branch
+                            .iter()
+                            .map(|s| NodeRef::Statement(s)),
+                    );
+                }
+                Statement::While(while_statement) => {
+                    children.push(NodeRef::Expression(&while_statement.condition));
+                    children.push(NodeRef::Block(&while_statement.body));
+                }
+                Statement::Block(block) => children.push(NodeRef::Block(block)),
+                Statement::Import(import) => children.push(NodeRef::Identifier(&import.module)),
+            },
+            NodeRef::Parameter(parameter) => {
+                children.push(NodeRef::Identifier(&parameter.identifier));
+                children.push(NodeRef::Type(&parameter.ttype));
+            }
+            NodeRef::Block(block) => {
+                children.extend(block.statements.iter().map(NodeRef::Statement));
+            }
+            NodeRef::Expression(expression) => match expression {
+                Expression::IntegerLiteral(_) => {}
+                Expression::Identifier(identifier) => {
+                    children.push(NodeRef::Identifier(identifier));
+                }
+                Expression::BinaryExpression(binary) => {
+                    children.push(NodeRef::Expression(&binary.left));
+                    children.push(NodeRef::Expression(&binary.right));
+                }
+                Expression::Call(call) => {
+                    children.push(NodeRef::Expression(&call.callee));
+                    children.extend(call.arguments.iter().map(NodeRef::Expression));
+                }
+                Expression::Cast(cast) => {
+                    children.push(NodeRef::Expression(&cast.expression));
+                    children.push(NodeRef::Type(&cast.ttype));
+                }
+            },
+            NodeRef::Identifier(_) => {}
+            NodeRef::Type(ttype) => match ttype {
+                TypeExpr::Named(_) => {}
+                TypeExpr::Array(array) => {
+                    children.push(NodeRef::Type(&array.element));
+                    children.push(NodeRef::Expression(&array.size));
+                }
+                TypeExpr::Generic(generic) => {
+                    children.extend(generic.arguments.iter().map(NodeRef::Type));
+                }
+                TypeExpr::Tuple(elements) => children.extend(elements.iter().map(NodeRef::Type)),
+                TypeExpr::Function(function) => {
+                    children.extend(function.parameters.iter().map(NodeRef::Type));
+                    children.push(NodeRef::Type(&function.return_type));
+                }
+            },
+        }
+        children.into_iter()
+    }
+}
+
+impl Spanned for NodeRef<'_> {
+    // A program's span runs from the start of its first statement to the end
+    // of its last.
+    fn span(&self) -> Span {
+        match *self {
+            NodeRef::Program(program) => {
+                match (program.statements.first(), program.statements.last()) {
+                    (Some(first), Some(last)) => first.span().to(last.span()),
+                    _ => Span::default(),
+                }
+            }
+            NodeRef::Statement(statement) => statement.span(),
+            NodeRef::Parameter(parameter) => parameter.span,
+            NodeRef::Block(block) => block.span,
+            NodeRef::Expression(expression) => expression.span(),
+            NodeRef::Identifier(identifier) => identifier.span,
+            NodeRef::Type(ttype) => ttype.span(),
+        }
+    }
+}
+
+macro_rules! impl_node {
+    ($($variant:ident($node:ty)),*) => {
+        $(
+            impl<'a> From<&'a $node> for NodeRef<'a> {
+                fn from(node: &'a $node) -> NodeRef<'a> {
+                    NodeRef::$variant(node)
+                }
+            }
+
+            impl<'a> $node {
+                // Returns the node's direct children in source order.
+                pub fn children(&'a self) -> impl Iterator<Item = NodeRef<'a>> {
+                    NodeRef::from(self).children()
+                }
+            }
+        )*
+    };
+}
+
+impl_node!(
+    Program(Program<'a>),
+    Statement(Statement<'a>),
+    Parameter(Parameter<'a>),
+    Block(Block<'a>),
+    Expression(Expression<'a>),
+    Type(TypeExpr<'a>)
+);
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    // Returns the depth of the tree below `node`, counting `node` itself.
+    fn depth(node: NodeRef) -> usize {
+        1 + node.children().map(depth).max().unwrap_or(0)
+    }
+
+    // Returns the number of nodes in the tree below `node`, including `node`.
+    fn size(node: NodeRef) -> usize {
+        1 + node.children().map(size).sum::<usize>()
+    }
+
+    #[test]
+    fn generic_walks_reach_every_node() {
+        let tokens = Lexer::tokenize("fn f(a: List<int32>) { return a * (b + 1); } c;");
+        let program = Parser::parse_program(&tokens).unwrap();
+        let statements: Vec<_> = program.children().collect();
+        assert_eq!(statements.len(), 2);
+        assert!(matches!(statements[0], NodeRef::Statement(_)));
+        // Program, function, block, return, '*', '+', 'b', its identifier.
+        assert_eq!(depth(NodeRef::from(&program)), 8);
+        assert_eq!(size(NodeRef::from(&program)), 20);
+        assert_eq!(program.statements[1].children().count(), 1);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::ssa::Function;
+use std::fmt;
+
+// Optimizations over the SSA form of functions, and the pipeline that runs
+// them in order.
+pub mod constprop;
+pub mod cse;
+pub mod dce;
+pub mod inline;
+
+// A transformation of a function that keeps what it does. Passes must leave
+// the function well formed, as `Function::verify` checks.
+pub trait Optimization {
+    fn name(&self) -> &'static str;
+
+    // Transforms a function, returning true if anything changed.
+    fn run(&mut self, function: &mut Function) -> bool;
+}
+
+// Returns the optimization called `name`.
+pub fn optimization(name: &str) -> Option<Box<dyn Optimization>> {
+    Some(match name {
+        "const-prop" => Box::new(constprop::ConstantPropagation),
+        "cse" => Box::new(cse::CommonSubexpressionElimination),
+        "dce" => Box::new(dce::DeadCodeElimination),
+        _ => return None,
+    })
+}
+
+// The name of an optimization that does not exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownOptimization(pub String);
+
+impl fmt::Display for UnknownOptimization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown optimization `{}`", self.0)
+    }
+}
+
+// Runs an ordered list of optimizations over each function, optionally
+// recording the function before and after each of them.
+#[derive(Default)]
+pub struct Pipeline {
+    passes: Vec<Box<dyn Optimization>>,
+    dumping: Dumping,
+}
+
+// Which passes' dumps a pipeline records.
+#[derive(Default)]
+enum Dumping {
+    #[default]
+    Nothing,
+    All,
+    Passes(Vec<String>),
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    // Constant propagation, which makes more values equal and more code
+    // dead, then common subexpression elimination, which leaves more code
+    // dead, then dead code elimination.
+    pub fn standard() -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .add(constprop::ConstantPropagation)
+            .add(cse::CommonSubexpressionElimination)
+            .add(dce::DeadCodeElimination);
+        pipeline
+    }
+
+    // Builds a pipeline from the names of its passes, such as
+    // `["const-prop", "dce"]`. A pass can be named more than once.
+    pub fn from_names(names: &[&str]) -> Result<Pipeline, UnknownOptimization> {
+        let mut pipeline = Pipeline::new();
+        for name in names {
+            let pass = optimization(name).ok_or_else(|| UnknownOptimization(name.to_string()))?;
+            pipeline.passes.push(pass);
+        }
+        Ok(pipeline)
+    }
+
+    // Adds a pass to run after those already added.
+    pub fn add(&mut self, pass: impl Optimization + 'static) -> &mut Pipeline {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    // Records a dump for every run of the pass called `name`.
+    pub fn dump(&mut self, name: &str) -> &mut Pipeline {
+        match &mut self.dumping {
+            Dumping::All => {}
+            Dumping::Passes(names) => names.push(name.to_string()),
+            Dumping::Nothing => self.dumping = Dumping::Passes(vec![name.to_string()]),
+        }
+        self
+    }
+
+    // Records a dump for every run of every pass.
+    pub fn dump_all(&mut self) -> &mut Pipeline {
+        self.dumping = Dumping::All;
+        self
+    }
+
+    // Runs every pass in order over a function, returning the dumps it was
+    // asked to record.
+    //
+    // In debug builds, it panics if a pass leaves the function malformed.
+    pub fn run(&mut self, function: &mut Function) -> Vec<Dump> {
+        let mut dumps = vec![];
+        for pass in &mut self.passes {
+            let dumped = match &self.dumping {
+                Dumping::Nothing => false,
+                Dumping::All => true,
+                Dumping::Passes(names) => names.iter().any(|name| name == pass.name()),
+            };
+            let before = dumped.then(|| function.to_string());
+            let changed = pass.run(function);
+            debug_assert_eq!(
+                function.verify(),
+                Ok(()),
+                "`{}` left a malformed function:\n{}",
+                pass.name(),
+                function
+            );
+            if let Some(before) = before {
+                dumps.push(Dump {
+                    pass: pass.name(),
+                    function: function.binding.name.to_string(),
+                    before,
+                    after: changed.then(|| function.to_string()),
+                });
+            }
+        }
+        dumps
+    }
+
+    // Runs the pipeline over every function in turn.
+    pub fn run_all(&mut self, functions: &mut [Function]) -> Vec<Dump> {
+        functions
+            .iter_mut()
+            .flat_map(|function| self.run(function))
+            .collect()
+    }
+}
+
+// A function before and after a pass ran over it, for debugging the pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dump {
+    pub pass: &'static str,
+    pub function: String,
+    pub before: String,
+    // `None` if the pass changed nothing.
+    pub after: Option<String>,
+}
+
+// A dump is displayed as the function before the pass and after it, each
+// under a header:
+//
+//   *** before dce on f ***
+//   fn f() -> ()
+//   ...
+//
+//   *** after dce on f ***
+//   ...
+impl fmt::Display for Dump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "*** before {} on {} ***", self.pass, self.function)?;
+        writeln!(f, "{}", self.before)?;
+        match &self.after {
+            Some(after) => write!(
+                f,
+                "*** after {} on {} ***\n{}",
+                self.pass, self.function, after
+            ),
+            None => writeln!(
+                f,
+                "*** {} changed nothing in {} ***",
+                self.pass, self.function
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, ssa};
+
+    pub(crate) fn build(source: &str) -> Vec<Function> {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        assert!(program.typecheck().errors.is_empty(), "{}", source);
+        ssa::build_all(&program.to_hir())
+    }
+
+    #[test]
+    fn the_standard_pipeline_simplifies_functions() {
+        let source = "\
+fn f(a: int32) -> int32 {
+    let two: int32 = 1 + 1;
+    let b: int32 = a * two;
+    if two > 1 {
+        return b + a * two;
+    }
+    let c: int32 = a / two;
+    println(c);
+    return 0;
+}";
+        let mut function = build(source).remove(0);
+        let mut pipeline = Pipeline::standard();
+        assert_eq!(pipeline.names(), ["const-prop", "cse", "dce"]);
+        assert!(pipeline.run(&mut function).is_empty());
+        assert_eq!(
+            function.to_string(),
+            "\
+fn f(v0: int32) -> int32
+
+bb0:
+    v3: int32 = 2
+    v4: int32 = (* v0 v3)
+    goto bb1
+
+bb1:
+    v8: int32 = (+ v4 v4)
+    return v8
+
+bb2:
+    unreachable
+
+bb3:
+    unreachable
+
+bb4:
+    unreachable
+"
+        );
+    }
+
+    #[test]
+    fn pipelines_are_configured_by_name_and_dump_passes() {
+        assert_eq!(
+            Pipeline::from_names(&["dce", "inline"]).err(),
+            Some(UnknownOptimization("inline".to_string()))
+        );
+        let mut pipeline = Pipeline::from_names(&["dce", "const-prop", "dce"]).unwrap();
+        assert_eq!(pipeline.names(), ["dce", "const-prop", "dce"]);
+        pipeline.dump("dce");
+
+        let mut functions = build("fn f() -> int8 { let x: int8 = 1; return x + 2; }");
+        let dumps = pipeline.run_all(&mut functions);
+        assert_eq!(dumps.len(), 2);
+        assert_eq!(
+            dumps[0].to_string(),
+            "\
+*** before dce on f ***
+fn f() -> int8
+
+bb0:
+    v0: int8 = 1
+    v1: int8 = 2
+    v2: int8 = (+ v0 v1)
+    return v2
+
+bb1:
+    unreachable
+
+*** dce changed nothing in f ***
+"
+        );
+        assert_eq!(
+            dumps[1].to_string(),
+            "\
+*** before dce on f ***
+fn f() -> int8
+
+bb0:
+    v0: int8 = 1
+    v1: int8 = 2
+    v2: int8 = 3
+    return v2
+
+bb1:
+    unreachable
+
+*** after dce on f ***
+fn f() -> int8
+
+bb0:
+    v2: int8 = 3
+    return v2
+
+bb1:
+    unreachable
+"
+        );
+
+        pipeline.dump_all();
+        assert_eq!(pipeline.run(&mut functions[0]).len(), 3);
+    }
+}
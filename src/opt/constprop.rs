@@ -0,0 +1,301 @@
+use super::Optimization;
+use crate::{
+    ast::TypeKind,
+    cfg::BlockId,
+    consteval::ConstValue,
+    ssa::{Function, Instruction, InstructionKind, Terminator, ValueId},
+    typecheck::Ty,
+    value::Value,
+};
+use std::collections::{HashMap, HashSet};
+
+// Sparse conditional constant propagation: finds the values that are the
+// same every time they are computed and the blocks that can run at all,
+// given that a branch on a constant condition only ever takes one way. Then
+// it replaces those values with constants, branches on constant conditions
+// with jumps, and the code of blocks that cannot run with `unreachable`.
+//
+// Values are computed as the program would compute them, so arithmetic
+// wraps around. Only integer and `bool` values are propagated; computations
+// that fail, such as a division by zero, are left to fail when the program
+// runs.
+pub struct ConstantPropagation;
+
+impl Optimization for ConstantPropagation {
+    fn name(&self) -> &'static str {
+        "const-prop"
+    }
+
+    fn run(&mut self, function: &mut Function) -> bool {
+        let analysis = Analysis::run(function);
+        analysis.rewrite(function)
+    }
+}
+
+// What is known about a value so far.
+#[derive(Debug, Clone, PartialEq)]
+enum Lattice {
+    // Nothing yet: the value has not been computed in a block that can run.
+    Unknown,
+    Constant(Value),
+    Varying,
+}
+
+impl Lattice {
+    // Combines what is known about a value from two places.
+    fn meet(self, other: Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Unknown, other) | (other, Lattice::Unknown) => other,
+            (Lattice::Constant(a), Lattice::Constant(b)) if a == b => Lattice::Constant(a),
+            _ => Lattice::Varying,
+        }
+    }
+}
+
+struct Analysis {
+    values: HashMap<ValueId, Lattice>,
+    // The edges control can take between blocks that can run.
+    edges: HashSet<(BlockId, BlockId)>,
+    // Indexed by `BlockId`.
+    executable: Vec<bool>,
+}
+
+impl Analysis {
+    // Starts knowing nothing and evaluates the blocks that can run until
+    // nothing more is learned. What is known about a value only ever goes
+    // from unknown to constant to varying, so that happens.
+    fn run(function: &Function) -> Analysis {
+        let mut analysis = Analysis {
+            values: HashMap::new(),
+            edges: HashSet::new(),
+            executable: vec![false; function.blocks.len()],
+        };
+        for parameter in &function.parameters {
+            analysis.values.insert(parameter.value, Lattice::Varying);
+        }
+        analysis.executable[Function::ENTRY.index()] = true;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in function.block_ids() {
+                if !analysis.executable[id.index()] {
+                    continue;
+                }
+                let block = function.block(id);
+                for instruction in &block.instructions {
+                    let value = analysis.evaluate(instruction, id);
+                    let old = analysis.get(instruction.value);
+                    let new = old.clone().meet(value);
+                    if new != old {
+                        analysis.values.insert(instruction.value, new);
+                        changed = true;
+                    }
+                }
+                for successor in analysis.successors(&block.terminator) {
+                    if analysis.edges.insert((id, successor)) {
+                        analysis.executable[successor.index()] = true;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        analysis
+    }
+
+    fn get(&self, value: ValueId) -> Lattice {
+        self.values.get(&value).cloned().unwrap_or(Lattice::Unknown)
+    }
+
+    // Returns what is known about an instruction's value in block `id`.
+    fn evaluate(&self, instruction: &Instruction, id: BlockId) -> Lattice {
+        let kind = match instruction.ty {
+            Ty::Primitive(kind) if kind.is_integer() || kind == TypeKind::Bool => kind,
+            _ => return Lattice::Varying,
+        };
+        match &instruction.kind {
+            InstructionKind::Constant(value) => {
+                Value::from_const(*value, kind).map_or(Lattice::Varying, Lattice::Constant)
+            }
+            InstructionKind::Binary(operator, left, right) => {
+                match (self.get(*left), self.get(*right)) {
+                    (Lattice::Varying, _) | (_, Lattice::Varying) => Lattice::Varying,
+                    (Lattice::Constant(left), Lattice::Constant(right)) => left
+                        .binary(operator, &right)
+                        .map_or(Lattice::Varying, Lattice::Constant),
+                    _ => Lattice::Unknown,
+                }
+            }
+            InstructionKind::Cast(operand) => match self.get(*operand) {
+                Lattice::Constant(value) => {
+                    value.cast(kind).map_or(Lattice::Varying, Lattice::Constant)
+                }
+                other => other,
+            },
+            // Only what comes along edges control can take counts.
+            InstructionKind::Phi(incoming) => incoming
+                .iter()
+                .filter(|(predecessor, _)| self.edges.contains(&(*predecessor, id)))
+                .fold(Lattice::Unknown, |known, (_, value)| {
+                    known.meet(self.get(*value))
+                }),
+            InstructionKind::Outer(_)
+            | InstructionKind::Call(..)
+            | InstructionKind::Builtin(..) => Lattice::Varying,
+        }
+    }
+
+    // Returns the blocks control can go to from a terminator.
+    fn successors(&self, terminator: &Terminator) -> Vec<BlockId> {
+        match terminator {
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => match self.get(*condition) {
+                Lattice::Constant(Value::Bool(true)) => vec![*then_block],
+                Lattice::Constant(Value::Bool(false)) => vec![*else_block],
+                Lattice::Unknown => vec![],
+                _ => vec![*then_block, *else_block],
+            },
+            terminator => terminator.successors(),
+        }
+    }
+
+    fn rewrite(&self, function: &mut Function) -> bool {
+        let mut changed = false;
+        for (i, block) in function.blocks.iter_mut().enumerate() {
+            let id = BlockId(i as u32);
+            if !self.executable[i] {
+                if !block.instructions.is_empty() || block.terminator != Terminator::Unreachable {
+                    block.instructions.clear();
+                    block.terminator = Terminator::Unreachable;
+                    changed = true;
+                }
+                continue;
+            }
+            for instruction in &mut block.instructions {
+                match &mut instruction.kind {
+                    InstructionKind::Constant(_) => {}
+                    InstructionKind::Phi(incoming) => {
+                        let count = incoming.len();
+                        incoming
+                            .retain(|(predecessor, _)| self.edges.contains(&(*predecessor, id)));
+                        changed |= incoming.len() != count;
+                    }
+                    kind => {
+                        let constant = match self.get(instruction.value) {
+                            Lattice::Constant(Value::Integer(_, value)) => {
+                                ConstValue::Integer(value.into())
+                            }
+                            Lattice::Constant(Value::Bool(value)) => ConstValue::Bool(value),
+                            _ => continue,
+                        };
+                        *kind = InstructionKind::Constant(constant);
+                        changed = true;
+                    }
+                }
+            }
+            if let Terminator::Branch { .. } = block.terminator {
+                if let [successor] = self.successors(&block.terminator)[..] {
+                    block.terminator = Terminator::Goto(successor);
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opt::tests::build;
+
+    fn propagate(source: &str) -> (String, bool) {
+        let mut function = build(source).remove(0);
+        let changed = ConstantPropagation.run(&mut function);
+        assert_eq!(function.verify(), Ok(()), "{}", function);
+        (function.to_string(), changed)
+    }
+
+    #[test]
+    fn constants_are_folded_as_the_program_computes_them() {
+        let source = "\
+fn f(a: int8) -> int8 {
+    let big: int8 = 100;
+    let wrapped: int8 = big + big;
+    let zero: int8 = 0;
+    let x: float32 = 1;
+    return wrapped as int16 as int8 + a / zero + (x / 2) as int8;
+}";
+        assert_eq!(
+            propagate(source),
+            (
+                "\
+fn f(v0: int8) -> int8
+
+bb0:
+    v1: int8 = 100
+    v2: int8 = -56
+    v3: int8 = 0
+    v4: float32 = 1
+    v5: int16 = -56
+    v6: int8 = -56
+    v7: int8 = (/ v0 v3)
+    v8: int8 = (+ v6 v7)
+    v9: float32 = 2
+    v10: float32 = (/ v4 v9)
+    v11: int8 = (as int8 v10)
+    v12: int8 = (+ v8 v11)
+    return v12
+
+bb1:
+    unreachable
+"
+                .to_string(),
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn branches_on_constants_become_jumps() {
+        let source = "\
+fn f(a: bool) -> int64 {
+    let n: int64 = 3;
+    if n > 2 { return n; } else { println(a); }
+    return 0;
+}";
+        let (text, changed) = propagate(source);
+        assert!(changed);
+        assert_eq!(
+            text,
+            "\
+fn f(v0: bool) -> int64
+
+bb0:
+    v1: int64 = 3
+    v2: int64 = 2
+    v3: bool = true
+    goto bb1
+
+bb1:
+    return v1
+
+bb2:
+    unreachable
+
+bb3:
+    unreachable
+
+bb4:
+    unreachable
+
+bb5:
+    unreachable
+"
+        );
+        let (_, changed) = propagate("fn f(a: bool) -> bool { return a; }");
+        assert!(!changed);
+    }
+}
@@ -0,0 +1,185 @@
+use super::Optimization;
+use crate::{
+    ast::BinaryOperator,
+    cfg::BlockId,
+    ssa::{Function, InstructionKind, ValueId},
+    typecheck::Ty,
+};
+use std::collections::HashMap;
+
+// Common subexpression elimination: when an instruction computes what an
+// instruction that dominates it already computed, its uses are replaced with
+// the earlier value and it is removed.
+//
+// Calls and builtins are never merged, since they can do something
+// different each time, and neither are phis, which depend on where control
+// came from. Operands of operators whose order does not matter are sorted,
+// so `a + b` and `b + a` are the same.
+pub struct CommonSubexpressionElimination;
+
+impl Optimization for CommonSubexpressionElimination {
+    fn name(&self) -> &'static str {
+        "cse"
+    }
+
+    fn run(&mut self, function: &mut Function) -> bool {
+        let mut children = vec![vec![]; function.blocks.len()];
+        for (i, dominator) in function.dominators().into_iter().enumerate() {
+            match dominator {
+                Some(dominator) if dominator.index() != i => {
+                    children[dominator.index()].push(BlockId(i as u32))
+                }
+                _ => {}
+            }
+        }
+        let mut numbering = Numbering {
+            function,
+            children,
+            available: HashMap::new(),
+            replacements: HashMap::new(),
+        };
+        numbering.visit(Function::ENTRY);
+        let replacements = numbering.replacements;
+        if replacements.is_empty() {
+            return false;
+        }
+
+        for block in &mut function.blocks {
+            block
+                .instructions
+                .retain(|instruction| !replacements.contains_key(&instruction.value));
+            let operands = block
+                .instructions
+                .iter_mut()
+                .flat_map(|instruction| instruction.kind.operands_mut())
+                .chain(block.terminator.operands_mut());
+            for operand in operands {
+                if let Some(replacement) = replacements.get(operand) {
+                    *operand = *replacement;
+                }
+            }
+        }
+        true
+    }
+}
+
+struct Numbering<'f> {
+    function: &'f Function,
+    // The blocks each block immediately dominates.
+    children: Vec<Vec<BlockId>>,
+    // What the instructions in the blocks that dominate the one being
+    // visited compute, and the values they compute it in.
+    available: HashMap<(InstructionKind, Ty), ValueId>,
+    // The value each removed instruction's uses are replaced with.
+    replacements: HashMap<ValueId, ValueId>,
+}
+
+impl Numbering<'_> {
+    // Visits a block and then the blocks it dominates, so that every
+    // instruction is seen after those that dominate it.
+    fn visit(&mut self, id: BlockId) {
+        let mut added = vec![];
+        for instruction in &self.function.block(id).instructions {
+            if matches!(
+                instruction.kind,
+                InstructionKind::Call(..) | InstructionKind::Builtin(..) | InstructionKind::Phi(_)
+            ) {
+                continue;
+            }
+            let mut kind = instruction.kind.clone();
+            for operand in kind.operands_mut() {
+                if let Some(replacement) = self.replacements.get(operand) {
+                    *operand = *replacement;
+                }
+            }
+            if let InstructionKind::Binary(operator, left, right) = &mut kind {
+                let commutative = matches!(
+                    operator,
+                    BinaryOperator::Plus
+                        | BinaryOperator::Star
+                        | BinaryOperator::Equal
+                        | BinaryOperator::NotEqual
+                );
+                if commutative && right < left {
+                    std::mem::swap(left, right);
+                }
+            }
+            let key = (kind, instruction.ty.clone());
+            match self.available.get(&key) {
+                Some(value) => {
+                    self.replacements.insert(instruction.value, *value);
+                }
+                None => {
+                    self.available.insert(key.clone(), instruction.value);
+                    added.push(key);
+                }
+            }
+        }
+        for child in self.children[id.index()].clone() {
+            self.visit(child);
+        }
+        // Leaving the block, what it computes is no longer available.
+        for key in added {
+            self.available.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opt::tests::build;
+
+    #[test]
+    fn repeated_computations_are_merged() {
+        let source = "\
+fn f(a: int64, b: int64, flag: bool) -> int64 {
+    let x: int64 = a + b;
+    if flag {
+        let y: int64 = (b + a) * 2;
+        println(y);
+        println(y);
+    } else {
+        let z: int64 = a - b;
+    }
+    let w: int64 = (a - b) * (a + b) + (a + b) * 2;
+    return w;
+}";
+        let mut function = build(source).remove(0);
+        assert!(CommonSubexpressionElimination.run(&mut function));
+        assert_eq!(function.verify(), Ok(()), "{}", function);
+        assert_eq!(
+            function.to_string(),
+            "\
+fn f(v0: int64, v1: int64, v2: bool) -> int64
+
+bb0:
+    v3: int64 = (+ v0 v1)
+    branch v2 bb1 bb3
+
+bb1:
+    v5: int64 = 2
+    v6: int64 = (* v3 v5)
+    v7: () = (println v6)
+    v8: () = (println v6)
+    goto bb2
+
+bb2:
+    v9: int64 = (- v0 v1)
+    v11: int64 = (* v9 v3)
+    v13: int64 = 2
+    v14: int64 = (* v3 v13)
+    v15: int64 = (+ v11 v14)
+    return v15
+
+bb3:
+    v16: int64 = (- v0 v1)
+    goto bb2
+
+bb4:
+    unreachable
+"
+        );
+        assert!(!CommonSubexpressionElimination.run(&mut function));
+    }
+}
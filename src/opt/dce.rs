@@ -0,0 +1,157 @@
+use super::Optimization;
+use crate::{
+    ast::BinaryOperator,
+    consteval::ConstValue,
+    ssa::{Function, Instruction, InstructionKind, Terminator, ValueId},
+};
+use std::collections::{HashMap, HashSet};
+
+// Dead code elimination: empties the blocks control cannot reach, then
+// removes the instructions whose values are never used and whose only
+// effect is computing them.
+//
+// Calls and builtins are kept, since they can do anything, and so are
+// integer divisions and powers unless their right operand is a constant
+// that cannot make them fail.
+pub struct DeadCodeElimination;
+
+impl Optimization for DeadCodeElimination {
+    fn name(&self) -> &'static str {
+        "dce"
+    }
+
+    fn run(&mut self, function: &mut Function) -> bool {
+        let mut changed = false;
+        let reachable: Vec<bool> = function.dominators().iter().map(Option::is_some).collect();
+        for (i, block) in function.blocks.iter_mut().enumerate() {
+            if reachable[i] {
+                // Phis no longer have values from blocks that are emptied.
+                for instruction in &mut block.instructions {
+                    if let InstructionKind::Phi(incoming) = &mut instruction.kind {
+                        let count = incoming.len();
+                        incoming.retain(|(predecessor, _)| reachable[predecessor.index()]);
+                        changed |= incoming.len() != count;
+                    }
+                }
+            } else if !block.instructions.is_empty() || block.terminator != Terminator::Unreachable
+            {
+                block.instructions.clear();
+                block.terminator = Terminator::Unreachable;
+                changed = true;
+            }
+        }
+
+        // Marks the values that are used, starting from the instructions
+        // that have effects and the terminators.
+        let definitions: HashMap<ValueId, &Instruction> = function
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .map(|instruction| (instruction.value, instruction))
+            .collect();
+        let mut worklist = vec![];
+        for block in &function.blocks {
+            for instruction in &block.instructions {
+                if has_effects(instruction, &definitions) {
+                    worklist.push(instruction.value);
+                }
+            }
+            worklist.extend(block.terminator.operands());
+        }
+        let mut live = HashSet::new();
+        while let Some(value) = worklist.pop() {
+            if live.insert(value) {
+                if let Some(instruction) = definitions.get(&value) {
+                    worklist.extend(instruction.kind.operands());
+                }
+            }
+        }
+
+        for block in &mut function.blocks {
+            let count = block.instructions.len();
+            block
+                .instructions
+                .retain(|instruction| live.contains(&instruction.value));
+            changed |= block.instructions.len() != count;
+        }
+        changed
+    }
+}
+
+// Returns true if executing an instruction can do more than compute its
+// value.
+fn has_effects(instruction: &Instruction, definitions: &HashMap<ValueId, &Instruction>) -> bool {
+    match &instruction.kind {
+        InstructionKind::Call(..) | InstructionKind::Builtin(..) => true,
+        InstructionKind::Binary(operator, _, right) if instruction.ty.is_integer() => {
+            let right = definitions.get(right).map(|right| &right.kind);
+            match (operator, right) {
+                (
+                    BinaryOperator::Divide,
+                    Some(InstructionKind::Constant(ConstValue::Integer(n))),
+                ) => *n == 0,
+                (
+                    BinaryOperator::Power,
+                    Some(InstructionKind::Constant(ConstValue::Integer(n))),
+                ) => *n < 0,
+                (BinaryOperator::Divide | BinaryOperator::Power, _) => true,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opt::tests::build;
+
+    #[test]
+    fn unused_values_without_effects_are_removed() {
+        let source = "\
+fn f(a: int32, g: fn() -> int32) -> int32 {
+    let unused: int32 = a * 2 + a / 2;
+    let kept: int32 = a / a + a ** 2 + a ** a;
+    g();
+    let two: int32 = 2;
+    while a > two { return a; }
+    return 0;
+    let after: int32 = g();
+}";
+        let mut function = build(source).remove(0);
+        assert!(DeadCodeElimination.run(&mut function));
+        assert_eq!(function.verify(), Ok(()), "{}", function);
+        assert_eq!(
+            function.to_string(),
+            "\
+fn f(v0: int32, v1: fn() -> int32) -> int32
+
+bb0:
+    v7: int32 = (/ v0 v0)
+    v11: int32 = (** v0 v0)
+    v13: int32 = (call v1)
+    v14: int32 = 2
+    goto bb1
+
+bb1:
+    v15: bool = (> v0 v14)
+    branch v15 bb2 bb3
+
+bb2:
+    return v0
+
+bb3:
+    v16: int32 = 0
+    return v16
+
+bb4:
+    unreachable
+
+bb5:
+    unreachable
+"
+        );
+        assert!(!DeadCodeElimination.run(&mut function));
+    }
+}
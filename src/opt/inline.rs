@@ -0,0 +1,512 @@
+use super::Optimization;
+use crate::{
+    ast::{NodeId, TypeKind},
+    cfg::BlockId,
+    hir::{self, Statement},
+    ssa::{BasicBlock, Function, Instruction, InstructionKind, Terminator, ValueId},
+    typecheck::Ty,
+};
+use std::collections::{HashMap, HashSet};
+
+// Replaces calls of small top-level functions, and of those marked
+// `#[inline]`, with a copy of the function's body. The mark is a comment of
+// just that text on the line before the function, as the language has no
+// attribute syntax.
+//
+// Only direct calls are inlined, of functions declared at the top level of
+// the program: functions nested in others can use their variables, which
+// mean nothing anywhere else. A function is never inlined into itself, and
+// code that was inlined is not inlined into again in the same run, so
+// recursive functions are inlined one level per run.
+//
+// Inlined instructions keep the spans of the code they were copied from.
+pub struct Inliner {
+    // The functions calls can be replaced with, by the id of their binding.
+    callees: HashMap<NodeId, Function>,
+    // The largest function, in instructions, that is inlined without being
+    // marked `#[inline]`.
+    threshold: usize,
+    // The size, in instructions, past which a function has nothing more
+    // inlined into it.
+    budget: usize,
+}
+
+impl Inliner {
+    pub const DEFAULT_THRESHOLD: usize = 12;
+    pub const DEFAULT_BUDGET: usize = 1000;
+
+    // Inlines the given functions. Nested functions among them are left
+    // out.
+    pub fn new(program: &hir::Program, functions: &[Function]) -> Inliner {
+        let top_level: HashSet<NodeId> = program
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Function(function) => Some(function.binding.id),
+                _ => None,
+            })
+            .collect();
+        Inliner {
+            callees: functions
+                .iter()
+                .filter(|function| top_level.contains(&function.binding.id))
+                .map(|function| (function.binding.id, function.clone()))
+                .collect(),
+            threshold: Inliner::DEFAULT_THRESHOLD,
+            budget: Inliner::DEFAULT_BUDGET,
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Inliner {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_budget(mut self, budget: usize) -> Inliner {
+        self.budget = budget;
+        self
+    }
+
+    // Returns the function a call at `position` in `block` should be
+    // replaced with, if any. `outer` has the binding of each value that is a
+    // binding from outside the function.
+    fn callee(
+        &self,
+        function: &Function,
+        outer: &HashMap<ValueId, NodeId>,
+        block: BlockId,
+        position: usize,
+    ) -> Option<&Function> {
+        let instruction = &function.block(block).instructions[position];
+        let InstructionKind::Call(callee, _) = instruction.kind else {
+            return None;
+        };
+        let binding = outer.get(&callee)?;
+        let callee = self.callees.get(binding)?;
+        if *binding == function.binding.id || (!callee.inline && size(callee) > self.threshold) {
+            return None;
+        }
+        // A unit result has no value to replace its uses with.
+        let used = || {
+            function.blocks.iter().any(|block| {
+                block
+                    .instructions
+                    .iter()
+                    .flat_map(|instruction| instruction.kind.operands())
+                    .chain(block.terminator.operands())
+                    .any(|operand| operand == instruction.value)
+            })
+        };
+        if is_unit(&callee.return_type) && used() {
+            return None;
+        }
+        Some(callee)
+    }
+}
+
+impl Optimization for Inliner {
+    fn name(&self) -> &'static str {
+        "inline"
+    }
+
+    fn run(&mut self, function: &mut Function) -> bool {
+        let mut changed = false;
+        // Values from copied blocks are not needed, since those blocks are
+        // not inlined into.
+        let outer: HashMap<ValueId, NodeId> = function
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .filter_map(|instruction| match &instruction.kind {
+                InstructionKind::Outer(binding) => Some((instruction.value, binding.id)),
+                _ => None,
+            })
+            .collect();
+        // The blocks copied from callees, which are not inlined into.
+        let mut copied = HashSet::new();
+        let mut i = 0;
+        while i < function.blocks.len() {
+            let id = BlockId(i as u32);
+            let mut position = 0;
+            while !copied.contains(&id) && position < function.block(id).instructions.len() {
+                if size(function) > self.budget {
+                    return changed;
+                }
+                match self.callee(function, &outer, id, position) {
+                    Some(callee) => {
+                        let callee = callee.clone();
+                        copied.extend(inline(function, id, position, &callee));
+                        changed = true;
+                        // The rest of the block moved to a block of its own,
+                        // which is visited later.
+                        break;
+                    }
+                    None => position += 1,
+                }
+            }
+            i += 1;
+        }
+        changed
+    }
+}
+
+// The number of instructions in a function.
+fn size(function: &Function) -> usize {
+    function
+        .blocks
+        .iter()
+        .map(|block| block.instructions.len())
+        .sum()
+}
+
+// Replaces the call at `position` in block `id` with a copy of `callee`,
+// returning the ids of the copied blocks.
+//
+// The block is split at the call: it jumps to the copy of the callee's
+// entry, and the instructions after the call move, with its terminator, to
+// a new block that the copied returns jump to. The call's value becomes a
+// phi of the returned values there, or the returned value itself if there is
+// only one.
+fn inline(
+    function: &mut Function,
+    id: BlockId,
+    position: usize,
+    callee: &Function,
+) -> Vec<BlockId> {
+    let offset = function.blocks.len() as u32;
+    let rest = BlockId(offset + callee.blocks.len() as u32);
+    let block = &mut function.blocks[id.index()];
+    let mut after = block.instructions.split_off(position);
+    let call = after.remove(0);
+    let InstructionKind::Call(_, arguments) = &call.kind else {
+        unreachable!("only calls are inlined");
+    };
+    let terminator = std::mem::replace(&mut block.terminator, Terminator::Goto(BlockId(offset)));
+    // The successors of the split block now come from the new block.
+    for successor in terminator.successors() {
+        for instruction in &mut function.blocks[successor.index()].instructions {
+            if let InstructionKind::Phi(incoming) = &mut instruction.kind {
+                for (predecessor, _) in incoming {
+                    if *predecessor == id {
+                        *predecessor = rest;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut values: HashMap<ValueId, ValueId> = callee
+        .parameters
+        .iter()
+        .map(|parameter| parameter.value)
+        .zip(arguments.iter().copied())
+        .collect();
+    for instruction in callee.blocks.iter().flat_map(|block| &block.instructions) {
+        values.insert(instruction.value, ValueId(function.values));
+        function.values += 1;
+    }
+    let block_id = |block: BlockId| BlockId(offset + block.0);
+    let mut returns = vec![];
+    for (i, block) in callee.blocks.iter().enumerate() {
+        let mut instructions = block.instructions.clone();
+        for instruction in &mut instructions {
+            instruction.value = values[&instruction.value];
+            for operand in instruction.kind.operands_mut() {
+                *operand = values[operand];
+            }
+            if let InstructionKind::Phi(incoming) = &mut instruction.kind {
+                for (predecessor, _) in incoming {
+                    *predecessor = block_id(*predecessor);
+                }
+            }
+        }
+        let terminator = match &block.terminator {
+            Terminator::Goto(target) => Terminator::Goto(block_id(*target)),
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => Terminator::Branch {
+                condition: values[condition],
+                then_block: block_id(*then_block),
+                else_block: block_id(*else_block),
+            },
+            Terminator::Return(value) => {
+                returns.push((
+                    block_id(BlockId(i as u32)),
+                    value.map(|value| values[&value]),
+                ));
+                Terminator::Goto(rest)
+            }
+            Terminator::Unreachable => Terminator::Unreachable,
+        };
+        function.blocks.push(BasicBlock {
+            instructions,
+            terminator,
+        });
+    }
+
+    let mut single = None;
+    if !is_unit(&callee.return_type) {
+        match returns[..] {
+            [(_, Some(value))] => single = Some(value),
+            _ => {
+                let incoming = returns
+                    .iter()
+                    .map(|(block, value)| (*block, value.expect("a value is returned")))
+                    .collect();
+                after.insert(
+                    0,
+                    Instruction {
+                        kind: InstructionKind::Phi(incoming),
+                        ..call.clone()
+                    },
+                );
+            }
+        }
+    }
+    function.blocks.push(BasicBlock {
+        instructions: after,
+        terminator,
+    });
+    if let Some(value) = single {
+        for block in &mut function.blocks {
+            let operands = block
+                .instructions
+                .iter_mut()
+                .flat_map(|instruction| instruction.kind.operands_mut())
+                .chain(block.terminator.operands_mut());
+            for operand in operands {
+                if *operand == call.value {
+                    *operand = value;
+                }
+            }
+        }
+    }
+    (offset..rest.0).map(BlockId).collect()
+}
+
+fn is_unit(ty: &Ty) -> bool {
+    *ty == Ty::Primitive(TypeKind::Unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser, ssa};
+
+    // Inlines into the function called `name` in a program, returning it.
+    fn inline(
+        source: &str,
+        name: &str,
+        inliner: impl FnOnce(Inliner) -> Inliner,
+    ) -> (Function, bool) {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        assert!(program.typecheck().errors.is_empty(), "{}", source);
+        let program = program.to_hir();
+        let mut functions = ssa::build_all(&program);
+        let mut inliner = inliner(Inliner::new(&program, &functions));
+        let position = functions
+            .iter()
+            .position(|function| function.binding.name == name)
+            .unwrap();
+        let mut function = functions.remove(position);
+        let changed = inliner.run(&mut function);
+        assert_eq!(function.verify(), Ok(()), "{}", function);
+        (function, changed)
+    }
+
+    #[test]
+    fn small_functions_are_inlined() {
+        let source = "\
+fn max(a: int32, b: int32) -> int32 {
+    if a > b { return a; }
+    return b;
+}
+fn f(x: int32) -> int32 {
+    let y: int32 = max(x, 0) + 1;
+    return y;
+}";
+        let (function, changed) = inline(source, "f", |inliner| inliner);
+        assert!(changed);
+        assert_eq!(
+            function.to_string(),
+            "\
+fn f(v0: int32) -> int32
+
+bb0:
+    v1: fn(int32, int32) -> int32 = (outer max)
+    v2: int32 = 0
+    goto bb2
+
+bb1:
+    unreachable
+
+bb2:
+    v6: bool = (> v0 v2)
+    branch v6 bb3 bb4
+
+bb3:
+    goto bb7
+
+bb4:
+    goto bb7
+
+bb5:
+    goto bb4
+
+bb6:
+    unreachable
+
+bb7:
+    v3: int32 = (phi bb3 v0 bb4 v2)
+    v4: int32 = 1
+    v5: int32 = (+ v3 v4)
+    return v5
+"
+        );
+    }
+
+    #[test]
+    fn large_functions_are_inlined_only_if_marked() {
+        let source = "\
+#[inline]
+fn marked(a: int64) -> int64 {
+    return a * a + a * 2 + 1;
+}
+fn unmarked(a: int64) -> int64 {
+    return a * a + a * 2 + 1;
+}
+fn f(x: int64) {
+    println(marked(x));
+    println(unmarked(x));
+}";
+        let (function, changed) = inline(source, "f", |inliner| inliner.with_threshold(4));
+        assert!(changed);
+        assert_eq!(
+            function.to_string(),
+            "\
+fn f(v0: int64) -> ()
+
+bb0:
+    v1: fn(int64) -> int64 = (outer marked)
+    goto bb2
+
+bb1:
+    unreachable
+
+bb2:
+    v7: int64 = (* v0 v0)
+    v8: int64 = 2
+    v9: int64 = (* v0 v8)
+    v10: int64 = (+ v7 v9)
+    v11: int64 = 1
+    v12: int64 = (+ v10 v11)
+    goto bb4
+
+bb3:
+    unreachable
+
+bb4:
+    v3: () = (println v12)
+    v4: fn(int64) -> int64 = (outer unmarked)
+    v5: int64 = (call v4 v0)
+    v6: () = (println v5)
+    return
+"
+        );
+    }
+
+    #[test]
+    fn recursive_and_nested_functions_are_not_inlined() {
+        let source = "\
+fn even(n: int32) -> bool {
+    if n == 0 { return 1 > 0; }
+    return odd(n - 1);
+}
+fn odd(n: int32) -> bool {
+    fn zero() -> bool { return n == 0; }
+    if zero() { return 0 > 1; }
+    return even(n - 1);
+}";
+        let (function, changed) = inline(source, "odd", |inliner| inliner);
+        assert!(changed);
+        // The call of `even` is inlined, but not the call of `odd` in its
+        // copy, nor the nested `zero`.
+        assert_eq!(
+            function.to_string(),
+            "\
+fn odd(v0: int32) -> bool
+
+bb0:
+    v1: fn() -> bool = (outer zero)
+    v2: bool = (call v1)
+    branch v2 bb1 bb2
+
+bb1:
+    v3: int32 = 0
+    v4: int32 = 1
+    v5: bool = (> v3 v4)
+    return v5
+
+bb2:
+    v6: fn(int32) -> bool = (outer even)
+    v7: int32 = 1
+    v8: int32 = (- v0 v7)
+    goto bb5
+
+bb3:
+    goto bb2
+
+bb4:
+    unreachable
+
+bb5:
+    v10: int32 = 0
+    v11: bool = (== v8 v10)
+    branch v11 bb6 bb7
+
+bb6:
+    v12: int32 = 1
+    v13: int32 = 0
+    v14: bool = (> v12 v13)
+    goto bb10
+
+bb7:
+    v15: fn(int32) -> bool = (outer odd)
+    v16: int32 = 1
+    v17: int32 = (- v8 v16)
+    v18: bool = (call v15 v17)
+    goto bb10
+
+bb8:
+    goto bb7
+
+bb9:
+    unreachable
+
+bb10:
+    v9: bool = (phi bb6 v14 bb7 v18)
+    return v9
+"
+        );
+        let (_, changed) = inline(source, "odd", |inliner| inliner.with_budget(0));
+        assert!(!changed);
+    }
+
+    #[test]
+    fn inlined_instructions_keep_their_spans() {
+        let source = "\
+fn twice(a: int8) -> int8 { return a + a; }
+fn f(x: int8) -> int8 { return twice(x); }";
+        let (function, _) = inline(source, "f", |inliner| inliner);
+        let spans: Vec<&str> = function
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .map(|instruction| &source[instruction.span.start..instruction.span.end])
+            .collect();
+        assert_eq!(spans, ["twice", "a + a"]);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{
+    ast::{Program, Span, Statement, TypeExpr, TypeKind},
+    lexer::Lexer,
+    parser::{Parser, ParserError},
+    printer::print_type,
+};
+
+// The outline of a file: the names it declares, nested the way an editor
+// shows them in its outline view and breadcrumbs, as with the Language
+// Server Protocol's document symbols.
+//
+// Functions and constants are symbols wherever they are declared, and so
+// are `let` bindings. Each symbol's children are those declared inside it:
+// the bindings, constants and functions in a function's body, including
+// those in blocks nested in it. Parameters are left out, since the
+// function's detail already shows them.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Function,
+    Variable,
+    Constant,
+}
+
+impl SymbolKind {
+    // The number of the kind in the LSP's `SymbolKind` enumeration.
+    pub fn lsp(&self) -> u32 {
+        match self {
+            SymbolKind::Function => 12,
+            SymbolKind::Variable => 13,
+            SymbolKind::Constant => 14,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // What a symbol's name is not enough to tell: a function's parameters
+    // and return type, or a binding's or constant's type.
+    pub detail: String,
+    // The whole declaration, and the name in it.
+    pub span: Span,
+    pub name_span: Span,
+    pub children: Vec<DocumentSymbol>,
+}
+
+// Returns the outline of a file, or why it does not parse.
+pub fn document_symbols(source: &str) -> Result<Vec<DocumentSymbol>, ParserError> {
+    let tokens = Lexer::tokenize(source);
+    let program = Parser::parse_program(&tokens)?;
+    Ok(outline(&program))
+}
+
+// Returns the outline of a parsed program.
+pub fn outline(program: &Program) -> Vec<DocumentSymbol> {
+    let mut symbols = vec![];
+    statements(&program.statements, &mut symbols);
+    symbols
+}
+
+fn statements(statements: &[Statement], symbols: &mut Vec<DocumentSymbol>) {
+    for statement in statements {
+        self::statement(statement, symbols);
+    }
+}
+
+fn statement(statement: &Statement, symbols: &mut Vec<DocumentSymbol>) {
+    match statement {
+        Statement::FunctionDeclaration(function) => {
+            let parameters: Vec<String> = function
+                .parameters
+                .iter()
+                .map(|p| format!("{}: {}", p.identifier.name, print_type(&p.ttype)))
+                .collect();
+            let returns = match &function.return_type {
+                TypeExpr::Named(named) if named.kind == TypeKind::Unit => String::new(),
+                ttype => format!(" -> {}", print_type(ttype)),
+            };
+            let mut children = vec![];
+            if let Some(body) = &function.body {
+                self::statements(&body.statements, &mut children);
+            }
+            symbols.push(DocumentSymbol {
+                name: function.identifier.name.to_string(),
+                kind: SymbolKind::Function,
+                detail: format!("fn({}){}", parameters.join(", "), returns),
+                span: function.span,
+                name_span: function.identifier.span,
+                children,
+            });
+        }
+        Statement::Let(let_statement) => symbols.push(DocumentSymbol {
+            name: let_statement.identifier.name.to_string(),
+            kind: SymbolKind::Variable,
+            detail: print_type(&let_statement.ttype),
+            span: let_statement.span,
+            name_span: let_statement.identifier.span,
+            children: vec![],
+        }),
+        Statement::Const(constant) => symbols.push(DocumentSymbol {
+            name: constant.identifier.name.to_string(),
+            kind: SymbolKind::Constant,
+            detail: print_type(&constant.ttype),
+            span: constant.span,
+            name_span: constant.identifier.span,
+            children: vec![],
+        }),
+        Statement::If(if_statement) => {
+            self::statements(&if_statement.then_block.statements, symbols);
+            if let Some(else_branch) = &if_statement.else_branch {
+                self::statement(else_branch, symbols);
+            }
+        }
+        Statement::While(while_statement) => {
+            self::statements(&while_statement.body.statements, symbols)
+        }
+        Statement::Block(block) => self::statements(&block.statements, symbols),
+        Statement::Expression(_) | Statement::Return(_) | Statement::Import(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lists symbols depth first, indented by depth, with their detail and
+    // the text of their name's span.
+    fn list(source: &str) -> String {
+        fn write(source: &str, symbols: &[DocumentSymbol], depth: usize, output: &mut String) {
+            for symbol in symbols {
+                output.push_str(&format!(
+                    "{}{:?} {} `{}` {}\n",
+                    "  ".repeat(depth),
+                    symbol.kind,
+                    symbol.name,
+                    &source[symbol.name_span.range()],
+                    symbol.detail
+                ));
+                write(source, &symbol.children, depth + 1, output);
+            }
+        }
+        let mut output = String::new();
+        write(source, &document_symbols(source).unwrap(), 0, &mut output);
+        output
+    }
+
+    #[test]
+    fn declarations_are_nested_in_their_functions() {
+        let source = "\
+const LIMIT: int64 = 10;
+fn count(n: int32, step: int8) -> int32 {
+    let total: int32 = n;
+    while total < 0 {
+        let next: int32 = total + 1;
+    }
+    if n > 0 {
+        fn helper() {
+            let inner: int8 = 1;
+        }
+    } else {
+        const LOCAL: int32 = 2;
+    }
+    return total;
+}
+fn log(message: string);
+let answer: int32 = count(1, 2);
+";
+        assert_eq!(
+            list(source),
+            "\
+Constant LIMIT `LIMIT` int64
+Function count `count` fn(n: int32, step: int8) -> int32
+  Variable total `total` int32
+  Variable next `next` int32
+  Function helper `helper` fn()
+    Variable inner `inner` int8
+  Constant LOCAL `LOCAL` int32
+Function log `log` fn(message: string)
+Variable answer `answer` int32
+"
+        );
+    }
+
+    #[test]
+    fn spans_cover_whole_declarations() {
+        let source = "fn f() {\n    let x: int32 = 1;\n}\n";
+        let symbols = document_symbols(source).unwrap();
+        assert_eq!(&source[symbols[0].span.range()], source.trim_end());
+        let x = &symbols[0].children[0];
+        assert!(source[x.span.range()].starts_with("let x: int32 = 1"));
+        assert_eq!(SymbolKind::Function.lsp(), 12);
+        assert!(document_symbols("fn f( {").is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use crate::{
+    ast::{BinaryOperator, TypeKind},
+    value::{compare, ArithmeticError, Closure, Value},
+    vm::Slot,
+};
+use std::{fmt, marker::PhantomData, rc::Rc};
+
+// A `Value` packed into 64 bits by NaN-boxing, for the VM's stack; see
+// `Vm::packed`.
+//
+// A `float64` is kept as its own bits. Every other value is kept in the
+// negative quiet NaNs, which no arithmetic produces once NaNs are made
+// positive: the top 13 bits are set, the next 4 are a tag and the low 47 are
+// the payload.
+//
+// - tags 0 to 6: an integer of `KINDS[tag]`, sign-extended from 47 bits
+// - tags 7 to 9: a float of `KINDS[tag]`, as the bits of an `f32`
+// - tag 10: a bool
+// - tag 11: a function, as the pointer of an `Rc<Closure>`
+// - tag 12: any other value, as the pointer of an `Rc<Value>`
+//
+// Pointers are shifted right by their alignment. Only `int64` values that
+// need more than 47 bits and strings go to the heap, so arithmetic allocates
+// nothing.
+pub struct Packed {
+    bits: u64,
+    // A packed value may own an `Rc`.
+    marker: PhantomData<Rc<Value>>,
+}
+
+const BOXED: u64 = 0xfff8 << 48;
+const TAG_SHIFT: u32 = 47;
+const PAYLOAD: u64 = (1 << TAG_SHIFT) - 1;
+const KINDS: [TypeKind; 10] = [
+    TypeKind::Int1,
+    TypeKind::Int2,
+    TypeKind::Int4,
+    TypeKind::Int8,
+    TypeKind::Int16,
+    TypeKind::Int32,
+    TypeKind::Int64,
+    TypeKind::Float16,
+    TypeKind::BFloat16,
+    TypeKind::Float32,
+];
+const INT64: u64 = 6;
+const FLOAT16: u64 = 7;
+const BOOL: u64 = 10;
+const FUNCTION: u64 = 11;
+const HEAP: u64 = 12;
+// The low bits of a pointer, which are always zero.
+const ALIGNMENT: u32 = if align_of::<Value>() < align_of::<Closure>() {
+    align_of::<Value>().trailing_zeros()
+} else {
+    align_of::<Closure>().trailing_zeros()
+};
+
+impl Packed {
+    const fn boxed(tag: u64, payload: u64) -> Packed {
+        Packed {
+            bits: BOXED | tag << TAG_SHIFT | payload & PAYLOAD,
+            marker: PhantomData,
+        }
+    }
+
+    fn float64(value: f64) -> Packed {
+        let value = if value.is_nan() { f64::NAN } else { value };
+        Packed {
+            bits: value.to_bits(),
+            marker: PhantomData,
+        }
+    }
+
+    fn integer(kind: TypeKind, value: i64) -> Packed {
+        if value << (64 - TAG_SHIFT) >> (64 - TAG_SHIFT) != value {
+            return Packed::pointer(HEAP, Rc::into_raw(Rc::new(Value::Integer(kind, value))));
+        }
+        Packed::boxed(tag(kind), value as u64)
+    }
+
+    fn bool(value: bool) -> Packed {
+        Packed::boxed(BOOL, value.into())
+    }
+
+    // Takes ownership of a reference counted by an `Rc`.
+    fn pointer<T>(tag: u64, pointer: *const T) -> Packed {
+        let pointer = pointer as u64;
+        assert!(pointer >> ALIGNMENT <= PAYLOAD, "address too large to pack");
+        Packed::boxed(tag, pointer >> ALIGNMENT)
+    }
+
+    // The tag, or `None` for a `float64`.
+    fn tag(&self) -> Option<u64> {
+        (self.bits & BOXED == BOXED).then_some(self.bits >> TAG_SHIFT & 0xf)
+    }
+
+    fn payload(&self) -> u64 {
+        self.bits & PAYLOAD
+    }
+
+    // The integer payload of tags 0 to 6.
+    fn signed(&self) -> i64 {
+        (self.bits << (64 - TAG_SHIFT)) as i64 >> (64 - TAG_SHIFT)
+    }
+
+    // The pointer of tags 11 and 12.
+    fn address<T>(&self) -> *const T {
+        (self.payload() << ALIGNMENT) as *const T
+    }
+
+    pub fn unpack(&self) -> Value {
+        match self.tag() {
+            None => Value::Float(TypeKind::Float64, f64::from_bits(self.bits)),
+            Some(tag @ 0..=INT64) => Value::Integer(KINDS[tag as usize], self.signed()),
+            Some(tag @ FLOAT16..BOOL) => {
+                let value = f32::from_bits(self.payload() as u32);
+                Value::Float(KINDS[tag as usize], value.into())
+            }
+            Some(BOOL) => Value::Bool(self.payload() != 0),
+            Some(FUNCTION) => Value::Function(self.function()),
+            // Safety: a packed value with tag 12 holds a reference to an
+            // `Rc<Value>`, so the value is alive.
+            Some(_) => unsafe { (*self.address::<Value>()).clone() },
+        }
+    }
+}
+
+fn tag(kind: TypeKind) -> u64 {
+    KINDS.iter().position(|k| *k == kind).unwrap() as u64
+}
+
+// Wraps an integer to the width of the integer type with tag `tag`, as
+// `value::wrap` does.
+fn wrap(value: i128, tag: u64) -> i64 {
+    match KINDS[tag as usize].integer_bits().unwrap() {
+        1 => (value & 1) as i64,
+        bits => (value << (128 - bits) >> (128 - bits)) as i64,
+    }
+}
+
+impl From<Value> for Packed {
+    fn from(value: Value) -> Packed {
+        match value {
+            Value::Integer(kind, value) => Packed::integer(kind, value),
+            Value::Float(TypeKind::Float64, value) => Packed::float64(value),
+            // Narrower floats are exactly representable as `f32`.
+            Value::Float(kind, value) => Packed::boxed(tag(kind), (value as f32).to_bits().into()),
+            Value::Bool(value) => Packed::bool(value),
+            Value::Function(closure) => Packed::pointer(FUNCTION, Rc::into_raw(closure)),
+            value => Packed::pointer(HEAP, Rc::into_raw(Rc::new(value))),
+        }
+    }
+}
+
+// Safety, for the pointers of tags 11 and 12: they came from `Rc::into_raw`
+// and each packed value that has one holds a reference, so the `Rc` is
+// alive until the last of them is dropped.
+impl Clone for Packed {
+    fn clone(&self) -> Packed {
+        match self.tag() {
+            Some(FUNCTION) => unsafe { Rc::increment_strong_count(self.address::<Closure>()) },
+            Some(HEAP) => unsafe { Rc::increment_strong_count(self.address::<Value>()) },
+            _ => {}
+        }
+        Packed {
+            bits: self.bits,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl Drop for Packed {
+    fn drop(&mut self) {
+        match self.tag() {
+            Some(FUNCTION) => unsafe { Rc::decrement_strong_count(self.address::<Closure>()) },
+            Some(HEAP) => unsafe { Rc::decrement_strong_count(self.address::<Value>()) },
+            _ => {}
+        }
+    }
+}
+
+impl PartialEq for Packed {
+    fn eq(&self, other: &Packed) -> bool {
+        self.unpack() == other.unpack()
+    }
+}
+
+impl fmt::Debug for Packed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Packed({:?})", self.unpack())
+    }
+}
+
+impl Slot for Packed {
+    const UNSET: Packed = Packed::boxed(BOOL, 0);
+
+    fn pack(value: Value) -> Packed {
+        value.into()
+    }
+
+    fn unpack(&self) -> Value {
+        Packed::unpack(self)
+    }
+
+    // Integers of the same type and `float64`s are added, subtracted,
+    // multiplied and compared without unpacking; `float64`s are divided too.
+    fn binary(&self, operator: &BinaryOperator, right: &Packed) -> Result<Packed, ArithmeticError> {
+        match (self.tag(), right.tag()) {
+            (None, None) => {
+                let left = f64::from_bits(self.bits);
+                let right = f64::from_bits(right.bits);
+                if operator.is_comparison() {
+                    let ordering = left.partial_cmp(&right);
+                    return Ok(Packed::bool(compare(operator, ordering)));
+                }
+                match operator {
+                    BinaryOperator::Plus => return Ok(Packed::float64(left + right)),
+                    BinaryOperator::Minus => return Ok(Packed::float64(left - right)),
+                    BinaryOperator::Star => return Ok(Packed::float64(left * right)),
+                    BinaryOperator::Divide => return Ok(Packed::float64(left / right)),
+                    _ => {}
+                }
+            }
+            (Some(tag @ 0..=INT64), Some(other)) if tag == other => {
+                let (left, right) = (i128::from(self.signed()), i128::from(right.signed()));
+                if operator.is_comparison() {
+                    let ordering = Some(left.cmp(&right));
+                    return Ok(Packed::bool(compare(operator, ordering)));
+                }
+                let value = match operator {
+                    BinaryOperator::Plus => Some(left + right),
+                    BinaryOperator::Minus => Some(left - right),
+                    BinaryOperator::Star => Some(left * right),
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    return Ok(Packed::integer(KINDS[tag as usize], wrap(value, tag)));
+                }
+            }
+            _ => {}
+        }
+        let value = self.unpack().binary(operator, &right.unpack())?;
+        Ok(Packed::from(value))
+    }
+
+    fn cast(&self, kind: TypeKind) -> Packed {
+        let value = self
+            .unpack()
+            .cast(kind)
+            .expect("the type checker allows only valid casts");
+        Packed::from(value)
+    }
+
+    fn truth(&self) -> bool {
+        match self.tag() {
+            Some(BOOL) => self.payload() != 0,
+            _ => panic!("condition of type `{}`", self.unpack().type_name()),
+        }
+    }
+
+    fn function(&self) -> Rc<Closure> {
+        assert_eq!(
+            self.tag(),
+            Some(FUNCTION),
+            "call of a value that is not a function"
+        );
+        let pointer = self.address::<Closure>();
+        // Safety: see `clone`; the new `Rc` takes the reference added here.
+        unsafe {
+            Rc::increment_strong_count(pointer);
+            Rc::from_raw(pointer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Symbol;
+
+    #[test]
+    fn values_survive_packing() {
+        let closure = Rc::new(Closure {
+            name: Symbol::new("f"),
+            index: 0,
+            captures: vec![],
+        });
+        let values = [
+            Value::Integer(TypeKind::Int1, 1),
+            Value::Integer(TypeKind::Int8, -128),
+            Value::Integer(TypeKind::Int32, i32::MIN.into()),
+            Value::Integer(TypeKind::Int64, (1 << 46) - 1),
+            Value::Integer(TypeKind::Int64, -(1 << 46)),
+            Value::Integer(TypeKind::Int64, 1 << 46),
+            Value::Integer(TypeKind::Int64, i64::MIN),
+            Value::Float(TypeKind::Float64, -0.0),
+            Value::Float(TypeKind::Float64, f64::NEG_INFINITY),
+            Value::Float(TypeKind::Float64, f64::MIN_POSITIVE / 2.0),
+            Value::float(TypeKind::Float16, 65504.0),
+            Value::float(TypeKind::BFloat16, -1e38),
+            Value::float(TypeKind::Float32, 0.1),
+            Value::Bool(true),
+            Value::String("packed".into()),
+            Value::Function(closure.clone()),
+        ];
+        for value in values {
+            let packed = Packed::from(value.clone());
+            assert_eq!(packed.clone().unpack(), value);
+            assert_eq!(size_of_val(&packed), 8);
+        }
+        assert_eq!(Rc::strong_count(&closure), 1);
+
+        let nan = -f64::NAN;
+        let packed = Packed::from(Value::Float(TypeKind::Float64, nan));
+        assert_eq!(packed.tag(), None);
+        assert!(matches!(packed.unpack(), Value::Float(_, value) if value.is_nan()));
+    }
+
+    #[test]
+    fn packed_arithmetic_agrees_with_values() {
+        let int = |kind, value| Value::integer(kind, value);
+        let float = |kind, value| Value::float(kind, value);
+        let pairs = [
+            (int(TypeKind::Int8, 100), int(TypeKind::Int8, 100)),
+            (int(TypeKind::Int64, 1 << 45), int(TypeKind::Int64, 1 << 45)),
+            (
+                int(TypeKind::Int64, i64::MAX.into()),
+                int(TypeKind::Int64, 3),
+            ),
+            (int(TypeKind::Int32, -7), int(TypeKind::Int32, 0)),
+            (int(TypeKind::Int4, 3), int(TypeKind::Int4, 5)),
+            (float(TypeKind::Float64, 0.1), float(TypeKind::Float64, 0.2)),
+            (
+                float(TypeKind::Float64, f64::NAN),
+                float(TypeKind::Float64, 1.0),
+            ),
+            (float(TypeKind::Float16, 1.0), float(TypeKind::Float16, 3.0)),
+            (Value::Bool(true), Value::Bool(false)),
+            (int(TypeKind::Int8, 1), Value::Bool(false)),
+        ];
+        let operators = [
+            BinaryOperator::Plus,
+            BinaryOperator::Minus,
+            BinaryOperator::Star,
+            BinaryOperator::Divide,
+            BinaryOperator::Power,
+            BinaryOperator::Equal,
+            BinaryOperator::NotEqual,
+            BinaryOperator::Less,
+            BinaryOperator::GreaterEqual,
+        ];
+        for (left, right) in pairs {
+            for operator in &operators {
+                let expected = left.binary(operator, &right);
+                let packed =
+                    Packed::from(left.clone()).binary(operator, &Packed::from(right.clone()));
+                let packed = packed.map(|value| value.unpack());
+                match (&expected, &packed) {
+                    (Ok(Value::Float(_, a)), Ok(Value::Float(_, b))) if a.is_nan() => {
+                        assert!(b.is_nan())
+                    }
+                    _ => assert_eq!(packed, expected, "{} {:?} {}", left, operator, right),
+                }
+            }
+        }
+    }
+}
@@ -3,259 +3,1165 @@
 use crate::{
     ast::Program,
     ast::{
-        self, BinaryExpression, Expression, Identifier, IntegerLiteral, LetStatement, Statement,
-        Type,
+        self, BinaryExpression, Block, CallExpression, CastExpression, ConstDeclaration,
+        Expression, Identifier, IfStatement, ImportStatement, IntegerLiteral, LetStatement, NodeId,
+        ReturnStatement, Span, Statement, Type, TypeExpr, TypeKind, WhileStatement,
     },
-    token::{Kind, Token},
+    diagnostic::Diagnostic,
+    grammar::{Production::*, Rule},
+    lexer::{get_column, get_line, Lexer},
+    symbol::Interner,
+    token::{Kind, Token, TokenStream},
 };
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::ops::Range;
 
-#[derive(Debug)]
-pub struct ParserError {
-    pub message: String,
-}
+// Parsing stops at the first syntax error, which is reported as a single
+// diagnostic.
+pub type ParserError = Box<Diagnostic>;
 
-pub struct Parser<'a> {
-    tokens: &'a [Token<'a>],
+// The parser reads its tokens one at a time from a `TokenStream`, and only
+// ever looks at the current one, so it can parse straight from a lexer
+// without the tokens being collected first.
+pub struct Parser<'a, 'i, S> {
+    tokens: S,
+    // Interns the names of identifiers and types as they are read.
+    interner: &'i mut Interner,
+    token: Token<'a>,
+    // The whitespace skipped just before the current token, if any.
+    whitespace: Option<Token<'a>>,
+    // The number of tokens read so far, which identifies the current one.
     position: usize,
+    // The end of the last token consumed.
+    previous_end: usize,
+    // Opening delimiters that have not been closed yet, innermost last.
+    delimiters: Vec<Token<'a>>,
+    // Token kinds that would have been accepted at `expected_position`.
+    expected: Vec<Kind>,
+    expected_position: usize,
+    // The id to assign to the next AST node.
+    next_id: NodeId,
 }
 
-impl<'a> Parser<'a> {
-    fn new(tokens: &'a [Token]) -> Parser<'a> {
-        let parser = Parser {
+impl<'a, 'i, S: TokenStream<'a>> Parser<'a, 'i, S> {
+    fn new(mut tokens: S, next_id: NodeId, interner: &'i mut Interner) -> Parser<'a, 'i, S> {
+        let token = tokens.next_token();
+        let mut parser = Parser {
             tokens,
+            interner,
+            token,
+            whitespace: None,
             position: 0,
+            previous_end: 0,
+            delimiters: vec![],
+            expected: vec![],
+            expected_position: 0,
+            next_id,
         };
-        assert!(!parser.tokens.is_empty());
-        assert!(parser.tokens.last().unwrap().kind() == Kind::EndOfFile);
+        // Ignore leading whitespace.
+        parser.skip_whitespace();
         parser
     }
 
+    // Returns a fresh id for a new AST node.
+    fn node_id(&mut self) -> NodeId {
+        self.next_id.advance()
+    }
+
     // Returns the current token.
-    fn token(&self) -> &'a Token<'a> {
-        &self.tokens[self.position]
+    fn token(&self) -> Token<'a> {
+        self.token
+    }
+
+    fn read(&mut self) {
+        self.token = self.tokens.next_token();
+        self.position += 1;
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.whitespace = None;
+        while self.token.kind() == Kind::Whitespace {
+            self.whitespace = Some(self.token);
+            self.read();
+        }
     }
 
     // Advances the parser.
     fn step(&mut self) {
-        if self.position >= self.tokens.len() {
+        if self.token.kind() == Kind::EndOfFile {
             return;
         }
-        self.position += 1;
+        self.previous_end = self.token.offset() + self.token.len();
+        self.read();
         // Ignore whitespace.
-        while self.token().kind() == Kind::Whitespace && self.position < self.tokens.len() {
-            self.position += 1;
+        self.skip_whitespace();
+    }
+
+    // Records that any of `kinds` would be accepted at the current position.
+    fn expect(&mut self, kinds: &[Kind]) {
+        if self.expected_position != self.position {
+            self.expected.clear();
+            self.expected_position = self.position;
+        }
+        for kind in kinds {
+            if !self.expected.contains(kind) {
+                self.expected.push(*kind);
+            }
         }
     }
 
-    // Resets the parser to a previous position.
-    fn reset(&mut self, position: usize) {
-        self.position = position;
+    // Returns true if the current token has the given kind, recording the kind
+    // as one that would be accepted at the current position.
+    fn check(&mut self, kind: Kind) -> bool {
+        self.expect(&[kind]);
+        self.token().kind() == kind
     }
 
-    fn consume(&mut self, kind: Kind, start: usize) -> Result<(), String> {
+    // Describes the token kinds that would be accepted at the current position.
+    fn describe_expected(&self) -> String {
+        let kinds: Vec<String> = if self.expected_position == self.position {
+            self.expected.iter().map(|k| k.to_string()).collect()
+        } else {
+            vec![]
+        };
+        match kinds.len() {
+            0 => "a different token".to_string(),
+            1 => kinds[0].clone(),
+            _ => format!("one of {}", kinds.join(", ")),
+        }
+    }
+
+    // Returns an error describing the current token and the token kinds that
+    // would have been accepted instead.
+    //
+    // Running out of input or meeting the wrong closing delimiter while a
+    // delimiter is open is reported against the unclosed opening delimiter,
+    // which is labelled.
+    fn unexpected(&mut self) -> ParserError {
         let token = self.token();
-        if token.kind() == kind {
+        // Running out of input is reported just after the last token.
+        let span = match token.kind() {
+            Kind::EndOfFile => {
+                let end = self.previous_token_end();
+                Span::new(end, end)
+            }
+            _ => token_span(token),
+        };
+        let diagnostic = match self.delimiters.last() {
+            Some(opener) if token.kind() == Kind::EndOfFile => Diagnostic::error(
+                "E0101",
+                format!(
+                    "Unclosed {} opened at {}:{}",
+                    delimiter_name(opener.kind()),
+                    get_line(opener),
+                    get_column(opener)
+                ),
+                span,
+            )
+            .with_label(token_span(*opener), "unclosed delimiter"),
+            Some(opener)
+                if closing_delimiter(token.kind()).is_some()
+                    && closing_delimiter(opener.kind()) != Some(token.kind()) =>
+            {
+                Diagnostic::error(
+                    "E0102",
+                    format!(
+                        "Mismatched '{}' for {} opened at {}:{}",
+                        token.text(),
+                        delimiter_name(opener.kind()),
+                        get_line(opener),
+                        get_column(opener)
+                    ),
+                    span,
+                )
+                .with_label(token_span(*opener), "opened here")
+            }
+            _ => Diagnostic::error(
+                "E0100",
+                format!("Expected {}, got {:?}", self.describe_expected(), token),
+                span,
+            ),
+        };
+        Box::new(diagnostic)
+    }
+
+    fn consume(&mut self, kind: Kind) -> Result<(), ParserError> {
+        if self.check(kind) {
             self.step();
             Ok(())
         } else {
-            self.reset(start);
-            Err(format!("Expected {:?}, got {:?}", kind, token))
+            Err(self.unexpected())
         }
     }
 
-    fn consume_identifier_name(&mut self, start: usize) -> Result<&'a str, String> {
+    // Consumes an opening delimiter.
+    fn open(&mut self, kind: Kind) -> Result<(), ParserError> {
+        let token = self.token();
+        self.consume(kind)?;
+        self.delimiters.push(token);
+        Ok(())
+    }
+
+    // Consumes the closing delimiter matching the innermost open delimiter.
+    fn close(&mut self, kind: Kind) -> Result<(), ParserError> {
+        self.consume(kind)?;
+        self.delimiters.pop();
+        Ok(())
+    }
+
+    // Returns the span from byte offset `start` to the end of the last consumed
+    // token.
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(start, self.previous_token_end())
+    }
+
+    fn parse_identifier(&mut self) -> Result<Identifier, ParserError> {
         let token = self.token();
-        if token.kind() == Kind::Identifier {
+        if self.check(Kind::Identifier) {
             self.step();
-            Ok(token.text())
+            Ok(Identifier {
+                id: self.node_id(),
+                span: token_span(token),
+                name: self.interner.intern(token.text()),
+            })
         } else {
-            self.reset(start);
-            Err(format!("Expected identifier, got {:?}", token))
+            Err(self.unexpected())
         }
     }
 
-    fn maybe_consume(&mut self, kind: Kind) {
-        let token = self.token();
-        if token.kind() == kind {
-            self.step();
+    // Parses an expression, respecting operator precedence and associativity.
+    fn parse_expression(&mut self) -> Result<Expression<'a>, ParserError> {
+        self.parse_binary_expression(0)
+    }
+
+    // Parses a chain of binary operators that bind at least as tightly as
+    // `min_precedence`.
+    fn parse_binary_expression(
+        &mut self,
+        min_precedence: u8,
+    ) -> Result<Expression<'a>, ParserError> {
+        let start_offset = self.token().offset();
+        let left = self.parse_simple_expression()?;
+        let mut left = self.parse_casts(left, start_offset)?;
+        loop {
+            self.expect(&BINARY_OPERATORS);
+            let Some(operator) = binary_operator(self.token().kind()) else {
+                break;
+            };
+            let precedence = operator.precedence();
+            if precedence < min_precedence {
+                break;
+            }
+            self.step(); // Consume the op symbol.
+            let next_precedence = if operator.is_right_associative() {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let right = self.parse_binary_expression(next_precedence)?;
+            left = Expression::BinaryExpression(BinaryExpression {
+                id: self.node_id(),
+                span: self.span_from(start_offset),
+                operator,
+                left: Box::new(left),
+                right: Box::new(right),
+            });
         }
+        Ok(left)
     }
 
-    fn parse_simple_expression(&mut self, start: usize) -> Result<Expression<'a>, String> {
+    fn parse_simple_expression(&mut self) -> Result<Expression<'a>, ParserError> {
+        self.expect(&EXPRESSION_STARTS);
         let token = self.token();
         match token.kind() {
+            Kind::LeftParenthesis => {
+                self.open(Kind::LeftParenthesis)?;
+                let expression = self.parse_expression()?;
+                self.close(Kind::RightParenthesis)?;
+                self.parse_calls(expression, token.offset())
+            }
             Kind::Identifier => {
-                let id = Identifier { name: token.text() };
+                let id = Identifier {
+                    id: self.node_id(),
+                    span: token_span(token),
+                    name: self.interner.intern(token.text()),
+                };
                 self.step(); // Consume the identifier.
-                Ok(Expression::Identifier(id))
+                self.parse_calls(Expression::Identifier(id), token.offset())
             }
             Kind::IntegerLiteral => {
-                let literal = IntegerLiteral { text: token.text() };
+                let literal = IntegerLiteral {
+                    id: self.node_id(),
+                    span: token_span(token),
+                    text: token.text().into(),
+                };
                 self.step(); // Consume the integer literal.
                 Ok(Expression::IntegerLiteral(literal))
             }
-            _ => {
-                self.reset(start);
-                Err(format!(
-                    "Expected identifier or integer literal, got {:?}",
-                    token
-                ))
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    // Parses any argument lists following `callee`, which starts at byte
+    // offset `start_offset`.
+    fn parse_calls(
+        &mut self,
+        mut callee: Expression<'a>,
+        start_offset: usize,
+    ) -> Result<Expression<'a>, ParserError> {
+        while self.check(Kind::LeftParenthesis) {
+            self.open(Kind::LeftParenthesis)?;
+            let mut arguments = vec![];
+            while !self.check(Kind::RightParenthesis) {
+                arguments.push(self.parse_expression()?);
+                if !self.check(Kind::RightParenthesis) {
+                    self.consume(Kind::Comma)?;
+                }
+            }
+            self.close(Kind::RightParenthesis)?;
+            callee = Expression::Call(CallExpression {
+                id: self.node_id(),
+                span: self.span_from(start_offset),
+                callee: Box::new(callee),
+                arguments,
+            });
+        }
+        Ok(callee)
+    }
+
+    // Parses any `as` casts following `expression`, which starts at byte
+    // offset `start_offset`. Casts bind more tightly than binary operators.
+    fn parse_casts(
+        &mut self,
+        mut expression: Expression<'a>,
+        start_offset: usize,
+    ) -> Result<Expression<'a>, ParserError> {
+        while self.check(Kind::As) {
+            self.step(); // Consume the 'as' keyword.
+            let ttype = self.parse_type()?;
+            expression = Expression::Cast(CastExpression {
+                id: self.node_id(),
+                span: self.span_from(start_offset),
+                expression: Box::new(expression),
+                ttype,
+            });
+        }
+        Ok(expression)
+    }
+
+    // Parses a comma-separated list of types up to, but not including, the
+    // `closing` token.
+    fn parse_type_list(&mut self, closing: Kind) -> Result<Vec<TypeExpr<'a>>, ParserError> {
+        let mut types = vec![];
+        while !self.check(closing) {
+            types.push(self.parse_type()?);
+            if !self.check(closing) {
+                self.consume(Kind::Comma)?;
             }
         }
+        Ok(types)
     }
 
-    fn parse_let_stmt(&mut self) -> Result<Statement<'a>, String> {
-        let start = self.position;
-        self.consume(Kind::Let, start)?;
+    fn unit_type(&mut self, span: Span) -> TypeExpr<'a> {
+        TypeExpr::Named(Type {
+            id: self.node_id(),
+            span,
+            kind: TypeKind::Unit,
+            name: self.interner.intern(TypeKind::Unit.name()),
+        })
+    }
 
-        // See if we have a `mut` keyword
-        let mut_token = self.token();
-        let mutable = match mut_token.kind() {
-            Kind::Mut => {
-                self.step(); // Consume the "mut" token.
-                true
+    // Parses a type expression.
+    fn parse_type(&mut self) -> Result<TypeExpr<'a>, ParserError> {
+        self.expect(&TYPE_STARTS);
+        let token = self.token();
+        match token.kind() {
+            Kind::Identifier => {
+                let base = Type {
+                    id: self.node_id(),
+                    span: token_span(token),
+                    kind: TypeKind::from_name(token.text()),
+                    name: self.interner.intern(token.text()),
+                };
+                self.step(); // Consume the type name.
+                if !self.check(Kind::LessThan) {
+                    return Ok(TypeExpr::Named(base));
+                }
+                self.step(); // Consume the '<' token.
+                let arguments = self.parse_type_list(Kind::GreaterThan)?;
+                self.consume(Kind::GreaterThan)?;
+                Ok(TypeExpr::Generic(ast::GenericType {
+                    id: self.node_id(),
+                    span: self.span_from(token.offset()),
+                    base,
+                    arguments,
+                }))
             }
-            _ => false,
-        };
-        let identifier = Identifier {
-            name: self.consume_identifier_name(start)?,
-        };
-        self.consume(Kind::Colon, start)?;
-        let ttype = ast::Type {
-            name: self.consume_identifier_name(start)?,
-        };
-        self.consume(Kind::EqualSign, start)?;
-        let expression = Box::new(self.parse_simple_expression(start)?);
-        self.consume(Kind::Semicolon, start)?;
+            Kind::LeftSquareBracket => {
+                self.open(Kind::LeftSquareBracket)?;
+                let element = Box::new(self.parse_type()?);
+                self.consume(Kind::Semicolon)?;
+                let size = Box::new(self.parse_expression()?);
+                self.close(Kind::RightSquareBracket)?;
+                Ok(TypeExpr::Array(ast::ArrayType {
+                    id: self.node_id(),
+                    span: self.span_from(token.offset()),
+                    element,
+                    size,
+                }))
+            }
+            Kind::LeftParenthesis => {
+                self.open(Kind::LeftParenthesis)?;
+                if self.check(Kind::RightParenthesis) {
+                    self.close(Kind::RightParenthesis)?;
+                    return Ok(self.unit_type(self.span_from(token.offset())));
+                }
+                let first = self.parse_type()?;
+                // A single parenthesized type without a trailing comma is
+                // just that type.
+                if self.check(Kind::RightParenthesis) {
+                    self.close(Kind::RightParenthesis)?;
+                    return Ok(first);
+                }
+                self.consume(Kind::Comma)?;
+                let mut types = vec![first];
+                types.extend(self.parse_type_list(Kind::RightParenthesis)?);
+                self.close(Kind::RightParenthesis)?;
+                Ok(TypeExpr::Tuple(types))
+            }
+            Kind::Fn => {
+                self.step(); // Consume the 'fn' token.
+                self.open(Kind::LeftParenthesis)?;
+                let parameters = self.parse_type_list(Kind::RightParenthesis)?;
+                self.close(Kind::RightParenthesis)?;
+                self.consume(Kind::Arrow)?;
+                let return_type = Box::new(self.parse_type()?);
+                Ok(TypeExpr::Function(ast::FunctionType {
+                    id: self.node_id(),
+                    span: self.span_from(token.offset()),
+                    parameters,
+                    return_type,
+                }))
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn parse_let_stmt(&mut self) -> Result<Statement<'a>, ParserError> {
+        let start_offset = self.token().offset();
+        self.consume(Kind::Let)?;
+
+        // See if we have a `mut` keyword
+        let mutable = self.check(Kind::Mut);
+        if mutable {
+            self.step(); // Consume the "mut" token.
+        }
+        let identifier = self.parse_identifier()?;
+        self.consume(Kind::Colon)?;
+        let ttype = self.parse_type()?;
+        self.consume(Kind::EqualSign)?;
+        let expression = Box::new(self.parse_expression()?);
+        self.consume(Kind::Semicolon)?;
 
         Ok(ast::Statement::Let(LetStatement {
+            id: self.node_id(),
+            span: self.span_from(start_offset),
             identifier,
             mutable,
             ttype,
             expression,
+            docs: vec![],
         }))
     }
 
-    fn parse_binary_expression(&mut self) -> Result<Statement<'a>, String> {
-        let start = self.position;
-        let left = Box::new(self.parse_simple_expression(start)?);
+    fn parse_const(&mut self) -> Result<Statement<'a>, ParserError> {
+        let start_offset = self.token().offset();
+        self.consume(Kind::Const)?;
+        let identifier = self.parse_identifier()?;
+        self.consume(Kind::Colon)?;
+        let ttype = self.parse_type()?;
+        self.consume(Kind::EqualSign)?;
+        let expression = Box::new(self.parse_expression()?);
+        self.consume(Kind::Semicolon)?;
 
-        let op_token = self.token();
-        let operator = match op_token.kind() {
-            Kind::Plus => ast::BinaryOperator::Plus,
-            Kind::Minus => ast::BinaryOperator::Minus,
-            Kind::Star => ast::BinaryOperator::Star,
-            Kind::Divide => ast::BinaryOperator::Divide,
-            _ => {
-                self.reset(start);
-                return Err(format!("Expected a binary op, got {:?}", op_token));
-            }
-        };
-        self.step(); // Consume the op symbol.
-
-        let right = Box::new(self.parse_simple_expression(start)?);
-        self.consume(Kind::Semicolon, start)?;
+        Ok(ast::Statement::Const(ConstDeclaration {
+            id: self.node_id(),
+            span: self.span_from(start_offset),
+            identifier,
+            ttype,
+            expression,
+            docs: vec![],
+        }))
+    }
 
-        Ok(ast::Statement::Expression(Expression::BinaryExpression(
-            BinaryExpression {
-                operator,
-                left,
-                right,
-            },
-        )))
+    // Parses an expression followed by a semicolon.
+    fn parse_expression_statement(&mut self) -> Result<Statement<'a>, ParserError> {
+        let expression = self.parse_expression()?;
+        self.consume(Kind::Semicolon)?;
+        Ok(ast::Statement::Expression(expression))
     }
 
-    fn parse_function(&mut self) -> Result<Statement<'a>, String> {
-        let start = self.position;
-        self.consume(Kind::Fn, start)?;
+    fn parse_function(&mut self) -> Result<Statement<'a>, ParserError> {
+        let start_offset = self.token().offset();
+        self.consume(Kind::Fn)?;
 
-        let name = self.consume_identifier_name(start)?;
-        let identifier = Identifier { name };
+        let identifier = self.parse_identifier()?;
 
-        self.consume(Kind::LeftParenthesis, start)?;
+        self.open(Kind::LeftParenthesis)?;
 
         // Parse the parameters.
         let mut parameters = vec![];
-        while self.token().kind() != Kind::RightParenthesis {
+        while !self.check(Kind::RightParenthesis) {
             let parameter_token = self.token();
-            if let Kind::Identifier = parameter_token.kind() {
-                let name = parameter_token.text();
-                self.step(); // Consume the identifier.
-                self.consume(Kind::Colon, start)?;
-                let type_name = self.consume_identifier_name(start)?;
+            if self.check(Kind::Identifier) {
+                let identifier = self.parse_identifier()?;
+                self.consume(Kind::Colon)?;
+                let ttype = self.parse_type()?;
                 parameters.push(ast::Parameter {
-                    identifier: ast::Identifier { name },
-                    ttype: ast::Type { name: type_name },
+                    id: self.node_id(),
+                    span: self.span_from(parameter_token.offset()),
+                    identifier,
+                    ttype,
                 });
-                self.maybe_consume(Kind::Comma);
             } else {
-                self.reset(start);
-                return Err(format!(
-                    "Expected identifier or ')', got {:?}",
-                    parameter_token
-                ));
+                return Err(self.unexpected());
             };
-            self.maybe_consume(Kind::Comma);
+            if !self.check(Kind::RightParenthesis) {
+                self.consume(Kind::Comma)?;
+            }
         }
-        self.step(); // Consume the ')' token.
-        self.consume(Kind::Arrow, start)?;
+        self.close(Kind::RightParenthesis)?;
 
-        let return_type = match self.token().kind() {
-            Kind::Identifier => Type {
-                name: self.token().text(),
-            },
-            _ => {
-                self.reset(start);
-                return Err(format!("Expected type identifier, got {:?}", self.token()));
-            }
+        // Functions without a return type return unit.
+        let return_type = if self.check(Kind::Arrow) {
+            self.step(); // Consume the '->' token.
+            self.parse_type()?
+        } else {
+            let end = self.previous_token_end();
+            self.unit_type(Span::new(end, end))
+        };
+        let body = if self.check(Kind::LeftBrace) {
+            Some(self.parse_block()?)
+        } else {
+            self.consume(Kind::Semicolon)?;
+            None
         };
-        self.step(); // Consume the return type.
-        self.consume(Kind::Semicolon, start)?;
 
-        return Ok(ast::Statement::FunctionDeclaration(
+        Ok(ast::Statement::FunctionDeclaration(
             ast::FunctionDeclaration {
+                id: self.node_id(),
+                span: self.span_from(start_offset),
                 identifier,
                 parameters,
                 return_type,
+                body,
+                docs: vec![],
+                inline: false,
             },
-        ));
+        ))
+    }
+
+    // Parses a brace-delimited sequence of statements.
+    fn parse_block(&mut self) -> Result<Block<'a>, ParserError> {
+        let start_offset = self.token().offset();
+        self.open(Kind::LeftBrace)?;
+        let statements = self
+            .parse_statement_list(Kind::RightBrace)?
+            .into_iter()
+            .map(|(statement, _)| statement)
+            .collect();
+        self.close(Kind::RightBrace)?;
+        Ok(Block {
+            id: self.node_id(),
+            span: self.span_from(start_offset),
+            statements,
+        })
+    }
+
+    fn parse_return(&mut self) -> Result<Statement<'a>, ParserError> {
+        let start_offset = self.token().offset();
+        self.consume(Kind::Return)?;
+        let expression = if self.check(Kind::Semicolon) {
+            None
+        } else {
+            Some(Box::new(self.parse_expression()?))
+        };
+        self.consume(Kind::Semicolon)?;
+        Ok(ast::Statement::Return(ReturnStatement {
+            id: self.node_id(),
+            span: self.span_from(start_offset),
+            expression,
+        }))
+    }
+
+    // Parses `if condition { ... }` with optional `else` branches.
+    fn parse_if(&mut self) -> Result<Statement<'a>, ParserError> {
+        let start_offset = self.token().offset();
+        self.consume(Kind::If)?;
+        let condition = Box::new(self.parse_expression()?);
+        let then_block = self.parse_block()?;
+        let else_branch = if self.check(Kind::Else) {
+            self.step(); // Consume the 'else' token.
+            if self.check(Kind::If) {
+                Some(Box::new(self.parse_if()?))
+            } else {
+                Some(Box::new(Statement::Block(self.parse_block()?)))
+            }
+        } else {
+            None
+        };
+        Ok(Statement::If(IfStatement {
+            id: self.node_id(),
+            span: self.span_from(start_offset),
+            condition,
+            then_block,
+            else_branch,
+        }))
+    }
+
+    fn parse_while(&mut self) -> Result<Statement<'a>, ParserError> {
+        let start_offset = self.token().offset();
+        self.consume(Kind::While)?;
+        let condition = Box::new(self.parse_expression()?);
+        let body = self.parse_block()?;
+        Ok(Statement::While(WhileStatement {
+            id: self.node_id(),
+            span: self.span_from(start_offset),
+            condition,
+            body,
+        }))
+    }
+
+    // Consumes any comments before the next statement, returning the text of
+    // the doc comments immediately preceding it and whether an `#[inline]`
+    // comment does. A regular comment or a blank line separates them from the
+    // statement that follows.
+    fn parse_comments(&mut self) -> (Vec<Cow<'a, str>>, bool) {
+        let mut docs = vec![];
+        let mut inline = false;
+        loop {
+            let token = self.token();
+            match token.kind() {
+                Kind::Comment if token.text().trim_end() == "#[inline]" => inline = true,
+                Kind::Comment => {
+                    docs.clear();
+                    inline = false;
+                }
+                Kind::DocComment => {
+                    let text = &token.text()[2..];
+                    docs.push(text.strip_prefix(' ').unwrap_or(text).into());
+                }
+                _ => return (docs, inline),
+            }
+            self.step();
+            if self
+                .whitespace
+                .is_some_and(|whitespace| whitespace.text().contains('\n'))
+            {
+                docs.clear();
+                inline = false;
+            }
+        }
+    }
+
+    // Parses `import name;`.
+    fn parse_import(&mut self) -> Result<Statement<'a>, ParserError> {
+        let start_offset = self.token().offset();
+        self.consume(Kind::Import)?;
+        let module = self.parse_identifier()?;
+        self.consume(Kind::Semicolon)?;
+        Ok(Statement::Import(ImportStatement {
+            id: self.node_id(),
+            span: self.span_from(start_offset),
+            module,
+        }))
     }
 
     // Reads the next statement.
-    fn parse_statement(&mut self) -> Result<Statement<'a>, String> {
+    fn parse_statement(&mut self) -> Result<Statement<'a>, ParserError> {
+        self.expect(&STATEMENT_STARTS);
         let token = self.token();
         match token.kind() {
             Kind::Let => self.parse_let_stmt(),
-            Kind::Identifier => self.parse_binary_expression(),
-            Kind::IntegerLiteral => self.parse_binary_expression(),
+            Kind::Const => self.parse_const(),
+            Kind::Identifier | Kind::IntegerLiteral | Kind::LeftParenthesis => {
+                self.parse_expression_statement()
+            }
             Kind::Fn => self.parse_function(),
-            _ => Err(format!("Failed to parse token {:?}", token)),
+            Kind::Return => self.parse_return(),
+            Kind::If => self.parse_if(),
+            Kind::While => self.parse_while(),
+            Kind::LeftBrace => Ok(Statement::Block(self.parse_block()?)),
+            _ => Err(self.unexpected()),
         }
     }
 
-    // Parses a program from tokens.
-    //
-    // Returns an error if the program cannot be parsed.
-    pub fn parse_program(tokens: &'a [Token]) -> Result<Program<'a>, ParserError> {
-        let mut parser = Parser::new(tokens);
+    // Parses statements up to, but not including, the `closing` token,
+    // returning each statement together with the byte range of the source it
+    // was parsed from. The range includes any comments preceding the statement.
+    fn parse_statement_list(
+        &mut self,
+        closing: Kind,
+    ) -> Result<Vec<(Statement<'a>, Range<usize>)>, ParserError> {
         let mut statements = vec![];
-        while parser.token().kind() != Kind::EndOfFile {
-            if parser.token().kind() == Kind::Comment {
-                parser.step();
-                continue;
+        loop {
+            let start = self.token().offset();
+            let (docs, inline) = self.parse_comments();
+            if self.check(closing) {
+                return Ok(statements);
             }
-            match parser.parse_statement() {
-                Ok(stmt) => statements.push(stmt),
-                Err(message) => return Err(ParserError { message }),
+            // Only the top level of a file may import.
+            let mut statement = if closing == Kind::EndOfFile && self.check(Kind::Import) {
+                self.parse_import()?
+            } else {
+                self.parse_statement()?
+            };
+            match &mut statement {
+                Statement::Let(let_statement) => let_statement.docs = docs,
+                Statement::Const(constant) => constant.docs = docs,
+                Statement::FunctionDeclaration(function) => {
+                    function.docs = docs;
+                    function.inline = inline;
+                }
+                Statement::Expression(_)
+                | Statement::Return(_)
+                | Statement::If(_)
+                | Statement::While(_)
+                | Statement::Block(_)
+                | Statement::Import(_) => {}
             }
+            statements.push((statement, start..self.previous_token_end()));
         }
+    }
+
+    // Returns the offset one past the end of the last non-whitespace token
+    // before the current position.
+    fn previous_token_end(&self) -> usize {
+        self.previous_end
+    }
+
+    // Parses top-level statements from tokens, returning each statement
+    // together with the byte range of the source it was parsed from. The range
+    // includes any comments preceding the statement.
+    //
+    // Node ids are assigned starting from `next_id`, which is advanced past
+    // the last id used, and names are interned by `interner`.
+    //
+    // Returns an error if any statement cannot be parsed.
+    pub(crate) fn parse_statements(
+        tokens: S,
+        next_id: &mut NodeId,
+        interner: &'i mut Interner,
+    ) -> Result<Vec<(Statement<'a>, Range<usize>)>, ParserError> {
+        let mut parser = Parser::new(tokens, *next_id, interner);
+        let statements = parser.parse_statement_list(Kind::EndOfFile)?;
+        *next_id = parser.next_id;
+        Ok(statements)
+    }
+
+    // Parses a program from a stream of tokens, interning its names in an
+    // interner of its own.
+    //
+    // Returns an error if the program cannot be parsed.
+    pub fn parse_stream(tokens: S) -> Result<Program<'a>, ParserError> {
+        Parser::parse_stream_in(tokens, &mut Interner::new())
+    }
+
+    // Parses a program from a stream of tokens, interning its names in the
+    // given interner, such as a session's.
+    //
+    // Returns an error if the program cannot be parsed.
+    pub fn parse_stream_in(
+        tokens: S,
+        interner: &'i mut Interner,
+    ) -> Result<Program<'a>, ParserError> {
+        let statements = Parser::parse_statements(tokens, &mut NodeId(0), interner)?
+            .into_iter()
+            .map(|(statement, _)| statement)
+            .collect();
         Ok(Program { statements })
     }
 }
 
-#[cfg(test)]
+impl<'t, 'a> Parser<'a, '_, &'t [Token<'a>]> {
+    // Parses a program from tokens, which must end with an end-of-file
+    // token.
+    //
+    // Returns an error if the program cannot be parsed.
+    pub fn parse_program(tokens: &'t [Token<'a>]) -> Result<Program<'a>, ParserError> {
+        Parser::parse_stream(tokens)
+    }
+}
+
+impl<'a> Parser<'a, '_, Lexer<'a>> {
+    // Parses a program from its source, lexing each token as the parser
+    // reaches it rather than all of them first. Only the program is kept in
+    // memory, not its tokens as well.
+    //
+    // Returns an error if the program cannot be parsed.
+    pub fn parse_source(source: &'a str) -> Result<Program<'a>, ParserError> {
+        Parser::parse_stream(Lexer::new(source))
+    }
+}
+
+// Returns the span covered by a token.
+fn token_span(token: Token) -> Span {
+    Span::new(token.offset(), token.offset() + token.len())
+}
+
+// Returns the closing delimiter for an opening delimiter, or the kind itself
+// for a closing delimiter.
+fn closing_delimiter(kind: Kind) -> Option<Kind> {
+    match kind {
+        Kind::LeftBrace | Kind::RightBrace => Some(Kind::RightBrace),
+        Kind::LeftParenthesis | Kind::RightParenthesis => Some(Kind::RightParenthesis),
+        Kind::LeftSquareBracket | Kind::RightSquareBracket => Some(Kind::RightSquareBracket),
+        _ => None,
+    }
+}
+
+// Returns the name used in diagnostics for a delimiter.
+fn delimiter_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::LeftBrace | Kind::RightBrace => "brace",
+        Kind::LeftParenthesis | Kind::RightParenthesis => "parenthesis",
+        _ => "square bracket",
+    }
+}
+
+// The syntax the parser accepts, as data, for `grammar` to export. Each rule
+// is parsed by the function of the same name above, apart from the levels of
+// `expression`, which `parse_binary_expression` parses by precedence.
+// Whitespace may appear between any two tokens, but comments only between
+// statements and before the `}` of a block or the end of the file, where
+// `parse_comments` reads them. A comment that is just `#[inline]` marks the
+// function after it for inlining; `grammar::to_ebnf` says both.
+//
+// Where the rules are ambiguous the parser is greedy: a `<` after the name
+// of a type always opens its arguments, even in a cast, so `x as T < y`
+// does not parse. Keep this in step with the parser: the tests in `grammar`
+// parse sentences derived from it, and check that the `_STARTS` sets below
+// are the ones it gives.
+pub static GRAMMAR: &[Rule] = &[
+    Rule::new(
+        "program",
+        Repeat(&Choice(&[Ref("import_statement"), Ref("statement")])),
+    ),
+    Rule::new(
+        "import_statement",
+        Sequence(&[
+            Terminal(Kind::Import),
+            Terminal(Kind::Identifier),
+            Terminal(Kind::Semicolon),
+        ]),
+    ),
+    Rule::new(
+        "statement",
+        Choice(&[
+            Ref("let_statement"),
+            Ref("const_declaration"),
+            Ref("function_declaration"),
+            Ref("return_statement"),
+            Ref("if_statement"),
+            Ref("while_statement"),
+            Ref("block"),
+            Ref("expression_statement"),
+        ]),
+    ),
+    Rule::new(
+        "let_statement",
+        Sequence(&[
+            Terminal(Kind::Let),
+            Optional(&Terminal(Kind::Mut)),
+            Terminal(Kind::Identifier),
+            Terminal(Kind::Colon),
+            Ref("type"),
+            Terminal(Kind::EqualSign),
+            Ref("expression"),
+            Terminal(Kind::Semicolon),
+        ]),
+    ),
+    Rule::new(
+        "const_declaration",
+        Sequence(&[
+            Terminal(Kind::Const),
+            Terminal(Kind::Identifier),
+            Terminal(Kind::Colon),
+            Ref("type"),
+            Terminal(Kind::EqualSign),
+            Ref("expression"),
+            Terminal(Kind::Semicolon),
+        ]),
+    ),
+    Rule::new(
+        "function_declaration",
+        Sequence(&[
+            Terminal(Kind::Fn),
+            Terminal(Kind::Identifier),
+            Terminal(Kind::LeftParenthesis),
+            Ref("parameters"),
+            Terminal(Kind::RightParenthesis),
+            Optional(&Sequence(&[Terminal(Kind::Arrow), Ref("type")])),
+            Choice(&[Ref("block"), Terminal(Kind::Semicolon)]),
+        ]),
+    ),
+    Rule::new(
+        "parameters",
+        Optional(&Sequence(&[
+            Ref("parameter"),
+            Repeat(&Sequence(&[Terminal(Kind::Comma), Ref("parameter")])),
+            Optional(&Terminal(Kind::Comma)),
+        ])),
+    ),
+    Rule::new(
+        "parameter",
+        Sequence(&[
+            Terminal(Kind::Identifier),
+            Terminal(Kind::Colon),
+            Ref("type"),
+        ]),
+    ),
+    Rule::new(
+        "return_statement",
+        Sequence(&[
+            Terminal(Kind::Return),
+            Optional(&Ref("expression")),
+            Terminal(Kind::Semicolon),
+        ]),
+    ),
+    Rule::new(
+        "if_statement",
+        Sequence(&[
+            Terminal(Kind::If),
+            Ref("expression"),
+            Ref("block"),
+            Optional(&Sequence(&[
+                Terminal(Kind::Else),
+                Choice(&[Ref("if_statement"), Ref("block")]),
+            ])),
+        ]),
+    ),
+    Rule::new(
+        "while_statement",
+        Sequence(&[Terminal(Kind::While), Ref("expression"), Ref("block")]),
+    ),
+    Rule::new(
+        "block",
+        Sequence(&[
+            Terminal(Kind::LeftBrace),
+            Repeat(&Ref("statement")),
+            Terminal(Kind::RightBrace),
+        ]),
+    ),
+    Rule::new(
+        "expression_statement",
+        Sequence(&[Ref("expression"), Terminal(Kind::Semicolon)]),
+    ),
+    Rule::new(
+        "expression",
+        Sequence(&[
+            Ref("sum"),
+            Repeat(&Sequence(&[
+                Choice(&[
+                    Terminal(Kind::EqualEqual),
+                    Terminal(Kind::NotEqual),
+                    Terminal(Kind::LessThan),
+                    Terminal(Kind::LessEqual),
+                    Terminal(Kind::GreaterThan),
+                    Terminal(Kind::GreaterEqual),
+                ]),
+                Ref("sum"),
+            ])),
+        ]),
+    ),
+    Rule::new(
+        "sum",
+        Sequence(&[
+            Ref("product"),
+            Repeat(&Sequence(&[
+                Choice(&[Terminal(Kind::Plus), Terminal(Kind::Minus)]),
+                Ref("product"),
+            ])),
+        ]),
+    ),
+    Rule::new(
+        "product",
+        Sequence(&[
+            Ref("power"),
+            Repeat(&Sequence(&[
+                Choice(&[Terminal(Kind::Star), Terminal(Kind::Divide)]),
+                Ref("power"),
+            ])),
+        ]),
+    ),
+    // `**` groups to the right.
+    Rule::new(
+        "power",
+        Sequence(&[
+            Ref("cast"),
+            Optional(&Sequence(&[Terminal(Kind::StarStar), Ref("power")])),
+        ]),
+    ),
+    Rule::new(
+        "cast",
+        Sequence(&[
+            Ref("primary"),
+            Repeat(&Sequence(&[Terminal(Kind::As), Ref("type")])),
+        ]),
+    ),
+    Rule::new(
+        "primary",
+        Choice(&[
+            Terminal(Kind::IntegerLiteral),
+            Sequence(&[
+                Choice(&[
+                    Terminal(Kind::Identifier),
+                    Sequence(&[
+                        Terminal(Kind::LeftParenthesis),
+                        Ref("expression"),
+                        Terminal(Kind::RightParenthesis),
+                    ]),
+                ]),
+                Repeat(&Ref("call")),
+            ]),
+        ]),
+    ),
+    Rule::new(
+        "call",
+        Sequence(&[
+            Terminal(Kind::LeftParenthesis),
+            Optional(&Sequence(&[
+                Ref("expression"),
+                Repeat(&Sequence(&[Terminal(Kind::Comma), Ref("expression")])),
+                Optional(&Terminal(Kind::Comma)),
+            ])),
+            Terminal(Kind::RightParenthesis),
+        ]),
+    ),
+    // A parenthesized list of no types is the unit type, and of one type
+    // without a trailing comma is that type; any other is a tuple.
+    Rule::new(
+        "type",
+        Choice(&[
+            Sequence(&[
+                Terminal(Kind::Identifier),
+                Optional(&Sequence(&[
+                    Terminal(Kind::LessThan),
+                    Ref("type_list"),
+                    Terminal(Kind::GreaterThan),
+                ])),
+            ]),
+            Sequence(&[
+                Terminal(Kind::LeftSquareBracket),
+                Ref("type"),
+                Terminal(Kind::Semicolon),
+                Ref("expression"),
+                Terminal(Kind::RightSquareBracket),
+            ]),
+            Sequence(&[
+                Terminal(Kind::LeftParenthesis),
+                Ref("type_list"),
+                Terminal(Kind::RightParenthesis),
+            ]),
+            Sequence(&[
+                Terminal(Kind::Fn),
+                Terminal(Kind::LeftParenthesis),
+                Ref("type_list"),
+                Terminal(Kind::RightParenthesis),
+                Terminal(Kind::Arrow),
+                Ref("type"),
+            ]),
+        ]),
+    ),
+    Rule::new(
+        "type_list",
+        Optional(&Sequence(&[
+            Ref("type"),
+            Repeat(&Sequence(&[Terminal(Kind::Comma), Ref("type")])),
+            Optional(&Terminal(Kind::Comma)),
+        ])),
+    ),
+];
+
+// Token kinds that can start a statement.
+pub(crate) const STATEMENT_STARTS: [Kind; 10] = [
+    Kind::Let,
+    Kind::Const,
+    Kind::Fn,
+    Kind::Return,
+    Kind::If,
+    Kind::While,
+    Kind::LeftBrace,
+    Kind::Identifier,
+    Kind::IntegerLiteral,
+    Kind::LeftParenthesis,
+];
+
+// Token kinds that can start an expression.
+pub(crate) const EXPRESSION_STARTS: [Kind; 3] = [
+    Kind::Identifier,
+    Kind::IntegerLiteral,
+    Kind::LeftParenthesis,
+];
+
+// Token kinds that can start a type.
+pub(crate) const TYPE_STARTS: [Kind; 4] = [
+    Kind::Identifier,
+    Kind::LeftSquareBracket,
+    Kind::LeftParenthesis,
+    Kind::Fn,
+];
+
+// Token kinds of binary operators.
+const BINARY_OPERATORS: [Kind; 11] = [
+    Kind::Plus,
+    Kind::Minus,
+    Kind::Star,
+    Kind::Divide,
+    Kind::StarStar,
+    Kind::EqualEqual,
+    Kind::NotEqual,
+    Kind::LessThan,
+    Kind::LessEqual,
+    Kind::GreaterThan,
+    Kind::GreaterEqual,
+];
+
+// Returns the binary operator for a token kind, if it is one.
+fn binary_operator(kind: Kind) -> Option<ast::BinaryOperator> {
+    match kind {
+        Kind::Plus => Some(ast::BinaryOperator::Plus),
+        Kind::Minus => Some(ast::BinaryOperator::Minus),
+        Kind::Star => Some(ast::BinaryOperator::Star),
+        Kind::Divide => Some(ast::BinaryOperator::Divide),
+        Kind::StarStar => Some(ast::BinaryOperator::Power),
+        Kind::EqualEqual => Some(ast::BinaryOperator::Equal),
+        Kind::NotEqual => Some(ast::BinaryOperator::NotEqual),
+        Kind::LessThan => Some(ast::BinaryOperator::Less),
+        Kind::LessEqual => Some(ast::BinaryOperator::LessEqual),
+        Kind::GreaterThan => Some(ast::BinaryOperator::Greater),
+        Kind::GreaterEqual => Some(ast::BinaryOperator::GreaterEqual),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    use crate::{ast, lexer::Lexer, matcher::*, parser::Parser, *};
+    use crate::{
+        ast::{self, Span},
+        lexer::Lexer,
+        matcher,
+        matcher::*,
+        parser::Parser,
+        token::{Kind, TokenStream},
+    };
 
     #[test]
     fn empty_file_can_be_parsed() {
@@ -266,6 +1172,14 @@ mod tests {
         assert!(program.unwrap().statements.is_empty());
     }
 
+    #[test]
+    fn leading_whitespace_is_ignored() {
+        let input = "  \n let x: int32 = 5;";
+        let tokens = Lexer::tokenize(input);
+        let program = Parser::parse_program(&tokens);
+        assert_eq!(program.unwrap().statements.len(), 1);
+    }
+
     #[test]
     fn fail_to_parse_let_statement_with_no_trailing_semicolon() {
         let input = "let x: int32 = 5";
@@ -276,12 +1190,142 @@ mod tests {
                 panic!("Expected parse error");
             }
             Err(err) => {
-                assert!(err
-                    .message
-                    .eq("Expected Semicolon, got Token { text: \"<EOF>\", offset: 15, kind: EndOfFile }"));
+                assert!(err.message.eq(
+                    "Expected one of 'as', '+', '-', '*', '/', '**', '==', '!=', '<', '<=', \
+                     '>', '>=', ';', got Token { text: \"<EOF>\", offset: 15, kind: EndOfFile }"
+                ));
             }
         }
     }
+    // Returns the error message from parsing `input`.
+    fn parse_error(input: &str) -> String {
+        let tokens = Lexer::tokenize(input);
+        match Parser::parse_program(&tokens) {
+            Ok(program) => panic!("Expected parse error, got {:?}", program),
+            Err(err) => err.message,
+        }
+    }
+
+    #[test]
+    fn unclosed_brace_is_reported_at_the_opener() {
+        let input = "fn f() -> int32 {\n    return 1;\n";
+        assert_eq!(parse_error(input), "Unclosed brace opened at 1:17");
+    }
+
+    #[test]
+    fn innermost_unclosed_delimiter_is_reported() {
+        let input = "fn f() -> int32 {\n    return (1 + 2\n";
+        assert_eq!(parse_error(input), "Unclosed parenthesis opened at 2:12");
+    }
+
+    #[test]
+    fn unclosed_delimiters_in_types_and_parameters() {
+        assert_eq!(
+            parse_error("let xs: [int32; 4"),
+            "Unclosed square bracket opened at 1:9"
+        );
+        assert_eq!(
+            parse_error("fn f(x: int32"),
+            "Unclosed parenthesis opened at 1:5"
+        );
+    }
+
+    #[test]
+    fn parameters_are_separated_by_one_comma() {
+        for input in ["fn f(a: int32, b: int32);", "fn f(a: int32, b: int32,);"] {
+            let program = Parser::parse_source(input).unwrap();
+            match &program.statements[0] {
+                ast::Statement::FunctionDeclaration(function) => {
+                    assert_eq!(function.parameters.len(), 2)
+                }
+                statement => panic!("Expected a function, got {:?}", statement),
+            }
+        }
+        assert_eq!(
+            parse_error("fn f(a: int32 b: int32);"),
+            "Expected one of '<', ')', ',', got Token { text: \"b\", offset: 14, kind: Identifier }"
+        );
+        for (input, offset) in [
+            ("fn f(a: int32,, b: int32);", 14),
+            ("fn f(a: int32,,);", 14),
+            ("fn f(,);", 5),
+        ] {
+            assert_eq!(
+                parse_error(input),
+                format!(
+                    "Expected one of ')', identifier, got Token {{ text: \",\", offset: {}, kind: Comma }}",
+                    offset
+                )
+            );
+        }
+        // Comments may only appear between statements.
+        assert!(parse_error("fn f(a: int32, # b\n b: int32);").ends_with("kind: Comment }"));
+        assert!(Parser::parse_source("fn f(a: int32); # f\n# g\nfn g() {\n    # end\n}").is_ok());
+    }
+
+    #[test]
+    fn parsing_from_the_lexer_matches_parsing_from_tokens() {
+        let input = "## Squares.\nfn square(x: int32) -> int32 {\n    return x * x;\n}\n\n# Done.\nsquare(2 as int32);";
+        let tokens = Lexer::tokenize(input);
+        let expected = Parser::parse_program(&tokens).unwrap();
+        let program = Parser::parse_source(input).unwrap();
+        assert_eq!(format!("{:?}", program), format!("{:?}", expected));
+
+        let error = Parser::parse_source("fn f() {\n    (1 + 2];").unwrap_err();
+        assert_eq!(error.code, "E0102");
+        assert_eq!(error.span, Span::new(19, 20));
+
+        // The end-of-file token is read again once reached.
+        let mut lexer = Lexer::new("x");
+        assert_eq!(lexer.next_token().kind(), Kind::Identifier);
+        assert_eq!(lexer.next_token().kind(), Kind::EndOfFile);
+        assert_eq!(lexer.next_token().kind(), Kind::EndOfFile);
+    }
+
+    #[test]
+    fn mismatched_closing_delimiter_is_reported_at_the_opener() {
+        let input = "let x: int32 = (1 + 2];";
+        assert_eq!(
+            parse_error(input),
+            "Mismatched ']' for parenthesis opened at 1:16"
+        );
+    }
+
+    #[test]
+    fn errors_are_diagnostics_labelling_the_opener() {
+        let input = "fn f() -> int32 {\n    return (1 + 2];";
+        let tokens = Lexer::tokenize(input);
+        let error = Parser::parse_program(&tokens).unwrap_err();
+        assert_eq!(error.code, "E0102");
+        assert_eq!(&input[error.span.range()], "]");
+        assert_eq!(error.labels.len(), 1);
+        assert_eq!(&input[error.labels[0].span.range()], "(");
+
+        let tokens = Lexer::tokenize("fn f() {");
+        let error = Parser::parse_program(&tokens).unwrap_err();
+        assert_eq!(error.code, "E0101");
+        assert_eq!(error.span, Span::new(8, 8));
+        assert_eq!(error.labels[0].span, Span::new(7, 8));
+
+        let tokens = Lexer::tokenize("let = 1;");
+        assert_eq!(Parser::parse_program(&tokens).unwrap_err().code, "E0100");
+    }
+
+    #[test]
+    fn function_bodies_can_be_parsed() {
+        let input = "fn sq(x: int32) -> int32 {\n    let y: int32 = x * x;\n    return y;\n}";
+        let tokens = Lexer::tokenize(input);
+        let program = Parser::parse_program(&tokens).unwrap();
+        match &program.statements[0] {
+            ast::Statement::FunctionDeclaration(function) => {
+                let body = function.body.as_ref().unwrap();
+                assert_eq!(body.statements.len(), 2);
+                assert!(matches!(body.statements[1], ast::Statement::Return(_)));
+            }
+            statement => panic!("Expected a function, got {:?}", statement),
+        }
+    }
+
     #[test]
     fn multiple_statements_can_be_parsed() {
         let input = "fn max() -> int32; fn min() -> int32; fn mean() -> float32;";
@@ -295,6 +1339,77 @@ mod tests {
         }
     }
 
+    #[test]
+    fn doc_comments_are_attached_to_declarations() {
+        let input = "\
+## Returns the larger value.
+##
+## Ties return `x`.
+fn max(x: int32, y: int32) -> int32;
+# Not documentation.
+let x: int32 = 5;
+## Detached by the blank line.
+
+## The answer.
+let mut y: int32 = 42;
+";
+        let tokens = Lexer::tokenize(input);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let docs: Vec<&Vec<_>> = program
+            .statements
+            .iter()
+            .map(|statement| match statement {
+                ast::Statement::FunctionDeclaration(f) => &f.docs,
+                ast::Statement::Let(l) => &l.docs,
+                _ => panic!("Unexpected statement {:?}", statement),
+            })
+            .collect();
+        assert_eq!(
+            docs,
+            [
+                &vec!["Returns the larger value.", "", "Ties return `x`."],
+                &Vec::<&str>::new(),
+                &vec!["The answer."],
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_attributes_mark_functions() {
+        let input = "\
+## Doubles.
+#[inline]
+fn double(x: int32) -> int32;
+#[inline]
+
+fn detached();
+#[inline]
+# Not an attribute.
+fn commented();
+fn plain();
+";
+        let tokens = Lexer::tokenize(input);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let functions: Vec<(bool, &Vec<_>)> = program
+            .statements
+            .iter()
+            .map(|statement| match statement {
+                ast::Statement::FunctionDeclaration(f) => (f.inline, &f.docs),
+                _ => panic!("Unexpected statement {:?}", statement),
+            })
+            .collect();
+        let no_docs = &Vec::<std::borrow::Cow<str>>::new();
+        assert_eq!(
+            functions,
+            [
+                (true, &vec!["Doubles.".into()]),
+                (false, no_docs),
+                (false, no_docs),
+                (false, no_docs),
+            ]
+        );
+    }
+
     #[test]
     fn test_matcher() {
         let input = "x + y;";
@@ -324,7 +1439,8 @@ mod tests {
                         {
                             if let ast::Statement::Expression(expr) = statement {
                                 assert!(matcher.matches(expr),
-                                        "Matcher failed to match expression {:?}", expr);
+                                        "Matcher failed to match expression {:?}: {}",
+                                        expr, matcher.explain(expr));
                             } else {
                                 panic!("Expected an expression statement");
                             }
@@ -386,6 +1502,146 @@ mod tests {
         )
     );
 
+    parse_expression_test!(
+        parse_binary_power_expression,
+        "2 ** 4;",
+        match_binary_expression!(
+            match_integer_literal!("2"),
+            ast::BinaryOperator::Power,
+            match_integer_literal!("4")
+        )
+    );
+
+    parse_expression_test!(
+        parse_power_is_right_associative,
+        "2 ** 3 ** 4;",
+        match_binary_expression!(
+            match_integer_literal!("2"),
+            ast::BinaryOperator::Power,
+            match_binary_expression!(
+                match_integer_literal!("3"),
+                ast::BinaryOperator::Power,
+                match_integer_literal!("4")
+            )
+        )
+    );
+
+    parse_expression_test!(
+        parse_power_binds_tighter_than_star,
+        "2 * 3 ** 4; 2 ** 3 * 4;",
+        match_binary_expression!(
+            match_integer_literal!("2"),
+            ast::BinaryOperator::Star,
+            match_binary_expression!()
+        ),
+        match_binary_expression!(
+            match_binary_expression!(),
+            ast::BinaryOperator::Star,
+            match_integer_literal!("4")
+        )
+    );
+
+    parse_expression_test!(
+        parse_star_binds_tighter_than_plus,
+        "1 + 2 * 3 - 4;",
+        match_binary_expression!(
+            match_binary_expression!(
+                match_integer_literal!("1"),
+                ast::BinaryOperator::Plus,
+                match_binary_expression!(
+                    match_integer_literal!("2"),
+                    ast::BinaryOperator::Star,
+                    match_integer_literal!("3")
+                )
+            ),
+            ast::BinaryOperator::Minus,
+            match_integer_literal!("4")
+        )
+    );
+
+    parse_expression_test!(
+        parse_parenthesized_expression,
+        "(1 + 2) * x;",
+        match_binary_expression!(
+            match_binary_expression!(),
+            ast::BinaryOperator::Star,
+            match_identifier!("x")
+        )
+    );
+
+    parse_expression_test!(
+        parse_binary_expression_whose_left_side_is_not_a_literal,
+        "x * 2;",
+        match_binary_expression!(
+            not!(match_integer_literal!()),
+            ast::BinaryOperator::Star,
+            any_of!(match_integer_literal!("1"), match_integer_literal!("2"))
+        )
+    );
+
+    parse_expression_test!(
+        parse_binary_expression_excluded_by_not,
+        "3 * x;",
+        all_of!(
+            match_binary_expression!(),
+            not!(match_binary_expression!(
+                not!(match_integer_literal!()),
+                ast::BinaryOperator::Star,
+                match_any_expression!()
+            ))
+        )
+    );
+
+    parse_expression_test!(
+        parse_binary_expression_with_nested_combinators,
+        "f(x) + 1;",
+        all_of!(
+            match_binary_expression!(
+                any_of!(match_identifier!(), match_call_expression!()),
+                ast::BinaryOperator::Plus,
+                match_integer_literal!()
+            ),
+            not!(match_binary_expression!(
+                match_identifier!(),
+                ast::BinaryOperator::Plus,
+                match_any_expression!()
+            )),
+            match_any_expression!()
+        )
+    );
+
+    parse_expression_test!(
+        parse_identifiers_matching_a_regex,
+        "tmp_1 + tmp_value; tmp + 2;",
+        match_binary_expression!(
+            match_identifier_regex!("tmp_.*"),
+            ast::BinaryOperator::Plus,
+            any_of!(
+                match_identifier_regex!("tmp_[a-z]+"),
+                match_integer_in!(0..=1)
+            )
+        ),
+        match_binary_expression!(
+            not!(match_identifier_regex!("tmp_.*")),
+            ast::BinaryOperator::Plus,
+            all_of!(match_integer_in!(2..), not!(match_integer_in!(..2)))
+        )
+    );
+
+    #[test]
+    fn integer_range_matchers() {
+        let tokens = Lexer::tokenize("255; 256; 99999999999999999999999999999999999999999;");
+        let program = Parser::parse_program(&tokens).unwrap();
+        let byte = match_expression_statement!(match_integer_in!(0..=255));
+        let fits: Vec<bool> = program.statements.iter().map(|s| byte.matches(s)).collect();
+        assert_eq!(fits, [true, false, false]);
+        assert_eq!(
+            byte.explain(&program.statements[1]).to_string(),
+            "at expression: expected integer literal in 0..=255, got `256`"
+        );
+        assert!(matcher::identifier_regex("tmp_(").is_err());
+    }
+
     macro_rules! parse_statement_test {
         ($name:ident, $input:expr, $($m:expr),+) => {
             #[test]
@@ -397,7 +1653,8 @@ mod tests {
                         for (statement, matcher) in program.statements.iter().zip(matchers.iter())
                         {
                             assert!(matcher.matches(statement),
-                                    "Matcher failed to match expression {:?}", statement);
+                                    "Matcher failed to match statement {:?}: {}",
+                                    statement, matcher.explain(statement));
                         }
                     }
                     Err(err) => panic!("Failed to parse program: {}", err.message),
@@ -442,6 +1699,409 @@ mod tests {
             match_type!("int32"))
     }
 
+    parse_statement_test! {
+        parse_let_statement_with_array_type,
+        "let xs: [int32; 4] = y;",
+        match_let_statement!(
+            "xs",
+            match_array_type!(match_type!("int32"), "4"),
+            match_any_expression!())
+    }
+
+    parse_statement_test! {
+        parse_let_statement_with_generic_type,
+        "let xs: Map<string, List<int32>> = y;",
+        match_let_statement!(
+            "xs",
+            match_generic_type!(
+                "Map",
+                vec![
+                    match_type!("string"),
+                    match_generic_type!("List", vec![match_type!("int32")])
+                ]
+            ),
+            match_any_expression!())
+    }
+
+    parse_statement_test! {
+        parse_let_statement_with_tuple_types,
+        "let t: (int32, (float32,)) = y; let u: () = y; let v: (int32) = y;",
+        match_let_statement!(
+            "t",
+            match_tuple_type!(vec![
+                match_type!("int32"),
+                match_tuple_type!(vec![match_type!("float32")])
+            ]),
+            match_any_expression!()),
+        match_let_statement!("u", match_unit_type!(), match_any_expression!()),
+        match_let_statement!("v", match_type!("int32"), match_any_expression!())
+    }
+
+    parse_statement_test! {
+        parse_function_with_function_type_parameter,
+        "fn apply(f: fn(int32) -> int32, x: int32) -> [int32; 2];",
+        match_function_declaration!(
+            "apply",
+            vec![
+                NamedParameterMatcher::new(
+                    "f".to_string(),
+                    match_function_type!(vec![match_type!("int32")], match_type!("int32"))
+                ),
+                match_parameter!("x", "int32")
+            ],
+            match_array_type!(match_type!("int32"), "2"))
+    }
+
+    #[test]
+    fn imports_parse_only_at_the_top_level() {
+        let tokens = Lexer::tokenize("import geometry;\nfn main() { import shapes; }");
+        let error = Parser::parse_program(&tokens).unwrap_err();
+        assert!(error.message.starts_with("Expected one of '}', 'let',"));
+        assert_eq!(error.span, Span::new(29, 35));
+
+        let tokens = Lexer::tokenize("# Shapes.\nimport geometry;\nfn main() {}\nimport shapes;");
+        let program = Parser::parse_program(&tokens).unwrap();
+        let names: Vec<&str> = program.imports().map(|i| i.module.name.as_str()).collect();
+        assert_eq!(names, ["geometry", "shapes"]);
+        assert_eq!(program.imports().next().unwrap().span, Span::new(10, 26));
+        assert!(parse_error("import;").starts_with("Expected identifier"));
+    }
+
+    #[test]
+    fn fail_to_parse_array_type_without_size() {
+        let input = "let xs: [int32] = y;";
+        let tokens = Lexer::tokenize(input);
+        let err = Parser::parse_program(&tokens).unwrap_err();
+        assert!(err.message.starts_with("Expected one of '<', ';', got"));
+    }
+
+    parse_statement_test! {
+        parse_functions_without_return_types,
+        "fn log(msg: string) { return; } fn flush(); fn done() -> () {}",
+        match_function_declaration!(
+            "log",
+            vec![match_parameter!("msg", "string")],
+            match_unit_type!()),
+        match_function_declaration!("flush", match_unit_type!()),
+        match_function_declaration!("done", match_unit_type!())
+    }
+
+    #[test]
+    fn fail_to_parse_function_with_arrow_and_no_return_type() {
+        let input = "fn f() -> { }";
+        assert_eq!(
+            parse_error(input),
+            "Expected one of identifier, '[', '(', 'fn', \
+             got Token { text: \"{\", offset: 10, kind: LeftBrace }"
+        );
+    }
+
+    #[test]
+    fn errors_list_every_acceptable_token() {
+        assert_eq!(
+            parse_error("let x: int32 5;"),
+            "Expected one of '<', '=', got Token { text: \"5\", offset: 13, kind: IntegerLiteral }"
+        );
+        assert_eq!(
+            parse_error("let mut = 5;"),
+            "Expected identifier, got Token { text: \"=\", offset: 8, kind: EqualSign }"
+        );
+        assert_eq!(
+            parse_error("fn f() int32;"),
+            "Expected one of '->', '{', ';', \
+             got Token { text: \"int32\", offset: 7, kind: Identifier }"
+        );
+        assert_eq!(
+            parse_error("-> x;"),
+            "Expected one of end of file, 'import', 'let', 'const', 'fn', 'return', 'if', \
+             'while', '{', identifier, integer literal, '(', got Token { text: \"->\", offset: 0, kind: Arrow }"
+        );
+    }
+
+    #[test]
+    fn parse_control_flow_and_calls() {
+        let input = "
+            fn f(n: int32) -> int32 {
+                let mut i: int32 = 0;
+                while i < n { step(i, n * 2); }
+                if i == n { return i; } else if (g)() >= 1 { return; } else { { } }
+            }
+            f(3) != 2;";
+        let tokens = Lexer::tokenize(input);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let matcher = match_program!(
+            match_function_declaration!(
+                "f",
+                vec![match_parameter!("n", "int32")],
+                match_type!("int32")
+            ),
+            match_expression_statement!(match_binary_expression!(
+                match_call_expression!(match_identifier!("f"), vec![match_integer_literal!("3")]),
+                ast::BinaryOperator::NotEqual,
+                match_integer_literal!("2")
+            ))
+        );
+        matcher.assert_matches(&program);
+
+        let ast::Statement::FunctionDeclaration(function) = &program.statements[0] else {
+            panic!("Expected a function declaration");
+        };
+        let body = match_block!(
+            match_mutable_let_statement!("i", match_type!("int32"), match_integer_literal!("0")),
+            match_while_statement!(
+                match_binary_expression!(
+                    match_identifier!("i"),
+                    ast::BinaryOperator::Less,
+                    match_identifier!("n")
+                ),
+                vec![match_expression_statement!(match_call_expression!(
+                    match_identifier!("step"),
+                    vec![match_identifier!("i"), match_binary_expression!()]
+                ))]
+            ),
+            match_if_statement!(
+                match_binary_expression!(
+                    match_identifier!("i"),
+                    ast::BinaryOperator::Equal,
+                    match_identifier!("n")
+                ),
+                vec![match_return_statement!(match_identifier!("i"))],
+                match_if_statement!(
+                    match_binary_expression!(
+                        match_call_expression!(match_identifier!("g"), vec![]),
+                        ast::BinaryOperator::GreaterEqual,
+                        match_integer_literal!("1")
+                    ),
+                    vec![match_return_statement!()],
+                    match_block!(match_block!())
+                )
+            )
+        );
+        assert!(body.matches(&ast::Statement::Block(function.body.clone().unwrap())));
+    }
+
+    #[test]
+    fn function_body_matchers() {
+        let input = "
+            fn main() {
+                let x: int32 = 1;
+                while x < 10 { if x == 5 { return; } }
+            }
+            fn empty();";
+        let tokens = Lexer::tokenize(input);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let main = &program.statements[0];
+        let exact = match_function_with_body!(
+            "main",
+            [
+                match_let_statement!("x", match_type!("int32"), match_integer_literal!("1")),
+                match_while_statement!(match_any_expression!(), vec![match_any_expression!()])
+            ]
+        );
+        assert!(exact.matches(main));
+        // Exact matching only looks at the top level of the body.
+        let nested = match_function_with_body!("main", [match_return_statement!()]);
+        assert!(!nested.matches(main));
+        let contains = match_function_with_body!(
+            "main",
+            contains [match_return_statement!(), match_let_statement!(
+                "x",
+                match_type!(),
+                match_any_expression!()
+            )]
+        );
+        assert!(contains.matches(main));
+        assert!(!match_function_with_body!(
+            "main",
+            contains[match_return_statement!(match_any_expression!())]
+        )
+        .matches(main));
+        assert!(!match_function_with_body!("empty", contains []).matches(&program.statements[1]));
+        assert!(match_contains!(match_if_statement!(
+            match_any_expression!(),
+            vec![match_return_statement!()]
+        ))
+        .matches(main));
+    }
+
+    #[test]
+    fn explain_points_at_the_first_differing_field() {
+        let tokens = Lexer::tokenize("let x: Map<string, int32> = a + f(1, y); while x { }");
+        let program = Parser::parse_program(&tokens).unwrap();
+        let statement = &program.statements[0];
+        let explain = |matcher: Box<dyn StatementMatcher>| matcher.explain(statement).to_string();
+        assert_eq!(
+            explain(match_let_statement!(
+                "x",
+                match_type!(),
+                match_binary_expression!(
+                    match_identifier!("a"),
+                    ast::BinaryOperator::Plus,
+                    match_call_expression!(
+                        match_identifier!("f"),
+                        vec![match_integer_literal!("1"), match_integer_literal!("2")]
+                    )
+                )
+            )),
+            "at expression.right.arguments[1]: expected `2`, got `y`"
+        );
+        assert_eq!(
+            explain(match_let_statement!(
+                "x",
+                match_generic_type!("Map", vec![match_type!("string")]),
+                match_any_expression!()
+            )),
+            "at ttype.arguments.len: expected 1, got 2"
+        );
+        assert_eq!(
+            explain(match_mutable_let_statement!(
+                "x",
+                match_type!(),
+                match_any_expression!()
+            )),
+            "at mutable: expected mutable = true, got mutable = false"
+        );
+        assert_eq!(
+            explain(match_return_statement!()),
+            "expected return statement, got let statement"
+        );
+        assert_eq!(
+            match_program!(
+                match_let_statement!("x", match_type!(), match_any_expression!()),
+                match_while_statement!(match_identifier!("y"), vec![])
+            )
+            .explain(&program)
+            .to_string(),
+            "at statements[1].condition: expected `y`, got `x`"
+        );
+        assert!(match_program!(
+            match_any_expression!(),
+            match_while_statement!(match_identifier!("x"), vec![])
+        )
+        .explain(&program)
+        .is_match());
+    }
+
+    #[test]
+    fn repeated_matchers() {
+        let input = "
+            fn f() {
+                let a: int32 = 1;
+                let b: int32 = 2;
+                let c: int32 = 3;
+                if a < b { let d: int32 = 4; }
+                return c;
+            }";
+        let tokens = Lexer::tokenize(input);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let f = &program.statements[0];
+        let any_let = || parse_pattern("let _: _ = _;").unwrap();
+
+        // A function with more than three let bindings, at any depth.
+        assert!(match_function_with_body!(
+            "f",
+            contains[match_repeated!(Box::new(any_let()), at_least = 4)]
+        )
+        .matches(f));
+        assert!(!match_function_with_body!(
+            "f",
+            contains[match_repeated!(Box::new(any_let()), at_most = 3)]
+        )
+        .matches(f));
+
+        // Runs of statements in an exact sequence.
+        let sequence = |min, max| {
+            match_function_with_body!(
+                "f",
+                [
+                    match_repeated!(Box::new(any_let()), at_least = min, at_most = max),
+                    match_repeated!(match_contains!(Box::new(any_let()))),
+                    match_return_statement!(match_any_expression!())
+                ]
+            )
+        };
+        assert!(sequence(1, 3).matches(f));
+        assert!(sequence(0, 2).matches(f));
+        assert!(!sequence(4, 5).matches(f));
+        assert_eq!(
+            match_function_with_body!(
+                "f",
+                [
+                    match_repeated!(Box::new(any_let()), exactly = 3),
+                    match_return_statement!()
+                ]
+            )
+            .explain(f)
+            .to_string(),
+            "at body.statements: expected statements matching a repeated sequence, \
+             got 5 statements that do not"
+        );
+        assert!(
+            match_program!(match_repeated!(match_any_expression!(), exactly = 1)).matches(&program)
+        );
+        assert_eq!(
+            match_program!(match_repeated!(match_any_expression!(), at_least = 2))
+                .mismatch(&program),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn program_matcher_reports_the_first_mismatch() {
+        let tokens = Lexer::tokenize("x; return; if x { }");
+        let program = Parser::parse_program(&tokens).unwrap();
+        let matcher = match_program!(
+            match_expression_statement!(match_identifier!("x")),
+            match_return_statement!(match_any_expression!()),
+            match_if_statement!(match_identifier!("x"), vec![])
+        );
+        assert_eq!(matcher.mismatch(&program), Some(1));
+        assert_eq!(match_program!().mismatch(&program), Some(0));
+        assert!(match_program!(
+            match_expression_statement!(match_any_expression!()),
+            match_return_statement!(),
+            match_if_statement!(match_any_expression!(), vec![])
+        )
+        .matches(&program));
+    }
+
+    #[test]
+    fn comparisons_bind_looser_than_arithmetic() {
+        let tokens = Lexer::tokenize("a + 1 <= b * 2;");
+        let program = Parser::parse_program(&tokens).unwrap();
+        match_program!(match_expression_statement!(match_binary_expression!(
+            match_binary_expression!(),
+            ast::BinaryOperator::LessEqual,
+            match_binary_expression!()
+        )))
+        .assert_matches(&program);
+    }
+
+    #[test]
+    fn casts_bind_tighter_than_binary_operators() {
+        let tokens = Lexer::tokenize("a + f(b) as int64 as float64 * c; (a + b) as int8;");
+        let program = Parser::parse_program(&tokens).unwrap();
+        match_program!(
+            match_expression_statement!(match_binary_expression!(
+                match_identifier!("a"),
+                ast::BinaryOperator::Plus,
+                match_binary_expression!(
+                    match_cast_expression!(
+                        match_cast_expression!(match_call_expression!(), match_type!("int64")),
+                        match_type!("float64")
+                    ),
+                    ast::BinaryOperator::Star,
+                    match_identifier!("c")
+                )
+            )),
+            match_expression_statement!(match_cast_expression!(
+                match_binary_expression!(),
+                match_type!("int8")
+            ))
+        )
+        .assert_matches(&program);
+    }
+
     parse_statement_test! {
         parse_function_with_no_parameters,
         "fn max() -> int32;",
@@ -0,0 +1,371 @@
+use crate::{
+    ast::Program,
+    callgraph::CallGraph,
+    cfg::{self, Cfg},
+    constprop::Constants,
+    diagnostic::{Diagnostic, DiagnosticSink},
+    hir,
+    lexer::Lexer,
+    lint::{rules::RULES, Level, LintLevels},
+    resolver::{resolve, Resolution},
+    source_map::SourceMap,
+    timings::Timings,
+    typecheck::{typecheck, TypeCheck},
+};
+
+// What the passes share: the program and its source, and everything earlier
+// passes found out about it. Each analysis is `None` until the pass that
+// computes it has run.
+pub struct Context<'p, 'a> {
+    pub program: &'p Program<'a>,
+    pub source_map: &'p SourceMap,
+    // The lint levels diagnostics are reported at, including those set by
+    // lint attributes in the source.
+    pub lints: LintLevels,
+    pub resolution: Option<Resolution>,
+    pub typecheck: Option<TypeCheck>,
+    pub call_graph: Option<CallGraph>,
+    pub hir: Option<hir::Program>,
+    // The control flow graph of every function with a body, in source
+    // order, and what constant propagation found out about each.
+    pub cfgs: Option<Vec<Cfg>>,
+    pub constants: Option<Vec<Constants>>,
+    // The diagnostics reported so far, with lint levels applied.
+    pub diagnostics: Vec<Diagnostic>,
+    // How long each pass took.
+    pub timings: Timings,
+}
+
+impl<'p, 'a> Context<'p, 'a> {
+    // Creates a context for a program parsed from the source in
+    // `source_map`, applying the lint attributes found in the source on top
+    // of `lints`.
+    pub fn new(
+        program: &'p Program<'a>,
+        source_map: &'p SourceMap,
+        lints: &LintLevels,
+    ) -> Context<'p, 'a> {
+        let mut context = Context {
+            program,
+            source_map,
+            lints: lints.clone(),
+            resolution: None,
+            typecheck: None,
+            call_graph: None,
+            hir: None,
+            cfgs: None,
+            constants: None,
+            diagnostics: vec![],
+            timings: Timings::new(),
+        };
+        let tokens = Lexer::tokenize(source_map.source());
+        context
+            .lints
+            .apply_attributes(&tokens, &mut context.diagnostics);
+        context
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    // Returns the result of name resolution. Panics if it has not run, which
+    // means the passes were added in the wrong order.
+    pub fn resolution(&self) -> &Resolution {
+        self.resolution
+            .as_ref()
+            .expect("name resolution must run before this pass")
+    }
+
+    // Returns the result of type checking. Panics if it has not run.
+    pub fn typecheck(&self) -> &TypeCheck {
+        self.typecheck
+            .as_ref()
+            .expect("type checking must run before this pass")
+    }
+
+    // Returns the lowered program. Panics if it has not been lowered.
+    pub fn hir(&self) -> &hir::Program {
+        self.hir
+            .as_ref()
+            .expect("lowering to HIR must run before this pass")
+    }
+
+    // Returns the control flow graphs. Panics if they have not been built.
+    pub fn cfgs(&self) -> &[Cfg] {
+        self.cfgs
+            .as_ref()
+            .expect("the control flow graphs must be built before this pass")
+    }
+}
+
+// Diagnostics emitted to the context are reported at their lint's level.
+impl DiagnosticSink for Context<'_, '_> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        if let Some(diagnostic) = self.lints.apply(diagnostic) {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+}
+
+// An analysis or transformation over a program. Passes read what earlier
+// passes stored in the context and store their own results there.
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    // Whether the pass should be skipped when earlier passes have reported
+    // errors, because it needs a well-formed program.
+    fn needs_valid_program(&self) -> bool {
+        false
+    }
+
+    fn run(&mut self, context: &mut Context);
+}
+
+// Runs an ordered list of passes over a context.
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    pub fn new() -> PassManager {
+        PassManager::default()
+    }
+
+    // The semantic passes every program goes through: name resolution, type
+    // checking, which also evaluates constants, lowering to HIR, and constant
+    // propagation over each function's control flow graph, then the lint
+    // rules.
+    pub fn semantic() -> PassManager {
+        let mut manager = PassManager::new();
+        manager
+            .add(ResolvePass)
+            .add(TypeCheckPass)
+            .add(LowerPass)
+            .add(CfgPass)
+            .add(ConstantPropagationPass)
+            .add(LintPass);
+        manager
+    }
+
+    // Adds a pass to run after those already added.
+    pub fn add(&mut self, pass: impl Pass + 'static) -> &mut PassManager {
+        self.passes.push(Box::new(pass));
+        self
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    // Runs every pass in order, then sorts the diagnostics into source
+    // order. The sort is stable, so diagnostics at the same place keep the
+    // order their passes reported them in.
+    pub fn run(&mut self, context: &mut Context) {
+        for pass in &mut self.passes {
+            if pass.needs_valid_program() && context.has_errors() {
+                continue;
+            }
+            let mut timings = std::mem::take(&mut context.timings);
+            timings.time(pass.name(), || pass.run(context));
+            context.timings = timings;
+        }
+        context.diagnostics.sort_by_key(|d| d.span.start);
+    }
+}
+
+// Resolves names, reporting undeclared and unused ones.
+pub struct ResolvePass;
+
+impl Pass for ResolvePass {
+    fn name(&self) -> &'static str {
+        "resolve"
+    }
+
+    // A program linked from modules comes resolved by the loader, as its
+    // modules see only what they import.
+    fn run(&mut self, context: &mut Context) {
+        let resolution = match context.resolution.take() {
+            Some(resolution) => resolution,
+            None => resolve(context.program),
+        };
+        resolution.report(context);
+        context.resolution = Some(resolution);
+    }
+}
+
+// Evaluates constants and checks types.
+pub struct TypeCheckPass;
+
+impl Pass for TypeCheckPass {
+    fn name(&self) -> &'static str {
+        "typecheck"
+    }
+
+    fn run(&mut self, context: &mut Context) {
+        let check = typecheck(context.program, context.resolution());
+        check.report(context);
+        context.typecheck = Some(check);
+    }
+}
+
+// Builds the call graph.
+pub struct CallGraphPass;
+
+impl Pass for CallGraphPass {
+    fn name(&self) -> &'static str {
+        "call-graph"
+    }
+
+    fn run(&mut self, context: &mut Context) {
+        context.call_graph = Some(CallGraph::build(context.program, context.resolution()));
+    }
+}
+
+// Lowers the program to HIR. Only runs on programs without errors.
+pub struct LowerPass;
+
+impl Pass for LowerPass {
+    fn name(&self) -> &'static str {
+        "lower"
+    }
+
+    fn needs_valid_program(&self) -> bool {
+        true
+    }
+
+    fn run(&mut self, context: &mut Context) {
+        context.hir = Some(hir::lower(
+            context.program,
+            context.resolution(),
+            context.typecheck(),
+        ));
+    }
+}
+
+// Builds the control flow graph of every function. Needs HIR, so only runs on
+// programs without errors.
+pub struct CfgPass;
+
+impl Pass for CfgPass {
+    fn name(&self) -> &'static str {
+        "cfg"
+    }
+
+    fn needs_valid_program(&self) -> bool {
+        true
+    }
+
+    fn run(&mut self, context: &mut Context) {
+        context.cfgs = Some(cfg::build_all(context.hir()));
+    }
+}
+
+// Propagates constants through each control flow graph and reports
+// divisions that always divide by zero.
+pub struct ConstantPropagationPass;
+
+impl Pass for ConstantPropagationPass {
+    fn name(&self) -> &'static str {
+        "const-prop"
+    }
+
+    fn needs_valid_program(&self) -> bool {
+        true
+    }
+
+    fn run(&mut self, context: &mut Context) {
+        let mut diagnostics = vec![];
+        let constants = context
+            .cfgs()
+            .iter()
+            .map(|cfg| {
+                let constants = Constants::analyze(cfg);
+                constants.check(cfg, &mut diagnostics);
+                constants
+            })
+            .collect();
+        for diagnostic in diagnostics {
+            context.emit(diagnostic);
+        }
+        context.constants = Some(constants);
+    }
+}
+
+// Runs the lint rules that are not allowed. Rules that need an analysis
+// skip the program when it has not run, so the pass runs on programs with
+// errors too.
+pub struct LintPass;
+
+impl Pass for LintPass {
+    fn name(&self) -> &'static str {
+        "lint"
+    }
+
+    fn run(&mut self, context: &mut Context) {
+        let mut diagnostics = vec![];
+        for rule in &RULES {
+            if context.lints.level(rule.lint) != Level::Allow {
+                (rule.check)(context, &mut diagnostics);
+            }
+        }
+        for diagnostic in diagnostics {
+            context.emit(diagnostic);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    // Reports every recursive function, for a program that must not recurse.
+    struct NoRecursion;
+
+    impl Pass for NoRecursion {
+        fn name(&self) -> &'static str {
+            "no-recursion"
+        }
+
+        fn run(&mut self, context: &mut Context) {
+            let graph = context.call_graph.as_ref().unwrap();
+            for diagnostic in graph.check_recursion() {
+                context.emit(diagnostic);
+            }
+        }
+    }
+
+    #[test]
+    fn passes_run_in_order_and_share_the_context() {
+        let source = "fn f(n: int32) -> int32 { return f(n); } fn main() { f(1); }";
+        let map = SourceMap::new("main", source);
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+
+        let mut manager = PassManager::semantic();
+        assert_eq!(
+            manager.names(),
+            ["resolve", "typecheck", "lower", "cfg", "const-prop", "lint"]
+        );
+        let mut context = Context::new(&program, &map, &LintLevels::default());
+        manager.run(&mut context);
+        assert!(context.diagnostics.is_empty());
+        assert!(context.hir.is_some());
+
+        let mut manager = PassManager::new();
+        manager
+            .add(ResolvePass)
+            .add(CallGraphPass)
+            .add(NoRecursion)
+            .add(TypeCheckPass)
+            .add(LowerPass);
+        let mut context = Context::new(&program, &map, &LintLevels::default());
+        manager.run(&mut context);
+        let codes: Vec<&str> = context.diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, ["E0500"]);
+        assert!(context.typecheck.is_some());
+        assert!(context.hir.is_none());
+    }
+}
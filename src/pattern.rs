@@ -0,0 +1,462 @@
+use crate::{
+    ast::{
+        Block, Expression, Identifier, Program, Span, Statement, Symbol, Type, TypeExpr, TypeKind,
+    },
+    diagnostic::Diagnostic,
+    lexer::Lexer,
+    matcher::{ExpressionMatcher, StatementMatcher},
+    node::NodeRef,
+    parser::{Parser, ParserError},
+    token::{Kind, Token},
+    visit::{self, Control, Visitor},
+};
+use std::collections::HashMap;
+
+// A structural pattern compiled from source text, such as
+// `let _: int32 = $value;` or `$x * $x`.
+//
+// A pattern is ordinary source code in which `_` matches any identifier,
+// expression or type, and a metavariable such as `$value` matches anything
+// `_` would and records what it matched. A metavariable used more than once
+// must match structurally equal nodes each time. A function pattern without a
+// body matches declarations with or without one.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    node: PatternNode,
+}
+
+#[derive(Debug, Clone)]
+enum PatternNode {
+    Statement(Statement<'static>),
+    Expression(Expression<'static>),
+}
+
+// The nodes bound to a pattern's metavariables, keyed by name without the
+// leading '$'.
+pub type Bindings<'a> = HashMap<Symbol, NodeRef<'a>>;
+
+// A node matched by `Pattern::find_all`.
+#[derive(Debug, Clone)]
+pub struct PatternMatch<'a> {
+    pub node: NodeRef<'a>,
+    pub bindings: Bindings<'a>,
+}
+
+// Compiles a pattern. The pattern must be a single statement, or a single
+// expression without a trailing ';'.
+pub fn parse_pattern(pattern: &str) -> Result<Pattern, ParserError> {
+    let (mut program, is_expression) = match parse(pattern) {
+        Ok(program) => (program, false),
+        Err(error) => {
+            // Retry as an expression, keeping the original error if that
+            // does not parse either.
+            match parse(&format!("{};", pattern)) {
+                Ok(program) if is_single_expression(&program) => (program, true),
+                _ => return Err(error),
+            }
+        }
+    };
+    if program.statements.len() != 1 {
+        return Err(Box::new(Diagnostic::error(
+            "E0103",
+            format!(
+                "Expected a single statement or expression in pattern, got {}",
+                program.statements.len()
+            ),
+            Span::new(0, pattern.len()),
+        )));
+    }
+    let statement = program.statements.pop().unwrap();
+    let node = match statement {
+        Statement::Expression(expression) if is_expression => PatternNode::Expression(expression),
+        statement => PatternNode::Statement(statement),
+    };
+    Ok(Pattern { node })
+}
+
+fn is_single_expression(program: &Program) -> bool {
+    matches!(program.statements.as_slice(), [Statement::Expression(_)])
+}
+
+fn parse(pattern: &str) -> Result<Program<'static>, ParserError> {
+    let tokens = tokenize(pattern);
+    Ok(Parser::parse_program(&tokens)?.into_owned())
+}
+
+// Tokenizes a pattern. The lexer does not know about `_` and `$name`, so the
+// text between them is lexed separately and they are inserted as
+// identifiers.
+pub(crate) fn tokenize(pattern: &str) -> Vec<Token<'_>> {
+    let bytes = pattern.as_bytes();
+    let is_word = |i: usize| {
+        bytes
+            .get(i)
+            .is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_')
+    };
+    let mut tokens = vec![];
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let len = match bytes[i] {
+            b'_' if !i.checked_sub(1).is_some_and(is_word) && !is_word(i + 1) => 1,
+            b'$' if bytes
+                .get(i + 1)
+                .is_some_and(|b| b.is_ascii_alphabetic() || *b == b'_') =>
+            {
+                1 + (i + 1..bytes.len()).take_while(|j| is_word(*j)).count()
+            }
+            _ => 0,
+        };
+        if len == 0 {
+            i += 1;
+            continue;
+        }
+        if start < i {
+            let mut chunk = Lexer::tokenize_range(pattern, start..i);
+            chunk.pop();
+            tokens.extend(chunk);
+        }
+        tokens.push(Token::new(bytes, i, len, Kind::Identifier));
+        i += len;
+        start = i;
+    }
+    tokens.extend(Lexer::tokenize_range(pattern, start..bytes.len()));
+    tokens
+}
+
+impl Pattern {
+    // The statement a statement pattern is, or `None` for an expression
+    // pattern.
+    pub(crate) fn statement(&self) -> Option<&Statement<'static>> {
+        match &self.node {
+            PatternNode::Statement(statement) => Some(statement),
+            PatternNode::Expression(_) => None,
+        }
+    }
+
+    pub(crate) fn expression(&self) -> Option<&Expression<'static>> {
+        match &self.node {
+            PatternNode::Statement(_) => None,
+            PatternNode::Expression(expression) => Some(expression),
+        }
+    }
+
+    // Returns the metavariable bindings if the statement matches.
+    pub fn match_statement<'a>(&self, statement: &'a Statement<'a>) -> Option<Bindings<'a>> {
+        let PatternNode::Statement(pattern) = &self.node else {
+            return None;
+        };
+        let mut matching = Matching::default();
+        matching
+            .statement(pattern, statement)
+            .then_some(matching.bindings)
+    }
+
+    // Returns the metavariable bindings if the expression matches.
+    pub fn match_expression<'a>(&self, expression: &'a Expression<'a>) -> Option<Bindings<'a>> {
+        let PatternNode::Expression(pattern) = &self.node else {
+            return None;
+        };
+        let mut matching = Matching::default();
+        matching
+            .expression(pattern, expression)
+            .then_some(matching.bindings)
+    }
+
+    // Returns every statement or expression in the program that matches, in
+    // source order. Matches may be nested inside each other.
+    pub fn find_all<'a>(&self, program: &'a Program<'a>) -> Vec<PatternMatch<'a>> {
+        let mut finder = Finder {
+            pattern: self,
+            matches: vec![],
+        };
+        visit::walk_program(&mut finder, program);
+        finder.matches
+    }
+}
+
+impl StatementMatcher for Pattern {
+    fn matches(&self, statement: &Statement) -> bool {
+        self.match_statement(statement).is_some()
+    }
+}
+
+impl ExpressionMatcher for Pattern {
+    fn matches(&self, expression: &Expression) -> bool {
+        self.match_expression(expression).is_some()
+    }
+}
+
+struct Finder<'p, 'a> {
+    pattern: &'p Pattern,
+    matches: Vec<PatternMatch<'a>>,
+}
+
+impl<'a> Visitor<'a> for Finder<'_, 'a> {
+    fn visit_statement(&mut self, statement: &'a Statement<'a>) -> Control {
+        if let Some(bindings) = self.pattern.match_statement(statement) {
+            self.matches.push(PatternMatch {
+                node: NodeRef::Statement(statement),
+                bindings,
+            });
+        }
+        Control::Continue
+    }
+
+    fn visit_expression(&mut self, expression: &'a Expression<'a>) -> Control {
+        if let Some(bindings) = self.pattern.match_expression(expression) {
+            self.matches.push(PatternMatch {
+                node: NodeRef::Expression(expression),
+                bindings,
+            });
+        }
+        Control::Continue
+    }
+}
+
+// What a placeholder name in a pattern stands for.
+enum Placeholder<'p> {
+    Wildcard,
+    Metavariable(&'p str),
+}
+
+fn placeholder(name: &str) -> Option<Placeholder<'_>> {
+    match name {
+        "_" => Some(Placeholder::Wildcard),
+        _ => name.strip_prefix('$').map(Placeholder::Metavariable),
+    }
+}
+
+// The state of matching one pattern against one node.
+#[derive(Default)]
+struct Matching<'a> {
+    bindings: Bindings<'a>,
+}
+
+impl<'a> Matching<'a> {
+    // Binds a metavariable, or checks that a node is equal to the one it is
+    // already bound to.
+    fn bind(&mut self, name: &str, node: NodeRef<'a>) -> bool {
+        let name = Symbol::new(name);
+        let Some(bound) = self.bindings.get(&name).copied() else {
+            self.bindings.insert(name, node);
+            return true;
+        };
+        // Nodes parsed from source contain no placeholders, so matching one
+        // against the other compares them structurally.
+        let mut equal = Matching::default();
+        match (bound, node) {
+            (NodeRef::Identifier(a), NodeRef::Identifier(b)) => a.name == b.name,
+            (NodeRef::Expression(a), NodeRef::Expression(b)) => equal.expression(a, b),
+            (NodeRef::Type(a), NodeRef::Type(b)) => equal.ttype(a, b),
+            _ => false,
+        }
+    }
+
+    fn identifier(&mut self, pattern: &Identifier, identifier: &'a Identifier) -> bool {
+        match placeholder(&pattern.name) {
+            Some(Placeholder::Wildcard) => true,
+            Some(Placeholder::Metavariable(name)) => {
+                self.bind(name, NodeRef::Identifier(identifier))
+            }
+            None => pattern.name == identifier.name,
+        }
+    }
+
+    fn statement(&mut self, pattern: &Statement, statement: &'a Statement<'a>) -> bool {
+        match (pattern, statement) {
+            (Statement::Let(p), Statement::Let(s)) => {
+                p.mutable == s.mutable
+                    && self.identifier(&p.identifier, &s.identifier)
+                    && self.ttype(&p.ttype, &s.ttype)
+                    && self.expression(&p.expression, &s.expression)
+            }
+            (Statement::Const(p), Statement::Const(s)) => {
+                self.identifier(&p.identifier, &s.identifier)
+                    && self.ttype(&p.ttype, &s.ttype)
+                    && self.expression(&p.expression, &s.expression)
+            }
+            (Statement::FunctionDeclaration(p), Statement::FunctionDeclaration(s)) => {
+                self.identifier(&p.identifier, &s.identifier)
+                    && p.parameters.len() == s.parameters.len()
+                    && p.parameters.iter().zip(&s.parameters).all(|(p, s)| {
+                        self.identifier(&p.identifier, &s.identifier)
+                            && self.ttype(&p.ttype, &s.ttype)
+                    })
+                    && self.ttype(&p.return_type, &s.return_type)
+                    && match (&p.body, &s.body) {
+                        (None, _) => true,
+                        (Some(p), Some(s)) => self.block(p, s),
+                        (Some(_), None) => false,
+                    }
+            }
+            (Statement::Expression(p), Statement::Expression(s)) => self.expression(p, s),
+            (Statement::Return(p), Statement::Return(s)) => match (&p.expression, &s.expression) {
+                (Some(p), Some(s)) => self.expression(p, s),
+                (None, None) => true,
+                _ => false,
+            },
+            (Statement::If(p), Statement::If(s)) => {
+                self.expression(&p.condition, &s.condition)
+                    && self.block(&p.then_block, &s.then_block)
+                    && match (&p.else_branch, &s.else_branch) {
+                        (Some(p), Some(s)) => self.statement(p, s),
+                        (None, None) => true,
+                        _ => false,
+                    }
+            }
+            (Statement::While(p), Statement::While(s)) => {
+                self.expression(&p.condition, &s.condition) && self.block(&p.body, &s.body)
+            }
+            (Statement::Block(p), Statement::Block(s)) => self.block(p, s),
+            _ => false,
+        }
+    }
+
+    fn block(&mut self, pattern: &Block, block: &'a Block<'a>) -> bool {
+        pattern.statements.len() == block.statements.len()
+            && pattern
+                .statements
+                .iter()
+                .zip(&block.statements)
+                .all(|(p, s)| self.statement(p, s))
+    }
+
+    fn expression(&mut self, pattern: &Expression, expression: &'a Expression<'a>) -> bool {
+        if let Expression::Identifier(p) = pattern {
+            match placeholder(&p.name) {
+                Some(Placeholder::Wildcard) => return true,
+                Some(Placeholder::Metavariable(name)) => {
+                    return self.bind(name, NodeRef::Expression(expression))
+                }
+                None => {}
+            }
+        }
+        match (pattern, expression) {
+            (Expression::IntegerLiteral(p), Expression::IntegerLiteral(e)) => p.text == e.text,
+            (Expression::Identifier(p), Expression::Identifier(e)) => p.name == e.name,
+            (Expression::BinaryExpression(p), Expression::BinaryExpression(e)) => {
+                p.operator == e.operator
+                    && self.expression(&p.left, &e.left)
+                    && self.expression(&p.right, &e.right)
+            }
+            (Expression::Call(p), Expression::Call(e)) => {
+                self.expression(&p.callee, &e.callee)
+                    && p.arguments.len() == e.arguments.len()
+                    && p.arguments
+                        .iter()
+                        .zip(&e.arguments)
+                        .all(|(p, e)| self.expression(p, e))
+            }
+            (Expression::Cast(p), Expression::Cast(e)) => {
+                self.expression(&p.expression, &e.expression) && self.ttype(&p.ttype, &e.ttype)
+            }
+            _ => false,
+        }
+    }
+
+    fn ttype(&mut self, pattern: &TypeExpr, ttype: &'a TypeExpr<'a>) -> bool {
+        if let TypeExpr::Named(p) = pattern {
+            match placeholder_type(p) {
+                Some(Placeholder::Wildcard) => return true,
+                Some(Placeholder::Metavariable(name)) => {
+                    return self.bind(name, NodeRef::Type(ttype))
+                }
+                None => {}
+            }
+        }
+        match (pattern, ttype) {
+            (TypeExpr::Named(p), TypeExpr::Named(t)) => p.name == t.name,
+            (TypeExpr::Array(p), TypeExpr::Array(t)) => {
+                self.expression(&p.size, &t.size) && self.ttype(&p.element, &t.element)
+            }
+            (TypeExpr::Generic(p), TypeExpr::Generic(t)) => {
+                (placeholder_type(&p.base).is_some() || p.base.name == t.base.name)
+                    && self.types(&p.arguments, &t.arguments)
+            }
+            (TypeExpr::Tuple(p), TypeExpr::Tuple(t)) => self.types(p, t),
+            (TypeExpr::Function(p), TypeExpr::Function(t)) => {
+                self.types(&p.parameters, &t.parameters)
+                    && self.ttype(&p.return_type, &t.return_type)
+            }
+            _ => false,
+        }
+    }
+
+    fn types(&mut self, patterns: &[TypeExpr], types: &'a [TypeExpr<'a>]) -> bool {
+        patterns.len() == types.len() && patterns.iter().zip(types).all(|(p, t)| self.ttype(p, t))
+    }
+}
+
+fn placeholder_type(named: &Type) -> Option<Placeholder<'_>> {
+    match named.kind {
+        TypeKind::Named => placeholder(named.name.as_str()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(source: &str) -> Program<'static> {
+        Parser::parse_program(&Lexer::tokenize(source))
+            .unwrap()
+            .into_owned()
+    }
+
+    #[test]
+    fn wildcards_and_metavariables() {
+        let pattern = parse_pattern("let _ : int32 = $value;").unwrap();
+        let program = program("let x: int32 = a + 1; let y: int64 = 2; let mut z: int32 = 3;");
+        let bindings = pattern.match_statement(&program.statements[0]).unwrap();
+        let NodeRef::Expression(value) = bindings[&Symbol::new("value")] else {
+            panic!("Expected an expression binding");
+        };
+        assert_eq!(crate::printer::print_expression(value), "a + 1");
+        assert!(!StatementMatcher::matches(&pattern, &program.statements[1]));
+        assert!(!StatementMatcher::matches(&pattern, &program.statements[2]));
+    }
+
+    #[test]
+    fn repeated_metavariables_must_match_equal_nodes() {
+        let pattern = parse_pattern("$x * $x").unwrap();
+        let program = program("let a: int32 = f(1) * f(1) + b * c;");
+        let found = pattern.find_all(&program);
+        assert_eq!(found.len(), 1);
+        assert!(matches!(
+            found[0].node,
+            NodeRef::Expression(Expression::BinaryExpression(_))
+        ));
+        assert!(matches!(
+            found[0].bindings[&Symbol::new("x")],
+            NodeRef::Expression(Expression::Call(_))
+        ));
+    }
+
+    #[test]
+    fn patterns_match_types_and_nested_statements() {
+        let pattern = parse_pattern("fn $name(_: List<$t>) -> $t;").unwrap();
+        let program = program(
+            "fn first(xs: List<int32>) -> int32 { return 0; } fn bad(xs: List<int32>) -> int64;",
+        );
+        let found = pattern.find_all(&program);
+        assert_eq!(found.len(), 1);
+        let NodeRef::Identifier(name) = found[0].bindings[&Symbol::new("name")] else {
+            panic!("Expected an identifier binding");
+        };
+        assert_eq!(name.name, "first");
+
+        let pattern = parse_pattern("return _;").unwrap();
+        assert_eq!(pattern.find_all(&program).len(), 1);
+    }
+
+    #[test]
+    fn invalid_patterns_are_errors() {
+        assert!(parse_pattern("let _ = ;").is_err());
+        assert_eq!(
+            parse_pattern("a; b;").unwrap_err().message,
+            "Expected a single statement or expression in pattern, got 2"
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use crate::{
+    ast::Program,
+    bytecode,
+    diagnostic::Diagnostic,
+    hir,
+    lint::LintLevels,
+    resolver::Resolution,
+    session::{Checked, Session},
+    source_map::SourceMap,
+    typecheck::TypeCheck,
+};
+use std::fmt;
+
+// Compiling a source in one call, for programs that embed the language and
+// would rather not drive the phases themselves: `compile` lexes and parses
+// the source, resolves names, checks types and runs the lints, then lowers
+// the program as far as the options ask.
+
+// How far `compile` lowers a program after checking it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lowering {
+    // Stop once the program is checked.
+    None,
+    // Lower it to HIR, which the interpreter and the native back ends run.
+    #[default]
+    Hir,
+    // Lower it to HIR, and compile that to bytecode for the VM.
+    Bytecode,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    // The name diagnostics give the source.
+    pub name: String,
+    // The lint levels to start from. Lint attributes in the source
+    // override them.
+    pub lints: LintLevels,
+    pub lowering: Lowering,
+    // Whether the phases are timed for a report, which takes the `timings`
+    // feature. The source is then lexed before it is parsed rather than as
+    // it is parsed, so that the two are timed apart.
+    pub timings: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> CompileOptions {
+        CompileOptions {
+            name: "<source>".to_string(),
+            lints: LintLevels::default(),
+            lowering: Lowering::default(),
+            timings: false,
+        }
+    }
+}
+
+// A program that compiled without errors, and what compiling it found out.
+#[derive(Debug)]
+pub struct CompiledProgram {
+    pub source_map: SourceMap,
+    pub program: Program<'static>,
+    pub resolution: Resolution,
+    pub typecheck: TypeCheck,
+    // The lowered program, if the options asked for it.
+    pub hir: Option<hir::Program>,
+    pub bytecode: Option<bytecode::Module>,
+    // The warnings, in source order.
+    pub warnings: Vec<Diagnostic>,
+}
+
+// Why a program did not compile: its errors, and its warnings, in source
+// order, with the source they are about.
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    pub source_map: SourceMap,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.is_error())
+    }
+
+    // Renders every diagnostic against the source, separated by blank lines.
+    pub fn render(&self) -> String {
+        self.diagnostics
+            .iter()
+            .map(|d| d.render(&self.source_map))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Serializes every diagnostic as a line of JSON; see
+    // `Diagnostic::to_json`.
+    pub fn render_json(&self) -> String {
+        self.diagnostics
+            .iter()
+            .map(|d| d.to_json(&self.source_map) + "\n")
+            .collect()
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+// Compiles a source, returning the program or, if the source does not parse
+// or has errors, every diagnostic.
+pub fn compile(source: &str, options: CompileOptions) -> Result<CompiledProgram, Diagnostics> {
+    check(source, options).map(Unlowered::lower)
+}
+
+// A program that checked without errors, before it is lowered. Unlike
+// bytecode, which holds `Rc`s, it can be sent between threads.
+pub(crate) struct Unlowered {
+    session: Session,
+    program: Program<'static>,
+    checked: Checked,
+}
+
+// Parses and checks a source, as far as `compile` goes before lowering.
+pub(crate) fn check(source: &str, options: CompileOptions) -> Result<Unlowered, Diagnostics> {
+    let mut session = Session::new(source, options);
+    let Some(program) = session.parse() else {
+        return Err(session.into_diagnostics());
+    };
+    let checked = session.check(&program);
+    if session.has_errors() {
+        return Err(session.into_diagnostics());
+    }
+    Ok(Unlowered {
+        session,
+        program,
+        checked,
+    })
+}
+
+impl Unlowered {
+    // Lowers the program as far as the options ask.
+    pub(crate) fn lower(self) -> CompiledProgram {
+        let Unlowered {
+            mut session,
+            program,
+            checked:
+                Checked {
+                    resolution,
+                    typecheck,
+                    hir,
+                },
+        } = self;
+        // The semantic passes lower every program without errors.
+        let lowering = session.options.lowering;
+        let hir = match lowering {
+            Lowering::None => None,
+            Lowering::Hir | Lowering::Bytecode => hir,
+        };
+        let bytecode = match (lowering, &hir) {
+            (Lowering::Bytecode, Some(hir)) => {
+                Some(session.timings.time("bytecode", || bytecode::compile(hir)))
+            }
+            _ => None,
+        };
+        CompiledProgram {
+            warnings: session.take_diagnostics(),
+            source_map: session.source_map,
+            program,
+            resolution,
+            typecheck,
+            hir,
+            bytecode,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::TypeKind,
+        lint::{self, Level},
+        value::Value,
+        vm::Vm,
+    };
+
+    const SOURCE: &str = "\
+fn square(x: int32) -> int32 {
+    let unused: int32 = 1;
+    return x * x;
+}
+fn main() -> int32 {
+    return square(7);
+}
+";
+
+    #[test]
+    fn sources_compile_as_far_as_asked() {
+        let options = CompileOptions {
+            lowering: Lowering::Bytecode,
+            ..CompileOptions::default()
+        };
+        let compiled = compile(SOURCE, options).unwrap();
+        let warnings: Vec<&str> = compiled.warnings.iter().map(|w| w.code).collect();
+        assert_eq!(warnings, ["W0200"]);
+        assert!(compiled.hir.is_some());
+        let module = compiled.bytecode.unwrap();
+        let result = Vm::new(&module).call("main", vec![]).unwrap();
+        assert_eq!(result, Some(Value::Integer(TypeKind::Int32, 49)));
+
+        let compiled = compile(SOURCE, CompileOptions::default()).unwrap();
+        assert!(compiled.hir.is_some() && compiled.bytecode.is_none());
+        assert_eq!(compiled.program.statements.len(), 2);
+        let options = CompileOptions {
+            lowering: Lowering::None,
+            ..CompileOptions::default()
+        };
+        assert!(compile(SOURCE, options).unwrap().hir.is_none());
+    }
+
+    #[test]
+    fn errors_are_returned_with_their_source() {
+        let diagnostics = compile("fn f( {", CompileOptions::default()).unwrap_err();
+        assert_eq!(diagnostics.diagnostics.len(), 1);
+        assert!(diagnostics.render().contains("--> <source>:1:"));
+
+        let mut lints = LintLevels::new();
+        lints.set(&lint::UNUSED, Level::Deny);
+        let options = CompileOptions {
+            name: "main.my".to_string(),
+            lints,
+            lowering: Lowering::Bytecode,
+            timings: false,
+        };
+        let source = SOURCE.replace("return square(7)", "return square(x)");
+        let diagnostics = compile(&source, options).unwrap_err();
+        let errors: Vec<&str> = diagnostics.errors().map(|e| e.code).collect();
+        assert_eq!(errors, ["W0200", "E0200"]);
+        assert!(diagnostics
+            .to_string()
+            .contains("error[E0200]: use of undeclared variable `x`\n --> main.my:6:19"));
+        assert_eq!(diagnostics.render_json().lines().count(), 2);
+    }
+}
@@ -0,0 +1,568 @@
+use crate::{
+    ast::{
+        BinaryOperator, Block, Expression, FunctionDeclaration, IfStatement, LetStatement, Program,
+        Spanned, Statement, TypeExpr,
+    },
+    format::{BraceStyle, FormatOptions},
+};
+use std::borrow::Cow;
+
+// Regenerates mylang source from a program.
+//
+// The output parses back into an equivalent program. Comments other than doc
+// comments are not part of the AST and are not reproduced.
+pub fn print(program: &Program) -> String {
+    Printer::new(&FormatOptions::default(), None).program(program)
+}
+
+// Returns the source text of an expression.
+pub fn print_expression(expression: &Expression) -> String {
+    let mut output = String::new();
+    write_expression(&mut output, expression);
+    output
+}
+
+// Returns the source text of a type.
+pub fn print_type(ttype: &TypeExpr) -> String {
+    let mut output = String::new();
+    write_type(&mut output, ttype);
+    output
+}
+
+pub(crate) struct Printer<'s> {
+    output: String,
+    indent: usize,
+    options: &'s FormatOptions,
+    source: Option<Source<'s>>,
+}
+
+// The source a program was parsed from, when it is being formatted. Its
+// comments, which the AST does not have, are written from here instead,
+// along with the blank lines between statements.
+pub(crate) struct Source<'s> {
+    text: &'s str,
+    // The offset and text of each comment and doc comment, in order.
+    comments: &'s [(usize, &'s str)],
+    // The offset where what was last written ends.
+    end: usize,
+    // Whether nothing has been written yet in the current list of
+    // statements, which does not start with a blank line.
+    first: bool,
+}
+
+impl<'s> Source<'s> {
+    pub(crate) fn new(text: &'s str, comments: &'s [(usize, &'s str)]) -> Source<'s> {
+        Source {
+            text,
+            comments,
+            end: 0,
+            first: true,
+        }
+    }
+}
+
+impl<'s> Printer<'s> {
+    pub(crate) fn new(options: &'s FormatOptions, source: Option<Source<'s>>) -> Printer<'s> {
+        Printer {
+            output: String::new(),
+            indent: 0,
+            options,
+            source,
+        }
+    }
+
+    pub(crate) fn program(mut self, program: &Program) -> String {
+        self.statements(&program.statements);
+        if let Some(source) = &self.source {
+            self.comments(source.text.len());
+        }
+        self.output
+    }
+
+    // Starts a new line at the current indentation.
+    fn line(&mut self) {
+        for _ in 0..self.indent {
+            match self.options.hard_tabs {
+                true => self.output.push('\t'),
+                false => (0..self.options.indent_width).for_each(|_| self.output.push(' ')),
+            }
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            let span = statement.span();
+            if self.source.is_some() {
+                self.comments(span.start);
+                self.space(span.start);
+            }
+            self.statement(statement);
+            if let Some(source) = &mut self.source {
+                (source.end, source.first) = (span.end, false);
+            }
+        }
+    }
+
+    // Writes the comments of the source that come before `offset`. One on
+    // the same line as what was written before it stays there; the others
+    // get lines of their own.
+    fn comments(&mut self, offset: usize) {
+        while let Some(source) = &self.source {
+            let Some(&(start, text)) = source.comments.first().filter(|(start, _)| *start < offset)
+            else {
+                return;
+            };
+            let trailing = !source.text[source.end..start].contains('\n');
+            if trailing && self.output.ends_with('\n') {
+                self.output.pop();
+                self.output.push(' ');
+            } else {
+                self.space(start);
+                self.line();
+            }
+            self.output.push_str(text.trim_end());
+            self.output.push('\n');
+            let source = self.source.as_mut().unwrap();
+            source.comments = &source.comments[1..];
+            (source.end, source.first) = (start + text.len(), false);
+        }
+    }
+
+    // Keeps up to the allowed number of blank lines from the source before
+    // what is written at `offset`.
+    fn space(&mut self, offset: usize) {
+        let Some(source) = &self.source else {
+            return;
+        };
+        let newlines = source.text[source.end..offset].matches('\n').count();
+        if !source.first {
+            let blank_lines = newlines.saturating_sub(1).min(self.options.max_blank_lines);
+            for _ in 0..blank_lines {
+                self.output.push('\n');
+            }
+        }
+    }
+
+    fn docs(&mut self, docs: &[Cow<str>]) {
+        // Doc comments are among the source's comments when formatting.
+        if self.source.is_some() {
+            return;
+        }
+        for doc in docs {
+            self.line();
+            if doc.is_empty() {
+                self.output.push_str("##\n");
+            } else {
+                self.output.push_str("## ");
+                self.output.push_str(doc);
+                self.output.push('\n');
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let(let_statement) => self.let_statement(let_statement),
+            Statement::Const(constant) => {
+                self.docs(&constant.docs);
+                self.line();
+                self.output.push_str("const ");
+                self.output.push_str(&constant.identifier.name);
+                self.output.push_str(": ");
+                write_type(&mut self.output, &constant.ttype);
+                self.output.push_str(" = ");
+                write_expression(&mut self.output, &constant.expression);
+                self.output.push_str(";\n");
+            }
+            Statement::FunctionDeclaration(function) => self.function(function),
+            Statement::Expression(expression) => {
+                self.line();
+                write_expression(&mut self.output, expression);
+                self.output.push_str(";\n");
+            }
+            Statement::Return(return_statement) => {
+                self.line();
+                self.output.push_str("return");
+                if let Some(expression) = &return_statement.expression {
+                    self.output.push(' ');
+                    write_expression(&mut self.output, expression);
+                }
+                self.output.push_str(";\n");
+            }
+            Statement::If(if_statement) => {
+                self.line();
+                self.if_statement(if_statement);
+                self.output.push('\n');
+            }
+            Statement::While(while_statement) => {
+                self.line();
+                self.output.push_str("while ");
+                write_expression(&mut self.output, &while_statement.condition);
+                self.output.push(' ');
+                self.block(&while_statement.body);
+                self.output.push('\n');
+            }
+            Statement::Block(block) => {
+                self.line();
+                self.block(block);
+                self.output.push('\n');
+            }
+            Statement::Import(import) => {
+                self.line();
+                self.output.push_str("import ");
+                self.output.push_str(&import.module.name);
+                self.output.push_str(";\n");
+            }
+        }
+    }
+
+    // Writes an if statement, leaving the output after its last closing brace.
+    fn if_statement(&mut self, if_statement: &IfStatement) {
+        self.output.push_str("if ");
+        write_expression(&mut self.output, &if_statement.condition);
+        self.output.push(' ');
+        self.block(&if_statement.then_block);
+        match if_statement.else_branch.as_deref() {
+            Some(Statement::If(else_if)) => {
+                self.else_keyword();
+                self.if_statement(else_if);
+            }
+            Some(Statement::Block(block)) => {
+                self.else_keyword();
+                self.block(block);
+            }
+            Some(_) => unreachable!("else branches are blocks or if statements"),
+            None => {}
+        }
+    }
+
+    fn let_statement(&mut self, let_statement: &LetStatement) {
+        self.docs(&let_statement.docs);
+        self.line();
+        self.output.push_str("let ");
+        if let_statement.mutable {
+            self.output.push_str("mut ");
+        }
+        self.output.push_str(&let_statement.identifier.name);
+        self.output.push_str(": ");
+        write_type(&mut self.output, &let_statement.ttype);
+        self.output.push_str(" = ");
+        write_expression(&mut self.output, &let_statement.expression);
+        self.output.push_str(";\n");
+    }
+
+    fn function(&mut self, function: &FunctionDeclaration) {
+        self.docs(&function.docs);
+        if function.inline && self.source.is_none() {
+            self.line();
+            self.output.push_str("#[inline]\n");
+        }
+        self.line();
+        self.output.push_str("fn ");
+        self.output.push_str(&function.identifier.name);
+        self.output.push('(');
+        for (i, parameter) in function.parameters.iter().enumerate() {
+            if i > 0 {
+                self.output.push_str(", ");
+            }
+            self.output.push_str(&parameter.identifier.name);
+            self.output.push_str(": ");
+            write_type(&mut self.output, &parameter.ttype);
+        }
+        self.output.push(')');
+        if !function.return_type.is_unit() {
+            self.output.push_str(" -> ");
+            write_type(&mut self.output, &function.return_type);
+        }
+        match &function.body {
+            Some(body) => {
+                self.output.push(' ');
+                self.block(body);
+                self.output.push('\n');
+            }
+            None => self.output.push_str(";\n"),
+        }
+    }
+
+    // Writes a block, leaving the output after the closing brace.
+    fn block(&mut self, block: &Block) {
+        let line = &self.output[self.output.rfind('\n').map_or(0, |i| i + 1)..];
+        if self.options.brace_style == BraceStyle::NextLine && !line.trim().is_empty() {
+            // The space before the brace is already written.
+            self.output.pop();
+            self.output.push('\n');
+            self.line();
+        }
+        self.output.push_str("{\n");
+        if let Some(source) = &mut self.source {
+            (source.end, source.first) = (block.span.start + 1, true);
+        }
+        self.indent += 1;
+        self.statements(&block.statements);
+        if self.source.is_some() {
+            self.comments(block.span.end - 1);
+        }
+        self.indent -= 1;
+        self.line();
+        self.output.push('}');
+        if let Some(source) = &mut self.source {
+            (source.end, source.first) = (block.span.end, false);
+        }
+    }
+
+    // Starts an `else` after a block's closing brace.
+    fn else_keyword(&mut self) {
+        match self.options.brace_style {
+            BraceStyle::SameLine => self.output.push_str(" else "),
+            BraceStyle::NextLine => {
+                self.output.push('\n');
+                self.line();
+                self.output.push_str("else ");
+            }
+        }
+    }
+}
+
+fn write_expression(output: &mut String, expression: &Expression) {
+    match expression {
+        Expression::IntegerLiteral(literal) => output.push_str(&literal.text),
+        Expression::Identifier(identifier) => output.push_str(&identifier.name),
+        Expression::BinaryExpression(binary) => {
+            write_operand(output, &binary.left, &binary.operator, false);
+            output.push(' ');
+            output.push_str(operator_text(&binary.operator));
+            output.push(' ');
+            write_operand(output, &binary.right, &binary.operator, true);
+        }
+        Expression::Call(call) => {
+            if let Expression::BinaryExpression(_) | Expression::Cast(_) = call.callee.as_ref() {
+                output.push('(');
+                write_expression(output, &call.callee);
+                output.push(')');
+            } else {
+                write_expression(output, &call.callee);
+            }
+            output.push('(');
+            for (i, argument) in call.arguments.iter().enumerate() {
+                if i > 0 {
+                    output.push_str(", ");
+                }
+                write_expression(output, argument);
+            }
+            output.push(')');
+        }
+        Expression::Cast(cast) => {
+            // A cast binds more tightly than any binary operator.
+            if let Expression::BinaryExpression(_) = cast.expression.as_ref() {
+                output.push('(');
+                write_expression(output, &cast.expression);
+                output.push(')');
+            } else {
+                write_expression(output, &cast.expression);
+            }
+            output.push_str(" as ");
+            write_type(output, &cast.ttype);
+        }
+    }
+}
+
+// Writes an operand of a binary expression, adding parentheses when the
+// operand would otherwise bind differently.
+fn write_operand(
+    output: &mut String,
+    operand: &Expression,
+    parent: &BinaryOperator,
+    is_right: bool,
+) {
+    let needs_parentheses = match operand {
+        Expression::BinaryExpression(child) => {
+            let (child_precedence, parent_precedence) =
+                (child.operator.precedence(), parent.precedence());
+            child_precedence < parent_precedence
+                || (child_precedence == parent_precedence
+                    && is_right != parent.is_right_associative())
+        }
+        _ => false,
+    };
+    // A cast's type followed by `<` would take it as type arguments.
+    let needs_parentheses = needs_parentheses
+        || (!is_right && *parent == BinaryOperator::Less && ends_with_cast(operand));
+    if needs_parentheses {
+        output.push('(');
+        write_expression(output, operand);
+        output.push(')');
+    } else {
+        write_expression(output, operand);
+    }
+}
+
+// Returns true if the text of an expression ends with a cast's type.
+fn ends_with_cast(expression: &Expression) -> bool {
+    match expression {
+        Expression::Cast(_) => true,
+        Expression::BinaryExpression(binary) => ends_with_cast(&binary.right),
+        _ => false,
+    }
+}
+
+// Returns the source text of a binary operator.
+pub fn operator_text(operator: &BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Divide => "/",
+        BinaryOperator::Plus => "+",
+        BinaryOperator::Minus => "-",
+        BinaryOperator::Power => "**",
+        BinaryOperator::Star => "*",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::Less => "<",
+        BinaryOperator::LessEqual => "<=",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::GreaterEqual => ">=",
+    }
+}
+
+fn write_type_list(output: &mut String, types: &[TypeExpr]) {
+    for (i, ttype) in types.iter().enumerate() {
+        if i > 0 {
+            output.push_str(", ");
+        }
+        write_type(output, ttype);
+    }
+}
+
+fn write_type(output: &mut String, ttype: &TypeExpr) {
+    match ttype {
+        TypeExpr::Named(named) => output.push_str(&named.name),
+        TypeExpr::Array(array) => {
+            output.push('[');
+            write_type(output, &array.element);
+            output.push_str("; ");
+            write_expression(output, &array.size);
+            output.push(']');
+        }
+        TypeExpr::Generic(generic) => {
+            output.push_str(&generic.base.name);
+            output.push('<');
+            write_type_list(output, &generic.arguments);
+            output.push('>');
+        }
+        TypeExpr::Tuple(elements) => {
+            output.push('(');
+            write_type_list(output, elements);
+            if elements.len() == 1 {
+                output.push(',');
+            }
+            output.push(')');
+        }
+        TypeExpr::Function(function) => {
+            output.push_str("fn(");
+            write_type_list(output, &function.parameters);
+            output.push_str(") -> ");
+            write_type(output, &function.return_type);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    // Parses `input` and prints it back.
+    fn round_trip(input: &str) -> String {
+        let tokens = Lexer::tokenize(input);
+        let program = Parser::parse_program(&tokens).unwrap();
+        print(&program)
+    }
+
+    // Checks that `input` prints as `expected` and that the output is stable.
+    fn check_print(input: &str, expected: &str) {
+        let printed = round_trip(input);
+        assert_eq!(printed, expected);
+        assert_eq!(round_trip(&printed), expected);
+    }
+
+    #[test]
+    fn let_statements() {
+        check_print(
+            "let x:int32=5;let mut y : int32 = x;",
+            "let x: int32 = 5;\nlet mut y: int32 = x;\n",
+        );
+    }
+
+    #[test]
+    fn const_declarations() {
+        check_print(
+            "## Size.\nconst N:int64=2*3;let a: [int32; N+1] = b;",
+            "## Size.\nconst N: int64 = 2 * 3;\nlet a: [int32; N + 1] = b;\n",
+        );
+    }
+
+    #[test]
+    fn types() {
+        check_print(
+            "let t: (int32, ([float32; 2],), fn(List<int32>, ()) -> Map<string, bool>) = x;",
+            "let t: (int32, ([float32; 2],), fn(List<int32>, ()) -> Map<string, bool>) = x;\n",
+        );
+    }
+
+    #[test]
+    fn functions_with_docs_and_bodies() {
+        check_print(
+            "## Squares.\n##\nfn sq(x: int32) -> int32 { let y: int32 = x * x; return y; }\nfn log(msg: string);\nfn main() { return; }",
+            "## Squares.\n##\nfn sq(x: int32) -> int32 {\n    let y: int32 = x * x;\n    return y;\n}\nfn log(msg: string);\nfn main() {\n    return;\n}\n",
+        );
+    }
+
+    #[test]
+    fn inline_attributes() {
+        check_print(
+            "## Doubles.\n#[inline]\nfn double(x: int32) -> int32;",
+            "## Doubles.\n#[inline]\nfn double(x: int32) -> int32;\n",
+        );
+    }
+
+    #[test]
+    fn control_flow_and_calls() {
+        check_print(
+            "fn main() { if a < b { f(a, g(b)); } else if a == b { { h(); } } else { while x != 0 { (f)(x); } } }",
+            concat!(
+                "fn main() {\n",
+                "    if a < b {\n",
+                "        f(a, g(b));\n",
+                "    } else if a == b {\n",
+                "        {\n",
+                "            h();\n",
+                "        }\n",
+                "    } else {\n",
+                "        while x != 0 {\n",
+                "            f(x);\n",
+                "        }\n",
+                "    }\n",
+                "}\n",
+            ),
+        );
+    }
+
+    #[test]
+    fn casts() {
+        check_print(
+            "(a + b) as int64 * c as float32; (x as fn() -> int8)(); x as int8 as bool;",
+            "(a + b) as int64 * c as float32;\n(x as fn() -> int8)();\nx as int8 as bool;\n",
+        );
+        // Without parentheses, `int8 < b` would start type arguments.
+        check_print(
+            "(a as int8) < b; (a + b as int8) < c; a > b as int8;",
+            "(a as int8) < b;\n(a + b as int8) < c;\na > b as int8;\n",
+        );
+    }
+
+    #[test]
+    fn parentheses_are_kept_only_where_needed() {
+        check_print(
+            "(1 + 2) * 3; 1 + (2 * 3); (1 - 2) - 3; 1 - (2 - 3); (2 ** 3) ** 4; 2 ** (3 ** 4);",
+            "(1 + 2) * 3;\n1 + 2 * 3;\n1 - 2 - 3;\n1 - (2 - 3);\n(2 ** 3) ** 4;\n2 ** 3 ** 4;\n",
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use crate::pipeline::{check, CompileOptions, CompiledProgram, Diagnostics, Unlowered};
+
+// Compiling a project: many source files that do not import each other,
+// each compiled as `compile` would. With the `parallel` feature the files
+// are lexed, parsed and checked on a thread pool; lowering, whose bytecode
+// shares its values between threads no better than the VM does, is then
+// done on the calling thread. Either way the results are in the order
+// of the files, and each file's diagnostics in source order, so the output
+// does not depend on how the work was scheduled.
+
+#[derive(Debug)]
+pub struct CompiledProject {
+    // The name of each file and what compiling it gave, in the order the
+    // files were given.
+    pub files: Vec<(String, Result<CompiledProgram, Diagnostics>)>,
+}
+
+impl CompiledProject {
+    pub fn has_errors(&self) -> bool {
+        self.files.iter().any(|(_, result)| result.is_err())
+    }
+
+    // Renders the diagnostics of every file, file by file, separated by
+    // blank lines.
+    pub fn render(&self) -> String {
+        self.files
+            .iter()
+            .flat_map(|(_, result)| {
+                let (map, diagnostics) = match result {
+                    Ok(program) => (&program.source_map, &program.warnings),
+                    Err(diagnostics) => (&diagnostics.source_map, &diagnostics.diagnostics),
+                };
+                diagnostics.iter().map(|d| d.render(map))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// Compiles the files of a project, given by name and source, in parallel
+// with the `parallel` feature. The options apply to every file, each named
+// as given.
+pub fn compile_project(files: &[(&str, &str)], options: &CompileOptions) -> CompiledProject {
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        let checked = files
+            .par_iter()
+            .map(|file| check_file(file, options))
+            .collect();
+        lower(checked)
+    }
+    #[cfg(not(feature = "parallel"))]
+    compile_project_sequentially(files, options)
+}
+
+// Compiles the files of a project one after another on the calling thread.
+pub fn compile_project_sequentially(
+    files: &[(&str, &str)],
+    options: &CompileOptions,
+) -> CompiledProject {
+    let checked = files.iter().map(|file| check_file(file, options)).collect();
+    lower(checked)
+}
+
+fn check_file(
+    (name, source): &(&str, &str),
+    options: &CompileOptions,
+) -> (String, Result<Unlowered, Diagnostics>) {
+    let options = CompileOptions {
+        name: name.to_string(),
+        ..options.clone()
+    };
+    (name.to_string(), check(source, options))
+}
+
+fn lower(files: Vec<(String, Result<Unlowered, Diagnostics>)>) -> CompiledProject {
+    let files = files
+        .into_iter()
+        .map(|(name, result)| (name, result.map(Unlowered::lower)))
+        .collect();
+    CompiledProject { files }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Lowering;
+
+    fn files() -> Vec<(String, String)> {
+        (0..40)
+            .map(|i| {
+                let source = match i % 4 {
+                    0 => format!("fn f{i}(x: int32) -> int32 {{ return x * {i}; }}\n"),
+                    1 => format!("let unused: int8 = {i};\n"),
+                    2 => format!("fn main() {{ println(missing{i}); }}\n"),
+                    _ => format!("fn g(x: int64) -> int64 {{ return x + {i}; }}\nlet y: int64 = g({i});\nprintln(y);\n"),
+                };
+                (format!("file{i}.my"), source)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn projects_compile_file_by_file_in_order() {
+        let files = files();
+        let files: Vec<(&str, &str)> = files
+            .iter()
+            .map(|(name, source)| (name.as_str(), source.as_str()))
+            .collect();
+        let options = CompileOptions {
+            lowering: Lowering::Bytecode,
+            ..CompileOptions::default()
+        };
+        let project = compile_project(&files, &options);
+        let names: Vec<&str> = project
+            .files
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let given: Vec<&str> = files.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, given);
+        assert!(project.has_errors());
+        for (i, (_, result)) in project.files.iter().enumerate() {
+            match result {
+                Ok(program) => assert!(i % 4 != 2 && program.bytecode.is_some()),
+                Err(diagnostics) => {
+                    assert_eq!(i % 4, 2);
+                    assert_eq!(diagnostics.errors().count(), 1);
+                }
+            }
+        }
+        let rendered = project.render();
+        assert!(rendered.contains("--> file1.my:1:5"));
+        assert!(rendered.find("file1.my").unwrap() < rendered.find("file2.my").unwrap());
+        assert_eq!(
+            rendered,
+            compile_project_sequentially(&files, &options).render()
+        );
+    }
+}
@@ -0,0 +1,616 @@
+use crate::{
+    ast::{Expression, Program, Statement, TypeExpr},
+    matcher::{ExpressionMatcher, StatementMatcher, TypeMatcher},
+    node::NodeRef,
+    printer,
+    visit::{self, Control, Visitor},
+};
+use std::{collections::HashSet, fmt};
+
+pub use crate::matcher::{parse_pattern, MatchReport, Pattern};
+
+// Runs matchers over a whole program, for tools such as linters, codemods
+// and grading scripts. Each function returns the matching nodes at any
+// nesting depth, in source order.
+//
+// Nodes can also be found with path queries, which read like XPath:
+//
+//   fn[name=main]//let[mutable]
+//
+// finds the mutable `let` statements at any depth in the top-level functions
+// named `main`. A query is a list of steps, each taking the children (after
+// `/`) or the descendants (after `//`) of the nodes the step before found,
+// starting from the program. A step names a kind of node, or `*` for any,
+// followed by predicates in brackets that the nodes must satisfy:
+//
+//   [mutable]        the node has the attribute
+//   [!mutable]       it does not
+//   [name=main]      the attribute has that value, or `!=` for any other
+//   [.//call]        the path from the node finds something
+//   [`$x * $x`]      the statement or expression matches the pattern
+//
+// See `kind` for the names of the kinds of node, and `attribute` for their
+// attributes.
+
+pub fn find_statements<'a>(
+    program: &'a Program<'a>,
+    matcher: &dyn StatementMatcher,
+) -> Vec<&'a Statement<'a>> {
+    let mut finder = Finder {
+        matcher,
+        found: vec![],
+    };
+    visit::walk_program(&mut finder, program);
+    finder.found
+}
+
+pub fn find_expressions<'a>(
+    program: &'a Program<'a>,
+    matcher: &dyn ExpressionMatcher,
+) -> Vec<&'a Expression<'a>> {
+    let mut finder = Finder {
+        matcher,
+        found: vec![],
+    };
+    visit::walk_program(&mut finder, program);
+    finder.found
+}
+
+pub fn find_types<'a>(
+    program: &'a Program<'a>,
+    matcher: &dyn TypeMatcher,
+) -> Vec<&'a TypeExpr<'a>> {
+    let mut finder = Finder {
+        matcher,
+        found: vec![],
+    };
+    visit::walk_program(&mut finder, program);
+    finder.found
+}
+
+// Collects the nodes of one kind that a matcher accepts.
+struct Finder<'m, M: ?Sized, T> {
+    matcher: &'m M,
+    found: Vec<T>,
+}
+
+impl<'ast> Visitor<'ast> for Finder<'_, dyn StatementMatcher + '_, &'ast Statement<'ast>> {
+    fn visit_statement(&mut self, statement: &'ast Statement<'ast>) -> Control {
+        if self.matcher.matches(statement) {
+            self.found.push(statement);
+        }
+        Control::Continue
+    }
+}
+
+impl<'ast> Visitor<'ast> for Finder<'_, dyn ExpressionMatcher + '_, &'ast Expression<'ast>> {
+    fn visit_expression(&mut self, expression: &'ast Expression<'ast>) -> Control {
+        if self.matcher.matches(expression) {
+            self.found.push(expression);
+        }
+        Control::Continue
+    }
+}
+
+impl<'ast> Visitor<'ast> for Finder<'_, dyn TypeMatcher + '_, &'ast TypeExpr<'ast>> {
+    fn visit_type(&mut self, ttype: &'ast TypeExpr<'ast>) -> Control {
+        if self.matcher.matches(ttype) {
+            self.found.push(ttype);
+        }
+        Control::Continue
+    }
+}
+
+// A compiled path query.
+#[derive(Debug, Clone)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    // Whether the step takes descendants rather than children.
+    descendants: bool,
+    // The kind of node, or `None` for any.
+    kind: Option<String>,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone)]
+enum Predicate {
+    // The attribute is present, or absent if `negated`.
+    Has {
+        name: String,
+        negated: bool,
+    },
+    // The attribute is present and equal to the value, or different from it
+    // if `negated`.
+    Equals {
+        name: String,
+        value: String,
+        negated: bool,
+    },
+    Path(Query),
+    Pattern(Pattern),
+}
+
+// A query that does not parse, with the byte offset in it where it stops
+// making sense.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+// Finds the nodes a path query selects, in source order.
+pub fn query<'a>(program: &'a Program<'a>, query: &str) -> Result<Vec<NodeRef<'a>>, QueryError> {
+    Ok(Query::parse(query)?.find(program))
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Query, QueryError> {
+        let mut parser = QueryParser { text, position: 0 };
+        let query = parser.query()?;
+        match parser.peek() {
+            None => Ok(query),
+            Some(c) => Err(parser.error(format!("unexpected `{}`", c))),
+        }
+    }
+
+    pub fn find<'a>(&self, program: &'a Program<'a>) -> Vec<NodeRef<'a>> {
+        self.find_from(NodeRef::Program(program))
+    }
+
+    // Runs the query from `node` instead of the program.
+    pub fn find_from<'a>(&self, node: NodeRef<'a>) -> Vec<NodeRef<'a>> {
+        let mut nodes = vec![node];
+        for step in &self.steps {
+            let mut seen = HashSet::new();
+            let mut found = vec![];
+            for node in nodes {
+                let mut candidates = vec![];
+                if step.descendants {
+                    descendants(node, &mut candidates);
+                } else {
+                    candidates.extend(node.children());
+                }
+                // Nested nodes share descendants; each is found once, where
+                // the outermost finds it.
+                found.extend(
+                    candidates
+                        .into_iter()
+                        .filter(|candidate| step.accepts(*candidate))
+                        .filter(|candidate| seen.insert(identity(*candidate))),
+                );
+            }
+            nodes = found;
+        }
+        nodes
+    }
+}
+
+impl Step {
+    fn accepts(&self, node: NodeRef) -> bool {
+        self.kind.as_ref().is_none_or(|name| kind(node) == name)
+            && self.predicates.iter().all(|predicate| match predicate {
+                Predicate::Has { name, negated } => attribute(node, name).is_some() != *negated,
+                Predicate::Equals {
+                    name,
+                    value,
+                    negated,
+                } => attribute(node, name).is_some_and(|found| (found == *value) != *negated),
+                Predicate::Path(query) => !query.find_from(node).is_empty(),
+                Predicate::Pattern(pattern) => match node {
+                    NodeRef::Statement(statement) => StatementMatcher::matches(pattern, statement),
+                    NodeRef::Expression(expression) => {
+                        ExpressionMatcher::matches(pattern, expression)
+                    }
+                    _ => false,
+                },
+            })
+    }
+}
+
+// Adds the nodes below `node` in source order, parents before children.
+fn descendants<'a>(node: NodeRef<'a>, nodes: &mut Vec<NodeRef<'a>>) {
+    for child in node.children() {
+        nodes.push(child);
+        descendants(child, nodes);
+    }
+}
+
+// Tells nodes apart by kind and address: an expression and the identifier
+// it holds may be at the same address.
+fn identity(node: NodeRef) -> (u8, *const ()) {
+    match node {
+        NodeRef::Program(program) => (0, program as *const Program as *const ()),
+        NodeRef::Statement(statement) => (1, statement as *const Statement as *const ()),
+        NodeRef::Parameter(parameter) => (2, parameter as *const _ as *const ()),
+        NodeRef::Block(block) => (3, block as *const _ as *const ()),
+        NodeRef::Expression(expression) => (4, expression as *const Expression as *const ()),
+        NodeRef::Identifier(identifier) => (5, identifier as *const _ as *const ()),
+        NodeRef::Type(ttype) => (6, ttype as *const TypeExpr as *const ()),
+    }
+}
+
+// The name a query gives a node's kind. Statements are named after their
+// keywords, and every type is a `type`.
+pub fn kind(node: NodeRef) -> &'static str {
+    match node {
+        NodeRef::Program(_) => "program",
+        NodeRef::Statement(statement) => match statement {
+            Statement::Let(_) => "let",
+            Statement::Const(_) => "const",
+            Statement::FunctionDeclaration(_) => "fn",
+            Statement::Expression(_) => "expression-statement",
+            Statement::Return(_) => "return",
+            Statement::If(_) => "if",
+            Statement::While(_) => "while",
+            Statement::Block(_) => "block-statement",
+            Statement::Import(_) => "import",
+        },
+        NodeRef::Parameter(_) => "parameter",
+        NodeRef::Block(_) => "block",
+        NodeRef::Expression(expression) => match expression {
+            Expression::IntegerLiteral(_) => "integer",
+            Expression::Identifier(_) => "variable",
+            Expression::BinaryExpression(_) => "binary",
+            Expression::Call(_) => "call",
+            Expression::Cast(_) => "cast",
+        },
+        NodeRef::Identifier(_) => "identifier",
+        NodeRef::Type(_) => "type",
+    }
+}
+
+// The value of a node's attribute, or `None` if it does not have it. Flags
+// have the value `true` when set and are missing otherwise.
+//
+//   name      what declares or names the node: a `let`, `const`, `fn`,
+//             parameter, identifier or variable, the function a call calls
+//             by name, or a type as written
+//   type      the declared type of a `let`, `const` or parameter, the type
+//             of a cast, or what a `fn` returns
+//   mutable   a `let mut`
+//   inline    an `#[inline]` function
+//   body      a function with a body rather than a declaration
+//   op        a binary expression's operator, such as `*`
+//   value     an integer literal's text
+pub fn attribute(node: NodeRef, name: &str) -> Option<String> {
+    let flag = |set: bool| set.then(|| "true".to_string());
+    match (node, name) {
+        (NodeRef::Statement(statement), _) => match (statement, name) {
+            (Statement::Let(let_statement), "name") => {
+                Some(let_statement.identifier.name.to_string())
+            }
+            (Statement::Let(let_statement), "type") => {
+                Some(printer::print_type(&let_statement.ttype))
+            }
+            (Statement::Let(let_statement), "mutable") => flag(let_statement.mutable),
+            (Statement::Const(constant), "name") => Some(constant.identifier.name.to_string()),
+            (Statement::Const(constant), "type") => Some(printer::print_type(&constant.ttype)),
+            (Statement::FunctionDeclaration(function), "name") => {
+                Some(function.identifier.name.to_string())
+            }
+            (Statement::FunctionDeclaration(function), "type") => {
+                Some(printer::print_type(&function.return_type))
+            }
+            (Statement::FunctionDeclaration(function), "inline") => flag(function.inline),
+            (Statement::FunctionDeclaration(function), "body") => flag(function.body.is_some()),
+            _ => None,
+        },
+        (NodeRef::Parameter(parameter), "name") => Some(parameter.identifier.name.to_string()),
+        (NodeRef::Parameter(parameter), "type") => Some(printer::print_type(&parameter.ttype)),
+        (NodeRef::Identifier(identifier), "name") => Some(identifier.name.to_string()),
+        (NodeRef::Expression(expression), _) => match (expression, name) {
+            (Expression::Identifier(identifier), "name") => Some(identifier.name.to_string()),
+            (Expression::Call(call), "name") => match call.callee.as_ref() {
+                Expression::Identifier(callee) => Some(callee.name.to_string()),
+                _ => None,
+            },
+            (Expression::Cast(cast), "type") => Some(printer::print_type(&cast.ttype)),
+            (Expression::BinaryExpression(binary), "op") => {
+                Some(printer::operator_text(&binary.operator).to_string())
+            }
+            (Expression::IntegerLiteral(literal), "value") => Some(literal.text.to_string()),
+            _ => None,
+        },
+        (NodeRef::Type(ttype), "name") => Some(printer::print_type(ttype)),
+        _ => None,
+    }
+}
+
+struct QueryParser<'t> {
+    text: &'t str,
+    position: usize,
+}
+
+impl QueryParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn eat(&mut self, prefix: &str) -> bool {
+        let found = self.text[self.position..].starts_with(prefix);
+        if found {
+            self.position += prefix.len();
+        }
+        found
+    }
+
+    fn error(&self, message: impl Into<String>) -> QueryError {
+        QueryError {
+            offset: self.position,
+            message: message.into(),
+        }
+    }
+
+    // Reads a query, which may start with `/` or `//`.
+    fn query(&mut self) -> Result<Query, QueryError> {
+        let mut steps = vec![];
+        let mut descendants = self.separator().unwrap_or(false);
+        loop {
+            steps.push(self.step(descendants)?);
+            match self.separator() {
+                Some(next) => descendants = next,
+                None => return Ok(Query { steps }),
+            }
+        }
+    }
+
+    // Reads `/` or `//`, returning whether it was `//`.
+    fn separator(&mut self) -> Option<bool> {
+        if self.eat("//") {
+            Some(true)
+        } else if self.eat("/") {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    fn name(&mut self) -> &str {
+        let start = self.position;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            self.position += 1;
+        }
+        &self.text[start..self.position]
+    }
+
+    fn step(&mut self, descendants: bool) -> Result<Step, QueryError> {
+        let kind = if self.eat("*") {
+            None
+        } else {
+            match self.name() {
+                "" => return Err(self.error("expected the kind of a node")),
+                name => Some(name.to_string()),
+            }
+        };
+        let mut predicates = vec![];
+        while self.eat("[") {
+            predicates.push(self.predicate()?);
+            if !self.eat("]") {
+                return Err(self.error("expected `]`"));
+            }
+        }
+        Ok(Step {
+            descendants,
+            kind,
+            predicates,
+        })
+    }
+
+    fn predicate(&mut self) -> Result<Predicate, QueryError> {
+        if self.eat(".") {
+            if !matches!(self.peek(), Some('/')) {
+                return Err(self.error("expected `/` or `//`"));
+            }
+            return Ok(Predicate::Path(self.query()?));
+        }
+        if self.eat("`") {
+            let start = self.position;
+            let Some(length) = self.text[start..].find('`') else {
+                return Err(self.error("unclosed pattern"));
+            };
+            self.position += length + 1;
+            return match parse_pattern(&self.text[start..start + length]) {
+                Ok(pattern) => Ok(Predicate::Pattern(pattern)),
+                Err(error) => Err(QueryError {
+                    offset: start + error.span.start,
+                    message: format!("invalid pattern: {}", error.message),
+                }),
+            };
+        }
+        let absent = self.eat("!");
+        let name = self.name().to_string();
+        if name.is_empty() {
+            return Err(self.error("expected an attribute"));
+        }
+        let negated = if absent {
+            return Ok(Predicate::Has {
+                name,
+                negated: true,
+            });
+        } else if self.eat("!=") {
+            true
+        } else if self.eat("=") {
+            false
+        } else {
+            return Ok(Predicate::Has {
+                name,
+                negated: false,
+            });
+        };
+        // The value runs up to the closing bracket.
+        let start = self.position;
+        let Some(length) = self.text[start..].find(']') else {
+            self.position = self.text.len();
+            return Err(self.error("expected `]`"));
+        };
+        self.position += length;
+        Ok(Predicate::Equals {
+            name,
+            value: self.text[start..start + length].trim().to_string(),
+            negated,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // No glob import of `matcher`: the macros must work from any module, as
+    // they would in a downstream crate.
+    use super::*;
+    use crate::{ast::BinaryOperator, lexer::Lexer, matcher, parser::Parser, printer};
+
+    #[test]
+    fn queries_find_nodes_at_any_depth() {
+        let tokens = Lexer::tokenize(
+            "fn f(x: int32) -> int32 { let y: int32 = x * 2; if y > 1 { let z: int64 = y * y; } return y; }",
+        );
+        let program = Parser::parse_program(&tokens).unwrap();
+
+        let lets = find_statements(
+            &program,
+            crate::match_let_statement!("z", crate::match_type!(), crate::match_any_expression!())
+                .as_ref(),
+        );
+        assert_eq!(lets.len(), 1);
+
+        let products = matcher::binary_expression(
+            matcher::any_identifier(),
+            BinaryOperator::Star,
+            matcher::any(),
+        );
+        let found: Vec<String> = find_expressions(&program, products.as_ref())
+            .into_iter()
+            .map(printer::print_expression)
+            .collect();
+        assert_eq!(found, ["x * 2", "y * y"]);
+
+        let wide = crate::any_of!(matcher::named_type("int64"), crate::match_unit_type!());
+        assert_eq!(find_types(&program, wide.as_ref()).len(), 1);
+
+        let pattern = parse_pattern("return $value;").unwrap();
+        assert_eq!(find_statements(&program, &pattern).len(), 1);
+    }
+
+    #[test]
+    fn queries_can_be_limited_to_lines_and_functions() {
+        let source = "\
+fn helper() {
+    let a: int32 = 1;
+}
+fn main() {
+    let b: int32 = 2;
+    if b > 1 {
+        let c: int32 = 3;
+    }
+    let d: int32 = 4;
+}
+let e: int32 = 5;
+";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let names = |matcher: &dyn StatementMatcher| -> Vec<&str> {
+            find_statements(&program, matcher)
+                .into_iter()
+                .filter_map(|s| match s {
+                    Statement::Let(l) => Some(l.identifier.name.as_str()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let any_let = || parse_pattern("let $name: _ = _;").unwrap();
+
+        // Let statements in function main after line 6.
+        let query = matcher::within_function(
+            &program,
+            "main",
+            crate::match_within_lines!(source, 7.., Box::new(any_let())),
+        );
+        assert_eq!(names(query.as_ref()), ["c", "d"]);
+        assert_eq!(
+            names(matcher::within_function(&program, "main", Box::new(any_let())).as_ref()),
+            ["b", "c", "d"]
+        );
+        assert_eq!(
+            names(matcher::within_lines(source, 2..=5, Box::new(any_let())).as_ref()),
+            ["a", "b"]
+        );
+        assert!(
+            names(matcher::within_function(&program, "none", Box::new(any_let())).as_ref())
+                .is_empty()
+        );
+        let first_line = matcher::within_lines(source, ..=1, Box::new(any_let()));
+        assert_eq!(
+            StatementMatcher::explain(first_line.as_ref(), &program.statements[2]).to_string(),
+            "at span: expected a node within 0..14, got a node at 143..160"
+        );
+    }
+
+    #[test]
+    fn path_queries_select_nodes() {
+        let source = "\
+fn main() -> int32 {
+    let mut a: int32 = 1;
+    let b: int64 = square(a) as int64;
+    while a < 10 {
+        let mut c: int32 = a * a;
+    }
+    return a;
+}
+fn other() {
+    let mut d: int32 = 2;
+}
+#[inline]
+fn square(x: int32) -> int32 { return x * x; }
+";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let names = |text: &str| -> Vec<String> {
+            query(&program, text)
+                .unwrap()
+                .into_iter()
+                .map(|node| attribute(node, "name").unwrap_or_else(|| kind(node).to_string()))
+                .collect()
+        };
+
+        assert_eq!(names("fn[name=main]//let[mutable]"), ["a", "c"]);
+        assert_eq!(names("//let[mutable]"), ["a", "c", "d"]);
+        assert_eq!(names("fn[name=main]/block/let[!mutable]"), ["b"]);
+        assert_eq!(names("//let[type!=int32]"), ["b"]);
+        assert_eq!(names("fn[inline]/parameter"), ["x"]);
+        assert_eq!(names("fn[.//call[name=square]]"), ["main"]);
+        assert_eq!(names("//binary[op=*]/variable"), ["a", "a", "x", "x"]);
+        assert_eq!(names("//*[`$x * $x`]"), ["binary", "binary"]);
+        assert_eq!(names("//while/*"), ["binary", "block"]);
+        // Nested blocks find what they share once.
+        assert_eq!(names("//block//let").len(), 4);
+        assert!(names("let").is_empty());
+
+        for (text, offset, message) in [
+            ("", 0, "expected the kind of a node"),
+            ("fn[name=main", 12, "expected `]`"),
+            ("fn[.call]", 4, "expected `/` or `//`"),
+            ("fn]", 2, "unexpected `]`"),
+        ] {
+            let error = Query::parse(text).unwrap_err();
+            assert_eq!(
+                (error.offset, error.message.as_str()),
+                (offset, message),
+                "{}",
+                text
+            );
+        }
+    }
+}
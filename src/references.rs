@@ -0,0 +1,184 @@
+use crate::{
+    ast::{Identifier, NodeId, Program, Span},
+    builtin::Builtin,
+    lexer::Lexer,
+    parser::Parser,
+    resolver::{resolve, DeclarationKind, Resolution},
+    visit::{self, Control, Visitor},
+};
+
+// Finds every place a name is declared or used, for editors to highlight
+// and rename them and for lints that need to know how a name is used.
+
+// How a reference uses its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Access {
+    // Declares a function or a parameter, whose value comes from elsewhere.
+    Declaration,
+    // Reads the name's value, or calls the function it names.
+    Read,
+    // Declares a `let` binding or a constant and writes its value.
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    // The span of the identifier.
+    pub span: Span,
+    pub access: Access,
+}
+
+// Returns the references to the name at a byte offset of a file, in source
+// order. The offset may be anywhere in the name or just after it, as a
+// cursor at its end is. Finds nothing if the file does not parse, or if
+// there is no name at the offset or it does not resolve.
+pub fn references(source: &str, offset: usize) -> Vec<Reference> {
+    let tokens = Lexer::tokenize(source);
+    match Parser::parse_program(&tokens) {
+        Ok(program) => find_references(&program, &resolve(&program), offset),
+        Err(_) => vec![],
+    }
+}
+
+// Like `references`, for a program that has been parsed and resolved.
+pub fn find_references(
+    program: &Program,
+    resolution: &Resolution,
+    offset: usize,
+) -> Vec<Reference> {
+    let mut collector = Identifiers::default();
+    visit::walk_program(&mut collector, program);
+    let identifiers = collector.identifiers;
+    let Some(target) = identifiers
+        .iter()
+        .find(|identifier| identifier.span.start <= offset && offset <= identifier.span.end)
+        .and_then(|identifier| Target::of(identifier, resolution))
+    else {
+        return vec![];
+    };
+    identifiers
+        .iter()
+        .filter(|identifier| Target::of(identifier, resolution) == Some(target))
+        .map(|identifier| Reference {
+            span: identifier.span,
+            access: access(identifier, resolution),
+        })
+        .collect()
+}
+
+// What a name refers to: a declaration, by the id of its identifier, or a
+// built-in function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Declaration(NodeId),
+    Builtin(Builtin),
+}
+
+impl Target {
+    fn of(identifier: &Identifier, resolution: &Resolution) -> Option<Target> {
+        if resolution.declarations.get(identifier.id).is_some() {
+            return Some(Target::Declaration(identifier.id));
+        }
+        if let Some(builtin) = resolution.builtins.get(identifier.id) {
+            return Some(Target::Builtin(*builtin));
+        }
+        resolution
+            .uses
+            .get(identifier.id)
+            .map(|declaration| Target::Declaration(*declaration))
+    }
+}
+
+fn access(identifier: &Identifier, resolution: &Resolution) -> Access {
+    match resolution.declarations.get(identifier.id).map(|d| d.kind) {
+        Some(DeclarationKind::Variable { .. } | DeclarationKind::Constant) => Access::Write,
+        Some(DeclarationKind::Function | DeclarationKind::Parameter) => Access::Declaration,
+        None => Access::Read,
+    }
+}
+
+// Collects every identifier of a program in source order.
+#[derive(Default)]
+struct Identifiers<'ast> {
+    identifiers: Vec<&'ast Identifier>,
+}
+
+impl<'ast> Visitor<'ast> for Identifiers<'ast> {
+    fn visit_identifier(&mut self, identifier: &'ast Identifier) -> Control {
+        self.identifiers.push(identifier);
+        Control::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::SourceMap;
+
+    // Returns the line and column of each reference to the name at the
+    // first occurrence of `at` in the source, and its access.
+    fn find(source: &str, at: &str) -> Vec<(usize, usize, Access)> {
+        let map = SourceMap::new("main", source);
+        references(source, source.find(at).unwrap())
+            .into_iter()
+            .map(|reference| {
+                let (line, column) = map.location(reference.span.start);
+                (line, column, reference.access)
+            })
+            .collect()
+    }
+
+    const SOURCE: &str = "\
+const N: int32 = 2;
+fn f(n: int32) -> int32 {
+    let m: int32 = n * N;
+    if m > N {
+        let m: int32 = m + 1;
+        return f(m);
+    }
+    println(m);
+    println(n);
+    return m;
+}
+";
+
+    #[test]
+    fn references_are_found_from_any_of_them() {
+        use Access::*;
+        let n = [(2, 6, Declaration), (3, 20, Read), (9, 13, Read)];
+        assert_eq!(find(SOURCE, "n: int32"), n);
+        assert_eq!(find(SOURCE, "n);"), n);
+        assert_eq!(
+            find(SOURCE, "N;"),
+            [(1, 7, Write), (3, 24, Read), (4, 12, Read)]
+        );
+        assert_eq!(find(SOURCE, "f(m)"), [(2, 4, Declaration), (6, 16, Read)]);
+        assert_eq!(find(SOURCE, "println"), [(8, 5, Read), (9, 5, Read)]);
+    }
+
+    #[test]
+    fn shadowed_names_are_told_apart() {
+        use Access::*;
+        let outer = [
+            (3, 9, Write),
+            (4, 8, Read),
+            (5, 24, Read),
+            (8, 13, Read),
+            (10, 12, Read),
+        ];
+        assert_eq!(find(SOURCE, "m: int32 = n"), outer);
+        assert_eq!(find(SOURCE, "m > N"), outer);
+        assert_eq!(find(SOURCE, "m);\n    }"), [(5, 13, Write), (6, 18, Read)]);
+    }
+
+    #[test]
+    fn the_cursor_may_be_just_after_a_name() {
+        let offset = SOURCE.find("N:").unwrap() + 1;
+        assert_eq!(references(SOURCE, offset).len(), 3);
+        let offset = SOURCE.find(" int32").unwrap();
+        assert!(references(SOURCE, offset).is_empty());
+        assert!(references(SOURCE, SOURCE.find("->").unwrap()).is_empty());
+        assert!(references("let x = ;", 4).is_empty());
+        assert!(references("println(y);", 8).is_empty());
+    }
+}
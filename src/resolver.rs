@@ -0,0 +1,798 @@
+use crate::{
+    ast::{
+        Block, Expression, FunctionDeclaration, Identifier, NodeId, NodeMap, Program, Span,
+        Statement, Symbol, TypeExpr, TypeKind,
+    },
+    builtin::Builtin,
+    diagnostic::{Diagnostic, DiagnosticSink},
+    suggest,
+    token::KEYWORDS,
+};
+use std::collections::{HashMap, HashSet};
+
+// What kind of binding a declaration introduces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclarationKind {
+    Variable { mutable: bool },
+    Constant,
+    Parameter,
+    Function,
+}
+
+// A name introduced by a `let`, a `const`, a parameter or a function
+// declaration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Declaration {
+    pub name: Symbol,
+    pub kind: DeclarationKind,
+    // The span of the declaring identifier.
+    pub span: Span,
+}
+
+// Identifies a scope in a resolution's scope tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScopeId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeKind {
+    Program,
+    // Holds a function's parameters. Its body is a nested block scope, so
+    // the body can shadow a parameter.
+    Function,
+    Block,
+    // Holds the names a module imports from others, around its program
+    // scope.
+    Imports,
+}
+
+// A region of the program in which names declared there are visible.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    // The enclosing scope; `None` only for the outermost scope, which is the
+    // program scope unless the program imports names.
+    pub parent: Option<ScopeId>,
+    // The names declared directly in this scope. A name declared twice maps
+    // to its last declaration.
+    pub names: HashMap<Symbol, NodeId>,
+}
+
+// The result of name resolution.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    // Every declaration, keyed by the id of its declaring identifier.
+    pub declarations: NodeMap<Declaration>,
+    // For every identifier use that was resolved, the id of the declaring
+    // identifier it refers to.
+    pub uses: NodeMap<NodeId>,
+    // For every identifier use that refers to a builtin function, which one.
+    pub builtins: NodeMap<Builtin>,
+    // The scope tree, indexed by `ScopeId`. The program scope comes first.
+    pub scopes: Vec<Scope>,
+    // The scope each declaration was made in.
+    pub declaration_scopes: NodeMap<ScopeId>,
+    // Uses of undeclared names and unknown types, and names declared twice
+    // in one scope.
+    pub errors: Vec<Diagnostic>,
+    // Unused `let` bindings, constants and functions, and `let` bindings
+    // shadowing another in the same scope, in source order.
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Resolution {
+    // Returns the declaration an identifier use refers to.
+    pub fn declaration_of(&self, identifier: &Identifier) -> Option<&Declaration> {
+        self.uses
+            .get(identifier.id)
+            .and_then(|id| self.declarations.get(*id))
+    }
+
+    // Emits the errors and then the warnings.
+    pub fn report(&self, sink: &mut dyn DiagnosticSink) {
+        for diagnostic in self.errors.iter().chain(&self.warnings) {
+            sink.emit(diagnostic.clone());
+        }
+    }
+
+    pub fn scope(&self, id: ScopeId) -> &Scope {
+        &self.scopes[id.0 as usize]
+    }
+
+    // Returns the names visible in a scope, innermost first. A name shadowed
+    // by an inner scope is only returned once.
+    pub fn visible_names(&self, mut scope: ScopeId) -> Vec<Symbol> {
+        let mut names = vec![];
+        loop {
+            let current = self.scope(scope);
+            let mut declared: Vec<(NodeId, Symbol)> = current
+                .names
+                .iter()
+                .map(|(name, id)| (*id, name.clone()))
+                .collect();
+            declared.sort_by_key(|(id, _)| *id);
+            for (_, name) in declared {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            match current.parent {
+                Some(parent) => scope = parent,
+                None => return names,
+            }
+        }
+    }
+
+    // Returns the declaration `name` refers to in a scope, looking outwards
+    // through enclosing scopes.
+    pub fn lookup(&self, mut scope: ScopeId, name: &Symbol) -> Option<NodeId> {
+        loop {
+            let current = self.scope(scope);
+            if let Some(id) = current.names.get(name) {
+                return Some(*id);
+            }
+            scope = current.parent?;
+        }
+    }
+}
+
+// Links every identifier use in a program to its declaration.
+//
+// Top-level functions are visible throughout the program, so they can be
+// called before they are declared and can be recursive. Other names are
+// visible from the statement after their declaration until the end of the
+// enclosing block, function or program.
+//
+// A declaration in an inner scope shadows one of the same name in an outer
+// scope. Within one scope, a `let` may shadow an earlier `let`, but gets a
+// warning unless its name starts with an underscore; declaring any other
+// name twice in the same scope is an error. Both point at the original
+// declaration.
+//
+// Uses of undeclared names and types that are not primitives are errors,
+// with a suggestion if a visible name, keyword or primitive type is spelled
+// similarly. Generic types are not checked.
+//
+// A `let` binding or a function with a body that is never referred to gets a
+// warning, unless its name starts with an underscore. `main` and functions
+// declared without a body are used from outside the program, so they never
+// get one. A function that is only called from its own body is unused.
+pub fn resolve(program: &Program) -> Resolution {
+    resolve_with_imports(program, &[])
+}
+
+// Like `resolve`, for a module that imports names declared in other modules,
+// each given as the id of its declaring identifier and its declaration. The
+// imported names are visible throughout the program, from a scope around the
+// program scope, so the program's own declarations shadow them. They are
+// never reported as unused, and their spans are in the modules that declare
+// them.
+pub fn resolve_with_imports(program: &Program, imports: &[(NodeId, Declaration)]) -> Resolution {
+    let mut resolver = Resolver {
+        scope: ScopeId(0),
+        resolution: Resolution::default(),
+        checked: vec![],
+        functions: vec![],
+        recursive_uses: HashSet::new(),
+    };
+    resolver.resolution.scopes.push(Scope {
+        kind: ScopeKind::Program,
+        parent: None,
+        names: HashMap::new(),
+    });
+    if !imports.is_empty() {
+        let scope = ScopeId(1);
+        resolver.resolution.scopes[0].parent = Some(scope);
+        let mut names = HashMap::new();
+        for (id, declaration) in imports {
+            names.insert(declaration.name.clone(), *id);
+            resolver.resolution.declaration_scopes.insert(*id, scope);
+            resolver
+                .resolution
+                .declarations
+                .insert(*id, declaration.clone());
+        }
+        resolver.resolution.scopes.push(Scope {
+            kind: ScopeKind::Imports,
+            parent: None,
+            names,
+        });
+    }
+    for statement in &program.statements {
+        if let Statement::FunctionDeclaration(function) = statement {
+            resolver.declare_function(function);
+        }
+    }
+    for statement in &program.statements {
+        resolver.statement(statement, true);
+    }
+    resolver.report_unused();
+    resolver.resolution
+}
+
+struct Resolver {
+    // The innermost scope at the current point of the walk.
+    scope: ScopeId,
+    resolution: Resolution,
+    // The declarations to warn about if they turn out to be unused.
+    checked: Vec<NodeId>,
+    // The declarations of the functions enclosing the current point.
+    functions: Vec<NodeId>,
+    // Uses of a function from within its own body.
+    recursive_uses: HashSet<NodeId>,
+}
+
+impl Resolver {
+    // Runs `f` in a new scope nested in the current one.
+    fn in_scope(&mut self, kind: ScopeKind, f: impl FnOnce(&mut Resolver)) {
+        let outer = self.scope;
+        self.scope = ScopeId(self.resolution.scopes.len() as u32);
+        self.resolution.scopes.push(Scope {
+            kind,
+            parent: Some(outer),
+            names: HashMap::new(),
+        });
+        f(self);
+        self.scope = outer;
+    }
+
+    fn declare(&mut self, identifier: &Identifier, kind: DeclarationKind) {
+        let previous = self.resolution.scopes[self.scope.0 as usize]
+            .names
+            .insert(identifier.name.clone(), identifier.id);
+        if let Some(previous) = previous {
+            self.redeclared(identifier, kind, previous);
+        }
+        self.resolution
+            .declaration_scopes
+            .insert(identifier.id, self.scope);
+        self.resolution.declarations.insert(
+            identifier.id,
+            Declaration {
+                name: identifier.name.clone(),
+                kind,
+                span: identifier.span,
+            },
+        );
+    }
+
+    // Reports a declaration of a name already declared in the same scope.
+    fn redeclared(&mut self, identifier: &Identifier, kind: DeclarationKind, previous: NodeId) {
+        let previous = self.resolution.declarations.get(previous).unwrap();
+        let both_variables = matches!(
+            (previous.kind, kind),
+            (
+                DeclarationKind::Variable { .. },
+                DeclarationKind::Variable { .. }
+            )
+        );
+        if !both_variables {
+            self.resolution.errors.push(
+                Diagnostic::error(
+                    "E0201",
+                    format!("`{}` is already declared in this scope", identifier.name),
+                    identifier.span,
+                )
+                .with_label(previous.span, "first declared here"),
+            );
+        } else if !identifier.name.as_str().starts_with('_') {
+            self.resolution.warnings.push(
+                Diagnostic::warning(
+                    "W0201",
+                    format!("`{}` shadows a binding in the same scope", identifier.name),
+                    identifier.span,
+                )
+                .with_label(previous.span, "shadowed binding declared here")
+                .with_note("the shadowed binding can no longer be used"),
+            );
+        }
+    }
+
+    fn declare_function(&mut self, function: &FunctionDeclaration) {
+        self.declare(&function.identifier, DeclarationKind::Function);
+        if function.body.is_some() && function.identifier.name != "main" {
+            self.check_unused(&function.identifier);
+        }
+    }
+
+    fn check_unused(&mut self, identifier: &Identifier) {
+        if !identifier.name.as_str().starts_with('_') {
+            self.checked.push(identifier.id);
+        }
+    }
+
+    fn report_unused(&mut self) {
+        let used: HashSet<NodeId> = self
+            .resolution
+            .uses
+            .iter()
+            .filter(|(id, _)| !self.recursive_uses.contains(id))
+            .map(|(_, declaration)| *declaration)
+            .collect();
+        for id in &self.checked {
+            if used.contains(id) {
+                continue;
+            }
+            let declaration = self.resolution.declarations.get(*id).unwrap();
+            let what = match declaration.kind {
+                DeclarationKind::Function => "function",
+                DeclarationKind::Constant => "constant",
+                _ => "variable",
+            };
+            self.resolution.warnings.push(
+                Diagnostic::warning(
+                    "W0200",
+                    format!("unused {} `{}`", what, declaration.name),
+                    declaration.span,
+                )
+                .with_suggestion(
+                    "if this is intentional, prefix it with an underscore",
+                    declaration.span,
+                    format!("_{}", declaration.name),
+                ),
+            );
+        }
+        self.resolution.warnings.sort_by_key(|w| w.span.start);
+    }
+
+    fn statement(&mut self, statement: &Statement, top_level: bool) {
+        match statement {
+            Statement::Let(let_statement) => {
+                self.ttype(&let_statement.ttype);
+                // The initializer cannot refer to the name being declared.
+                self.expression(&let_statement.expression);
+                let kind = DeclarationKind::Variable {
+                    mutable: let_statement.mutable,
+                };
+                self.declare(&let_statement.identifier, kind);
+                self.check_unused(&let_statement.identifier);
+            }
+            Statement::Const(constant) => {
+                self.ttype(&constant.ttype);
+                self.expression(&constant.expression);
+                self.declare(&constant.identifier, DeclarationKind::Constant);
+                self.check_unused(&constant.identifier);
+            }
+            Statement::FunctionDeclaration(function) => {
+                if !top_level {
+                    self.declare_function(function);
+                }
+                self.function(function);
+            }
+            Statement::Expression(expression) => self.expression(expression),
+            Statement::Return(return_statement) => {
+                if let Some(expression) = &return_statement.expression {
+                    self.expression(expression);
+                }
+            }
+            Statement::If(if_statement) => {
+                self.expression(&if_statement.condition);
+                self.block(&if_statement.then_block);
+                if let Some(else_branch) = &if_statement.else_branch {
+                    self.statement(else_branch, false);
+                }
+            }
+            Statement::While(while_statement) => {
+                self.expression(&while_statement.condition);
+                self.block(&while_statement.body);
+            }
+            Statement::Block(block) => self.block(block),
+            // The loader resolves imports before this pass runs.
+            Statement::Import(_) => {}
+        }
+    }
+
+    fn function(&mut self, function: &FunctionDeclaration) {
+        self.functions.push(function.identifier.id);
+        self.in_scope(ScopeKind::Function, |resolver| {
+            for parameter in &function.parameters {
+                resolver.ttype(&parameter.ttype);
+            }
+            resolver.ttype(&function.return_type);
+            for parameter in &function.parameters {
+                resolver.declare(&parameter.identifier, DeclarationKind::Parameter);
+            }
+            if let Some(body) = &function.body {
+                resolver.block(body);
+            }
+        });
+        self.functions.pop();
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.in_scope(ScopeKind::Block, |resolver| {
+            for statement in &block.statements {
+                resolver.statement(statement, false);
+            }
+        });
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::IntegerLiteral(_) => {}
+            Expression::Identifier(identifier) => self.use_identifier(identifier),
+            Expression::BinaryExpression(binary) => {
+                self.expression(&binary.left);
+                self.expression(&binary.right);
+            }
+            Expression::Call(call) => {
+                self.expression(&call.callee);
+                for argument in &call.arguments {
+                    self.expression(argument);
+                }
+            }
+            Expression::Cast(cast) => {
+                self.expression(&cast.expression);
+                self.ttype(&cast.ttype);
+            }
+        }
+    }
+
+    // Resolves the constants used in array sizes.
+    fn ttype(&mut self, ttype: &TypeExpr) {
+        match ttype {
+            TypeExpr::Named(named) => {
+                if named.kind == TypeKind::Named {
+                    self.unknown_type(&named.name, named.span);
+                }
+            }
+            TypeExpr::Array(array) => {
+                self.ttype(&array.element);
+                self.expression(&array.size);
+            }
+            // No type takes arguments, so every generic base is unknown.
+            TypeExpr::Generic(generic) => {
+                self.unknown_type(&generic.base.name, generic.base.span);
+                generic.arguments.iter().for_each(|t| self.ttype(t));
+            }
+            TypeExpr::Tuple(elements) => elements.iter().for_each(|t| self.ttype(t)),
+            TypeExpr::Function(function) => {
+                function.parameters.iter().for_each(|t| self.ttype(t));
+                self.ttype(&function.return_type);
+            }
+        }
+    }
+
+    fn use_identifier(&mut self, identifier: &Identifier) {
+        match self.resolution.lookup(self.scope, &identifier.name) {
+            Some(declaration) => {
+                if self.functions.contains(&declaration) {
+                    self.recursive_uses.insert(identifier.id);
+                }
+                self.resolution.uses.insert(identifier.id, declaration);
+            }
+            None => {
+                if let Some(builtin) = Builtin::from_symbol(&identifier.name) {
+                    self.resolution.builtins.insert(identifier.id, builtin);
+                    return;
+                }
+                let error = Diagnostic::error(
+                    "E0200",
+                    format!("use of undeclared variable `{}`", identifier.name),
+                    identifier.span,
+                );
+                let visible = self.resolution.visible_names(self.scope);
+                let names = visible.iter().map(Symbol::as_str);
+                let error = match suggest::closest(identifier.name.as_str(), names) {
+                    Some(name) => error.with_suggestion(
+                        "a name with a similar spelling is in scope",
+                        identifier.span,
+                        name,
+                    ),
+                    None => match suggest::closest(identifier.name.as_str(), keywords()) {
+                        Some(keyword) => error.with_suggestion(
+                            "a keyword with a similar spelling exists",
+                            identifier.span,
+                            keyword,
+                        ),
+                        None => error,
+                    },
+                };
+                self.resolution.errors.push(error);
+            }
+        }
+    }
+
+    fn unknown_type(&mut self, name: &str, span: Span) {
+        let mut error = Diagnostic::error("E0202", format!("unknown type `{}`", name), span);
+        let primitives = TypeKind::PRIMITIVES.iter().map(TypeKind::name);
+        if let Some(primitive) = suggest::closest(name, primitives) {
+            error = error.with_suggestion("a type with a similar name exists", span, primitive);
+        }
+        self.resolution.errors.push(error);
+    }
+}
+
+// The keywords, in a fixed order so that suggestions are deterministic.
+fn keywords() -> Vec<&'static str> {
+    let mut keywords: Vec<&str> = KEYWORDS.keys().copied().collect();
+    keywords.sort_unstable();
+    keywords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lexer::Lexer,
+        parser::Parser,
+        visit::{self, Control, Visitor},
+    };
+
+    // Collects the identifiers used as expressions.
+    struct Uses<'ast>(Vec<&'ast Identifier>);
+
+    impl<'ast> Visitor<'ast> for Uses<'ast> {
+        fn visit_expression(&mut self, expression: &'ast Expression<'ast>) -> Control {
+            if let Expression::Identifier(identifier) = expression {
+                self.0.push(identifier);
+            }
+            Control::Continue
+        }
+    }
+
+    #[test]
+    fn uses_are_linked_to_declarations() {
+        let source = "fn f(a: int32) -> int32 { let b: int32 = a + g(a); return b; } fn g(c: int32) -> int32;";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let resolution = program.resolve();
+        assert!(resolution.errors.is_empty());
+
+        let mut uses = Uses(vec![]);
+        visit::walk_program(&mut uses, &program);
+        let resolved: Vec<(&str, DeclarationKind, usize)> = uses
+            .0
+            .iter()
+            .map(|identifier| {
+                let declaration = resolution.declaration_of(identifier).unwrap();
+                (
+                    &source[declaration.span.range()],
+                    declaration.kind,
+                    declaration.span.start,
+                )
+            })
+            .collect();
+        assert_eq!(
+            resolved,
+            [
+                ("a", DeclarationKind::Parameter, 5),
+                ("g", DeclarationKind::Function, 66),
+                ("a", DeclarationKind::Parameter, 5),
+                ("b", DeclarationKind::Variable { mutable: false }, 30),
+            ]
+        );
+    }
+
+    #[test]
+    fn undeclared_variables_are_reported() {
+        let source = "let x: int32 = x; fn f(p: int32) { return p; } let y: int32 = p + z;";
+        let tokens = Lexer::tokenize(source);
+        let resolution = Parser::parse_program(&tokens).unwrap().resolve();
+        let errors: Vec<(&str, &str)> = resolution
+            .errors
+            .iter()
+            .map(|e| (e.message.as_str(), &source[e.span.range()]))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("use of undeclared variable `x`", "x"),
+                ("use of undeclared variable `p`", "p"),
+                ("use of undeclared variable `z`", "z"),
+            ]
+        );
+    }
+
+    // Returns, for each identifier used as an expression, the byte offset of
+    // the declaration it resolves to.
+    fn resolved_offsets(source: &str) -> Vec<Option<usize>> {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let resolution = program.resolve();
+        let mut uses = Uses(vec![]);
+        visit::walk_program(&mut uses, &program);
+        uses.0
+            .iter()
+            .map(|i| resolution.declaration_of(i).map(|d| d.span.start))
+            .collect()
+    }
+
+    #[test]
+    fn block_bindings_are_not_visible_outside() {
+        let source =
+            "fn f() { { let a: int32 = 1; a; } a; if a { let b: int32 = 2; } else { b; } } b;";
+        assert_eq!(resolved_offsets(source), [Some(15), None, None, None, None]);
+
+        let source = "fn f() { while 1 { let c: int32 = 1; } return c; }";
+        assert_eq!(resolved_offsets(source), [None]);
+    }
+
+    #[test]
+    fn inner_declarations_shadow_outer_ones() {
+        let source = "\
+let x: int32 = 1;
+fn f(x: int32) -> int32 {
+    x;
+    let x: int32 = x;
+    {
+        let x: int32 = x;
+        x;
+    }
+    return x;
+}
+x;";
+        let offsets: Vec<Option<usize>> = vec![
+            Some(23), // the parameter
+            Some(23), // the parameter, in the initializer of the body's `x`
+            Some(59), // the body's `x`, in the initializer of the block's `x`
+            Some(91), // the block's `x`
+            Some(59), // the body's `x` again
+            Some(4),  // the global
+        ];
+        assert_eq!(resolved_offsets(source), offsets);
+    }
+
+    #[test]
+    fn scopes_form_a_tree() {
+        let tokens = Lexer::tokenize("let a: int32 = 1; fn f(p: int32) { { let b: int32 = p; } }");
+        let resolution = Parser::parse_program(&tokens).unwrap().resolve();
+        let kinds: Vec<(ScopeKind, Option<u32>)> = resolution
+            .scopes
+            .iter()
+            .map(|s| (s.kind, s.parent.map(|p| p.0)))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (ScopeKind::Program, None),
+                (ScopeKind::Function, Some(0)),
+                (ScopeKind::Block, Some(1)),
+                (ScopeKind::Block, Some(2)),
+            ]
+        );
+        let b = resolution.lookup(ScopeId(3), &Symbol::new("b")).unwrap();
+        assert_eq!(resolution.declaration_scopes.get(b), Some(&ScopeId(3)));
+        assert!(resolution.lookup(ScopeId(2), &Symbol::new("b")).is_none());
+        assert!(resolution.lookup(ScopeId(3), &Symbol::new("a")).is_some());
+    }
+
+    #[test]
+    fn imported_names_are_visible_around_the_program() {
+        let tokens = Lexer::tokenize("fn g() {} fn main() { f(); g(); }");
+        let program = Parser::parse_program(&tokens).unwrap();
+        let declaration = |name: &str| Declaration {
+            name: Symbol::new(name),
+            kind: DeclarationKind::Function,
+            span: Span::new(3, 4),
+        };
+        let imports = [
+            (NodeId(100), declaration("f")),
+            (NodeId(101), declaration("g")),
+        ];
+        let resolution = resolve_with_imports(&program, &imports);
+        assert!(resolution.errors.is_empty());
+        assert_eq!(resolution.scopes[0].parent, Some(ScopeId(1)));
+        assert_eq!(resolution.scope(ScopeId(1)).kind, ScopeKind::Imports);
+        let targets: Vec<NodeId> = resolution.uses.iter().map(|(_, id)| *id).collect();
+        // The program's own `g` shadows the imported one.
+        assert_eq!(targets, [NodeId(100), NodeId(0)]);
+        assert_eq!(
+            resolution.declarations.get(NodeId(100)).unwrap().name,
+            Symbol::new("f")
+        );
+    }
+
+    #[test]
+    fn unused_bindings_and_functions_are_reported() {
+        let source = "\
+fn main() { let a: int32 = 1; let _b: int32 = 2; let c: int32 = helper(a); }
+fn helper(x: int32) -> int32 { fn inner() { } return x; }
+fn unused() { unused(); }
+fn _ignored() { }
+fn external() -> int32;";
+        let tokens = Lexer::tokenize(source);
+        let resolution = Parser::parse_program(&tokens).unwrap().resolve();
+        let warnings: Vec<(&str, &str)> = resolution
+            .warnings
+            .iter()
+            .map(|w| (w.message.as_str(), &source[w.span.range()]))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                ("unused variable `c`", "c"),
+                ("unused function `inner`", "inner"),
+                ("unused function `unused`", "unused"),
+            ]
+        );
+        let warning = &resolution.warnings[0];
+        assert_eq!(warning.code, "W0200");
+        assert_eq!(warning.suggestions[0].span, warning.span);
+        assert_eq!(warning.suggestions[0].replacement, "_c");
+    }
+
+    #[test]
+    fn shadowing_in_the_same_scope_is_reported() {
+        let source = "\
+const N: int32 = 1;
+fn f(a: int32, a: int32) -> int32 {
+    let b: int32 = a;
+    let b: int32 = b + 1;
+    let _c: int32 = b;
+    let _c: int32 = _c;
+    { let b: int32 = _c; return b; }
+}
+let N: int32 = 2;
+fn f() { }";
+        let tokens = Lexer::tokenize(source);
+        let resolution = Parser::parse_program(&tokens).unwrap().resolve();
+        let report = |diagnostics: &[Diagnostic]| -> Vec<(&str, usize, usize)> {
+            diagnostics
+                .iter()
+                .map(|d| (d.code, d.span.start, d.labels[0].span.start))
+                .collect()
+        };
+        assert_eq!(
+            report(&resolution.errors),
+            [("E0201", 211, 23), ("E0201", 35, 25), ("E0201", 194, 6)]
+        );
+        let shadowing: Vec<Diagnostic> = resolution
+            .warnings
+            .iter()
+            .filter(|w| w.code == "W0201")
+            .cloned()
+            .collect();
+        assert_eq!(report(&shadowing), [("W0201", 86, 64)]);
+        let warning = &shadowing[0];
+        assert_eq!(warning.message, "`b` shadows a binding in the same scope");
+        assert_eq!(warning.labels[0].message, "shadowed binding declared here");
+        assert_eq!(
+            resolution.errors[0].message,
+            "`f` is already declared in this scope"
+        );
+    }
+
+    #[test]
+    fn unknown_names_suggest_similar_ones() {
+        let source = "\
+fn compute(count: int32) -> in32 {
+    let total: int32 = cuont + totl;
+    retrun;
+    let flag: Bool = xyz;
+    return total;
+}";
+        let tokens = Lexer::tokenize(source);
+        let resolution = Parser::parse_program(&tokens).unwrap().resolve();
+        let errors: Vec<(&str, &str, Option<&str>)> = resolution
+            .errors
+            .iter()
+            .map(|e| {
+                (
+                    e.message.as_str(),
+                    &source[e.span.range()],
+                    e.suggestions.first().map(|s| s.replacement.as_str()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("unknown type `in32`", "in32", Some("int32")),
+                ("use of undeclared variable `cuont`", "cuont", Some("count")),
+                ("use of undeclared variable `totl`", "totl", None),
+                (
+                    "use of undeclared variable `retrun`",
+                    "retrun",
+                    Some("return")
+                ),
+                ("unknown type `Bool`", "Bool", Some("bool")),
+                ("use of undeclared variable `xyz`", "xyz", None),
+            ]
+        );
+        assert_eq!(resolution.errors[0].code, "E0202");
+        assert_eq!(
+            resolution.errors[1].suggestions[0].message,
+            "a name with a similar spelling is in scope"
+        );
+    }
+}
@@ -0,0 +1,423 @@
+use crate::{
+    ast::{Expression, Identifier, Program, Span, Spanned, Statement, Symbol, TypeExpr, TypeKind},
+    diagnostic::Diagnostic,
+    fold::{self, Folder},
+    incremental::TextEdit,
+    lexer::Lexer,
+    node::NodeRef,
+    parser::{Parser, ParserError},
+    pattern::{self, parse_pattern, Bindings, Pattern, PatternMatch},
+    printer,
+    sexp::sexp,
+    token::Kind,
+};
+use std::{ops::Range, ptr};
+
+// Structural find-and-replace, for scripts that migrate code from one form
+// to another:
+//
+//   rewrite(source, "$x * $x", "square($x)")
+//
+// finds the statements or expressions the pattern matches, as
+// `Pattern::find_all` does, and returns an edit for each that replaces it
+// with the replacement: the text of a pattern of the same kind, with each
+// metavariable replaced by the text it matched. Only matched text changes,
+// so the formatting and comments of the rest of the source, and of the text
+// the metavariables matched, are kept. A matched expression that would group
+// differently in the replacement, such as `a + b` in `$x * 2`, is
+// parenthesized.
+//
+// Matches inside another match are left alone; rewriting the result again
+// finds them. Errors in the pattern or the replacement have spans in their
+// own text.
+pub fn rewrite(
+    source: &str,
+    pattern: &str,
+    replacement: &str,
+) -> Result<Vec<TextEdit>, ParserError> {
+    let template = Template::new(pattern, replacement)?;
+    let tokens = Lexer::tokenize(source);
+    let program = Parser::parse_program(&tokens)?;
+    let mut edits: Vec<TextEdit> = vec![];
+    for found in template.pattern.find_all(&program) {
+        let mut range = found.node.span().range();
+        // The span of an expression statement leaves out its ';', which the
+        // replacement has.
+        if let NodeRef::Statement(Statement::Expression(_)) = found.node {
+            range.end = semicolon_end(source, range.end);
+        }
+        if edits
+            .last()
+            .is_some_and(|edit| range.start < edit.range.end)
+        {
+            continue;
+        }
+        let parent = match found.node {
+            NodeRef::Expression(expression) => parent(NodeRef::Program(&program), expression),
+            _ => None,
+        };
+        let replacement = template.instantiate(source, &found, parent)?;
+        edits.push(TextEdit { range, replacement });
+    }
+    Ok(edits)
+}
+
+// Applies the edits `rewrite` returned, which are in source order and do not
+// overlap.
+pub fn apply(source: &str, edits: &[TextEdit]) -> String {
+    let mut text = String::with_capacity(source.len());
+    let mut end = 0;
+    for edit in edits {
+        text.push_str(&source[end..edit.range.start]);
+        text.push_str(&edit.replacement);
+        end = edit.range.end;
+    }
+    text + &source[end..]
+}
+
+// The node `child` is a child of, if it is in the tree below `node`.
+fn parent<'a>(node: NodeRef<'a>, child: &Expression) -> Option<NodeRef<'a>> {
+    for candidate in node.children() {
+        if let NodeRef::Expression(expression) = candidate {
+            if ptr::eq(expression, child) {
+                return Some(node);
+            }
+        }
+        if let Some(parent) = parent(candidate, child) {
+            return Some(parent);
+        }
+    }
+    None
+}
+
+// Whether `replacement`, put in place of `child` as an operand of `parent`,
+// needs parentheses to be read as one.
+fn needs_parentheses(
+    parent: Option<NodeRef>,
+    child: &Expression,
+    replacement: &Expression,
+) -> bool {
+    let Expression::BinaryExpression(inner) = replacement else {
+        return false;
+    };
+    match parent {
+        Some(NodeRef::Expression(Expression::Cast(_))) => true,
+        Some(NodeRef::Expression(Expression::BinaryExpression(outer))) => {
+            let left = ptr::eq(outer.left.as_ref(), child);
+            let (outer_precedence, precedence) =
+                (outer.operator.precedence(), inner.operator.precedence());
+            precedence < outer_precedence
+                || (precedence == outer_precedence && left == outer.operator.is_right_associative())
+        }
+        _ => false,
+    }
+}
+
+// The offset just past the ';' that follows `end` after any whitespace, or
+// `end` if none does.
+fn semicolon_end(source: &str, end: usize) -> usize {
+    let rest = &source[end..];
+    let trimmed = rest.trim_start();
+    match trimmed.starts_with(';') {
+        true => end + (rest.len() - trimmed.len()) + 1,
+        false => end,
+    }
+}
+
+struct Template<'r> {
+    pattern: Pattern,
+    replacement: Pattern,
+    text: &'r str,
+    // The metavariables in the text, with their names without the '$'.
+    holes: Vec<(Range<usize>, &'r str)>,
+}
+
+impl<'r> Template<'r> {
+    fn new(pattern: &str, replacement: &'r str) -> Result<Template<'r>, ParserError> {
+        let text = replacement.trim();
+        let error =
+            |message: String, span: Span| Box::new(Diagnostic::error("E0104", message, span));
+        let parsed = parse_pattern(pattern)?;
+        let template = parse_pattern(text)?;
+        if parsed.statement().is_some() != template.statement().is_some() {
+            let (kind, other) = match parsed.statement() {
+                Some(_) => ("a statement", "an expression"),
+                None => ("an expression", "a statement"),
+            };
+            return Err(error(
+                format!("the pattern is {}, but the replacement is {}", kind, other),
+                Span::new(0, text.len()),
+            ));
+        }
+        let bound: Vec<&str> = pattern::tokenize(pattern)
+            .iter()
+            .map(|token| token.text())
+            .filter(|text| text.starts_with('$'))
+            .collect();
+        let mut holes = vec![];
+        for token in pattern::tokenize(text) {
+            let span = Span::new(token.offset(), token.offset() + token.len());
+            match token.text() {
+                "_" if token.kind() == Kind::Identifier => {
+                    return Err(error(
+                        "`_` cannot be used in a replacement".to_string(),
+                        span,
+                    ));
+                }
+                name if name.starts_with('$') && !bound.contains(&name) => {
+                    return Err(error(format!("`{}` is not in the pattern", name), span));
+                }
+                name if name.starts_with('$') => {
+                    holes.push((span.range(), &text[span.start + 1..span.end]))
+                }
+                _ => {}
+            }
+        }
+        Ok(Template {
+            pattern: parsed,
+            replacement: template,
+            text,
+            holes,
+        })
+    }
+
+    // The replacement for a match, checked to parse into the replacement's
+    // tree with the matched nodes in place of its metavariables, and
+    // parenthesized if it would otherwise group differently as an operand of
+    // the match's parent.
+    fn instantiate(
+        &self,
+        source: &str,
+        found: &PatternMatch,
+        parent: Option<NodeRef>,
+    ) -> Result<String, ParserError> {
+        let bindings = &found.bindings;
+        let mut substitute = Substitute {
+            bindings,
+            misplaced: None,
+        };
+        let (expected, expression) =
+            match (self.replacement.statement(), self.replacement.expression()) {
+                (Some(statement), _) => (substitute.fold_statement(statement.clone()), false),
+                (None, Some(expression)) => (
+                    Statement::Expression(substitute.fold_expression(expression.clone())),
+                    true,
+                ),
+                (None, None) => unreachable!("a pattern is a statement or an expression"),
+            };
+        if let Some(name) = substitute.misplaced {
+            let (range, _) = self
+                .holes
+                .iter()
+                .find(|(_, hole)| *hole == name.as_str())
+                .unwrap();
+            return Err(Box::new(Diagnostic::error(
+                "E0104",
+                format!("what `${}` matched cannot be used here", name),
+                Span::new(range.start, range.end),
+            )));
+        }
+        let grouped = match (found.node, &expected) {
+            (NodeRef::Expression(child), Statement::Expression(replacement)) => {
+                needs_parentheses(parent, child, replacement)
+            }
+            _ => false,
+        };
+        let expected = sexp(&Program {
+            statements: vec![expected],
+        });
+        for parenthesize in [false, true] {
+            let text = self.fill(source, bindings, parenthesize);
+            let parsed = match expression {
+                true => format!("{};", text),
+                false => text.clone(),
+            };
+            let tokens = Lexer::tokenize(&parsed);
+            if Parser::parse_program(&tokens).is_ok_and(|program| sexp(&program) == expected) {
+                return Ok(match grouped {
+                    true => format!("({})", text),
+                    false => text,
+                });
+            }
+        }
+        Err(Box::new(Diagnostic::error(
+            "E0104",
+            "the replacement does not parse with what the metavariables matched",
+            Span::new(0, self.text.len()),
+        )))
+    }
+
+    // The replacement's text with the text each metavariable matched in its
+    // place, parenthesized if it is an operation and `parenthesize` is set.
+    fn fill(&self, source: &str, bindings: &Bindings, parenthesize: bool) -> String {
+        let mut text = String::new();
+        let mut end = 0;
+        for (range, name) in &self.holes {
+            text.push_str(&self.text[end..range.start]);
+            let node = bindings[&Symbol::new(name)];
+            match node {
+                NodeRef::Identifier(identifier) => text.push_str(identifier.name.as_str()),
+                // Tuple types' spans leave out their parentheses.
+                NodeRef::Type(ttype @ TypeExpr::Tuple(_)) => {
+                    text.push_str(&printer::print_type(ttype))
+                }
+                NodeRef::Expression(
+                    expression @ (Expression::BinaryExpression(_) | Expression::Cast(_)),
+                ) if parenthesize => {
+                    text.push_str(&format!("({})", &source[expression.span().range()]))
+                }
+                node => text.push_str(&source[node.span().range()]),
+            }
+            end = range.end;
+        }
+        text + &self.text[end..]
+    }
+}
+
+// Puts the nodes a match bound in place of the metavariables of a
+// replacement.
+struct Substitute<'b, 'a> {
+    bindings: &'b Bindings<'a>,
+    // A metavariable whose node cannot go where the replacement has it,
+    // such as an expression where it declares a name.
+    misplaced: Option<Symbol>,
+}
+
+impl<'a> Substitute<'_, 'a> {
+    fn bound(&mut self, name: &str) -> Option<NodeRef<'a>> {
+        let name = Symbol::new(name.strip_prefix('$')?);
+        let node = self.bindings.get(&name).copied();
+        if node.is_none() {
+            self.misplaced = Some(name);
+        }
+        node
+    }
+}
+
+impl<'a> Folder<'a> for Substitute<'_, 'a> {
+    fn fold_expression(&mut self, expression: Expression<'a>) -> Expression<'a> {
+        if let Expression::Identifier(identifier) = &expression {
+            match self.bound(identifier.name.as_str()) {
+                Some(NodeRef::Expression(bound)) => return bound.clone(),
+                Some(NodeRef::Identifier(bound)) => return Expression::Identifier(bound.clone()),
+                Some(_) => {
+                    self.misplaced = Some(Symbol::new(&identifier.name.as_str()[1..]));
+                    return expression;
+                }
+                None => {}
+            }
+        }
+        fold::walk_expression(self, expression)
+    }
+
+    fn fold_identifier(&mut self, identifier: Identifier) -> Identifier {
+        match self.bound(identifier.name.as_str()) {
+            Some(NodeRef::Identifier(bound)) => bound.clone(),
+            Some(NodeRef::Expression(Expression::Identifier(bound))) => bound.clone(),
+            Some(_) => {
+                self.misplaced = Some(Symbol::new(&identifier.name.as_str()[1..]));
+                identifier
+            }
+            None => identifier,
+        }
+    }
+
+    fn fold_type(&mut self, ttype: TypeExpr<'a>) -> TypeExpr<'a> {
+        if let TypeExpr::Named(named) = &ttype {
+            if named.kind == TypeKind::Named {
+                let name = &named.name;
+                match self.bound(name.as_str()) {
+                    Some(NodeRef::Type(bound)) => return bound.clone(),
+                    Some(_) => {
+                        self.misplaced = Some(Symbol::new(&name.as_str()[1..]));
+                        return ttype;
+                    }
+                    None => {}
+                }
+            }
+        }
+        fold::walk_type(self, ttype)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewritten(source: &str, pattern: &str, replacement: &str) -> String {
+        apply(source, &rewrite(source, pattern, replacement).unwrap())
+    }
+
+    #[test]
+    fn matches_are_replaced_and_the_rest_is_kept() {
+        let source = "\
+# Squares.
+fn f(a: int32) -> int32 {
+    let b: int32 = a*a;   # Twice.
+    return (b + 1) * (b + 1) + f(f(2));
+}
+";
+        assert_eq!(
+            rewritten(source, "$x * $x", "square($x)"),
+            "\
+# Squares.
+fn f(a: int32) -> int32 {
+    let b: int32 = square(a);   # Twice.
+    return square(b + 1) + f(f(2));
+}
+"
+        );
+        // Operations are parenthesized where they would group differently.
+        assert_eq!(
+            rewritten("f(1 + 2); f(x);", "f($x)", "$x * 2"),
+            "(1 + 2) * 2; x * 2;"
+        );
+        assert_eq!(
+            rewritten("a - f(b); f(c) - d; f(e) as int8;", "f($x)", "$x - 1"),
+            "a - (b - 1); c - 1 - d; (e - 1) as int8;"
+        );
+        // Matches inside matches are found by rewriting again.
+        assert_eq!(rewritten("f(f(2));", "f($x)", "g($x)"), "g(f(2));");
+
+        // Statements, with their names and types.
+        let source = "let  count : int32 = 7 ;\nprint(count);\n";
+        assert_eq!(
+            rewritten(
+                source,
+                "let $n: int32 = $v;",
+                "let mut $n: int64 = $v as int64;"
+            ),
+            "let mut count: int64 = 7 as int64;\nprint(count);\n"
+        );
+        assert_eq!(
+            rewritten(source, "print($x);", "println($x, 1);"),
+            "let  count : int32 = 7 ;\nprintln(count, 1);\n"
+        );
+        assert!(rewrite(source, "$x * $x", "$x").unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_replacements_are_errors() {
+        let message = |pattern: &str, replacement: &str| {
+            rewrite("f(1);", pattern, replacement).unwrap_err().message
+        };
+        assert_eq!(message("f($x)", "g($y)"), "`$y` is not in the pattern");
+        assert_eq!(
+            message("f($x)", "g(_)"),
+            "`_` cannot be used in a replacement"
+        );
+        assert_eq!(
+            message("f($x);", "g($x)"),
+            "the pattern is a statement, but the replacement is an expression"
+        );
+        assert_eq!(
+            message("f($x)", "fn $x() {}"),
+            "the pattern is an expression, but the replacement is a statement"
+        );
+        assert_eq!(
+            message("f($x);", "let $x: int32 = 1;"),
+            "what `$x` matched cannot be used here"
+        );
+        assert!(rewrite("f(", "f($x)", "g($x)").is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    ast::Program,
+    lexer::Lexer,
+    parser::{Parser, ParserError},
+    printer::print,
+    sexp::sexp,
+};
+use std::fmt;
+
+// Checks that the printer and the parser agree, for property tests here and
+// in crates that build or rewrite programs:
+//
+//   - printing a program and parsing the output gives the same program,
+//     spans and node ids aside, and
+//   - printing that program again gives the same source, so printing what
+//     was parsed is stable.
+//
+// The checks return an error rather than panic, so they fit proptest's
+// `prop_assert!` and quickcheck's `TestResult` as well as `assert!`; the
+// `assert_` forms panic with the error for plain tests.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoundTripError {
+    // The printed source does not parse.
+    Unparsable {
+        printed: String,
+        error: ParserError,
+    },
+    // The printed source parses into another program. Both programs are
+    // given as s-expressions.
+    Changed {
+        printed: String,
+        expected: String,
+        found: String,
+    },
+    // Printing the parsed program gives other source than the first print.
+    Unstable {
+        first: String,
+        second: String,
+    },
+}
+
+impl fmt::Display for RoundTripError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoundTripError::Unparsable { printed, error } => write!(
+                f,
+                "printed source does not parse: {}\n{}",
+                error.message, printed
+            ),
+            RoundTripError::Changed {
+                printed,
+                expected,
+                found,
+            } => write!(
+                f,
+                "printed source parses into another program\n{}\nexpected:\n{}found:\n{}",
+                printed, expected, found
+            ),
+            RoundTripError::Unstable { first, second } => write!(
+                f,
+                "printing the parsed program changes its source\nfirst:\n{}second:\n{}",
+                first, second
+            ),
+        }
+    }
+}
+
+// Checks that a program prints as source that parses back into it, and
+// that printing it again gives the same source.
+pub fn check_program(program: &Program) -> Result<(), RoundTripError> {
+    let printed = print(program);
+    let tokens = Lexer::tokenize(&printed);
+    let parsed = Parser::parse_program(&tokens).map_err(|error| RoundTripError::Unparsable {
+        printed: printed.clone(),
+        error,
+    })?;
+    let (expected, found) = (sexp(program), sexp(&parsed));
+    if expected != found {
+        return Err(RoundTripError::Changed {
+            printed,
+            expected,
+            found,
+        });
+    }
+    let second = print(&parsed);
+    if second != printed {
+        return Err(RoundTripError::Unstable {
+            first: printed,
+            second,
+        });
+    }
+    Ok(())
+}
+
+// Checks that the program a source parses into round trips. Source that
+// does not parse has no program to print, and passes.
+pub fn check_source(source: &str) -> Result<(), RoundTripError> {
+    let tokens = Lexer::tokenize(source);
+    match Parser::parse_program(&tokens) {
+        Ok(program) => check_program(&program),
+        Err(_) => Ok(()),
+    }
+}
+
+pub fn assert_program_round_trips(program: &Program) {
+    if let Err(error) = check_program(program) {
+        panic!("{}", error);
+    }
+}
+
+pub fn assert_source_round_trips(source: &str) {
+    if let Err(error) = check_source(source) {
+        panic!("{}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Expression, Statement, Symbol};
+    use proptest::{collection::vec, prelude::*, sample::select};
+
+    // Statements and fragments that sources are put together from, some of
+    // which do not parse on their own or next to others.
+    const FRAGMENTS: &[&str] = &[
+        "let x: int32 = 1 + 2 * 3;\n",
+        "const N: int64 = 4;",
+        "fn f(a: int8, b: [int32; N + 1]) -> bool { return a < b; }",
+        "## Docs.\n",
+        "#[inline]\n",
+        "# A comment.\n",
+        "if a { b(); } else if c { } else { d; }",
+        "while (x as int8) < y { x; }",
+        "f(g)(h);",
+        "2 ** 3 ** 4;",
+        "(1 - 2) - (3 - 4) / 5;",
+        "{ let mut y: float16 = x as float16; }",
+        "fn g();",
+        "let t: (int8,) = u;",
+        "let h: fn((), List<bool>) -> int1 = k;",
+        "return;",
+        ") garbage",
+    ];
+
+    // Renames the identifier of the first statement, an expression.
+    fn renamed(source: &str, name: &str) -> Program<'static> {
+        let tokens = Lexer::tokenize(source);
+        let mut program = Parser::parse_program(&tokens).unwrap().into_owned();
+        if let Statement::Expression(Expression::Identifier(identifier)) =
+            &mut program.statements[0]
+        {
+            identifier.name = Symbol::new(name);
+        }
+        program
+    }
+
+    #[test]
+    fn programs_that_do_not_round_trip_are_reported() {
+        assert_source_round_trips("fn main() { println((a as int8) < b); }");
+        assert_eq!(check_source("fn f( {"), Ok(()));
+        let error = check_program(&renamed("x;", "while")).unwrap_err();
+        assert!(matches!(error, RoundTripError::Unparsable { .. }));
+        assert!(error
+            .to_string()
+            .starts_with("printed source does not parse"));
+        match check_program(&renamed("x;", "a + b")).unwrap_err() {
+            RoundTripError::Changed {
+                printed,
+                expected,
+                found,
+            } => {
+                assert_eq!(printed, "a + b;\n");
+                assert_eq!(expected, "(expr a + b)\n");
+                assert_eq!(found, "(expr (+ a b))\n");
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn sources_made_of_fragments_round_trip(fragments in vec(select(FRAGMENTS), 0..8)) {
+            let source = fragments.concat();
+            let result = check_source(&source);
+            prop_assert!(result.is_ok(), "{}", result.unwrap_err());
+        }
+    }
+
+    #[cfg(feature = "arbitrary")]
+    proptest! {
+        #[test]
+        fn generated_programs_round_trip(bytes in vec(any::<u8>(), 0..4096)) {
+            let mut u = arbitrary::Unstructured::new(&bytes);
+            let program = crate::generate::program(&mut u).unwrap();
+            let result = check_program(&program);
+            prop_assert!(result.is_ok(), "{}", result.unwrap_err());
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{
+    ast::Span,
+    diagnostic::{json_string, Diagnostic, Severity},
+    lint::{self, Level},
+    source_map::SourceMap,
+};
+
+// Writes diagnostics as a SARIF 2.1.0 log, the format code scanning
+// services such as GitHub's read analysis results in.
+//
+// The log has one run, whose tool lists a rule for every diagnostic code
+// that occurs. Rules for lints carry the lint's name, description and
+// default level. Each diagnostic is a result with its location, its labels
+// as related locations, its notes appended to its message and its
+// suggestions as fixes. Columns count UTF-16 code units, SARIF's default.
+
+pub const VERSION: &str = "2.1.0";
+pub const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+// Writes the diagnostics of some files, each with the source map it was
+// reported against, as a SARIF log.
+pub fn to_sarif(files: &[(&SourceMap, &[Diagnostic])]) -> String {
+    let mut codes: Vec<&str> = files
+        .iter()
+        .flat_map(|(_, diagnostics)| diagnostics.iter().map(|d| d.code))
+        .collect();
+    codes.sort();
+    codes.dedup();
+    let rules: Vec<String> = codes.iter().map(|code| rule(code)).collect();
+    let results: Vec<String> = files
+        .iter()
+        .flat_map(|(map, diagnostics)| {
+            diagnostics
+                .iter()
+                .map(|d| result(d, map, codes.binary_search(&d.code).unwrap()))
+        })
+        .collect();
+    format!(
+        concat!(
+            r#"{{"$schema":{},"version":{},"runs":[{{"tool":{{"driver":{{"#,
+            r#""name":"mylang","version":{},"rules":[{}]}}}},"results":[{}]}}]}}"#
+        ),
+        json_string(SCHEMA),
+        json_string(VERSION),
+        json_string(env!("CARGO_PKG_VERSION")),
+        rules.join(","),
+        results.join(",")
+    )
+}
+
+fn rule(code: &str) -> String {
+    let Some(lint) = lint::for_code(code) else {
+        return format!(r#"{{"id":{}}}"#, json_string(code));
+    };
+    let level = match lint.default {
+        Level::Allow => "none",
+        Level::Warn => "warning",
+        Level::Deny => "error",
+    };
+    format!(
+        concat!(
+            r#"{{"id":{},"name":{},"shortDescription":{{"text":{}}},"#,
+            r#""defaultConfiguration":{{"level":{}}}}}"#
+        ),
+        json_string(code),
+        json_string(lint.name),
+        json_string(lint.description),
+        json_string(level)
+    )
+}
+
+fn result(diagnostic: &Diagnostic, map: &SourceMap, rule_index: usize) -> String {
+    let level = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message += &format!("\nnote: {}", note);
+    }
+    let related: Vec<String> = diagnostic
+        .labels
+        .iter()
+        .enumerate()
+        .map(|(id, label)| {
+            format!(
+                r#"{{"id":{},"message":{{"text":{}}},"physicalLocation":{}}}"#,
+                id,
+                json_string(&label.message),
+                location(map, label.span)
+            )
+        })
+        .collect();
+    let fixes: Vec<String> = diagnostic
+        .suggestions
+        .iter()
+        .map(|suggestion| {
+            format!(
+                concat!(
+                    r#"{{"description":{{"text":{}}},"artifactChanges":[{{"#,
+                    r#""artifactLocation":{{"uri":{}}},"replacements":[{{"#,
+                    r#""deletedRegion":{},"insertedContent":{{"text":{}}}}}]}}]}}"#
+                ),
+                json_string(&suggestion.message),
+                json_string(map.name_at(suggestion.span.start)),
+                region(map, suggestion.span),
+                json_string(&suggestion.replacement)
+            )
+        })
+        .collect();
+    format!(
+        concat!(
+            r#"{{"ruleId":{},"ruleIndex":{},"level":{},"message":{{"text":{}}},"#,
+            r#""locations":[{{"physicalLocation":{}}}],"relatedLocations":[{}],"fixes":[{}]}}"#
+        ),
+        json_string(diagnostic.code),
+        rule_index,
+        json_string(level),
+        json_string(&message),
+        location(map, diagnostic.span),
+        related.join(","),
+        fixes.join(",")
+    )
+}
+
+fn location(map: &SourceMap, span: Span) -> String {
+    format!(
+        r#"{{"artifactLocation":{{"uri":{}}},"region":{}}}"#,
+        json_string(map.name_at(span.start)),
+        region(map, span)
+    )
+}
+
+fn region(map: &SourceMap, span: Span) -> String {
+    let (line, column) = position(map, span.start);
+    let (end_line, end_column) = position(map, span.end);
+    format!(
+        r#"{{"startLine":{},"startColumn":{},"endLine":{},"endColumn":{}}}"#,
+        line, column, end_line, end_column
+    )
+}
+
+// The 1-based line and UTF-16 column of a byte offset.
+fn position(map: &SourceMap, offset: usize) -> (usize, usize) {
+    let (line, column) = map.location(offset);
+    let before = &map.source()[offset + 1 - column..offset];
+    (line, before.encode_utf16().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn diagnostics_are_written_as_a_sarif_run() {
+        let map = SourceMap::new("src/main.my", "# é\nlet é_x: int32 = y;\n");
+        let diagnostics = [
+            Diagnostic::warning("W0200", "unused variable `x`", Span::new(9, 13))
+                .with_suggestion("prefix it", Span::new(9, 13), "_x")
+                .with_label(Span::new(0, 4), "see here"),
+            Diagnostic::error("E0200", "use of undeclared variable `y`", Span::new(23, 24))
+                .with_note("declare it first"),
+        ];
+        let log: Value = serde_json::from_str(&to_sarif(&[(&map, &diagnostics)])).unwrap();
+        assert_eq!(log["version"], "2.1.0");
+        let run = &log["runs"][0];
+        assert_eq!(
+            run["tool"]["driver"]["rules"],
+            json!([
+                { "id": "E0200" },
+                {
+                    "id": "W0200",
+                    "name": "unused",
+                    "shortDescription": { "text": lint::UNUSED.description },
+                    "defaultConfiguration": { "level": "warning" },
+                },
+            ])
+        );
+        let region = |line, column, end_column| {
+            json!({
+                "startLine": line, "startColumn": column,
+                "endLine": line, "endColumn": end_column,
+            })
+        };
+        let location =
+            |region| json!({ "artifactLocation": { "uri": "src/main.my" }, "region": region });
+        let warning = &run["results"][0];
+        assert_eq!(warning["ruleIndex"], 1);
+        assert_eq!(warning["level"], "warning");
+        // `é` is two bytes, but one UTF-16 code unit.
+        assert_eq!(
+            warning["locations"][0]["physicalLocation"],
+            location(region(2, 5, 8))
+        );
+        assert_eq!(
+            warning["relatedLocations"][0]["physicalLocation"],
+            location(region(1, 1, 4))
+        );
+        let replacement = &warning["fixes"][0]["artifactChanges"][0]["replacements"][0];
+        assert_eq!(replacement["deletedRegion"], region(2, 5, 8));
+        assert_eq!(replacement["insertedContent"]["text"], "_x");
+        let error = &run["results"][1];
+        assert_eq!(
+            (&error["ruleId"], &error["level"]),
+            (&json!("E0200"), &json!("error"))
+        );
+        assert_eq!(
+            error["message"]["text"],
+            "use of undeclared variable `y`\nnote: declare it first"
+        );
+        assert_eq!(error["fixes"], json!([]));
+    }
+}
@@ -0,0 +1,387 @@
+use crate::{
+    ast::{Identifier, Program, Span, TypeExpr},
+    lexer::Lexer,
+    parser::Parser,
+    resolver::{resolve, DeclarationKind, Resolution},
+    token::{Kind, Token},
+    visit::{self, Control, Visitor},
+};
+use std::collections::HashMap;
+
+// Classifies the tokens of a file for highlighting in an editor, in the
+// categories of the Language Server Protocol's semantic tokens.
+//
+// Keywords, literals, comments and operators are classified by the lexer
+// alone. Identifiers are classified by what they name, using name
+// resolution: a function, a variable, a mutable variable, a parameter or a
+// constant, or a type where they are used as one. Identifiers that do not
+// resolve are left out, so that an editor falls back to its own
+// highlighting for them instead of showing a wrong guess.
+
+// What a token is, for highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Keyword,
+    Type,
+    Function,
+    // A built-in function such as `println`.
+    BuiltinFunction,
+    Variable,
+    // A variable declared with `let mut`.
+    MutableVariable,
+    Parameter,
+    Constant,
+    Number,
+    String,
+    Comment,
+    DocComment,
+    Operator,
+}
+
+impl TokenKind {
+    // The LSP token type of this kind, and the modifiers that set it apart
+    // from others of that type.
+    pub fn lsp(&self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            TokenKind::Keyword => ("keyword", &[]),
+            TokenKind::Type => ("type", &[]),
+            TokenKind::Function => ("function", &[]),
+            TokenKind::BuiltinFunction => ("function", &["defaultLibrary"]),
+            TokenKind::Variable => ("variable", &[]),
+            TokenKind::MutableVariable => ("variable", &["mutable"]),
+            TokenKind::Parameter => ("parameter", &[]),
+            TokenKind::Constant => ("variable", &["readonly"]),
+            TokenKind::Number => ("number", &[]),
+            TokenKind::String => ("string", &[]),
+            TokenKind::Comment => ("comment", &[]),
+            TokenKind::DocComment => ("comment", &["documentation"]),
+            TokenKind::Operator => ("operator", &[]),
+        }
+    }
+}
+
+// The token types and modifiers that `encode` refers to by index, for a
+// server to send as its semantic tokens legend.
+pub const TOKEN_TYPES: [&str; 9] = [
+    "keyword",
+    "type",
+    "function",
+    "variable",
+    "parameter",
+    "number",
+    "string",
+    "comment",
+    "operator",
+];
+
+pub const TOKEN_MODIFIERS: [&str; 5] = [
+    "declaration",
+    "readonly",
+    "mutable",
+    "defaultLibrary",
+    "documentation",
+];
+
+// A classified token. `declaration` is set on the identifier that declares
+// a name, as opposed to those that use it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub span: Span,
+    pub kind: TokenKind,
+    pub declaration: bool,
+}
+
+// Classifies the tokens of a file, in source order. A file that does not
+// parse has only its keywords, literals, comments and operators
+// classified.
+pub fn semantic_tokens(source: &str) -> Vec<SemanticToken> {
+    let tokens = Lexer::tokenize(source);
+    match Parser::parse_program(&tokens) {
+        Ok(program) => classify(&tokens, Some((&program, &resolve(&program)))),
+        Err(_) => classify(&tokens, None),
+    }
+}
+
+// Classifies tokens, using a program parsed from them and its resolution,
+// if there is one, for the identifiers.
+pub fn classify(tokens: &[Token], program: Option<(&Program, &Resolution)>) -> Vec<SemanticToken> {
+    let names = match program {
+        Some((program, resolution)) => Names::collect(program, resolution),
+        None => HashMap::new(),
+    };
+    tokens
+        .iter()
+        .filter_map(|token| {
+            let span = Span::new(token.offset(), token.offset() + token.text().len());
+            let (kind, declaration) = match token.kind() {
+                Kind::Identifier => *names.get(&span.start)?,
+                kind => (lexical(kind)?, false),
+            };
+            Some(SemanticToken {
+                span,
+                kind,
+                declaration,
+            })
+        })
+        .collect()
+}
+
+// The kind of a token that is not an identifier, or `None` for
+// punctuation, whitespace and tokens that did not lex.
+fn lexical(kind: Kind) -> Option<TokenKind> {
+    let kind = match kind {
+        Kind::As
+        | Kind::Const
+        | Kind::Else
+        | Kind::Fn
+        | Kind::If
+        | Kind::Import
+        | Kind::Let
+        | Kind::Mut
+        | Kind::Return
+        | Kind::While => TokenKind::Keyword,
+        Kind::IntegerLiteral | Kind::DecimalLiteral => TokenKind::Number,
+        Kind::String => TokenKind::String,
+        Kind::Comment => TokenKind::Comment,
+        Kind::DocComment => TokenKind::DocComment,
+        Kind::Arrow
+        | Kind::Divide
+        | Kind::EqualEqual
+        | Kind::EqualSign
+        | Kind::GreaterEqual
+        | Kind::GreaterThan
+        | Kind::LessEqual
+        | Kind::LessThan
+        | Kind::Minus
+        | Kind::NotEqual
+        | Kind::Plus
+        | Kind::Star
+        | Kind::StarStar => TokenKind::Operator,
+        Kind::Colon
+        | Kind::Comma
+        | Kind::EndOfFile
+        | Kind::Identifier
+        | Kind::LeftBrace
+        | Kind::LeftParenthesis
+        | Kind::LeftSquareBracket
+        | Kind::RightBrace
+        | Kind::RightParenthesis
+        | Kind::RightSquareBracket
+        | Kind::Semicolon
+        | Kind::Unknown
+        | Kind::Whitespace => return None,
+    };
+    Some(kind)
+}
+
+// Finds the kind of every identifier in a program, by the offset it starts
+// at.
+struct Names<'r> {
+    resolution: &'r Resolution,
+    kinds: HashMap<usize, (TokenKind, bool)>,
+}
+
+impl Names<'_> {
+    fn collect(program: &Program, resolution: &Resolution) -> HashMap<usize, (TokenKind, bool)> {
+        let mut names = Names {
+            resolution,
+            kinds: HashMap::new(),
+        };
+        visit::walk_program(&mut names, program);
+        names.kinds
+    }
+
+    // The kind of the name an identifier declares or uses, and whether it
+    // declares it.
+    fn kind(&self, identifier: &Identifier) -> Option<(TokenKind, bool)> {
+        if self.resolution.builtins.get(identifier.id).is_some() {
+            return Some((TokenKind::BuiltinFunction, false));
+        }
+        let (declaration, declares) = match self.resolution.declarations.get(identifier.id) {
+            Some(declaration) => (declaration, true),
+            None => (self.resolution.declaration_of(identifier)?, false),
+        };
+        let kind = match declaration.kind {
+            DeclarationKind::Variable { mutable: false } => TokenKind::Variable,
+            DeclarationKind::Variable { mutable: true } => TokenKind::MutableVariable,
+            DeclarationKind::Constant => TokenKind::Constant,
+            DeclarationKind::Parameter => TokenKind::Parameter,
+            DeclarationKind::Function => TokenKind::Function,
+        };
+        Some((kind, declares))
+    }
+}
+
+impl<'ast> Visitor<'ast> for Names<'_> {
+    fn visit_identifier(&mut self, identifier: &'ast Identifier) -> Control {
+        if let Some(kind) = self.kind(identifier) {
+            self.kinds.insert(identifier.span.start, kind);
+        }
+        Control::Continue
+    }
+
+    fn visit_type(&mut self, ttype: &'ast TypeExpr<'ast>) -> Control {
+        let name = match ttype {
+            TypeExpr::Named(named) => Some(named.span),
+            TypeExpr::Generic(generic) => Some(generic.base.span),
+            _ => None,
+        };
+        if let Some(span) = name {
+            self.kinds.insert(span.start, (TokenKind::Type, false));
+        }
+        Control::Continue
+    }
+}
+
+// Encodes tokens the way the LSP sends them: five numbers per token, which
+// are its line and start column relative to the previous token's, its
+// length, the index of its type in `TOKEN_TYPES` and a bit set of the
+// indices of its modifiers in `TOKEN_MODIFIERS`. Lines count from 0 and
+// columns and lengths are in UTF-16 code units. A token that spans lines,
+// which only strings can, is cut off at the end of its first line.
+pub fn encode(tokens: &[SemanticToken], source: &str) -> Vec<u32> {
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let (mut previous_line, mut previous_column) = (0, 0);
+    // The line of the previous token, and the offset it starts at.
+    let (mut line, mut line_start) = (0, 0);
+    for token in tokens {
+        let before = &source[line_start..token.span.start];
+        if let Some(last) = before.rfind('\n') {
+            line += before.matches('\n').count() as u32;
+            line_start += last + 1;
+        }
+        let column = utf16_len(&source[line_start..token.span.start]);
+        let text = &source[token.span.range()];
+        let length = utf16_len(text.split('\n').next().unwrap_or(""));
+        let (ttype, modifiers) = token.kind.lsp();
+        let mut bits = 0;
+        for modifier in modifiers
+            .iter()
+            .chain(token.declaration.then_some(&"declaration"))
+        {
+            bits |= 1 << index(&TOKEN_MODIFIERS, modifier);
+        }
+        let delta_column = match line == previous_line {
+            true => column - previous_column,
+            false => column,
+        };
+        data.extend([
+            line - previous_line,
+            delta_column,
+            length,
+            index(&TOKEN_TYPES, ttype),
+            bits,
+        ]);
+        (previous_line, previous_column) = (line, column);
+    }
+    data
+}
+
+fn index(names: &[&str], name: &str) -> u32 {
+    names.iter().position(|n| *n == name).unwrap() as u32
+}
+
+fn utf16_len(text: &str) -> u32 {
+    text.encode_utf16().count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classified(source: &str) -> Vec<(&str, TokenKind, bool)> {
+        semantic_tokens(source)
+            .into_iter()
+            .map(|token| (&source[token.span.range()], token.kind, token.declaration))
+            .collect()
+    }
+
+    #[test]
+    fn identifiers_are_classified_by_what_they_name() {
+        let source = "\
+const N: int32 = 2;
+fn f(a: int32) -> int32 {
+    let mut b: int32 = a * N;
+    println(b);
+    return f(b);
+}
+";
+        use TokenKind::*;
+        assert_eq!(
+            classified(source),
+            [
+                ("const", Keyword, false),
+                ("N", Constant, true),
+                ("int32", Type, false),
+                ("=", Operator, false),
+                ("2", Number, false),
+                ("fn", Keyword, false),
+                ("f", Function, true),
+                ("a", Parameter, true),
+                ("int32", Type, false),
+                ("->", Operator, false),
+                ("int32", Type, false),
+                ("let", Keyword, false),
+                ("mut", Keyword, false),
+                ("b", MutableVariable, true),
+                ("int32", Type, false),
+                ("=", Operator, false),
+                ("a", Parameter, false),
+                ("*", Operator, false),
+                ("N", Constant, false),
+                ("println", BuiltinFunction, false),
+                ("b", MutableVariable, false),
+                ("return", Keyword, false),
+                ("f", Function, false),
+                ("b", MutableVariable, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn unresolved_names_and_unparsed_files_are_classified_lexically() {
+        use TokenKind::*;
+        assert_eq!(
+            classified("## Doc.\nlet x: int32 = y; # note\n"),
+            [
+                ("## Doc.", DocComment, false),
+                ("let", Keyword, false),
+                ("x", Variable, true),
+                ("int32", Type, false),
+                ("=", Operator, false),
+                ("# note", Comment, false),
+            ]
+        );
+        assert_eq!(
+            classified("let x = 1"),
+            [
+                ("let", Keyword, false),
+                ("=", Operator, false),
+                ("1", Number, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn tokens_are_encoded_relative_to_each_other() {
+        let source = "let x: int32 = 1; # 😀\nfn f() {}\n";
+        let data = encode(&semantic_tokens(source), source);
+        let keyword = index(&TOKEN_TYPES, "keyword");
+        let variable = index(&TOKEN_TYPES, "variable");
+        let function = index(&TOKEN_TYPES, "function");
+        let declaration = 1;
+        assert_eq!(
+            data.chunks(5).collect::<Vec<_>>(),
+            [
+                &[0, 0, 3, keyword, 0][..],
+                &[0, 4, 1, variable, declaration],
+                &[0, 3, 5, index(&TOKEN_TYPES, "type"), 0],
+                &[0, 6, 1, index(&TOKEN_TYPES, "operator"), 0],
+                &[0, 2, 1, index(&TOKEN_TYPES, "number"), 0],
+                &[0, 3, 4, index(&TOKEN_TYPES, "comment"), 0],
+                &[1, 0, 2, keyword, 0],
+                &[0, 3, 1, function, declaration],
+            ]
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{
+    analyze,
+    ast::Program,
+    diagnostic::{Diagnostic, DiagnosticSink},
+    hir,
+    lexer::Lexer,
+    loader::Loader,
+    parser::Parser,
+    pipeline::{CompileOptions, Diagnostics},
+    resolver::Resolution,
+    source_map::SourceMap,
+    symbol::{Interner, Symbol},
+    timings::Timings,
+    token::Token,
+    typecheck::TypeCheck,
+};
+use std::path::Path;
+
+// The state of compiling one source, shared by its phases: the source map,
+// the options, the interner and the diagnostics reported so far. Each phase
+// is a method that reads what it needs from the session and reports to it,
+// so callers hand the phases one session rather than a source map here,
+// lint levels there and a list to collect diagnostics in.
+//
+// The session's interner is the one every phase uses: the parser interns
+// the names of identifiers as it reads their tokens, and the AST, the
+// resolver and the HIR hold the symbols, so equal names are compared by
+// pointer. The interner is the session's own, so its text is freed once the
+// session and the symbols taken from it are dropped, and the AST does not
+// borrow its names from the source.
+
+#[derive(Debug)]
+pub struct Session {
+    pub source_map: SourceMap,
+    pub options: CompileOptions,
+    // How long each phase took, with the `timings` feature.
+    pub timings: Timings,
+    diagnostics: Vec<Diagnostic>,
+    interner: Interner,
+    // The resolution of a program linked from modules, for `check`.
+    linked: Option<Resolution>,
+}
+
+// What checking a program found out about it.
+#[derive(Debug)]
+pub struct Checked {
+    pub resolution: Resolution,
+    pub typecheck: TypeCheck,
+    // The program lowered to HIR, if it has no errors.
+    pub hir: Option<hir::Program>,
+}
+
+impl Session {
+    // Starts a session for a source, named as the options say.
+    pub fn new(source: impl Into<String>, options: CompileOptions) -> Session {
+        Session::with_source_map(SourceMap::new(options.name.clone(), source), options)
+    }
+
+    // Starts a session for a source that is already in a source map, such
+    // as a file mapped into memory.
+    pub fn with_source_map(source_map: SourceMap, options: CompileOptions) -> Session {
+        Session {
+            source_map,
+            options,
+            timings: Timings::new(),
+            diagnostics: vec![],
+            interner: Interner::new(),
+            linked: None,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        self.source_map.source()
+    }
+
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        self.interner.intern(text)
+    }
+
+    // The diagnostics reported so far, in the order they were reported.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    // Reports diagnostics found before, such as by a cached check, as they
+    // are: their lint levels have already been applied.
+    pub fn restore(&mut self, diagnostics: impl IntoIterator<Item = Diagnostic>) {
+        self.diagnostics.extend(diagnostics);
+    }
+
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    // Renders the diagnostics against the source, separated by blank lines.
+    pub fn render(&self) -> String {
+        self.diagnostics
+            .iter()
+            .map(|d| d.render(&self.source_map))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Ends the session, keeping its diagnostics and the source they are
+    // about.
+    pub fn into_diagnostics(self) -> Diagnostics {
+        Diagnostics {
+            source_map: self.source_map,
+            diagnostics: self.diagnostics,
+        }
+    }
+
+    pub fn tokenize(&self) -> Vec<Token<'_>> {
+        Lexer::tokenize(self.source())
+    }
+
+    // Parses the source, reporting why it does not parse if it does not.
+    //
+    // The parser lexes the source as it goes, so the tokens are never all
+    // held at once, except when the options ask for the phases to be timed:
+    // then they are read first, so that lexing and parsing are timed apart.
+    //
+    // The program is returned owned, as the session holds its source. Names
+    // are symbols, so what `into_owned` copies is only the text the AST
+    // still borrows, such as literals and doc comments, not the tokens.
+    pub fn parse(&mut self) -> Option<Program<'static>> {
+        let source = self.source_map.source();
+        let parsed = if self.options.timings {
+            let tokens = self.timings.time("lex", || Lexer::tokenize(source));
+            self.timings.count("tokens", || tokens.len());
+            let interner = &mut self.interner;
+            self.timings.time("parse", || {
+                Parser::parse_stream_in(tokens.as_slice(), interner)
+            })
+        } else {
+            Parser::parse_stream_in(Lexer::new(source), &mut self.interner)
+        };
+        match parsed {
+            Ok(program) => {
+                self.timings
+                    .count("nodes", || program.metrics().total_nodes());
+                Some(program.into_owned())
+            }
+            Err(error) => {
+                self.diagnostics.push(*error);
+                None
+            }
+        }
+    }
+
+    // Links a program parsed from the source with the modules it imports, so
+    // that it can be checked and run whole; a program that imports nothing
+    // is returned as it is. The modules are looked for in the directory of
+    // the file the options name, and the session's source map becomes the
+    // files of the program and its modules joined, as `Loader::link` gives
+    // it. If a module cannot be loaded, does not parse or its names do not
+    // resolve, the session reports why and returns `None`.
+    pub fn link(&mut self, program: Program<'static>) -> Option<Program<'static>> {
+        if program.imports().next().is_none() {
+            return Some(program);
+        }
+        let path = Path::new(&self.options.name);
+        let directory = match path.parent() {
+            Some(directory) if !directory.as_os_str().is_empty() => directory,
+            _ => Path::new("."),
+        };
+        let mut loader = Loader::new(vec![directory.to_path_buf()]);
+        let root = loader.load_source(path, self.source().to_string());
+        let linked = loader.link(root);
+        self.source_map = linked.source_map;
+        if linked.diagnostics.iter().any(Diagnostic::is_error) {
+            for diagnostic in linked.diagnostics {
+                self.emit(diagnostic);
+            }
+            return None;
+        }
+        self.linked = Some(linked.resolution);
+        Some(linked.program)
+    }
+
+    // Runs the semantic passes over a program parsed from the source, with
+    // the lint levels of the options and of the source's lint attributes,
+    // and reports what they find in source order. A program `link` linked
+    // keeps the resolution the loader gave it.
+    pub fn check(&mut self, program: &Program) -> Checked {
+        let (map, lints) = (&self.source_map, &self.options.lints);
+        let result = match self.linked.take() {
+            Some(resolution) => analyze::check_resolved(program, map, lints, resolution),
+            None => analyze::check_program_with_lints(program, map, lints),
+        };
+        self.diagnostics.extend(result.diagnostics);
+        self.timings.extend(result.timings);
+        Checked {
+            resolution: result.resolution,
+            typecheck: result.typecheck,
+            hir: result.hir,
+        }
+    }
+}
+
+// Diagnostics emitted to a session are reported at the level the options
+// give their lint.
+impl DiagnosticSink for Session {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        if let Some(diagnostic) = self.options.lints.apply(diagnostic) {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::Span,
+        lint::{self, Level, LintLevels},
+    };
+
+    #[test]
+    fn phases_report_to_the_session() {
+        let mut lints = LintLevels::new();
+        lints.set(&lint::UNUSED, Level::Allow);
+        let options = CompileOptions {
+            name: "main.my".to_string(),
+            lints,
+            ..CompileOptions::default()
+        };
+        let mut session = Session::new("let x: int8 = 300;\nprintln(y);\n", options);
+        let program = session.parse().unwrap();
+        let checked = session.check(&program);
+        assert!(checked.hir.is_none());
+        let codes: Vec<&str> = session.diagnostics().iter().map(|d| d.code).collect();
+        assert_eq!(codes, ["E0308", "E0200"]);
+        assert!(session.render().contains(" --> main.my:2:9"));
+
+        session.emit(Diagnostic::warning("W0200", "unused", Span::new(4, 5)));
+        session.emit(Diagnostic::warning("W0300", "inexact", Span::new(14, 17)));
+        let codes: Vec<&str> = session.take_diagnostics().iter().map(|d| d.code).collect();
+        assert_eq!(codes, ["E0308", "E0200", "W0300"]);
+        assert!(!session.has_errors());
+        assert_eq!(session.intern("x"), Symbol::new("x"));
+
+        let mut session = Session::new("fn f( {", CompileOptions::default());
+        assert!(session.parse().is_none());
+        assert!(session.timings.phases().is_empty());
+        assert_eq!(session.tokenize().len(), 7);
+        assert_eq!(session.into_diagnostics().diagnostics.len(), 1);
+    }
+
+    // The source is lexed apart from parsing only when timings are asked
+    // for; otherwise the parser streams the tokens from the lexer.
+    #[cfg(feature = "timings")]
+    #[test]
+    fn lexing_is_timed_on_request() {
+        let options = CompileOptions {
+            timings: true,
+            ..CompileOptions::default()
+        };
+        let mut session = Session::new("let x: int32 = 1;", options);
+        session.parse().unwrap();
+        let names: Vec<&str> = session.timings.phases().iter().map(|p| p.name).collect();
+        assert_eq!(names, ["lex", "parse"]);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{
+    ast::{Block, Expression, Program, Statement, TypeExpr},
+    printer::operator_text,
+};
+use std::borrow::Cow;
+
+// Returns the program as s-expressions, one top-level statement per line,
+// e.g. `(let x int32 (int 5))`.
+//
+// The format is compact and stable so that it can be used for golden tests:
+// a change in how the parser builds the tree shows up as a readable diff.
+pub fn sexp(program: &Program) -> String {
+    let mut output = String::new();
+    for statement in &program.statements {
+        write_statement(&mut output, statement);
+        output.push('\n');
+    }
+    output
+}
+
+// Returns a single expression as an s-expression.
+pub fn expression_sexp(expression: &Expression) -> String {
+    let mut output = String::new();
+    write_expression(&mut output, expression);
+    output
+}
+
+fn write_docs(output: &mut String, docs: &[Cow<str>]) {
+    if docs.is_empty() {
+        return;
+    }
+    output.push_str(" (docs");
+    for doc in docs {
+        output.push_str(&format!(" {:?}", doc));
+    }
+    output.push(')');
+}
+
+fn write_statement(output: &mut String, statement: &Statement) {
+    match statement {
+        Statement::Let(let_statement) => {
+            output.push_str("(let ");
+            if let_statement.mutable {
+                output.push_str("mut ");
+            }
+            output.push_str(&let_statement.identifier.name);
+            output.push(' ');
+            write_type(output, &let_statement.ttype);
+            output.push(' ');
+            write_expression(output, &let_statement.expression);
+            write_docs(output, &let_statement.docs);
+            output.push(')');
+        }
+        Statement::Const(constant) => {
+            output.push_str("(const ");
+            output.push_str(&constant.identifier.name);
+            output.push(' ');
+            write_type(output, &constant.ttype);
+            output.push(' ');
+            write_expression(output, &constant.expression);
+            write_docs(output, &constant.docs);
+            output.push(')');
+        }
+        Statement::FunctionDeclaration(function) => {
+            output.push_str("(fn ");
+            output.push_str(&function.identifier.name);
+            output.push_str(" (");
+            for (i, parameter) in function.parameters.iter().enumerate() {
+                if i > 0 {
+                    output.push(' ');
+                }
+                output.push('(');
+                output.push_str(&parameter.identifier.name);
+                output.push(' ');
+                write_type(output, &parameter.ttype);
+                output.push(')');
+            }
+            output.push_str(") ");
+            write_type(output, &function.return_type);
+            if let Some(body) = &function.body {
+                output.push(' ');
+                write_block(output, body);
+            }
+            write_docs(output, &function.docs);
+            if function.inline {
+                output.push_str(" inline");
+            }
+            output.push(')');
+        }
+        Statement::Expression(expression) => {
+            output.push_str("(expr ");
+            write_expression(output, expression);
+            output.push(')');
+        }
+        Statement::Return(return_statement) => {
+            output.push_str("(return");
+            if let Some(expression) = &return_statement.expression {
+                output.push(' ');
+                write_expression(output, expression);
+            }
+            output.push(')');
+        }
+        Statement::If(if_statement) => {
+            output.push_str("(if ");
+            write_expression(output, &if_statement.condition);
+            output.push(' ');
+            write_block(output, &if_statement.then_block);
+            if let Some(else_branch) = &if_statement.else_branch {
+                output.push(' ');
+                write_statement(output, else_branch);
+            }
+            output.push(')');
+        }
+        Statement::While(while_statement) => {
+            output.push_str("(while ");
+            write_expression(output, &while_statement.condition);
+            output.push(' ');
+            write_block(output, &while_statement.body);
+            output.push(')');
+        }
+        Statement::Block(block) => write_block(output, block),
+        Statement::Import(import) => {
+            output.push_str("(import ");
+            output.push_str(&import.module.name);
+            output.push(')');
+        }
+    }
+}
+
+fn write_block(output: &mut String, block: &Block) {
+    output.push_str("(block");
+    for statement in &block.statements {
+        output.push(' ');
+        write_statement(output, statement);
+    }
+    output.push(')');
+}
+
+fn write_expression(output: &mut String, expression: &Expression) {
+    match expression {
+        Expression::IntegerLiteral(literal) => {
+            output.push_str("(int ");
+            output.push_str(&literal.text);
+            output.push(')');
+        }
+        Expression::Identifier(identifier) => output.push_str(&identifier.name),
+        Expression::BinaryExpression(binary) => {
+            output.push('(');
+            output.push_str(operator_text(&binary.operator));
+            output.push(' ');
+            write_expression(output, &binary.left);
+            output.push(' ');
+            write_expression(output, &binary.right);
+            output.push(')');
+        }
+        Expression::Call(call) => {
+            output.push_str("(call ");
+            write_expression(output, &call.callee);
+            for argument in &call.arguments {
+                output.push(' ');
+                write_expression(output, argument);
+            }
+            output.push(')');
+        }
+        Expression::Cast(cast) => {
+            output.push_str("(as ");
+            write_expression(output, &cast.expression);
+            output.push(' ');
+            write_type(output, &cast.ttype);
+            output.push(')');
+        }
+    }
+}
+
+// Writes a list of types separated by spaces.
+fn write_types(output: &mut String, types: &[TypeExpr]) {
+    for ttype in types {
+        output.push(' ');
+        write_type(output, ttype);
+    }
+}
+
+fn write_type(output: &mut String, ttype: &TypeExpr) {
+    match ttype {
+        TypeExpr::Named(named) => output.push_str(&named.name),
+        TypeExpr::Array(array) => {
+            output.push_str("(array ");
+            write_type(output, &array.element);
+            output.push(' ');
+            write_expression(output, &array.size);
+            output.push(')');
+        }
+        TypeExpr::Generic(generic) => {
+            output.push_str("(generic ");
+            output.push_str(&generic.base.name);
+            write_types(output, &generic.arguments);
+            output.push(')');
+        }
+        TypeExpr::Tuple(elements) => {
+            output.push_str("(tuple");
+            write_types(output, elements);
+            output.push(')');
+        }
+        TypeExpr::Function(function) => {
+            output.push_str("(fn-type (");
+            for (i, parameter) in function.parameters.iter().enumerate() {
+                if i > 0 {
+                    output.push(' ');
+                }
+                write_type(output, parameter);
+            }
+            output.push_str(") ");
+            write_type(output, &function.return_type);
+            output.push(')');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn check_sexp(input: &str, expected: &str) {
+        let tokens = Lexer::tokenize(input);
+        let program = Parser::parse_program(&tokens).unwrap();
+        assert_eq!(sexp(&program), expected);
+    }
+
+    #[test]
+    fn statements() {
+        check_sexp(
+            "let x: int32 = 5; let mut y: int32 = x + 2 * 3; x ** 2;",
+            "(let x int32 (int 5))\n(let mut y int32 (+ x (* (int 2) (int 3))))\n(expr (** x (int 2)))\n",
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        check_sexp(
+            "while i < n { if f(i) { } else if g() { } else { { } } }",
+            "(while (< i n) (block (if (call f i) (block) (if (call g) (block) (block (block))))))\n",
+        );
+    }
+
+    #[test]
+    fn functions_and_types() {
+        check_sexp(
+            "## Doc.\nfn f(a: [int32; 2], b: Map<string, (int32,)>, c: fn(int32) -> ()) -> int32 { return a; }\nfn g();",
+            concat!(
+                "(fn f ((a (array int32 (int 2))) (b (generic Map string (tuple int32))) ",
+                "(c (fn-type (int32) ()))) int32 (block (return a)) (docs \"Doc.\"))\n",
+                "(fn g () ())\n",
+            ),
+        );
+    }
+}
@@ -0,0 +1,386 @@
+use crate::{
+    ast::{NodeId, Symbol, TypeKind},
+    value::{Closure, Value},
+};
+use std::{fmt, rc::Rc};
+
+// The binary format of an interpreter's globals, for checkpointing a
+// program's state and restoring it in a later process:
+//
+//   snapshot := "mls" version:u8 count:u32 (name value)*
+//   value    := 0 kind:u8 i64         an integer
+//             | 1 kind:u8 f64         a float
+//             | 2 u8                  a bool
+//             | 3 string              a string
+//             | 4 name index:u32 count:u32 (id:u32 value)*
+//                                     a function and what it captures
+//   name     := string
+//   string   := length:u32 utf-8 bytes
+//
+// Numbers are little-endian, and a `kind` is an index into
+// `TypeKind::PRIMITIVES`. Functions and captured bindings are identified by
+// their index and node ids, so a snapshot can only be restored into the
+// program it was taken from. Functions nest at most `MAX_DEPTH` deep, so
+// decoding a snapshot does not recurse without bound.
+
+const MAGIC: &[u8] = b"mls";
+const VERSION: u8 = 1;
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    // The bytes are not a snapshot, or are from another version.
+    Format,
+    // The bytes end in the middle of the snapshot.
+    Truncated,
+    // The program has no global of this name, or fewer of them.
+    UnknownGlobal(Symbol),
+    // A function value that is not one of the program's functions.
+    UnknownFunction(Symbol),
+    // The value of a global, or of something a function in it captures,
+    // does not have the type the program gives it.
+    TypeMismatch(Symbol),
+    // Function values nested more than `MAX_DEPTH` deep.
+    TooDeep,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Format => f.write_str("not a snapshot"),
+            SnapshotError::Truncated => f.write_str("snapshot is truncated"),
+            SnapshotError::UnknownGlobal(name) => {
+                write!(f, "snapshot has a global `{}` the program does not", name)
+            }
+            SnapshotError::UnknownFunction(name) => {
+                write!(f, "snapshot has a function `{}` the program does not", name)
+            }
+            SnapshotError::TypeMismatch(name) => {
+                write!(f, "snapshot has a global `{}` of another type", name)
+            }
+            SnapshotError::TooDeep => f.write_str("snapshot nests functions too deeply"),
+        }
+    }
+}
+
+// Encodes globals, in the order they are declared.
+pub fn encode(globals: &[(Symbol, &Value)]) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    bytes.extend((globals.len() as u32).to_le_bytes());
+    for (name, value) in globals {
+        string(&mut bytes, name);
+        self::value(&mut bytes, value);
+    }
+    bytes
+}
+
+fn string(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend((string.len() as u32).to_le_bytes());
+    bytes.extend(string.as_bytes());
+}
+
+fn kind(bytes: &mut Vec<u8>, kind: TypeKind) {
+    let index = TypeKind::PRIMITIVES.iter().position(|k| *k == kind);
+    bytes.push(index.expect("values have primitive types") as u8);
+}
+
+fn value(bytes: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Integer(k, value) => {
+            bytes.push(0);
+            kind(bytes, *k);
+            bytes.extend(value.to_le_bytes());
+        }
+        Value::Float(k, value) => {
+            bytes.push(1);
+            kind(bytes, *k);
+            bytes.extend(value.to_bits().to_le_bytes());
+        }
+        Value::Bool(value) => bytes.extend([2, *value as u8]),
+        Value::String(value) => {
+            bytes.push(3);
+            string(bytes, value);
+        }
+        Value::Function(closure) => {
+            bytes.push(4);
+            string(bytes, &closure.name);
+            bytes.extend(closure.index.to_le_bytes());
+            bytes.extend((closure.captures.len() as u32).to_le_bytes());
+            for (id, value) in &closure.captures {
+                bytes.extend(id.0.to_le_bytes());
+                self::value(bytes, value);
+            }
+        }
+    }
+}
+
+// Decodes the globals of a snapshot. Each function value is checked with
+// `function`, which is given its name and index.
+pub fn decode(
+    bytes: &[u8],
+    function: &dyn Fn(Symbol, u32) -> bool,
+) -> Result<Vec<(Symbol, Value)>, SnapshotError> {
+    let mut decoder = Decoder {
+        bytes,
+        function,
+        depth: 0,
+    };
+    if decoder.take(MAGIC.len())? != MAGIC || decoder.byte()? != VERSION {
+        return Err(SnapshotError::Format);
+    }
+    let count = decoder.u32()?;
+    let mut globals = vec![];
+    for _ in 0..count {
+        let name = decoder.name()?;
+        globals.push((name, decoder.value()?));
+    }
+    if !decoder.bytes.is_empty() {
+        return Err(SnapshotError::Format);
+    }
+    Ok(globals)
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    function: &'a dyn Fn(Symbol, u32) -> bool,
+    // The number of function values being decoded that contain the next.
+    depth: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < count {
+            return Err(SnapshotError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<&'a str, SnapshotError> {
+        let length = self.u32()? as usize;
+        std::str::from_utf8(self.take(length)?).map_err(|_| SnapshotError::Format)
+    }
+
+    fn name(&mut self) -> Result<Symbol, SnapshotError> {
+        Ok(Symbol::new(self.string()?))
+    }
+
+    fn kind(&mut self) -> Result<TypeKind, SnapshotError> {
+        let index = self.byte()? as usize;
+        TypeKind::PRIMITIVES
+            .get(index)
+            .copied()
+            .ok_or(SnapshotError::Format)
+    }
+
+    fn value(&mut self) -> Result<Value, SnapshotError> {
+        let value = match self.byte()? {
+            0 => match self.kind()? {
+                kind if kind.is_integer() => Value::integer(kind, self.u64()? as i64 as i128),
+                _ => return Err(SnapshotError::Format),
+            },
+            1 => match self.kind()? {
+                kind if kind.is_float() => Value::float(kind, f64::from_bits(self.u64()?)),
+                _ => return Err(SnapshotError::Format),
+            },
+            2 => match self.byte()? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                _ => return Err(SnapshotError::Format),
+            },
+            3 => Value::String(self.string()?.into()),
+            4 => {
+                let name = self.name()?;
+                let index = self.u32()?;
+                if !(self.function)(name.clone(), index) {
+                    return Err(SnapshotError::UnknownFunction(name));
+                }
+                if self.depth == MAX_DEPTH {
+                    return Err(SnapshotError::TooDeep);
+                }
+                let count = self.u32()?;
+                let mut captures = vec![];
+                self.depth += 1;
+                for _ in 0..count {
+                    let id = NodeId(self.u32()?);
+                    captures.push((id, self.value()?));
+                }
+                self.depth -= 1;
+                Value::Function(Rc::new(Closure {
+                    name,
+                    index,
+                    captures,
+                }))
+            }
+            _ => return Err(SnapshotError::Format),
+        };
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interpreter::Interpreter, lexer::Lexer, parser::Parser};
+
+    fn parse(source: &str) -> crate::hir::Program {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        assert!(program.typecheck().errors.is_empty(), "{}", source);
+        program.to_hir()
+    }
+
+    #[test]
+    fn globals_survive_a_restart() {
+        let hir = parse(
+            "\
+let a: int64 = 40;
+let f: float32 = 1;
+let f: float32 = f / 10;
+let b: bool = a > 1;
+fn adder(x: int64) -> fn(int64) -> int64 {
+    fn add(y: int64) -> int64 { return x + y; }
+    return add;
+}
+let g: fn(int64) -> int64 = adder(a);
+let a: int8 = 2;
+fn main() -> int64 { if b { return g(a as int64); } return 0; }",
+        );
+        let mut interpreter = Interpreter::new(&hir);
+        interpreter.run().unwrap();
+        let bytes = interpreter.snapshot();
+
+        let mut restored = Interpreter::new(&hir);
+        restored.restore(&bytes).unwrap();
+        assert_eq!(
+            restored.call("main", vec![]),
+            Ok(Some(Value::integer(TypeKind::Int64, 42)))
+        );
+        assert_eq!(restored.snapshot(), bytes);
+
+        let mut fresh = Interpreter::new(&hir);
+        for end in [0, 3, bytes.len() - 1] {
+            let error = fresh.restore(&bytes[..end]).unwrap_err();
+            assert_eq!(error, SnapshotError::Truncated);
+        }
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert_eq!(fresh.restore(&extra), Err(SnapshotError::Format));
+        assert_eq!(fresh.snapshot(), encode(&[]));
+
+        let other = parse("let a: int64 = 1;\nfn adder() { }");
+        let error = Interpreter::new(&other).restore(&bytes).unwrap_err();
+        assert_eq!(error, SnapshotError::UnknownFunction(Symbol::new("add")));
+        let mut interpreter = Interpreter::new(&other);
+        interpreter.run().unwrap();
+        let bytes = interpreter.snapshot();
+        let other = parse("let b: bool = false;");
+        let error = Interpreter::new(&other).restore(&bytes).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "snapshot has a global `a` the program does not"
+        );
+    }
+
+    #[test]
+    fn values_must_have_the_types_of_their_globals() {
+        let snapshot = |source: &str| {
+            let hir = parse(source);
+            let mut interpreter = Interpreter::new(&hir);
+            interpreter.run().unwrap();
+            interpreter.snapshot()
+        };
+        let integer = snapshot("let g: int64 = 5;");
+        let function = snapshot(
+            "\
+fn add(y: int64) -> int64 { return y; }
+let g: fn(int64) -> int64 = add;",
+        );
+        let captured = snapshot(
+            "\
+fn adder(x: int64) -> fn(int64) -> int64 {
+    fn add(y: int64) -> int64 { return x + y; }
+    return add;
+}
+let g: fn(int64) -> int64 = adder(1);",
+        );
+        for (bytes, source) in [
+            (
+                &integer,
+                "let g: bool = true;\nfn main() -> int32 { if g { return 1; } return 0; }",
+            ),
+            (&integer, "let g: int32 = 5;"),
+            (
+                &function,
+                "\
+fn add(y: bool) -> bool { return y; }
+fn inc(y: int64) -> int64 { return y + 1; }
+let g: fn(int64) -> int64 = inc;",
+            ),
+            (
+                &captured,
+                "\
+fn adder(x: int32) -> fn(int64) -> int64 {
+    fn add(y: int64) -> int64 { return y; }
+    return add;
+}
+let g: fn(int64) -> int64 = adder(1);",
+            ),
+        ] {
+            let hir = parse(source);
+            let mut interpreter = Interpreter::new(&hir);
+            let error = interpreter.restore(bytes).unwrap_err();
+            assert_eq!(
+                error,
+                SnapshotError::TypeMismatch(Symbol::new("g")),
+                "{}",
+                source
+            );
+            assert_eq!(interpreter.snapshot(), encode(&[]));
+        }
+    }
+
+    #[test]
+    fn malformed_values_are_rejected() {
+        let header = |bytes: &mut Vec<u8>| {
+            bytes.extend(MAGIC);
+            bytes.push(VERSION);
+            bytes.extend(1u32.to_le_bytes());
+            string(bytes, "g");
+        };
+        let any = |_: Symbol, _: u32| true;
+
+        let mut bytes = vec![];
+        header(&mut bytes);
+        bytes.push(0);
+        kind(&mut bytes, TypeKind::Bool);
+        bytes.extend(0u64.to_le_bytes());
+        assert_eq!(decode(&bytes, &any), Err(SnapshotError::Format));
+
+        let mut bytes = vec![];
+        header(&mut bytes);
+        for _ in 0..=MAX_DEPTH {
+            bytes.push(4);
+            string(&mut bytes, "f");
+            bytes.extend(0u32.to_le_bytes());
+            bytes.extend(1u32.to_le_bytes());
+            bytes.extend(0u32.to_le_bytes());
+        }
+        value(&mut bytes, &Value::Bool(true));
+        assert_eq!(decode(&bytes, &any), Err(SnapshotError::TooDeep));
+    }
+}
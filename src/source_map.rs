@@ -0,0 +1,241 @@
+use crate::ast::Span;
+use alloc::{string::String, vec, vec::Vec};
+#[cfg(feature = "mmap")]
+use std::{fs::File, io, path::Path, str, sync::Arc};
+
+// A source file together with an index of where its lines start, used to
+// turn byte offsets into line and column numbers for diagnostics.
+//
+// A source map may also hold several files one after another, as `join`
+// makes for a program linked from modules. Offsets then count from the start
+// of the first file, and lines and columns, like names, are those of the file
+// an offset is in.
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    // The files, in the order their text is in the source. There is always
+    // at least one.
+    files: Vec<SourceFile>,
+    source: Text,
+    // The byte offset at which each line starts. The first line starts at 0.
+    line_starts: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct SourceFile {
+    name: String,
+    // The offset of the file's first byte, and the index of its first line
+    // in `line_starts`.
+    start: usize,
+    first_line: usize,
+}
+
+// The text of a source: in a string, or, with the `mmap` feature, in a file
+// mapped into memory, which is checked to be UTF-8 when it is mapped and
+// which the caller of `SourceMap::map` promised would not change.
+#[derive(Debug, Clone)]
+enum Text {
+    Owned(String),
+    #[cfg(feature = "mmap")]
+    Mapped(Arc<memmap2::Mmap>),
+}
+
+impl Text {
+    fn as_str(&self) -> &str {
+        match self {
+            Text::Owned(text) => text,
+            // SAFETY: The bytes were checked to be UTF-8 when the file was
+            // mapped, and the caller of `SourceMap::map` promised that they
+            // do not change while they are mapped.
+            #[cfg(feature = "mmap")]
+            Text::Mapped(map) => unsafe { str::from_utf8_unchecked(map) },
+        }
+    }
+}
+
+impl SourceMap {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> SourceMap {
+        SourceMap::with_text(name.into(), Text::Owned(source.into()))
+    }
+
+    /// Maps a file into memory as the source, rather than reading it into a
+    /// string, so that a large generated file is not copied before it is
+    /// lexed. The file must be UTF-8.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or written to, by this process or any
+    /// other, while the source map or any clone of it is alive. Reading a
+    /// truncated mapping may raise SIGBUS, and rewritten bytes are handed out
+    /// as a `&str` without being checked as UTF-8 again.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn map(name: impl Into<String>, path: impl AsRef<Path>) -> io::Result<SourceMap> {
+        let file = File::open(path)?;
+        // SAFETY: The caller promises that the file does not change.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        if let Err(error) = str::from_utf8(&map) {
+            let message = format!("not UTF-8 at byte {}", error.valid_up_to());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        Ok(SourceMap::with_text(
+            name.into(),
+            Text::Mapped(Arc::new(map)),
+        ))
+    }
+
+    fn with_text(name: String, source: Text) -> SourceMap {
+        let line_starts = core::iter::once(0)
+            .chain(source.as_str().match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceMap {
+            files: vec![SourceFile {
+                name,
+                start: 0,
+                first_line: 0,
+            }],
+            source,
+            line_starts,
+        }
+    }
+
+    // Puts the files of source maps one after another in a new source map.
+    // A file that does not end in a line break is given one, so that every
+    // file starts on a line of its own; `file_start` tells where each starts.
+    pub fn join<'m>(maps: impl IntoIterator<Item = &'m SourceMap>) -> SourceMap {
+        let mut source = String::new();
+        let mut files = vec![];
+        for map in maps {
+            for (i, file) in map.files.iter().enumerate() {
+                let end = map.files.get(i + 1).map_or(map.source().len(), |f| f.start);
+                files.push((file.name.clone(), source.len()));
+                source.push_str(&map.source()[file.start..end]);
+                if !source.is_empty() && !source.ends_with('\n') {
+                    source.push('\n');
+                }
+            }
+        }
+        let mut joined = SourceMap::with_text(String::new(), Text::Owned(source));
+        joined.files = files
+            .into_iter()
+            .map(|(name, start)| SourceFile {
+                name,
+                start,
+                first_line: joined.line_starts.partition_point(|&line| line < start),
+            })
+            .collect();
+        if joined.files.is_empty() {
+            joined.files.push(SourceFile {
+                name: String::new(),
+                start: 0,
+                first_line: 0,
+            });
+        }
+        joined
+    }
+
+    // The name the source is reported under, such as its path: the name of
+    // its first file.
+    pub fn name(&self) -> &str {
+        &self.files[0].name
+    }
+
+    // The name of the file an offset is in.
+    pub fn name_at(&self, offset: usize) -> &str {
+        &self.file(offset).name
+    }
+
+    // The offset of the first byte of the file at an index.
+    pub fn file_start(&self, index: usize) -> usize {
+        self.files[index].start
+    }
+
+    fn file(&self, offset: usize) -> &SourceFile {
+        let index = self.files.partition_point(|file| file.start <= offset);
+        &self.files[index.max(1) - 1]
+    }
+
+    pub fn source(&self) -> &str {
+        self.source.as_str()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    // Returns the 1-based line and column of a byte offset in its file.
+    // Columns count bytes, like `lexer::get_column`.
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let first_line = self.file(offset).first_line;
+        (line - first_line, offset - self.line_starts[line - 1] + 1)
+    }
+
+    // Returns the text of the line an offset is on, without its line break.
+    pub fn line_at(&self, offset: usize) -> &str {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.source().len(), |next| next - 1);
+        self.source()[start..end].trim_end_matches('\r')
+    }
+
+    pub fn text(&self, span: Span) -> &str {
+        &self.source()[span.range()]
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_map_to_lines_and_columns() {
+        let map = SourceMap::new("main", "let x: int32 = 1;\r\n\nx;");
+        assert_eq!(map.line_count(), 3);
+        assert_eq!(map.location(0), (1, 1));
+        assert_eq!(map.location(4), (1, 5));
+        assert_eq!(map.location(19), (2, 1));
+        assert_eq!(map.location(20), (3, 1));
+        assert_eq!(map.location(22), (3, 3));
+        assert_eq!(map.line_at(4), "let x: int32 = 1;");
+        assert_eq!(map.line_at(19), "");
+        assert_eq!(map.line_at(21), "x;");
+        assert_eq!(map.text(Span::new(4, 5)), "x");
+    }
+
+    #[test]
+    fn joined_files_keep_their_names_and_lines() {
+        let a = SourceMap::new("a.my", "fn f() {}");
+        let b = SourceMap::new("b.my", "\nf();\n");
+        let map = SourceMap::join([&a, &b]);
+        assert_eq!(map.source(), "fn f() {}\n\nf();\n");
+        assert_eq!(map.name(), "a.my");
+        assert_eq!(map.file_start(1), 10);
+        assert_eq!(map.name_at(3), "a.my");
+        assert_eq!(map.location(3), (1, 4));
+        assert_eq!(map.name_at(11), "b.my");
+        assert_eq!(map.location(11), (2, 1));
+        assert_eq!(map.line_at(12), "f();");
+        assert_eq!(SourceMap::join([&map]).location(11), (2, 1));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn files_can_be_mapped() {
+        let path = std::env::temp_dir().join(format!("mylang-map-{}.my", std::process::id()));
+        std::fs::write(&path, "let x: int32 = 1;\nx;\n").unwrap();
+        // SAFETY: Nothing writes to the file while it is mapped.
+        let map = unsafe { SourceMap::map("mapped.my", &path) }.unwrap();
+        assert_eq!(map.name(), "mapped.my");
+        assert_eq!(map.line_at(18), "x;");
+        assert_eq!(map.clone().text(Span::new(4, 5)), "x");
+
+        drop(map);
+        std::fs::write(&path, b"x;\n\xff").unwrap();
+        // SAFETY: As above.
+        let error = unsafe { SourceMap::map("mapped.my", &path) }.unwrap_err();
+        assert_eq!(error.to_string(), "not UTF-8 at byte 3");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
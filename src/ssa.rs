@@ -0,0 +1,1041 @@
+use crate::{
+    ast::{BinaryOperator, NodeId, Span, TypeKind},
+    builtin::Builtin,
+    cfg::{self, BlockId, Cfg},
+    consteval::ConstValue,
+    hir::{self, Binding, Expression, ExpressionKind},
+    printer::operator_text,
+    typecheck::Ty,
+};
+use std::{collections::HashMap, fmt};
+
+// A mid-level intermediate representation in static single assignment form,
+// between the HIR and the backends, so that optimizations can work on one
+// representation.
+//
+// A function is a graph of basic blocks, as in its CFG, but each instruction
+// computes one value from the values of other instructions instead of
+// evaluating a tree of expressions. Every value is defined once, by a
+// parameter or an instruction, and its definition dominates its uses.
+//
+// Since bindings cannot be assigned to, each is defined once and before its
+// uses, so building a function from its CFG only names the value of every
+// expression; a `let` names no new value at all. Phis are never needed to
+// build a function, but passes that merge control flow, such as inlining a
+// function that returns in more than one place, can add them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ValueId(pub u32);
+
+impl fmt::Display for ValueId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub binding: Binding,
+    pub parameters: Vec<Parameter>,
+    pub return_type: Ty,
+    // Indexed by `BlockId`. Control enters at the first block.
+    pub blocks: Vec<BasicBlock>,
+    // The number of values ids have been given to, so that passes can
+    // define new ones.
+    pub values: u32,
+    // Whether it was marked `#[inline]`.
+    pub inline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub value: ValueId,
+    pub ty: Ty,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    // Phis come first.
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub value: ValueId,
+    pub kind: InstructionKind,
+    pub ty: Ty,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum InstructionKind {
+    // A literal, in the instruction's type.
+    Constant(ConstValue),
+    // The value of a binding declared outside the function: a global, a
+    // function, or a variable of an enclosing function.
+    Outer(Binding),
+    Binary(BinaryOperator, ValueId, ValueId),
+    Call(ValueId, Vec<ValueId>),
+    Builtin(Builtin, Vec<ValueId>),
+    // A conversion to the instruction's type.
+    Cast(ValueId),
+    // The value from whichever predecessor control came from.
+    Phi(Vec<(BlockId, ValueId)>),
+}
+
+impl InstructionKind {
+    // Returns the values the instruction uses.
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            InstructionKind::Constant(_) | InstructionKind::Outer(_) => vec![],
+            InstructionKind::Binary(_, left, right) => vec![*left, *right],
+            InstructionKind::Call(callee, arguments) => {
+                let mut operands = vec![*callee];
+                operands.extend(arguments);
+                operands
+            }
+            InstructionKind::Builtin(_, arguments) => arguments.clone(),
+            InstructionKind::Cast(operand) => vec![*operand],
+            InstructionKind::Phi(incoming) => incoming.iter().map(|(_, value)| *value).collect(),
+        }
+    }
+
+    // Returns the values the instruction uses, for replacing them.
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            InstructionKind::Constant(_) | InstructionKind::Outer(_) => vec![],
+            InstructionKind::Binary(_, left, right) => vec![left, right],
+            InstructionKind::Call(callee, arguments) => {
+                let mut operands = vec![callee];
+                operands.extend(arguments);
+                operands
+            }
+            InstructionKind::Builtin(_, arguments) => arguments.iter_mut().collect(),
+            InstructionKind::Cast(operand) => vec![operand],
+            InstructionKind::Phi(incoming) => incoming.iter_mut().map(|(_, value)| value).collect(),
+        }
+    }
+}
+
+// How control leaves a basic block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    Goto(BlockId),
+    // Continues at `then_block` if the condition is true and at
+    // `else_block` otherwise.
+    Branch {
+        condition: ValueId,
+        then_block: BlockId,
+        else_block: BlockId,
+    },
+    Return(Option<ValueId>),
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Goto(target) => vec![*target],
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::Unreachable => vec![],
+        }
+    }
+
+    // Returns the values the terminator uses.
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Terminator::Branch { condition, .. } => vec![*condition],
+            Terminator::Return(Some(value)) => vec![*value],
+            Terminator::Goto(_) | Terminator::Return(None) | Terminator::Unreachable => vec![],
+        }
+    }
+
+    // Returns the values the terminator uses, for replacing them.
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Terminator::Branch { condition, .. } => vec![condition],
+            Terminator::Return(Some(value)) => vec![value],
+            Terminator::Goto(_) | Terminator::Return(None) | Terminator::Unreachable => vec![],
+        }
+    }
+}
+
+impl Function {
+    pub const ENTRY: BlockId = Cfg::ENTRY;
+
+    // Builds a function from its CFG, or returns `None` if it has no body.
+    //
+    // The program must be free of errors: it panics on
+    // `ExpressionKind::Error`.
+    pub fn build(function: &hir::Function) -> Option<Function> {
+        let cfg = Cfg::build(function)?;
+        Some(Function::from_cfg(function, &cfg))
+    }
+
+    // Builds a function from `cfg`, the graph of its body. Blocks keep their
+    // ids.
+    pub fn from_cfg(function: &hir::Function, cfg: &Cfg) -> Function {
+        let mut builder = Builder::default();
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|parameter| {
+                let value = builder.new_value();
+                builder.bindings.insert(parameter.binding.id, value);
+                Parameter {
+                    value,
+                    ty: parameter.ty.clone(),
+                }
+            })
+            .collect();
+        // The blocks of a CFG are numbered so that a binding is defined in a
+        // block before any block that uses it.
+        let blocks = cfg
+            .blocks
+            .iter()
+            .map(|block| builder.block(block))
+            .collect();
+        Function {
+            binding: function.binding.clone(),
+            parameters,
+            return_type: function.return_type.clone(),
+            blocks,
+            values: builder.values,
+            inline: function.inline,
+        }
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id.index()]
+    }
+
+    pub fn block_ids(&self) -> impl Iterator<Item = BlockId> {
+        (0..self.blocks.len() as u32).map(BlockId)
+    }
+
+    pub fn successors(&self, id: BlockId) -> Vec<BlockId> {
+        self.block(id).terminator.successors()
+    }
+
+    // Returns the predecessors of every block, indexed by `BlockId`.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        for id in self.block_ids() {
+            for successor in self.successors(id) {
+                predecessors[successor.index()].push(id);
+            }
+        }
+        predecessors
+    }
+
+    // Returns the immediate dominator of every block, indexed by `BlockId`:
+    // the last block control passes through on every path from the entry
+    // to it. The entry is its own, and blocks control never reaches have
+    // none.
+    pub fn dominators(&self) -> Vec<Option<BlockId>> {
+        // Cooper, Harvey and Kennedy's algorithm, which refines a guess in
+        // reverse postorder until it stops changing.
+        let mut order = vec![];
+        let mut visited = vec![false; self.blocks.len()];
+        let mut stack = vec![(Function::ENTRY, 0)];
+        visited[Function::ENTRY.index()] = true;
+        while let Some((id, next)) = stack.pop() {
+            match self.successors(id).get(next) {
+                Some(&successor) => {
+                    stack.push((id, next + 1));
+                    if !visited[successor.index()] {
+                        visited[successor.index()] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => order.push(id),
+            }
+        }
+        order.reverse();
+        let mut position = vec![usize::MAX; self.blocks.len()];
+        for (i, id) in order.iter().enumerate() {
+            position[id.index()] = i;
+        }
+
+        let predecessors = self.predecessors();
+        let mut dominators = vec![None; self.blocks.len()];
+        dominators[Function::ENTRY.index()] = Some(Function::ENTRY);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &id in &order[1..] {
+                let mut dominator = None;
+                for &predecessor in &predecessors[id.index()] {
+                    if dominators[predecessor.index()].is_none() {
+                        continue;
+                    }
+                    dominator = Some(match dominator {
+                        None => predecessor,
+                        Some(mut other) => {
+                            // Walk up from both to where their paths from
+                            // the entry meet.
+                            let mut block = predecessor;
+                            while block != other {
+                                while position[block.index()] > position[other.index()] {
+                                    block = dominators[block.index()].unwrap();
+                                }
+                                while position[other.index()] > position[block.index()] {
+                                    other = dominators[other.index()].unwrap();
+                                }
+                            }
+                            block
+                        }
+                    });
+                }
+                if dominators[id.index()] != dominator {
+                    dominators[id.index()] = dominator;
+                    changed = true;
+                }
+            }
+        }
+        dominators
+    }
+
+    // Checks that the function is well formed: that every value is defined
+    // once and its definition dominates its uses, that blocks exist where
+    // they are jumped to, that phis come first and have a value for each
+    // predecessor, and that operands have the types their uses expect.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let mut verifier = Verifier {
+            function: self,
+            definitions: HashMap::new(),
+            dominators: vec![],
+        };
+        verifier.verify()
+    }
+}
+
+// Builds the SSA form of every function with a body in a program, including
+// nested functions, in source order.
+pub fn build_all(program: &hir::Program) -> Vec<Function> {
+    cfg::functions(program)
+        .into_iter()
+        .filter_map(Function::build)
+        .collect()
+}
+
+#[derive(Default)]
+struct Builder {
+    values: u32,
+    // The value of every parameter and `let` binding seen so far.
+    bindings: HashMap<NodeId, ValueId>,
+    // The instructions of the block being built.
+    instructions: Vec<Instruction>,
+}
+
+impl Builder {
+    fn new_value(&mut self) -> ValueId {
+        self.values += 1;
+        ValueId(self.values - 1)
+    }
+
+    fn block(&mut self, block: &cfg::BasicBlock) -> BasicBlock {
+        for instruction in &block.instructions {
+            match instruction {
+                cfg::Instruction::Let(let_statement) => {
+                    let value = self.expression(&let_statement.value);
+                    self.bindings.insert(let_statement.binding.id, value);
+                }
+                cfg::Instruction::Expression(expression) => {
+                    self.expression(expression);
+                }
+            }
+        }
+        let terminator = match &block.terminator {
+            cfg::Terminator::Goto(target) => Terminator::Goto(*target),
+            cfg::Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => Terminator::Branch {
+                condition: self.expression(condition),
+                then_block: *then_block,
+                else_block: *else_block,
+            },
+            cfg::Terminator::Return(return_statement) => Terminator::Return(
+                return_statement
+                    .value
+                    .as_ref()
+                    .map(|value| self.expression(value)),
+            ),
+            cfg::Terminator::Unreachable => Terminator::Unreachable,
+        };
+        BasicBlock {
+            instructions: std::mem::take(&mut self.instructions),
+            terminator,
+        }
+    }
+
+    // Adds the instructions that compute an expression, returning its value.
+    fn expression(&mut self, expression: &Expression) -> ValueId {
+        let kind = match &expression.kind {
+            ExpressionKind::Integer(value) => {
+                InstructionKind::Constant(ConstValue::Integer(*value))
+            }
+            ExpressionKind::Bool(value) => InstructionKind::Constant(ConstValue::Bool(*value)),
+            ExpressionKind::Name(binding) => match self.bindings.get(&binding.id) {
+                Some(value) => return *value,
+                None => InstructionKind::Outer(binding.clone()),
+            },
+            ExpressionKind::Binary(operator, left, right) => {
+                let left = self.expression(left);
+                let right = self.expression(right);
+                InstructionKind::Binary(operator.clone(), left, right)
+            }
+            ExpressionKind::Call(callee, arguments) => {
+                let callee = self.expression(callee);
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.expression(argument))
+                    .collect();
+                InstructionKind::Call(callee, arguments)
+            }
+            ExpressionKind::Builtin(builtin, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.expression(argument))
+                    .collect();
+                InstructionKind::Builtin(*builtin, arguments)
+            }
+            ExpressionKind::Cast(operand) => InstructionKind::Cast(self.expression(operand)),
+            ExpressionKind::Error => panic!("cannot build a program with errors"),
+        };
+        let value = self.new_value();
+        self.instructions.push(Instruction {
+            value,
+            kind,
+            ty: expression.ty.clone(),
+            span: expression.span,
+        });
+        value
+    }
+}
+
+// What is wrong with a function that fails to verify, and in which block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyError {
+    pub kind: VerifyErrorKind,
+    pub block: BlockId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    Undefined(ValueId),
+    Redefined(ValueId),
+    // A use of a value whose definition does not dominate it.
+    NotDominated(ValueId),
+    NoSuchBlock(BlockId),
+    // A phi after an instruction that is not one.
+    MisplacedPhi(ValueId),
+    // A phi whose incoming blocks are not the predecessors of its block.
+    PhiPredecessors(ValueId),
+    Mismatch {
+        value: ValueId,
+        expected: Ty,
+        found: Ty,
+    },
+    NotCallable(ValueId),
+    ArgumentCount {
+        call: ValueId,
+        expected: usize,
+        found: usize,
+    },
+    // A `return` without a value in a function that returns one.
+    MissingReturnValue,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.block)?;
+        match &self.kind {
+            VerifyErrorKind::Undefined(value) => write!(f, "`{}` is not defined", value),
+            VerifyErrorKind::Redefined(value) => write!(f, "`{}` is defined again", value),
+            VerifyErrorKind::NotDominated(value) => {
+                write!(
+                    f,
+                    "`{}` is used where its definition does not dominate",
+                    value
+                )
+            }
+            VerifyErrorKind::NoSuchBlock(id) => write!(f, "no block `{}`", id),
+            VerifyErrorKind::MisplacedPhi(value) => {
+                write!(
+                    f,
+                    "phi `{}` follows an instruction that is not a phi",
+                    value
+                )
+            }
+            VerifyErrorKind::PhiPredecessors(value) => write!(
+                f,
+                "phi `{}` does not have one value for each predecessor",
+                value
+            ),
+            VerifyErrorKind::Mismatch {
+                value,
+                expected,
+                found,
+            } => write!(
+                f,
+                "mismatched types for `{}`: expected `{}`, found `{}`",
+                value, expected, found
+            ),
+            VerifyErrorKind::NotCallable(value) => write!(f, "`{}` is not a function", value),
+            VerifyErrorKind::ArgumentCount {
+                call,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` passes {} arguments to a function that takes {}",
+                call, found, expected
+            ),
+            VerifyErrorKind::MissingReturnValue => f.write_str("`return` is missing a value"),
+        }
+    }
+}
+
+struct Verifier<'f> {
+    function: &'f Function,
+    definitions: HashMap<ValueId, Definition<'f>>,
+    dominators: Vec<Option<BlockId>>,
+}
+
+// The type of a value, and where it is defined: the block and the
+// instruction's position in it, or `None` for a parameter.
+struct Definition<'f> {
+    ty: &'f Ty,
+    position: Option<(BlockId, usize)>,
+}
+
+impl<'f> Verifier<'f> {
+    fn verify(&mut self) -> Result<(), VerifyError> {
+        let function = self.function;
+        for parameter in &function.parameters {
+            self.define(parameter.value, &parameter.ty, None, Function::ENTRY)?;
+        }
+        for id in function.block_ids() {
+            let block = function.block(id);
+            for (i, instruction) in block.instructions.iter().enumerate() {
+                self.define(instruction.value, &instruction.ty, Some((id, i)), id)?;
+            }
+            for successor in block.terminator.successors() {
+                if successor.index() >= function.blocks.len() {
+                    return Err(error(VerifyErrorKind::NoSuchBlock(successor), id));
+                }
+            }
+        }
+        self.dominators = function.dominators();
+        let predecessors = function.predecessors();
+        for id in function.block_ids() {
+            let block = function.block(id);
+            let mut phis = true;
+            for (i, instruction) in block.instructions.iter().enumerate() {
+                if let InstructionKind::Phi(incoming) = &instruction.kind {
+                    let value = instruction.value;
+                    if !phis {
+                        return Err(error(VerifyErrorKind::MisplacedPhi(value), id));
+                    }
+                    let mut blocks: Vec<_> = incoming.iter().map(|(block, _)| *block).collect();
+                    let mut expected = predecessors[id.index()].clone();
+                    blocks.sort();
+                    expected.sort();
+                    expected.dedup();
+                    if blocks != expected {
+                        return Err(error(VerifyErrorKind::PhiPredecessors(value), id));
+                    }
+                    // A phi uses each value at the end of the block it
+                    // comes from.
+                    for &(predecessor, operand) in incoming {
+                        let end = function.block(predecessor).instructions.len();
+                        self.available(operand, predecessor, end)?;
+                    }
+                } else {
+                    phis = false;
+                    for operand in instruction.kind.operands() {
+                        self.available(operand, id, i)?;
+                    }
+                }
+                self.check_instruction(instruction)
+                    .map_err(|kind| error(kind, id))?;
+            }
+            self.check_terminator(&block.terminator, id)?;
+        }
+        Ok(())
+    }
+
+    fn define(
+        &mut self,
+        value: ValueId,
+        ty: &'f Ty,
+        position: Option<(BlockId, usize)>,
+        block: BlockId,
+    ) -> Result<(), VerifyError> {
+        let definition = Definition { ty, position };
+        if self.definitions.insert(value, definition).is_some() {
+            return Err(error(VerifyErrorKind::Redefined(value), block));
+        }
+        Ok(())
+    }
+
+    // Checks that `value` can be used in `block` before the instruction at
+    // `position`, returning its type.
+    fn available(
+        &self,
+        value: ValueId,
+        block: BlockId,
+        position: usize,
+    ) -> Result<&'f Ty, VerifyError> {
+        let Some(definition) = self.definitions.get(&value) else {
+            return Err(error(VerifyErrorKind::Undefined(value), block));
+        };
+        // Anything goes in a block control never reaches.
+        let dominated = match definition.position {
+            _ if self.dominators[block.index()].is_none() => true,
+            None => true,
+            Some((defined, i)) if defined == block => i < position,
+            Some((defined, _)) => self.dominates(defined, block),
+        };
+        match dominated {
+            true => Ok(definition.ty),
+            false => Err(error(VerifyErrorKind::NotDominated(value), block)),
+        }
+    }
+
+    // Returns true if every path from the entry to `block` passes through
+    // `dominator`.
+    fn dominates(&self, dominator: BlockId, mut block: BlockId) -> bool {
+        loop {
+            if block == dominator {
+                return true;
+            }
+            match self.dominators[block.index()] {
+                Some(next) if next != block => block = next,
+                _ => return false,
+            }
+        }
+    }
+
+    fn ty(&self, value: ValueId) -> &'f Ty {
+        self.definitions[&value].ty
+    }
+
+    // Checks that the types of an instruction's operands and result agree.
+    fn check_instruction(&self, instruction: &Instruction) -> Result<(), VerifyErrorKind> {
+        let value = instruction.value;
+        let expect = |value: ValueId, expected: &Ty, found: &Ty| match expected == found {
+            true => Ok(()),
+            false => Err(VerifyErrorKind::Mismatch {
+                value,
+                expected: expected.clone(),
+                found: found.clone(),
+            }),
+        };
+        match &instruction.kind {
+            InstructionKind::Constant(ConstValue::Bool(_)) => {
+                expect(value, &Ty::Primitive(TypeKind::Bool), &instruction.ty)
+            }
+            InstructionKind::Constant(_) | InstructionKind::Outer(_) | InstructionKind::Cast(_) => {
+                Ok(())
+            }
+            InstructionKind::Binary(operator, left, right) => {
+                let operands = self.ty(*left);
+                expect(*right, operands, self.ty(*right))?;
+                let result = match operator.is_comparison() {
+                    true => &Ty::Primitive(TypeKind::Bool),
+                    false => operands,
+                };
+                expect(value, result, &instruction.ty)
+            }
+            InstructionKind::Call(callee, arguments) => {
+                let Ty::Function(parameters, return_type) = self.ty(*callee) else {
+                    return Err(VerifyErrorKind::NotCallable(*callee));
+                };
+                self.check_arguments(value, parameters, arguments)?;
+                expect(value, return_type, &instruction.ty)
+            }
+            InstructionKind::Builtin(builtin, arguments) => {
+                if builtin.arity() != arguments.len() {
+                    return Err(VerifyErrorKind::ArgumentCount {
+                        call: value,
+                        expected: builtin.arity(),
+                        found: arguments.len(),
+                    });
+                }
+                expect(value, &builtin.return_type(), &instruction.ty)
+            }
+            InstructionKind::Phi(incoming) => {
+                for (_, operand) in incoming {
+                    expect(*operand, &instruction.ty, self.ty(*operand))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn check_arguments(
+        &self,
+        call: ValueId,
+        parameters: &[Ty],
+        arguments: &[ValueId],
+    ) -> Result<(), VerifyErrorKind> {
+        if parameters.len() != arguments.len() {
+            return Err(VerifyErrorKind::ArgumentCount {
+                call,
+                expected: parameters.len(),
+                found: arguments.len(),
+            });
+        }
+        for (parameter, argument) in parameters.iter().zip(arguments) {
+            let found = self.ty(*argument);
+            if parameter != found {
+                return Err(VerifyErrorKind::Mismatch {
+                    value: *argument,
+                    expected: parameter.clone(),
+                    found: found.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn check_terminator(&self, terminator: &Terminator, id: BlockId) -> Result<(), VerifyError> {
+        let end = self.function.block(id).instructions.len();
+        let (value, expected) = match terminator {
+            Terminator::Goto(_) | Terminator::Unreachable => return Ok(()),
+            Terminator::Branch { condition, .. } => (*condition, Ty::Primitive(TypeKind::Bool)),
+            Terminator::Return(None) => {
+                return match self.function.return_type == Ty::Primitive(TypeKind::Unit) {
+                    true => Ok(()),
+                    false => Err(error(VerifyErrorKind::MissingReturnValue, id)),
+                };
+            }
+            Terminator::Return(Some(value)) => (*value, self.function.return_type.clone()),
+        };
+        let found = self.available(value, id, end)?;
+        if *found != expected {
+            let kind = VerifyErrorKind::Mismatch {
+                value,
+                expected,
+                found: found.clone(),
+            };
+            return Err(error(kind, id));
+        }
+        Ok(())
+    }
+}
+
+fn error(kind: VerifyErrorKind, block: BlockId) -> VerifyError {
+    VerifyError { kind, block }
+}
+
+// A function is displayed as its signature followed by its blocks, one per
+// paragraph, with an instruction per line in the HIR's s-expression syntax:
+//
+//   fn f(v0: int64) -> bool
+//
+//   bb0:
+//       v1: int64 = 2
+//       v2: bool = (< v0 v1)
+//       return v2
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fn {}(", self.binding.name)?;
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", parameter.value, parameter.ty)?;
+        }
+        writeln!(f, ") -> {}", self.return_type)?;
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "\n{}:", BlockId(i as u32))?;
+            for instruction in &block.instructions {
+                writeln!(f, "    {}", instruction)?;
+            }
+            match &block.terminator {
+                Terminator::Goto(target) => writeln!(f, "    goto {}", target)?,
+                Terminator::Branch {
+                    condition,
+                    then_block,
+                    else_block,
+                } => writeln!(f, "    branch {} {} {}", condition, then_block, else_block)?,
+                Terminator::Return(None) => writeln!(f, "    return")?,
+                Terminator::Return(Some(value)) => writeln!(f, "    return {}", value)?,
+                Terminator::Unreachable => writeln!(f, "    unreachable")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} = ", self.value, self.ty)?;
+        let list = |f: &mut fmt::Formatter<'_>, values: &[ValueId]| {
+            for value in values {
+                write!(f, " {}", value)?;
+            }
+            f.write_str(")")
+        };
+        match &self.kind {
+            InstructionKind::Constant(value) => write!(f, "{}", value),
+            InstructionKind::Outer(binding) => write!(f, "(outer {})", binding.name),
+            InstructionKind::Binary(operator, left, right) => {
+                write!(f, "({} {} {})", operator_text(operator), left, right)
+            }
+            InstructionKind::Call(callee, arguments) => {
+                write!(f, "(call {}", callee)?;
+                list(f, arguments)
+            }
+            InstructionKind::Builtin(builtin, arguments) => {
+                write!(f, "({}", builtin.name())?;
+                list(f, arguments)
+            }
+            InstructionKind::Cast(operand) => write!(f, "(as {} {})", self.ty, operand),
+            InstructionKind::Phi(incoming) => {
+                f.write_str("(phi")?;
+                for (block, value) in incoming {
+                    write!(f, " {} {}", block, value)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Lexer, parser::Parser};
+
+    fn build(source: &str) -> Vec<Function> {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        assert!(program.typecheck().errors.is_empty(), "{}", source);
+        build_all(&program.to_hir())
+    }
+
+    #[test]
+    fn expressions_are_split_into_values() {
+        let source = "\
+let limit: int64 = 10;
+fn f(a: int32) -> int64 {
+    let b: int64 = a + 1;
+    let c: int64 = b;
+    fn g(x: int64) { println(x); }
+    while b < limit {
+        if c == 2 { return c * b; } else { g(b); }
+    }
+    return 1;
+}";
+        let functions = build(source);
+        assert_eq!(functions.len(), 2);
+        assert_eq!(
+            functions[0].to_string(),
+            "\
+fn f(v0: int32) -> int64
+
+bb0:
+    v1: int32 = 1
+    v2: int32 = (+ v0 v1)
+    v3: int64 = (as int64 v2)
+    goto bb1
+
+bb1:
+    v4: int64 = (outer limit)
+    v5: bool = (< v3 v4)
+    branch v5 bb2 bb3
+
+bb2:
+    v6: int64 = 2
+    v7: bool = (== v3 v6)
+    branch v7 bb4 bb6
+
+bb3:
+    v8: int64 = 1
+    return v8
+
+bb4:
+    v9: int64 = (* v3 v3)
+    return v9
+
+bb5:
+    goto bb1
+
+bb6:
+    v10: fn(int64) -> () = (outer g)
+    v11: () = (call v10 v3)
+    goto bb5
+
+bb7:
+    goto bb5
+
+bb8:
+    unreachable
+"
+        );
+        assert_eq!(
+            functions[1].to_string(),
+            "\
+fn g(v0: int64) -> ()
+
+bb0:
+    v1: () = (println v0)
+    return
+
+bb1:
+    unreachable
+"
+        );
+    }
+
+    #[test]
+    fn built_functions_verify() {
+        let source = "\
+fn fib(n: int32) -> int32 {
+    if n < 2 { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+fn f(x: float32, flag: bool) -> float64 {
+    let y: float64 = x * 2;
+    fn inner(z: float64) -> float64 { return z + y; }
+    if flag { return inner(y); } else { return 0; }
+    let unreachable: float64 = y + 1;
+    return unreachable;
+}
+fn g() { while 1 > 0 { g(); } }";
+        let functions = build(source);
+        assert_eq!(functions.len(), 4);
+        for function in functions {
+            assert_eq!(function.verify(), Ok(()), "{}", function);
+        }
+    }
+
+    #[test]
+    fn dominators_are_found() {
+        let functions = build("fn f(a: bool) { if a { } else { while a { } } }");
+        assert_eq!(
+            functions[0].dominators(),
+            [0, 0, 0, 0, 3, 4, 4]
+                .map(|id| Some(BlockId(id)))
+                .into_iter()
+                .chain([None])
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn phis_verify_when_they_have_a_value_for_each_predecessor() {
+        let mut function = build("fn f(a: bool) -> int8 { if a { } return 1; }").remove(0);
+        let constant = |value, number| Instruction {
+            value: ValueId(value),
+            kind: InstructionKind::Constant(ConstValue::Integer(number)),
+            ty: Ty::Primitive(TypeKind::Int8),
+            span: Span::new(0, 0),
+        };
+        let phi = |incoming| Instruction {
+            kind: InstructionKind::Phi(incoming),
+            ..constant(12, 0)
+        };
+        function.blocks[0].instructions.push(constant(10, 2));
+        function.blocks[1].instructions.push(constant(11, 3));
+        let incoming = vec![(BlockId(0), ValueId(10)), (BlockId(1), ValueId(11))];
+        function.blocks[2].instructions.insert(0, phi(incoming));
+        function.blocks[2].terminator = Terminator::Return(Some(ValueId(12)));
+        assert_eq!(function.verify(), Ok(()), "{}", function);
+        assert_eq!(
+            function.block(BlockId(2)).instructions[0].to_string(),
+            "v12: int8 = (phi bb0 v10 bb1 v11)"
+        );
+
+        let mut broken = function.clone();
+        broken.blocks[2].instructions[0] = phi(vec![(BlockId(0), ValueId(10))]);
+        assert_eq!(
+            broken.verify().unwrap_err().to_string(),
+            "bb2: phi `v12` does not have one value for each predecessor"
+        );
+        broken.blocks[2].instructions[0] =
+            phi(vec![(BlockId(0), ValueId(11)), (BlockId(1), ValueId(11))]);
+        assert_eq!(
+            broken.verify().unwrap_err().to_string(),
+            "bb0: `v11` is used where its definition does not dominate"
+        );
+    }
+
+    #[test]
+    fn malformed_functions_fail_to_verify() {
+        let source = "fn f(a: int64, b: bool) -> int64 { if b { return a + 1; } return a; }";
+        let function = build(source).remove(0);
+        let errors = [
+            (
+                "bb1: `v9` is not defined",
+                Box::new(|f: &mut Function| {
+                    f.blocks[1].terminator = Terminator::Return(Some(ValueId(9)))
+                }) as Box<dyn Fn(&mut Function)>,
+            ),
+            (
+                "bb1: `v1` is defined again",
+                Box::new(|f| f.blocks[1].instructions[0].value = ValueId(1)),
+            ),
+            (
+                "bb2: `v3` is used where its definition does not dominate",
+                Box::new(|f| f.blocks[2].terminator = Terminator::Return(Some(ValueId(3)))),
+            ),
+            (
+                "bb1: `v2` is used where its definition does not dominate",
+                Box::new(|f| f.blocks[1].instructions.swap(0, 1)),
+            ),
+            (
+                "bb0: no block `bb7`",
+                Box::new(|f| f.blocks[0].terminator = Terminator::Goto(BlockId(7))),
+            ),
+            (
+                "bb0: mismatched types for `v0`: expected `bool`, found `int64`",
+                Box::new(|f| {
+                    f.blocks[0].terminator = Terminator::Branch {
+                        condition: ValueId(0),
+                        then_block: BlockId(1),
+                        else_block: BlockId(2),
+                    }
+                }),
+            ),
+            (
+                "bb1: mismatched types for `v2`: expected `int64`, found `bool`",
+                Box::new(|f| f.blocks[1].instructions[0].ty = Ty::Primitive(TypeKind::Bool)),
+            ),
+            (
+                "bb1: `v0` is not a function",
+                Box::new(|f| {
+                    f.blocks[1].instructions[1].kind = InstructionKind::Call(ValueId(0), vec![])
+                }),
+            ),
+            (
+                "bb2: `return` is missing a value",
+                Box::new(|f| f.blocks[2].terminator = Terminator::Return(None)),
+            ),
+            (
+                "bb1: phi `v3` follows an instruction that is not a phi",
+                Box::new(|f| {
+                    f.blocks[1].instructions[1].kind =
+                        InstructionKind::Phi(vec![(BlockId(0), ValueId(0))])
+                }),
+            ),
+        ];
+        assert_eq!(function.verify(), Ok(()), "{}", function);
+        for (expected, corrupt) in errors {
+            let mut broken = function.clone();
+            corrupt(&mut broken);
+            let error = broken.verify().unwrap_err();
+            assert_eq!(error.to_string(), expected, "{}", broken);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+// Finding the name someone probably meant when they wrote one that does not
+// exist.
+
+use std::cmp::Reverse;
+
+// Returns the number of single character insertions, deletions,
+// substitutions and swaps of adjacent characters needed to turn `a` into
+// `b` (the optimal string alignment distance).
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // `rows[i][j]` is the distance between the first `i` characters of `a`
+    // and the first `j` characters of `b`.
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+// Returns the length of the longest sequence of characters that appears, in
+// order but not necessarily adjacent, in both `a` and `b`.
+fn common_subsequence(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous = vec![0; b.len() + 1];
+    for x in a.chars() {
+        let mut row = vec![0; b.len() + 1];
+        for (j, y) in b.iter().enumerate() {
+            row[j + 1] = if x == *y {
+                previous[j] + 1
+            } else {
+                previous[j + 1].max(row[j])
+            };
+        }
+        previous = row;
+    }
+    previous[b.len()]
+}
+
+// Returns the candidate closest to `name`, if any is close enough to be a
+// likely typo: at most one edit for every three characters of `name`, and
+// at least one. Among equally close candidates, the one keeping more of
+// `name`'s characters wins, so `in32` suggests `int32` rather than `int2`;
+// remaining ties go to the earliest candidate. `name` itself is never
+// suggested.
+pub fn closest<'c>(name: &str, candidates: impl IntoIterator<Item = &'c str>) -> Option<&'c str> {
+    let limit = (name.chars().count() / 3).max(1);
+    let mut best: Option<((usize, Reverse<usize>), &str)> = None;
+    for candidate in candidates {
+        if candidate == name {
+            continue;
+        }
+        let distance = edit_distance(name, candidate);
+        if distance > limit {
+            continue;
+        }
+        let key = (distance, Reverse(common_subsequence(name, candidate)));
+        if best.is_none_or(|(k, _)| key < k) {
+            best = Some((key, candidate));
+        }
+    }
+    best.map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances_count_edits() {
+        assert_eq!(edit_distance("int32", "int32"), 0);
+        assert_eq!(edit_distance("in32", "int32"), 1);
+        assert_eq!(edit_distance("retrun", "return"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn only_close_candidates_are_suggested() {
+        let candidates = ["int2", "int8", "int16", "int32", "float32"];
+        assert_eq!(closest("in32", candidates), Some("int32"));
+        assert_eq!(closest("flaot32", candidates), Some("float32"));
+        assert_eq!(closest("int", candidates), Some("int2"));
+        assert_eq!(closest("string", candidates), None);
+        assert_eq!(closest("int8", ["int8"]), None);
+    }
+}
@@ -0,0 +1,375 @@
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as Map;
+use alloc::{boxed::Box, sync::Arc};
+use core::{
+    borrow::Borrow,
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    sync::atomic::{self, AtomicU32},
+};
+#[cfg(feature = "std")]
+use std::collections::HashMap as Map;
+
+// A name, interned by the `Interner` of the session it was read in.
+//
+// An interner numbers the names it interns, so symbols from the same
+// interner are compared by number, and every symbol hashes as a hash of its
+// text computed when it was interned. Symbols from different interners, or
+// made with `Symbol::new`, are compared by text only when their hashes are
+// equal. The names in `sym` are the same symbols in every interner. The
+// text is freed when the interner and the last symbol holding it are
+// dropped, and symbols can be freely shared between threads.
+#[derive(Clone)]
+pub struct Symbol {
+    // The number of the interner that made it, or `WELL_KNOWN` or
+    // `UNINTERNED`.
+    interner: u32,
+    // Its number within its interner, or its index in `sym::TEXT`.
+    id: u32,
+    // `None` for the names in `sym`.
+    name: Option<Arc<Name>>,
+}
+
+// The text of an interned name, shared by its symbols.
+#[derive(Debug)]
+struct Name {
+    hash: u64,
+    text: Box<str>,
+}
+
+// The interner numbers of the symbols in `sym` and of those made with
+// `Symbol::new`. Interners are numbered from there on.
+const WELL_KNOWN: u32 = 0;
+const UNINTERNED: u32 = 1;
+
+// Names the compiler looks for, which every interner interns as these
+// constants.
+pub mod sym {
+    use super::Symbol;
+
+    pub const MAIN: Symbol = Symbol::well_known(0);
+    pub const PRINT: Symbol = Symbol::well_known(1);
+    pub const PRINTLN: Symbol = Symbol::well_known(2);
+    pub const READ_LINE: Symbol = Symbol::well_known(3);
+
+    pub(super) const TEXT: [&str; 4] = ["main", "print", "println", "read_line"];
+}
+
+const WELL_KNOWN_HASHES: [u64; sym::TEXT.len()] = {
+    let mut hashes = [0; sym::TEXT.len()];
+    let mut i = 0;
+    while i < hashes.len() {
+        hashes[i] = hash(sym::TEXT[i]);
+        i += 1;
+    }
+    hashes
+};
+
+// The text of the symbols read in one session, each kept once with its
+// number. It is owned by the session, or the database, that reads the
+// source, so a long-running host keeps the names of the sources it still
+// holds and no others.
+#[derive(Debug)]
+pub struct Interner {
+    // The interner's own number, which no other interner has.
+    number: u32,
+    names: Map<Key, u32>,
+    next_id: u32,
+}
+
+// A name as a key of an interner's map, which looks it up by its text.
+#[derive(Debug)]
+struct Key(Arc<Name>);
+
+impl Default for Interner {
+    fn default() -> Interner {
+        static NEXT_NUMBER: AtomicU32 = AtomicU32::new(UNINTERNED + 1);
+        Interner {
+            number: NEXT_NUMBER.fetch_add(1, atomic::Ordering::Relaxed),
+            names: Map::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    // Returns the symbol for `text`, interning it if it has not been seen
+    // before.
+    pub fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(symbol) = well_known(text) {
+            return symbol;
+        }
+        let (name, id) = match self.names.get_key_value(text) {
+            Some((Key(name), id)) => (name.clone(), *id),
+            None => {
+                let name = Arc::new(Name {
+                    hash: hash(text),
+                    text: text.into(),
+                });
+                let id = self.next_id;
+                self.next_id += 1;
+                self.names.insert(Key(name.clone()), id);
+                (name, id)
+            }
+        };
+        Symbol {
+            interner: self.number,
+            id,
+            name: Some(name),
+        }
+    }
+
+    // Forgets the names that no symbol holds any more, freeing their text,
+    // for a host that keeps one interner as its sources change.
+    pub fn forget_unused(&mut self) {
+        self.names
+            .retain(|Key(name), _| Arc::strong_count(name) > 1);
+    }
+
+    // The number of distinct names interned, not counting those in `sym`.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl Symbol {
+    // Returns a symbol for `text` that is not shared with any interner, for
+    // names made up by the compiler or looked up once.
+    pub fn new(text: &str) -> Symbol {
+        well_known(text).unwrap_or_else(|| Symbol {
+            interner: UNINTERNED,
+            id: 0,
+            name: Some(Arc::new(Name {
+                hash: hash(text),
+                text: text.into(),
+            })),
+        })
+    }
+
+    const fn well_known(id: u32) -> Symbol {
+        Symbol {
+            interner: WELL_KNOWN,
+            id,
+            name: None,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &self.name {
+            Some(name) => &name.text,
+            None => sym::TEXT[self.id as usize],
+        }
+    }
+
+    fn text_hash(&self) -> u64 {
+        match &self.name {
+            Some(name) => name.hash,
+            None => WELL_KNOWN_HASHES[self.id as usize],
+        }
+    }
+}
+
+fn well_known(text: &str) -> Option<Symbol> {
+    let id = sym::TEXT.iter().position(|known| *known == text)?;
+    Some(Symbol::well_known(id as u32))
+}
+
+// The 64-bit FNV-1a hash of `text`, which is the same in every interner and
+// every process.
+const fn hash(text: &str) -> u64 {
+    let bytes = text.as_bytes();
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+        i += 1;
+    }
+    hash
+}
+
+impl Borrow<str> for Key {
+    fn borrow(&self) -> &str {
+        &self.0.text
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.0.text == other.0.text
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.text.hash(state);
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        self.0.text.cmp(&other.0.text)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(text: &str) -> Symbol {
+        Symbol::new(text)
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        if self.interner == other.interner && self.interner != UNINTERNED {
+            return self.id == other.id;
+        }
+        self.text_hash() == other.text_hash() && self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.text_hash());
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Symbol, D::Error> {
+        let text = <alloc::borrow::Cow<str>>::deserialize(deserializer)?;
+        Ok(Symbol::new(&text))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn shared(symbol: &Symbol) -> &Arc<Name> {
+        symbol.name.as_ref().expect("not well known")
+    }
+
+    #[test]
+    fn equal_text_interns_to_the_same_symbol() {
+        let mut interner = Interner::new();
+        let a = interner.intern("interned");
+        let b = interner.intern(String::from("interned").as_str());
+        assert!(Arc::ptr_eq(shared(&a), shared(&b)));
+        assert_eq!((a.interner, a.id), (b.interner, b.id));
+        assert_eq!(a, b);
+        assert_ne!(a, interner.intern("other"));
+        assert_eq!(interner.len(), 2);
+        assert_eq!(a, Symbol::new("interned"));
+        assert_eq!(a, Interner::new().intern("interned"));
+        assert_ne!(a, Interner::new().intern("other"));
+        assert_eq!(a.as_str(), "interned");
+        assert_eq!(a, "interned");
+        assert_eq!(format!("{} {:?}", a, a), "interned \"interned\"");
+    }
+
+    #[test]
+    fn well_known_symbols_are_the_same_in_every_interner() {
+        let mut interner = Interner::new();
+        interner.intern("other");
+        let main = interner.intern("main");
+        assert_eq!((main.interner, main.id), (sym::MAIN.interner, sym::MAIN.id));
+        assert_eq!(Interner::new().intern("read_line"), sym::READ_LINE);
+        assert_eq!(Symbol::new("println"), sym::PRINTLN);
+        assert_eq!(sym::PRINT.as_str(), "print");
+        assert_eq!(std::mem::size_of::<Symbol>(), 16);
+        assert_eq!(interner.len(), 1);
+        for builtin in crate::builtin::Builtin::ALL {
+            assert_eq!(builtin.symbol(), builtin.name());
+        }
+    }
+
+    #[test]
+    fn text_is_freed_with_the_interner_and_its_symbols() {
+        let mut interner = Interner::new();
+        let symbol = interner.intern("freed");
+        let text = Arc::downgrade(shared(&symbol));
+        drop(interner);
+        assert_eq!(&*text.upgrade().unwrap().text, "freed");
+        drop(symbol);
+        assert!(text.upgrade().is_none());
+
+        let mut interner = Interner::new();
+        let kept = interner.intern("kept");
+        interner.intern("unused");
+        interner.forget_unused();
+        assert_eq!(interner.len(), 1);
+        assert_eq!(interner.intern("kept"), kept);
+    }
+
+    #[test]
+    fn symbols_can_be_shared_between_threads() {
+        let symbol = Interner::new().intern("shared");
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let symbol = symbol.clone();
+                std::thread::spawn(move || symbol)
+            })
+            .collect();
+        let symbols: Vec<Symbol> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert!(symbols.iter().all(|s| *s == symbol));
+    }
+}
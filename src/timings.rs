@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+// How long each phase of compiling a source took, and how much it made, for
+// `mylang --timings` and for profiling.
+//
+// With the `timings` feature, each phase is timed and runs in a `tracing`
+// span named `phase`, with the phase's name as its `name` field, so that a
+// subscriber sees the phases nested in whatever span the caller is in.
+// Without it, phases just run: nothing is timed or counted, and `phases` is
+// always empty.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phase {
+    pub name: &'static str,
+    pub duration: Duration,
+    // How many of what the phase made, such as tokens or nodes, if it
+    // counts them.
+    pub count: Option<(usize, &'static str)>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timings {
+    phases: Vec<Phase>,
+}
+
+impl Timings {
+    pub fn new() -> Timings {
+        Timings::default()
+    }
+
+    // The phases run so far, in the order they finished.
+    pub fn phases(&self) -> &[Phase] {
+        &self.phases
+    }
+
+    // Runs a phase, timing it.
+    #[inline]
+    pub fn time<T>(&mut self, name: &'static str, phase: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "timings")]
+        {
+            let span = tracing::info_span!("phase", name);
+            let _entered = span.enter();
+            let start = std::time::Instant::now();
+            let result = phase();
+            self.phases.push(Phase {
+                name,
+                duration: start.elapsed(),
+                count: None,
+            });
+            result
+        }
+        #[cfg(not(feature = "timings"))]
+        {
+            let _ = name;
+            phase()
+        }
+    }
+
+    // Counts what the last phase made. `count` is only called when phases
+    // are timed.
+    #[inline]
+    pub fn count(&mut self, unit: &'static str, count: impl FnOnce() -> usize) {
+        #[cfg(feature = "timings")]
+        if let Some(phase) = self.phases.last_mut() {
+            phase.count = Some((count(), unit));
+        }
+        #[cfg(not(feature = "timings"))]
+        let _ = (unit, count);
+    }
+
+    // Adds the phases of another part of the compilation after these.
+    pub fn extend(&mut self, other: Timings) {
+        self.phases.extend(other.phases);
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|phase| phase.duration).sum()
+    }
+
+    // Renders the phases as a table, one per line, then their total:
+    //
+    //   phase              time     count
+    //   lex             41.20µs  57 tokens
+    //   ...
+    //   total          310.92µs
+    pub fn render(&self) -> String {
+        let mut table = format!("{:<12} {:>12}  {}\n", "phase", "time", "count");
+        for phase in &self.phases {
+            let count = match phase.count {
+                Some((count, unit)) => format!("{} {}", count, unit),
+                None => String::new(),
+            };
+            let line = format!("{:<12} {:>12.2?}  {}", phase.name, phase.duration, count);
+            table.push_str(line.trim_end());
+            table.push('\n');
+        }
+        table + &format!("{:<12} {:>12.2?}\n", "total", self.total())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_are_timed_with_the_feature() {
+        let mut timings = Timings::new();
+        assert_eq!(timings.time("lex", || 1 + 1), 2);
+        timings.count("tokens", || 7);
+        let mut later = Timings::new();
+        later.time("parse", || ());
+        timings.extend(later);
+        let names: Vec<&str> = timings.phases().iter().map(|p| p.name).collect();
+        if cfg!(feature = "timings") {
+            assert_eq!(names, ["lex", "parse"]);
+            assert_eq!(timings.phases()[0].count, Some((7, "tokens")));
+            let report = timings.render();
+            let lines: Vec<&str> = report.lines().collect();
+            assert!(lines[1].starts_with("lex ") && lines[1].ends_with(" 7 tokens"));
+            assert!(lines[3].starts_with("total "));
+        } else {
+            assert!(names.is_empty());
+        }
+    }
+}
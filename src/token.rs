@@ -1,24 +1,37 @@
-use std::str;
+use core::str;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Kind {
     Arrow,
+    As,
     Colon,
     Comma,
     Comment,
+    Const,
     DecimalLiteral,
     Divide,
+    DocComment,
+    Else,
     EndOfFile,
+    EqualEqual,
     EqualSign,
     Fn,
+    GreaterEqual,
+    GreaterThan,
     Identifier,
+    If,
+    Import,
     IntegerLiteral,
     LeftBrace,
     LeftParenthesis,
     LeftSquareBracket,
+    LessEqual,
+    LessThan,
     Let,
     Minus,
     Mut,
+    NotEqual,
     Plus,
     Return,
     RightBrace,
@@ -26,11 +39,63 @@ pub enum Kind {
     RightSquareBracket,
     Semicolon,
     Star,
+    StarStar,
     String,
     Unknown,
+    While,
     Whitespace,
 }
 
+impl core::fmt::Display for Kind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let text = match self {
+            Kind::Arrow => "'->'",
+            Kind::As => "'as'",
+            Kind::Colon => "':'",
+            Kind::Comma => "','",
+            Kind::Comment => "comment",
+            Kind::Const => "'const'",
+            Kind::DecimalLiteral => "decimal literal",
+            Kind::Divide => "'/'",
+            Kind::DocComment => "doc comment",
+            Kind::Else => "'else'",
+            Kind::EndOfFile => "end of file",
+            Kind::EqualEqual => "'=='",
+            Kind::EqualSign => "'='",
+            Kind::Fn => "'fn'",
+            Kind::GreaterEqual => "'>='",
+            Kind::GreaterThan => "'>'",
+            Kind::Identifier => "identifier",
+            Kind::If => "'if'",
+            Kind::Import => "'import'",
+            Kind::IntegerLiteral => "integer literal",
+            Kind::LeftBrace => "'{'",
+            Kind::LeftParenthesis => "'('",
+            Kind::LeftSquareBracket => "'['",
+            Kind::LessEqual => "'<='",
+            Kind::LessThan => "'<'",
+            Kind::Let => "'let'",
+            Kind::Minus => "'-'",
+            Kind::Mut => "'mut'",
+            Kind::NotEqual => "'!='",
+            Kind::Plus => "'+'",
+            Kind::Return => "'return'",
+            Kind::RightBrace => "'}'",
+            Kind::RightParenthesis => "')'",
+            Kind::RightSquareBracket => "']'",
+            Kind::Semicolon => "';'",
+            Kind::Star => "'*'",
+            Kind::StarStar => "'**'",
+            Kind::String => "string literal",
+            Kind::Unknown => "unknown token",
+            Kind::While => "'while'",
+            Kind::Whitespace => "whitespace",
+        };
+        f.write_str(text)
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct Token<'a> {
     source: &'a [u8],
     offset: usize,
@@ -39,7 +104,7 @@ pub struct Token<'a> {
 }
 
 impl core::fmt::Debug for Token<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Token")
             .field("text", &self.text())
             .field("offset", &self.offset())
@@ -62,11 +127,11 @@ impl<'a> Token<'a> {
         self.kind
     }
 
-    pub fn source(&self) -> &[u8] {
+    pub fn source(&self) -> &'a [u8] {
         self.source
     }
 
-    pub fn text(&self) -> &str {
+    pub fn text(&self) -> &'a str {
         match self.kind {
             Kind::EndOfFile => "<EOF>",
             _ => str::from_utf8(&self.source[self.offset..self.offset + self.len]).unwrap(),
@@ -95,9 +160,36 @@ impl<'a> Token<'a> {
     }
 }
 
+// A source of tokens, read one at a time up to the end-of-file token, which
+// is then read again each time another token is asked for. A `Lexer` is one,
+// reading each token from the source as it is asked for, and so are tokens
+// already read into a slice.
+pub trait TokenStream<'a> {
+    fn next_token(&mut self) -> Token<'a>;
+}
+
+// The slice must end with an end-of-file token.
+impl<'a> TokenStream<'a> for &[Token<'a>] {
+    fn next_token(&mut self) -> Token<'a> {
+        let (token, rest) = self
+            .split_first()
+            .expect("tokens end with an end-of-file token");
+        if token.kind() != Kind::EndOfFile {
+            *self = rest;
+        }
+        *token
+    }
+}
+
 pub(crate) static KEYWORDS: phf::Map<&'static str, Kind> = phf::phf_map! {
     "let"=> Kind::Let,
+    "const"=> Kind::Const,
     "fn"=> Kind::Fn,
     "mut"=> Kind::Mut,
     "return"=> Kind::Return,
+    "if"=> Kind::If,
+    "else"=> Kind::Else,
+    "while"=> Kind::While,
+    "as"=> Kind::As,
+    "import"=> Kind::Import,
 };
@@ -0,0 +1,1410 @@
+use crate::{
+    ast::{
+        BinaryExpression, BinaryOperator, Block, CallExpression, CastExpression, Expression,
+        FunctionDeclaration, IntegerLiteral, NodeId, NodeMap, Program, Span, Spanned, Statement,
+        TypeExpr, TypeKind,
+    },
+    builtin::Builtin,
+    consteval::{evaluate_consts, ConstErrorKind, ConstValue, Consts},
+    diagnostic::{Diagnostic, DiagnosticSink},
+    printer::operator_text,
+    resolver::Resolution,
+    visit::{self, Control, Visitor},
+};
+use std::fmt;
+
+// A type as the checker sees it, built from a type annotation or inferred for
+// an expression.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Ty {
+    // A primitive or unit type.
+    Primitive(TypeKind),
+    // An integer literal, or arithmetic on integer literals only, whose width
+    // has not been fixed by the context it is used in.
+    IntegerLiteral,
+    Array(Box<Ty>, u64),
+    Tuple(Vec<Ty>),
+    Function(Vec<Ty>, Box<Ty>),
+    // The type of something that already has an error. It is compatible with
+    // every type, so one mistake is reported once.
+    Error,
+}
+
+impl Ty {
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Ty::Primitive(kind) if kind.is_integer())
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        match self {
+            Ty::Primitive(kind) => kind.is_integer() || kind.is_float(),
+            Ty::IntegerLiteral => true,
+            _ => false,
+        }
+    }
+
+    // Returns true if an integer literal can be given this type, which is
+    // any integer or float type.
+    pub fn takes_literals(&self) -> bool {
+        matches!(self, Ty::Primitive(kind) if kind.is_integer() || kind.is_float())
+    }
+
+    // Returns true if a value of type `found` can be used where `self` is
+    // expected.
+    pub fn accepts(&self, found: &Ty) -> bool {
+        match (self, found) {
+            (Ty::Error, _) | (_, Ty::Error) => true,
+            (expected, Ty::IntegerLiteral) => expected.takes_literals(),
+            (Ty::Primitive(expected), Ty::Primitive(found)) => widens(*found, *expected),
+            (expected, found) => expected == found,
+        }
+    }
+}
+
+// Returns true if every value of type `from` is represented exactly in type
+// `to`, so that a `from` can be used where a `to` is expected without a cast.
+// Integers widen to wider integers, and floats to floats with at least as
+// much range and precision. An integer converts to a float whose significand
+// holds every value of the integer type, so `int16` converts to `float32` but
+// `int32` does not. Every other conversion needs an `as` cast.
+pub fn widens(from: TypeKind, to: TypeKind) -> bool {
+    if from == to {
+        return true;
+    }
+    match (from.integer_range(), to.integer_range(), to.float_format()) {
+        (Some((from_min, from_max)), Some((to_min, to_max)), _) => {
+            to_min <= from_min && from_max <= to_max
+        }
+        (Some((min, max)), None, Some((_, precision))) => {
+            min.unsigned_abs().max(max.unsigned_abs()) <= 1 << precision
+        }
+        (None, None, Some((to_exponent, to_precision))) => {
+            from.float_format().is_some_and(|(exponent, precision)| {
+                exponent <= to_exponent && precision <= to_precision
+            })
+        }
+        _ => false,
+    }
+}
+
+// Returns the type the operands of a binary operator are converted to before
+// it is applied: the wider of the two types, or, for two floats neither of
+// which widens to the other, the narrowest of `float32` and `float64` that
+// both do. So `float16` and `bfloat16`, which each have range or precision
+// the other lacks, are computed in `float32`. Other mixes, such as `int32`
+// and `float16`, need an explicit cast.
+pub fn promote(left: TypeKind, right: TypeKind) -> Option<TypeKind> {
+    if widens(left, right) {
+        Some(right)
+    } else if widens(right, left) {
+        Some(left)
+    } else if left.is_float() && right.is_float() {
+        [TypeKind::Float32, TypeKind::Float64]
+            .into_iter()
+            .find(|&kind| widens(left, kind) && widens(right, kind))
+    } else {
+        None
+    }
+}
+
+// Rounds an integer to the nearest value of a float type, ties to even, as a
+// literal given that type is stored. Returns `None` if the result is too large
+// for the type and would become infinite, or too large for an `i128`.
+pub fn round_to_float(value: i128, kind: TypeKind) -> Option<i128> {
+    let (_, precision) = kind.float_format()?;
+    let magnitude = value.unsigned_abs();
+    let bits = u128::BITS - magnitude.leading_zeros();
+    let rounded = if bits <= precision {
+        magnitude
+    } else {
+        let shift = bits - precision;
+        let mut significand = magnitude >> shift;
+        let remainder = magnitude & ((1 << shift) - 1);
+        let half = 1 << (shift - 1);
+        if remainder > half || (remainder == half && significand & 1 == 1) {
+            significand += 1;
+        }
+        significand << shift
+    };
+    if largest_float(kind).is_some_and(|max| rounded > max as u128) {
+        return None;
+    }
+    let rounded = i128::try_from(rounded).ok()?;
+    Some(if value < 0 { -rounded } else { rounded })
+}
+
+// Returns the largest finite value of a float type, (2^precision - 1) *
+// 2^(max_exponent - precision + 1), or `None` if it is larger than every
+// `i128`, as it is for `bfloat16` and wider types.
+fn largest_float(kind: TypeKind) -> Option<i128> {
+    let (exponent, precision) = kind.float_format()?;
+    let max_exponent = (1 << (exponent - 1)) - 1;
+    if max_exponent >= 127 {
+        return None;
+    }
+    Some(((1 << precision) - 1) << (max_exponent - precision + 1))
+}
+
+// Returns true if an `as` cast may convert a `from` to a `to`. Casts convert
+// between any two numeric types, truncating or rounding as needed, and from
+// `bool` to an integer type.
+pub fn can_cast(from: TypeKind, to: TypeKind) -> bool {
+    let numeric = |kind: TypeKind| kind.is_integer() || kind.is_float();
+    from == to || (numeric(to) && numeric(from)) || (from == TypeKind::Bool && to.is_integer())
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Primitive(kind) => write!(f, "{}", kind),
+            Ty::IntegerLiteral => f.write_str("{integer}"),
+            Ty::Array(element, size) => write!(f, "[{}; {}]", element, size),
+            Ty::Tuple(types) => {
+                f.write_str("(")?;
+                write_list(f, types)?;
+                f.write_str(")")
+            }
+            Ty::Function(parameters, return_type) => {
+                f.write_str("fn(")?;
+                write_list(f, parameters)?;
+                write!(f, ") -> {}", return_type)
+            }
+            Ty::Error => f.write_str("{error}"),
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, types: &[Ty]) -> fmt::Result {
+    for (i, ty) in types.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", ty)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeErrorKind {
+    // An expression's type is not the one its context requires.
+    Mismatch {
+        expected: Ty,
+        found: Ty,
+    },
+    // A binary operator applied to operands it does not accept.
+    InvalidOperands {
+        operator: BinaryOperator,
+        left: Ty,
+        right: Ty,
+    },
+    NotCallable(Ty),
+    InvalidCast {
+        from: Ty,
+        to: Ty,
+    },
+    ArgumentCount {
+        expected: usize,
+        found: usize,
+    },
+    ReturnOutsideFunction,
+    // A function with a non-unit return type in which some path reaches the
+    // end of the body without returning.
+    MissingReturn(Ty),
+    // An array size that is not a non-negative integer.
+    InvalidArraySize,
+    // A constant or an array size that could not be evaluated.
+    Const(ConstErrorKind),
+    // An integer literal outside the range of the type it was given.
+    LiteralOutOfRange {
+        ty: Ty,
+        min: i128,
+        max: i128,
+    },
+    // A builtin function used other than by calling it.
+    BuiltinNotCalled(Builtin),
+    // An argument to `print` or `println` that is not a printable value.
+    NotPrintable(Ty),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            TypeErrorKind::Mismatch { expected, found } => write!(
+                f,
+                "mismatched types: expected `{}`, found `{}`",
+                expected, found
+            ),
+            TypeErrorKind::InvalidOperands {
+                operator,
+                left,
+                right,
+            } => write!(
+                f,
+                "cannot apply `{}` to `{}` and `{}`",
+                operator_text(operator),
+                left,
+                right
+            ),
+            TypeErrorKind::NotCallable(ty) => write!(f, "`{}` is not a function", ty),
+            TypeErrorKind::InvalidCast { from, to } => {
+                write!(f, "cannot cast `{}` to `{}`", from, to)
+            }
+            TypeErrorKind::ArgumentCount { expected, found } => write!(
+                f,
+                "expected {} argument{}, found {}",
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found
+            ),
+            TypeErrorKind::ReturnOutsideFunction => f.write_str("`return` outside of a function"),
+            TypeErrorKind::MissingReturn(ty) => {
+                write!(f, "not all paths return a value of type `{}`", ty)
+            }
+            TypeErrorKind::InvalidArraySize => {
+                f.write_str("array size must be a non-negative integer")
+            }
+            TypeErrorKind::Const(kind) => kind.fmt(f),
+            TypeErrorKind::LiteralOutOfRange { ty, min, max } => write!(
+                f,
+                "literal out of range for `{}`: expected a value in {}..={}",
+                ty, min, max
+            ),
+            TypeErrorKind::BuiltinNotCalled(builtin) => {
+                write!(f, "builtin `{}` can only be called", builtin.name())
+            }
+            TypeErrorKind::NotPrintable(ty) => write!(f, "cannot print a value of type `{}`", ty),
+        }
+    }
+}
+
+impl TypeError {
+    pub fn code(&self) -> &'static str {
+        match &self.kind {
+            TypeErrorKind::Mismatch { .. } => "E0300",
+            TypeErrorKind::InvalidOperands { .. } => "E0301",
+            TypeErrorKind::NotCallable(_) => "E0302",
+            TypeErrorKind::InvalidCast { .. } => "E0303",
+            TypeErrorKind::ArgumentCount { .. } => "E0304",
+            TypeErrorKind::ReturnOutsideFunction => "E0305",
+            TypeErrorKind::MissingReturn(_) => "E0306",
+            TypeErrorKind::InvalidArraySize => "E0307",
+            TypeErrorKind::LiteralOutOfRange { .. } => "E0308",
+            TypeErrorKind::BuiltinNotCalled(_) => "E0309",
+            TypeErrorKind::NotPrintable(_) => "E0310",
+            TypeErrorKind::Const(kind) => kind.code(),
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.code(), self.to_string(), self.span);
+        match &self.kind {
+            // A numeric value that only fails to widen can be converted.
+            TypeErrorKind::Mismatch {
+                expected: Ty::Primitive(to),
+                found: Ty::Primitive(from),
+            } if (from.is_integer() || from.is_float()) && can_cast(*from, *to) => diagnostic
+                .with_suggestion(
+                    "convert it explicitly",
+                    Span::new(self.span.end, self.span.end),
+                    format!(" as {}", to),
+                ),
+            TypeErrorKind::MissingReturn(_) => diagnostic
+                .with_note("the end of the function body can be reached without a `return`"),
+            _ => diagnostic,
+        }
+    }
+}
+
+// The result of type checking.
+#[derive(Debug, Default)]
+pub struct TypeCheck {
+    // The type of every expression, keyed by expression id.
+    pub types: NodeMap<Ty>,
+    // The type of every declaration, keyed by the id of its declaring
+    // identifier.
+    pub declarations: NodeMap<Ty>,
+    // The values of the program's constants.
+    pub consts: Consts,
+    // Type errors and constant evaluation errors, in source order.
+    pub errors: Vec<TypeError>,
+    // Float literals that round to a different value.
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl TypeCheck {
+    pub fn report(&self, sink: &mut dyn DiagnosticSink) {
+        for error in &self.errors {
+            sink.emit(error.to_diagnostic());
+        }
+        for warning in &self.warnings {
+            sink.emit(warning.clone());
+        }
+    }
+
+    pub fn type_of(&self, expression: &Expression) -> Option<&Ty> {
+        self.types.get(expression_id(expression))
+    }
+}
+
+fn expression_id(expression: &Expression) -> NodeId {
+    match expression {
+        Expression::IntegerLiteral(i) => i.id,
+        Expression::Identifier(i) => i.id,
+        Expression::BinaryExpression(b) => b.id,
+        Expression::Call(c) => c.id,
+        Expression::Cast(c) => c.id,
+    }
+}
+
+// Checks that let initializers match their annotations, that operators are
+// applied to compatible operands, that calls match the callee's signature and
+// that `return` statements return the enclosing function's declared type.
+//
+// A value of a numeric type can be used where a wider type is expected, as
+// defined by `widens`; other conversions need an `as` cast. Integer literals
+// take their width from the context they are used in, and default to `int32`
+// where nothing fixes it. Identifiers that did not resolve
+// have the error type; the resolver has already reported them.
+pub fn typecheck(program: &Program, resolution: &Resolution) -> TypeCheck {
+    let mut checker = Checker {
+        resolution,
+        return_type: None,
+        check: TypeCheck {
+            consts: evaluate_consts(program, resolution),
+            ..TypeCheck::default()
+        },
+    };
+    // Top-level functions can be called before they are declared.
+    for statement in &program.statements {
+        if let Statement::FunctionDeclaration(function) = statement {
+            checker.declare_function(function);
+        }
+    }
+    for statement in &program.statements {
+        checker.statement(statement, true);
+    }
+    let mut check = checker.check;
+    let mut overflow = OverflowCheck {
+        resolution,
+        check: &check,
+        errors: vec![],
+    };
+    visit::walk_program(&mut overflow, program);
+    let overflows = overflow.errors;
+    check.errors.extend(overflows);
+    for error in std::mem::take(&mut check.consts.errors) {
+        check.errors.push(TypeError {
+            kind: TypeErrorKind::Const(error.kind),
+            span: error.span,
+        });
+    }
+    check.errors.sort_by_key(|e| e.span.start);
+    check
+}
+
+// Reports arithmetic on literals and constants that overflows the integer
+// type it is computed in, such as `127 + 1` given `int8`, which would
+// otherwise wrap when the program runs. Constant declarations and array sizes
+// are evaluated, and their overflow reported, by `consteval`.
+struct OverflowCheck<'r> {
+    resolution: &'r Resolution,
+    check: &'r TypeCheck,
+    errors: Vec<TypeError>,
+}
+
+impl<'ast> Visitor<'ast> for OverflowCheck<'_> {
+    fn visit_statement(&mut self, statement: &'ast Statement<'ast>) -> Control {
+        match statement {
+            Statement::Const(_) => Control::SkipChildren,
+            _ => Control::Continue,
+        }
+    }
+
+    fn visit_type(&mut self, _ttype: &'ast TypeExpr<'ast>) -> Control {
+        Control::SkipChildren
+    }
+
+    fn visit_expression(&mut self, expression: &'ast Expression<'ast>) -> Control {
+        let (Expression::BinaryExpression(_), Some(Ty::Primitive(kind))) =
+            (expression, self.check.type_of(expression))
+        else {
+            return Control::Continue;
+        };
+        if !kind.is_integer() {
+            return Control::Continue;
+        }
+        match self
+            .check
+            .consts
+            .evaluate(expression, *kind, self.resolution)
+        {
+            Ok(_) => Control::SkipChildren,
+            Err(Some(error)) if matches!(error.kind, ConstErrorKind::Overflow(_)) => {
+                // A literal too large for the type on its own is already
+                // reported as out of range.
+                if !self.check.errors.iter().any(|e| e.span == error.span) {
+                    self.errors.push(TypeError {
+                        kind: TypeErrorKind::Const(error.kind),
+                        span: error.span,
+                    });
+                }
+                Control::SkipChildren
+            }
+            // Part of the expression is not constant, but its operands may be.
+            Err(_) => Control::Continue,
+        }
+    }
+}
+
+struct Checker<'r> {
+    resolution: &'r Resolution,
+    // The declared return type of the innermost enclosing function.
+    return_type: Option<Ty>,
+    check: TypeCheck,
+}
+
+impl Checker<'_> {
+    fn error(&mut self, kind: TypeErrorKind, span: Span) {
+        self.check.errors.push(TypeError { kind, span });
+    }
+
+    fn lower(&mut self, ttype: &TypeExpr) -> Ty {
+        match ttype {
+            // The resolver has reported the unknown name.
+            TypeExpr::Named(t) if t.kind == TypeKind::Named => Ty::Error,
+            TypeExpr::Named(t) => Ty::Primitive(t.kind),
+            TypeExpr::Array(array) => {
+                let element = self.lower(&array.element);
+                self.expect(&array.size, &Ty::Primitive(TypeKind::Int64));
+                let size =
+                    self.check
+                        .consts
+                        .evaluate(&array.size, TypeKind::Int64, self.resolution);
+                match size {
+                    Ok(ConstValue::Integer(size)) if size >= 0 => {
+                        Ty::Array(Box::new(element), size as u64)
+                    }
+                    Ok(_) => {
+                        self.error(TypeErrorKind::InvalidArraySize, array.size.span());
+                        Ty::Error
+                    }
+                    Err(Some(error)) => {
+                        self.error(TypeErrorKind::Const(error.kind), error.span);
+                        Ty::Error
+                    }
+                    Err(None) => Ty::Error,
+                }
+            }
+            // No type takes arguments, so the resolver has reported the base
+            // as unknown.
+            TypeExpr::Generic(generic) => {
+                self.lower_all(&generic.arguments);
+                Ty::Error
+            }
+            TypeExpr::Tuple(types) if types.is_empty() => Ty::Primitive(TypeKind::Unit),
+            TypeExpr::Tuple(types) => Ty::Tuple(self.lower_all(types)),
+            TypeExpr::Function(function) => {
+                let parameters = self.lower_all(&function.parameters);
+                Ty::Function(parameters, Box::new(self.lower(&function.return_type)))
+            }
+        }
+    }
+
+    fn lower_all(&mut self, types: &[TypeExpr]) -> Vec<Ty> {
+        types.iter().map(|t| self.lower(t)).collect()
+    }
+
+    fn declare_function(&mut self, function: &FunctionDeclaration) -> Ty {
+        let mut parameters = vec![];
+        for parameter in &function.parameters {
+            let ty = self.lower(&parameter.ttype);
+            self.check
+                .declarations
+                .insert(parameter.identifier.id, ty.clone());
+            parameters.push(ty);
+        }
+        let return_type = self.lower(&function.return_type);
+        self.check.declarations.insert(
+            function.identifier.id,
+            Ty::Function(parameters, Box::new(return_type.clone())),
+        );
+        return_type
+    }
+
+    fn statement(&mut self, statement: &Statement, top_level: bool) {
+        match statement {
+            Statement::Let(let_statement) => {
+                let ty = self.lower(&let_statement.ttype);
+                self.expect(&let_statement.expression, &ty);
+                self.check
+                    .declarations
+                    .insert(let_statement.identifier.id, ty);
+            }
+            Statement::Const(constant) => {
+                let ty = self.lower(&constant.ttype);
+                self.expect(&constant.expression, &ty);
+                self.check.declarations.insert(constant.identifier.id, ty);
+            }
+            Statement::FunctionDeclaration(function) => {
+                let return_type = if top_level {
+                    match self.check.declarations.get(function.identifier.id) {
+                        Some(Ty::Function(_, return_type)) => (**return_type).clone(),
+                        _ => self.declare_function(function),
+                    }
+                } else {
+                    self.declare_function(function)
+                };
+                if let Some(body) = &function.body {
+                    let outer = self.return_type.replace(return_type.clone());
+                    self.block(body);
+                    self.return_type = outer;
+                    if !return_type.accepts(&Ty::Primitive(TypeKind::Unit))
+                        && !always_returns(&body.statements)
+                    {
+                        self.error(
+                            TypeErrorKind::MissingReturn(return_type),
+                            function.signature_span(),
+                        );
+                    }
+                }
+            }
+            Statement::Expression(expression) => {
+                let ty = self.expression(expression);
+                self.default_literal(expression, &ty);
+            }
+            Statement::Return(return_statement) => {
+                let Some(return_type) = self.return_type.clone() else {
+                    self.error(TypeErrorKind::ReturnOutsideFunction, return_statement.span);
+                    if let Some(expression) = &return_statement.expression {
+                        let ty = self.expression(expression);
+                        self.default_literal(expression, &ty);
+                    }
+                    return;
+                };
+                match &return_statement.expression {
+                    Some(expression) => self.expect(expression, &return_type),
+                    None if return_type.accepts(&Ty::Primitive(TypeKind::Unit)) => {}
+                    None => self.error(
+                        TypeErrorKind::Mismatch {
+                            expected: return_type,
+                            found: Ty::Primitive(TypeKind::Unit),
+                        },
+                        return_statement.span,
+                    ),
+                }
+            }
+            Statement::If(if_statement) => {
+                self.expect(&if_statement.condition, &Ty::Primitive(TypeKind::Bool));
+                self.block(&if_statement.then_block);
+                if let Some(else_branch) = &if_statement.else_branch {
+                    self.statement(else_branch, false);
+                }
+            }
+            Statement::While(while_statement) => {
+                self.expect(&while_statement.condition, &Ty::Primitive(TypeKind::Bool));
+                self.block(&while_statement.body);
+            }
+            Statement::Block(block) => self.block(block),
+            Statement::Import(_) => {}
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        for statement in &block.statements {
+            self.statement(statement, false);
+        }
+    }
+
+    // Checks that an expression has the expected type, fixing the width of
+    // any integer literals in it.
+    fn expect(&mut self, expression: &Expression, expected: &Ty) {
+        let found = self.expression(expression);
+        if found == Ty::IntegerLiteral && expected.takes_literals() {
+            self.settle(expression, expected);
+        } else if !expected.accepts(&found) {
+            self.default_literal(expression, &found);
+            self.error(
+                TypeErrorKind::Mismatch {
+                    expected: expected.clone(),
+                    found,
+                },
+                expression.span(),
+            );
+        }
+    }
+
+    // Infers and records the type of an expression.
+    fn expression(&mut self, expression: &Expression) -> Ty {
+        let ty = match expression {
+            Expression::IntegerLiteral(_) => Ty::IntegerLiteral,
+            Expression::Identifier(identifier) => {
+                if let Some(builtin) = self.resolution.builtins.get(identifier.id) {
+                    self.error(TypeErrorKind::BuiltinNotCalled(*builtin), identifier.span);
+                }
+                self.resolution
+                    .uses
+                    .get(identifier.id)
+                    .and_then(|declaration| self.check.declarations.get(*declaration))
+                    .cloned()
+                    .unwrap_or(Ty::Error)
+            }
+            Expression::BinaryExpression(binary) => self.binary(binary),
+            Expression::Call(call) => self.call(call),
+            Expression::Cast(cast) => self.cast(cast),
+        };
+        self.check
+            .types
+            .insert(expression_id(expression), ty.clone());
+        ty
+    }
+
+    fn binary(&mut self, binary: &BinaryExpression) -> Ty {
+        let left = self.expression(&binary.left);
+        let right = self.expression(&binary.right);
+        let operand = match (&left, &right) {
+            (Ty::Error, _) | (_, Ty::Error) => Some(Ty::Error),
+            (Ty::IntegerLiteral, Ty::IntegerLiteral) => Some(Ty::IntegerLiteral),
+            (Ty::IntegerLiteral, other) if other.takes_literals() => {
+                self.settle(&binary.left, other);
+                Some(other.clone())
+            }
+            (other, Ty::IntegerLiteral) if other.takes_literals() => {
+                self.settle(&binary.right, other);
+                Some(other.clone())
+            }
+            (Ty::Primitive(left), Ty::Primitive(right)) => {
+                promote(*left, *right).map(Ty::Primitive)
+            }
+            (left, right) if right.accepts(left) => Some(right.clone()),
+            (left, right) if left.accepts(right) => Some(left.clone()),
+            _ => None,
+        };
+        let accepted = match &operand {
+            Some(Ty::Error) => true,
+            Some(ty) => match binary.operator {
+                BinaryOperator::Equal | BinaryOperator::NotEqual => true,
+                _ => ty.is_numeric(),
+            },
+            None => false,
+        };
+        if !accepted {
+            self.error(
+                TypeErrorKind::InvalidOperands {
+                    operator: binary.operator.clone(),
+                    left,
+                    right,
+                },
+                binary.span,
+            );
+            return Ty::Error;
+        }
+        let operand = operand.unwrap();
+        if binary.operator.is_comparison() {
+            if operand == Ty::IntegerLiteral {
+                self.settle(&binary.left, &Ty::Primitive(TypeKind::Int32));
+                self.settle(&binary.right, &Ty::Primitive(TypeKind::Int32));
+            }
+            Ty::Primitive(TypeKind::Bool)
+        } else {
+            operand
+        }
+    }
+
+    fn call(&mut self, call: &CallExpression) -> Ty {
+        if let Expression::Identifier(identifier) = &*call.callee {
+            if let Some(builtin) = self.resolution.builtins.get(identifier.id) {
+                return self.builtin_call(*builtin, call);
+            }
+        }
+        let callee = self.expression(&call.callee);
+        let (parameters, return_type) = match callee {
+            Ty::Function(parameters, return_type) => (parameters, *return_type),
+            Ty::Error => (vec![], Ty::Error),
+            other => {
+                self.error(TypeErrorKind::NotCallable(other), call.callee.span());
+                (vec![], Ty::Error)
+            }
+        };
+        if return_type != Ty::Error && parameters.len() != call.arguments.len() {
+            self.error(
+                TypeErrorKind::ArgumentCount {
+                    expected: parameters.len(),
+                    found: call.arguments.len(),
+                },
+                call.span,
+            );
+        }
+        for (i, argument) in call.arguments.iter().enumerate() {
+            match parameters.get(i) {
+                Some(parameter) => self.expect(argument, parameter),
+                None => {
+                    let ty = self.expression(argument);
+                    self.default_literal(argument, &ty);
+                }
+            }
+        }
+        return_type
+    }
+
+    // Checks a call of a builtin, whose callee has no type of its own.
+    fn builtin_call(&mut self, builtin: Builtin, call: &CallExpression) -> Ty {
+        if call.arguments.len() != builtin.arity() {
+            self.error(
+                TypeErrorKind::ArgumentCount {
+                    expected: builtin.arity(),
+                    found: call.arguments.len(),
+                },
+                call.span,
+            );
+        }
+        for argument in &call.arguments {
+            let ty = self.expression(argument);
+            self.default_literal(argument, &ty);
+            let printable = match self.type_of(argument) {
+                Ty::Primitive(kind) => TypeKind::PRIMITIVES.contains(&kind),
+                Ty::Error => true,
+                _ => false,
+            };
+            if builtin.arity() > 0 && !printable {
+                let ty = self.type_of(argument);
+                self.error(TypeErrorKind::NotPrintable(ty), argument.span());
+            }
+        }
+        builtin.return_type()
+    }
+
+    fn cast(&mut self, cast: &CastExpression) -> Ty {
+        let from = self.expression(&cast.expression);
+        let to = self.lower(&cast.ttype);
+        // A literal cast to an integer type must fit in it.
+        if to.is_integer() {
+            self.settle(&cast.expression, &to);
+        } else {
+            self.default_literal(&cast.expression, &from);
+        }
+        let from = self.type_of(&cast.expression);
+        match (&from, &to) {
+            (Ty::Error, _) | (_, Ty::Error) => to,
+            (Ty::Primitive(from), Ty::Primitive(to)) if can_cast(*from, *to) => Ty::Primitive(*to),
+            _ => {
+                self.error(TypeErrorKind::InvalidCast { from, to }, cast.span);
+                Ty::Error
+            }
+        }
+    }
+
+    fn type_of(&self, expression: &Expression) -> Ty {
+        self.check.type_of(expression).cloned().unwrap_or(Ty::Error)
+    }
+
+    // Gives an expression inferred as `Ty::IntegerLiteral` a width, where
+    // nothing else fixes it.
+    fn default_literal(&mut self, expression: &Expression, ty: &Ty) {
+        if *ty == Ty::IntegerLiteral {
+            self.settle(expression, &Ty::Primitive(TypeKind::Int32));
+        }
+    }
+
+    // Records `ty` as the type of an expression inferred as
+    // `Ty::IntegerLiteral` and of the literals it is made of.
+    fn settle(&mut self, expression: &Expression, ty: &Ty) {
+        if self.check.types.get(expression_id(expression)) != Some(&Ty::IntegerLiteral) {
+            return;
+        }
+        self.check
+            .types
+            .insert(expression_id(expression), ty.clone());
+        match expression {
+            Expression::IntegerLiteral(literal) => self.check_range(literal, ty),
+            Expression::BinaryExpression(binary) => {
+                self.settle(&binary.left, ty);
+                self.settle(&binary.right, ty);
+            }
+            _ => {}
+        }
+    }
+
+    // Reports a literal whose value does not fit in the integer type it was
+    // given, and checks one given a float type.
+    //
+    // The language has no unary minus yet, so every literal is non-negative.
+    // When it does, `-128` must be checked as one negative value, or it would
+    // not fit in `int8`.
+    fn check_range(&mut self, literal: &IntegerLiteral, ty: &Ty) {
+        let Ty::Primitive(kind) = ty else {
+            return;
+        };
+        if kind.is_float() {
+            return self.check_float(literal, *kind);
+        }
+        let Some((min, max)) = kind.integer_range() else {
+            return;
+        };
+        // Literals too long for an `i128` do not fit in any integer type.
+        let fits = literal
+            .text
+            .parse::<i128>()
+            .is_ok_and(|value| (min..=max).contains(&value));
+        if !fits {
+            self.error(
+                TypeErrorKind::LiteralOutOfRange {
+                    ty: ty.clone(),
+                    min,
+                    max,
+                },
+                literal.span,
+            );
+        }
+    }
+
+    // Reports a literal too large for a float type, and warns about one the
+    // type cannot represent exactly, such as `2049` as a `float16`, which
+    // holds every integer only up to 2048.
+    fn check_float(&mut self, literal: &IntegerLiteral, kind: TypeKind) {
+        let value = literal.text.parse::<i128>().ok();
+        match value.map(|value| (value, round_to_float(value, kind))) {
+            Some((_, Some(rounded))) if Some(rounded) == value => {}
+            Some((value, Some(rounded))) => self.check.warnings.push(Diagnostic::warning(
+                "W0300",
+                format!(
+                    "literal `{}` is not exactly representable in `{}`; it rounds to `{}`",
+                    value, kind, rounded
+                ),
+                literal.span,
+            )),
+            _ => {
+                let max = largest_float(kind).unwrap_or(i128::MAX);
+                self.error(
+                    TypeErrorKind::LiteralOutOfRange {
+                        ty: Ty::Primitive(kind),
+                        min: -max,
+                        max,
+                    },
+                    literal.span,
+                );
+            }
+        }
+    }
+}
+
+// Returns true if every path through the statements ends in a return. There
+// are no tail expressions, so a value can only be produced by `return`, and a
+// `while` loop may run zero times whatever its condition.
+pub(crate) fn always_returns(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match statement {
+        Statement::Return(_) => true,
+        Statement::Block(block) => always_returns(&block.statements),
+        Statement::If(if_statement) => match &if_statement.else_branch {
+            Some(else_branch) => {
+                always_returns(&if_statement.then_block.statements)
+                    && always_returns(std::slice::from_ref(else_branch))
+            }
+            None => false,
+        },
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lexer::Lexer,
+        parser::Parser,
+        visit::{self, Control, Visitor},
+    };
+
+    // Returns each type error's message and the source text it points at.
+    fn errors(source: &str) -> Vec<(String, &str)> {
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        program
+            .typecheck()
+            .errors
+            .iter()
+            .map(|e| (e.to_string(), &source[e.span.range()]))
+            .collect()
+    }
+
+    #[test]
+    fn well_typed_programs_have_no_errors() {
+        let source = "\
+fn square(x: int64) -> int64 { return x * x; }
+fn main() {
+    let a: int64 = square(2) + 1;
+    let b: bool = a > 3;
+    let c: bool = b == (1 < 2);
+    let f: fn(int64) -> int64 = square;
+    while b { f(a); }
+    return;
+}";
+        assert_eq!(errors(source), []);
+    }
+
+    #[test]
+    fn let_initializers_must_match_their_annotations() {
+        let source = "\
+let a: int32 = 1;
+let b: int8 = a;
+let c: bool = 2;
+let d: [int32; 2] = b;";
+        assert_eq!(
+            errors(source),
+            [
+                (
+                    "mismatched types: expected `int8`, found `int32`".to_string(),
+                    "a"
+                ),
+                (
+                    "mismatched types: expected `bool`, found `{integer}`".to_string(),
+                    "2"
+                ),
+                (
+                    "mismatched types: expected `[int32; 2]`, found `int8`".to_string(),
+                    "b"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn operands_must_be_compatible() {
+        let source = "\
+fn f(a: int32, b: float32, c: bool, g: fn() -> int32) {
+    a + b;
+    c * c;
+    c == c;
+    g < 1;
+    if a { }
+    (a + b) * c;
+}";
+        assert_eq!(
+            errors(source),
+            [
+                (
+                    "cannot apply `+` to `int32` and `float32`".to_string(),
+                    "a + b"
+                ),
+                ("cannot apply `*` to `bool` and `bool`".to_string(), "c * c"),
+                (
+                    "cannot apply `<` to `fn() -> int32` and `{integer}`".to_string(),
+                    "g < 1"
+                ),
+                (
+                    "mismatched types: expected `bool`, found `int32`".to_string(),
+                    "a"
+                ),
+                // Only the inner error is reported.
+                (
+                    "cannot apply `+` to `int32` and `float32`".to_string(),
+                    "a + b"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn functions_return_their_declared_type() {
+        let source = "\
+fn f(x: int8) -> int8 { if x > 0 { return x; } return; }
+fn g() { return 1; }
+fn h() -> bool { return f(1, 2); }
+return 0;";
+        assert_eq!(
+            errors(source),
+            [
+                (
+                    "mismatched types: expected `int8`, found `()`".to_string(),
+                    "return;"
+                ),
+                (
+                    "mismatched types: expected `()`, found `{integer}`".to_string(),
+                    "1"
+                ),
+                ("expected 1 argument, found 2".to_string(), "f(1, 2)"),
+                (
+                    "mismatched types: expected `bool`, found `int8`".to_string(),
+                    "f(1, 2)"
+                ),
+                ("`return` outside of a function".to_string(), "return 0;"),
+            ]
+        );
+    }
+
+    #[test]
+    fn builtins_are_called_with_printable_values() {
+        let source = "\
+fn f(g: fn() -> int32) {
+    print(g);
+    println();
+    let p: int32 = print;
+    let s: string = read_line(1);
+    println(s);
+    println(g() + 1);
+    fn read_line(x: int32) -> int32 { return x; }
+    read_line(2);
+}";
+        assert_eq!(
+            errors(source),
+            [
+                (
+                    "cannot print a value of type `fn() -> int32`".to_string(),
+                    "g"
+                ),
+                ("expected 1 argument, found 0".to_string(), "println()"),
+                ("builtin `print` can only be called".to_string(), "print"),
+                ("expected 0 arguments, found 1".to_string(), "read_line(1)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn literals_take_their_width_from_the_context() {
+        let source = "fn f(x: int16) { let y: int64 = 1 + 2 * 3; x - 4; 5; x(6); }";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let check = program.typecheck();
+        let literals: Vec<(&str, String)> = check
+            .types
+            .iter()
+            .filter_map(|(id, ty)| {
+                let span = find_literal(&program, id)?;
+                Some((&source[span.range()], ty.to_string()))
+            })
+            .collect();
+        assert_eq!(
+            literals,
+            [
+                ("1", "int64".to_string()),
+                ("2", "int64".to_string()),
+                ("3", "int64".to_string()),
+                ("4", "int16".to_string()),
+                ("5", "int32".to_string()),
+                ("6", "int32".to_string()),
+            ]
+        );
+        assert_eq!(
+            check.errors.iter().map(|e| &e.kind).collect::<Vec<_>>(),
+            [&TypeErrorKind::NotCallable(Ty::Primitive(TypeKind::Int16))]
+        );
+    }
+
+    // Returns the span of the integer literal with the given id.
+    fn find_literal(program: &Program, id: NodeId) -> Option<Span> {
+        struct Find(NodeId, Option<Span>);
+        impl<'ast> Visitor<'ast> for Find {
+            fn visit_expression(&mut self, expression: &'ast Expression<'ast>) -> Control {
+                if let Expression::IntegerLiteral(literal) = expression {
+                    if literal.id == self.0 {
+                        self.1 = Some(literal.span);
+                    }
+                }
+                Control::Continue
+            }
+        }
+        let mut find = Find(id, None);
+        visit::walk_program(&mut find, program);
+        find.1
+    }
+
+    #[test]
+    fn literals_must_fit_their_width() {
+        let source = "\
+let a: int1 = 1;
+let b: int1 = 5;
+let c: int2 = 1 + 2;
+let d: int4 = 7;
+let e: int8 = 300;
+let f: int64 = 9223372036854775807;
+let g: int64 = 9223372036854775808;
+let h: int32 = 1000000000000000000000000000000000000000000;
+let i: bool = 1 < 200;";
+        assert_eq!(
+            errors(source),
+            [
+                (
+                    "literal out of range for `int1`: expected a value in 0..=1".to_string(),
+                    "5"
+                ),
+                (
+                    "literal out of range for `int2`: expected a value in -2..=1".to_string(),
+                    "2"
+                ),
+                (
+                    "literal out of range for `int8`: expected a value in -128..=127".to_string(),
+                    "300"
+                ),
+                (
+                    "literal out of range for `int64`: expected a value in \
+                     -9223372036854775808..=9223372036854775807"
+                        .to_string(),
+                    "9223372036854775808"
+                ),
+                (
+                    "literal out of range for `int32`: expected a value in \
+                     -2147483648..=2147483647"
+                        .to_string(),
+                    "1000000000000000000000000000000000000000000"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn narrow_values_widen_implicitly() {
+        assert!(widens(TypeKind::Int1, TypeKind::Int2));
+        assert!(widens(TypeKind::Int8, TypeKind::Int64));
+        assert!(!widens(TypeKind::Int64, TypeKind::Int32));
+        assert!(widens(TypeKind::Int8, TypeKind::BFloat16));
+        assert!(!widens(TypeKind::Int16, TypeKind::Float16));
+        assert!(widens(TypeKind::Int16, TypeKind::Float32));
+        assert!(widens(TypeKind::Int32, TypeKind::Float64));
+        assert!(!widens(TypeKind::Int64, TypeKind::Float64));
+        assert!(widens(TypeKind::BFloat16, TypeKind::Float32));
+        assert!(!widens(TypeKind::Float16, TypeKind::BFloat16));
+        assert!(!widens(TypeKind::BFloat16, TypeKind::Float16));
+        assert!(!widens(TypeKind::Float32, TypeKind::Int64));
+        assert!(!widens(TypeKind::Bool, TypeKind::Int1));
+
+        let source = "\
+fn f(a: int8, b: int32, x: float16, y: bfloat16) {
+    let c: int64 = a + b;
+    let z: float32 = x + a;
+    let w: float32 = x + y;
+    let v: float16 = x * y;
+}";
+        assert_eq!(
+            errors(source),
+            [(
+                "mismatched types: expected `float16`, found `float32`".to_string(),
+                "x * y"
+            )]
+        );
+    }
+
+    #[test]
+    fn half_precision_operands_and_literals() {
+        assert_eq!(
+            promote(TypeKind::Float16, TypeKind::BFloat16),
+            Some(TypeKind::Float32)
+        );
+        assert_eq!(
+            promote(TypeKind::Int8, TypeKind::Float16),
+            Some(TypeKind::Float16)
+        );
+        assert_eq!(promote(TypeKind::Int32, TypeKind::Float16), None);
+        assert_eq!(round_to_float(2049, TypeKind::Float16), Some(2048));
+        assert_eq!(round_to_float(2051, TypeKind::Float16), Some(2052));
+        assert_eq!(round_to_float(65519, TypeKind::Float16), Some(65504));
+        assert_eq!(round_to_float(65520, TypeKind::Float16), None);
+        assert_eq!(round_to_float(257, TypeKind::BFloat16), Some(256));
+        assert_eq!(round_to_float(i128::MAX, TypeKind::BFloat16), None);
+        assert_eq!(round_to_float(1 << 100, TypeKind::Float32), Some(1 << 100));
+
+        let source = "\
+fn f(x: float16, y: bfloat16, i: int32) -> float16 {
+    let a: float16 = 2048 + x;
+    let b: bfloat16 = y * 257 + 300;
+    let c: float16 = 70000;
+    x < 1;
+    x + i;
+    return 2049;
+}";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let check = program.typecheck();
+        let warnings: Vec<(&str, &str)> = check
+            .warnings
+            .iter()
+            .map(|w| (w.message.as_str(), &source[w.span.range()]))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                (
+                    "literal `257` is not exactly representable in `bfloat16`; it rounds to `256`",
+                    "257"
+                ),
+                (
+                    "literal `2049` is not exactly representable in `float16`; it rounds to `2048`",
+                    "2049"
+                ),
+            ]
+        );
+        assert_eq!(
+            errors(source),
+            [
+                (
+                    "literal out of range for `float16`: expected a value in -65504..=65504"
+                        .to_string(),
+                    "70000"
+                ),
+                (
+                    "cannot apply `+` to `float16` and `int32`".to_string(),
+                    "x + i"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn narrowing_needs_an_explicit_cast() {
+        let source = "\
+fn f(x: float32, b: bool, g: fn() -> int8) {
+    let a: int1 = x;
+    let c: int1 = x as int1;
+    let d: int8 = b as int8 + 300 as int8;
+    let e: float16 = 70000 as float16;
+    x as bool;
+    g as int8;
+}";
+        assert_eq!(
+            errors(source),
+            [
+                (
+                    "mismatched types: expected `int1`, found `float32`".to_string(),
+                    "x"
+                ),
+                (
+                    "literal out of range for `int8`: expected a value in -128..=127".to_string(),
+                    "300"
+                ),
+                ("cannot cast `float32` to `bool`".to_string(), "x as bool"),
+                (
+                    "cannot cast `fn() -> int8` to `int8`".to_string(),
+                    "g as int8"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn array_sizes_are_constant_expressions() {
+        let source = "\
+const N: int64 = 2;
+const M: int8 = 100 + 100;
+fn f(a: [int32; N * 2], b: [int32; 4], x: int64) {
+    let c: [int32; 4] = a;
+    let d: [int32; N] = b;
+    let e: [int32; N - 3] = b;
+    let g: [int32; x] = b;
+    let h: [int32; 1 < 2] = b;
+}";
+        assert_eq!(
+            errors(source),
+            [
+                ("arithmetic overflow in `int8`".to_string(), "100 + 100"),
+                (
+                    "mismatched types: expected `[int32; 2]`, found `[int32; 4]`".to_string(),
+                    "b"
+                ),
+                (
+                    "array size must be a non-negative integer".to_string(),
+                    "N - 3"
+                ),
+                ("`x` is not a constant".to_string(), "x"),
+                (
+                    "mismatched types: expected `int64`, found `bool`".to_string(),
+                    "1 < 2"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_path_must_return_a_value() {
+        let source = "\
+fn a(x: bool) -> int32 { if x { return 1; } else { return 2; } }
+fn b(x: bool) -> int32 { if x { return 1; } else if x { return 2; } else { { return 3; } } }
+fn c(x: bool) -> int32 { if x { return 1; } }
+fn d(x: bool) -> int32 { while x { return 1; } }
+fn e() { }
+fn f() -> int32;
+fn g(x: bool) -> int32 {
+    if x { return 1; } else if x { return 2; }
+    fn h() -> bool { }
+}";
+        assert_eq!(
+            errors(source),
+            [
+                (
+                    "not all paths return a value of type `int32`".to_string(),
+                    "fn c(x: bool) -> int32"
+                ),
+                (
+                    "not all paths return a value of type `int32`".to_string(),
+                    "fn d(x: bool) -> int32"
+                ),
+                (
+                    "not all paths return a value of type `int32`".to_string(),
+                    "fn g(x: bool) -> int32"
+                ),
+                (
+                    "not all paths return a value of type `bool`".to_string(),
+                    "fn h() -> bool"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_are_reported_as_diagnostics() {
+        let source = "\
+const N: int8 = 1 / 0;
+fn f(a: int32, b: bool) -> int8 {
+    let c: int8 = a;
+    let d: int8 = b;
+}";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let mut diagnostics = vec![];
+        program.typecheck().report(&mut diagnostics);
+        let codes: Vec<&str> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, ["E0402", "E0306", "E0300", "E0300"]);
+        assert_eq!(
+            diagnostics[1].notes,
+            ["the end of the function body can be reached without a `return`"]
+        );
+        let suggestion = &diagnostics[2].suggestions[0];
+        assert_eq!(suggestion.span, Span::new(76, 76));
+        assert_eq!(suggestion.replacement, " as int8");
+        assert!(diagnostics[3].suggestions.is_empty());
+    }
+
+    #[test]
+    fn folded_constant_arithmetic_must_not_overflow() {
+        let source = "\
+const N: int8 = 100;
+fn f(a: int8) -> int64 {
+    let x: int8 = 127 + 1;
+    let y: int8 = a + (N + 100) * 2;
+    let z: int16 = N * 100;
+    let w: int8 = 200 + 1;
+    let v: int8 = (300 as int8) * 2 + a;
+    return (a < 64 * 2) as int64 + 99999 * 99999;
+}";
+        assert_eq!(
+            errors(source),
+            [
+                ("arithmetic overflow in `int8`".to_string(), "127 + 1"),
+                ("arithmetic overflow in `int8`".to_string(), "N + 100"),
+                ("arithmetic overflow in `int8`".to_string(), "N * 100"),
+                (
+                    "literal out of range for `int8`: expected a value in -128..=127".to_string(),
+                    "200"
+                ),
+                (
+                    "literal out of range for `int8`: expected a value in -128..=127".to_string(),
+                    "300"
+                ),
+                ("arithmetic overflow in `int8`".to_string(), "64 * 2"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{
+    ast::{BinaryOperator, NodeId, Symbol, TypeKind},
+    consteval::{arithmetic, ConstErrorKind, ConstValue},
+    printer::operator_text,
+};
+use std::{cmp::Ordering, fmt, rc::Rc};
+
+// A value as a running program sees it.
+//
+// Every number carries its type and is always a value of that type: integers
+// are within its range and floats are exactly representable in its format.
+// `float16` and `bfloat16` values are kept in an `f64` like the wider floats,
+// rounded to their precision after every operation.
+//
+// Strings and functions live on the heap behind an `Rc` and are shared, not
+// copied, when a value is cloned. Reference counting frees all of them: the
+// language has no mutation, so a value can only refer to values that existed
+// before it, and a function refers to itself through its call rather than a
+// capture, so no cycle can form. A collector becomes necessary once arrays or
+// structs that can be mutated are added.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(TypeKind, i64),
+    Float(TypeKind, f64),
+    Bool(bool),
+    String(Rc<str>),
+    Function(Rc<Closure>),
+}
+
+// A function as a value: which function it is, and the values of the
+// bindings of enclosing functions it can use.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Closure {
+    pub name: Symbol,
+    // Identifies the function among those of the program being run, in a
+    // way that is up to whatever runs it.
+    pub index: u32,
+    // Each captured binding, by the id of its declaring identifier, with its
+    // value.
+    pub captures: Vec<(NodeId, Value)>,
+}
+
+impl Closure {
+    // Returns the number of bytes the closure takes up on the heap, not
+    // counting what the values it captures point to.
+    pub fn size(&self) -> usize {
+        std::mem::size_of::<Closure>()
+            + self.captures.len() * std::mem::size_of::<(NodeId, Value)>()
+    }
+}
+
+// Why an operator could not be applied to two values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArithmeticError {
+    DivisionByZero,
+    NegativeExponent,
+    // Operands of different types, or of a type the operator does not apply
+    // to, by the names of their types. The type checker rules these out.
+    Operands(BinaryOperator, &'static str, &'static str),
+}
+
+impl fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithmeticError::DivisionByZero => f.write_str("division by zero"),
+            ArithmeticError::NegativeExponent => f.write_str("negative exponent"),
+            ArithmeticError::Operands(operator, left, right) => write!(
+                f,
+                "cannot apply `{}` to `{}` and `{}`",
+                operator_text(operator),
+                left,
+                right
+            ),
+        }
+    }
+}
+
+impl Value {
+    // Returns an integer of type `kind`, keeping the low bits of `value` as
+    // an `as` cast does. Panics if `kind` is not an integer type.
+    pub fn integer(kind: TypeKind, value: i128) -> Value {
+        let value = wrap(value, kind).expect("not an integer type");
+        Value::Integer(kind, value as i64)
+    }
+
+    // Returns a float of type `kind`, rounding `value` to its precision.
+    // Panics if `kind` is not a float type.
+    pub fn float(kind: TypeKind, value: f64) -> Value {
+        Value::Float(kind, round_float(value, kind).expect("not a float type"))
+    }
+
+    // Returns the value a compile-time constant has at run time in a type
+    // the type checker gave it, or `None` if the constant is not of that
+    // type.
+    pub fn from_const(value: ConstValue, kind: TypeKind) -> Option<Value> {
+        match value {
+            ConstValue::Integer(value) if kind.is_integer() => Some(Value::integer(kind, value)),
+            ConstValue::Integer(value) if kind.is_float() => Some(Value::float(kind, value as f64)),
+            ConstValue::Bool(value) if kind == TypeKind::Bool => Some(Value::Bool(value)),
+            _ => None,
+        }
+    }
+
+    // Returns the type of a value, or `None` for a function.
+    pub fn kind(&self) -> Option<TypeKind> {
+        match self {
+            Value::Integer(kind, _) | Value::Float(kind, _) => Some(*kind),
+            Value::Bool(_) => Some(TypeKind::Bool),
+            Value::String(_) => Some(TypeKind::String),
+            Value::Function(_) => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.kind().map_or("function", |kind| kind.name())
+    }
+
+    // Converts a value as an `as` cast does: an integer keeps its low bits,
+    // a float is rounded to the target's precision, and a float converted to
+    // an integer is truncated toward zero and clamped to the integer's range,
+    // with NaN becoming zero. `bool` converts to 0 or 1. Returns `None` for
+    // a conversion the type checker does not allow.
+    pub fn cast(&self, kind: TypeKind) -> Option<Value> {
+        if self.kind() == Some(kind) {
+            return Some(self.clone());
+        }
+        match *self {
+            Value::Integer(_, value) if kind.is_integer() => {
+                Some(Value::integer(kind, value.into()))
+            }
+            Value::Integer(_, value) if kind.is_float() => Some(Value::float(kind, value as f64)),
+            Value::Float(_, value) if kind.is_integer() => {
+                let (min, max) = kind.integer_range()?;
+                let value = if value.is_nan() {
+                    0
+                } else {
+                    (value.trunc() as i128).clamp(min, max)
+                };
+                Some(Value::integer(kind, value))
+            }
+            Value::Float(_, value) if kind.is_float() => Some(Value::float(kind, value)),
+            Value::Bool(value) if kind.is_integer() => Some(Value::integer(kind, value.into())),
+            _ => None,
+        }
+    }
+
+    // Applies a binary operator to two values of the same type.
+    //
+    // Integer arithmetic wraps around at the width of the type, and
+    // division truncates toward zero. Float arithmetic is computed in `f64`
+    // and rounded to the type, so it follows IEEE 754: dividing by zero
+    // gives an infinity or NaN rather than an error. Comparisons apply to
+    // every type; NaN compares unequal to everything, itself included.
+    pub fn binary(
+        &self,
+        operator: &BinaryOperator,
+        right: &Value,
+    ) -> Result<Value, ArithmeticError> {
+        let mismatch =
+            || ArithmeticError::Operands(operator.clone(), self.type_name(), right.type_name());
+        if self.kind() != right.kind() {
+            return Err(mismatch());
+        }
+        if operator.is_comparison() {
+            let ordering = match (self, right) {
+                (Value::Integer(_, left), Value::Integer(_, right)) => Some(left.cmp(right)),
+                (Value::Float(_, left), Value::Float(_, right)) => left.partial_cmp(right),
+                (Value::Bool(left), Value::Bool(right)) => Some(left.cmp(right)),
+                (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+                _ => return Err(mismatch()),
+            };
+            return Ok(Value::Bool(compare(operator, ordering)));
+        }
+        match (self, right) {
+            (Value::Integer(kind, left), Value::Integer(_, right)) => {
+                let (left, right) = (i128::from(*left), i128::from(*right));
+                let value = match arithmetic(operator, left, right) {
+                    Ok(Some(value)) => value,
+                    // Only a power overflows an `i128` when the operands fit
+                    // in an `i64`. Wrapping at 128 bits leaves the low bits,
+                    // which are all that is kept, unchanged.
+                    Ok(None) => wrapping_power(left, right as u64),
+                    Err(ConstErrorKind::NegativeExponent) => {
+                        return Err(ArithmeticError::NegativeExponent)
+                    }
+                    Err(_) => return Err(ArithmeticError::DivisionByZero),
+                };
+                Ok(Value::integer(*kind, value))
+            }
+            (Value::Float(kind, left), Value::Float(_, right)) => {
+                let value = match operator {
+                    BinaryOperator::Plus => left + right,
+                    BinaryOperator::Minus => left - right,
+                    BinaryOperator::Star => left * right,
+                    BinaryOperator::Divide => left / right,
+                    BinaryOperator::Power => left.powf(*right),
+                    _ => unreachable!("comparisons are handled above"),
+                };
+                Ok(Value::float(*kind, value))
+            }
+            _ => Err(mismatch()),
+        }
+    }
+}
+
+// Floats are written with the fewest digits that identify them among the
+// values of their type; `float16` and `bfloat16` as if they were `float32`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(_, value) => write!(f, "{}", value),
+            Value::Float(TypeKind::Float64, value) => write!(f, "{:?}", value),
+            Value::Float(_, value) => write!(f, "{:?}", *value as f32),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::String(value) => f.write_str(value),
+            Value::Function(closure) => write!(f, "fn {}", closure.name),
+        }
+    }
+}
+
+pub(crate) fn compare(operator: &BinaryOperator, ordering: Option<Ordering>) -> bool {
+    match operator {
+        BinaryOperator::Equal => ordering.is_some_and(Ordering::is_eq),
+        BinaryOperator::NotEqual => !ordering.is_some_and(Ordering::is_eq),
+        BinaryOperator::Less => ordering.is_some_and(Ordering::is_lt),
+        BinaryOperator::LessEqual => ordering.is_some_and(Ordering::is_le),
+        BinaryOperator::Greater => ordering.is_some_and(Ordering::is_gt),
+        BinaryOperator::GreaterEqual => ordering.is_some_and(Ordering::is_ge),
+        _ => unreachable!("not a comparison"),
+    }
+}
+
+// Computes `base` to the power of `exponent`, wrapping around at 128 bits.
+fn wrapping_power(mut base: i128, mut exponent: u64) -> i128 {
+    let mut result: i128 = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+    result
+}
+
+// Reduces an integer to the range of an integer type, keeping its low bits,
+// two's complement. Returns `None` if `kind` is not an integer type.
+pub fn wrap(value: i128, kind: TypeKind) -> Option<i128> {
+    let (min, max) = kind.integer_range()?;
+    let modulus = max - min + 1;
+    Some((value - min).rem_euclid(modulus) + min)
+}
+
+// Rounds a number to the nearest value of a float type, ties to even.
+// Results too large for the type become infinities, and those too small for
+// its smallest subnormal become zero. Returns `None` if `kind` is not a float
+// type.
+pub fn round_float(value: f64, kind: TypeKind) -> Option<f64> {
+    let (exponent, precision) = kind.float_format()?;
+    if kind == TypeKind::Float64 || !value.is_finite() || value == 0.0 {
+        return Some(value);
+    }
+    if kind == TypeKind::Float32 {
+        return Some(value as f32 as f64);
+    }
+    let max_exponent = (1 << (exponent - 1)) - 1;
+    let min_exponent = 1 - max_exponent;
+    // The exponent of `value`, as in `value = 1.m * 2^e`. `f64` subnormals
+    // are far below the smallest subnormal of any narrower type, so their
+    // exponent only needs to be small enough.
+    let biased = ((value.to_bits() >> 52) & 0x7ff) as i32;
+    let value_exponent = if biased == 0 { -1023 } else { biased - 1023 };
+    // The place value of the last significand bit, which is fixed for
+    // subnormals.
+    let quantum = 2f64.powi(value_exponent.max(min_exponent) - (precision as i32 - 1));
+    let rounded = (value / quantum).round_ties_even() * quantum;
+    let largest = (2.0 - 2f64.powi(1 - precision as i32)) * 2f64.powi(max_exponent);
+    Some(if rounded.abs() > largest {
+        f64::INFINITY.copysign(value)
+    } else {
+        rounded
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_wrap_at_their_width() {
+        let add = |kind, left, right| {
+            Value::integer(kind, left)
+                .binary(&BinaryOperator::Plus, &Value::integer(kind, right))
+                .unwrap()
+        };
+        assert_eq!(
+            add(TypeKind::Int8, 127, 1),
+            Value::Integer(TypeKind::Int8, -128)
+        );
+        assert_eq!(
+            add(TypeKind::Int4, 7, 2),
+            Value::Integer(TypeKind::Int4, -7)
+        );
+        assert_eq!(add(TypeKind::Int1, 1, 1), Value::Integer(TypeKind::Int1, 0));
+        assert_eq!(
+            add(TypeKind::Int64, i64::MAX.into(), 1),
+            Value::Integer(TypeKind::Int64, i64::MIN)
+        );
+
+        let three = Value::integer(TypeKind::Int32, 3);
+        let power = three.binary(
+            &BinaryOperator::Power,
+            &Value::integer(TypeKind::Int32, 100),
+        );
+        assert_eq!(
+            power,
+            Ok(Value::integer(
+                TypeKind::Int32,
+                3i128.wrapping_pow(100) as i32 as i128
+            ))
+        );
+        let zero = Value::integer(TypeKind::Int32, 0);
+        assert_eq!(
+            three.binary(&BinaryOperator::Divide, &zero),
+            Err(ArithmeticError::DivisionByZero)
+        );
+        assert_eq!(
+            three
+                .binary(&BinaryOperator::Plus, &Value::Bool(true))
+                .unwrap_err()
+                .to_string(),
+            "cannot apply `+` to `int32` and `bool`"
+        );
+
+        let value = Value::integer(TypeKind::Int16, 300);
+        assert_eq!(
+            value.cast(TypeKind::Int8),
+            Some(Value::integer(TypeKind::Int8, 44))
+        );
+        assert_eq!(
+            Value::Bool(true).cast(TypeKind::Int2),
+            Some(Value::integer(TypeKind::Int2, 1))
+        );
+        assert_eq!(value.cast(TypeKind::Bool), None);
+    }
+
+    #[test]
+    fn floats_round_to_their_precision() {
+        let float16 = Value::float(TypeKind::Float16, 2049.0);
+        assert_eq!(float16, Value::Float(TypeKind::Float16, 2048.0));
+        assert_eq!(round_float(65520.0, TypeKind::Float16), Some(f64::INFINITY));
+        assert_eq!(round_float(65519.0, TypeKind::Float16), Some(65504.0));
+        // The smallest subnormal `float16` is 2^-24; half of it rounds to
+        // zero, ties to even, and anything more rounds up to it.
+        assert_eq!(round_float(2f64.powi(-25), TypeKind::Float16), Some(0.0));
+        assert_eq!(
+            round_float(1.5 * 2f64.powi(-25), TypeKind::Float16),
+            Some(2f64.powi(-24))
+        );
+        assert_eq!(round_float(257.0, TypeKind::BFloat16), Some(256.0));
+        assert_eq!(round_float(1e39, TypeKind::BFloat16), Some(f64::INFINITY));
+        assert_eq!(round_float(0.1, TypeKind::Float32), Some(0.1f32 as f64));
+
+        let tenth = Value::float(TypeKind::Float32, 0.1);
+        let sum = tenth.binary(&BinaryOperator::Plus, &tenth).unwrap();
+        assert_eq!(
+            sum,
+            Value::Float(TypeKind::Float32, (0.1f32 + 0.1f32) as f64)
+        );
+        assert_eq!(sum.to_string(), "0.2");
+        assert_eq!(Value::float(TypeKind::Float64, 0.1).to_string(), "0.1");
+
+        let nan = Value::float(TypeKind::Float64, f64::NAN);
+        assert_eq!(
+            nan.binary(&BinaryOperator::Equal, &nan),
+            Ok(Value::Bool(false))
+        );
+        assert_eq!(
+            nan.binary(&BinaryOperator::NotEqual, &nan),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            Value::float(TypeKind::Float32, -2.9).cast(TypeKind::Int8),
+            Some(Value::integer(TypeKind::Int8, -2))
+        );
+        assert_eq!(
+            Value::float(TypeKind::Float32, 1e10).cast(TypeKind::Int8),
+            Some(Value::integer(TypeKind::Int8, 127))
+        );
+        assert_eq!(
+            Value::from_const(ConstValue::Integer(2049), TypeKind::Float16),
+            Some(Value::Float(TypeKind::Float16, 2048.0))
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use crate::ast::{Block, Expression, Identifier, Parameter, Program, Statement, TypeExpr};
+use core::ops::ControlFlow;
+
+// Tells the walker how to continue after visiting a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    // Visit the node's children, then carry on.
+    Continue,
+    // Do not visit the node's children, but carry on with its siblings.
+    SkipChildren,
+    // Stop the walk immediately.
+    Stop,
+}
+
+// Inspects an AST in source order without modifying it.
+//
+// Each method is called on a node before its children and returns a
+// `Control` deciding whether to descend into the node, skip it, or end the
+// walk. All methods default to `Control::Continue`. Nodes are borrowed for
+// `'ast`, so a visitor can keep references to the nodes it finds.
+pub trait Visitor<'ast> {
+    fn visit_statement(&mut self, _statement: &'ast Statement<'ast>) -> Control {
+        Control::Continue
+    }
+
+    fn visit_parameter(&mut self, _parameter: &'ast Parameter<'ast>) -> Control {
+        Control::Continue
+    }
+
+    fn visit_block(&mut self, _block: &'ast Block<'ast>) -> Control {
+        Control::Continue
+    }
+
+    fn visit_expression(&mut self, _expression: &'ast Expression<'ast>) -> Control {
+        Control::Continue
+    }
+
+    // Called for every identifier, both where a name is declared and where it
+    // is used. Identifiers used as expressions are visited after
+    // `visit_expression`.
+    fn visit_identifier(&mut self, _identifier: &'ast Identifier) -> Control {
+        Control::Continue
+    }
+
+    fn visit_type(&mut self, _ttype: &'ast TypeExpr<'ast>) -> Control {
+        Control::Continue
+    }
+}
+
+// Walks every statement of a program. Returns `Control::Stop` if the visitor
+// stopped the walk and `Control::Continue` otherwise.
+pub fn walk_program<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    program: &'ast Program<'ast>,
+) -> Control {
+    finish(
+        program
+            .statements
+            .iter()
+            .try_for_each(|s| statement(visitor, s)),
+    )
+}
+
+// Walks a statement and its children.
+pub fn walk_statement<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    statement: &'ast Statement<'ast>,
+) -> Control {
+    finish(self::statement(visitor, statement))
+}
+
+// Walks an expression and its children.
+pub fn walk_expression<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    expression: &'ast Expression<'ast>,
+) -> Control {
+    finish(self::expression(visitor, expression))
+}
+
+fn finish(flow: ControlFlow<()>) -> Control {
+    match flow {
+        ControlFlow::Continue(()) => Control::Continue,
+        ControlFlow::Break(()) => Control::Stop,
+    }
+}
+
+// Acts on the control value returned for a node: returns from the calling
+// walk function unless the node's children should be visited.
+macro_rules! enter {
+    ($control:expr) => {
+        match $control {
+            Control::Continue => {}
+            Control::SkipChildren => return ControlFlow::Continue(()),
+            Control::Stop => return ControlFlow::Break(()),
+        }
+    };
+}
+
+fn statement<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    statement: &'ast Statement<'ast>,
+) -> ControlFlow<()> {
+    enter!(visitor.visit_statement(statement));
+    match statement {
+        Statement::Let(let_statement) => {
+            identifier(visitor, &let_statement.identifier)?;
+            ttype(visitor, &let_statement.ttype)?;
+            expression(visitor, &let_statement.expression)
+        }
+        Statement::Const(constant) => {
+            identifier(visitor, &constant.identifier)?;
+            ttype(visitor, &constant.ttype)?;
+            expression(visitor, &constant.expression)
+        }
+        Statement::FunctionDeclaration(function) => {
+            identifier(visitor, &function.identifier)?;
+            for parameter in &function.parameters {
+                self::parameter(visitor, parameter)?;
+            }
+            ttype(visitor, &function.return_type)?;
+            match &function.body {
+                Some(body) => block(visitor, body),
+                None => ControlFlow::Continue(()),
+            }
+        }
+        Statement::Expression(e) => expression(visitor, e),
+        Statement::Return(return_statement) => match &return_statement.expression {
+            Some(e) => expression(visitor, e),
+            None => ControlFlow::Continue(()),
+        },
+        Statement::If(if_statement) => {
+            expression(visitor, &if_statement.condition)?;
+            block(visitor, &if_statement.then_block)?;
+            match &if_statement.else_branch {
+                Some(else_branch) => self::statement(visitor, else_branch),
+                None => ControlFlow::Continue(()),
+            }
+        }
+        Statement::While(while_statement) => {
+            expression(visitor, &while_statement.condition)?;
+            block(visitor, &while_statement.body)
+        }
+        Statement::Block(b) => block(visitor, b),
+        Statement::Import(import) => identifier(visitor, &import.module),
+    }
+}
+
+fn parameter<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    parameter: &'ast Parameter<'ast>,
+) -> ControlFlow<()> {
+    enter!(visitor.visit_parameter(parameter));
+    identifier(visitor, &parameter.identifier)?;
+    ttype(visitor, &parameter.ttype)
+}
+
+fn block<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    block: &'ast Block<'ast>,
+) -> ControlFlow<()> {
+    enter!(visitor.visit_block(block));
+    block
+        .statements
+        .iter()
+        .try_for_each(|s| statement(visitor, s))
+}
+
+fn expression<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    expression: &'ast Expression<'ast>,
+) -> ControlFlow<()> {
+    enter!(visitor.visit_expression(expression));
+    match expression {
+        Expression::IntegerLiteral(_) => ControlFlow::Continue(()),
+        Expression::Identifier(i) => identifier(visitor, i),
+        Expression::BinaryExpression(binary) => {
+            self::expression(visitor, &binary.left)?;
+            self::expression(visitor, &binary.right)
+        }
+        Expression::Call(call) => {
+            self::expression(visitor, &call.callee)?;
+            call.arguments
+                .iter()
+                .try_for_each(|e| self::expression(visitor, e))
+        }
+        Expression::Cast(cast) => {
+            self::expression(visitor, &cast.expression)?;
+            ttype(visitor, &cast.ttype)
+        }
+    }
+}
+
+fn identifier<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    identifier: &'ast Identifier,
+) -> ControlFlow<()> {
+    enter!(visitor.visit_identifier(identifier));
+    ControlFlow::Continue(())
+}
+
+fn ttype<'ast, V: Visitor<'ast> + ?Sized>(
+    visitor: &mut V,
+    ttype: &'ast TypeExpr<'ast>,
+) -> ControlFlow<()> {
+    enter!(visitor.visit_type(ttype));
+    match ttype {
+        TypeExpr::Named(_) => ControlFlow::Continue(()),
+        TypeExpr::Array(array) => {
+            self::ttype(visitor, &array.element)?;
+            expression(visitor, &array.size)
+        }
+        TypeExpr::Generic(generic) => generic
+            .arguments
+            .iter()
+            .try_for_each(|t| self::ttype(visitor, t)),
+        TypeExpr::Tuple(elements) => elements.iter().try_for_each(|t| self::ttype(visitor, t)),
+        TypeExpr::Function(function) => {
+            for parameter in &function.parameters {
+                self::ttype(visitor, parameter)?;
+            }
+            self::ttype(visitor, &function.return_type)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::{FunctionDeclaration, Symbol},
+        lexer::Lexer,
+        parser::Parser,
+    };
+
+    // Finds the first function with a given name, skipping function bodies.
+    struct FindFunction<'ast> {
+        name: Symbol,
+        found: Option<&'ast FunctionDeclaration<'ast>>,
+        visited: usize,
+    }
+
+    impl<'ast> Visitor<'ast> for FindFunction<'ast> {
+        fn visit_statement(&mut self, statement: &'ast Statement<'ast>) -> Control {
+            self.visited += 1;
+            match statement {
+                Statement::FunctionDeclaration(f) if f.identifier.name == self.name => {
+                    self.found = Some(f);
+                    Control::Stop
+                }
+                _ => Control::SkipChildren,
+            }
+        }
+    }
+
+    #[test]
+    fn stops_at_the_first_match() {
+        let tokens = Lexer::tokenize(
+            "fn helper() { fn main(); } let x: int32 = 1; fn main() -> int32 { return 0; } fn main();",
+        );
+        let program = Parser::parse_program(&tokens).unwrap();
+        let mut finder = FindFunction {
+            name: Symbol::new("main"),
+            found: None,
+            visited: 0,
+        };
+        assert_eq!(walk_program(&mut finder, &program), Control::Stop);
+        assert!(finder.found.unwrap().body.is_some());
+        assert_eq!(finder.visited, 3);
+    }
+
+    // Records the names of identifiers in visiting order.
+    struct Identifiers(Vec<Symbol>);
+
+    impl<'ast> Visitor<'ast> for Identifiers {
+        fn visit_identifier(&mut self, identifier: &'ast Identifier) -> Control {
+            self.0.push(identifier.name.clone());
+            Control::Continue
+        }
+    }
+
+    #[test]
+    fn visits_nodes_in_source_order() {
+        let tokens = Lexer::tokenize("fn f(a: int32) { let b: int32 = a + c; return b; }");
+        let program = Parser::parse_program(&tokens).unwrap();
+        let mut identifiers = Identifiers(vec![]);
+        assert_eq!(walk_program(&mut identifiers, &program), Control::Continue);
+        let names: Vec<&str> = identifiers.0.iter().map(Symbol::as_str).collect();
+        assert_eq!(names, ["f", "a", "b", "a", "c", "b"]);
+    }
+}
@@ -0,0 +1,732 @@
+use crate::{
+    ast::{BinaryOperator, Span, TypeKind},
+    builtin::{self, Streams},
+    bytecode::{FunctionId, Instruction, Module},
+    interpreter::{Call, Limits, Meter, Observer, RuntimeError, RuntimeErrorKind, Step},
+    packed::Packed,
+    value::{ArithmeticError, Closure, Value},
+};
+use std::rc::Rc;
+
+// How the VM holds values on its stack and in its globals: as plain
+// `Value`s, or packed into 64 bits as `Packed` values.
+pub trait Slot: Clone {
+    // What a local slot or global holds before it is first assigned. The
+    // compiler only reads slots that have been written, so it is never seen.
+    const UNSET: Self;
+    fn pack(value: Value) -> Self;
+    fn unpack(&self) -> Value;
+    fn binary(&self, operator: &BinaryOperator, right: &Self) -> Result<Self, ArithmeticError>;
+    // Converts to `kind`, which the type checker allows.
+    fn cast(&self, kind: TypeKind) -> Self;
+    // The bool this is, for a condition.
+    fn truth(&self) -> bool;
+    // The function this is, for a call.
+    fn function(&self) -> Rc<Closure>;
+}
+
+impl Slot for Value {
+    const UNSET: Value = Value::Bool(false);
+
+    fn pack(value: Value) -> Value {
+        value
+    }
+
+    fn unpack(&self) -> Value {
+        self.clone()
+    }
+
+    fn binary(&self, operator: &BinaryOperator, right: &Value) -> Result<Value, ArithmeticError> {
+        Value::binary(self, operator, right)
+    }
+
+    fn cast(&self, kind: TypeKind) -> Value {
+        Value::cast(self, kind).expect("the type checker allows only valid casts")
+    }
+
+    fn truth(&self) -> bool {
+        match self {
+            Value::Bool(value) => *value,
+            other => panic!("condition of type `{}`", other.type_name()),
+        }
+    }
+
+    fn function(&self) -> Rc<Closure> {
+        match self {
+            Value::Function(closure) => closure.clone(),
+            _ => panic!("call of a value that is not a function"),
+        }
+    }
+}
+
+// A function call in progress.
+struct Frame {
+    closure: Rc<Closure>,
+    // The index of the next instruction in the function's code.
+    ip: usize,
+    // Where the function's local slots start on the stack. The function
+    // being called is just below them.
+    base: usize,
+}
+
+// A stack machine that runs compiled modules.
+//
+// The operand stack holds each running function's local slots followed by
+// its temporaries. A call leaves the callee and its arguments on the stack,
+// and the arguments become the first of the new frame's slots.
+//
+// Like `Interpreter`, `run` executes the top-level statements and `call`
+// then calls a top-level function, and both give the same results.
+//
+// Values are held as `S`. `Vm::new` holds them as `Value`s and `Vm::packed`
+// as `Packed` values, which are half the size; `benches/vm.rs` compares the
+// two.
+pub struct Vm<'m, S: Slot = Value> {
+    module: &'m Module,
+    constants: Vec<S>,
+    stack: Vec<S>,
+    frames: Vec<Frame>,
+    globals: Vec<S>,
+    meter: Meter,
+    observer: Option<Box<dyn Observer + 'm>>,
+    streams: Option<Streams<'m>>,
+}
+
+impl<'m> Vm<'m> {
+    pub fn new(module: &'m Module) -> Vm<'m> {
+        Vm::with_slots(module)
+    }
+}
+
+impl<'m> Vm<'m, Packed> {
+    pub fn packed(module: &'m Module) -> Vm<'m, Packed> {
+        Vm::with_slots(module)
+    }
+}
+
+impl<'m, S: Slot> Vm<'m, S> {
+    fn with_slots(module: &'m Module) -> Vm<'m, S> {
+        Vm {
+            module,
+            constants: module.constants.iter().cloned().map(S::pack).collect(),
+            stack: vec![],
+            frames: vec![],
+            globals: vec![S::UNSET; module.globals as usize],
+            meter: Meter::new(Limits::default()),
+            observer: None,
+            streams: None,
+        }
+    }
+
+    // Limits what the program may use from now on.
+    pub fn limit(&mut self, limits: Limits) {
+        self.meter = Meter::new(limits);
+    }
+
+    // Sets the observer called before each instruction.
+    pub fn observe(&mut self, observer: impl Observer + 'm) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    // Sets where the I/O builtins read and write.
+    pub fn redirect(&mut self, streams: Streams<'m>) {
+        self.streams = Some(streams);
+    }
+
+    // Executes the program's top-level statements.
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        self.invoke(self.module.main, vec![], Span::new(0, 0))
+            .map(|_| ())
+    }
+
+    // Calls a top-level function by name, returning its result or `None`
+    // for a function that returns unit.
+    pub fn call(
+        &mut self,
+        name: &str,
+        arguments: Vec<Value>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let Some(id) = self.module.find(name) else {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::UnknownFunction(name.to_string()),
+                Span::new(0, 0),
+            ));
+        };
+        self.invoke(id, arguments, Span::new(0, 0))
+    }
+
+    fn invoke(
+        &mut self,
+        id: FunctionId,
+        arguments: Vec<Value>,
+        span: Span,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let closure = self.closure(id, vec![], span)?;
+        let start = self.stack.len();
+        self.stack.push(S::pack(Value::Function(closure)));
+        let count = arguments.len() as u32;
+        self.stack.extend(arguments.into_iter().map(S::pack));
+        let depth = self.frames.len();
+        let result = self.enter(count, span).and_then(|()| self.execute(depth));
+        let result = result.map_err(|error| self.unwind(error, depth));
+        let value = (self.stack.len() > start).then(|| self.pop().unpack());
+        self.stack.truncate(start);
+        result.map(|()| value)
+    }
+
+    // Abandons the calls made since there were `depth`, recording them in
+    // the error's backtrace.
+    fn unwind(&mut self, mut error: RuntimeError, depth: usize) -> RuntimeError {
+        for i in (depth..self.frames.len()).rev() {
+            let frame = &self.frames[i];
+            if FunctionId(frame.closure.index) == self.module.main {
+                continue;
+            }
+            // A caller's next instruction is just after its call.
+            let span = (i > depth).then(|| {
+                let caller = &self.frames[i - 1];
+                self.module.function(FunctionId(caller.closure.index)).spans[caller.ip - 1]
+            });
+            error.backtrace.push(Call {
+                function: frame.closure.name.clone(),
+                span,
+            });
+        }
+        self.frames.truncate(depth);
+        error
+    }
+
+    // Creates a function value at `span`.
+    fn closure(
+        &mut self,
+        id: FunctionId,
+        captures: Vec<Value>,
+        span: Span,
+    ) -> Result<Rc<Closure>, RuntimeError> {
+        let function = self.module.function(id);
+        let closure = Closure {
+            name: function.name.clone(),
+            index: id.0,
+            captures: function
+                .captures
+                .iter()
+                .map(|binding| binding.id)
+                .zip(captures)
+                .collect(),
+        };
+        self.meter.allocate(&closure, span)?;
+        Ok(Rc::new(closure))
+    }
+
+    fn pop(&mut self) -> S {
+        self.stack.pop().expect("the operand stack is empty")
+    }
+
+    // Starts a call to the function below the `count` arguments on top of
+    // the stack. `span` is the call, for errors.
+    fn enter(&mut self, count: u32, span: Span) -> Result<(), RuntimeError> {
+        let base = self.stack.len() - count as usize;
+        let closure = self.stack[base - 1].function();
+        let function = self.module.function(FunctionId(closure.index));
+        if !function.defined {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::NoBody(function.name.clone()),
+                span,
+            ));
+        }
+        self.meter.call(self.frames.len() + 1, span)?;
+        self.stack.resize(base + function.locals as usize, S::UNSET);
+        self.frames.push(Frame {
+            closure,
+            ip: 0,
+            base,
+        });
+        Ok(())
+    }
+
+    // Leaves the innermost call, replacing its slots and the function on
+    // the stack with the value it returns, if any.
+    fn leave(&mut self, value: Option<S>) {
+        let frame = self.frames.pop().unwrap();
+        self.stack.truncate(frame.base - 1);
+        self.stack.extend(value);
+    }
+
+    // Runs until the number of calls in progress drops to `depth`.
+    fn execute(&mut self, depth: usize) -> Result<(), RuntimeError> {
+        while self.frames.len() > depth {
+            let frame = self.frames.last_mut().unwrap();
+            let function = self.module.function(FunctionId(frame.closure.index));
+            let instruction = function.code[frame.ip];
+            let span = function.spans[frame.ip];
+            let base = frame.base;
+            if let Some(observer) = &mut self.observer {
+                let locals: Vec<_> = function
+                    .variables
+                    .iter()
+                    .filter(|variable| variable.in_scope(frame.ip))
+                    .map(|variable| {
+                        let value = self.stack[base + variable.slot as usize].unpack();
+                        (variable.binding.clone(), value)
+                    })
+                    .collect();
+                let main = FunctionId(frame.closure.index) == self.module.main;
+                let step = Step {
+                    span,
+                    depth: self.frames.len(),
+                    function: (!main).then_some(function.name.clone()),
+                    locals: &locals,
+                };
+                if observer.step(&step).is_break() {
+                    return Err(RuntimeError::new(RuntimeErrorKind::Stopped, span));
+                }
+            }
+            self.meter.step(span)?;
+            let frame = self.frames.last_mut().unwrap();
+            frame.ip += 1;
+            match instruction {
+                Instruction::Constant(id) => self.stack.push(self.constants[id as usize].clone()),
+                Instruction::Local(slot) => {
+                    self.stack.push(self.stack[base + slot as usize].clone())
+                }
+                Instruction::SetLocal(slot) => {
+                    self.stack[base + slot as usize] = self.pop();
+                }
+                Instruction::Global(global) => {
+                    self.stack.push(self.globals[global as usize].clone())
+                }
+                Instruction::SetGlobal(global) => {
+                    self.globals[global as usize] = self.pop();
+                }
+                Instruction::Capture(i) => {
+                    let value = frame.closure.captures[i as usize].1.clone();
+                    self.stack.push(S::pack(value));
+                }
+                Instruction::Function(id) => {
+                    let closure = self.closure(id, vec![], span)?;
+                    self.stack.push(S::pack(Value::Function(closure)));
+                }
+                Instruction::Closure(id) => {
+                    let count = self.module.function(id).captures.len();
+                    let captures = self.stack.split_off(self.stack.len() - count);
+                    let captures = captures.iter().map(S::unpack).collect();
+                    let closure = self.closure(id, captures, span)?;
+                    self.stack.push(S::pack(Value::Function(closure)));
+                }
+                Instruction::Callee => {
+                    let closure = frame.closure.clone();
+                    self.stack.push(S::pack(Value::Function(closure)));
+                }
+                Instruction::Cast(kind) => {
+                    let value = self.pop().cast(kind);
+                    self.stack.push(value);
+                }
+                Instruction::Call(count) => self.enter(count, span)?,
+                Instruction::Builtin(builtin) => {
+                    let start = self.stack.len() - builtin.arity();
+                    let arguments: Vec<_> = self.stack.drain(start..).map(|a| a.unpack()).collect();
+                    let value = builtin::call(&mut self.streams, builtin, &arguments, span)?;
+                    self.stack.extend(value.map(S::pack));
+                }
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::Jump(to) => frame.ip = to as usize,
+                Instruction::JumpIfFalse(to) => {
+                    if !self.pop().truth() {
+                        self.frames.last_mut().unwrap().ip = to as usize;
+                    }
+                }
+                Instruction::Return => {
+                    let value = self.pop();
+                    self.leave(Some(value));
+                }
+                Instruction::ReturnUnit => self.leave(None),
+                operator => {
+                    let operator = operator.operator().unwrap();
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = left.binary(&operator, &right).map_err(|error| {
+                        RuntimeError::new(RuntimeErrorKind::Arithmetic(error), span)
+                    })?;
+                    self.stack.push(value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::TypeKind,
+        bytecode,
+        interpreter::{Interpreter, Resource},
+        lexer::Lexer,
+        parser::Parser,
+        source_map::SourceMap,
+        value::ArithmeticError,
+    };
+    use std::ops::ControlFlow;
+
+    // Programs whose `main` both the interpreter and the VM run.
+    const CORPUS: &[&str] = &[
+        "fn main() -> int64 { return 1 + 2 * 3 ** 2 - 4 / 3; }",
+        "\
+fn factorial(n: int64) -> int64 {
+    if n <= 1 { return 1; }
+    return n * factorial(n - 1);
+}
+fn main() -> int64 { return factorial(20); }",
+        "\
+fn fib(n: int32) -> int32 {
+    if n < 2 { return n; } else { return fib(n - 1) + fib(n - 2); }
+}
+fn main() -> int32 { return fib(15); }",
+        "\
+fn main() -> int8 {
+    let a: int8 = 120;
+    let b: int8 = a + 10;
+    let c: int16 = 1000;
+    return b * 3 + c as int8;
+}",
+        "\
+fn main() -> int4 {
+    let x: int4 = 7;
+    let y: int2 = 1;
+    let z: int1 = 1;
+    return x + (y + 1) as int4 + (z + z) as int4;
+}",
+        "\
+fn main() -> int32 {
+    let h: float16 = 2049;
+    let b: bfloat16 = 257;
+    let sum: float32 = h + b;
+    return (sum / 3) as int32;
+}",
+        "\
+fn main() -> float64 {
+    let x: float32 = 1;
+    let tenth: float32 = x / 10;
+    return (tenth + tenth + tenth) as float64;
+}",
+        "\
+fn main() -> bool {
+    let x: float64 = 0;
+    let nan: float64 = x / x;
+    return nan == nan;
+}",
+        "\
+let base: int64 = 1000;
+fn add(x: int64) -> int64 { return x + base; }
+fn main() -> int64 { return add(add(1)); }",
+        "\
+fn twice(f: fn(int64) -> int64, x: int64) -> int64 { return f(f(x)); }
+fn main() -> int64 {
+    let step: int64 = 5;
+    fn add(x: int64) -> int64 { return x + step; }
+    fn count(n: int64) -> int64 {
+        if n == 0 { return 0; }
+        return add(count(n - 1));
+    }
+    return twice(count, 2);
+}",
+        "\
+fn outer(a: int64) -> fn(int64) -> int64 {
+    fn middle(b: int64) -> int64 {
+        fn inner(c: int64) -> int64 { return a * 100 + b * 10 + c; }
+        return inner(3);
+    }
+    return middle;
+}
+fn main() -> int64 { return outer(1)(2); }",
+        "\
+fn main() -> int64 {
+    let n: int64 = 3;
+    while n > 0 {
+        if n == 3 { return 30; }
+    }
+    return 0;
+}",
+        "\
+fn side(x: int64) { }
+fn main() -> int64 {
+    let big: int64 = 9223372036854775807;
+    side(big);
+    { let inner: int64 = big + 1; return inner / 2; }
+}",
+        "\
+fn main() -> int64 {
+    let zero: int64 = 2 - 2;
+    return 10 / zero;
+}",
+        "\
+fn main() -> int32 {
+    let minus: int32 = 0 - 1;
+    return 2 ** minus;
+}",
+        "\
+fn missing(x: int64) -> int64;
+fn main() -> int64 { return missing(1); }",
+    ];
+
+    #[test]
+    fn the_vm_agrees_with_the_interpreter() {
+        for source in CORPUS {
+            let tokens = Lexer::tokenize(source);
+            let program = Parser::parse_program(&tokens).unwrap();
+            let errors = program.typecheck().errors;
+            assert!(errors.is_empty(), "{}: {:?}", source, errors);
+            let hir = program.to_hir();
+
+            let mut interpreter = Interpreter::new(&hir);
+            interpreter.run().unwrap();
+            let expected = interpreter.call("main", vec![]);
+
+            let module = bytecode::compile(&hir);
+            let mut vm = Vm::new(&module);
+            vm.run().unwrap();
+            assert_eq!(vm.call("main", vec![]), expected, "{}", source);
+            assert!(vm.stack.is_empty() && vm.frames.is_empty());
+
+            let mut vm = Vm::packed(&module);
+            vm.run().unwrap();
+            assert_eq!(vm.call("main", vec![]), expected, "{}", source);
+            assert!(vm.stack.is_empty() && vm.frames.is_empty());
+        }
+    }
+
+    #[test]
+    fn observers_see_the_same_locals_in_both() {
+        let source = "\
+fn f(n: int64) -> int64 {
+    let a: int64 = n + 1;
+    if a > 1 {
+        let b: int64 = a * 2;
+        fn g(x: int64) -> int64 { let y: int64 = x + b; return y; }
+        return g(a);
+    }
+    return a;
+}
+let r: int64 = f(1);
+{ let s: int64 = r; s; }";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let hir = program.to_hir();
+        let module = bytecode::compile(&hir);
+
+        // Each distinct state in turn, as the call depth and the locals.
+        fn record(states: &mut Vec<String>) -> impl FnMut(&Step) -> ControlFlow<()> + '_ {
+            |step: &Step| {
+                let locals: Vec<String> = step
+                    .locals
+                    .iter()
+                    .map(|(binding, value)| format!("{}={}", binding.name, value))
+                    .collect();
+                let function = step
+                    .function
+                    .as_ref()
+                    .map_or("-".to_string(), |f| f.to_string());
+                let state = format!("{} {} [{}]", step.depth, function, locals.join(" "));
+                if states.last() != Some(&state) {
+                    states.push(state);
+                }
+                ControlFlow::Continue(())
+            }
+        }
+        let mut expected = vec![];
+        let mut interpreter = Interpreter::new(&hir);
+        interpreter.observe(record(&mut expected));
+        interpreter.run().unwrap();
+        drop(interpreter);
+        let mut states = vec![];
+        let mut vm = Vm::new(&module);
+        vm.observe(record(&mut states));
+        vm.run().unwrap();
+        drop(vm);
+
+        // The VM also stops between the statements, such as when a call
+        // returns to the middle of one, so it sees more states.
+        let mut remaining = states.iter();
+        for state in &expected {
+            assert!(remaining.any(|s| s == state), "{} in {:?}", state, states);
+        }
+        assert!(states.contains(&"3 g [x=2 y=6]".to_string()));
+        assert!(states.contains(&"1 - [s=6]".to_string()));
+    }
+
+    #[test]
+    fn observers_can_stop_the_program() {
+        let source = "fn main() -> int64 { while 1 > 0 { 0; } return 0; }";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let hir = program.to_hir();
+        let module = bytecode::compile(&hir);
+        // Stops at the hundredth step.
+        fn stop(steps: &mut usize) -> impl FnMut(&Step) -> ControlFlow<()> + '_ {
+            |_: &Step| {
+                *steps += 1;
+                match *steps {
+                    100 => ControlFlow::Break(()),
+                    _ => ControlFlow::Continue(()),
+                }
+            }
+        }
+        let (mut interpreted, mut executed) = (0, 0);
+        let mut interpreter = Interpreter::new(&hir);
+        interpreter.observe(stop(&mut interpreted));
+        let mut vm = Vm::new(&module);
+        vm.observe(stop(&mut executed));
+        for error in [
+            interpreter.call("main", vec![]).unwrap_err(),
+            vm.call("main", vec![]).unwrap_err(),
+        ] {
+            assert_eq!(error.kind, RuntimeErrorKind::Stopped);
+            assert_eq!(error.to_string(), "the program was stopped");
+        }
+        assert!(vm.stack.is_empty() && vm.frames.is_empty());
+        drop((interpreter, vm));
+        assert_eq!((interpreted, executed), (100, 100));
+    }
+
+    #[test]
+    fn limits_stop_runaway_programs() {
+        let cases = [
+            (
+                "fn main() -> int64 { while 1 > 0 { } return 0; }",
+                Limits {
+                    fuel: Some(10_000),
+                    ..Limits::default()
+                },
+                Resource::Fuel,
+                None,
+            ),
+            (
+                "fn main() -> int64 { return main() + 1; }",
+                Limits::default(),
+                Resource::CallDepth,
+                Some("main()"),
+            ),
+            (
+                "\
+fn main() -> int64 {
+    let a: int64 = 1;
+    while a > 0 { fn f() -> int64 { return a; } }
+    return a;
+}",
+                Limits {
+                    max_allocated: Some(1 << 16),
+                    ..Limits::default()
+                },
+                Resource::Allocation,
+                Some("fn f() -> int64 { return a; }"),
+            ),
+        ];
+        for (source, limits, resource, at) in cases {
+            let tokens = Lexer::tokenize(source);
+            let program = Parser::parse_program(&tokens).unwrap();
+            let hir = program.to_hir();
+            let module = bytecode::compile(&hir);
+            let mut interpreter = Interpreter::new(&hir);
+            interpreter.limit(limits);
+            let mut vm = Vm::new(&module);
+            vm.limit(limits);
+            for error in [
+                interpreter.call("main", vec![]).unwrap_err(),
+                vm.call("main", vec![]).unwrap_err(),
+            ] {
+                assert_eq!(error.kind, RuntimeErrorKind::ResourceLimit(resource));
+                // Statements and instructions burn fuel at different rates, so
+                // only the other limits stop both at the same place.
+                if let Some(at) = at {
+                    assert_eq!(&source[error.span.range()], at, "{}", source);
+                }
+            }
+            assert!(vm.stack.is_empty() && vm.frames.is_empty());
+        }
+        let error = RuntimeError::new(
+            RuntimeErrorKind::ResourceLimit(Resource::CallDepth),
+            Span::new(0, 0),
+        );
+        assert_eq!(error.to_string(), "resource limit exceeded: call depth");
+    }
+
+    #[test]
+    fn errors_point_at_the_failing_expression() {
+        let source = "\
+fn divide(a: int64, b: int64) -> int64 { return a / b; }
+fn main() -> int64 { return divide(1, 0); }";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let module = bytecode::compile(&program.to_hir());
+        let mut vm = Vm::new(&module);
+        vm.run().unwrap();
+        let error = vm.call("main", vec![]).unwrap_err();
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::Arithmetic(ArithmeticError::DivisionByZero)
+        );
+        assert_eq!(&source[error.span.range()], "a / b");
+        assert!(vm.stack.is_empty() && vm.frames.is_empty());
+
+        let arguments = vec![
+            Value::integer(TypeKind::Int64, 7),
+            Value::integer(TypeKind::Int64, 2),
+        ];
+        assert_eq!(
+            vm.call("divide", arguments),
+            Ok(Some(Value::integer(TypeKind::Int64, 3)))
+        );
+        assert_eq!(
+            vm.call("nothing", vec![]).unwrap_err().to_string(),
+            "no function named `nothing`"
+        );
+    }
+
+    #[test]
+    fn errors_carry_a_backtrace() {
+        let source = "\
+fn divide(a: int64, b: int64) -> int64 { return a / b; }
+fn down(n: int64) -> int64 {
+    if n == 0 { return divide(1, n); }
+    return down(n - 1);
+}
+fn half(n: int64) -> int64 {
+    fn go(m: int64) -> int64 { return down(m); }
+    return go(n / 2);
+}
+let x: int64 = half(6);";
+        let tokens = Lexer::tokenize(source);
+        let program = Parser::parse_program(&tokens).unwrap();
+        let hir = program.to_hir();
+        let module = bytecode::compile(&hir);
+        let error = Interpreter::new(&hir).run().unwrap_err();
+        assert_eq!(Vm::new(&module).run().unwrap_err(), error);
+        let map = SourceMap::new("main", source);
+        assert_eq!(
+            error.to_diagnostic(&map).render(&map),
+            "\
+error[E0800]: division by zero
+ --> main:1:49
+  |
+1 | fn divide(a: int64, b: int64) -> int64 { return a / b; }
+  |                                                 ^^^^^
+  = note: in `divide`, called at main:3:24
+  = note: in `down`, called at main:4:12 (3 times)
+  = note: in `down`, called at main:7:39
+  = note: in `go`, called at main:8:12
+  = note: in `half`, called at main:10:16
+"
+        );
+
+        let mut vm = Vm::new(&module);
+        let arguments = vec![Value::integer(TypeKind::Int64, 0)];
+        let error = vm.call("down", arguments).unwrap_err();
+        assert_eq!(
+            error.to_diagnostic(&map).notes,
+            ["in `divide`, called at main:3:24", "in `down`"]
+        );
+    }
+}
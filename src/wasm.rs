@@ -0,0 +1,97 @@
+use crate::{
+    ast::Span, database::Lexeme, lexer::Lexer, pipeline::CompileOptions, session::Session,
+};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+// Bindings for JavaScript, so that a playground in the browser can run the
+// front end compiled to WebAssembly:
+//
+//   cargo rustc --lib --release --target wasm32-unknown-unknown \
+//       --features wasm --crate-type cdylib
+//   wasm-bindgen --target web --out-dir playground \
+//       target/wasm32-unknown-unknown/release/mylang2.wasm
+//
+// The functions take source text and return JSON text, which JavaScript
+// reads with `JSON.parse`, so that no type crosses the boundary but
+// strings. Sources are named `<playground>` in diagnostics. Off wasm32 the
+// functions are plain Rust ones.
+
+const NAME: &str = "<playground>";
+
+fn session(source: &str) -> Session {
+    let options = CompileOptions {
+        name: NAME.to_string(),
+        ..CompileOptions::default()
+    };
+    Session::new(source, options)
+}
+
+// The tokens of a source, whitespace and comments included, as an array of
+// `{"kind":"Let","span":{"start":0,"end":3}}` objects. Offsets are in bytes.
+#[wasm_bindgen]
+pub fn tokenize(source: &str) -> String {
+    let tokens: Vec<Lexeme> = Lexer::tokenize(source)
+        .iter()
+        .map(|token| Lexeme {
+            kind: token.kind(),
+            span: Span::new(token.offset(), token.offset() + token.len()),
+        })
+        .collect();
+    serde_json::to_string(&tokens).expect("tokens serialize")
+}
+
+// The syntax tree of a source, serialized as with the `serde` feature. A
+// source that does not parse throws the rendered diagnostic.
+#[wasm_bindgen]
+pub fn parse_to_json(source: &str) -> Result<String, String> {
+    let mut session = session(source);
+    match session.parse() {
+        Some(program) => Ok(serde_json::to_string(&program).expect("programs serialize")),
+        None => Err(session.render()),
+    }
+}
+
+// Parses and checks a source, returning its diagnostics, in source order,
+// as an array of the objects `Diagnostic::to_json` writes.
+#[wasm_bindgen]
+pub fn check(source: &str) -> String {
+    let mut session = session(source);
+    if let Some(program) = session.parse() {
+        session.check(&program);
+    }
+    let diagnostics: Vec<String> = session
+        .diagnostics()
+        .iter()
+        .map(|d| d.to_json(&session.source_map))
+        .collect();
+    format!("[{}]", diagnostics.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn functions_return_json() {
+        let tokens: Value = serde_json::from_str(&tokenize("let x")).unwrap();
+        assert_eq!(tokens[0]["kind"], "Let");
+        assert_eq!(tokens[2]["span"]["end"], 5);
+
+        let program: Value = serde_json::from_str(&parse_to_json("f(1);").unwrap()).unwrap();
+        assert_eq!(program["statements"].as_array().unwrap().len(), 1);
+        let error = parse_to_json("fn f( {").unwrap_err();
+        assert!(error.contains("--> <playground>:1:"), "{}", error);
+
+        let diagnostics: Value = serde_json::from_str(&check("let x: int8 = y;")).unwrap();
+        let codes: Vec<&str> = diagnostics
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|d| d["code"].as_str().unwrap())
+            .collect();
+        assert_eq!(codes, ["W0200", "E0200"]);
+        assert_eq!(diagnostics[1]["file"], NAME);
+        assert_eq!(check("let _x: int8 = 1;"), "[]");
+    }
+}